@@ -59,7 +59,7 @@ impl MaxAmp for i8 {
 fn generate_wavetable_sine<T: Sized + MaxAmp>(t: T, len: usize) -> Wavetable<T> {
     let mut sine_table = Wavetable::<T> {
         table: Vec::with_capacity(len),
-        len: len,
+        len,
         norm: (t.max_amp() + 1_f64) as u32,
     };
 
@@ -75,7 +75,7 @@ fn generate_wavetable_sine<T: Sized + MaxAmp>(t: T, len: usize) -> Wavetable<T>
 fn generate_wavetable_exp<T: Sized + MaxAmp>(t: T, len: usize) -> Wavetable<T> {
     let mut exp_table = Wavetable::<T> {
         table: Vec::with_capacity(len),
-        len: len,
+        len,
         norm: (t.max_amp() + 1_f64) as u32,
     };
 
@@ -100,7 +100,7 @@ fn write_table_to_file<T: Sized + MaxAmp + Display + Copy + Add<T, Output = T> +
 
     // Construct a name like SINE_i32 or EXP_i8 etc.
     array_string.push_str(&wave_string);
-    array_string.push_str("_");
+    array_string.push('_');
     array_string.push_str(&type_string.to_uppercase());
 
     // Specify array type and length
@@ -131,7 +131,7 @@ fn write_table_to_file<T: Sized + MaxAmp + Display + Copy + Add<T, Output = T> +
 
         array_string.push_str("pub const ");
         array_string.push_str(&wave_string);
-        array_string.push_str("_");
+        array_string.push('_');
         array_string.push_str(&type_string.to_uppercase());
         array_string.push_str("_TAU: usize = ");
         array_string.push_str(tau.to_string().as_str());
@@ -139,7 +139,7 @@ fn write_table_to_file<T: Sized + MaxAmp + Display + Copy + Add<T, Output = T> +
 
         array_string.push_str("pub const ");
         array_string.push_str(&wave_string);
-        array_string.push_str("_");
+        array_string.push('_');
         array_string.push_str(&type_string.to_uppercase());
         array_string.push_str("_NORM: usize = ");
         array_string.push_str(norm.to_string().as_str());
@@ -0,0 +1,102 @@
+// Host simulation of playback on a stereo I2S DAC through circular DMA.
+//
+// The example doesn't use an embedded HAL and doesn't run on a
+// microcontroller. It stands in a simulated DAC for the I2S transmitter
+// and its DMA stream, which reads the double buffer sample by sample while
+// the audio task refills the half that finished playing, just like the
+// half transfer and transfer complete interrupts would on a target.
+
+use isopod::io::buffer::{Half, PingPong};
+use isopod::synth::fmpiano::FmPiano;
+use isopod::synth::Synth;
+use isopod::util::units::Hz;
+
+/// Stereo frames per half of the buffer, i.e. about 1.5 ms at 44.1 kHz
+const FRAMES: usize = 64;
+
+static mut BUFFER: PingPong<{ 2 * FRAMES }> = PingPong::new(2);
+
+/// I2S transmitter with a circular DMA stream, as simulated below
+trait I2sDma {
+    fn sample_rate(&self) -> Hz;
+    /// Starts the circular transfer of `len` samples from `address`.
+    fn start(&mut self, address: *const i16, len: usize);
+    /// Blocks until the next simulated interrupt and returns the half that
+    /// finished playing.
+    fn wait(&mut self) -> Half;
+}
+
+/// Audio task owning the synth
+struct Audio {
+    piano: FmPiano,
+}
+
+impl Audio {
+    /// Called for each finished half, like from a DMA interrupt. The
+    /// finished half is refilled while the other one plays.
+    fn on_dma(&mut self, half: Half) {
+        // Safety: only the audio task writes the buffer, and the DMA reads
+        // the other half meanwhile
+        let buffer = unsafe { &mut *core::ptr::addr_of_mut!(BUFFER) };
+        buffer.fill(half, &mut self.piano);
+    }
+}
+
+/// DAC reading the buffer on the host like the DMA would
+struct SimulatedDac {
+    address: *const i16,
+    len: usize,
+    position: usize,
+    peak: i16,
+}
+
+impl I2sDma for SimulatedDac {
+    fn sample_rate(&self) -> Hz {
+        Hz(44_100)
+    }
+
+    fn start(&mut self, address: *const i16, len: usize) {
+        (self.address, self.len) = (address, len);
+    }
+
+    fn wait(&mut self) -> Half {
+        // The hardware reads the samples while the other half is written
+        let half = self.len / 2;
+        for n in self.position..self.position + half {
+            let x = unsafe { self.address.add(n).read_volatile() };
+            self.peak = self.peak.max(x.saturating_abs());
+        }
+        self.position = (self.position + half) % self.len;
+        if self.position == 0 {
+            Half::Second
+        } else {
+            Half::First
+        }
+    }
+}
+
+fn main() {
+    let mut dac = SimulatedDac {
+        address: core::ptr::null(),
+        len: 0,
+        position: 0,
+        peak: 0,
+    };
+    let mut piano = FmPiano::new();
+    piano.set_sample_rate(dac.sample_rate());
+    piano.note_on(60, 100);
+
+    // Prefill both halves before starting the transfer
+    let mut audio = Audio { piano };
+    audio.on_dma(Half::First);
+    audio.on_dma(Half::Second);
+    let samples = unsafe { (*core::ptr::addr_of!(BUFFER)).as_slice() };
+    dac.start(samples.as_ptr(), samples.len());
+
+    // One second of audio
+    for _ in 0..dac.sample_rate().0 as usize / FRAMES {
+        let half = dac.wait();
+        audio.on_dma(half);
+    }
+    println!("peak level {}", dac.peak);
+}
@@ -0,0 +1,47 @@
+// FM piano in the browser through an AudioWorklet.
+//
+// Build the module with
+//   cargo build --release --example web --target wasm32-unknown-unknown --no-default-features
+// copy target/wasm32-unknown-unknown/release/examples/web.wasm to
+// examples/web and serve that directory. index.html loads the module and
+// hands it to the processor in processor.js, which calls the functions below
+// on the audio thread and copies the block into its outputs.
+
+use isopod::io::web::WorkletBlock;
+use isopod::synth::fmpiano::FmPiano;
+use isopod::synth::Synth;
+use isopod::util::units::Hz;
+
+static mut BLOCK: WorkletBlock<2> = WorkletBlock::new();
+static mut PIANO: Option<FmPiano> = None;
+
+/// Returns the piano. Safety: the worklet calls the exports from the audio
+/// thread only, and JavaScript is single threaded within the worklet.
+fn piano() -> &'static mut FmPiano {
+    unsafe { (*core::ptr::addr_of_mut!(PIANO)).get_or_insert_with(FmPiano::new) }
+}
+
+/// Sets the sample rate of the AudioContext and returns the offset of the
+/// block in the wasm memory.
+#[no_mangle]
+pub extern "C" fn isopod_init(sample_rate: u32) -> *const f32 {
+    piano().set_sample_rate(Hz(sample_rate));
+    unsafe { (*core::ptr::addr_of!(BLOCK)).as_ptr() }
+}
+
+#[no_mangle]
+pub extern "C" fn isopod_note_on(note: u8, velocity: u8) {
+    piano().note_on(note, velocity);
+}
+
+#[no_mangle]
+pub extern "C" fn isopod_note_off(note: u8) {
+    piano().note_off(note);
+}
+
+/// Renders the next block of both channels.
+#[no_mangle]
+pub extern "C" fn isopod_process() {
+    let block = unsafe { &mut *core::ptr::addr_of_mut!(BLOCK) };
+    block.fill(piano());
+}
@@ -0,0 +1,33 @@
+// AudioWorkletProcessor playing the isopod module of examples/web.rs.
+
+const QUANTUM = 128;
+
+class IsopodProcessor extends AudioWorkletProcessor {
+  constructor(options) {
+    super();
+    const module = new WebAssembly.Module(options.processorOptions.module);
+    this.isopod = new WebAssembly.Instance(module, {}).exports;
+    const offset = this.isopod.isopod_init(sampleRate);
+    // The memory of the module does not grow, so the view stays valid
+    this.block = new Float32Array(this.isopod.memory.buffer, offset, 2 * QUANTUM);
+    this.port.onmessage = ({ data }) => {
+      if (data.velocity > 0) {
+        this.isopod.isopod_note_on(data.note, data.velocity);
+      } else {
+        this.isopod.isopod_note_off(data.note);
+      }
+    };
+  }
+
+  process(inputs, outputs) {
+    this.isopod.isopod_process();
+    const output = outputs[0];
+    for (let c = 0; c < output.length; c++) {
+      const channel = Math.min(c, 1);
+      output[c].set(this.block.subarray(channel * QUANTUM, (channel + 1) * QUANTUM));
+    }
+    return true;
+  }
+}
+
+registerProcessor("isopod", IsopodProcessor);
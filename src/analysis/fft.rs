@@ -0,0 +1,181 @@
+// Radix-2 fixed-point FFT of 256 to 2048 points.
+
+use crate::osc::luts::SINE_I16;
+
+/// Fewest points of [fft]
+pub const MIN_POINTS: usize = 256;
+/// Most points of [fft], i.e. the finest resolution of the twiddle factors
+pub const MAX_POINTS: usize = 2048;
+
+/// Complex value of the FFT with fixed-point parts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Complex {
+    pub re: i32,
+    pub im: i32,
+}
+
+impl Complex {
+    pub const fn new(re: i32, im: i32) -> Self {
+        Self { re, im }
+    }
+
+    /// Returns the magnitude, rounded down.
+    pub fn magnitude(self) -> u32 {
+        let (re, im) = (self.re as i64, self.im as i64);
+        ((re * re + im * im) as u64).isqrt() as u32
+    }
+}
+
+/// Returns cos and sin of `2 pi * phase / MAX_POINTS` normalized to 2^15.
+/// The sine table has half the resolution, so odd phases are the average
+/// of their neighbours, which is off by about 1 at most.
+#[inline]
+pub(crate) fn cos_sin(phase: usize) -> (i32, i32) {
+    let lookup = |phase: usize| {
+        let i = (phase % MAX_POINTS) / 2;
+        let x = SINE_I16[i] as i32;
+        if phase.is_multiple_of(2) {
+            x
+        } else {
+            (x + SINE_I16[(i + 1) % SINE_I16.len()] as i32) / 2
+        }
+    };
+    (lookup(phase + MAX_POINTS / 4), lookup(phase))
+}
+
+/// Transforms `data` in place into its spectrum, divided by `N`
+///
+/// `N` is a power of two from [MIN_POINTS] to [MAX_POINTS]. Every stage of
+/// the decimation in time halves its output, which keeps the values in
+/// range whatever the input: with parts up to 2^30 in magnitude, all bins
+/// stay below 2^30 as well. Bin `k` holds the frequency `k / N` times the
+/// sample rate, and bins above `N / 2` the negative frequencies. A sine
+/// with amplitude `a` in the center of a bin therefore shows up with the
+/// magnitude `a / 2` in its bin and in its mirror.
+///
+/// ```
+/// use isopod::analysis::fft::{fft, Complex};
+///
+/// // An impulse has a flat spectrum
+/// let mut data = [Complex::default(); 256];
+/// data[0] = Complex::new(1 << 20, 0);
+/// fft(&mut data);
+/// assert!(data.iter().all(|x| x.re == 1 << 12 && x.im == 0));
+/// ```
+pub fn fft<const N: usize>(data: &mut [Complex; N]) {
+    const {
+        assert!(N.is_power_of_two() && N >= MIN_POINTS && N <= MAX_POINTS);
+    }
+    let bits = N.trailing_zeros();
+    for i in 0..N {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            data.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= N {
+        let half = len / 2;
+        let step = MAX_POINTS / len;
+        for start in (0..N).step_by(len) {
+            for k in 0..half {
+                // Twiddle factor exp(-2 pi i k / len)
+                let (c, s) = cos_sin(k * step);
+                let (c, s) = (c as i64, s as i64);
+                let a = data[start + k];
+                let b = data[start + k + half];
+                let (br, bi) = (b.re as i64, b.im as i64);
+                let tr = (c * br + s * bi) >> 15;
+                let ti = (c * bi - s * br) >> 15;
+                let (ar, ai) = (a.re as i64, a.im as i64);
+                data[start + k] = Complex::new(((ar + tr) >> 1) as i32, ((ai + ti) >> 1) as i32);
+                data[start + k + half] =
+                    Complex::new(((ar - tr) >> 1) as i32, ((ai - ti) >> 1) as i32);
+            }
+        }
+        len *= 2;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns the exact DFT of `data` divided by its length.
+    fn dft(data: &[Complex]) -> Vec<(f64, f64)> {
+        let n = data.len();
+        (0..n)
+            .map(|k| {
+                let (mut re, mut im) = (0.0, 0.0);
+                for (t, x) in data.iter().enumerate() {
+                    let phase = -core::f64::consts::TAU * (k * t % n) as f64 / n as f64;
+                    re += x.re as f64 * phase.cos() - x.im as f64 * phase.sin();
+                    im += x.re as f64 * phase.sin() + x.im as f64 * phase.cos();
+                }
+                (re / n as f64, im / n as f64)
+            })
+            .collect()
+    }
+
+    fn check<const N: usize>() {
+        // Noise from a linear congruential generator, at full scale
+        let mut seed: u32 = 12_345;
+        let mut data = [Complex::default(); N];
+        for x in data.iter_mut() {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            x.re = (seed as i32) >> 2;
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            x.im = (seed as i32) >> 2;
+        }
+        let exact = dft(&data);
+        fft(&mut data);
+        // The result of each stage is rounded, and the twiddles have 15
+        // fractional bits
+        let mut max_error = 0.0_f64;
+        for (x, (re, im)) in data.iter().zip(exact.iter()) {
+            max_error = max_error
+                .max((x.re as f64 - re).abs())
+                .max((x.im as f64 - im).abs());
+        }
+        let full_scale = (1 << 30) as f64;
+        assert!(max_error < full_scale * 2e-5, "{} {}", N, max_error);
+    }
+
+    #[test]
+    fn test_fft() {
+        check::<256>();
+        check::<1024>();
+        check::<2048>();
+    }
+
+    #[test]
+    fn test_cos_sin() {
+        assert_eq!(cos_sin(0), (i16::MAX as i32, 0));
+        assert_eq!(cos_sin(MAX_POINTS / 4), (0, i16::MAX as i32));
+        for phase in 0..MAX_POINTS {
+            let (c, s) = cos_sin(phase);
+            let angle = core::f64::consts::TAU * phase as f64 / MAX_POINTS as f64;
+            assert!((c as f64 - 32_767.0 * angle.cos()).abs() < 1.5);
+            assert!((s as f64 - 32_767.0 * angle.sin()).abs() < 1.5);
+        }
+    }
+
+    #[test]
+    fn test_fft_sine() {
+        // A cosine in bin 10 and its mirror, at half the amplitude each
+        let mut data = [Complex::default(); 512];
+        for (t, x) in data.iter_mut().enumerate() {
+            x.re = SINE_I16[(t * 20 + 256) % 1024] as i32 * (1 << 14);
+        }
+        fft(&mut data);
+        for (k, x) in data.iter().enumerate() {
+            let expected = if k == 10 || k == 502 { 1 << 28 } else { 0 };
+            assert!(
+                (x.magnitude() as i64 - expected).abs() < 1 << 16,
+                "{} {:?}",
+                k,
+                x
+            );
+        }
+    }
+}
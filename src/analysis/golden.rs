@@ -0,0 +1,278 @@
+// Golden renders: blocks of synths, oscillators and filters compared with
+// stored WAV files, so that changes of the DSP can't alter the sound
+// unnoticed.
+
+use crate::io::wav::{load_wav, WavError, WavSpec, WavWriter};
+use crate::synth::Synth;
+use crate::util::units::Hz;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Environment variable that makes [Golden::check] write the renders as the
+/// new golden files
+pub const BLESS_VAR: &str = "ISOPOD_BLESS";
+
+/// Reason why a render doesn't match its golden file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GoldenError {
+    /// There is no golden file yet, see [BLESS_VAR].
+    Missing,
+    /// The golden file couldn't be read or written.
+    Wav(WavError),
+    /// The render is at a different sample rate than the golden file.
+    SampleRate { expected: Hz, actual: Hz },
+    /// The render has a different number of samples than the golden file.
+    Length { expected: usize, actual: usize },
+    /// The first sample that differs by more than the tolerance.
+    Mismatch {
+        index: usize,
+        expected: i16,
+        actual: i16,
+    },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::Missing => write!(f, "no golden file, run with {}=1", BLESS_VAR),
+            GoldenError::Wav(error) => write!(f, "golden file: {:?}", error),
+            GoldenError::SampleRate { expected, actual } => {
+                write!(
+                    f,
+                    "sample rate {} Hz instead of {} Hz",
+                    actual.0, expected.0
+                )
+            }
+            GoldenError::Length { expected, actual } => {
+                write!(f, "{} samples instead of {}", actual, expected)
+            }
+            GoldenError::Mismatch {
+                index,
+                expected,
+                actual,
+            } => write!(f, "sample {} is {} instead of {}", index, actual, expected),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+/// Renders `len` samples of `synth` in blocks. The synths are fixed-point
+/// and render the same samples on every platform, which is what makes
+/// golden files possible.
+pub fn render<S: Synth + ?Sized>(synth: &mut S, len: usize) -> Vec<i16> {
+    let mut out = vec![0; len];
+    for block in out.chunks_mut(64) {
+        synth.render(block);
+    }
+    out
+}
+
+/// Directory of golden files
+///
+/// Renders are compared sample by sample with the mono WAV file of their
+/// name in the directory. A difference of up to the tolerance passes, which
+/// is 0 by default since the rendering is deterministic. With [BLESS_VAR]
+/// set, or [Golden::set_bless], the renders replace the golden files
+/// instead, e.g. after an intended change of the sound. The new files are
+/// then reviewed by ear and committed.
+///
+/// ```no_run
+/// use isopod::analysis::golden::{render, Golden};
+/// use isopod::synth::fmpiano::FmPiano;
+/// use isopod::synth::Synth;
+///
+/// let golden = Golden::new("tests/golden");
+/// let mut piano = FmPiano::new();
+/// piano.note_on(60, 100);
+/// let out = render(&mut piano, 4_096);
+/// golden.check("fmpiano", &out, piano.get_sample_rate()).unwrap();
+/// ```
+pub struct Golden {
+    dir: PathBuf,
+    tolerance: u16,
+    bless: bool,
+}
+
+impl Golden {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            tolerance: 0,
+            bless: std::env::var_os(BLESS_VAR).is_some_and(|v| !v.is_empty() && v != "0"),
+        }
+    }
+
+    /// Sets the largest difference of a sample that still matches.
+    pub fn set_tolerance(&mut self, tolerance: u16) {
+        self.tolerance = tolerance;
+    }
+
+    /// Sets whether renders replace the golden files instead of being
+    /// compared with them.
+    pub fn set_bless(&mut self, bless: bool) {
+        self.bless = bless;
+    }
+
+    /// Returns the path of the golden file of `name`.
+    pub fn get_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.wav", name))
+    }
+
+    /// Compares `samples` rendered at `sample_rate` with the golden file of
+    /// `name`, or writes them as the golden file if blessing.
+    pub fn check(&self, name: &str, samples: &[i16], sample_rate: Hz) -> Result<(), GoldenError> {
+        let path = self.get_path(name);
+        if self.bless {
+            return self.write(path, samples, sample_rate);
+        }
+        let (expected, expected_rate) = match load_wav(&path) {
+            Ok(golden) => golden,
+            Err(WavError::Io(std::io::ErrorKind::NotFound)) => return Err(GoldenError::Missing),
+            Err(error) => return Err(GoldenError::Wav(error)),
+        };
+        if expected_rate != sample_rate {
+            return Err(GoldenError::SampleRate {
+                expected: expected_rate,
+                actual: sample_rate,
+            });
+        }
+        if expected.len() != samples.len() {
+            return Err(GoldenError::Length {
+                expected: expected.len(),
+                actual: samples.len(),
+            });
+        }
+        let mismatch = expected
+            .iter()
+            .zip(samples.iter())
+            .position(|(x, y)| x.abs_diff(*y) > self.tolerance);
+        match mismatch {
+            Some(index) => Err(GoldenError::Mismatch {
+                index,
+                expected: expected[index],
+                actual: samples[index],
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn write(&self, path: PathBuf, samples: &[i16], sample_rate: Hz) -> Result<(), GoldenError> {
+        let io = |error: std::io::Error| GoldenError::Wav(error.into());
+        std::fs::create_dir_all(&self.dir).map_err(io)?;
+        let mut writer = WavWriter::create(path, WavSpec::mono(sample_rate)).map_err(io)?;
+        writer.write(samples).map_err(io)?;
+        writer.finish().map_err(io)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fx::filter::{StateVariableFilter, SvfOutput, Q_MAX};
+    use crate::osc::blep::{BlepOscillator, Waveform};
+    use crate::osc::noise::WhiteNoise;
+    use crate::osc::wavetable::SineOscillator;
+    use crate::synth::fmpiano::FmPiano;
+    use crate::synth::subtractive::SubtractiveVoice;
+    use crate::util::units::mHz;
+
+    const LEN: usize = 4_096;
+
+    fn golden() -> Golden {
+        Golden::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"))
+    }
+
+    fn check(name: &str, samples: &[i16], sample_rate: Hz) {
+        if let Err(error) = golden().check(name, samples, sample_rate) {
+            panic!("golden render {}: {}", name, error);
+        }
+    }
+
+    #[test]
+    fn test_golden_oscillators() {
+        let mut sine = SineOscillator::new();
+        sine.set_mfreq(mHz(440_000));
+        sine.start();
+        let mut out = vec![0; LEN];
+        sine.render(&mut out);
+        check("sine", &out, Hz(44_100));
+
+        let mut square = BlepOscillator::new();
+        square.set_waveform(Waveform::Square);
+        square.set_mfreq(mHz(1_234_000));
+        square.render(&mut out);
+        check("blep_square", &out, Hz(44_100));
+    }
+
+    #[test]
+    fn test_golden_filter() {
+        let mut noise = WhiteNoise::new();
+        let mut out = vec![0; LEN];
+        noise.render(&mut out);
+        let mut filter = StateVariableFilter::<i16>::new();
+        filter.set_mfreq(mHz(2_000_000));
+        filter.set_q(Q_MAX - Q_MAX / 8);
+        for block in out.chunks_mut(64) {
+            block.iter_mut().for_each(|x| *x /= 8);
+            filter.process_block(block, SvfOutput::Bandpass);
+        }
+        check("svf_noise", &out, Hz(44_100));
+    }
+
+    #[test]
+    fn test_golden_voices() {
+        let mut voice = SubtractiveVoice::new();
+        voice.note_on(48, 100);
+        let mut out = render(&mut voice, LEN);
+        voice.note_off(48);
+        out.extend(render(&mut voice, LEN));
+        check("subtractive", &out, voice.get_sample_rate());
+
+        let mut piano = FmPiano::new();
+        piano.note_on(60, 100);
+        check(
+            "fmpiano",
+            &render(&mut piano, 2 * LEN),
+            piano.get_sample_rate(),
+        );
+    }
+
+    #[test]
+    fn test_golden_mismatch() {
+        let dir = std::env::temp_dir().join(format!("isopod_golden_{}", std::process::id()));
+        let mut golden = Golden::new(&dir);
+        golden.set_bless(false);
+        assert_eq!(
+            golden.check("ramp", &[1, 2, 3], Hz(8_000)),
+            Err(GoldenError::Missing)
+        );
+        golden.set_bless(true);
+        golden.check("ramp", &[1, 2, 3], Hz(8_000)).unwrap();
+        golden.set_bless(false);
+        golden.check("ramp", &[1, 2, 3], Hz(8_000)).unwrap();
+        assert_eq!(
+            golden.check("ramp", &[1, 4, 3], Hz(8_000)),
+            Err(GoldenError::Mismatch {
+                index: 1,
+                expected: 2,
+                actual: 4
+            })
+        );
+        golden.set_tolerance(2);
+        golden.check("ramp", &[1, 4, 3], Hz(8_000)).unwrap();
+        assert_eq!(
+            golden.check("ramp", &[1, 2], Hz(8_000)),
+            Err(GoldenError::Length {
+                expected: 3,
+                actual: 2
+            })
+        );
+        assert!(matches!(
+            golden.check("ramp", &[1, 2, 3], Hz(16_000)),
+            Err(GoldenError::SampleRate { .. })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,12 @@
+// Analysis of signals: spectra with a fixed-point FFT, e.g. for spectrum
+// displays, vocoder bands and checks of aliasing and filter responses, an
+// oscilloscope tap, and quality metrics and golden renders for regression
+// tests of the DSP.
+
+pub mod fft;
+#[cfg(feature = "std")]
+pub mod golden;
+pub mod quality;
+#[cfg(feature = "alloc")]
+pub mod scope;
+pub mod spectrum;
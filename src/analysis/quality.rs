@@ -0,0 +1,350 @@
+// Signal quality metrics for regression tests of the DSP: frequency
+// accuracy, harmonic distortion, aliasing and the gain of filters.
+
+use crate::osc::wavetable::SineOscillator;
+use crate::util::units::{mHz, SAMPLE_NORM};
+
+/// Bins on each side of a component in the center of a bin that hold part
+/// of it with a Hann window
+const SPREAD: usize = 1;
+
+/// Fractional bits of the zero crossing positions
+const POSITION_FRAC: u32 = 16;
+
+/// Returns the frequency of a periodic signal from the spacing of its rising
+/// zero crossings, interpolated between samples, or `None` with less than
+/// two crossings. The result averages over all periods in `samples`, which
+/// makes it much finer than the bins of a spectrum.
+///
+/// ```
+/// use isopod::analysis::quality::estimate_mfreq;
+/// use isopod::osc::luts::SINE_I16;
+/// use isopod::util::units::mHz;
+///
+/// // A period of 32 samples at 48 kHz
+/// let input: Vec<i16> = (0..4_096).map(|n| SINE_I16[(n * 32) % 1024]).collect();
+/// assert_eq!(estimate_mfreq(&input, mHz(48_000_000)), Some(mHz(1_500_000)));
+/// ```
+pub fn estimate_mfreq(samples: &[i16], msample_rate: mHz) -> Option<mHz> {
+    let mut first = None;
+    let mut last = 0;
+    let mut crossings = 0_u64;
+    for (n, w) in samples.windows(2).enumerate() {
+        let (a, b) = (w[0] as i64, w[1] as i64);
+        if a < 0 && b >= 0 {
+            let position = ((n as i64) << POSITION_FRAC) + ((-a) << POSITION_FRAC) / (b - a);
+            first.get_or_insert(position);
+            last = position;
+            crossings += 1;
+        }
+    }
+    let span = (last - first?) as u128;
+    if crossings < 2 || span == 0 {
+        return None;
+    }
+    let periods = (crossings - 1) as u128;
+    Some(mHz(
+        ((periods * msample_rate.0 as u128) << POSITION_FRAC).div_euclid(span) as u32,
+    ))
+}
+
+/// Returns the sum of squares of the bins around `bin`.
+fn power(spectrum: &[i16], bin: usize) -> u128 {
+    let lo = bin.saturating_sub(SPREAD);
+    let hi = (bin + SPREAD + 1).min(spectrum.len());
+    spectrum[lo.min(hi)..hi]
+        .iter()
+        .map(|x| (*x as i64 * *x as i64) as u128)
+        .sum()
+}
+
+/// Returns the square root of `part` relative to `total`, normalized to
+/// [SAMPLE_NORM].
+fn ratio(part: u128, total: u128) -> u32 {
+    if total == 0 {
+        return 0;
+    }
+    let norm = SAMPLE_NORM as u128;
+    (part * norm * norm / total).isqrt().min(u32::MAX as u128) as u32
+}
+
+/// Returns the total harmonic distortion of a Hann windowed `spectrum`, see
+/// [crate::analysis::spectrum::spectrum], with the fundamental in the center
+/// of bin `fundamental`
+///
+/// The THD is the RMS sum of the harmonics up to the end of the spectrum
+/// relative to the fundamental, as a gain normalized to [SAMPLE_NORM], so
+/// [crate::util::units::dB::from_gain] gives it in dB.
+///
+/// ```
+/// use isopod::analysis::quality::thd;
+/// use isopod::analysis::spectrum::{spectrum, Window};
+/// use isopod::util::units::dB;
+///
+/// // A sine in bin 16 with a second harmonic at a tenth of its amplitude
+/// let input: [i16; 1024] = core::array::from_fn(|n| {
+///     let phase = core::f64::consts::TAU * 16.0 * n as f64 / 1024.0;
+///     (10_000.0 * phase.sin() + 1_000.0 * (2.0 * phase).sin()) as i16
+/// });
+/// let mut out = [0; 512];
+/// spectrum(&input, Window::Hann, &mut out);
+/// assert_eq!(dB::from_gain(thd(&out, 16)), dB(-20));
+/// ```
+pub fn thd(spectrum: &[i16], fundamental: usize) -> u32 {
+    if fundamental == 0 {
+        return 0;
+    }
+    let harmonics = (2 * fundamental..spectrum.len())
+        .step_by(fundamental)
+        .map(|bin| power(spectrum, bin))
+        .sum();
+    ratio(harmonics, power(spectrum, fundamental))
+}
+
+/// Returns the RMS sum of everything but DC, the fundamental and its
+/// harmonics relative to the fundamental, normalized like [thd]
+///
+/// Harmonics above the Nyquist frequency fold back between the harmonics,
+/// so for an oscillator this is its aliasing, together with the noise of
+/// the fixed-point rendering.
+pub fn aliasing(spectrum: &[i16], fundamental: usize) -> u32 {
+    if fundamental == 0 {
+        return 0;
+    }
+    let rest = spectrum
+        .iter()
+        .enumerate()
+        .filter(|(k, _)| {
+            let offset = k % fundamental;
+            *k > SPREAD && offset > SPREAD && fundamental - offset > SPREAD
+        })
+        .map(|(_, x)| (*x as i64 * *x as i64) as u128)
+        .sum();
+    ratio(rest, power(spectrum, fundamental))
+}
+
+/// Returns the gain of `process` for a sine of `mfreq` at `amplitude`,
+/// normalized to [SAMPLE_NORM]
+///
+/// `process` replaces a block of samples with its output, e.g. the
+/// [crate::fx::filter::StateVariableFilter::process_block] of a filter. The
+/// gain is the ratio of the RMS levels of output and input after `settle`
+/// samples, measured over `len` samples.
+///
+/// ```
+/// use isopod::analysis::quality::measure_gain;
+/// use isopod::util::units::{mHz, SAMPLE_NORM};
+///
+/// let half = measure_gain(
+///     |block: &mut [i16]| block.iter_mut().for_each(|x| *x /= 2),
+///     mHz(1_000_000),
+///     mHz(48_000_000),
+///     16_000,
+///     1_000,
+///     4_800,
+/// );
+/// assert!(half.abs_diff(SAMPLE_NORM as u32 / 2) < 10);
+/// ```
+pub fn measure_gain<F: FnMut(&mut [i16])>(
+    mut process: F,
+    mfreq: mHz,
+    msample_rate: mHz,
+    amplitude: i16,
+    settle: usize,
+    len: usize,
+) -> u32 {
+    let mut sine = SineOscillator::new();
+    sine.set_msample_rate(msample_rate);
+    sine.set_mfreq(mfreq);
+    sine.start();
+    let scale = amplitude as i32;
+    let mut block = [0; 128];
+    let mut input = [0; 128];
+    let (mut sum_in, mut sum_out) = (0_u128, 0_u128);
+    let mut n = 0;
+    while n < settle + len {
+        for (x, y) in input.iter_mut().zip(block.iter_mut()) {
+            let s = sine.next().unwrap_or(0) as i32 * scale / SAMPLE_NORM;
+            (*x, *y) = (s as i16, s as i16);
+        }
+        process(&mut block);
+        for (x, y) in input.iter().zip(block.iter()) {
+            if n >= settle && n < settle + len {
+                sum_in += (*x as i64 * *x as i64) as u128;
+                sum_out += (*y as i64 * *y as i64) as u128;
+            }
+            n += 1;
+        }
+    }
+    ratio(sum_out, sum_in)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::spectrum::{bin_mfreq, peak_bin, spectrum, Window};
+    use crate::fx::filter::{StateVariableFilter, SvfOutput, Q_MAX};
+    use crate::osc::blep::BlepOscillator;
+    use crate::osc::luts::SINE_I16;
+    use crate::util::units::dB;
+
+    /// Returns the deviation of `measured` from `expected` in parts per
+    /// million.
+    fn ppm(measured: mHz, expected: mHz) -> i64 {
+        (measured.0 as i64 - expected.0 as i64) * 1_000_000 / expected.0 as i64
+    }
+
+    #[test]
+    fn test_estimate_mfreq() {
+        assert_eq!(estimate_mfreq(&[0; 100], mHz(48_000_000)), None);
+        // Crossings between samples are interpolated
+        let input: Vec<i16> = (0..10_000)
+            .map(|n| (10_000.0 * (n as f64 * core::f64::consts::TAU / 44.1).sin()) as i16)
+            .collect();
+        let mfreq = estimate_mfreq(&input, mHz(44_100_000)).unwrap();
+        assert!(ppm(mfreq, mHz(1_000_000)).abs() < 10, "{:?}", mfreq);
+    }
+
+    #[test]
+    fn test_thd_aliasing() {
+        // The table sine is clean up to its quantization
+        let input: [i16; 1024] = core::array::from_fn(|n| SINE_I16[(n * 8) % 1024]);
+        let mut out = [0; 512];
+        spectrum(&input, Window::Hann, &mut out);
+        assert!(dB::from_gain(thd(&out, 8)) <= dB(-80));
+        assert!(dB::from_gain(aliasing(&out, 8)) <= dB(-80));
+        assert_eq!(thd(&out, 0), 0);
+
+        // A component between the harmonics is aliasing
+        let input: [i16; 1024] =
+            core::array::from_fn(|n| SINE_I16[(n * 8) % 1024] / 2 + SINE_I16[(n * 37) % 1024] / 20);
+        spectrum(&input, Window::Hann, &mut out);
+        assert!(dB::from_gain(thd(&out, 8)) <= dB(-60));
+        assert_eq!(dB::from_gain(aliasing(&out, 8)), dB(-20));
+    }
+
+    #[test]
+    fn test_engine_pitch() {
+        for (freq, msample_rate) in [
+            (mHz(55_000), mHz(44_100_000)),
+            (mHz(440_000), mHz(44_100_000)),
+            (mHz(440_000), mHz(48_000_000)),
+            (mHz(1_234_567), mHz(48_000_000)),
+            (mHz(8_000_000), mHz(96_000_000)),
+        ] {
+            let mut sine = SineOscillator::new();
+            sine.set_msample_rate(msample_rate);
+            sine.set_mfreq(freq);
+            sine.start();
+            let mut out = vec![0; (msample_rate.0 / 1_000) as usize];
+            sine.render(&mut out);
+            let measured = estimate_mfreq(&out, msample_rate).unwrap();
+            // The phase increment is rounded down to 1 / 2^20 of a period,
+            // which is a step of 2^-20 * fs / f relative to the frequency
+            let bound = (1_000_000 * msample_rate.0 as u64 / ((freq.0 as u64) << 20)) as i64;
+            assert!(
+                (-bound - 5..=5).contains(&ppm(measured, freq)),
+                "{:?} {:?}",
+                freq,
+                measured
+            );
+        }
+    }
+
+    #[test]
+    fn test_engine_harmonics() {
+        let msample_rate = mHz(48_000_000);
+        let mut out = [0; 1024];
+        let mut bins = [0; 512];
+
+        // A sine from the table in the center of bin 20
+        let mut sine = SineOscillator::new();
+        sine.set_msample_rate(msample_rate);
+        sine.set_mfreq(bin_mfreq(20, 1024, msample_rate));
+        sine.start();
+        sine.render(&mut out);
+        sine.render(&mut out);
+        spectrum(&out, Window::Hann, &mut bins);
+        assert_eq!(peak_bin(&bins), 20);
+        assert!(dB::from_gain(thd(&bins, 20)) <= dB(-40));
+
+        // A saw has harmonics at 1 / n. The BLEP keeps most of those above
+        // Nyquist from folding back, unlike a naive saw.
+        let mut saw = BlepOscillator::new();
+        saw.set_msample_rate(msample_rate);
+        saw.set_mfreq(bin_mfreq(20, 1024, msample_rate));
+        saw.render(&mut out);
+        saw.render(&mut out);
+        spectrum(&out, Window::Hann, &mut bins);
+        assert_eq!(peak_bin(&bins), 20);
+        let saw_thd = dB::from_gain(thd(&bins, 20));
+        assert!((dB(-3)..=dB(-1)).contains(&saw_thd), "{:?}", saw_thd);
+        let blep = dB::from_gain(aliasing(&bins, 20));
+
+        let naive: [i16; 1024] = core::array::from_fn(|n| ((n * 20 * 64) % 65_536) as i16);
+        spectrum(&naive, Window::Hann, &mut bins);
+        let naive = dB::from_gain(aliasing(&bins, 20));
+        assert!(blep.0 <= naive.0 - 6, "{:?} {:?}", blep, naive);
+    }
+
+    /// Returns the frequency of the largest bandpass gain of a resonant SVF
+    /// tuned to `cutoff`, searched in steps of 0.5 %.
+    fn svf_peak(cutoff: mHz, msample_rate: mHz) -> mHz {
+        let gain = |mfreq: mHz| {
+            let mut filter = StateVariableFilter::<i16>::new();
+            filter.set_msample_rate(msample_rate);
+            filter.set_mfreq(cutoff);
+            filter.set_q(Q_MAX - Q_MAX / 16);
+            measure_gain(
+                |block: &mut [i16]| filter.process_block(block, SvfOutput::Bandpass),
+                mfreq,
+                msample_rate,
+                1_000,
+                4_096,
+                8_192,
+            )
+        };
+        (-40..=60)
+            .map(|step| mHz((cutoff.0 as i64 * (1_000 + 5 * step) / 1_000) as u32))
+            .max_by_key(|mfreq| gain(*mfreq))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_svf_cutoff_error() {
+        // The first order approximation of the tuning coefficient and the
+        // delay in the damping path detune the filter upwards towards its
+        // highest cutoff
+        let msample_rate = mHz(44_100_000);
+        for (cutoff, max_ppm) in [
+            (mHz(500_000), 5_000),
+            (mHz(2_000_000), 15_000),
+            (mHz(5_000_000), 45_000),
+            (mHz(7_000_000), 80_000),
+        ] {
+            let error = ppm(svf_peak(cutoff, msample_rate), cutoff);
+            assert!((0..=max_ppm).contains(&error), "{:?} {}", cutoff, error);
+        }
+    }
+
+    #[test]
+    fn test_measure_gain() {
+        let mut filter = StateVariableFilter::<i16>::new();
+        filter.set_mfreq(mHz(1_000_000));
+        let mut lowpass = |mfreq| {
+            measure_gain(
+                |block: &mut [i16]| filter.process_block(block, SvfOutput::Lowpass),
+                mfreq,
+                mHz(44_100_000),
+                8_000,
+                4_096,
+                8_192,
+            )
+        };
+        // Unity in the passband, -12 dB per octave above the cutoff
+        assert_eq!(dB::from_gain(lowpass(mHz(100_000))), dB(0));
+        let (octave, two) = (lowpass(mHz(4_000_000)), lowpass(mHz(8_000_000)));
+        let slope = dB::from_gain(two).0 - dB::from_gain(octave).0;
+        assert!((-13..=-11).contains(&slope), "{}", slope);
+    }
+}
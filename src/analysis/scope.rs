@@ -0,0 +1,257 @@
+// Oscilloscope tap recording the last samples of a signal for displays on
+// another thread.
+
+use crate::fx::Effect;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicI16, AtomicUsize, Ordering};
+
+/// Reads that are retried if the writer overtook them
+const RETRIES: usize = 4;
+
+/// Ring buffer shared by a [Scope] and its [ScopeReader]s
+struct Shared {
+    // A power of two long, so the indices continue across the wraparound
+    // of the counter
+    samples: Vec<AtomicI16>,
+    // Samples written since the start, wrapping around
+    written: AtomicUsize,
+    // True once all samples were written
+    full: AtomicBool,
+}
+
+/// Writing side of a scope, see [scope]
+pub struct Scope {
+    shared: Arc<Shared>,
+    written: usize,
+    full: bool,
+}
+
+impl Scope {
+    /// Records and returns the next sample.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        let samples = &self.shared.samples;
+        samples[self.written & (samples.len() - 1)].store(input, Ordering::Relaxed);
+        self.written = self.written.wrapping_add(1);
+        if !self.full && self.written == samples.len() {
+            self.full = true;
+            self.shared.full.store(true, Ordering::Relaxed);
+        }
+        self.shared.written.store(self.written, Ordering::Release);
+        input
+    }
+
+    /// Records a block of samples, e.g. the output of a synth.
+    pub fn process_block(&mut self, block: &[i16]) {
+        for x in block {
+            self.process(*x);
+        }
+    }
+}
+
+impl Effect for Scope {
+    fn process(&mut self, input: i16) -> i16 {
+        Scope::process(self, input)
+    }
+}
+
+/// Reading side of a scope, see [scope]
+#[derive(Clone)]
+pub struct ScopeReader {
+    shared: Arc<Shared>,
+    len: usize,
+    history: Vec<i16>,
+}
+
+impl ScopeReader {
+    /// Returns the number of samples that can be read at once.
+    pub fn get_len(&self) -> usize {
+        self.len
+    }
+
+    /// Copies the last samples into `history`, oldest first, and returns
+    /// the number of copied samples. Fewer than `len` are available until
+    /// the scope recorded that many.
+    fn read_history(&mut self, len: usize) -> usize {
+        let samples = &self.shared.samples;
+        let capacity = samples.len();
+        for _ in 0..RETRIES {
+            let end = self.shared.written.load(Ordering::Acquire);
+            let available = if self.shared.full.load(Ordering::Relaxed) {
+                capacity
+            } else {
+                end
+            };
+            let start = end.wrapping_sub(len.min(available));
+            self.history.clear();
+            self.history.extend(
+                (0..len.min(available)).map(|n| {
+                    samples[start.wrapping_add(n) & (capacity - 1)].load(Ordering::Relaxed)
+                }),
+            );
+            // Valid if the writer didn't wrap around onto the first sample
+            let written = self.shared.written.load(Ordering::Acquire);
+            if written.wrapping_sub(start) <= capacity {
+                return self.history.len();
+            }
+        }
+        self.history.len()
+    }
+
+    /// Fills `out` with the last samples, oldest first. Returns the number
+    /// of samples, which is less than the length of `out` while the scope
+    /// hasn't recorded enough yet or if `out` is longer than
+    /// [Self::get_len].
+    pub fn read(&mut self, out: &mut [i16]) -> usize {
+        let n = self.read_history(out.len().min(self.len));
+        out[..n].copy_from_slice(&self.history);
+        n
+    }
+
+    /// Fills `out` with samples starting at the latest rising edge through
+    /// `level`, so that periodic signals stand still on a display. Without
+    /// a complete trigger in the recorded samples this is like [Self::read]
+    /// and returns false.
+    pub fn read_triggered(&mut self, out: &mut [i16], level: i16) -> bool {
+        let len = out.len().min(self.len);
+        let n = self.read_history(self.len);
+        // The latest edge that leaves `len` samples after it
+        let edge = (1..(n + 1).saturating_sub(len))
+            .rev()
+            .find(|i| self.history[i - 1] < level && self.history[*i] >= level);
+        match edge {
+            Some(i) => {
+                out[..len].copy_from_slice(&self.history[i..i + len]);
+                true
+            }
+            None => {
+                let start = n.saturating_sub(len);
+                out[..n - start].copy_from_slice(&self.history[start..]);
+                false
+            }
+        }
+    }
+}
+
+/// Creates a scope that keeps the last `len` samples
+///
+/// The [Scope] is a pass-through tap for the audio thread, as an [Effect]
+/// or fed with rendered blocks. Any number of [ScopeReader]s copy the
+/// recorded samples from other threads, e.g. for a waveform display.
+/// Neither side blocks: the samples are atomics in a ring buffer of at
+/// least twice the length, and a read that the writer overtook is retried.
+///
+/// ```
+/// use isopod::analysis::scope::scope;
+/// use isopod::osc::luts::SINE_I16;
+///
+/// let (mut tap, mut reader) = scope(256);
+/// let ui = std::thread::spawn(move || {
+///     let mut out = [0; 64];
+///     while !reader.read_triggered(&mut out, 0) {}
+///     out
+/// });
+/// for n in 0.. {
+///     tap.process(SINE_I16[(n * 8) % 1024]);
+///     if ui.is_finished() {
+///         break;
+///     }
+/// }
+/// // Aligned to the zero crossing of the sine
+/// let out = ui.join().unwrap();
+/// assert_eq!(out[..2], [0, 1_608]);
+/// ```
+pub fn scope(len: usize) -> (Scope, ScopeReader) {
+    let len = len.max(1);
+    let shared = Arc::new(Shared {
+        samples: (0..(2 * len).next_power_of_two())
+            .map(|_| AtomicI16::new(0))
+            .collect(),
+        written: AtomicUsize::new(0),
+        full: AtomicBool::new(false),
+    });
+    let reader = ScopeReader {
+        shared: shared.clone(),
+        len,
+        history: Vec::with_capacity(len),
+    };
+    (
+        Scope {
+            shared,
+            written: 0,
+            full: false,
+        },
+        reader,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scope_read() {
+        let (mut tap, mut reader) = scope(4);
+        let mut out = [0; 4];
+        assert_eq!(reader.read(&mut out), 0);
+        tap.process_block(&[1, 2, 3]);
+        assert_eq!(reader.read(&mut out), 3);
+        assert_eq!(out[..3], [1, 2, 3]);
+        tap.process_block(&[4, 5, 6, 7, 8, 9]);
+        assert_eq!(reader.read(&mut out), 4);
+        assert_eq!(out, [6, 7, 8, 9]);
+        let mut long = [0; 6];
+        assert_eq!(reader.read(&mut long), 4);
+
+        // The counter wraps around
+        tap.written = usize::MAX - 2;
+        tap.process_block(&[1, 2, 3, 4, 5]);
+        assert_eq!(reader.read(&mut out), 4);
+        assert_eq!(out, [2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_scope_trigger() {
+        let (mut tap, mut reader) = scope(12);
+        let saw = |n: i16| (n % 5) * 100 - 200;
+        for n in 0..20 {
+            tap.process(saw(n));
+        }
+        // The history is n = 8 to 19, with edges through 50 at 13 and 18
+        let mut out = [0; 3];
+        assert!(reader.read_triggered(&mut out, 50));
+        assert_eq!(out, [100, 200, -200]);
+        let mut out = [0; 5];
+        assert!(reader.read_triggered(&mut out, 50));
+        assert_eq!(out, [saw(13), saw(14), saw(15), saw(16), saw(17)]);
+        // No edge through a level above the signal
+        assert!(!reader.read_triggered(&mut out, 1_000));
+        assert_eq!(out, [saw(15), saw(16), saw(17), saw(18), saw(19)]);
+    }
+
+    #[test]
+    fn test_scope_threads() {
+        let (mut tap, reader) = scope(1_000);
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let mut reader = reader.clone();
+                std::thread::spawn(move || {
+                    let mut out = [0; 1_000];
+                    for _ in 0..100 {
+                        let n = reader.read(&mut out);
+                        // A ramp read in one piece
+                        assert!(out[..n].windows(2).all(|w| w[1] == w[0].wrapping_add(1)));
+                    }
+                })
+            })
+            .collect();
+        let mut x = 0_i16;
+        while readers.iter().any(|r| !r.is_finished()) {
+            x = tap.process(x.wrapping_add(1));
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// Magnitude spectra of blocks of samples.
+
+use crate::analysis::fft::{cos_sin, fft, Complex, MAX_POINTS};
+use crate::util::units::{dB, mHz};
+
+/// Headroom bits of the input in the FFT, as many as keep all parts below
+/// 2^30
+const INPUT_SHIFT: u32 = 14;
+
+/// Window applied to a block before the FFT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// No window. Exact for sines in the center of a bin, but others leak
+    /// into all bins.
+    Rectangular,
+    /// Raised cosine, with the leakage falling by 18 dB per octave of bins
+    /// at the cost of spreading a sine over 3 bins.
+    Hann,
+}
+
+impl Window {
+    /// Returns the weight of sample `n` of `points` normalized to 2^15.
+    fn weight(self, n: usize, points: usize) -> i64 {
+        match self {
+            Window::Rectangular => 1 << 15,
+            // Periodic, so the sum of the weights is exactly half
+            Window::Hann => ((1 << 15) - cos_sin(n * (MAX_POINTS / points)).0 as i64) / 2,
+        }
+    }
+
+    /// Returns the factor that undoes the average weight, normalized to
+    /// 2^15.
+    fn gain(self) -> i64 {
+        match self {
+            Window::Rectangular => 1 << 15,
+            Window::Hann => 1 << 16,
+        }
+    }
+}
+
+/// Fills `out` with the amplitudes of the bins of `input`
+///
+/// Bin `k` is centered at `k / N` times the sample rate, see [bin_mfreq],
+/// and `out` holds up to `N / 2` bins from DC up to below the Nyquist
+/// frequency. The amplitudes are normalized like the samples and corrected
+/// for the window, so a sine in the center of a bin shows its amplitude in
+/// that bin. Amplitudes above full scale, e.g. of the fundamental of a full
+/// scale square wave, saturate.
+///
+/// ```
+/// use isopod::analysis::spectrum::{peak_bin, spectrum, Window};
+///
+/// // A sine with a period of 16 samples, i.e. in bin 64 of 1024
+/// let input: [i16; 1024] =
+///     core::array::from_fn(|n| isopod::osc::luts::SINE_I16[(n * 64) % 1024] / 2);
+/// let mut out = [0; 512];
+/// spectrum(&input, Window::Hann, &mut out);
+/// assert_eq!(peak_bin(&out), 64);
+/// assert!((16_300..16_400).contains(&out[64]));
+/// assert!(out[100] < 10);
+/// ```
+pub fn spectrum<const N: usize>(input: &[i16; N], window: Window, out: &mut [i16]) {
+    let mut data = [Complex::default(); N];
+    for (n, (y, x)) in data.iter_mut().zip(input.iter()).enumerate() {
+        y.re = ((*x as i64 * window.weight(n, N)) >> (15 - INPUT_SHIFT)) as i32;
+    }
+    fft(&mut data);
+    for (k, (y, x)) in out.iter_mut().zip(data.iter()).take(N / 2).enumerate() {
+        // Positive and negative frequencies add up, except at DC
+        let sides = if k == 0 { 1 } else { 2 };
+        let amplitude = (x.magnitude() as i64 * sides * window.gain()) >> (15 + INPUT_SHIFT);
+        *y = amplitude.min(i16::MAX as i64) as i16;
+    }
+}
+
+/// Returns the center frequency of bin `bin` of a spectrum of `points`
+/// samples.
+pub fn bin_mfreq(bin: usize, points: usize, msample_rate: mHz) -> mHz {
+    mHz((bin as u64 * msample_rate.0 as u64 / points.max(1) as u64) as u32)
+}
+
+/// Returns the bin with the largest amplitude, the lowest one of a tie.
+pub fn peak_bin(spectrum: &[i16]) -> usize {
+    spectrum
+        .iter()
+        .enumerate()
+        .fold(0, |peak, (k, x)| if *x > spectrum[peak] { k } else { peak })
+}
+
+/// Returns the level of an amplitude relative to full scale.
+pub fn to_db(amplitude: i16) -> dB {
+    dB::from_gain(amplitude.max(0) as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sine<const N: usize>(cycles: f64, amplitude: f64) -> [i16; N] {
+        core::array::from_fn(|n| {
+            let phase = core::f64::consts::TAU * cycles * n as f64 / N as f64;
+            (amplitude * phase.sin()).round() as i16
+        })
+    }
+
+    #[test]
+    fn test_spectrum() {
+        let mut out = [0; 128];
+        spectrum(&sine::<256>(20.0, 8_000.0), Window::Rectangular, &mut out);
+        assert!((7_990..=8_000).contains(&out[20]), "{}", out[20]);
+        assert!(out.iter().enumerate().all(|(k, x)| k == 20 || *x < 4));
+
+        // Between two bins the window keeps the leakage local
+        let mut rect = [0; 1024];
+        let mut hann = [0; 1024];
+        let input = sine::<2048>(100.5, 16_000.0);
+        spectrum(&input, Window::Rectangular, &mut rect);
+        spectrum(&input, Window::Hann, &mut hann);
+        assert!(
+            rect[120] > 200 && hann[120] < 4,
+            "{} {}",
+            rect[120],
+            hann[120]
+        );
+        assert!(hann[100] > 9_000 && hann[101] > 9_000);
+
+        // DC and a full scale square wave don't overflow
+        let mut out = [0; 256];
+        spectrum(&[-20_000; 512], Window::Hann, &mut out);
+        assert!((19_990..=20_000).contains(&out[0]), "{}", out[0]);
+        spectrum(
+            &core::array::from_fn::<_, 512, _>(|n| if n % 64 < 32 { i16::MAX } else { i16::MIN }),
+            Window::Rectangular,
+            &mut out,
+        );
+        assert_eq!(peak_bin(&out), 8);
+        assert_eq!(out[8], i16::MAX);
+        assert_eq!(to_db(out[24]), dB(-7));
+    }
+
+    #[test]
+    fn test_bin_mfreq() {
+        assert_eq!(bin_mfreq(1, 1024, mHz(48_000_000)), mHz(46_875));
+        assert_eq!(bin_mfreq(512, 1024, mHz(48_000_000)), mHz(24_000_000));
+    }
+}
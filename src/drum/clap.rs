@@ -0,0 +1,227 @@
+// Hand clap from bursts of band-passed noise and a diffuse tail.
+
+use crate::drum::{noise, velocity_gain, Decay};
+use crate::fx::filter::StateVariableFilter;
+use crate::osc::noise::LFSR;
+use crate::preset::Preset;
+use crate::synth::voice::Voice;
+use crate::util::gate::{Trigger, Triggered};
+use crate::util::units::{mHz, ms, Frequency, Hz};
+
+/// Center of the noise band
+const BAND_FREQ: Hz = Hz(1_200);
+/// Resonance of the noise band
+const BAND_Q: u32 = 2_048;
+/// Number of bursts, the tail starts with the last one
+const BURSTS: u8 = 4;
+
+/// Parameters of a [Clap]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClapParams {
+    /// Time between the bursts
+    pub spread: ms,
+    /// Decay of the tail
+    pub decay: ms,
+}
+
+/// Hand clap voice
+///
+/// A band of noise is retriggered [BURSTS] times in quick succession, like
+/// several hands that don't clap at exactly the same time, and the last
+/// burst rings out in a longer tail like a small room. The spread is the
+/// time between the bursts. Like the [crate::drum::kick::Kick], the clap
+/// is a one-shot.
+///
+/// ```
+/// use isopod::drum::clap::Clap;
+/// use isopod::synth::voice::Voice;
+///
+/// let mut clap = Clap::new();
+/// clap.note_on(39, 100);
+/// let mut out = [0; 441];
+/// clap.render(&mut out);
+/// assert!(out.iter().any(|y| y.abs() > 1_000));
+/// ```
+pub struct Clap {
+    filter: StateVariableFilter,
+    lfsr: LFSR<u32>,
+    burst: Decay,
+    tail: Decay,
+
+    spread: ms,
+    // Samples between and until the next burst
+    spacing: u32,
+    until_burst: u32,
+    bursts_left: u8,
+    gain: i16,
+
+    msample_rate: mHz,
+}
+
+impl Clap {
+    pub fn new() -> Self {
+        let msample_rate = mHz(44_100_000);
+        let mut s = Self {
+            filter: StateVariableFilter::new(),
+            lfsr: LFSR::<u32>::default(),
+            burst: Decay::new(ms(10), msample_rate),
+            tail: Decay::new(ms(250), msample_rate),
+
+            spread: ms(10),
+            spacing: 0,
+            until_burst: 0,
+            bursts_left: 0,
+            gain: 0,
+
+            msample_rate,
+        };
+        s.set_msample_rate(msample_rate);
+        s
+    }
+
+    #[inline]
+    fn sample(&mut self) -> i16 {
+        if self.bursts_left > 0 {
+            if self.until_burst == 0 {
+                self.burst.trigger(i16::MAX);
+                self.bursts_left -= 1;
+                self.until_burst = self.spacing;
+                if self.bursts_left == 0 {
+                    self.tail.trigger(i16::MAX / 2);
+                }
+            }
+            self.until_burst -= 1;
+        }
+        self.filter.feed(noise(&mut self.lfsr) / 2);
+        let env = self.burst._next() as i32 + self.tail._next() as i32;
+        let y = (self.filter.get_bp() as i32 * env.min(i16::MAX as i32)) >> 14;
+        let y = y.clamp(-(i16::MAX as i32), i16::MAX as i32);
+        ((y * self.gain as i32) >> 15) as i16
+    }
+
+    /// Starts a clap with `velocity` from 1 to 127.
+    pub fn trigger(&mut self, velocity: u8) {
+        self.gain = velocity_gain(velocity);
+        self.bursts_left = BURSTS;
+        self.until_burst = 0;
+    }
+
+    /// Sets the time between the bursts, which also decay in this time.
+    pub fn set_spread_ms(&mut self, spread: ms) {
+        self.spread = ms(spread.0.max(1));
+        self.spacing =
+            ((self.spread.0 as u64 * self.msample_rate.0 as u64) / 1_000_000).max(1) as u32;
+        self.burst.set_time(self.spread, self.msample_rate);
+    }
+
+    /// Sets the decay of the tail.
+    pub fn set_decay_ms(&mut self, decay: ms) {
+        self.tail.set_time(decay, self.msample_rate);
+    }
+
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.filter.set_msample_rate(self.msample_rate);
+        self.filter.set_mfreq(BAND_FREQ.to_mHz());
+        self.filter.set_q(BAND_Q);
+        self.set_spread_ms(self.spread);
+        self.set_decay_ms(self.tail.get_time());
+    }
+}
+
+impl Default for Clap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for Clap {
+    type Params = ClapParams;
+
+    fn get_params(&self) -> ClapParams {
+        ClapParams {
+            spread: self.spread,
+            decay: self.tail.get_time(),
+        }
+    }
+
+    fn set_params(&mut self, params: &ClapParams) {
+        self.set_spread_ms(params.spread);
+        self.set_decay_ms(params.decay);
+    }
+}
+
+impl Voice for Clap {
+    /// Starts a clap. The note is ignored.
+    fn note_on(&mut self, _note: u8, velocity: u8) {
+        self.trigger(velocity);
+    }
+
+    fn set_note(&mut self, _note: u8) {}
+
+    /// Ignored, the clap has a fixed band.
+    fn set_mfreq(&mut self, _mfreq: mHz) {}
+
+    fn note_off(&mut self) {}
+
+    fn is_active(&self) -> bool {
+        self.bursts_left > 0 || self.burst.is_active() || self.tail.is_active()
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self.sample();
+        }
+    }
+}
+
+impl Triggered for Clap {
+    fn trigger(&mut self, trigger: Trigger) {
+        Clap::trigger(self, trigger.velocity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn peak(out: &[i16]) -> i16 {
+        out.iter().map(|y| y.abs()).max().unwrap()
+    }
+
+    #[test]
+    fn test_clap_bursts() {
+        let mut clap = Clap::new();
+        clap.note_on(39, 127);
+        let mut out = vec![0; 4 * 441];
+        clap.render(&mut out);
+        // Each burst is louder than the end of the previous one
+        for k in 1..BURSTS as usize {
+            let (before, after) = (&out[k * 441 - 60..k * 441], &out[k * 441..k * 441 + 60]);
+            assert!(peak(after) > 2 * peak(before), "{}", k);
+        }
+    }
+
+    #[test]
+    fn test_clap_tail() {
+        let render = |decay| {
+            let mut clap = Clap::new();
+            clap.set_params(&ClapParams {
+                spread: ms(5),
+                decay,
+            });
+            assert_eq!(clap.get_params().spread, ms(5));
+            clap.note_on(39, 127);
+            let mut n = 0;
+            let mut out = [0; 64];
+            while clap.is_active() {
+                clap.render(&mut out);
+                n += out.len();
+            }
+            n
+        };
+        let (short, long) = (render(ms(50)), render(ms(400)));
+        assert!(long > 4 * short, "{} {}", short, long);
+    }
+}
@@ -0,0 +1,275 @@
+// Ride and crash cymbals from the metallic square stack split into bands.
+
+use crate::drum::{velocity_gain, Decay, Metallic};
+use crate::fx::filter::StateVariableFilter;
+use crate::preset::Preset;
+use crate::synth::voice::Voice;
+use crate::util::gate::{Trigger, Triggered};
+use crate::util::units::{mHz, ms, Frequency, Hz};
+
+/// Center of the band of the bell
+const BELL_FREQ: Hz = Hz(3_400);
+/// Cutoff of the shimmer band
+const SHIMMER_FREQ: Hz = Hz(8_000);
+/// Resonance of both bands
+const BAND_Q: u32 = 1_024;
+/// Decay of the strike, the first stage of the shimmer envelope
+const STRIKE_TIME: ms = ms(80);
+/// Decay after a choke
+const CHOKE_TIME: ms = ms(30);
+
+/// Parameters of a [Cymbal]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CymbalParams {
+    /// Pitch of the lowest square
+    pub tune: Hz,
+    /// Decay of the shimmer. The bell decays in a third of the time.
+    pub decay: ms,
+    /// Level of the bell from 0 to i16::MAX
+    pub bell: i16,
+}
+
+impl CymbalParams {
+    pub const RIDE: CymbalParams = CymbalParams {
+        tune: Hz(330),
+        decay: ms(1_500),
+        bell: i16::MAX / 2,
+    };
+    pub const CRASH: CymbalParams = CymbalParams {
+        tune: Hz(250),
+        decay: ms(2_000),
+        bell: i16::MAX / 8,
+    };
+}
+
+/// Cymbal voice
+///
+/// The metallic stack of the [crate::drum::hihat::HiHat] is split into a
+/// band around a few kHz for the bell and a high band for the shimmer. The
+/// shimmer has two stages, a loud strike that decays quickly and a long
+/// tail, while the bell decays in between. Rides have more bell, crashes
+/// more shimmer, see [CymbalParams::RIDE] and [CymbalParams::CRASH].
+///
+/// ```
+/// use isopod::drum::cymbal::{Cymbal, CymbalParams};
+/// use isopod::preset::Preset;
+/// use isopod::synth::voice::Voice;
+///
+/// let mut crash = Cymbal::new();
+/// crash.set_params(&CymbalParams::CRASH);
+/// crash.note_on(49, 100);
+/// let mut out = [0; 44_100];
+/// crash.render(&mut out);
+/// assert!(crash.is_active());
+/// ```
+pub struct Cymbal {
+    metallic: Metallic,
+    bell_filter: StateVariableFilter,
+    shimmer_filter: StateVariableFilter,
+    strike: Decay,
+    tail: Decay,
+    bell_env: Decay,
+
+    tune: mHz,
+    decay: ms,
+    bell: i16,
+    gain: i16,
+
+    msample_rate: mHz,
+}
+
+impl Cymbal {
+    pub fn new() -> Self {
+        let msample_rate = mHz(44_100_000);
+        let params = CymbalParams::RIDE;
+        let mut s = Self {
+            metallic: Metallic::new(),
+            bell_filter: StateVariableFilter::new(),
+            shimmer_filter: StateVariableFilter::new(),
+            strike: Decay::new(STRIKE_TIME, msample_rate),
+            tail: Decay::new(params.decay, msample_rate),
+            bell_env: Decay::new(params.decay, msample_rate),
+
+            tune: params.tune.to_mHz(),
+            decay: params.decay,
+            bell: params.bell,
+            gain: 0,
+
+            msample_rate,
+        };
+        s.set_msample_rate(msample_rate);
+        s
+    }
+
+    #[inline]
+    fn sample(&mut self) -> i16 {
+        let x = self.metallic._next();
+        self.bell_filter.feed(x);
+        self.shimmer_filter.feed(x);
+        let bell_level = (self.bell_env._next() as i32 * self.bell as i32) >> 15;
+        let bell = self.bell_filter.get_bp() as i32 * bell_level;
+        let shimmer_level =
+            (self.strike._next() as i32 + self.tail._next() as i32).min(i16::MAX as i32);
+        let shimmer = self.shimmer_filter.get_hp() as i32 * shimmer_level;
+        let y = ((bell + shimmer) >> 14).clamp(-(i16::MAX as i32), i16::MAX as i32);
+        ((y * self.gain as i32) >> 15) as i16
+    }
+
+    /// Starts a hit with `velocity` from 1 to 127.
+    pub fn trigger(&mut self, velocity: u8) {
+        self.gain = velocity_gain(velocity);
+        self.set_decay_ms(self.decay);
+        self.strike.set_time(STRIKE_TIME, self.msample_rate);
+        self.strike.trigger(i16::MAX / 4 * 3);
+        self.tail.trigger(i16::MAX / 4);
+        self.bell_env.trigger(i16::MAX);
+    }
+
+    /// Fades out the ringing cymbal quickly, as when it is grabbed.
+    pub fn choke(&mut self) {
+        for env in [&mut self.strike, &mut self.tail, &mut self.bell_env] {
+            env.set_time(CHOKE_TIME, self.msample_rate);
+        }
+    }
+
+    /// Sets the pitch of the lowest square.
+    pub fn set_tune(&mut self, tune: mHz) {
+        self.tune = tune;
+        self.metallic.set_mfreq(tune, self.msample_rate);
+    }
+
+    /// Sets the decay of the shimmer. The bell decays in a third of the
+    /// time.
+    pub fn set_decay_ms(&mut self, decay: ms) {
+        self.decay = decay;
+        self.tail.set_time(decay, self.msample_rate);
+        self.bell_env.set_time(ms(decay.0 / 3), self.msample_rate);
+    }
+
+    /// Sets the level of the bell from 0 to i16::MAX.
+    pub fn set_bell(&mut self, bell: i16) {
+        self.bell = bell.max(0);
+    }
+
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        for (filter, freq) in [
+            (&mut self.bell_filter, BELL_FREQ),
+            (&mut self.shimmer_filter, SHIMMER_FREQ),
+        ] {
+            filter.set_msample_rate(self.msample_rate);
+            filter.set_mfreq(freq.to_mHz());
+            filter.set_q(BAND_Q);
+        }
+        self.strike.set_time(STRIKE_TIME, self.msample_rate);
+        self.set_decay_ms(self.decay);
+        self.set_tune(self.tune);
+    }
+}
+
+impl Default for Cymbal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for Cymbal {
+    type Params = CymbalParams;
+
+    fn get_params(&self) -> CymbalParams {
+        CymbalParams {
+            tune: self.tune.to_Hz(),
+            decay: self.decay,
+            bell: self.bell,
+        }
+    }
+
+    fn set_params(&mut self, params: &CymbalParams) {
+        self.set_tune(params.tune.to_mHz());
+        self.set_decay_ms(params.decay);
+        self.set_bell(params.bell);
+    }
+}
+
+impl Voice for Cymbal {
+    /// Starts a hit. The note is ignored, the pitch is set by the tune.
+    fn note_on(&mut self, _note: u8, velocity: u8) {
+        self.trigger(velocity);
+    }
+
+    fn set_note(&mut self, _note: u8) {}
+
+    /// Sets the tune.
+    fn set_mfreq(&mut self, mfreq: mHz) {
+        self.set_tune(mfreq);
+    }
+
+    fn note_off(&mut self) {}
+
+    fn is_active(&self) -> bool {
+        self.strike.is_active() || self.tail.is_active() || self.bell_env.is_active()
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self.sample();
+        }
+    }
+}
+
+impl Triggered for Cymbal {
+    fn trigger(&mut self, trigger: Trigger) {
+        Cymbal::trigger(self, trigger.velocity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rms(out: &[i16]) -> i64 {
+        let power: i64 = out.iter().map(|y| (*y as i64).pow(2)).sum();
+        (power / out.len() as i64).isqrt()
+    }
+
+    fn crossings(out: &[i16]) -> usize {
+        out.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count()
+    }
+
+    #[test]
+    fn test_cymbal_stages() {
+        let mut crash = Cymbal::new();
+        crash.set_params(&CymbalParams::CRASH);
+        assert_eq!(crash.get_params(), CymbalParams::CRASH);
+        crash.note_on(49, 127);
+        let mut out = vec![0; 44_100];
+        crash.render(&mut out);
+        // The strike falls fast, then the tail rings for seconds
+        let (strike, early_tail, late_tail) = (
+            rms(&out[..882]),
+            rms(&out[8_820..13_230]),
+            rms(&out[39_690..]),
+        );
+        assert!(strike > 3 * early_tail, "{} {}", strike, early_tail);
+        assert!(late_tail > early_tail / 10, "{} {}", early_tail, late_tail);
+
+        crash.choke();
+        crash.render(&mut out[..4_410]);
+        assert!(!crash.is_active());
+    }
+
+    #[test]
+    fn test_cymbal_bands() {
+        let render = |bell| {
+            let mut ride = Cymbal::new();
+            ride.set_bell(bell);
+            ride.note_on(51, 127);
+            let mut out = vec![0; 22_050];
+            ride.render(&mut out);
+            crossings(&out[4_410..])
+        };
+        // The bell band lowers the brightness
+        assert!(render(i16::MAX) < render(0) * 4 / 5);
+    }
+}
@@ -0,0 +1,269 @@
+// Hi-hat from a metallic square stack or noise through a high-pass filter.
+
+use crate::drum::{noise, velocity_gain, Decay, Metallic};
+use crate::fx::filter::StateVariableFilter;
+use crate::osc::noise::LFSR;
+use crate::preset::Preset;
+use crate::synth::voice::Voice;
+use crate::util::gate::{Trigger, Triggered};
+use crate::util::units::{mHz, ms, Frequency, Hz};
+
+/// Cutoff of the high-pass filter
+const CUTOFF: Hz = Hz(7_000);
+/// Resonance of the high-pass filter
+const RESONANCE: u32 = 1_536;
+/// Decay after a choke
+const CHOKE_TIME: ms = ms(8);
+
+/// Sound source of a [HiHat]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HiHatSource {
+    /// Six detuned squares, the metallic ring of analog drum machines.
+    Metallic,
+    /// White noise, which sounds brighter and less pitched.
+    Noise,
+}
+
+/// Parameters of a [HiHat]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HiHatParams {
+    pub source: HiHatSource,
+    /// Pitch of the lowest square
+    pub tune: Hz,
+    pub closed_decay: ms,
+    pub open_decay: ms,
+    pub open: bool,
+}
+
+/// Hi-hat voice
+///
+/// The source runs through a resonant high-pass filter and a decay, which
+/// is short when closed and long when open. [HiHat::choke] cuts a ringing
+/// hat within a few milliseconds, as a closed hat does to an open one.
+///
+/// ```
+/// use isopod::drum::hihat::HiHat;
+/// use isopod::synth::voice::Voice;
+///
+/// let mut hat = HiHat::new();
+/// hat.set_open(true);
+/// hat.note_on(46, 100);
+/// let mut out = [0; 1_024];
+/// hat.render(&mut out);
+/// hat.choke();
+/// hat.render(&mut out);
+/// assert!(!hat.is_active());
+/// ```
+pub struct HiHat {
+    metallic: Metallic,
+    lfsr: LFSR<u32>,
+    filter: StateVariableFilter,
+    amp: Decay,
+
+    source: HiHatSource,
+    tune: mHz,
+    closed_decay: ms,
+    open_decay: ms,
+    open: bool,
+    gain: i16,
+
+    msample_rate: mHz,
+}
+
+impl HiHat {
+    pub fn new() -> Self {
+        let msample_rate = mHz(44_100_000);
+        let mut s = Self {
+            metallic: Metallic::new(),
+            lfsr: LFSR::<u32>::default(),
+            filter: StateVariableFilter::new(),
+            amp: Decay::new(ms(60), msample_rate),
+
+            source: HiHatSource::Metallic,
+            tune: Hz(205).to_mHz(),
+            closed_decay: ms(60),
+            open_decay: ms(500),
+            open: false,
+            gain: 0,
+
+            msample_rate,
+        };
+        s.set_msample_rate(msample_rate);
+        s
+    }
+
+    #[inline]
+    fn sample(&mut self) -> i16 {
+        let x = match self.source {
+            HiHatSource::Metallic => self.metallic._next(),
+            HiHatSource::Noise => noise(&mut self.lfsr) / 2,
+        };
+        self.filter.feed(x);
+        let y = (self.filter.get_hp() as i32 * self.amp._next() as i32) >> 15;
+        ((y * self.gain as i32) >> 14).clamp(-(i16::MAX as i32), i16::MAX as i32) as i16
+    }
+
+    /// Starts a hit with `velocity` from 1 to 127.
+    pub fn trigger(&mut self, velocity: u8) {
+        self.gain = velocity_gain(velocity);
+        let decay = if self.open {
+            self.open_decay
+        } else {
+            self.closed_decay
+        };
+        self.amp.set_time(decay, self.msample_rate);
+        self.amp.trigger(i16::MAX);
+    }
+
+    /// Fades out the ringing hat within a few milliseconds.
+    pub fn choke(&mut self) {
+        self.amp.set_time(CHOKE_TIME, self.msample_rate);
+    }
+
+    pub fn set_source(&mut self, source: HiHatSource) {
+        self.source = source;
+    }
+
+    /// Sets the pitch of the lowest square of the metallic source.
+    pub fn set_tune(&mut self, tune: mHz) {
+        self.tune = tune;
+        self.metallic.set_mfreq(tune, self.msample_rate);
+    }
+
+    /// Plays the open or the closed decay from the next hit.
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    pub fn set_closed_decay_ms(&mut self, decay: ms) {
+        self.closed_decay = decay;
+    }
+
+    pub fn set_open_decay_ms(&mut self, decay: ms) {
+        self.open_decay = decay;
+    }
+
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.filter.set_msample_rate(self.msample_rate);
+        self.filter.set_mfreq(CUTOFF.to_mHz());
+        self.filter.set_q(RESONANCE);
+        self.amp.set_time(self.amp.get_time(), self.msample_rate);
+        self.set_tune(self.tune);
+    }
+}
+
+impl Default for HiHat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for HiHat {
+    type Params = HiHatParams;
+
+    fn get_params(&self) -> HiHatParams {
+        HiHatParams {
+            source: self.source,
+            tune: self.tune.to_Hz(),
+            closed_decay: self.closed_decay,
+            open_decay: self.open_decay,
+            open: self.open,
+        }
+    }
+
+    fn set_params(&mut self, params: &HiHatParams) {
+        self.set_source(params.source);
+        self.set_tune(params.tune.to_mHz());
+        self.set_closed_decay_ms(params.closed_decay);
+        self.set_open_decay_ms(params.open_decay);
+        self.set_open(params.open);
+    }
+}
+
+impl Voice for HiHat {
+    /// Starts a hit. The note is ignored, the pitch is set by the tune.
+    fn note_on(&mut self, _note: u8, velocity: u8) {
+        self.trigger(velocity);
+    }
+
+    fn set_note(&mut self, _note: u8) {}
+
+    /// Sets the tune.
+    fn set_mfreq(&mut self, mfreq: mHz) {
+        self.set_tune(mfreq);
+    }
+
+    fn note_off(&mut self) {}
+
+    fn is_active(&self) -> bool {
+        self.amp.is_active()
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self.sample();
+        }
+    }
+}
+
+impl Triggered for HiHat {
+    fn trigger(&mut self, trigger: Trigger) {
+        HiHat::trigger(self, trigger.velocity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns the number of samples until the hat falls silent.
+    fn length(hat: &mut HiHat) -> usize {
+        hat.note_on(42, 127);
+        let mut out = [0; 64];
+        let mut n = 0;
+        while hat.is_active() {
+            hat.render(&mut out);
+            n += out.len();
+        }
+        n
+    }
+
+    #[test]
+    fn test_hihat_open_closed() {
+        let mut hat = HiHat::new();
+        let closed = length(&mut hat);
+        hat.set_open(true);
+        let open = length(&mut hat);
+        assert!(open > 5 * closed, "{} {}", open, closed);
+        assert!((4_000..7_000).contains(&closed), "{}", closed);
+
+        // Choking a ringing open hat
+        hat.note_on(46, 127);
+        let mut out = vec![0; 4_410];
+        hat.render(&mut out[..441]);
+        hat.choke();
+        hat.render(&mut out);
+        assert!(!hat.is_active());
+        // The next hit has the full decay again
+        assert_eq!(length(&mut hat), open);
+    }
+
+    #[test]
+    fn test_hihat_sources() {
+        let mut hat = HiHat::new();
+        for source in [HiHatSource::Metallic, HiHatSource::Noise] {
+            hat.set_source(source);
+            hat.note_on(42, 127);
+            let mut out = vec![0; 441];
+            hat.render(&mut out);
+            let peak = out.iter().map(|y| y.abs()).max().unwrap();
+            assert!(peak > 5_000, "{:?} {}", source, peak);
+            // High-passed into mostly alternating samples
+            let crossings = out.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count();
+            assert!(crossings > 100, "{:?} {}", source, crossings);
+        }
+    }
+}
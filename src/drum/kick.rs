@@ -0,0 +1,243 @@
+// Kick drum from a sine with a pitch sweep, in the manner of analog drum
+// machines.
+
+use crate::drum::{delta_phi, noise, velocity_gain, Decay};
+use crate::osc::fm::FmOperator;
+use crate::osc::noise::LFSR;
+use crate::preset::Preset;
+use crate::synth::voice::Voice;
+use crate::util::gate::{Trigger, Triggered};
+use crate::util::units::{mHz, ms, Frequency, Hz};
+
+/// Time of the pitch sweep at full punch
+const SWEEP_TIME: ms = ms(60);
+/// Pitch at the start of the sweep at full punch relative to the tune
+const SWEEP_RATIO: u64 = 4;
+/// Time of the click transient
+const CLICK_TIME: ms = ms(5);
+
+/// Parameters of a [Kick]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KickParams {
+    /// Pitch at the end of the sweep
+    pub tune: Hz,
+    pub decay: ms,
+    /// Depth of the pitch sweep and level of the click from 0 to i16::MAX
+    pub punch: i16,
+}
+
+/// Kick drum voice
+///
+/// A sine starts at up to [SWEEP_RATIO] times the tune and falls
+/// exponentially to it, while a short noise click marks the attack. Punch
+/// sets both the sweep and the click, from a soft boom at 0 to a hard
+/// beater at full scale. The kick is a one-shot, so notes only set the
+/// velocity and note offs are ignored.
+///
+/// ```
+/// use isopod::drum::kick::Kick;
+/// use isopod::synth::voice::Voice;
+///
+/// let mut kick = Kick::new();
+/// kick.note_on(36, 127);
+/// let mut out = [0; 441];
+/// kick.render(&mut out);
+/// assert!(out.iter().any(|y| y.abs() > 10_000));
+/// ```
+pub struct Kick {
+    amp: Decay,
+    pitch: Decay,
+    click: Decay,
+    lfsr: LFSR<u32>,
+
+    phi: u32,
+    // Phase increment of the tune
+    delta_phi: u32,
+    tune: mHz,
+    punch: i16,
+    // Velocity gain of the current hit
+    gain: i16,
+
+    msample_rate: mHz,
+}
+
+impl Kick {
+    pub fn new() -> Self {
+        let msample_rate = mHz(44_100_000);
+        let mut s = Self {
+            amp: Decay::new(ms(400), msample_rate),
+            pitch: Decay::new(SWEEP_TIME, msample_rate),
+            click: Decay::new(CLICK_TIME, msample_rate),
+            lfsr: LFSR::<u32>::default(),
+
+            phi: 0,
+            delta_phi: 0,
+            tune: Hz(50).to_mHz(),
+            punch: i16::MAX / 2,
+            gain: 0,
+
+            msample_rate,
+        };
+        s.set_msample_rate(msample_rate);
+        s
+    }
+
+    /// Returns the next sample.
+    #[inline]
+    fn sample(&mut self) -> i16 {
+        let sweep = self.pitch._next() as u64 * self.punch as u64;
+        let delta =
+            self.delta_phi as u64 * (1 << 30) + self.delta_phi as u64 * (SWEEP_RATIO - 1) * sweep;
+        let body = (FmOperator::sine(self.phi) as i32 * self.amp._next() as i32) >> 15;
+        self.phi = self.phi.wrapping_add((delta >> 30) as u32);
+        let click = (noise(&mut self.lfsr) as i32 * self.click._next() as i32) >> 17;
+        let y = (body + click).clamp(-(i16::MAX as i32), i16::MAX as i32);
+        ((y * self.gain as i32) >> 15) as i16
+    }
+
+    /// Starts a hit with `velocity` from 1 to 127.
+    pub fn trigger(&mut self, velocity: u8) {
+        self.gain = velocity_gain(velocity);
+        self.phi = 0;
+        self.amp.trigger(i16::MAX);
+        self.pitch.trigger(i16::MAX);
+        self.click.trigger(self.punch);
+    }
+
+    /// Sets the pitch at the end of the sweep.
+    pub fn set_tune(&mut self, tune: mHz) {
+        self.tune = tune;
+        self.delta_phi = delta_phi(tune, self.msample_rate);
+    }
+
+    pub fn get_tune(&self) -> mHz {
+        self.tune
+    }
+
+    pub fn set_decay_ms(&mut self, decay: ms) {
+        self.amp.set_time(decay, self.msample_rate);
+    }
+
+    /// Sets the depth of the pitch sweep and the level of the click from 0
+    /// to i16::MAX.
+    pub fn set_punch(&mut self, punch: i16) {
+        self.punch = punch.max(0);
+    }
+
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.amp.set_time(self.amp.get_time(), self.msample_rate);
+        self.pitch.set_time(SWEEP_TIME, self.msample_rate);
+        self.click.set_time(CLICK_TIME, self.msample_rate);
+        self.set_tune(self.tune);
+    }
+}
+
+impl Default for Kick {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for Kick {
+    type Params = KickParams;
+
+    fn get_params(&self) -> KickParams {
+        KickParams {
+            tune: self.tune.to_Hz(),
+            decay: self.amp.get_time(),
+            punch: self.punch,
+        }
+    }
+
+    fn set_params(&mut self, params: &KickParams) {
+        self.set_tune(params.tune.to_mHz());
+        self.set_decay_ms(params.decay);
+        self.set_punch(params.punch);
+    }
+}
+
+impl Voice for Kick {
+    /// Starts a hit. The note is ignored, the pitch is set by the tune.
+    fn note_on(&mut self, _note: u8, velocity: u8) {
+        self.trigger(velocity);
+    }
+
+    fn set_note(&mut self, _note: u8) {}
+
+    /// Sets the tune.
+    fn set_mfreq(&mut self, mfreq: mHz) {
+        self.set_tune(mfreq);
+    }
+
+    fn note_off(&mut self) {}
+
+    fn is_active(&self) -> bool {
+        self.amp.is_active()
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self.sample();
+        }
+    }
+}
+
+impl Triggered for Kick {
+    fn trigger(&mut self, trigger: Trigger) {
+        Kick::trigger(self, trigger.velocity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn crossings(out: &[i16]) -> usize {
+        out.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count()
+    }
+
+    #[test]
+    fn test_kick_sweep() {
+        let mut kick = Kick::new();
+        kick.set_punch(0);
+        kick.note_on(36, 127);
+        // 10 periods of 50 Hz in 200 ms
+        let mut out = vec![0; 8_820];
+        kick.render(&mut out);
+        assert!((19..=21).contains(&crossings(&out)), "{}", crossings(&out));
+
+        // The sweep starts higher and ends at the tune
+        kick.set_punch(i16::MAX);
+        kick.note_on(36, 127);
+        kick.render(&mut out);
+        let (early, late) = (crossings(&out[..2_205]), crossings(&out[6_615..]));
+        assert!(early > 2 * late, "{} {}", early, late);
+        assert!((4..=6).contains(&late), "{}", late);
+    }
+
+    #[test]
+    fn test_kick_decay() {
+        let mut kick = Kick::new();
+        kick.set_params(&KickParams {
+            tune: Hz(60),
+            decay: ms(100),
+            punch: i16::MAX / 2,
+        });
+        assert_eq!(kick.get_params().decay, ms(100));
+        assert!(!kick.is_active());
+        kick.note_on(36, 64);
+        let mut out = vec![0; 4_410];
+        kick.render(&mut out);
+        let peak = |out: &[i16]| out.iter().map(|y| y.abs()).max().unwrap();
+        assert!(peak(&out[..441]) > 4 * peak(&out[3_969..]));
+        assert!(peak(&out) < i16::MAX / 2 + 1_000);
+        // Note offs don't cut the decay
+        kick.note_off();
+        kick.render(&mut out);
+        assert!(kick.is_active());
+        kick.render(&mut out);
+        assert!(!kick.is_active());
+    }
+}
@@ -0,0 +1,351 @@
+// Drum kit mapping notes to percussion voices and mixing them to stereo.
+
+use crate::drum::clap::Clap;
+use crate::drum::cymbal::Cymbal;
+use crate::drum::hihat::HiHat;
+use crate::drum::kick::Kick;
+use crate::drum::perc::PercVoice;
+use crate::drum::snare::Snare;
+use crate::drum::tom::{Tom, TomParams};
+use crate::fx::panner::Panner;
+use crate::preset::Preset;
+use crate::synth::voice::Voice;
+use crate::synth::NoteEvent;
+use crate::util::diag;
+use crate::util::units::{mHz, Frame};
+
+/// Number of samples rendered per voice at a time
+const BLOCK: usize = 64;
+
+/// One of the percussion voices of a [DrumKit]
+pub enum DrumVoice {
+    Kick(Kick),
+    Snare(Snare),
+    HiHat(HiHat),
+    Clap(Clap),
+    Tom(Tom),
+    Cymbal(Cymbal),
+    Perc(PercVoice),
+}
+
+impl DrumVoice {
+    fn as_voice(&mut self) -> &mut dyn Voice {
+        match self {
+            DrumVoice::Kick(v) => v,
+            DrumVoice::Snare(v) => v,
+            DrumVoice::HiHat(v) => v,
+            DrumVoice::Clap(v) => v,
+            DrumVoice::Tom(v) => v,
+            DrumVoice::Cymbal(v) => v,
+            DrumVoice::Perc(v) => v,
+        }
+    }
+
+    /// Fades out a ringing hi-hat or cymbal. Other voices ignore it.
+    pub fn choke(&mut self) {
+        match self {
+            DrumVoice::HiHat(v) => v.choke(),
+            DrumVoice::Cymbal(v) => v.choke(),
+            _ => {}
+        }
+    }
+
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        match self {
+            DrumVoice::Kick(v) => v.set_msample_rate(msample_rate),
+            DrumVoice::Snare(v) => v.set_msample_rate(msample_rate),
+            DrumVoice::HiHat(v) => v.set_msample_rate(msample_rate),
+            DrumVoice::Clap(v) => v.set_msample_rate(msample_rate),
+            DrumVoice::Tom(v) => v.set_msample_rate(msample_rate),
+            DrumVoice::Cymbal(v) => v.set_msample_rate(msample_rate),
+            DrumVoice::Perc(v) => v.set_msample_rate(msample_rate),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        match self {
+            DrumVoice::Kick(v) => v.is_active(),
+            DrumVoice::Snare(v) => v.is_active(),
+            DrumVoice::HiHat(v) => v.is_active(),
+            DrumVoice::Clap(v) => v.is_active(),
+            DrumVoice::Tom(v) => v.is_active(),
+            DrumVoice::Cymbal(v) => v.is_active(),
+            DrumVoice::Perc(v) => v.is_active(),
+        }
+    }
+}
+
+macro_rules! impl_from_voice {
+    ($($voice:ident => $variant:ident),*) => {
+        $(impl From<$voice> for DrumVoice {
+            fn from(voice: $voice) -> Self {
+                DrumVoice::$variant(voice)
+            }
+        })*
+    };
+}
+
+impl_from_voice!(Kick => Kick, Snare => Snare, HiHat => HiHat, Clap => Clap, Tom => Tom,
+    Cymbal => Cymbal, PercVoice => Perc);
+
+/// Pad of a [DrumKit]
+struct Pad {
+    note: u8,
+    voice: DrumVoice,
+    level: i16,
+    panner: Panner,
+    choke_group: Option<u8>,
+}
+
+/// Kit of `N` pads
+///
+/// Every pad plays its voice on one note, with its own level, pan and
+/// tune. Pads in the same choke group cut each other off, e.g. a closed
+/// hi-hat silences a ringing open one. [DrumKit::render] mixes all pads
+/// into stereo frames. [DrumKit::standard] sets up the General MIDI notes.
+///
+/// ```
+/// use isopod::drum::kit::DrumKit;
+/// use isopod::util::units::Frame;
+///
+/// let mut kit = DrumKit::standard();
+/// kit.note_on(36, 127);
+/// kit.note_on(42, 100);
+/// let mut out = [Frame::mono(0); 441];
+/// kit.render(&mut out);
+/// assert!(out.iter().any(|y| y.left.0.abs() > 1_000));
+/// ```
+pub struct DrumKit<const N: usize> {
+    pads: [Pad; N],
+}
+
+impl<const N: usize> DrumKit<N> {
+    /// Creates a kit from the notes and voices of its pads. All pads are at
+    /// full level, in the center and in no choke group.
+    pub fn new(pads: [(u8, DrumVoice); N]) -> Self {
+        Self {
+            pads: pads.map(|(note, voice)| Pad {
+                note,
+                voice,
+                level: i16::MAX,
+                panner: Panner::new(),
+                choke_group: None,
+            }),
+        }
+    }
+
+    /// Triggers `pad` with `velocity` and chokes the other pads in its
+    /// group.
+    pub fn trigger_pad(&mut self, pad: usize, velocity: u8) {
+        let Some(group) = self.pads.get(pad).map(|p| p.choke_group) else {
+            return;
+        };
+        if group.is_some() {
+            for (i, other) in self.pads.iter_mut().enumerate() {
+                if i != pad && other.choke_group == group && other.voice.is_active() {
+                    other.voice.choke();
+                }
+            }
+        }
+        let note = self.pads[pad].note;
+        self.pads[pad].voice.as_voice().note_on(note, velocity);
+    }
+
+    /// Triggers all pads on `note`. A velocity of 0 is ignored.
+    pub fn note_on(&mut self, note: u8, velocity: u8) {
+        if velocity == 0 {
+            return;
+        }
+        for pad in 0..N {
+            if self.pads[pad].note == note {
+                self.trigger_pad(pad, velocity);
+            }
+        }
+    }
+
+    /// Plays note ons. Note offs are ignored, since drums are one-shots.
+    pub fn handle_event(&mut self, event: NoteEvent) {
+        if let NoteEvent::On { note, velocity } = event {
+            self.note_on(note, velocity);
+        }
+    }
+
+    /// Fills `out` with the mix of all pads.
+    pub fn render(&mut self, out: &mut [Frame]) {
+        let mut buffer = [0; BLOCK];
+        for chunk in out.chunks_mut(BLOCK) {
+            let mut acc = [(0_i64, 0_i64); BLOCK];
+            for pad in self.pads.iter_mut() {
+                if !pad.voice.is_active() {
+                    continue;
+                }
+                let buffer = &mut buffer[..chunk.len()];
+                pad.voice.as_voice().render(buffer);
+                for (x, (left, right)) in buffer.iter().zip(acc.iter_mut()) {
+                    let y = pad
+                        .panner
+                        .process(((*x as i32 * pad.level as i32) >> 15) as i16);
+                    *left += y.left.0 as i64;
+                    *right += y.right.0 as i64;
+                }
+            }
+            for (y, (left, right)) in chunk.iter_mut().zip(acc) {
+                *y = Frame::new(diag::clip(left), diag::clip(right));
+            }
+        }
+    }
+
+    /// Sets the note of `pad`. Out of range pads are ignored.
+    pub fn set_note(&mut self, pad: usize, note: u8) {
+        if let Some(p) = self.pads.get_mut(pad) {
+            p.note = note;
+        }
+    }
+
+    /// Sets the level of `pad` from 0 to i16::MAX.
+    pub fn set_level(&mut self, pad: usize, level: i16) {
+        if let Some(p) = self.pads.get_mut(pad) {
+            p.level = level.max(0);
+        }
+    }
+
+    /// Sets the pan of `pad` from -i16::MAX (left) to i16::MAX (right).
+    pub fn set_pan(&mut self, pad: usize, pan: i16) {
+        if let Some(p) = self.pads.get_mut(pad) {
+            p.panner.set_pan(pan);
+        }
+    }
+
+    /// Sets the tune of the voice of `pad`.
+    pub fn set_tune(&mut self, pad: usize, tune: mHz) {
+        if let Some(p) = self.pads.get_mut(pad) {
+            p.voice.as_voice().set_mfreq(tune);
+        }
+    }
+
+    /// Puts `pad` in a choke group, or in none.
+    pub fn set_choke_group(&mut self, pad: usize, group: Option<u8>) {
+        if let Some(p) = self.pads.get_mut(pad) {
+            p.choke_group = group;
+        }
+    }
+
+    /// Returns the voice of `pad`, e.g. to set its parameters.
+    pub fn get_voice_mut(&mut self, pad: usize) -> Option<&mut DrumVoice> {
+        self.pads.get_mut(pad).map(|p| &mut p.voice)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.pads.iter().any(|p| p.voice.is_active())
+    }
+
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        for pad in self.pads.iter_mut() {
+            pad.voice.set_msample_rate(msample_rate);
+        }
+    }
+}
+
+impl DrumKit<10> {
+    /// Kit on the General MIDI drum notes: kick (36), snare (38), clap
+    /// (39), closed and open hi-hat (42, 46) in a choke group, low, mid and
+    /// high tom (41, 45, 48), crash (49) and ride (51).
+    pub fn standard() -> Self {
+        let mut open_hat = HiHat::new();
+        open_hat.set_open(true);
+        let tom = |params: TomParams| {
+            let mut tom = Tom::new();
+            tom.set_params(&params);
+            tom
+        };
+        let mut crash = Cymbal::new();
+        crash.set_params(&crate::drum::cymbal::CymbalParams::CRASH);
+        let mut kit = Self::new([
+            (36, Kick::new().into()),
+            (38, Snare::new().into()),
+            (39, Clap::new().into()),
+            (42, HiHat::new().into()),
+            (46, open_hat.into()),
+            (41, tom(TomParams::LOW).into()),
+            (45, tom(TomParams::MID).into()),
+            (48, tom(TomParams::HIGH).into()),
+            (49, crash.into()),
+            (51, Cymbal::new().into()),
+        ]);
+        kit.set_choke_group(3, Some(0));
+        kit.set_choke_group(4, Some(0));
+        // Spread the hats and toms a little like a drummer's view
+        for (pad, pan) in [(3, 8_000), (4, 8_000), (5, -8_000), (7, 8_000), (8, -6_000)] {
+            kit.set_pan(pad, pan);
+        }
+        for pad in [3, 4, 8, 9] {
+            kit.set_level(pad, i16::MAX / 2);
+        }
+        kit
+    }
+}
+
+impl Default for DrumKit<10> {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn peak(out: &[Frame]) -> (i16, i16) {
+        let left = out.iter().map(|y| y.left.0.abs()).max().unwrap();
+        let right = out.iter().map(|y| y.right.0.abs()).max().unwrap();
+        (left, right)
+    }
+
+    #[test]
+    fn test_kit_pads() {
+        let mut kit = DrumKit::new([(36, Kick::new().into()), (38, Snare::new().into())]);
+        let mut out = vec![Frame::mono(0); 441];
+        kit.render(&mut out);
+        assert_eq!(peak(&out), (0, 0));
+
+        // Hard left kick
+        kit.set_pan(0, -i16::MAX);
+        kit.handle_event(NoteEvent::On {
+            note: 36,
+            velocity: 127,
+        });
+        kit.render(&mut out);
+        let (left, right) = peak(&out);
+        assert!(left > 10_000 && right == 0, "{} {}", left, right);
+
+        // Silent snare, both pads mixed
+        kit.set_level(1, 0);
+        kit.note_on(38, 127);
+        assert!(kit.get_voice_mut(1).unwrap().is_active());
+        kit.render(&mut out);
+        assert_eq!(peak(&out).1, 0);
+    }
+
+    #[test]
+    fn test_kit_choke() {
+        let length = |kit: &mut DrumKit<10>| {
+            let mut out = [Frame::mono(0); 64];
+            let mut n = 0;
+            while kit.get_voice_mut(4).unwrap().is_active() {
+                kit.render(&mut out);
+                n += out.len();
+            }
+            n
+        };
+        let mut kit = DrumKit::standard();
+        kit.note_on(46, 127);
+        let open = length(&mut kit);
+        kit.note_on(46, 127);
+        let mut out = vec![Frame::mono(0); 441];
+        kit.render(&mut out);
+        // The closed hat chokes the open one
+        kit.note_on(42, 127);
+        let choked = length(&mut kit);
+        assert!(choked < open / 10, "{} {}", choked, open);
+    }
+}
@@ -0,0 +1,130 @@
+// Percussion voices for drum machines, one-shots that ring out after a
+// trigger.
+
+pub mod clap;
+pub mod cymbal;
+pub mod hihat;
+pub mod kick;
+pub mod kit;
+pub mod perc;
+pub mod snare;
+pub mod tom;
+
+use crate::fx::dynamics::{coefficient, COEF_NORM};
+use crate::osc::noise::LFSR;
+use crate::synth::velocity::{Velocity, VelocityCurve};
+use crate::util::units::{mHz, ms};
+
+/// Fractional bits of the decay level
+const LEVEL_SHIFT: u32 = 16;
+/// Time constants per decay time, as in [crate::env::adsr::Adsr]
+const TIME_CONSTANTS: u32 = 5;
+
+/// Exponential decay from a peak that is practically finished after the
+/// decay time
+pub(crate) struct Decay {
+    level: i64,
+    coef: u32,
+    time: ms,
+}
+
+impl Decay {
+    pub(crate) fn new(time: ms, msample_rate: mHz) -> Self {
+        let mut s = Self {
+            level: 0,
+            coef: 0,
+            time,
+        };
+        s.set_time(time, msample_rate);
+        s
+    }
+
+    /// Restarts from `peak`.
+    pub(crate) fn trigger(&mut self, peak: i16) {
+        self.level = (peak.max(0) as i64) << LEVEL_SHIFT;
+    }
+
+    /// Advances by one sample and returns the level.
+    #[inline]
+    pub(crate) fn _next(&mut self) -> i16 {
+        let y = (self.level >> LEVEL_SHIFT) as i16;
+        self.level -= (self.level * self.coef as i64) / COEF_NORM as i64;
+        if self.level < 1 << LEVEL_SHIFT {
+            self.level = 0;
+        }
+        y
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.level != 0
+    }
+
+    pub(crate) fn set_time(&mut self, time: ms, msample_rate: mHz) {
+        self.time = time;
+        self.coef = (coefficient(time, msample_rate) as u64 * TIME_CONSTANTS as u64)
+            .min(COEF_NORM as u64) as u32;
+    }
+
+    pub(crate) fn get_time(&self) -> ms {
+        self.time
+    }
+}
+
+/// Returns the phase increment of `mfreq`, at most half a period.
+pub(crate) fn delta_phi(mfreq: mHz, msample_rate: mHz) -> u32 {
+    ((mfreq.0 as u64) << 32)
+        .checked_div(msample_rate.0 as u64)
+        .unwrap_or(0)
+        .min(u32::MAX as u64 / 2) as u32
+}
+
+/// Returns the next white noise sample.
+#[inline]
+pub(crate) fn noise(lfsr: &mut LFSR<u32>) -> i16 {
+    (lfsr.next() & 0xFFFF) as u16 as i16
+}
+
+/// Returns the gain of a hit with `velocity`.
+pub(crate) fn velocity_gain(velocity: u8) -> i16 {
+    VelocityCurve::Linear.apply(Velocity::new(velocity))
+}
+
+/// Frequencies of the square oscillators of [Metallic] relative to the
+/// lowest one in 1/65536, as in classic analog drum machines
+const METALLIC_RATIOS: [u32; 6] = [65_536, 97_178, 117_990, 166_866, 172_388, 255_390];
+
+/// Stack of six squares at inharmonic ratios, the metallic source of
+/// hi-hats and cymbals
+pub(crate) struct Metallic {
+    phi: [u32; 6],
+    delta_phi: [u32; 6],
+}
+
+impl Metallic {
+    pub(crate) fn new() -> Self {
+        Self {
+            phi: [0; 6],
+            delta_phi: [0; 6],
+        }
+    }
+
+    /// Returns the next sample of the mix at a sixth of full scale per
+    /// square.
+    #[inline]
+    pub(crate) fn _next(&mut self) -> i16 {
+        let mut y = 0;
+        for (phi, delta) in self.phi.iter_mut().zip(self.delta_phi) {
+            y += if *phi < 1 << 31 { 5_461 } else { -5_461 };
+            *phi = phi.wrapping_add(delta);
+        }
+        y as i16
+    }
+
+    /// Sets the frequency of the lowest square.
+    pub(crate) fn set_mfreq(&mut self, mfreq: mHz, msample_rate: mHz) {
+        for (delta, ratio) in self.delta_phi.iter_mut().zip(METALLIC_RATIOS) {
+            let mfreq = mHz(((mfreq.0 as u64 * ratio as u64) >> 16) as u32);
+            *delta = delta_phi(mfreq, msample_rate);
+        }
+    }
+}
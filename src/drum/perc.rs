@@ -0,0 +1,306 @@
+// Generic percussion voice from a source, a pitch sweep, a filter and a decay.
+
+use crate::drum::{delta_phi, noise, velocity_gain, Decay, Metallic};
+use crate::fx::filter::StateVariableFilter;
+use crate::osc::fm::FmOperator;
+use crate::osc::noise::LFSR;
+use crate::preset::Preset;
+use crate::synth::voice::Voice;
+use crate::util::gate::{Trigger, Triggered};
+use crate::util::units::{mHz, ms, Frequency, Hz};
+
+/// Sound source of a [PercVoice]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PercSource {
+    Sine,
+    Square,
+    /// Six detuned squares as in the [crate::drum::hihat::HiHat]
+    Metallic,
+    /// White noise, which ignores the tune and the sweep.
+    Noise,
+}
+
+/// Filter mode of a [PercVoice]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PercFilter {
+    Off,
+    LowPass,
+    BandPass,
+    HighPass,
+}
+
+/// Parameters of a [PercVoice]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PercParams {
+    pub source: PercSource,
+    /// Pitch at the end of the sweep
+    pub tune: Hz,
+    /// Pitch at the start of the sweep above the tune in % of the tune
+    pub sweep: u16,
+    pub sweep_decay: ms,
+    pub filter: PercFilter,
+    pub cutoff: Hz,
+    /// Resonance of the filter up to 4096
+    pub resonance: u32,
+    pub decay: ms,
+}
+
+impl Default for PercParams {
+    fn default() -> Self {
+        Self {
+            source: PercSource::Sine,
+            tune: Hz(200),
+            sweep: 0,
+            sweep_decay: ms(50),
+            filter: PercFilter::Off,
+            cutoff: Hz(1_000),
+            resonance: 0,
+            decay: ms(200),
+        }
+    }
+}
+
+/// Percussion voice defined by data
+///
+/// For drums beyond the dedicated voices, the source is swept down to the
+/// tune, filtered and shaped by an exponential decay, all set by one
+/// [PercParams]. Since the parameters are plain data, a kit of custom
+/// sounds can be kept in constants or loaded as presets.
+///
+/// ```
+/// use isopod::drum::perc::{PercFilter, PercParams, PercSource, PercVoice};
+/// use isopod::preset::Preset;
+/// use isopod::synth::voice::Voice;
+/// use isopod::util::units::{ms, Hz};
+///
+/// const COWBELL: PercParams = PercParams {
+///     source: PercSource::Square,
+///     tune: Hz(560),
+///     sweep: 0,
+///     sweep_decay: ms(1),
+///     filter: PercFilter::BandPass,
+///     cutoff: Hz(800),
+///     resonance: 2_048,
+///     decay: ms(300),
+/// };
+///
+/// let mut cowbell = PercVoice::new();
+/// cowbell.set_params(&COWBELL);
+/// cowbell.note_on(56, 100);
+/// let mut out = [0; 441];
+/// cowbell.render(&mut out);
+/// assert!(cowbell.is_active());
+/// ```
+pub struct PercVoice {
+    metallic: Metallic,
+    lfsr: LFSR<u32>,
+    filter: StateVariableFilter,
+    amp: Decay,
+    pitch: Decay,
+
+    params: PercParams,
+    phi: u32,
+    delta_phi: u32,
+    gain: i16,
+
+    msample_rate: mHz,
+}
+
+impl PercVoice {
+    pub fn new() -> Self {
+        let msample_rate = mHz(44_100_000);
+        let params = PercParams::default();
+        let mut s = Self {
+            metallic: Metallic::new(),
+            lfsr: LFSR::<u32>::default(),
+            filter: StateVariableFilter::new(),
+            amp: Decay::new(params.decay, msample_rate),
+            pitch: Decay::new(params.sweep_decay, msample_rate),
+
+            params,
+            phi: 0,
+            delta_phi: 0,
+            gain: 0,
+
+            msample_rate,
+        };
+        s.set_msample_rate(msample_rate);
+        s
+    }
+
+    #[inline]
+    fn sample(&mut self) -> i16 {
+        let sweep = self.pitch._next() as u64 * self.params.sweep as u64 / 100;
+        let delta = self.delta_phi as u64 + ((self.delta_phi as u64 * sweep) >> 15);
+        let x = match self.params.source {
+            PercSource::Sine => FmOperator::sine(self.phi),
+            PercSource::Square if self.phi < 1 << 31 => i16::MAX / 2,
+            PercSource::Square => -i16::MAX / 2,
+            PercSource::Metallic => self.metallic._next(),
+            PercSource::Noise => noise(&mut self.lfsr) / 2,
+        };
+        self.phi = self.phi.wrapping_add(delta.min(u32::MAX as u64 / 2) as u32);
+        let x = match self.params.filter {
+            PercFilter::Off => x,
+            filter => {
+                self.filter.feed(x);
+                match filter {
+                    PercFilter::LowPass => self.filter.get_lp(),
+                    PercFilter::BandPass => self.filter.get_bp(),
+                    _ => self.filter.get_hp(),
+                }
+            }
+        };
+        let y = (x as i32 * self.amp._next() as i32) >> 15;
+        ((y * self.gain as i32) >> 15) as i16
+    }
+
+    /// Starts a hit with `velocity` from 1 to 127.
+    pub fn trigger(&mut self, velocity: u8) {
+        self.gain = velocity_gain(velocity);
+        self.phi = 0;
+        self.amp.trigger(i16::MAX);
+        self.pitch.trigger(i16::MAX);
+    }
+
+    /// Sets the pitch at the end of the sweep.
+    pub fn set_tune(&mut self, tune: mHz) {
+        self.params.tune = tune.to_Hz();
+        self.delta_phi = delta_phi(tune, self.msample_rate);
+        self.metallic.set_mfreq(tune, self.msample_rate);
+    }
+
+    pub fn set_decay_ms(&mut self, decay: ms) {
+        self.params.decay = decay;
+        self.amp.set_time(decay, self.msample_rate);
+    }
+
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.filter.set_msample_rate(self.msample_rate);
+        let params = self.params;
+        self.set_params(&params);
+    }
+}
+
+impl Default for PercVoice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for PercVoice {
+    type Params = PercParams;
+
+    fn get_params(&self) -> PercParams {
+        self.params
+    }
+
+    fn set_params(&mut self, params: &PercParams) {
+        self.params = *params;
+        self.set_tune(params.tune.to_mHz());
+        self.set_decay_ms(params.decay);
+        self.pitch.set_time(params.sweep_decay, self.msample_rate);
+        self.filter.set_mfreq(params.cutoff.to_mHz());
+        self.filter.set_q(params.resonance);
+    }
+}
+
+impl Voice for PercVoice {
+    /// Starts a hit. The note is ignored, the pitch is set by the tune.
+    fn note_on(&mut self, _note: u8, velocity: u8) {
+        self.trigger(velocity);
+    }
+
+    fn set_note(&mut self, _note: u8) {}
+
+    /// Sets the tune.
+    fn set_mfreq(&mut self, mfreq: mHz) {
+        self.set_tune(mfreq);
+    }
+
+    fn note_off(&mut self) {}
+
+    fn is_active(&self) -> bool {
+        self.amp.is_active()
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self.sample();
+        }
+    }
+}
+
+impl Triggered for PercVoice {
+    fn trigger(&mut self, trigger: Trigger) {
+        PercVoice::trigger(self, trigger.velocity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn crossings(out: &[i16]) -> usize {
+        out.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count()
+    }
+
+    #[test]
+    fn test_perc_sweep() {
+        let mut perc = PercVoice::new();
+        let params = PercParams {
+            tune: Hz(100),
+            sweep: 300,
+            sweep_decay: ms(500),
+            decay: ms(1_000),
+            ..Default::default()
+        };
+        perc.set_params(&params);
+        assert_eq!(perc.get_params(), params);
+        perc.note_on(36, 127);
+        let mut out = vec![0; 22_050];
+        perc.render(&mut out);
+        // Starts at about 4 times the tune and settles on it
+        let (early, late) = (crossings(&out[..4_410]), crossings(&out[17_640..]));
+        assert!(early > 2 * late, "{} {}", early, late);
+        assert!(late.abs_diff(20) <= 2, "{}", late);
+    }
+
+    #[test]
+    fn test_perc_sources_filters() {
+        let render = |source, filter| {
+            let mut perc = PercVoice::new();
+            perc.set_params(&PercParams {
+                source,
+                tune: Hz(300),
+                filter,
+                cutoff: Hz(2_000),
+                resonance: 1_024,
+                ..Default::default()
+            });
+            perc.note_on(38, 127);
+            let mut out = vec![0; 4_410];
+            perc.render(&mut out);
+            assert!(out.iter().any(|y| y.abs() > 1_000), "{:?}", source);
+            crossings(&out)
+        };
+        for source in [
+            PercSource::Sine,
+            PercSource::Square,
+            PercSource::Metallic,
+            PercSource::Noise,
+        ] {
+            render(source, PercFilter::Off);
+        }
+        // The high-pass brightens and the low-pass darkens the noise
+        let (lp, hp) = (
+            render(PercSource::Noise, PercFilter::LowPass),
+            render(PercSource::Noise, PercFilter::HighPass),
+        );
+        assert!(hp > 2 * lp, "{} {}", lp, hp);
+    }
+}
@@ -0,0 +1,262 @@
+// Snare drum from a tuned body and band-passed noise for the snares.
+
+use crate::drum::{delta_phi, noise, velocity_gain, Decay};
+use crate::fx::filter::StateVariableFilter;
+use crate::osc::fm::FmOperator;
+use crate::osc::noise::LFSR;
+use crate::preset::Preset;
+use crate::synth::voice::Voice;
+use crate::util::gate::{Trigger, Triggered};
+use crate::util::units::{mHz, ms, Frequency, Hz};
+
+/// Center of the band of the snare noise
+const NOISE_FREQ: Hz = Hz(4_000);
+/// Resonance of the noise band
+const NOISE_Q: u32 = 1_024;
+/// Time of the pitch drop of the body
+const DROP_TIME: ms = ms(20);
+
+/// Parameters of a [Snare]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnareParams {
+    /// Pitch of the lower mode of the body
+    pub tune: Hz,
+    /// Level of the snares relative to the body from 0 to i16::MAX
+    pub snappy: i16,
+    /// Decay of the snares. The body decays in half the time.
+    pub decay: ms,
+}
+
+/// Snare drum voice
+///
+/// The body is two sines a fifth apart, like the lowest modes of a drum
+/// head, which drop slightly in pitch after the hit. Band-passed noise
+/// with its own, longer envelope stands in for the snares. Snappy blends
+/// from the body alone to mostly snares. Like the
+/// [crate::drum::kick::Kick], the snare is a one-shot.
+///
+/// ```
+/// use isopod::drum::snare::Snare;
+/// use isopod::synth::voice::Voice;
+///
+/// let mut snare = Snare::new();
+/// snare.note_on(38, 100);
+/// let mut out = [0; 441];
+/// snare.render(&mut out);
+/// assert!(snare.is_active());
+/// ```
+pub struct Snare {
+    body_env: Decay,
+    noise_env: Decay,
+    drop: Decay,
+    filter: StateVariableFilter,
+    lfsr: LFSR<u32>,
+
+    phi: [u32; 2],
+    delta_phi: [u32; 2],
+    tune: mHz,
+    snappy: i16,
+    decay: ms,
+    gain: i16,
+
+    msample_rate: mHz,
+}
+
+impl Snare {
+    pub fn new() -> Self {
+        let msample_rate = mHz(44_100_000);
+        let mut s = Self {
+            body_env: Decay::new(ms(100), msample_rate),
+            noise_env: Decay::new(ms(200), msample_rate),
+            drop: Decay::new(DROP_TIME, msample_rate),
+            filter: StateVariableFilter::new(),
+            lfsr: LFSR::<u32>::default(),
+
+            phi: [0; 2],
+            delta_phi: [0; 2],
+            tune: Hz(180).to_mHz(),
+            snappy: i16::MAX / 2,
+            decay: ms(200),
+            gain: 0,
+
+            msample_rate,
+        };
+        s.set_msample_rate(msample_rate);
+        s
+    }
+
+    #[inline]
+    fn sample(&mut self) -> i16 {
+        // A quarter above the tune at the hit
+        let drop = self.drop._next() as u64;
+        let mut body = 0;
+        for (phi, delta) in self.phi.iter_mut().zip(self.delta_phi) {
+            body += FmOperator::sine(*phi) as i32 / 2;
+            let delta = delta as u64 + ((delta as u64 * drop) >> 17);
+            *phi = phi.wrapping_add(delta as u32);
+        }
+        let body = (body * self.body_env._next() as i32) >> 15;
+        self.filter.feed(noise(&mut self.lfsr) / 2);
+        let snares = (self.filter.get_bp() as i32 * self.noise_env._next() as i32) >> 14;
+
+        let snappy = self.snappy as i32;
+        let body_level = i16::MAX as i32 - snappy / 2;
+        let y = (body * body_level + snares * snappy) >> 15;
+        let y = y.clamp(-(i16::MAX as i32), i16::MAX as i32);
+        ((y * self.gain as i32) >> 15) as i16
+    }
+
+    /// Starts a hit with `velocity` from 1 to 127.
+    pub fn trigger(&mut self, velocity: u8) {
+        self.gain = velocity_gain(velocity);
+        self.phi = [0; 2];
+        self.body_env.trigger(i16::MAX);
+        self.noise_env.trigger(i16::MAX);
+        self.drop.trigger(i16::MAX);
+    }
+
+    /// Sets the pitch of the lower mode of the body.
+    pub fn set_tune(&mut self, tune: mHz) {
+        self.tune = tune;
+        self.delta_phi[0] = delta_phi(tune, self.msample_rate);
+        self.delta_phi[1] = delta_phi(mHz(tune.0 / 2 * 3), self.msample_rate);
+    }
+
+    pub fn get_tune(&self) -> mHz {
+        self.tune
+    }
+
+    /// Sets the level of the snares relative to the body from 0 to
+    /// i16::MAX.
+    pub fn set_snappy(&mut self, snappy: i16) {
+        self.snappy = snappy.max(0);
+    }
+
+    /// Sets the decay of the snares. The body decays in half the time.
+    pub fn set_decay_ms(&mut self, decay: ms) {
+        self.decay = decay;
+        self.noise_env.set_time(decay, self.msample_rate);
+        self.body_env.set_time(ms(decay.0 / 2), self.msample_rate);
+    }
+
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.filter.set_msample_rate(self.msample_rate);
+        self.filter.set_mfreq(NOISE_FREQ.to_mHz());
+        self.filter.set_q(NOISE_Q);
+        self.drop.set_time(DROP_TIME, self.msample_rate);
+        self.set_decay_ms(self.decay);
+        self.set_tune(self.tune);
+    }
+}
+
+impl Default for Snare {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for Snare {
+    type Params = SnareParams;
+
+    fn get_params(&self) -> SnareParams {
+        SnareParams {
+            tune: self.tune.to_Hz(),
+            snappy: self.snappy,
+            decay: self.decay,
+        }
+    }
+
+    fn set_params(&mut self, params: &SnareParams) {
+        self.set_tune(params.tune.to_mHz());
+        self.set_snappy(params.snappy);
+        self.set_decay_ms(params.decay);
+    }
+}
+
+impl Voice for Snare {
+    /// Starts a hit. The note is ignored, the pitch is set by the tune.
+    fn note_on(&mut self, _note: u8, velocity: u8) {
+        self.trigger(velocity);
+    }
+
+    fn set_note(&mut self, _note: u8) {}
+
+    /// Sets the tune.
+    fn set_mfreq(&mut self, mfreq: mHz) {
+        self.set_tune(mfreq);
+    }
+
+    fn note_off(&mut self) {}
+
+    fn is_active(&self) -> bool {
+        self.body_env.is_active() || self.noise_env.is_active()
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self.sample();
+        }
+    }
+}
+
+impl Triggered for Snare {
+    fn trigger(&mut self, trigger: Trigger) {
+        Snare::trigger(self, trigger.velocity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Mean squared sample difference relative to the power, a measure of
+    /// brightness
+    fn brightness(out: &[i16]) -> i64 {
+        let diff: i64 = out
+            .windows(2)
+            .map(|w| (w[1] as i64 - w[0] as i64).pow(2))
+            .sum();
+        let power: i64 = out.iter().map(|y| (*y as i64).pow(2)).sum();
+        diff * 1_000 / power.max(1)
+    }
+
+    #[test]
+    fn test_snare_snappy() {
+        let render = |snappy| {
+            let mut snare = Snare::new();
+            snare.set_snappy(snappy);
+            snare.note_on(38, 127);
+            let mut out = vec![0; 4_410];
+            snare.render(&mut out);
+            out
+        };
+        let (body, snares) = (render(0), render(i16::MAX));
+        assert!(body.iter().any(|y| y.abs() > 10_000));
+        assert!(snares.iter().any(|y| y.abs() > 5_000));
+        assert!(
+            brightness(&snares) > 10 * brightness(&body),
+            "{} {}",
+            brightness(&snares),
+            brightness(&body)
+        );
+    }
+
+    #[test]
+    fn test_snare_decay() {
+        let mut snare = Snare::new();
+        snare.set_params(&SnareParams {
+            tune: Hz(200),
+            snappy: i16::MAX / 2,
+            decay: ms(100),
+        });
+        assert_eq!(snare.get_params().tune, Hz(200));
+        snare.note_on(38, 100);
+        let mut out = vec![0; 13_230];
+        snare.render(&mut out);
+        assert!(!snare.is_active());
+        assert!(out[..441].iter().any(|y| y.abs() > 3_000));
+        assert!(out[8_820..].iter().all(|y| y.abs() < 100));
+    }
+}
@@ -0,0 +1,237 @@
+// Tom from a sine with a moderate pitch drop and a noise attack.
+
+use crate::drum::{delta_phi, noise, velocity_gain, Decay};
+use crate::osc::fm::FmOperator;
+use crate::osc::noise::LFSR;
+use crate::preset::Preset;
+use crate::synth::voice::Voice;
+use crate::util::gate::{Trigger, Triggered};
+use crate::util::units::{mHz, ms, Frequency, Hz};
+
+/// Time of the pitch drop
+const DROP_TIME: ms = ms(120);
+/// Time of the noise attack
+const ATTACK_TIME: ms = ms(12);
+
+/// Parameters of a [Tom]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TomParams {
+    /// Pitch at the end of the drop
+    pub tune: Hz,
+    pub decay: ms,
+}
+
+impl TomParams {
+    pub const LOW: TomParams = TomParams {
+        tune: Hz(80),
+        decay: ms(600),
+    };
+    pub const MID: TomParams = TomParams {
+        tune: Hz(120),
+        decay: ms(500),
+    };
+    pub const HIGH: TomParams = TomParams {
+        tune: Hz(170),
+        decay: ms(400),
+    };
+}
+
+/// Tom voice
+///
+/// A sine starts half an octave above the tune and settles on it, while a
+/// little noise marks the stick. Unlike the [crate::drum::kick::Kick], the
+/// drop is slow and shallow, so the tom keeps its pitch. The presets
+/// [TomParams::LOW], [TomParams::MID] and [TomParams::HIGH] tune a set of
+/// toms.
+///
+/// ```
+/// use isopod::drum::tom::{Tom, TomParams};
+/// use isopod::preset::Preset;
+/// use isopod::synth::voice::Voice;
+///
+/// let mut tom = Tom::new();
+/// tom.set_params(&TomParams::HIGH);
+/// tom.note_on(50, 100);
+/// let mut out = [0; 441];
+/// tom.render(&mut out);
+/// assert!(tom.is_active());
+/// ```
+pub struct Tom {
+    amp: Decay,
+    drop: Decay,
+    attack: Decay,
+    lfsr: LFSR<u32>,
+
+    phi: u32,
+    delta_phi: u32,
+    tune: mHz,
+    gain: i16,
+
+    msample_rate: mHz,
+}
+
+impl Tom {
+    pub fn new() -> Self {
+        let msample_rate = mHz(44_100_000);
+        let mut s = Self {
+            amp: Decay::new(TomParams::MID.decay, msample_rate),
+            drop: Decay::new(DROP_TIME, msample_rate),
+            attack: Decay::new(ATTACK_TIME, msample_rate),
+            lfsr: LFSR::<u32>::default(),
+
+            phi: 0,
+            delta_phi: 0,
+            tune: TomParams::MID.tune.to_mHz(),
+            gain: 0,
+
+            msample_rate,
+        };
+        s.set_msample_rate(msample_rate);
+        s
+    }
+
+    #[inline]
+    fn sample(&mut self) -> i16 {
+        // Up to half the tune above it
+        let drop = self.drop._next() as u64;
+        let delta = self.delta_phi as u64 + ((self.delta_phi as u64 * drop) >> 16);
+        let body = (FmOperator::sine(self.phi) as i32 * self.amp._next() as i32) >> 15;
+        self.phi = self.phi.wrapping_add(delta as u32);
+        let stick = (noise(&mut self.lfsr) as i32 * self.attack._next() as i32) >> 17;
+        let y = (body + stick).clamp(-(i16::MAX as i32), i16::MAX as i32);
+        ((y * self.gain as i32) >> 15) as i16
+    }
+
+    /// Starts a hit with `velocity` from 1 to 127.
+    pub fn trigger(&mut self, velocity: u8) {
+        self.gain = velocity_gain(velocity);
+        self.phi = 0;
+        self.amp.trigger(i16::MAX);
+        self.drop.trigger(i16::MAX);
+        self.attack.trigger(i16::MAX);
+    }
+
+    /// Sets the pitch at the end of the drop.
+    pub fn set_tune(&mut self, tune: mHz) {
+        self.tune = tune;
+        self.delta_phi = delta_phi(tune, self.msample_rate);
+    }
+
+    pub fn get_tune(&self) -> mHz {
+        self.tune
+    }
+
+    pub fn set_decay_ms(&mut self, decay: ms) {
+        self.amp.set_time(decay, self.msample_rate);
+    }
+
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.amp.set_time(self.amp.get_time(), self.msample_rate);
+        self.drop.set_time(DROP_TIME, self.msample_rate);
+        self.attack.set_time(ATTACK_TIME, self.msample_rate);
+        self.set_tune(self.tune);
+    }
+}
+
+impl Default for Tom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for Tom {
+    type Params = TomParams;
+
+    fn get_params(&self) -> TomParams {
+        TomParams {
+            tune: self.tune.to_Hz(),
+            decay: self.amp.get_time(),
+        }
+    }
+
+    fn set_params(&mut self, params: &TomParams) {
+        self.set_tune(params.tune.to_mHz());
+        self.set_decay_ms(params.decay);
+    }
+}
+
+impl Voice for Tom {
+    /// Starts a hit. The note is ignored, the pitch is set by the tune.
+    fn note_on(&mut self, _note: u8, velocity: u8) {
+        self.trigger(velocity);
+    }
+
+    fn set_note(&mut self, _note: u8) {}
+
+    /// Sets the tune.
+    fn set_mfreq(&mut self, mfreq: mHz) {
+        self.set_tune(mfreq);
+    }
+
+    fn note_off(&mut self) {}
+
+    fn is_active(&self) -> bool {
+        self.amp.is_active()
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self.sample();
+        }
+    }
+}
+
+impl Triggered for Tom {
+    fn trigger(&mut self, trigger: Trigger) {
+        Tom::trigger(self, trigger.velocity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn crossings(out: &[i16]) -> usize {
+        out.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count()
+    }
+
+    #[test]
+    fn test_tom_tunings() {
+        let mut out = vec![0; 4_410];
+        let mut counts = Vec::new();
+        for params in [TomParams::LOW, TomParams::MID, TomParams::HIGH] {
+            let mut tom = Tom::new();
+            tom.set_params(&params);
+            assert_eq!(tom.get_params(), params);
+            tom.note_on(45, 127);
+            // Skip the attack and most of the drop
+            tom.render(&mut out);
+            tom.render(&mut out);
+            tom.render(&mut out);
+            counts.push(crossings(&out));
+        }
+        // About 2 crossings per period of 100 ms
+        for (count, params) in counts
+            .iter()
+            .zip([TomParams::LOW, TomParams::MID, TomParams::HIGH])
+        {
+            let expected = params.tune.0 as usize / 5;
+            assert!(count.abs_diff(expected) <= expected / 10, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn test_tom_drop() {
+        let mut tom = Tom::new();
+        tom.note_on(45, 127);
+        let mut out = vec![0; 13_230];
+        tom.render(&mut out);
+        // Higher at the start, with the noise attack
+        let (early, late) = (crossings(&out[441..1_323]), crossings(&out[12_348..]));
+        assert!(early > late + late / 6, "{} {}", early, late);
+        let peak = out.iter().map(|y| y.abs()).max().unwrap();
+        assert!(peak > 20_000);
+    }
+}
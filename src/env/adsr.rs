@@ -0,0 +1,289 @@
+// Attack decay sustain release envelope with a linear attack and
+// exponential decay and release.
+
+use crate::fx::dynamics::{coefficient, COEF_NORM};
+use crate::preset::Preset;
+use crate::util::param::{check_msample_rate, ParamError};
+use crate::util::units::{mHz, ms};
+
+/// Fractional bits of the envelope level
+const LEVEL_SHIFT: u32 = 16;
+const LEVEL_MAX: i64 = (i16::MAX as i64) << LEVEL_SHIFT;
+/// Time constants per decay or release time. After 5 time constants the
+/// level has fallen to below 1 %.
+const TIME_CONSTANTS: u32 = 5;
+
+/// Stage of an [Adsr]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdsrStage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Parameters of an [Adsr]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AdsrParams {
+    pub attack: ms,
+    pub decay: ms,
+    pub sustain: i16,
+    pub release: ms,
+}
+
+/// ADSR envelope from 0 to i16::MAX
+///
+/// The attack rises linearly from the current level, so retriggering
+/// doesn't click. Decay and release approach their targets exponentially
+/// and are practically finished after the set times.
+pub struct Adsr {
+    stage: AdsrStage,
+    level: i64,
+
+    attack_step: i64,
+    decay_coef: u32,
+    release_coef: u32,
+    sustain: i16,
+
+    attack: ms,
+    decay: ms,
+    release: ms,
+    msample_rate: mHz,
+}
+
+impl Adsr {
+    pub fn new() -> Self {
+        let mut s = Self {
+            stage: AdsrStage::Idle,
+            level: 0,
+
+            attack_step: 0,
+            decay_coef: 0,
+            release_coef: 0,
+            sustain: i16::MAX / 2,
+
+            attack: ms(5),
+            decay: ms(200),
+            release: ms(300),
+            msample_rate: mHz(44_100_000),
+        };
+        s.update_rates();
+        s
+    }
+
+    fn update_rates(&mut self) {
+        let samples = (self.attack.0 as u64 * self.msample_rate.0 as u64) / 1_000_000;
+        // Rounded up, so the maximum is reached after the attack time
+        let samples = (samples as i64).max(1);
+        self.attack_step = (LEVEL_MAX + samples - 1) / samples;
+        let coef = |time: ms| {
+            (coefficient(time, self.msample_rate) as u64 * TIME_CONSTANTS as u64)
+                .min(COEF_NORM as u64) as u32
+        };
+        self.decay_coef = coef(self.decay);
+        self.release_coef = coef(self.release);
+    }
+
+    /// Moves the level towards `target` by a fraction `coef` of the distance.
+    fn approach(&mut self, target: i64, coef: u32) {
+        let step = ((target - self.level) * coef as i64) >> 24;
+        if step == 0 || (target - self.level - step).abs() < 1 << LEVEL_SHIFT {
+            self.level = target;
+        } else {
+            self.level += step;
+        }
+    }
+
+    /// Advances by one sample and returns the level.
+    #[inline]
+    pub fn _next(&mut self) -> i16 {
+        match self.stage {
+            AdsrStage::Idle | AdsrStage::Sustain => {}
+            AdsrStage::Attack => {
+                self.level += self.attack_step;
+                if self.level >= LEVEL_MAX {
+                    self.level = LEVEL_MAX;
+                    self.stage = AdsrStage::Decay;
+                }
+            }
+            AdsrStage::Decay => {
+                let sustain = (self.sustain as i64) << LEVEL_SHIFT;
+                self.approach(sustain, self.decay_coef);
+                if self.level == sustain {
+                    self.stage = AdsrStage::Sustain;
+                }
+            }
+            AdsrStage::Release => {
+                self.approach(0, self.release_coef);
+                if self.level == 0 {
+                    self.stage = AdsrStage::Idle;
+                }
+            }
+        }
+        self.get_level()
+    }
+
+    /// Fills `out` with the next levels.
+    pub fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self._next();
+        }
+    }
+
+    /// Starts the attack from the current level.
+    pub fn gate_on(&mut self) {
+        self.stage = AdsrStage::Attack;
+    }
+
+    /// Starts the release.
+    pub fn gate_off(&mut self) {
+        if self.stage != AdsrStage::Idle {
+            self.stage = AdsrStage::Release;
+        }
+    }
+
+    /// Jumps to the idle stage without release.
+    pub fn reset(&mut self) {
+        self.stage = AdsrStage::Idle;
+        self.level = 0;
+    }
+
+    /// Returns the current level.
+    pub fn get_level(&self) -> i16 {
+        (self.level >> LEVEL_SHIFT) as i16
+    }
+
+    /// Returns the current stage.
+    pub fn get_stage(&self) -> AdsrStage {
+        self.stage
+    }
+
+    /// True until the release has finished.
+    pub fn is_active(&self) -> bool {
+        self.stage != AdsrStage::Idle
+    }
+
+    /// Sets the time of the linear rise from 0 to the maximum.
+    pub fn set_attack_ms(&mut self, attack: ms) {
+        self.attack = attack;
+        self.update_rates();
+    }
+
+    /// Sets the time to decay from the maximum to the sustain level.
+    pub fn set_decay_ms(&mut self, decay: ms) {
+        self.decay = decay;
+        self.update_rates();
+    }
+
+    /// Sets the sustain level normalized to
+    /// [crate::util::units::SAMPLE_NORM].
+    pub fn set_sustain(&mut self, sustain: i16) {
+        self.sustain = sustain.max(0);
+        if self.stage == AdsrStage::Sustain {
+            self.stage = AdsrStage::Decay;
+        }
+    }
+
+    /// Sets the time to fade out after the gate closed.
+    pub fn set_release_ms(&mut self, release: ms) {
+        self.release = release;
+        self.update_rates();
+    }
+
+    /// Sets the sample rate in mHz. A rate of 0 is raised to 1 mHz, see
+    /// [Adsr::try_set_msample_rate] for a checked variant.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.update_rates();
+    }
+
+    /// Sets the sample rate in mHz or fails if it is 0.
+    pub fn try_set_msample_rate(&mut self, msample_rate: mHz) -> Result<(), ParamError> {
+        check_msample_rate(msample_rate)?;
+        self.set_msample_rate(msample_rate);
+        Ok(())
+    }
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for Adsr {
+    type Params = AdsrParams;
+
+    fn get_params(&self) -> AdsrParams {
+        AdsrParams {
+            attack: self.attack,
+            decay: self.decay,
+            sustain: self.sustain,
+            release: self.release,
+        }
+    }
+
+    fn set_params(&mut self, params: &AdsrParams) {
+        (self.attack, self.decay, self.release) = (params.attack, params.decay, params.release);
+        self.update_rates();
+        self.set_sustain(params.sustain);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_adsr() {
+        let mut adsr = Adsr::new();
+        adsr.set_msample_rate(mHz(1_000_000));
+        adsr.set_attack_ms(ms(10));
+        adsr.set_decay_ms(ms(100));
+        adsr.set_sustain(i16::MAX / 4);
+        adsr.set_release_ms(ms(50));
+        assert!(!adsr.is_active());
+
+        adsr.gate_on();
+        let out: Vec<i16> = (0..10).map(|_| adsr._next()).collect();
+        assert!(out.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(out[9], i16::MAX);
+        assert_eq!(adsr.get_stage(), AdsrStage::Decay);
+
+        // Close to the sustain level after the decay time
+        let out: Vec<i16> = (0..100).map(|_| adsr._next()).collect();
+        assert!(
+            (out[99] - i16::MAX / 4).abs() < i16::MAX / 100,
+            "{}",
+            out[99]
+        );
+        for _ in 0..100 {
+            adsr._next();
+        }
+        assert_eq!(adsr.get_stage(), AdsrStage::Sustain);
+        assert_eq!(adsr.get_level(), i16::MAX / 4);
+
+        adsr.gate_off();
+        let out: Vec<i16> = (0..50).map(|_| adsr._next()).collect();
+        assert!(out[49] < i16::MAX / 400, "{}", out[49]);
+        for _ in 0..100 {
+            adsr._next();
+        }
+        assert!(!adsr.is_active());
+        assert_eq!(adsr.get_level(), 0);
+    }
+
+    #[test]
+    fn test_adsr_retrigger() {
+        let mut adsr = Adsr::new();
+        adsr.gate_on();
+        for _ in 0..100 {
+            adsr._next();
+        }
+        let level = adsr.get_level();
+        adsr.gate_on();
+        assert!(adsr._next() > level);
+    }
+}
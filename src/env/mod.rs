@@ -1 +1,2 @@
+pub mod adsr;
 pub mod linexp;
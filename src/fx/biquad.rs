@@ -0,0 +1,251 @@
+// Fixed point biquad filter with shelving and peaking coefficients based on
+// Robert Bristow-Johnson's "Audio EQ Cookbook". The coefficients are
+// computed without floating point arithmetic.
+
+use crate::fx::Effect;
+use crate::osc::luts::SINE_I16;
+use crate::util::diag;
+use crate::util::interp;
+use crate::util::units::mHz;
+
+/// Fractional bits of the filter coefficients
+const COEF_SHIFT: u32 = 28;
+const ONE: i64 = 1 << COEF_SHIFT;
+/// sqrt(2) with COEF_SHIFT fractional bits
+const SQRT_2: i64 = 379_625_062;
+
+/// Normalization of the peak filter quality factor, i.e. `Q_NORM` means
+/// Q = 1.
+pub const Q_NORM: u32 = 1 << 12;
+
+/// Largest supported boost or cut in dB
+pub const GAIN_DB_MAX: i32 = 24;
+
+/// sqrt(A) = 10^(dB/80) for dB in [-24, 24], normalized to 1 << 16
+static DB_SQRT_GAIN: [i64; 49] = [
+    32846, 33805, 34792, 35808, 36854, 37930, 39037, 40177, 41350, 42558, 43801, 45080, 46396,
+    47751, 49145, 50580, 52057, 53577, 55142, 56752, 58409, 60115, 61870, 63677, 65536, 67450,
+    69419, 71446, 73533, 75680, 77890, 80164, 82505, 84914, 87394, 89946, 92572, 95275, 98057,
+    100921, 103868, 106901, 110022, 113235, 116541, 119944, 123447, 127052, 130762,
+];
+
+/// Linearly interpolated sine of a phase where the full `u32` range
+/// corresponds to 2 pi. Returns a value normalized to 1 << 15.
+fn sin_interp(phase: u32) -> i64 {
+    let idx = (phase >> 22) as usize;
+    let frac = (phase >> 6) & 0xFFFF;
+    let next = SINE_I16[(idx + 1) % SINE_I16.len()];
+    interp::linear(SINE_I16[idx], next, frac) as i64
+}
+
+fn mul(a: i64, b: i64) -> i64 {
+    (a * b) >> COEF_SHIFT
+}
+
+/// Returns sqrt(A) and A for a gain in dB.
+fn shelf_gain(gain_db: i32) -> (i64, i64) {
+    let idx = (gain_db.clamp(-GAIN_DB_MAX, GAIN_DB_MAX) + GAIN_DB_MAX) as usize;
+    let sqrt_a = DB_SQRT_GAIN[idx] << (COEF_SHIFT - 16);
+    (sqrt_a, mul(sqrt_a, sqrt_a))
+}
+
+/// Returns sin(w0) and cos(w0) for the normalized angular frequency w0.
+fn sin_cos(mfreq: mHz, msample_rate: mHz) -> (i64, i64) {
+    let nyquist = msample_rate.0 as u64 / 2;
+    let phase = (((mfreq.0 as u64).min(nyquist) << 32) / (msample_rate.0 as u64).max(1))
+        .min(u32::MAX as u64) as u32;
+    let sin = sin_interp(phase) << (COEF_SHIFT - 15);
+    // cos(w0) = 1 - 2 sin(w0/2)^2 is more precise for low frequencies
+    let sin_half = sin_interp(phase >> 1);
+    let cos = ONE - ((sin_half * sin_half) >> (29 - COEF_SHIFT));
+    (sin, cos)
+}
+
+/// Direct form I biquad filter
+///
+/// The truncation error of the output is fed back into the next sample,
+/// which keeps low frequency filters quiet.
+pub struct Biquad {
+    b0: i64,
+    b1: i64,
+    b2: i64,
+    a1: i64,
+    a2: i64,
+
+    x1: i16,
+    x2: i16,
+    y1: i16,
+    y2: i16,
+    err: i64,
+}
+
+impl Biquad {
+    /// Returns a filter that passes the signal unchanged.
+    pub fn new() -> Self {
+        Self {
+            b0: ONE,
+            b1: 0,
+            b2: 0,
+            a1: 0,
+            a2: 0,
+
+            x1: 0,
+            x2: 0,
+            y1: 0,
+            y2: 0,
+            err: 0,
+        }
+    }
+
+    fn set_coefs(&mut self, b0: i64, b1: i64, b2: i64, a0: i64, a1: i64, a2: i64) {
+        self.b0 = (b0 << COEF_SHIFT) / a0;
+        self.b1 = (b1 << COEF_SHIFT) / a0;
+        self.b2 = (b2 << COEF_SHIFT) / a0;
+        self.a1 = (a1 << COEF_SHIFT) / a0;
+        self.a2 = (a2 << COEF_SHIFT) / a0;
+    }
+
+    /// Configures a low shelf with slope 1 at `mfreq` boosting or cutting by
+    /// `gain_db` (clamped to [GAIN_DB_MAX]).
+    pub fn set_low_shelf(&mut self, mfreq: mHz, gain_db: i32, msample_rate: mHz) {
+        let (sqrt_a, a) = shelf_gain(gain_db);
+        let (sin, cos) = sin_cos(mfreq, msample_rate);
+        let beta = mul(mul(SQRT_2, sqrt_a), sin);
+        let (ap1, am1) = (a + ONE, a - ONE);
+        self.set_coefs(
+            mul(a, ap1 - mul(am1, cos) + beta),
+            2 * mul(a, am1 - mul(ap1, cos)),
+            mul(a, ap1 - mul(am1, cos) - beta),
+            ap1 + mul(am1, cos) + beta,
+            -2 * (am1 + mul(ap1, cos)),
+            ap1 + mul(am1, cos) - beta,
+        );
+    }
+
+    /// Configures a high shelf with slope 1 at `mfreq` boosting or cutting
+    /// by `gain_db` (clamped to [GAIN_DB_MAX]).
+    pub fn set_high_shelf(&mut self, mfreq: mHz, gain_db: i32, msample_rate: mHz) {
+        let (sqrt_a, a) = shelf_gain(gain_db);
+        let (sin, cos) = sin_cos(mfreq, msample_rate);
+        let beta = mul(mul(SQRT_2, sqrt_a), sin);
+        let (ap1, am1) = (a + ONE, a - ONE);
+        self.set_coefs(
+            mul(a, ap1 + mul(am1, cos) + beta),
+            -2 * mul(a, am1 + mul(ap1, cos)),
+            mul(a, ap1 + mul(am1, cos) - beta),
+            ap1 - mul(am1, cos) + beta,
+            2 * (am1 - mul(ap1, cos)),
+            ap1 - mul(am1, cos) - beta,
+        );
+    }
+
+    /// Configures a peaking filter at `mfreq` with quality factor `q`
+    /// (normalized to [Q_NORM]) boosting or cutting by `gain_db` (clamped to
+    /// [GAIN_DB_MAX]).
+    pub fn set_peak(&mut self, mfreq: mHz, q: u32, gain_db: i32, msample_rate: mHz) {
+        let (_, a) = shelf_gain(gain_db);
+        let (sin, cos) = sin_cos(mfreq, msample_rate);
+        let alpha = (sin * Q_NORM as i64) / (2 * q.max(1) as i64);
+        let alpha_a = mul(alpha, a);
+        let alpha_over_a = (alpha << COEF_SHIFT) / a;
+        self.set_coefs(
+            ONE + alpha_a,
+            -2 * cos,
+            ONE - alpha_a,
+            ONE + alpha_over_a,
+            -2 * cos,
+            ONE - alpha_over_a,
+        );
+    }
+
+    /// Filters the next sample.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        let acc = self.b0 * input as i64 + self.b1 * self.x1 as i64 + self.b2 * self.x2 as i64
+            - self.a1 * self.y1 as i64
+            - self.a2 * self.y2 as i64
+            + self.err;
+        let y = acc >> COEF_SHIFT;
+        let out = diag::clip(y) as i64;
+        self.err = if y == out { acc - (y << COEF_SHIFT) } else { 0 };
+
+        self.x2 = self.x1;
+        self.x1 = input;
+        self.y2 = self.y1;
+        self.y1 = out as i16;
+        self.y1
+    }
+
+    /// Clears the filter state.
+    pub fn reset(&mut self) {
+        self.x1 = 0;
+        self.x2 = 0;
+        self.y1 = 0;
+        self.y2 = 0;
+        self.err = 0;
+    }
+}
+
+impl Default for Biquad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for Biquad {
+    fn process(&mut self, input: i16) -> i16 {
+        Biquad::process(self, input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::osc::wavetable::SineOscillator;
+    use crate::util::units::{Frequency, Hz};
+
+    const MSAMPLE_RATE: mHz = mHz(44_100_000);
+
+    /// Returns the peak output amplitude for a sine input of amplitude 4096.
+    fn response(filter: &mut Biquad, freq: Hz) -> i32 {
+        let mut sine = SineOscillator::new();
+        sine.set_freq(freq);
+        sine.start();
+        filter.reset();
+        let mut max = 0;
+        for i in 0..44100 {
+            let y = filter.process(sine.next().unwrap() / 8) as i32;
+            if i > 22050 {
+                max = max.max(y.abs());
+            }
+        }
+        max
+    }
+
+    #[test]
+    fn test_biquad_low_shelf() {
+        let mut filter = Biquad::new();
+        filter.set_low_shelf(Hz(500).to_mHz(), 12, MSAMPLE_RATE);
+        // +12 dB ~ 3.98
+        assert!((response(&mut filter, Hz(30)) - 16_300).abs() < 400);
+        assert!((response(&mut filter, Hz(10_000)) - 4_096).abs() < 100);
+    }
+
+    #[test]
+    fn test_biquad_high_shelf() {
+        let mut filter = Biquad::new();
+        filter.set_high_shelf(Hz(2_000).to_mHz(), -12, MSAMPLE_RATE);
+        assert!((response(&mut filter, Hz(100)) - 4_096).abs() < 100);
+        assert!((response(&mut filter, Hz(15_000)) - 1_029).abs() < 50);
+    }
+
+    #[test]
+    fn test_biquad_peak() {
+        let mut filter = Biquad::new();
+        filter.set_peak(Hz(1_000).to_mHz(), Q_NORM, 6, MSAMPLE_RATE);
+        // +6 dB ~ 1.995
+        assert!((response(&mut filter, Hz(1_000)) - 8_172).abs() < 200);
+        assert!((response(&mut filter, Hz(50)) - 4_096).abs() < 100);
+        assert!((response(&mut filter, Hz(15_000)) - 4_096).abs() < 100);
+    }
+}
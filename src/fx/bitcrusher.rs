@@ -0,0 +1,131 @@
+// Bitcrusher reducing the bit depth and the sample rate.
+
+use crate::fx::Effect;
+use crate::util::dither::Dither;
+
+/// Bitcrusher
+///
+/// Keeps only the upper bits of every sample and holds each sample for a
+/// number of samples, which lowers the sample rate without filtering. With
+/// dither the quantization steps become noise, which keeps quiet details
+/// audible at low bit depths.
+///
+/// ```
+/// use isopod::fx::bitcrusher::Bitcrusher;
+///
+/// let mut crusher = Bitcrusher::new();
+/// crusher.set_bits(4);
+/// crusher.set_downsample(2);
+/// assert_eq!(crusher.process(5_000), 4_096);
+/// assert_eq!(crusher.process(-9_000), 4_096);
+/// ```
+pub struct Bitcrusher {
+    dither: Dither,
+    bits: u32,
+    downsample: u16,
+    dithered: bool,
+
+    hold: i16,
+    count: u16,
+}
+
+impl Bitcrusher {
+    pub fn new() -> Self {
+        Self {
+            dither: Dither::new(),
+            bits: 8,
+            downsample: 1,
+            dithered: false,
+
+            hold: 0,
+            count: 0,
+        }
+    }
+
+    /// Returns the next crushed sample.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        if self.count == 0 {
+            self.hold = if self.dithered {
+                self.dither.quantize(input, self.bits)
+            } else {
+                // Round to the nearest step
+                let shift = 16 - self.bits;
+                let half = (1 << shift) >> 1;
+                (((input as i32 + half) >> shift).min(i16::MAX as i32 >> shift) << shift) as i16
+            };
+        }
+        self.count = (self.count + 1) % self.downsample;
+        self.hold
+    }
+
+    /// Sets the bit depth from 1 to 16.
+    pub fn set_bits(&mut self, bits: u32) {
+        self.bits = bits.clamp(1, 16);
+    }
+
+    /// Holds every sample for `factor` samples, dividing the sample rate.
+    pub fn set_downsample(&mut self, factor: u16) {
+        self.downsample = factor.max(1);
+        self.count = 0;
+    }
+
+    /// Enables TPDF dither before the bit reduction.
+    pub fn set_dither(&mut self, dithered: bool) {
+        self.dithered = dithered;
+    }
+}
+
+impl Default for Bitcrusher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for Bitcrusher {
+    fn process(&mut self, input: i16) -> i16 {
+        Bitcrusher::process(self, input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::osc::luts::SINE_I16;
+
+    #[test]
+    fn test_bitcrusher_steps() {
+        let mut crusher = Bitcrusher::new();
+        crusher.set_bits(3);
+        let mut levels: Vec<i16> = (i16::MIN..=i16::MAX)
+            .step_by(7)
+            .map(|x| crusher.process(x))
+            .collect();
+        levels.dedup();
+        assert_eq!(levels.len(), 8);
+
+        crusher.set_bits(16);
+        crusher.set_downsample(4);
+        let out: Vec<i16> = (0..8).map(|x| crusher.process(x)).collect();
+        assert_eq!(out, [0, 0, 0, 0, 4, 4, 4, 4]);
+    }
+
+    #[test]
+    fn test_bitcrusher_dither() {
+        // A sine below half a step vanishes without dither, but not with it
+        let quiet = |dithered| {
+            let mut crusher = Bitcrusher::new();
+            crusher.set_bits(6);
+            crusher.set_dither(dithered);
+            let correlation: i64 = (0..4_096)
+                .map(|n| {
+                    let x = SINE_I16[n % SINE_I16.len()] / 128;
+                    crusher.process(x) as i64 * x as i64
+                })
+                .sum();
+            correlation
+        };
+        assert_eq!(quiet(false), 0);
+        assert!(quiet(true) > 0);
+    }
+}
@@ -0,0 +1,104 @@
+// Crossfader blending between two signals.
+
+use crate::util::units::{Sample, SAMPLE_NORM};
+
+/// Fade curve of a [Crossfader]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FadeCurve {
+    /// Gains add up to 1. Correlated signals keep their level.
+    Linear,
+    /// Squared gains add up to 1. Uncorrelated signals keep their loudness.
+    EqualPower,
+}
+
+/// Crossfader
+///
+/// The position goes from 0 (only `a`) to i16::MAX (only `b`).
+pub struct Crossfader {
+    curve: FadeCurve,
+    position: i16,
+    gain_a: i16,
+    gain_b: i16,
+}
+
+impl Crossfader {
+    pub fn new() -> Self {
+        let mut s = Self {
+            curve: FadeCurve::Linear,
+            position: 0,
+            gain_a: 0,
+            gain_b: 0,
+        };
+        s.update_gains();
+        s
+    }
+
+    fn update_gains(&mut self) {
+        let position = self.position.max(0) as i32;
+        match self.curve {
+            FadeCurve::Linear => {
+                let b = (position * SAMPLE_NORM) / i16::MAX as i32;
+                self.gain_b = b.min(i16::MAX as i32) as i16;
+                self.gain_a = (SAMPLE_NORM - b).min(i16::MAX as i32) as i16;
+            }
+            FadeCurve::EqualPower => {
+                let (a, b) = Sample::equal_power_gains(self.position);
+                (self.gain_a, self.gain_b) = (a.0, b.0);
+            }
+        }
+    }
+
+    /// Returns the blend of `a` and `b` at the current position.
+    #[inline]
+    pub fn process(&self, a: i16, b: i16) -> i16 {
+        Sample(a)
+            .multiply_normed(Sample(self.gain_a))
+            .saturating_add(Sample(b).multiply_normed(Sample(self.gain_b)))
+            .0
+    }
+
+    /// Sets the fade position.
+    pub fn set_position(&mut self, position: i16) {
+        self.position = position;
+        self.update_gains();
+    }
+
+    /// Sets the fade curve.
+    pub fn set_curve(&mut self, curve: FadeCurve) {
+        self.curve = curve;
+        self.update_gains();
+    }
+}
+
+impl Default for Crossfader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crossfader_linear() {
+        let mut fader = Crossfader::new();
+        assert!((fader.process(10_000, -10_000) - 10_000).abs() <= 1);
+        fader.set_position(i16::MAX / 2);
+        assert!((fader.process(10_000, 6_000) - 8_000).abs() <= 1);
+        fader.set_position(i16::MAX);
+        assert!((fader.process(10_000, -10_000) + 10_000).abs() <= 1);
+    }
+
+    #[test]
+    fn test_crossfader_equal_power() {
+        let mut fader = Crossfader::new();
+        fader.set_curve(FadeCurve::EqualPower);
+        fader.set_position(i16::MAX / 2);
+        // cos(pi/4) = sin(pi/4) = 0.7071
+        assert!((fader.process(10_000, 0) - 7_071).abs() < 10);
+        assert!((fader.process(0, 10_000) - 7_071).abs() < 10);
+        fader.set_position(i16::MAX);
+        assert_eq!(fader.process(10_000, 0), 0);
+    }
+}
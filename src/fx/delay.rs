@@ -0,0 +1,136 @@
+// Fixed size delay line with integer and fractional read taps.
+
+use crate::util::interp;
+
+/// Fractional bits of delay times passed to [DelayLine::read_frac]
+pub const DELAY_FRAC_BITS: u32 = interp::FRAC_BITS;
+
+/// Circular delay buffer of `N` samples
+///
+/// The buffer lives inside the struct, so no allocation is necessary. A delay
+/// of 0 returns the most recently written sample.
+pub struct DelayLine<const N: usize> {
+    buffer: [i16; N],
+    // Index of the most recently written sample
+    pos: usize,
+}
+
+impl<const N: usize> DelayLine<N> {
+    pub fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            pos: 0,
+        }
+    }
+
+    /// Pushes the next sample into the delay line.
+    #[inline]
+    pub fn write(&mut self, signal: i16) {
+        self.pos += 1;
+        if self.pos >= N {
+            self.pos = 0;
+        }
+        self.buffer[self.pos] = signal;
+    }
+
+    /// Returns the sample written `delay` samples ago. Delays beyond the
+    /// buffer length are clamped to the oldest sample.
+    #[inline]
+    pub fn read(&self, delay: usize) -> i16 {
+        let delay = delay.min(N - 1);
+        let idx = if delay > self.pos {
+            self.pos + N - delay
+        } else {
+            self.pos - delay
+        };
+        self.buffer[idx]
+    }
+
+    /// Returns the linearly interpolated sample at a fractional delay with
+    /// [DELAY_FRAC_BITS] fractional bits.
+    #[inline]
+    pub fn read_frac(&self, delay: u32) -> i16 {
+        let idx = (delay >> DELAY_FRAC_BITS) as usize;
+        let frac = delay & (interp::FRAC_ONE - 1);
+        interp::linear(self.read(idx), self.read(idx + 1), frac)
+    }
+
+    /// Returns the cubic interpolated sample at a fractional delay with
+    /// [DELAY_FRAC_BITS] fractional bits, see [interp::cubic]. Delays below
+    /// one sample use the most recent sample twice.
+    #[inline]
+    pub fn read_cubic(&self, delay: u32) -> i16 {
+        let idx = (delay >> DELAY_FRAC_BITS) as usize;
+        let frac = delay & (interp::FRAC_ONE - 1);
+        let newer = self.read(idx.saturating_sub(1));
+        interp::cubic(
+            newer,
+            self.read(idx),
+            self.read(idx + 1),
+            self.read(idx + 2),
+            frac,
+        )
+    }
+
+    /// Sets all samples to 0.
+    pub fn clear(&mut self) {
+        self.buffer = [0; N];
+    }
+
+    /// Returns the length of the delay line in samples.
+    pub fn len(&self) -> usize {
+        N
+    }
+
+    /// True if the delay line can't hold any samples.
+    pub fn is_empty(&self) -> bool {
+        N == 0
+    }
+}
+
+impl<const N: usize> Default for DelayLine<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delay_line() {
+        let mut delay = DelayLine::<8>::new();
+        for x in 1..=20 {
+            delay.write(x);
+        }
+        assert_eq!(delay.read(0), 20);
+        assert_eq!(delay.read(3), 17);
+        assert_eq!(delay.read(7), 13);
+        // Clamped to the oldest sample
+        assert_eq!(delay.read(100), 13);
+    }
+
+    #[test]
+    fn test_delay_line_frac() {
+        let mut delay = DelayLine::<8>::new();
+        delay.write(0);
+        delay.write(1000);
+        assert_eq!(delay.read_frac(0), 1000);
+        assert_eq!(delay.read_frac(1 << (DELAY_FRAC_BITS - 1)), 500);
+        assert_eq!(delay.read_frac(1 << DELAY_FRAC_BITS), 0);
+    }
+
+    #[test]
+    fn test_delay_line_cubic() {
+        let mut delay = DelayLine::<8>::new();
+        for x in [0, 100, 200, 300] {
+            delay.write(x);
+        }
+        // Exact on a ramp, between the two newest samples at the start
+        let half = 1 << (DELAY_FRAC_BITS - 1);
+        assert_eq!(delay.read_cubic(half + (1 << DELAY_FRAC_BITS)), 150);
+        assert_eq!(delay.read_cubic(0), 300);
+        assert!((delay.read_cubic(half) - 250).abs() <= 10);
+    }
+}
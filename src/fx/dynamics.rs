@@ -0,0 +1,388 @@
+// Dynamics processors (compressor and noise gate). Both can be keyed by an
+// external sidechain signal, e.g. for kick-triggered ducking of pads.
+
+use crate::fx::Effect;
+use crate::util::units::{dB, mHz, ms, Sample, SAMPLE_NORM};
+
+/// Fixed point normalization of the smoothing coefficients
+pub(crate) const COEF_NORM: u32 = 1 << 24;
+/// Fractional bits of the envelope state
+const LEVEL_SHIFT: u32 = 16;
+
+/// Largest compression ratio, which already limits any level to the
+/// threshold
+pub const RATIO_MAX: u32 = 1 << 16;
+
+/// Attack time of the gate detector
+const GATE_DETECTOR_ATTACK: ms = ms(1);
+/// Release time of the gate detector
+const GATE_DETECTOR_RELEASE: ms = ms(10);
+
+/// Returns the one-pole smoothing coefficient for a time constant. We use
+/// the first order approximation 1 - exp(-1/N) ~ 1/N, where N is the time
+/// constant in samples.
+pub(crate) fn coefficient(time: ms, msample_rate: mHz) -> u32 {
+    let samples = (time.0 as u64 * msample_rate.0 as u64) / 1_000_000;
+    match (COEF_NORM as u64).checked_div(samples) {
+        Some(coef) => (coef as u32).max(1),
+        None => COEF_NORM,
+    }
+}
+
+/// Peak envelope follower with separate attack and release times
+pub struct EnvelopeFollower {
+    // Envelope with LEVEL_SHIFT fractional bits
+    level: i32,
+
+    attack: ms,
+    release: ms,
+    attack_coef: u32,
+    release_coef: u32,
+
+    msample_rate: mHz,
+}
+
+impl EnvelopeFollower {
+    pub fn new() -> Self {
+        let mut s = Self {
+            level: 0,
+
+            attack: ms(10),
+            release: ms(100),
+            attack_coef: 0,
+            release_coef: 0,
+
+            msample_rate: mHz(44_100_000),
+        };
+        s.update_coefs();
+        s
+    }
+
+    fn update_coefs(&mut self) {
+        self.attack_coef = coefficient(self.attack, self.msample_rate);
+        self.release_coef = coefficient(self.release, self.msample_rate);
+    }
+
+    /// Feeds the next sample and returns the current envelope level.
+    #[inline]
+    pub fn process(&mut self, signal: i16) -> i16 {
+        let target = (signal.unsigned_abs().min(i16::MAX as u16) as i32) << LEVEL_SHIFT;
+        let coef = if target > self.level {
+            self.attack_coef
+        } else {
+            self.release_coef
+        };
+        self.level += (((target - self.level) as i64 * coef as i64) / COEF_NORM as i64) as i32;
+        self.get_level()
+    }
+
+    /// Returns the current envelope level.
+    pub fn get_level(&self) -> i16 {
+        (self.level >> LEVEL_SHIFT) as i16
+    }
+
+    /// Returns the current envelope level in dBFS.
+    pub fn get_level_db(&self) -> dB {
+        dB::from_sample(self.get_level())
+    }
+
+    /// Sets the attack time, i.e. the time constant for rising levels.
+    pub fn set_attack_ms(&mut self, attack: ms) {
+        self.attack = attack;
+        self.update_coefs();
+    }
+
+    /// Sets the release time, i.e. the time constant for falling levels.
+    pub fn set_release_ms(&mut self, release: ms) {
+        self.release = release;
+        self.update_coefs();
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        self.update_coefs();
+    }
+}
+
+impl Default for EnvelopeFollower {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Feed-forward compressor
+///
+/// The gain reduction is computed in the linear domain: levels above the
+/// threshold are divided by the ratio. This avoids logarithms in the
+/// sample loop at the cost of a softer knee than a dB-domain compressor.
+pub struct Compressor {
+    detector: EnvelopeFollower,
+    threshold: i16,
+    ratio: u32,
+    // Current gain normalized to SAMPLE_NORM
+    gain: i16,
+}
+
+impl Compressor {
+    pub fn new() -> Self {
+        Self {
+            detector: EnvelopeFollower::new(),
+            threshold: i16::MAX / 4,
+            ratio: 4,
+            gain: i16::MAX,
+        }
+    }
+
+    /// Compresses `input` based on its own level.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        self.process_with_key(input, input)
+    }
+
+    /// Compresses `input` based on the level of the sidechain signal `key`.
+    #[inline]
+    pub fn process_with_key(&mut self, input: i16, key: i16) -> i16 {
+        let level = self.detector.process(key) as i32;
+        let threshold = self.threshold as i32;
+        self.gain = if level > threshold {
+            let out_level = threshold + (level - threshold) / self.ratio as i32;
+            ((out_level * SAMPLE_NORM) / level).min(i16::MAX as i32) as i16
+        } else {
+            i16::MAX
+        };
+        Sample(input).multiply_normed(Sample(self.gain)).0
+    }
+
+    /// Returns the current gain normalized to [SAMPLE_NORM].
+    pub fn get_gain(&self) -> i16 {
+        self.gain
+    }
+
+    /// Returns the current gain reduction as a level of at most 0 dB.
+    pub fn get_gain_db(&self) -> dB {
+        dB::from_gain(self.gain as u32)
+    }
+
+    /// Sets the level above which the signal gets compressed.
+    pub fn set_threshold(&mut self, threshold: i16) {
+        self.threshold = threshold.max(0);
+    }
+
+    /// Sets the threshold in dBFS.
+    pub fn set_threshold_db(&mut self, threshold: dB) {
+        self.set_threshold(threshold.to_q15());
+    }
+
+    /// Sets the compression ratio `ratio`:1 up to [RATIO_MAX]. A ratio of 0
+    /// is treated as 1.
+    pub fn set_ratio(&mut self, ratio: u32) {
+        self.ratio = ratio.clamp(1, RATIO_MAX);
+    }
+
+    /// Sets the attack time of the level detector.
+    pub fn set_attack_ms(&mut self, attack: ms) {
+        self.detector.set_attack_ms(attack);
+    }
+
+    /// Sets the release time of the level detector.
+    pub fn set_release_ms(&mut self, release: ms) {
+        self.detector.set_release_ms(release);
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.detector.set_msample_rate(msample_rate);
+    }
+}
+
+impl Default for Compressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for Compressor {
+    fn process(&mut self, input: i16) -> i16 {
+        Compressor::process(self, input)
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        Compressor::set_msample_rate(self, msample_rate);
+    }
+}
+
+/// Noise gate
+///
+/// Mutes the signal while the detected level is below the threshold. Attack
+/// and release set how fast the gate opens and closes.
+pub struct Gate {
+    detector: EnvelopeFollower,
+    envelope: EnvelopeFollower,
+    threshold: i16,
+}
+
+impl Gate {
+    pub fn new() -> Self {
+        let mut s = Self {
+            detector: EnvelopeFollower::new(),
+            envelope: EnvelopeFollower::new(),
+            threshold: i16::MAX / 64,
+        };
+        s.detector.set_attack_ms(GATE_DETECTOR_ATTACK);
+        s.detector.set_release_ms(GATE_DETECTOR_RELEASE);
+        s.envelope.set_attack_ms(ms(1));
+        s.envelope.set_release_ms(ms(50));
+        s
+    }
+
+    /// Gates `input` based on its own level.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        self.process_with_key(input, input)
+    }
+
+    /// Gates `input` based on the level of the sidechain signal `key`.
+    #[inline]
+    pub fn process_with_key(&mut self, input: i16, key: i16) -> i16 {
+        let open = self.detector.process(key) >= self.threshold;
+        let gain = self.envelope.process(if open { i16::MAX } else { 0 });
+        Sample(input).multiply_normed(Sample(gain)).0
+    }
+
+    /// Returns the current gain normalized to [SAMPLE_NORM].
+    pub fn get_gain(&self) -> i16 {
+        self.envelope.get_level()
+    }
+
+    /// Sets the level below which the gate closes.
+    pub fn set_threshold(&mut self, threshold: i16) {
+        self.threshold = threshold.max(0);
+    }
+
+    /// Sets the threshold in dBFS.
+    pub fn set_threshold_db(&mut self, threshold: dB) {
+        self.set_threshold(threshold.to_q15());
+    }
+
+    /// Sets the time it takes the gate to open.
+    pub fn set_attack_ms(&mut self, attack: ms) {
+        self.envelope.set_attack_ms(attack);
+    }
+
+    /// Sets the time it takes the gate to close.
+    pub fn set_release_ms(&mut self, release: ms) {
+        self.envelope.set_release_ms(release);
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.detector.set_msample_rate(msample_rate);
+        self.envelope.set_msample_rate(msample_rate);
+    }
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for Gate {
+    fn process(&mut self, input: i16) -> i16 {
+        Gate::process(self, input)
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        Gate::set_msample_rate(self, msample_rate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_envelope_follower() {
+        let mut follower = EnvelopeFollower::new();
+        follower.set_attack_ms(ms(1));
+        follower.set_release_ms(ms(10));
+        for _ in 0..4410 {
+            follower.process(-20_000);
+        }
+        assert!((follower.get_level() - 20_000).abs() < 100);
+        for _ in 0..4410 {
+            follower.process(0);
+        }
+        assert!(follower.get_level() < 100);
+    }
+
+    #[test]
+    fn test_compressor() {
+        let mut comp = Compressor::new();
+        comp.set_threshold(8_000);
+        comp.set_ratio(4);
+        let mut out = 0;
+        for _ in 0..44100 {
+            out = comp.process(24_000);
+        }
+        // 8_000 + (24_000 - 8_000) / 4
+        assert!((out - 12_000).abs() < 100);
+
+        for _ in 0..44100 {
+            out = comp.process(4_000);
+        }
+        assert!((out - 4_000).abs() < 10);
+
+        // Limits at the largest ratios
+        comp.set_ratio(u32::MAX);
+        for _ in 0..44100 {
+            out = comp.process(24_000);
+        }
+        assert!((out - 8_000).abs() < 100, "{}", out);
+    }
+
+    #[test]
+    fn test_compressor_db() {
+        let mut comp = Compressor::new();
+        comp.set_threshold_db(dB(-12));
+        comp.set_ratio(2);
+        for _ in 0..44100 {
+            comp.process(i16::MAX);
+        }
+        // Linear (8_231 + (32_767 - 8_231) / 2) / 32_767 ~ 0.626
+        assert_eq!(comp.get_gain_db(), dB(-4));
+        assert_eq!(comp.detector.get_level_db(), dB(0));
+    }
+
+    #[test]
+    fn test_compressor_sidechain() {
+        let mut comp = Compressor::new();
+        comp.set_threshold(1_000);
+        comp.set_ratio(10);
+        let mut out = 0;
+        for _ in 0..44100 {
+            out = comp.process_with_key(4_000, 30_000);
+        }
+        assert!(out < 1_000);
+        for _ in 0..44100 {
+            out = comp.process_with_key(4_000, 0);
+        }
+        assert!((out - 4_000).abs() < 10);
+    }
+
+    #[test]
+    fn test_gate_sidechain() {
+        let mut gate = Gate::new();
+        gate.set_threshold(1_000);
+        let mut out = 0;
+        for _ in 0..44100 {
+            out = gate.process_with_key(10_000, 0);
+        }
+        assert_eq!(out, 0);
+        for _ in 0..44100 {
+            out = gate.process_with_key(10_000, 5_000);
+        }
+        assert!((out - 10_000).abs() < 10);
+    }
+}
@@ -0,0 +1,167 @@
+// Three-band equalizer built from biquad sections.
+
+use crate::fx::biquad::{Biquad, Q_NORM};
+use crate::fx::Effect;
+use crate::util::units::{mHz, Frequency, Hz};
+
+/// Three-band equalizer
+///
+/// Consists of a low shelf, a mid peak and a high shelf filter in series.
+/// All gains are given in dB and are clamped to
+/// [crate::fx::biquad::GAIN_DB_MAX].
+pub struct Eq3 {
+    low: Biquad,
+    mid: Biquad,
+    high: Biquad,
+
+    low_mfreq: mHz,
+    mid_mfreq: mHz,
+    high_mfreq: mHz,
+    mid_q: u32,
+
+    low_gain: i32,
+    mid_gain: i32,
+    high_gain: i32,
+
+    msample_rate: mHz,
+}
+
+impl Eq3 {
+    pub fn new() -> Self {
+        let mut s = Self {
+            low: Biquad::new(),
+            mid: Biquad::new(),
+            high: Biquad::new(),
+
+            low_mfreq: Hz(250).to_mHz(),
+            mid_mfreq: Hz(1_000).to_mHz(),
+            high_mfreq: Hz(4_000).to_mHz(),
+            mid_q: Q_NORM,
+
+            low_gain: 0,
+            mid_gain: 0,
+            high_gain: 0,
+
+            msample_rate: mHz(44_100_000),
+        };
+        s.update_coefs();
+        s
+    }
+
+    fn update_coefs(&mut self) {
+        self.low
+            .set_low_shelf(self.low_mfreq, self.low_gain, self.msample_rate);
+        self.mid
+            .set_peak(self.mid_mfreq, self.mid_q, self.mid_gain, self.msample_rate);
+        self.high
+            .set_high_shelf(self.high_mfreq, self.high_gain, self.msample_rate);
+    }
+
+    /// Equalizes the next sample.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        self.high.process(self.mid.process(self.low.process(input)))
+    }
+
+    /// Sets the gain of the low shelf in dB.
+    pub fn set_low_gain(&mut self, gain_db: i32) {
+        self.low_gain = gain_db;
+        self.update_coefs();
+    }
+
+    /// Sets the gain of the mid peak in dB.
+    pub fn set_mid_gain(&mut self, gain_db: i32) {
+        self.mid_gain = gain_db;
+        self.update_coefs();
+    }
+
+    /// Sets the gain of the high shelf in dB.
+    pub fn set_high_gain(&mut self, gain_db: i32) {
+        self.high_gain = gain_db;
+        self.update_coefs();
+    }
+
+    /// Sets the corner frequency of the low shelf in mHz.
+    pub fn set_low_mfreq(&mut self, mfreq: mHz) {
+        self.low_mfreq = mfreq;
+        self.update_coefs();
+    }
+
+    /// Sets the center frequency of the mid peak in mHz.
+    pub fn set_mid_mfreq(&mut self, mfreq: mHz) {
+        self.mid_mfreq = mfreq;
+        self.update_coefs();
+    }
+
+    /// Sets the quality factor of the mid peak normalized to
+    /// [crate::fx::biquad::Q_NORM].
+    pub fn set_mid_q(&mut self, q: u32) {
+        self.mid_q = q;
+        self.update_coefs();
+    }
+
+    /// Sets the corner frequency of the high shelf in mHz.
+    pub fn set_high_mfreq(&mut self, mfreq: mHz) {
+        self.high_mfreq = mfreq;
+        self.update_coefs();
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        self.update_coefs();
+    }
+}
+
+impl Default for Eq3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for Eq3 {
+    fn process(&mut self, input: i16) -> i16 {
+        Eq3::process(self, input)
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        Eq3::set_msample_rate(self, msample_rate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::osc::wavetable::SineOscillator;
+
+    fn peak(eq: &mut Eq3, freq: Hz) -> i32 {
+        let mut sine = SineOscillator::new();
+        sine.set_freq(freq);
+        sine.start();
+        (0..44100)
+            .map(|_| eq.process(sine.next().unwrap() / 8) as i32)
+            .skip(22050)
+            .map(|y| y.abs())
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_eq3_flat() {
+        let mut eq = Eq3::new();
+        for freq in [50, 1_000, 10_000] {
+            assert!((peak(&mut eq, Hz(freq)) - 4_096).abs() < 50);
+        }
+    }
+
+    #[test]
+    fn test_eq3_bands() {
+        let mut eq = Eq3::new();
+        eq.set_low_gain(-12);
+        eq.set_mid_gain(6);
+        eq.set_high_gain(12);
+        assert!(peak(&mut eq, Hz(40)) < 1_300);
+        assert!(peak(&mut eq, Hz(1_000)) > 7_000);
+        assert!(peak(&mut eq, Hz(15_000)) > 14_000);
+    }
+}
@@ -1,18 +1,39 @@
+use crate::util::diag::{state_add, state_sub};
+use crate::util::fixed::div_frac;
+use crate::util::param::{check_mfreq, check_msample_rate, ParamError};
+use crate::util::sample::SampleType;
 use crate::util::units::mHz;
 
-// Constant multiplier for precision divisions
-const NORM: u32 = 1 << 12;
-pub const Q_MAX: u32 = NORM;
+// Fractional bits of the tuning and damping coefficients
+const COEFF_FRAC: u32 = 12;
+pub const Q_MAX: u32 = 1 << COEFF_FRAC;
+
+// 2*pi as the fraction 710/113 (relative error < 1e-7)
+const TWO_PI_NUM: u64 = 710;
+const TWO_PI_DEN: u64 = 113;
+
+/// Divisor of the sample rate that gives the highest stable cutoff, since
+/// this form of the filter becomes unstable well below Nyquist
+const STABLE_DIV: u32 = 6;
 
 // MF = 1/(2*pi*dt) for mHz
 // sample rate dt = 60 u
 // const MF: i32 = 2652582;
 
-pub struct StateVariableFilter {
-    lp: i16,
-    bp: i16,
-    hp: i16,
-    no: i16,
+/// Output of a [StateVariableFilter] selected in block processing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SvfOutput {
+    Lowpass,
+    Bandpass,
+    Highpass,
+    Notch,
+}
+
+pub struct StateVariableFilter<S: SampleType = i16> {
+    lp: S,
+    bp: S,
+    hp: S,
+    no: S,
 
     ft: u32,
     q_inv: u32,
@@ -26,62 +47,243 @@ pub struct StateVariableFilter {
 /// which, in turn, seems to be based on
 /// Hal Chamberlin, “Musical Applications of Microprocessors,” 2nd Ed,
 /// Hayden Book Company 1985. pp 490-492.
-impl StateVariableFilter {
+///
+/// The states are of the [SampleType] `S`, so the same filter runs on i16
+/// on MCUs and on i32 or f32 with more headroom and precision.
+impl<S: SampleType> StateVariableFilter<S> {
     pub fn new() -> Self {
         Self {
-            lp: 0,
-            bp: 0,
-            hp: 0,
-            no: 0,
+            lp: S::ZERO,
+            bp: S::ZERO,
+            hp: S::ZERO,
+            no: S::ZERO,
 
             ft: 0,
-            q_inv: NORM - 0,
+            q_inv: Q_MAX,
 
             msample_rate: mHz(44_100_000),
         }
     }
 
-    pub fn feed(&mut self, signal: i16) {
+    /// Feeds one sample. The states saturate, which the diagnostics count as
+    /// instabilities.
+    pub fn feed(&mut self, signal: S) {
         // TODO To gain performance we might try to store the sign, perform u32
         // divisions, then restore the sign.
-        self.lp += ((self.ft as i32 * self.bp as i32) / NORM as i32) as i16;
-        // dbg!(signal, self.lp, self.bp, self.hp, NORM, self.q_inv);
-        self.hp = signal - self.lp - ((self.q_inv as i32 * self.bp as i32) / NORM as i32) as i16;
-        self.bp += ((self.ft as i32 * self.hp as i32) / NORM as i32) as i16;
-        self.no = self.hp + self.lp;
+        let (ft, q_inv, shift) = (self.ft as i64, self.q_inv as i64, COEFF_FRAC);
+        self.lp = state_add(self.lp, self.bp.scale(ft, shift));
+        self.hp = state_sub(state_sub(signal, self.lp), self.bp.scale(q_inv, shift));
+        self.bp = state_add(self.bp, self.hp.scale(ft, shift));
+        self.no = self.hp.saturating_add(self.lp);
+    }
+
+    /// Feeds all samples in `buf` and replaces them with the selected
+    /// output. Equivalent to calling [StateVariableFilter::feed] per sample,
+    /// but keeps the filter state in registers.
+    pub fn process_block(&mut self, buf: &mut [S], output: SvfOutput) {
+        let (ft, q_inv, shift) = (self.ft as i64, self.q_inv as i64, COEFF_FRAC);
+        let (mut lp, mut bp, mut hp) = (self.lp, self.bp, self.hp);
+        for x in buf.iter_mut() {
+            lp = state_add(lp, bp.scale(ft, shift));
+            hp = state_sub(state_sub(*x, lp), bp.scale(q_inv, shift));
+            bp = state_add(bp, hp.scale(ft, shift));
+            *x = match output {
+                SvfOutput::Lowpass => lp,
+                SvfOutput::Bandpass => bp,
+                SvfOutput::Highpass => hp,
+                SvfOutput::Notch => hp.saturating_add(lp),
+            };
+        }
+        (self.lp, self.bp, self.hp) = (lp, bp, hp);
+        self.no = hp.saturating_add(lp);
     }
 
-    pub fn get_lp(&self) -> i16 {
+    pub fn get_lp(&self) -> S {
         self.lp
     }
 
-    pub fn get_bp(&self) -> i16 {
+    pub fn get_bp(&self) -> S {
         self.bp
     }
 
-    pub fn get_hp(&self) -> i16 {
+    pub fn get_hp(&self) -> S {
         self.hp
     }
 
-    pub fn get_no(&self) -> i16 {
+    pub fn get_no(&self) -> S {
         self.no
     }
 
+    /// Sets the cutoff in mHz. Frequencies above a sixth of the sample
+    /// rate, where the filter becomes unstable, are clamped, see
+    /// [StateVariableFilter::get_mfreq_max]. See
+    /// [StateVariableFilter::try_set_mfreq] for a checked variant.
     pub fn set_mfreq(&mut self, mfreq: mHz) {
-        // We use a first order Tailor approximation here. -> Deviations close
-        // to Nyquist frequency.
-        self.ft = ((NORM as i64 * mfreq.0 as i64) / self.msample_rate.0 as i64) as u32;
+        let mfreq = mfreq.0.min(self.get_mfreq_max().0);
+        // The tuning coefficient is 2*sin(pi*f/fs). We use a first order
+        // Tailor approximation here. -> Deviations close to Nyquist frequency.
+        self.ft = div_frac(
+            mfreq as u64 * TWO_PI_NUM,
+            TWO_PI_DEN * self.msample_rate.0 as u64,
+            COEFF_FRAC,
+        ) as u32;
+    }
+
+    /// Returns the highest stable cutoff, a sixth of the sample rate.
+    pub fn get_mfreq_max(&self) -> mHz {
+        mHz(self.msample_rate.0 / STABLE_DIV)
+    }
+
+    /// Sets the cutoff in mHz or fails if it is above Nyquist. Cutoffs
+    /// between the highest stable one and Nyquist are clamped.
+    pub fn try_set_mfreq(&mut self, mfreq: mHz) -> Result<(), ParamError> {
+        check_mfreq(mfreq, self.msample_rate)?;
+        self.set_mfreq(mfreq);
+        Ok(())
     }
 
     pub fn set_q(&mut self, q: u32) {
-        if q > NORM {
-            self.q_inv = 0;
-        } else {
-            self.q_inv = NORM - q;
-        }
+        self.q_inv = Q_MAX.saturating_sub(q);
     }
 
+    pub fn get_q(&self) -> u32 {
+        Q_MAX - self.q_inv
+    }
+
+    /// Sets the sample rate in mHz, which applies to the next cutoff. A rate
+    /// of 0 is raised to 1 mHz, see [StateVariableFilter::try_set_msample_rate]
+    /// for a checked variant.
     pub fn set_msample_rate(&mut self, msample_rate: mHz) {
-        self.msample_rate = msample_rate;
+        self.msample_rate = mHz(msample_rate.0.max(1));
+    }
+
+    /// Sets the sample rate in mHz or fails if it is 0.
+    pub fn try_set_msample_rate(&mut self, msample_rate: mHz) -> Result<(), ParamError> {
+        check_msample_rate(msample_rate)?;
+        self.set_msample_rate(msample_rate);
+        Ok(())
+    }
+}
+
+impl<S: SampleType> Default for StateVariableFilter<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::osc::wavetable::SineOscillator;
+    use crate::util::units::{Frequency, Hz};
+
+    #[test]
+    fn test_svf_block() {
+        let mut filter = StateVariableFilter::new();
+        filter.set_mfreq(Hz(500).to_mHz());
+        filter.set_q(Q_MAX / 2);
+        let mut block = StateVariableFilter::new();
+        block.set_mfreq(Hz(500).to_mHz());
+        block.set_q(Q_MAX / 2);
+        let mut sine = SineOscillator::new();
+        sine.start();
+        let input: Vec<i16> = (0..256).map(|_| sine.next().unwrap() / 8).collect();
+        let mut buf = input.clone();
+        block.process_block(&mut buf[..100], SvfOutput::Lowpass);
+        block.process_block(&mut buf[100..], SvfOutput::Lowpass);
+        for (x, y) in input.iter().zip(buf.iter()) {
+            filter.feed(*x);
+            assert_eq!(filter.get_lp(), *y);
+        }
+        assert_eq!(filter.get_no(), block.get_no());
+    }
+
+    #[test]
+    fn test_svf_sample_types() {
+        let mut filters = (
+            StateVariableFilter::<i16>::new(),
+            StateVariableFilter::<i32>::new(),
+            StateVariableFilter::<f32>::new(),
+        );
+        filters.0.set_mfreq(Hz(800).to_mHz());
+        filters.1.set_mfreq(Hz(800).to_mHz());
+        filters.2.set_mfreq(Hz(800).to_mHz());
+        let mut sine = SineOscillator::new();
+        sine.set_freq(Hz(300));
+        sine.start();
+        for _ in 0..1_000 {
+            let x = sine.next().unwrap() / 2;
+            filters.0.feed(x);
+            filters.1.feed(i32::from_i16(x));
+            filters.2.feed(f32::from_i16(x));
+            // The wider types only differ by the rounding of i16
+            let lp = filters.0.get_lp() as i32;
+            assert!((filters.1.get_lp().to_i16() as i32 - lp).abs() < 64);
+            assert!((filters.2.get_lp().to_i16() as i32 - lp).abs() < 64);
+        }
+    }
+
+    #[test]
+    fn test_svf_checked() {
+        let mut filter = StateVariableFilter::<i16>::new();
+        assert_eq!(
+            filter.try_set_msample_rate(mHz(0)),
+            Err(ParamError::ZeroSampleRate)
+        );
+        assert_eq!(
+            filter.try_set_mfreq(Hz(22_051).to_mHz()),
+            Err(ParamError::AboveNyquist)
+        );
+        assert_eq!(filter.try_set_mfreq(Hz(1_000).to_mHz()), Ok(()));
+
+        // Out of range values saturate instead of dividing by zero
+        let mut max = StateVariableFilter::<i16>::new();
+        max.set_mfreq(Hz(7_350).to_mHz());
+        assert_eq!(max.get_mfreq_max(), Hz(7_350).to_mHz());
+        filter.set_msample_rate(mHz(0));
+        filter.set_msample_rate(mHz(44_100_000));
+        filter.set_mfreq(Hz(40_000).to_mHz());
+        assert_eq!(filter.ft, max.ft);
+    }
+
+    #[test]
+    fn test_svf_stable() {
+        // Noise at the highest cutoff and a high resonance, with states
+        // that can't saturate
+        let mut filter = StateVariableFilter::<f32>::new();
+        filter.set_mfreq(Hz(22_050).to_mHz());
+        filter.set_q(Q_MAX - Q_MAX / 8);
+        let mut noise = crate::osc::noise::WhiteNoise::new();
+        let mut peak = 0.0_f32;
+        for _ in 0..44_100 {
+            filter.feed(noise.next().unwrap() as f32);
+            peak = peak.max(filter.get_lp().abs());
+        }
+        // Diverges to infinity when clamped to Nyquist only
+        assert!(peak < 1e6, "{}", peak);
+    }
+
+    fn bp_response(freq: Hz) -> i32 {
+        let mut filter = StateVariableFilter::new();
+        filter.set_mfreq(Hz(1_000).to_mHz());
+        filter.set_q(Q_MAX / 2);
+        let mut sine = SineOscillator::new();
+        sine.set_freq(freq);
+        sine.start();
+        let mut max = 0;
+        for i in 0..44100 {
+            filter.feed(sine.next().unwrap() / 8);
+            if i > 22050 {
+                max = max.max((filter.get_bp() as i32).abs());
+            }
+        }
+        max
+    }
+
+    #[test]
+    fn test_svf_bp_center() {
+        let center = bp_response(Hz(1_000));
+        assert!(center > bp_response(Hz(800)));
+        assert!(center > bp_response(Hz(1_250)));
     }
 }
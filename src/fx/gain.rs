@@ -0,0 +1,138 @@
+// Gain stage with a level in dB and click-free level changes.
+
+use crate::fx::Effect;
+use crate::util::sample::SampleType;
+use crate::util::units::{dB, mHz, ms};
+
+/// Extra fractional bits of the ramped gain
+const RAMP_SHIFT: u32 = 8;
+
+/// Gain stage
+///
+/// The level is converted to a linear multiplier normalized to
+/// [crate::util::units::SAMPLE_NORM]. Level changes are applied as a linear
+/// ramp over the smoothing time to avoid clicks. The output saturates.
+pub struct Gain {
+    level: dB,
+    // Gain with RAMP_SHIFT extra fractional bits
+    gain: i32,
+    target: i32,
+    step: i32,
+    remaining: u32,
+
+    smoothing: ms,
+    msample_rate: mHz,
+}
+
+impl Gain {
+    pub fn new() -> Self {
+        let target = (dB(0).to_gain() << RAMP_SHIFT) as i32;
+        Self {
+            level: dB(0),
+            gain: target,
+            target,
+            step: 0,
+            remaining: 0,
+
+            smoothing: ms(10),
+            msample_rate: mHz(44_100_000),
+        }
+    }
+
+    /// Scales and returns the next sample.
+    #[inline]
+    pub fn process<S: SampleType>(&mut self, input: S) -> S {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.gain = if self.remaining == 0 {
+                self.target
+            } else {
+                self.gain + self.step
+            };
+        }
+        input.scale(self.gain as i64, 15 + RAMP_SHIFT)
+    }
+
+    /// Sets the level. The change is spread over the smoothing time.
+    pub fn set_gain(&mut self, level: dB) {
+        self.level = level;
+        self.target = (level.to_gain() << RAMP_SHIFT) as i32;
+        let samples = ((self.smoothing.0 as u64 * self.msample_rate.0 as u64) / 1_000_000) as u32;
+        if samples == 0 {
+            self.gain = self.target;
+            self.remaining = 0;
+        } else {
+            self.step = (self.target - self.gain) / samples as i32;
+            self.remaining = samples;
+        }
+    }
+
+    /// Returns the target level.
+    pub fn get_gain(&self) -> dB {
+        self.level
+    }
+
+    /// Sets the time over which level changes are ramped.
+    pub fn set_smoothing_ms(&mut self, smoothing: ms) {
+        self.smoothing = smoothing;
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+    }
+}
+
+impl Default for Gain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: SampleType> Effect<S> for Gain {
+    fn process(&mut self, input: S) -> S {
+        Gain::process(self, input)
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        Gain::set_msample_rate(self, msample_rate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::sample::SampleType;
+
+    #[test]
+    fn test_gain() {
+        let mut gain = Gain::new();
+        assert_eq!(gain.process(1_000_i16), 1_000);
+
+        gain.set_smoothing_ms(ms(0));
+        gain.set_gain(dB(-6));
+        assert_eq!(gain.process(10_000_i16), 5_011);
+        gain.set_gain(dB(12));
+        assert_eq!(gain.process(10_000), i16::MAX);
+        assert_eq!(gain.process(-10_000), i16::MIN);
+
+        // Wider sample types keep the headroom of their own range
+        assert_eq!(gain.process(i32::from_i16(10_000)), i32::MAX);
+        gain.set_gain(dB(-6));
+        assert!((gain.process(0.5_f32) - 0.25).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_gain_smoothing() {
+        let mut gain = Gain::new();
+        gain.set_msample_rate(mHz(1_000_000));
+        gain.set_smoothing_ms(ms(10));
+        gain.set_gain(dB(-200));
+        let out: Vec<i16> = (0..12).map(|_| gain.process(10_000)).collect();
+        // Monotonically decreasing ramp that reaches silence after 10 samples
+        assert!(out.windows(2).all(|w| w[1] <= w[0]));
+        assert!(out[0] > 8_000);
+        assert_eq!(out[9], 0);
+        assert_eq!(out[11], 0);
+    }
+}
@@ -0,0 +1,250 @@
+// Looper capturing the incoming signal and playing it back as a loop.
+
+use crate::fx::Effect;
+use crate::util::interp;
+use crate::util::units::Sample;
+
+/// Playback speed normalization, i.e. `SPEED_NORM` is the original speed
+pub const SPEED_NORM: i32 = 1 << 16;
+
+/// Operating state of a [Looper]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LooperState {
+    /// Passes the input and keeps the last `N` samples for [Looper::freeze].
+    Bypass,
+    /// Records a new loop.
+    Recording,
+    /// Plays the loop on top of the input.
+    Playing,
+    /// Plays the loop and adds the input to it.
+    Overdubbing,
+}
+
+/// Looper with a buffer of `N` samples
+///
+/// The loop is played on top of the input. The playback speed is
+/// normalized to [SPEED_NORM]; negative speeds play the loop backwards.
+pub struct Looper<const N: usize> {
+    buffer: [i16; N],
+    state: LooperState,
+
+    // Next position written while bypassed or recording
+    write_pos: usize,
+
+    // Loop region of the circular buffer
+    start: usize,
+    len: usize,
+    // Playback position relative to start with 16 fractional bits
+    play_pos: u64,
+
+    speed: i32,
+    feedback: i16,
+}
+
+impl<const N: usize> Looper<N> {
+    pub fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            state: LooperState::Bypass,
+
+            write_pos: 0,
+
+            start: 0,
+            len: 0,
+            play_pos: 0,
+
+            speed: SPEED_NORM,
+            feedback: i16::MAX,
+        }
+    }
+
+    fn idx(&self, offset: usize) -> usize {
+        (self.start + offset) % N
+    }
+
+    fn write(&mut self, input: i16) {
+        self.buffer[self.write_pos] = input;
+        self.write_pos = (self.write_pos + 1) % N;
+    }
+
+    /// Processes the next sample according to the current state.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        match self.state {
+            LooperState::Bypass => {
+                self.write(input);
+                input
+            }
+            LooperState::Recording => {
+                self.write(input);
+                self.len += 1;
+                if self.len == N {
+                    self.play();
+                }
+                input
+            }
+            LooperState::Playing | LooperState::Overdubbing => {
+                let pos = (self.play_pos >> 16) as usize;
+                let frac = (self.play_pos & 0xFFFF) as u32;
+                let s0 = self.buffer[self.idx(pos)];
+                let s1 = self.buffer[self.idx((pos + 1) % self.len)];
+                let out = interp::linear(s0, s1, frac);
+
+                if self.state == LooperState::Overdubbing {
+                    let idx = self.idx(pos);
+                    self.buffer[idx] = Sample(self.buffer[idx])
+                        .multiply_normed(Sample(self.feedback))
+                        .saturating_add(Sample(input))
+                        .0;
+                }
+
+                let len = (self.len as i64) << 16;
+                self.play_pos = (self.play_pos as i64 + self.speed as i64).rem_euclid(len) as u64;
+                Sample(input).saturating_add(Sample(out)).0
+            }
+        }
+    }
+
+    /// Starts recording a new loop. Recording stops and playback starts
+    /// automatically once the buffer is full.
+    pub fn record(&mut self) {
+        self.state = LooperState::Recording;
+        self.start = self.write_pos;
+        self.len = 0;
+    }
+
+    /// Starts (or restarts) loop playback. Ends a running recording.
+    pub fn play(&mut self) {
+        if self.len > 0 {
+            self.state = LooperState::Playing;
+            self.play_pos = 0;
+        } else {
+            self.state = LooperState::Bypass;
+        }
+    }
+
+    /// Adds the input to the loop while it keeps playing.
+    pub fn overdub(&mut self) {
+        match self.state {
+            LooperState::Playing => self.state = LooperState::Overdubbing,
+            LooperState::Recording => {
+                self.play();
+                self.overdub();
+            }
+            _ => {}
+        }
+    }
+
+    /// Stops playback and goes back to bypass. The loop is kept.
+    pub fn stop(&mut self) {
+        self.state = LooperState::Bypass;
+        self.write_pos = self.idx(self.len);
+    }
+
+    /// Loops the last `len` samples (clamped to `N`) that passed in bypass.
+    pub fn freeze(&mut self, len: usize) {
+        self.len = len.min(N);
+        self.start = (self.write_pos + N - self.len) % N;
+        self.play();
+    }
+
+    /// Sets the playback speed normalized to [SPEED_NORM].
+    pub fn set_speed(&mut self, speed: i32) {
+        self.speed = speed;
+    }
+
+    /// Sets how much of the loop is kept on each overdub pass, normalized
+    /// to [crate::util::units::SAMPLE_NORM].
+    pub fn set_feedback(&mut self, feedback: i16) {
+        self.feedback = feedback.max(0);
+    }
+
+    /// Returns the current state.
+    pub fn get_state(&self) -> LooperState {
+        self.state
+    }
+
+    /// Returns the loop length in samples.
+    pub fn get_len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<const N: usize> Default for Looper<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Effect for Looper<N> {
+    fn process(&mut self, input: i16) -> i16 {
+        Looper::process(self, input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_looper_record_and_play() {
+        let mut looper = Looper::<64>::new();
+        looper.record();
+        for x in 1..=8 {
+            assert_eq!(looper.process(x * 100), x * 100);
+        }
+        looper.play();
+        let out: Vec<i16> = (0..16).map(|_| looper.process(0)).collect();
+        assert_eq!(&out[..8], &[100, 200, 300, 400, 500, 600, 700, 800]);
+        assert_eq!(&out[..8], &out[8..]);
+        assert_eq!(looper.get_len(), 8);
+
+        looper.set_speed(2 * SPEED_NORM);
+        looper.play();
+        let out: Vec<i16> = (0..4).map(|_| looper.process(0)).collect();
+        assert_eq!(out, [100, 300, 500, 700]);
+
+        looper.set_speed(-SPEED_NORM);
+        looper.play();
+        let out: Vec<i16> = (0..3).map(|_| looper.process(0)).collect();
+        assert_eq!(out, [100, 800, 700]);
+    }
+
+    #[test]
+    fn test_looper_overdub() {
+        let mut looper = Looper::<64>::new();
+        looper.record();
+        for _ in 0..4 {
+            looper.process(100);
+        }
+        looper.overdub();
+        for _ in 0..4 {
+            looper.process(10);
+        }
+        looper.play();
+        let out: Vec<i16> = (0..4).map(|_| looper.process(0)).collect();
+        assert!(out.iter().all(|y| (y - 110).abs() <= 1));
+    }
+
+    #[test]
+    fn test_looper_freeze() {
+        let mut looper = Looper::<16>::new();
+        for x in 0..40 {
+            looper.process(x);
+        }
+        looper.freeze(4);
+        assert_eq!(looper.get_state(), LooperState::Playing);
+        let out: Vec<i16> = (0..5).map(|_| looper.process(0)).collect();
+        assert_eq!(out, [36, 37, 38, 39, 36]);
+    }
+
+    #[test]
+    fn test_looper_full_buffer() {
+        let mut looper = Looper::<4>::new();
+        looper.record();
+        for x in 0..4 {
+            looper.process(x);
+        }
+        assert_eq!(looper.get_state(), LooperState::Playing);
+    }
+}
@@ -0,0 +1,289 @@
+// Level meter with peak hold, RMS and a clip counter, for level displays and
+// headroom checks.
+
+use crate::fx::Effect;
+use crate::util::units::{dB, mHz, ms, Sample};
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Levels of a [Meter] shared with a [MeterHandle]
+#[cfg(feature = "alloc")]
+#[derive(Default)]
+struct Shared {
+    peak: AtomicU32,
+    rms: AtomicU32,
+    clips: AtomicU32,
+}
+
+/// Level meter
+///
+/// Passes the signal through unchanged, so it can be inserted anywhere in a
+/// chain as an [Effect] or fed with rendered blocks. The peak is the
+/// largest magnitude, held for the hold time before it falls to the
+/// current peak. The RMS level is computed over consecutive windows of the
+/// window time and updates at the end of each window. Every sample at
+/// full scale, i.e. [Sample::is_clipping], counts as a clip.
+///
+/// ```
+/// use isopod::fx::meter::Meter;
+/// use isopod::util::units::{dB, Sample};
+///
+/// let mut meter = Meter::new();
+/// // A full scale square wave over more than the RMS window
+/// for n in 0..20_000 {
+///     meter.process(if n % 100 < 50 { i16::MAX } else { -i16::MAX });
+/// }
+/// assert_eq!(meter.get_peak(), Sample(i16::MAX));
+/// assert_eq!(meter.get_rms_db(), dB(0));
+/// assert_eq!(meter.get_clips(), 10_000);
+/// ```
+pub struct Meter {
+    peak: i16,
+    // Samples until the held peak falls
+    hold_remaining: u32,
+    // Largest magnitude since the peak was held
+    recent: i16,
+    rms: i16,
+    // Sum of squares and samples of the current window
+    sum: u64,
+    count: u32,
+    clips: u32,
+
+    hold: ms,
+    window: ms,
+    msample_rate: mHz,
+    hold_samples: u32,
+    window_samples: u32,
+
+    #[cfg(feature = "alloc")]
+    shared: Option<Arc<Shared>>,
+}
+
+impl Meter {
+    pub fn new() -> Self {
+        let mut meter = Self {
+            peak: 0,
+            hold_remaining: 0,
+            recent: 0,
+            rms: 0,
+            sum: 0,
+            count: 0,
+            clips: 0,
+
+            hold: ms(1_000),
+            window: ms(300),
+            msample_rate: mHz(44_100_000),
+            hold_samples: 0,
+            window_samples: 0,
+
+            #[cfg(feature = "alloc")]
+            shared: None,
+        };
+        meter.update_times();
+        meter
+    }
+
+    fn update_times(&mut self) {
+        let samples = |t: ms| ((t.0 as u64 * self.msample_rate.0 as u64) / 1_000_000) as u32;
+        self.hold_samples = samples(self.hold);
+        self.window_samples = samples(self.window).max(1);
+    }
+
+    /// Measures and returns the next sample.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        let magnitude = input.saturating_abs();
+        if Sample(input).is_clipping() {
+            self.clips = self.clips.saturating_add(1);
+        }
+        self.recent = self.recent.max(magnitude);
+        if magnitude >= self.peak {
+            self.peak = magnitude;
+            self.hold_remaining = self.hold_samples;
+            self.recent = 0;
+        } else if self.hold_remaining > 0 {
+            self.hold_remaining -= 1;
+        } else {
+            // The hold expired, the peak falls to the peak since it was held
+            self.peak = self.recent;
+            self.hold_remaining = self.hold_samples;
+            self.recent = 0;
+        }
+
+        self.sum += (input as i64 * input as i64) as u64;
+        self.count += 1;
+        if self.count >= self.window_samples {
+            self.rms = (self.sum / self.count as u64).isqrt().min(i16::MAX as u64) as i16;
+            (self.sum, self.count) = (0, 0);
+            self.publish();
+        }
+        input
+    }
+
+    /// Measures a block of samples, e.g. the output of a synth.
+    pub fn process_block(&mut self, block: &[i16]) {
+        for x in block {
+            self.process(*x);
+        }
+    }
+
+    /// Returns the held peak magnitude.
+    pub fn get_peak(&self) -> Sample {
+        Sample(self.peak)
+    }
+
+    /// Returns the RMS level of the last complete window.
+    pub fn get_rms(&self) -> Sample {
+        Sample(self.rms)
+    }
+
+    /// Returns the held peak in dB relative to full scale.
+    pub fn get_peak_db(&self) -> dB {
+        dB::from_gain(self.peak as u32)
+    }
+
+    /// Returns the RMS level in dB relative to a full scale square wave.
+    pub fn get_rms_db(&self) -> dB {
+        dB::from_gain(self.rms as u32)
+    }
+
+    /// Returns the number of clipping samples since the last reset.
+    pub fn get_clips(&self) -> u32 {
+        self.clips
+    }
+
+    /// Clears the levels and the clip counter.
+    pub fn reset(&mut self) {
+        (self.peak, self.recent, self.rms, self.clips) = (0, 0, 0, 0);
+        (self.sum, self.count, self.hold_remaining) = (0, 0, 0);
+        self.publish();
+    }
+
+    /// Sets how long peaks are held.
+    pub fn set_hold_ms(&mut self, hold: ms) {
+        self.hold = hold;
+        self.update_times();
+    }
+
+    /// Sets the length of the RMS window, which is also the update interval
+    /// of the handles.
+    pub fn set_window_ms(&mut self, window: ms) {
+        self.window = window;
+        self.update_times();
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        self.update_times();
+    }
+
+    /// Returns a handle for reading the levels from another thread, e.g. a
+    /// UI. The levels of the handles update at the end of each RMS window.
+    #[cfg(feature = "alloc")]
+    pub fn get_handle(&mut self) -> MeterHandle {
+        let shared = self.shared.get_or_insert_with(Default::default).clone();
+        self.publish();
+        MeterHandle { shared }
+    }
+
+    fn publish(&self) {
+        #[cfg(feature = "alloc")]
+        if let Some(shared) = &self.shared {
+            shared.peak.store(self.peak as u32, Ordering::Relaxed);
+            shared.rms.store(self.rms as u32, Ordering::Relaxed);
+            shared.clips.store(self.clips, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for Meter {
+    fn process(&mut self, input: i16) -> i16 {
+        Meter::process(self, input)
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        Meter::set_msample_rate(self, msample_rate);
+    }
+}
+
+/// Reading side of a [Meter], see [Meter::get_handle]
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub struct MeterHandle {
+    shared: Arc<Shared>,
+}
+
+#[cfg(feature = "alloc")]
+impl MeterHandle {
+    pub fn get_peak(&self) -> Sample {
+        Sample(self.shared.peak.load(Ordering::Relaxed) as i16)
+    }
+
+    pub fn get_rms(&self) -> Sample {
+        Sample(self.shared.rms.load(Ordering::Relaxed) as i16)
+    }
+
+    pub fn get_clips(&self) -> u32 {
+        self.shared.clips.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_meter_peak_hold() {
+        let mut meter = Meter::new();
+        meter.set_msample_rate(mHz(1_000_000));
+        meter.set_hold_ms(ms(100));
+        meter.process(-20_000);
+        for _ in 0..100 {
+            meter.process(5_000);
+        }
+        assert_eq!(meter.get_peak(), Sample(20_000));
+        // After the hold the peak falls to the largest magnitude since
+        meter.process(0);
+        assert_eq!(meter.get_peak(), Sample(5_000));
+        assert_eq!(meter.get_clips(), 0);
+        meter.process(i16::MIN);
+        assert_eq!(meter.get_peak(), Sample(i16::MAX));
+        assert_eq!(meter.get_clips(), 1);
+    }
+
+    #[test]
+    fn test_meter_rms() {
+        let mut meter = Meter::new();
+        meter.set_msample_rate(mHz(48_000_000));
+        meter.set_window_ms(ms(10));
+        // A sine has an RMS level of its amplitude over sqrt(2)
+        let sine: Vec<i16> = (0..480)
+            .map(|n| (16_000.0 * (n as f64 * core::f64::consts::TAU / 48.0).sin()) as i16)
+            .collect();
+        meter.process_block(&sine[..479]);
+        assert_eq!(meter.get_rms(), Sample(0));
+        meter.process(sine[479]);
+        let rms = meter.get_rms().0;
+        assert!((11_300..11_320).contains(&rms), "{}", rms);
+        assert_eq!(meter.get_rms_db(), dB(-9));
+
+        #[cfg(feature = "alloc")]
+        {
+            let handle = meter.get_handle();
+            assert_eq!(handle.get_rms().0, rms);
+            meter.process_block(&[i16::MAX; 480]);
+            assert_eq!(handle.get_clips(), 480);
+            meter.reset();
+            assert_eq!(handle.get_peak(), Sample(0));
+        }
+    }
+}
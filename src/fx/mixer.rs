@@ -0,0 +1,88 @@
+// Mixer summing a fixed number of channels.
+
+use crate::util::diag;
+use crate::util::units::dB;
+
+/// Mixer for `N` channels
+///
+/// Every channel has a gain normalized to
+/// [crate::util::units::SAMPLE_NORM] (default i16::MAX, i.e. unity).
+/// The weighted sum saturates at the i16 limits.
+pub struct Mix<const N: usize> {
+    gains: [i16; N],
+}
+
+impl<const N: usize> Mix<N> {
+    pub fn new() -> Self {
+        Self {
+            gains: [i16::MAX; N],
+        }
+    }
+
+    /// Returns the weighted sum of all channels.
+    #[inline]
+    pub fn process(&self, inputs: &[i16; N]) -> i16 {
+        let mut acc = 0_i64;
+        for (x, gain) in inputs.iter().zip(self.gains.iter()) {
+            acc += *x as i64 * *gain as i64;
+        }
+        diag::clip(acc >> 15)
+    }
+
+    /// Sets the gain of `channel`. Out of range channels are ignored.
+    pub fn set_gain(&mut self, channel: usize, gain: i16) {
+        if let Some(g) = self.gains.get_mut(channel) {
+            *g = gain;
+        }
+    }
+
+    /// Sets the gain of `channel` as a level of at most 0 dB.
+    pub fn set_gain_db(&mut self, channel: usize, level: dB) {
+        self.set_gain(channel, level.to_q15());
+    }
+
+    /// Returns the gain of `channel` or `None` if it is out of range.
+    pub fn get_gain(&self, channel: usize) -> Option<i16> {
+        self.gains.get(channel).copied()
+    }
+}
+
+impl<const N: usize> Default for Mix<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mix() {
+        let mut mix = Mix::<3>::new();
+        assert!((mix.process(&[1_000, 2_000, -500]) - 2_500).abs() <= 1);
+
+        mix.set_gain(1, i16::MAX / 2);
+        mix.set_gain(2, 0);
+        mix.set_gain(3, 0);
+        assert!((mix.process(&[1_000, 2_000, -500]) - 2_000).abs() <= 1);
+        assert_eq!(mix.get_gain(2), Some(0));
+        assert_eq!(mix.get_gain(3), None);
+    }
+
+    #[test]
+    fn test_mix_db() {
+        let mut mix = Mix::<2>::new();
+        mix.set_gain_db(0, dB(-6));
+        mix.set_gain_db(1, dB(-200));
+        assert!((mix.process(&[10_000, 10_000]) - 5_012).abs() <= 1);
+        assert_eq!(mix.get_gain(1), Some(0));
+    }
+
+    #[test]
+    fn test_mix_saturation() {
+        let mix = Mix::<4>::new();
+        assert_eq!(mix.process(&[i16::MAX; 4]), i16::MAX);
+        assert_eq!(mix.process(&[i16::MIN; 4]), i16::MIN);
+    }
+}
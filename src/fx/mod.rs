@@ -1 +1,38 @@
+pub mod biquad;
+pub mod bitcrusher;
+pub mod crossfader;
+pub mod delay;
+pub mod dynamics;
+pub mod eq;
 pub mod filter;
+pub mod gain;
+pub mod looper;
+pub mod meter;
+pub mod mixer;
+pub mod oversample;
+pub mod panner;
+pub mod pitchshift;
+pub mod resample;
+pub mod ringmod;
+pub mod tremolo;
+pub mod vibrato;
+pub mod vocoder;
+pub mod widener;
+
+use crate::util::sample::SampleType;
+use crate::util::units::mHz;
+
+/// Effect with one input and one output sample
+///
+/// Allows wrapping effects generically, e.g. in
+/// [oversample::Oversampled]. Effects that are generic over the
+/// [SampleType] implement it for all sample types, the others only for the
+/// default i16.
+pub trait Effect<S: SampleType = i16> {
+    /// Processes the next sample.
+    fn process(&mut self, input: S) -> S;
+
+    /// Sets the sample rate in mHz. Effects without a notion of time
+    /// ignore it.
+    fn set_msample_rate(&mut self, _msample_rate: mHz) {}
+}
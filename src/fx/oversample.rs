@@ -0,0 +1,304 @@
+// Oversampling wrapper with fixed-point halfband filters.
+
+use crate::fx::Effect;
+use crate::util::diag;
+use crate::util::units::mHz;
+
+/// Number of nonzero taps of the halfband filter besides the center tap
+const TAPS: usize = 24;
+
+/// Even taps of a 47 tap Kaiser windowed halfband lowpass, normalized to
+/// 1 << 15. The odd taps are 0 except for the center tap, which is 0.5.
+/// The passband reaches 0.4 * fs / 2 and the stopband from 0.6 * fs / 2 is
+/// attenuated by about 70 dB.
+static HALFBAND: [i32; TAPS] = [
+    -3, 13, -35, 77, -148, 261, -433, 693, -1096, 1787, -3290, 10366, 10366, -3290, 1787, -1096,
+    693, -433, 261, -148, 77, -35, 13, -3,
+];
+
+fn saturate(x: i32) -> i16 {
+    diag::clip(x as i64)
+}
+
+/// Sum of the even taps over the last `TAPS` samples
+#[inline]
+fn fir(window: &[i16]) -> i32 {
+    window
+        .iter()
+        .zip(HALFBAND.iter())
+        .map(|(x, h)| *x as i32 * h)
+        .sum()
+}
+
+/// Halfband interpolator doubling the sample rate
+///
+/// Only the nonzero taps are evaluated, so every output sample costs about
+/// half of the filter length.
+pub struct HalfbandUp {
+    // Every sample is stored twice, so the last TAPS samples are contiguous
+    history: [i16; 2 * TAPS],
+    pos: usize,
+}
+
+impl HalfbandUp {
+    pub fn new() -> Self {
+        Self {
+            history: [0; 2 * TAPS],
+            pos: 0,
+        }
+    }
+
+    /// Returns the two output samples for the next input sample.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> (i16, i16) {
+        self.pos = (self.pos + 1) % TAPS;
+        self.history[self.pos] = input;
+        self.history[self.pos + TAPS] = input;
+        let window = &self.history[self.pos + 1..self.pos + 1 + TAPS];
+        // Zero stuffing halves the level, hence the gain of 2
+        let even = saturate(fir(window) >> 14);
+        let odd = window[TAPS / 2];
+        (even, odd)
+    }
+
+    /// Sets all samples to 0.
+    pub fn reset(&mut self) {
+        self.history = [0; 2 * TAPS];
+    }
+}
+
+impl Default for HalfbandUp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Halfband decimator halving the sample rate
+pub struct HalfbandDown {
+    even: [i16; 2 * TAPS],
+    pos: usize,
+    // The last TAPS / 2 odd samples, which feed the center tap
+    odd: [i16; TAPS / 2],
+    odd_pos: usize,
+}
+
+impl HalfbandDown {
+    pub fn new() -> Self {
+        Self {
+            even: [0; 2 * TAPS],
+            pos: 0,
+            odd: [0; TAPS / 2],
+            odd_pos: 0,
+        }
+    }
+
+    /// Returns the output sample for the next two input samples.
+    #[inline]
+    pub fn process(&mut self, a: i16, b: i16) -> i16 {
+        self.pos = (self.pos + 1) % TAPS;
+        self.even[self.pos] = a;
+        self.even[self.pos + TAPS] = a;
+        let window = &self.even[self.pos + 1..self.pos + 1 + TAPS];
+        let acc = fir(window) + ((self.odd[self.odd_pos] as i32) << 14);
+        self.odd[self.odd_pos] = b;
+        self.odd_pos = (self.odd_pos + 1) % (TAPS / 2);
+        saturate(acc >> 15)
+    }
+
+    /// Sets all samples to 0.
+    pub fn reset(&mut self) {
+        self.even = [0; 2 * TAPS];
+        self.odd = [0; TAPS / 2];
+    }
+}
+
+impl Default for HalfbandDown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Oversampling factor of [Oversampled]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OversamplingFactor {
+    X2,
+    X4,
+}
+
+impl OversamplingFactor {
+    /// Returns the factor as a number.
+    pub fn ratio(self) -> u32 {
+        match self {
+            OversamplingFactor::X2 => 2,
+            OversamplingFactor::X4 => 4,
+        }
+    }
+}
+
+/// Runs an effect at 2 or 4 times the sample rate
+///
+/// The input is interpolated with halfband filters, processed by the inner
+/// effect and decimated again. Harmonics that nonlinear effects generate
+/// above the original Nyquist frequency are filtered out instead of being
+/// aliased. The inner effect has to be configured for the oversampled rate,
+/// e.g. `msample_rate * factor.ratio()`. The filters add a latency of about
+/// 23 (2x) or 35 (4x) samples.
+pub struct Oversampled<E: Effect> {
+    effect: E,
+    factor: OversamplingFactor,
+
+    // The second stages run at twice the sample rate and are used for 4x
+    up: [HalfbandUp; 2],
+    down: [HalfbandDown; 2],
+}
+
+impl<E: Effect> Oversampled<E> {
+    pub fn new(effect: E, factor: OversamplingFactor) -> Self {
+        Self {
+            effect,
+            factor,
+
+            up: [HalfbandUp::new(), HalfbandUp::new()],
+            down: [HalfbandDown::new(), HalfbandDown::new()],
+        }
+    }
+
+    /// Processes the next sample at the original sample rate.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        let (a, b) = self.up[0].process(input);
+        let (a, b) = match self.factor {
+            OversamplingFactor::X2 => (self.effect.process(a), self.effect.process(b)),
+            OversamplingFactor::X4 => (self.process_x2(a), self.process_x2(b)),
+        };
+        self.down[0].process(a, b)
+    }
+
+    /// Runs the inner effect at twice the rate of the first stage.
+    fn process_x2(&mut self, input: i16) -> i16 {
+        let (a, b) = self.up[1].process(input);
+        let a = self.effect.process(a);
+        let b = self.effect.process(b);
+        self.down[1].process(a, b)
+    }
+
+    /// Sets the oversampling factor and clears the filters.
+    pub fn set_factor(&mut self, factor: OversamplingFactor) {
+        self.factor = factor;
+        self.reset();
+    }
+
+    /// Returns the oversampling factor.
+    pub fn get_factor(&self) -> OversamplingFactor {
+        self.factor
+    }
+
+    /// Returns the inner effect.
+    pub fn get_effect(&self) -> &E {
+        &self.effect
+    }
+
+    /// Returns the inner effect for changing its parameters.
+    pub fn get_effect_mut(&mut self) -> &mut E {
+        &mut self.effect
+    }
+
+    /// Clears the filters.
+    pub fn reset(&mut self) {
+        for up in self.up.iter_mut() {
+            up.reset();
+        }
+        for down in self.down.iter_mut() {
+            down.reset();
+        }
+    }
+}
+
+impl<E: Effect> Effect for Oversampled<E> {
+    fn process(&mut self, input: i16) -> i16 {
+        Oversampled::process(self, input)
+    }
+
+    /// Runs the inner effect at the oversampled rate. [Oversampled::set_factor]
+    /// leaves the rate of the inner effect alone, so set the factor first.
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        let msample_rate = msample_rate.0.saturating_mul(self.factor.ratio());
+        self.effect.set_msample_rate(mHz(msample_rate));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::osc::wavetable::SineOscillator;
+
+    struct Bypass;
+
+    impl Effect for Bypass {
+        fn process(&mut self, input: i16) -> i16 {
+            input
+        }
+    }
+
+    /// Hard clipper, which generates plenty of high harmonics
+    struct Clip;
+
+    impl Effect for Clip {
+        fn process(&mut self, input: i16) -> i16 {
+            input.clamp(-4_000, 4_000)
+        }
+    }
+
+    #[test]
+    fn test_halfband_dc() {
+        let mut up = HalfbandUp::new();
+        let mut down = HalfbandDown::new();
+        let mut out = (0, 0, 0);
+        for _ in 0..100 {
+            let (a, b) = up.process(10_000);
+            out = (a, b, down.process(a, b));
+        }
+        assert_eq!(out.1, 10_000);
+        assert!(out.0.abs_diff(10_000) <= 1, "{:?}", out);
+        assert!(out.2.abs_diff(10_000) <= 1, "{:?}", out);
+    }
+
+    /// Returns the amplitude of the output at `probe` Hz for a sine input.
+    fn amplitude(effect: &mut impl Effect, freq: f64, probe: f64) -> f64 {
+        let w = 2.0 * std::f64::consts::PI / 44_100.0;
+        let (mut re, mut im) = (0.0, 0.0);
+        for n in 0..44_100 {
+            let x = (i16::MAX as f64 * (w * freq * n as f64).sin()) as i16;
+            let y = effect.process(x) as f64;
+            re += y * (w * probe * n as f64).cos();
+            im += y * (w * probe * n as f64).sin();
+        }
+        2.0 * (re * re + im * im).sqrt() / 44_100.0
+    }
+
+    #[test]
+    fn test_oversampled_bypass() {
+        for factor in [OversamplingFactor::X2, OversamplingFactor::X4] {
+            let mut oversampled = Oversampled::new(Bypass, factor);
+            let mut sine = SineOscillator::new();
+            sine.start();
+            let input: Vec<i16> = (0..4410).map(|_| sine.next().unwrap() / 2).collect();
+            let output: Vec<i16> = input.iter().map(|x| oversampled.process(*x)).collect();
+            let peak = output[1000..].iter().map(|y| y.abs()).max().unwrap();
+            assert!(peak.abs_diff(i16::MAX / 2) < 200, "{:?} {}", factor, peak);
+        }
+    }
+
+    #[test]
+    fn test_oversampled_aliasing() {
+        // The 5th harmonic of 8 kHz (40 kHz) aliases to 4.1 kHz
+        let plain = amplitude(&mut Clip, 8_000.0, 4_100.0);
+        for factor in [OversamplingFactor::X2, OversamplingFactor::X4] {
+            let mut oversampled = Oversampled::new(Clip, factor);
+            let fundamental = amplitude(&mut oversampled, 8_000.0, 8_000.0);
+            let alias = amplitude(&mut oversampled, 8_000.0, 4_100.0);
+            assert!(fundamental > 4_000.0, "{:?} {}", factor, fundamental);
+            assert!(alias * 100.0 < plain, "{:?} {} {}", factor, alias, plain);
+        }
+    }
+}
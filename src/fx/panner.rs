@@ -0,0 +1,123 @@
+// Stereo panner placing a mono signal in the stereo field.
+
+use crate::osc::luts::SINE_I16;
+use crate::util::units::{Frame, Sample, SAMPLE_NORM};
+
+/// Number of [SINE_I16] entries covering a quarter period
+const QUARTER: i32 = SINE_I16.len() as i32 / 4;
+
+/// Pan law of a [Panner]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PanLaw {
+    /// Gains add up to 1. The center is attenuated by 6 dB.
+    Linear,
+    /// Squared gains add up to 1. The center is attenuated by 3 dB, which
+    /// keeps the perceived loudness constant.
+    ConstantPower,
+}
+
+/// Mono to stereo panner
+///
+/// The pan position goes from -i16::MAX (hard left) over 0 (center) to
+/// i16::MAX (hard right). The channel gains are updated whenever the
+/// position changes, so it can be modulated at control rate.
+pub struct Panner {
+    law: PanLaw,
+    pan: i16,
+    gain_left: i16,
+    gain_right: i16,
+}
+
+impl Panner {
+    pub fn new() -> Self {
+        let mut s = Self {
+            law: PanLaw::ConstantPower,
+            pan: 0,
+            gain_left: 0,
+            gain_right: 0,
+        };
+        s.update_gains();
+        s
+    }
+
+    fn update_gains(&mut self) {
+        let pan = self.pan.max(-i16::MAX) as i32 + i16::MAX as i32;
+        match self.law {
+            PanLaw::Linear => {
+                let right = (pan * SAMPLE_NORM) / (2 * i16::MAX as i32);
+                self.gain_right = right.min(i16::MAX as i32) as i16;
+                self.gain_left = (SAMPLE_NORM - right).min(i16::MAX as i32) as i16;
+            }
+            PanLaw::ConstantPower => {
+                let idx = (pan * QUARTER) / (2 * i16::MAX as i32);
+                self.gain_right = SINE_I16[idx as usize];
+                self.gain_left = SINE_I16[(QUARTER - idx) as usize];
+            }
+        }
+    }
+
+    /// Pans `input` according to the current position.
+    #[inline]
+    pub fn process(&self, input: i16) -> Frame {
+        let input = Sample(input);
+        Frame {
+            left: input.multiply_normed(Sample(self.gain_left)),
+            right: input.multiply_normed(Sample(self.gain_right)),
+        }
+    }
+
+    /// Sets the pan position.
+    pub fn set_pan(&mut self, pan: i16) {
+        self.pan = pan;
+        self.update_gains();
+    }
+
+    /// Sets the pan law.
+    pub fn set_law(&mut self, law: PanLaw) {
+        self.law = law;
+        self.update_gains();
+    }
+}
+
+impl Default for Panner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_panner_constant_power() {
+        let mut panner = Panner::new();
+        let center = panner.process(10_000);
+        assert_eq!(center.left, center.right);
+        // cos(pi/4) = 0.7071
+        assert!((center.left.0 - 7_071).abs() < 10);
+
+        panner.set_pan(-i16::MAX);
+        let left = panner.process(10_000);
+        assert!((left.left.0 - 10_000).abs() <= 1);
+        assert_eq!(left.right.0, 0);
+
+        panner.set_pan(i16::MAX);
+        let right = panner.process(10_000);
+        assert_eq!(right.left.0, 0);
+        assert!((right.right.0 - 10_000).abs() <= 1);
+    }
+
+    #[test]
+    fn test_panner_linear() {
+        let mut panner = Panner::new();
+        panner.set_law(PanLaw::Linear);
+        let center = panner.process(10_000);
+        assert_eq!(center, Frame::new(5_000, 5_000));
+
+        panner.set_pan(i16::MAX);
+        let right = panner.process(10_000);
+        assert_eq!(right.left.0, 0);
+        assert!((right.right.0 - 10_000).abs() <= 1);
+    }
+}
@@ -0,0 +1,177 @@
+// Delay line based pitch shifter with two crossfaded taps.
+
+use crate::fx::delay::{DelayLine, DELAY_FRAC_BITS};
+use crate::fx::Effect;
+use crate::util::units::{mHz, ms, ratio, Sample};
+
+/// Length of the pitch shifter delay line
+const PITCH_LEN: usize = 2048;
+
+/// Largest supported shift in either direction in cents
+pub const SHIFT_MAX: i32 = 2_400;
+
+/// Pitch shifter
+///
+/// Two taps sweep through a delay window with a speed that corresponds to
+/// the pitch ratio. When a tap wraps around, it is faded out and the other
+/// tap, which is half a window apart, takes over. The result is the input
+/// transposed by the shift, with some amplitude modulation at the window
+/// rate for uncorrelated material.
+pub struct PitchShifter {
+    delay: DelayLine<PITCH_LEN>,
+
+    // Window phase, the full u32 range corresponds to one window
+    phi: u32,
+    delta_phi: i32,
+
+    shift: i32,
+    window: ms,
+    window_samples: u32,
+    mix: i16,
+
+    msample_rate: mHz,
+}
+
+impl PitchShifter {
+    pub fn new() -> Self {
+        let mut s = Self {
+            delay: DelayLine::new(),
+
+            phi: 0,
+            delta_phi: 0,
+
+            shift: 0,
+            window: ms(40),
+            window_samples: 0,
+            mix: i16::MAX,
+
+            msample_rate: mHz(44_100_000),
+        };
+        s.update_window();
+        s
+    }
+
+    fn update_window(&mut self) {
+        let samples = (self.window.0 as u64 * self.msample_rate.0 as u64) / 1_000_000;
+        self.window_samples = (samples as u32).clamp(8, PITCH_LEN as u32 - 2);
+        self.update_delta_phi();
+    }
+
+    fn update_delta_phi(&mut self) {
+        // The delay has to change by (1 - ratio) samples per sample
+        let speed = (1_i64 << 16) - ratio(self.shift) as i64;
+        self.delta_phi = ((speed << 16) / self.window_samples as i64) as i32;
+    }
+
+    fn tap(&self, phi: u32) -> i32 {
+        let delay = (phi as u64 * self.window_samples as u64) >> (32 - DELAY_FRAC_BITS);
+        let p = (phi >> 16) as i32;
+        let gain = if p < 32768 { 2 * p } else { 2 * (65535 - p) };
+        (self.delay.read_frac(delay as u32) as i32 * gain) >> 16
+    }
+
+    /// Feeds `input` into the delay line and returns the shifted signal.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        self.delay.write(input);
+        let wet = self.tap(self.phi) + self.tap(self.phi.wrapping_add(1 << 31));
+        self.phi = self.phi.wrapping_add(self.delta_phi as u32);
+
+        let dry = Sample(input).multiply_normed(Sample(i16::MAX - self.mix));
+        let wet = Sample(wet as i16).multiply_normed(Sample(self.mix));
+        dry.saturating_add(wet).0
+    }
+
+    /// Sets the shift in semitones and cents, clamped to [SHIFT_MAX].
+    pub fn set_shift(&mut self, semitones: i32, cents: i32) {
+        self.shift = semitones
+            .saturating_mul(100)
+            .saturating_add(cents)
+            .clamp(-SHIFT_MAX, SHIFT_MAX);
+        self.update_delta_phi();
+    }
+
+    /// Sets the window length, which trades off smearing (long windows)
+    /// against roughness (short windows).
+    pub fn set_window_ms(&mut self, window: ms) {
+        self.window = window;
+        self.update_window();
+    }
+
+    /// Sets the dry/wet mix normalized to [crate::util::units::SAMPLE_NORM].
+    pub fn set_mix(&mut self, mix: i16) {
+        self.mix = mix.max(0);
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        self.update_window();
+    }
+}
+
+impl Default for PitchShifter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for PitchShifter {
+    fn process(&mut self, input: i16) -> i16 {
+        PitchShifter::process(self, input)
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        PitchShifter::set_msample_rate(self, msample_rate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::osc::wavetable::SineOscillator;
+    use crate::util::units::Hz;
+
+    #[test]
+    fn test_ratio() {
+        assert_eq!(ratio(0), 1 << 16);
+        assert_eq!(ratio(1_200), 1 << 17);
+        assert_eq!(ratio(-2_400), 1 << 14);
+        assert_eq!(ratio(700), 98193);
+        assert_eq!(ratio(-500), 49096);
+    }
+
+    #[test]
+    fn test_shift_clamp() {
+        let mut shifter = PitchShifter::new();
+        shifter.set_shift(i32::MAX, i32::MAX);
+        assert_eq!(shifter.shift, SHIFT_MAX);
+        shifter.set_shift(i32::MIN, -1);
+        assert_eq!(shifter.shift, -SHIFT_MAX);
+        shifter.set_shift(-3, 50);
+        assert_eq!(shifter.shift, -250);
+    }
+
+    /// Counts the zero crossings in 0.9 s after a 0.1 s run-in.
+    fn zero_crossings(shifter: &mut PitchShifter, freq: Hz) -> usize {
+        let mut sine = SineOscillator::new();
+        sine.set_freq(freq);
+        sine.start();
+        let out: Vec<i16> = (0..44100)
+            .map(|_| shifter.process(sine.next().unwrap() / 2))
+            .skip(4410)
+            .collect();
+        out.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count()
+    }
+
+    #[test]
+    fn test_pitch_shifter() {
+        let mut shifter = PitchShifter::new();
+        // Two crossings per period
+        assert!(zero_crossings(&mut shifter, Hz(441)).abs_diff(794) < 5);
+        shifter.set_shift(12, 0);
+        assert!(zero_crossings(&mut shifter, Hz(441)).abs_diff(1_588) < 40);
+        shifter.set_shift(-12, 0);
+        assert!(zero_crossings(&mut shifter, Hz(441)).abs_diff(397) < 20);
+    }
+}
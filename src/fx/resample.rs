@@ -0,0 +1,155 @@
+// Sample rate converter with linear interpolation.
+
+use crate::util::interp;
+use crate::util::units::mHz;
+#[cfg(feature = "rodio")]
+use crate::util::units::Frequency;
+#[cfg(feature = "rodio")]
+use core::time::Duration;
+#[cfg(feature = "rodio")]
+use rodio::source::Source;
+
+/// Fractional bits of the read position between two input samples
+const POS_FRAC_BITS: u32 = 32;
+const POS_ONE: u64 = 1 << POS_FRAC_BITS;
+
+/// Converts the samples of `source` from one sample rate to another
+///
+/// Output samples are linearly interpolated between the two neighbouring
+/// input samples. This is cheap and fine for upsampling. When downsampling,
+/// content above the new Nyquist frequency aliases, so the source should be
+/// band limited (e.g. with [crate::fx::biquad::Biquad]) beforehand.
+pub struct Resampler<I: Iterator<Item = i16>> {
+    source: I,
+
+    // The two input samples around the read position
+    s0: i16,
+    s1: i16,
+    // Read position relative to s0
+    pos: u64,
+    step: u64,
+
+    msample_rate_in: mHz,
+    msample_rate_out: mHz,
+}
+
+impl<I: Iterator<Item = i16>> Resampler<I> {
+    pub fn new(source: I, msample_rate_in: mHz, msample_rate_out: mHz) -> Self {
+        let mut s = Self {
+            source,
+
+            s0: 0,
+            s1: 0,
+            // Pulls the first two samples on the first call
+            pos: 2 * POS_ONE,
+            step: POS_ONE,
+
+            msample_rate_in,
+            msample_rate_out,
+        };
+        s.update_step();
+        s
+    }
+
+    fn update_step(&mut self) {
+        self.step = ((self.msample_rate_in.0 as u64) << POS_FRAC_BITS)
+            .checked_div(self.msample_rate_out.0 as u64)
+            .unwrap_or(POS_ONE);
+    }
+
+    /// Returns the next sample at the output rate or `None` once the source
+    /// is exhausted.
+    #[inline]
+    pub fn _next(&mut self) -> Option<i16> {
+        while self.pos >= POS_ONE {
+            self.s0 = self.s1;
+            self.s1 = self.source.next()?;
+            self.pos -= POS_ONE;
+        }
+        let frac = (self.pos >> (POS_FRAC_BITS - interp::FRAC_BITS)) as u32;
+        let out = interp::linear(self.s0, self.s1, frac);
+        self.pos += self.step;
+        Some(out)
+    }
+
+    /// Sets the sample rate of the source in mHz.
+    pub fn set_msample_rate_in(&mut self, msample_rate: mHz) {
+        self.msample_rate_in = msample_rate;
+        self.update_step();
+    }
+
+    /// Sets the output sample rate in mHz.
+    pub fn set_msample_rate_out(&mut self, msample_rate: mHz) {
+        self.msample_rate_out = msample_rate;
+        self.update_step();
+    }
+
+    /// Returns the source for changing its parameters.
+    pub fn get_source_mut(&mut self) -> &mut I {
+        &mut self.source
+    }
+}
+
+impl<I: Iterator<Item = i16>> Iterator for Resampler<I> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self._next()
+    }
+}
+
+#[cfg(feature = "rodio")]
+impl<I: Iterator<Item = i16>> Source for Resampler<I> {
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.msample_rate_out.to_Hz().0
+    }
+
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::osc::wavetable::SineOscillator;
+    use crate::util::units::Hz;
+
+    #[test]
+    fn test_resampler_up() {
+        let ramp = [0, 100, 200, 300];
+        let resampler = Resampler::new(ramp.into_iter(), mHz(22_050_000), mHz(44_100_000));
+        let out: Vec<i16> = resampler.collect();
+        // The last sample is missing as there is nothing to interpolate towards
+        assert_eq!(out, [0, 50, 100, 150, 200, 250]);
+    }
+
+    #[test]
+    fn test_resampler_down() {
+        let resampler = Resampler::new(0..300, mHz(48_000_000), mHz(16_000_000));
+        let out: Vec<i16> = resampler.collect();
+        assert_eq!(out.len(), 100);
+        assert!(out.iter().enumerate().all(|(n, y)| *y == 3 * n as i16));
+    }
+
+    #[test]
+    fn test_resampler_frequency() {
+        // 441 Hz at 22.05 kHz played back at 44.1 kHz
+        let mut sine = SineOscillator::new();
+        sine.set_sample_rate(Hz(22_050));
+        sine.set_freq(Hz(441));
+        sine.start();
+        let resampler = Resampler::new(sine, mHz(22_050_000), mHz(44_100_000));
+        let out: Vec<i16> = resampler.take(44_100).collect();
+        let crossings = out.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count();
+        assert!(crossings.abs_diff(882) <= 2, "{}", crossings);
+    }
+}
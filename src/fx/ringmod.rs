@@ -0,0 +1,107 @@
+// Ring modulator multiplying the input with a carrier signal.
+
+use crate::fx::Effect;
+use crate::osc::wavetable::SineOscillator;
+use crate::util::sample::SampleType;
+use crate::util::units::{mHz, Hz};
+
+/// Ring modulator
+///
+/// The carrier is either the internal sine oscillator ([RingMod::process])
+/// or an external signal ([RingMod::process_with_carrier]). The mix is
+/// normalized to [crate::util::units::SAMPLE_NORM], where 0 is fully dry.
+pub struct RingMod {
+    carrier: SineOscillator,
+    mix: i16,
+}
+
+impl RingMod {
+    pub fn new() -> Self {
+        let mut s = Self {
+            carrier: SineOscillator::new(),
+            mix: i16::MAX,
+        };
+        s.carrier.start();
+        s
+    }
+
+    /// Modulates `input` with the internal carrier.
+    #[inline]
+    pub fn process<S: SampleType>(&mut self, input: S) -> S {
+        let carrier = self.carrier.next().unwrap_or(0);
+        self.process_with_carrier(input, carrier)
+    }
+
+    /// Modulates `input` with an external `carrier` signal normalized to
+    /// [crate::util::units::SAMPLE_NORM].
+    #[inline]
+    pub fn process_with_carrier<S: SampleType>(&self, input: S, carrier: i16) -> S {
+        let wet = input.scale(carrier as i64, 15);
+        let dry = input.scale((i16::MAX - self.mix) as i64, 15);
+        dry.saturating_add(wet.scale(self.mix as i64, 15))
+    }
+
+    /// Sets the dry/wet mix normalized to [crate::util::units::SAMPLE_NORM].
+    pub fn set_mix(&mut self, mix: i16) {
+        self.mix = mix.max(0);
+    }
+
+    /// Sets the frequency of the internal carrier in mHz.
+    pub fn set_mfreq(&mut self, mfreq: mHz) {
+        self.carrier.set_mfreq(mfreq);
+    }
+
+    /// Sets the frequency of the internal carrier in Hz.
+    pub fn set_freq(&mut self, freq: Hz) {
+        self.carrier.set_freq(freq);
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.carrier.set_msample_rate(msample_rate);
+    }
+}
+
+impl Default for RingMod {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: SampleType> Effect<S> for RingMod {
+    fn process(&mut self, input: S) -> S {
+        RingMod::process(self, input)
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        RingMod::set_msample_rate(self, msample_rate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ring_mod_external() {
+        let mut ring_mod = RingMod::new();
+        assert!((ring_mod.process_with_carrier(10_000_i16, i16::MAX) - 10_000).abs() <= 2);
+        assert!((ring_mod.process_with_carrier(10_000_i16, -i16::MAX) + 10_000).abs() <= 2);
+        assert_eq!(ring_mod.process_with_carrier(10_000_i16, 0), 0);
+
+        ring_mod.set_mix(0);
+        assert!((ring_mod.process_with_carrier(10_000_i16, 0) - 10_000).abs() <= 1);
+    }
+
+    #[test]
+    fn test_ring_mod_internal() {
+        let mut ring_mod = RingMod::new();
+        ring_mod.set_freq(Hz(441));
+        // DC input turns into the carrier itself, which averages out over
+        // whole periods
+        let sum: i32 = (0..44100)
+            .map(|_| ring_mod.process(10_000_i16) as i32)
+            .sum();
+        assert!((sum / 44100).abs() < 10);
+    }
+}
@@ -0,0 +1,112 @@
+// Tremolo effect, i.e. LFO-driven amplitude modulation.
+
+use crate::fx::Effect;
+use crate::osc::lfo::{Lfo, LfoShape};
+use crate::seq::Division;
+use crate::util::sample::SampleType;
+use crate::util::units::{mHz, ms, Bpm, Hz, SAMPLE_NORM};
+
+/// Tremolo
+///
+/// The gain swings between 1 and `1 - depth`, where `depth` is normalized
+/// to [SAMPLE_NORM].
+pub struct Tremolo {
+    lfo: Lfo,
+    depth: i16,
+}
+
+impl Tremolo {
+    pub fn new() -> Self {
+        let mut s = Self {
+            lfo: Lfo::new(),
+            depth: i16::MAX / 2,
+        };
+        s.lfo.set_freq(Hz(5));
+        s
+    }
+
+    /// Applies the amplitude modulation to `input` and advances the LFO.
+    #[inline]
+    pub fn process<S: SampleType>(&mut self, input: S) -> S {
+        let m = self.lfo.next().unwrap_or(0) as i32;
+        // Map the bipolar LFO onto [0, 1] and scale the attenuation by depth
+        let unipolar = (m + SAMPLE_NORM) >> 1;
+        let gain = SAMPLE_NORM - (self.depth as i32 * (SAMPLE_NORM - unipolar)) / SAMPLE_NORM;
+        input.scale(gain.min(i16::MAX as i32) as i64, 15)
+    }
+
+    /// Sets the modulation depth normalized to [SAMPLE_NORM].
+    pub fn set_depth(&mut self, depth: i16) {
+        self.depth = depth.max(0);
+    }
+
+    /// Sets the LFO waveform.
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.lfo.set_shape(shape);
+    }
+
+    /// Sets the modulation rate in mHz.
+    pub fn set_mrate(&mut self, mrate: mHz) {
+        self.lfo.set_mfreq(mrate);
+    }
+
+    /// Sets the modulation rate in Hz.
+    pub fn set_rate(&mut self, rate: Hz) {
+        self.lfo.set_freq(rate);
+    }
+
+    /// Syncs the rate to a tempo. See [Lfo::set_sync].
+    pub fn set_sync(&mut self, beat: ms, cycles: u32, beats: u32) {
+        self.lfo.set_sync(beat, cycles, beats);
+    }
+
+    /// Syncs the rate to one period per `division` at `bpm`.
+    pub fn set_sync_division(&mut self, bpm: Bpm, division: Division) {
+        self.lfo.set_sync_division(bpm, division);
+    }
+
+    /// Restarts the modulation cycle, e.g. on a downbeat.
+    pub fn reset(&mut self) {
+        self.lfo.reset();
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.lfo.set_msample_rate(msample_rate);
+    }
+}
+
+impl Default for Tremolo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: SampleType> Effect<S> for Tremolo {
+    fn process(&mut self, input: S) -> S {
+        Tremolo::process(self, input)
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        Tremolo::set_msample_rate(self, msample_rate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tremolo() {
+        let mut tremolo = Tremolo::new();
+        tremolo.set_msample_rate(mHz(1_000_000));
+        tremolo.set_rate(Hz(10));
+        tremolo.set_depth(i16::MAX);
+        let out: Vec<i16> = (0..100).map(|_| tremolo.process(10_000)).collect();
+        assert!(*out.iter().max().unwrap() > 9_900);
+        assert!(*out.iter().min().unwrap() < 100);
+
+        tremolo.set_depth(0);
+        assert!((0..100).all(|_| (tremolo.process(10_000_i16) - 10_000).abs() <= 1));
+    }
+}
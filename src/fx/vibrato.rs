@@ -0,0 +1,138 @@
+// Vibrato effect based on an LFO-modulated fractional delay.
+
+use crate::fx::delay::{DelayLine, DELAY_FRAC_BITS};
+use crate::fx::Effect;
+use crate::osc::lfo::{Lfo, LfoShape};
+use crate::util::units::{mHz, us, Hz, SAMPLE_NORM};
+
+/// Length of the vibrato delay line. Limits the depth to about 23 ms at
+/// 44.1 kHz.
+const VIBRATO_LEN: usize = 1024;
+
+/// Vibrato
+///
+/// Sweeps a delay tap between 0 and `depth`, which results in a periodic
+/// pitch deviation proportional to depth and rate. The source oscillator
+/// remains untouched.
+pub struct Vibrato {
+    delay: DelayLine<VIBRATO_LEN>,
+    lfo: Lfo,
+
+    depth: us,
+    // Depth in samples with DELAY_FRAC_BITS fractional bits
+    depth_frac: u32,
+
+    msample_rate: mHz,
+}
+
+impl Vibrato {
+    pub fn new() -> Self {
+        let mut s = Self {
+            delay: DelayLine::new(),
+            lfo: Lfo::new(),
+
+            depth: us(2_000),
+            depth_frac: 0,
+
+            msample_rate: mHz(44_100_000),
+        };
+        s.lfo.set_freq(Hz(5));
+        s.update_depth();
+        s
+    }
+
+    fn update_depth(&mut self) {
+        let max = ((VIBRATO_LEN - 2) as u64) << DELAY_FRAC_BITS;
+        let depth =
+            ((self.depth.0 as u64 * self.msample_rate.0 as u64) << DELAY_FRAC_BITS) / 1_000_000_000;
+        self.depth_frac = depth.min(max) as u32;
+    }
+
+    /// Feeds `input` into the delay line and returns the modulated tap.
+    #[inline]
+    pub fn process(&mut self, input: i16) -> i16 {
+        self.delay.write(input);
+        let m = self.lfo.next().unwrap_or(0) as i32;
+        let unipolar = ((m + SAMPLE_NORM) >> 1) as u64;
+        let delay = (self.depth_frac as u64 * unipolar) / SAMPLE_NORM as u64;
+        self.delay.read_frac(delay as u32)
+    }
+
+    /// Sets the maximum delay swing in us.
+    pub fn set_depth_us(&mut self, depth: us) {
+        self.depth = depth;
+        self.update_depth();
+    }
+
+    /// Sets the LFO waveform.
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.lfo.set_shape(shape);
+    }
+
+    /// Sets the modulation rate in mHz.
+    pub fn set_mrate(&mut self, mrate: mHz) {
+        self.lfo.set_mfreq(mrate);
+    }
+
+    /// Sets the modulation rate in Hz.
+    pub fn set_rate(&mut self, rate: Hz) {
+        self.lfo.set_freq(rate);
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        self.lfo.set_msample_rate(msample_rate);
+        self.update_depth();
+    }
+}
+
+impl Default for Vibrato {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for Vibrato {
+    fn process(&mut self, input: i16) -> i16 {
+        Vibrato::process(self, input)
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        Vibrato::set_msample_rate(self, msample_rate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vibrato_dc() {
+        let mut vibrato = Vibrato::new();
+        for _ in 0..VIBRATO_LEN {
+            vibrato.process(1_234);
+        }
+        assert!((0..44100).all(|_| vibrato.process(1_234) == 1_234));
+    }
+
+    #[test]
+    fn test_vibrato_delay_range() {
+        let mut vibrato = Vibrato::new();
+        vibrato.set_msample_rate(mHz(1_000_000));
+        vibrato.set_rate(Hz(10));
+        // 10 samples at 1 kHz
+        vibrato.set_depth_us(us(10_000));
+        let mut min_delay = i32::MAX;
+        let mut max_delay = 0;
+        for x in 0..1_000 {
+            let delay = x - vibrato.process(x as i16) as i32;
+            if x > 100 {
+                min_delay = min_delay.min(delay);
+                max_delay = max_delay.max(delay);
+            }
+        }
+        assert!(min_delay <= 1);
+        assert!((9..=10).contains(&max_delay));
+    }
+}
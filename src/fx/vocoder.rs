@@ -0,0 +1,181 @@
+// Channel vocoder built from state variable filter banks and envelope
+// followers.
+
+use crate::fx::dynamics::EnvelopeFollower;
+use crate::fx::filter::{StateVariableFilter, Q_MAX};
+use crate::util::diag;
+use crate::util::units::{mHz, ms, ratio, Frequency, Hz};
+
+/// Input attenuation that keeps the resonant band-pass filters from
+/// overflowing
+const INPUT_SHIFT: u32 = 3;
+/// Largest supported span of the filter bank in cents (10 octaves)
+const SPAN_MAX: i32 = 12_000;
+
+/// Channel vocoder with `B` bands
+///
+/// The modulator (e.g. a voice) is split into `B` bands whose envelopes
+/// control the levels of the corresponding bands of the carrier (e.g. a saw
+/// or noise). The band centers are spaced logarithmically between the low
+/// and the high frequency.
+pub struct Vocoder<const B: usize> {
+    analysis: [StateVariableFilter; B],
+    synthesis: [StateVariableFilter; B],
+    followers: [EnvelopeFollower; B],
+
+    low: mHz,
+    high: mHz,
+    q: u32,
+
+    msample_rate: mHz,
+}
+
+impl<const B: usize> Vocoder<B> {
+    pub fn new() -> Self {
+        let mut s = Self {
+            analysis: core::array::from_fn(|_| StateVariableFilter::new()),
+            synthesis: core::array::from_fn(|_| StateVariableFilter::new()),
+            followers: core::array::from_fn(|_| EnvelopeFollower::new()),
+
+            low: Hz(100).to_mHz(),
+            high: Hz(8_000).to_mHz(),
+            q: Q_MAX - Q_MAX / 4,
+
+            msample_rate: mHz(44_100_000),
+        };
+        s.set_attack_ms(ms(5));
+        s.set_release_ms(ms(30));
+        s.update_bands();
+        s
+    }
+
+    /// Returns the center frequency of band `band`.
+    pub fn get_band_mfreq(&self, band: usize) -> mHz {
+        // Find the span in cents by bisection, which avoids logarithms
+        let (mut lo, mut hi) = (0, SPAN_MAX);
+        while lo < hi {
+            let mid = (lo + hi + 1) / 2;
+            if (self.low.0 as u64 * ratio(mid) as u64) >> 16 <= self.high.0 as u64 {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        let cents = if B > 1 {
+            (lo as i64 * band as i64 / (B as i64 - 1)) as i32
+        } else {
+            0
+        };
+        mHz(((self.low.0 as u64 * ratio(cents) as u64) >> 16) as u32)
+    }
+
+    fn update_bands(&mut self) {
+        for band in 0..B {
+            let mfreq = self.get_band_mfreq(band);
+            for filter in [&mut self.analysis[band], &mut self.synthesis[band]] {
+                filter.set_msample_rate(self.msample_rate);
+                filter.set_mfreq(mfreq);
+                filter.set_q(self.q);
+            }
+        }
+    }
+
+    /// Imposes the spectral envelope of `modulator` onto `carrier`.
+    #[inline]
+    pub fn process(&mut self, modulator: i16, carrier: i16) -> i16 {
+        let mut acc = 0_i32;
+        for band in 0..B {
+            self.analysis[band].feed(modulator >> INPUT_SHIFT);
+            let env = self.followers[band].process(self.analysis[band].get_bp()) as i32;
+            self.synthesis[band].feed(carrier >> INPUT_SHIFT);
+            acc += (self.synthesis[band].get_bp() as i32 * env) >> (15 - 2 * INPUT_SHIFT);
+        }
+        diag::clip(acc as i64)
+    }
+
+    /// Sets the center frequencies of the lowest and the highest band.
+    pub fn set_range(&mut self, low: mHz, high: mHz) {
+        self.low = mHz(low.0.max(1));
+        self.high = high;
+        self.update_bands();
+    }
+
+    /// Sets the resonance of all band filters (see
+    /// [StateVariableFilter::set_q]). More bands call for higher values.
+    pub fn set_q(&mut self, q: u32) {
+        self.q = q;
+        self.update_bands();
+    }
+
+    /// Sets how fast the band levels follow rising modulator levels.
+    pub fn set_attack_ms(&mut self, attack: ms) {
+        for follower in self.followers.iter_mut() {
+            follower.set_attack_ms(attack);
+        }
+    }
+
+    /// Sets how fast the band levels follow falling modulator levels.
+    pub fn set_release_ms(&mut self, release: ms) {
+        for follower in self.followers.iter_mut() {
+            follower.set_release_ms(release);
+        }
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        for follower in self.followers.iter_mut() {
+            follower.set_msample_rate(msample_rate);
+        }
+        self.update_bands();
+    }
+}
+
+impl<const B: usize> Default for Vocoder<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::osc::noise::WhiteNoise;
+    use crate::osc::wavetable::SineOscillator;
+
+    #[test]
+    fn test_vocoder_bands() {
+        let mut vocoder = Vocoder::<7>::new();
+        vocoder.set_range(Hz(100).to_mHz(), Hz(6_400).to_mHz());
+        for (band, freq) in [100, 200, 400, 800, 1_600, 3_200, 6_400].iter().enumerate() {
+            // Within 1 %
+            let mfreq = vocoder.get_band_mfreq(band).0;
+            assert!(mfreq.abs_diff(Hz(*freq).to_mHz().0) < 10 * freq, "{}", band);
+        }
+    }
+
+    /// Returns the mean absolute output for a sine modulator.
+    fn level(vocoder: &mut Vocoder<8>, modulator: Option<Hz>) -> i32 {
+        let mut sine = SineOscillator::new();
+        if let Some(freq) = modulator {
+            sine.set_freq(freq);
+            sine.start();
+        }
+        let mut noise = WhiteNoise::new();
+        let mut sum = 0;
+        for _ in 0..22050 {
+            let m = sine.next().unwrap_or(0);
+            sum += (vocoder.process(m / 2, noise.next().unwrap() / 2) as i32).abs();
+        }
+        sum / 22050
+    }
+
+    #[test]
+    fn test_vocoder() {
+        let mut vocoder = Vocoder::<8>::new();
+        let silent = level(&mut vocoder, None);
+        let voiced = level(&mut vocoder, Some(Hz(1_000)));
+        assert!(silent < 10, "{}", silent);
+        assert!(voiced > 50 * silent.max(1), "{} {}", voiced, silent);
+    }
+}
@@ -0,0 +1,154 @@
+// Stereo widener using mid/side processing or the Haas effect.
+
+use crate::fx::delay::DelayLine;
+use crate::util::diag;
+use crate::util::units::{mHz, us, Frame, Sample, SAMPLE_NORM};
+
+/// Length of the Haas delay line. Limits the delay to about 46 ms at
+/// 44.1 kHz.
+const HAAS_LEN: usize = 2048;
+
+/// Maximum width, i.e. the side signal is doubled
+pub const WIDTH_MAX: u32 = 2 * SAMPLE_NORM as u32;
+
+/// Operating mode of a [StereoWidener]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WidenerMode {
+    /// Scales the side signal. Has no effect on mono input.
+    MidSide,
+    /// Delays the right channel by a few ms, which also widens mono input.
+    Haas,
+}
+
+/// Stereo widener
+///
+/// The width is normalized to [SAMPLE_NORM]. In mid/side mode 0 collapses
+/// the signal to mono, [SAMPLE_NORM] leaves it unchanged and [WIDTH_MAX]
+/// doubles the side signal. In Haas mode the width crossfades the right
+/// channel from dry (0) to fully delayed ([SAMPLE_NORM] and above).
+pub struct StereoWidener {
+    mode: WidenerMode,
+    width: u32,
+
+    delay: DelayLine<HAAS_LEN>,
+    haas_delay: us,
+    haas_samples: usize,
+
+    msample_rate: mHz,
+}
+
+impl StereoWidener {
+    pub fn new() -> Self {
+        let mut s = Self {
+            mode: WidenerMode::MidSide,
+            width: SAMPLE_NORM as u32,
+
+            delay: DelayLine::new(),
+            haas_delay: us(15_000),
+            haas_samples: 0,
+
+            msample_rate: mHz(44_100_000),
+        };
+        s.update_delay();
+        s
+    }
+
+    fn update_delay(&mut self) {
+        let samples = (self.haas_delay.0 as u64 * self.msample_rate.0 as u64) / 1_000_000_000;
+        self.haas_samples = (samples as usize).min(HAAS_LEN - 1);
+    }
+
+    /// Widens the next frame.
+    #[inline]
+    pub fn process(&mut self, input: Frame) -> Frame {
+        let left = input.left.0 as i32;
+        let right = input.right.0 as i32;
+        match self.mode {
+            WidenerMode::MidSide => {
+                let mid = (left + right) / 2;
+                let side =
+                    (((left - right) / 2) as i64 * self.width as i64 / SAMPLE_NORM as i64) as i32;
+                Frame {
+                    left: saturate(mid + side),
+                    right: saturate(mid - side),
+                }
+            }
+            WidenerMode::Haas => {
+                self.delay.write(input.right.0);
+                let delayed = self.delay.read(self.haas_samples) as i32;
+                let w = self.width.min(SAMPLE_NORM as u32) as i32;
+                Frame {
+                    left: input.left,
+                    right: saturate(right + ((delayed - right) * w) / SAMPLE_NORM),
+                }
+            }
+        }
+    }
+
+    /// Sets the width normalized to [SAMPLE_NORM], clamped to [WIDTH_MAX].
+    pub fn set_width(&mut self, width: u32) {
+        self.width = width.min(WIDTH_MAX);
+    }
+
+    /// Sets the operating mode.
+    pub fn set_mode(&mut self, mode: WidenerMode) {
+        self.mode = mode;
+    }
+
+    /// Sets the delay of the right channel in Haas mode.
+    pub fn set_haas_delay_us(&mut self, delay: us) {
+        self.haas_delay = delay;
+        self.update_delay();
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        self.update_delay();
+    }
+}
+
+impl Default for StereoWidener {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn saturate(x: i32) -> Sample {
+    Sample(diag::clip(x as i64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_widener_mid_side() {
+        let mut widener = StereoWidener::new();
+        assert_eq!(
+            widener.process(Frame::new(1_000, -200)),
+            Frame::new(1_000, -200)
+        );
+        widener.set_width(0);
+        assert_eq!(
+            widener.process(Frame::new(1_000, -200)),
+            Frame::new(400, 400)
+        );
+        widener.set_width(WIDTH_MAX);
+        assert_eq!(
+            widener.process(Frame::new(1_000, -200)),
+            Frame::new(1_600, -800)
+        );
+    }
+
+    #[test]
+    fn test_widener_haas() {
+        let mut widener = StereoWidener::new();
+        widener.set_mode(WidenerMode::Haas);
+        widener.set_msample_rate(mHz(1_000_000));
+        // 10 samples at 1 kHz
+        widener.set_haas_delay_us(us(10_000));
+        let out: Vec<Frame> = (0..20).map(|x| widener.process(Frame::mono(x))).collect();
+        assert_eq!(out[15], Frame::new(15, 5));
+    }
+}
@@ -0,0 +1,429 @@
+pub mod nodes;
+pub mod patch;
+
+#[cfg(feature = "alloc")]
+use crate::synth::Synth;
+#[cfg(feature = "alloc")]
+use crate::util::diag;
+use crate::util::units::mHz;
+#[cfg(feature = "alloc")]
+use crate::util::units::{Frequency, Hz};
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::any::Any;
+
+/// Samples evaluated per node at once
+pub const BLOCK: usize = 64;
+/// Buffer of one port
+pub type Block = [i16; BLOCK];
+/// Most inputs or outputs a node can have
+pub const MAX_PORTS: usize = 8;
+
+/// Processing block of a [Graph]
+///
+/// Signal inputs and outputs are evaluated at the sample rate. Controls
+/// are set once per block from the first sample of the connected output,
+/// which is cheap enough for modulation like cutoff sweeps. Nodes define
+/// the meaning of their controls, which are normalized to
+/// [crate::util::units::SAMPLE_NORM].
+pub trait Node: Any {
+    /// Number of signal inputs, at most [MAX_PORTS]
+    fn inputs(&self) -> usize {
+        0
+    }
+
+    /// Number of signal outputs, at most [MAX_PORTS]
+    fn outputs(&self) -> usize {
+        1
+    }
+
+    /// Number of controls
+    fn controls(&self) -> usize {
+        0
+    }
+
+    /// Sets control `index` to `value`.
+    fn set_control(&mut self, _index: usize, _value: i16) {}
+
+    /// Processes one sample of every input into one sample of every output.
+    fn process(&mut self, inputs: &[i16], outputs: &mut [i16]);
+
+    /// Processes the first `len` samples of the input blocks into the
+    /// output blocks. Nodes should override this with a block path where
+    /// possible, since it avoids a call per sample.
+    fn process_block(&mut self, inputs: &[Block], outputs: &mut [Block], len: usize) {
+        let mut x = [0_i16; MAX_PORTS];
+        let mut y = [0_i16; MAX_PORTS];
+        let (x, y) = (&mut x[..inputs.len()], &mut y[..outputs.len()]);
+        for n in 0..len {
+            for (x, input) in x.iter_mut().zip(inputs.iter()) {
+                *x = input[n];
+            }
+            self.process(x, y);
+            for (y, output) in y.iter().zip(outputs.iter_mut()) {
+                output[n] = *y;
+            }
+        }
+    }
+
+    /// Sets the sample rate in mHz. Nodes without a notion of time ignore
+    /// it.
+    fn set_msample_rate(&mut self, _msample_rate: mHz) {}
+}
+
+/// Handle of a node in a [Graph]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeId(usize);
+
+/// Reason why a connection was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphError {
+    /// The node isn't part of the graph.
+    InvalidNode,
+    /// The node has no port or control with this index.
+    InvalidPort,
+    /// The connection would create a feedback loop.
+    Cycle,
+}
+
+/// Destination of an edge
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Input(usize),
+    Control(usize),
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Edge {
+    from: usize,
+    port: usize,
+    to: usize,
+    target: Target,
+}
+
+/// Network of nodes connected by signal and control edges
+///
+/// The nodes are evaluated block by block in topological order, so every
+/// node sees the current block of its sources. Several edges into the
+/// same input are summed with saturation. Feedback loops are rejected when
+/// connecting; feedback needs a node with internal delay instead. The
+/// graph allocates when nodes are added and connected, but not while
+/// rendering.
+#[cfg(feature = "alloc")]
+pub struct Graph {
+    nodes: Vec<Box<dyn Node>>,
+    edges: Vec<Edge>,
+    // Evaluation order of the nodes
+    order: Vec<usize>,
+    // Index of the first output buffer of every node
+    offsets: Vec<usize>,
+    buffers: Vec<Block>,
+    scratch: [Block; MAX_PORTS],
+    output: Option<(usize, usize)>,
+
+    msample_rate: mHz,
+}
+
+#[cfg(feature = "alloc")]
+impl Graph {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            order: Vec::new(),
+            offsets: Vec::new(),
+            buffers: Vec::new(),
+            scratch: [[0; BLOCK]; MAX_PORTS],
+            output: None,
+
+            msample_rate: mHz(44_100_000),
+        }
+    }
+
+    /// Adds `node` and returns its handle. Panics if the node has more than
+    /// [MAX_PORTS] inputs or outputs.
+    pub fn add<N: Node + 'static>(&mut self, mut node: N) -> NodeId {
+        assert!(node.inputs() <= MAX_PORTS && node.outputs() <= MAX_PORTS);
+        node.set_msample_rate(self.msample_rate);
+        self.offsets.push(self.buffers.len());
+        self.buffers
+            .extend(core::iter::repeat_n([0; BLOCK], node.outputs()));
+        self.nodes.push(Box::new(node));
+        self.order.push(self.nodes.len() - 1);
+        NodeId(self.nodes.len() - 1)
+    }
+
+    fn add_edge(&mut self, edge: Edge) -> Result<(), GraphError> {
+        let (from, to) = match (self.nodes.get(edge.from), self.nodes.get(edge.to)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return Err(GraphError::InvalidNode),
+        };
+        let valid = match edge.target {
+            Target::Input(i) => i < to.inputs(),
+            Target::Control(i) => i < to.controls(),
+        };
+        if edge.port >= from.outputs() || !valid {
+            return Err(GraphError::InvalidPort);
+        }
+        self.edges.push(edge);
+        if !self.sort() {
+            self.edges.pop();
+            self.sort();
+            return Err(GraphError::Cycle);
+        }
+        Ok(())
+    }
+
+    /// Updates the evaluation order. Returns false if there's a cycle.
+    fn sort(&mut self) -> bool {
+        // Kahn's algorithm
+        let mut pending: Vec<usize> = vec![0; self.nodes.len()];
+        for edge in self.edges.iter() {
+            pending[edge.to] += 1;
+        }
+        let mut order: Vec<usize> = (0..self.nodes.len()).filter(|n| pending[*n] == 0).collect();
+        let mut i = 0;
+        while i < order.len() {
+            let n = order[i];
+            for edge in self.edges.iter().filter(|e| e.from == n) {
+                pending[edge.to] -= 1;
+                if pending[edge.to] == 0 {
+                    order.push(edge.to);
+                }
+            }
+            i += 1;
+        }
+        if order.len() < self.nodes.len() {
+            return false;
+        }
+        self.order = order;
+        true
+    }
+
+    /// Connects output `port` of `from` to input `input` of `to`.
+    pub fn connect(
+        &mut self,
+        from: NodeId,
+        port: usize,
+        to: NodeId,
+        input: usize,
+    ) -> Result<(), GraphError> {
+        self.add_edge(Edge {
+            from: from.0,
+            port,
+            to: to.0,
+            target: Target::Input(input),
+        })
+    }
+
+    /// Connects output `port` of `from` to control `control` of `to`.
+    pub fn connect_control(
+        &mut self,
+        from: NodeId,
+        port: usize,
+        to: NodeId,
+        control: usize,
+    ) -> Result<(), GraphError> {
+        self.add_edge(Edge {
+            from: from.0,
+            port,
+            to: to.0,
+            target: Target::Control(control),
+        })
+    }
+
+    /// Removes all edges from and to `node`.
+    pub fn disconnect(&mut self, node: NodeId) {
+        self.edges.retain(|e| e.from != node.0 && e.to != node.0);
+        self.sort();
+    }
+
+    /// Selects output `port` of `node` as the output of the graph.
+    pub fn set_output(&mut self, node: NodeId, port: usize) -> Result<(), GraphError> {
+        match self.nodes.get(node.0) {
+            None => Err(GraphError::InvalidNode),
+            Some(n) if port >= n.outputs() => Err(GraphError::InvalidPort),
+            Some(_) => {
+                self.output = Some((node.0, port));
+                Ok(())
+            }
+        }
+    }
+
+    /// Sets control `control` of `node` directly, e.g. a gate.
+    pub fn set_control(&mut self, node: NodeId, control: usize, value: i16) {
+        if let Some(node) = self.nodes.get_mut(node.0) {
+            node.set_control(control, value);
+        }
+    }
+
+    /// Returns `node` as its concrete type for changing its parameters,
+    /// or None if the type doesn't match.
+    pub fn get_node_mut<N: Node>(&mut self, node: NodeId) -> Option<&mut N> {
+        let node: &mut dyn Any = self.nodes.get_mut(node.0)?.as_mut();
+        node.downcast_mut()
+    }
+
+    /// Evaluates all nodes for `len` samples.
+    fn evaluate(&mut self, len: usize) {
+        for &n in self.order.iter() {
+            let node = &mut self.nodes[n];
+            let inputs = node.inputs();
+            for buf in self.scratch[..inputs].iter_mut() {
+                buf[..len].fill(0);
+            }
+            for edge in self.edges.iter().filter(|e| e.to == n) {
+                let source = &self.buffers[self.offsets[edge.from] + edge.port];
+                match edge.target {
+                    Target::Input(i) => {
+                        for (x, s) in self.scratch[i][..len].iter_mut().zip(source.iter()) {
+                            *x = diag::add(*x, *s);
+                        }
+                    }
+                    Target::Control(i) => node.set_control(i, source[0]),
+                }
+            }
+            let offset = self.offsets[n];
+            let outputs = &mut self.buffers[offset..offset + node.outputs()];
+            node.process_block(&self.scratch[..inputs], outputs, len);
+        }
+    }
+
+    /// Fills `out` with the next samples of the output.
+    pub fn render(&mut self, out: &mut [i16]) {
+        for chunk in out.chunks_mut(BLOCK) {
+            self.evaluate(chunk.len());
+            match self.output {
+                Some((n, port)) => {
+                    let buf = &self.buffers[self.offsets[n] + port];
+                    chunk.copy_from_slice(&buf[..chunk.len()]);
+                }
+                None => chunk.fill(0),
+            }
+        }
+    }
+
+    /// Returns the next sample of the output.
+    pub fn _next(&mut self) -> i16 {
+        let mut y = [0];
+        self.render(&mut y);
+        y[0]
+    }
+
+    /// Sets the sample rate of all nodes in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        for node in self.nodes.iter_mut() {
+            node.set_msample_rate(msample_rate);
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Synth for Graph {
+    fn _next(&mut self) -> Option<i16> {
+        Some(Graph::_next(self))
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        Graph::render(self, out);
+    }
+
+    fn get_sample_rate(&self) -> Hz {
+        self.msample_rate.to_Hz()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: Hz) {
+        self.set_msample_rate(sample_rate.to_mHz());
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod test {
+    use super::*;
+
+    /// Emits a constant level
+    struct Constant(i16);
+
+    impl Node for Constant {
+        fn process(&mut self, _inputs: &[i16], outputs: &mut [i16]) {
+            outputs[0] = self.0;
+        }
+    }
+
+    /// Adds its control value to its input
+    struct Offset(i16);
+
+    impl Node for Offset {
+        fn inputs(&self) -> usize {
+            1
+        }
+
+        fn controls(&self) -> usize {
+            1
+        }
+
+        fn set_control(&mut self, _index: usize, value: i16) {
+            self.0 = value;
+        }
+
+        fn process(&mut self, inputs: &[i16], outputs: &mut [i16]) {
+            outputs[0] = inputs[0] + self.0;
+        }
+    }
+
+    #[test]
+    fn test_graph_order() {
+        // Added in reverse, so evaluation must not follow insertion
+        let mut graph = Graph::new();
+        let b = graph.add(Offset(0));
+        let a = graph.add(Offset(0));
+        let c1 = graph.add(Constant(10));
+        let c2 = graph.add(Constant(5));
+        graph.connect(c1, 0, a, 0).unwrap();
+        graph.connect(c2, 0, a, 0).unwrap();
+        graph.connect(a, 0, b, 0).unwrap();
+        graph.connect_control(c2, 0, b, 0).unwrap();
+        graph.set_output(b, 0).unwrap();
+
+        let mut out = [0; 100];
+        graph.render(&mut out);
+        assert!(out.iter().all(|y| *y == 20), "{:?}", out);
+        graph.get_node_mut::<Constant>(c1).unwrap().0 = 20;
+        assert_eq!(graph._next(), 30);
+        assert!(graph.get_node_mut::<Offset>(c1).is_none());
+    }
+
+    #[test]
+    fn test_graph_errors() {
+        let mut graph = Graph::new();
+        let a = graph.add(Offset(0));
+        let b = graph.add(Offset(0));
+        let c = graph.add(Constant(1));
+        graph.connect(a, 0, b, 0).unwrap();
+        assert_eq!(graph.connect(b, 0, a, 0), Err(GraphError::Cycle));
+        assert_eq!(graph.connect_control(b, 0, a, 0), Err(GraphError::Cycle));
+        assert_eq!(graph.connect(a, 0, c, 0), Err(GraphError::InvalidPort));
+        assert_eq!(graph.connect(a, 1, b, 0), Err(GraphError::InvalidPort));
+        assert_eq!(
+            graph.connect(a, 0, NodeId(7), 0),
+            Err(GraphError::InvalidNode)
+        );
+
+        // The rejected edges left the graph intact
+        graph.connect(c, 0, a, 0).unwrap();
+        graph.set_output(b, 0).unwrap();
+        assert_eq!(graph._next(), 1);
+        graph.disconnect(a);
+        assert_eq!(graph._next(), 0);
+    }
+}
@@ -0,0 +1,297 @@
+// Graph nodes of the oscillator, envelope and filter primitives.
+
+use crate::env::adsr::{Adsr, AdsrStage};
+use crate::fx::filter::StateVariableFilter;
+use crate::fx::Effect;
+use crate::graph::{Block, Node};
+use crate::osc::blep::BlepOscillator;
+use crate::osc::fm::FmOperator;
+use crate::osc::lfo::Lfo;
+use crate::osc::noise::WhiteNoise;
+use crate::preset::Preset;
+use crate::util::units::{mHz, ratio};
+
+impl Node for BlepOscillator {
+    fn process(&mut self, _inputs: &[i16], outputs: &mut [i16]) {
+        outputs[0] = self._next();
+    }
+
+    fn process_block(&mut self, _inputs: &[Block], outputs: &mut [Block], len: usize) {
+        self.render(&mut outputs[0][..len]);
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        BlepOscillator::set_msample_rate(self, msample_rate);
+    }
+}
+
+/// The input modulates the phase.
+impl Node for FmOperator {
+    fn inputs(&self) -> usize {
+        1
+    }
+
+    fn process(&mut self, inputs: &[i16], outputs: &mut [i16]) {
+        outputs[0] = FmOperator::process(self, inputs[0]);
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        FmOperator::set_msample_rate(self, msample_rate);
+    }
+}
+
+impl Node for Lfo {
+    fn process(&mut self, _inputs: &[i16], outputs: &mut [i16]) {
+        outputs[0] = self.next().unwrap_or(0);
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        Lfo::set_msample_rate(self, msample_rate);
+    }
+}
+
+impl Node for WhiteNoise {
+    fn process(&mut self, _inputs: &[i16], outputs: &mut [i16]) {
+        outputs[0] = self.next().unwrap_or(0);
+    }
+
+    fn process_block(&mut self, _inputs: &[Block], outputs: &mut [Block], len: usize) {
+        self.render(&mut outputs[0][..len]);
+    }
+}
+
+/// The output is the level. Control 0 is the gate, which is open for
+/// positive values.
+impl Node for Adsr {
+    fn controls(&self) -> usize {
+        1
+    }
+
+    fn set_control(&mut self, _index: usize, value: i16) {
+        let open = !matches!(self.get_stage(), AdsrStage::Idle | AdsrStage::Release);
+        if value > 0 && !open {
+            self.gate_on();
+        } else if value <= 0 && open {
+            self.gate_off();
+        }
+    }
+
+    fn process(&mut self, _inputs: &[i16], outputs: &mut [i16]) {
+        outputs[0] = self._next();
+    }
+
+    fn process_block(&mut self, _inputs: &[Block], outputs: &mut [Block], len: usize) {
+        self.render(&mut outputs[0][..len]);
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        Adsr::set_msample_rate(self, msample_rate);
+    }
+}
+
+/// Node of an [Effect] with one input and one output
+pub struct EffectNode<E: Effect>(pub E);
+
+impl<E: Effect + 'static> Node for EffectNode<E> {
+    fn inputs(&self) -> usize {
+        1
+    }
+
+    fn process(&mut self, inputs: &[i16], outputs: &mut [i16]) {
+        outputs[0] = self.0.process(inputs[0]);
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.0.set_msample_rate(msample_rate);
+    }
+}
+
+/// Multiplies input 0 by input 1, e.g. a signal by an envelope
+pub struct Vca;
+
+impl Node for Vca {
+    fn inputs(&self) -> usize {
+        2
+    }
+
+    fn process(&mut self, inputs: &[i16], outputs: &mut [i16]) {
+        outputs[0] = ((inputs[0] as i32 * inputs[1] as i32) >> 15) as i16;
+    }
+}
+
+/// Range of the modulation depth of a [FilterNode] in cents
+pub const FILTER_DEPTH_MAX: i32 = 6_000;
+/// Lowest cutoff of a [FilterNode]
+const FILTER_CUTOFF_MIN: mHz = mHz(20_000);
+
+/// Parameters of a [FilterNode]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilterParams {
+    pub cutoff: mHz,
+    pub q: u32,
+    pub depth: i32,
+}
+
+/// [StateVariableFilter] with a modulated cutoff
+///
+/// The outputs are lowpass, bandpass, highpass and notch. Control 0 shifts
+/// the cutoff by up to the modulation depth in cents, which is limited to
+/// [FILTER_DEPTH_MAX] either way.
+pub struct FilterNode {
+    filter: StateVariableFilter,
+    cutoff: mHz,
+    depth: i32,
+}
+
+impl FilterNode {
+    pub fn new(cutoff: mHz) -> Self {
+        let mut s = Self {
+            filter: StateVariableFilter::new(),
+            cutoff,
+            depth: 2_400,
+        };
+        s.set_control(0, 0);
+        s
+    }
+
+    /// Sets the cutoff without modulation.
+    pub fn set_cutoff(&mut self, cutoff: mHz) {
+        self.cutoff = cutoff;
+        self.set_control(0, 0);
+    }
+
+    /// Sets the resonance from 0 to [crate::fx::filter::Q_MAX].
+    pub fn set_q(&mut self, q: u32) {
+        self.filter.set_q(q);
+    }
+
+    /// Sets the cutoff shift at full scale control in cents, up to
+    /// [FILTER_DEPTH_MAX] either way.
+    pub fn set_depth(&mut self, cents: i32) {
+        self.depth = cents.clamp(-FILTER_DEPTH_MAX, FILTER_DEPTH_MAX);
+    }
+}
+
+impl Preset for FilterNode {
+    type Params = FilterParams;
+
+    fn get_params(&self) -> FilterParams {
+        FilterParams {
+            cutoff: self.cutoff,
+            q: self.filter.get_q(),
+            depth: self.depth,
+        }
+    }
+
+    fn set_params(&mut self, params: &FilterParams) {
+        self.set_q(params.q);
+        self.set_depth(params.depth);
+        self.set_cutoff(params.cutoff);
+    }
+}
+
+impl Node for FilterNode {
+    fn inputs(&self) -> usize {
+        1
+    }
+
+    fn outputs(&self) -> usize {
+        4
+    }
+
+    fn controls(&self) -> usize {
+        1
+    }
+
+    fn set_control(&mut self, _index: usize, value: i16) {
+        let cents = (self.depth as i64 * value as i64 / i16::MAX as i64) as i32;
+        let mfreq = (self.cutoff.0 as u64 * ratio(cents) as u64) >> 16;
+        // The filter clamps the cutoff to its stable range
+        let mfreq = mfreq.clamp(FILTER_CUTOFF_MIN.0 as u64, u32::MAX as u64);
+        self.filter.set_mfreq(mHz(mfreq as u32));
+    }
+
+    fn process(&mut self, inputs: &[i16], outputs: &mut [i16]) {
+        self.filter.feed(inputs[0]);
+        outputs[0] = self.filter.get_lp();
+        outputs[1] = self.filter.get_bp();
+        outputs[2] = self.filter.get_hp();
+        outputs[3] = self.filter.get_no();
+    }
+
+    fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.filter.set_msample_rate(msample_rate);
+        self.set_control(0, 0);
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod test {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::util::units::{Frequency, Hz};
+
+    #[test]
+    fn test_graph_voice() {
+        // saw -> filter.lp -> vca <- adsr, lfo -> filter.cutoff
+        let mut graph = Graph::new();
+        let saw = graph.add(BlepOscillator::new());
+        let filter = graph.add(FilterNode::new(Hz(2_000).to_mHz()));
+        let adsr = graph.add(Adsr::new());
+        let vca = graph.add(Vca);
+        let lfo = graph.add(Lfo::new());
+        graph.connect(saw, 0, filter, 0).unwrap();
+        graph.connect(filter, 0, vca, 0).unwrap();
+        graph.connect(adsr, 0, vca, 1).unwrap();
+        graph.connect_control(lfo, 0, filter, 0).unwrap();
+        graph.set_output(vca, 0).unwrap();
+
+        let mut out = [0_i16; 1_000];
+        graph.render(&mut out);
+        assert!(out.iter().all(|y| *y == 0));
+
+        graph.set_control(adsr, 0, i16::MAX);
+        graph.render(&mut out);
+        let peak = out.iter().map(|y| y.saturating_abs()).max().unwrap();
+        assert!(peak > 5_000, "{}", peak);
+
+        // The block path matches the sample path
+        let mut a = Graph::new();
+        let mut b = Graph::new();
+        for graph in [&mut a, &mut b] {
+            let noise = graph.add(WhiteNoise::new());
+            let filter = graph.add(FilterNode::new(Hz(500).to_mHz()));
+            graph.connect(noise, 0, filter, 0).unwrap();
+            graph.set_output(filter, 2).unwrap();
+        }
+        let mut out = [0_i16; 200];
+        a.render(&mut out);
+        assert!(out.iter().all(|y| *y == b._next()));
+    }
+
+    #[test]
+    fn test_filter_node_depth() {
+        let mut filter = FilterNode::new(Hz(1_000).to_mHz());
+        filter.set_depth(-2_000_000_000);
+        assert_eq!(filter.get_params().depth, -FILTER_DEPTH_MAX);
+        let mut params = filter.get_params();
+        params.depth = i32::MAX;
+        params.cutoff = mHz(u32::MAX);
+        filter.set_params(&params);
+        assert_eq!(filter.get_params().depth, FILTER_DEPTH_MAX);
+        // Stays stable at both ends of the control
+        for value in [i16::MIN, i16::MAX] {
+            filter.set_control(0, value);
+            let mut outputs = [0; 4];
+            for n in 0..1_000 {
+                filter.process(&[if n % 2 == 0 { 10_000 } else { -10_000 }], &mut outputs);
+            }
+            assert!(
+                outputs.iter().all(|y| y.saturating_abs() < i16::MAX),
+                "{:?}",
+                outputs
+            );
+        }
+    }
+}
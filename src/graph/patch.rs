@@ -0,0 +1,272 @@
+// Declarative patches that expand to concrete structs without allocation.
+
+use crate::graph::{Node, MAX_PORTS};
+use crate::util::diag;
+
+/// Samples per update of the controls of a patch
+pub const CONTROL_BLOCK: u32 = 16;
+
+/// Destination of a [PatchEdge]
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatchTarget {
+    Input(usize),
+    Control(usize),
+    Output,
+}
+
+/// Edge of a patch generated by [crate::patch!]
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PatchEdge {
+    pub from: usize,
+    pub port: usize,
+    pub to: usize,
+    pub target: PatchTarget,
+}
+
+/// Evaluates the nodes of a patch for one sample and returns the output.
+#[doc(hidden)]
+pub fn evaluate<const N: usize>(
+    nodes: &mut [&mut dyn Node; N],
+    edges: &[PatchEdge],
+    outputs: &mut [[i16; MAX_PORTS]; N],
+    update_controls: bool,
+) -> i16 {
+    for (k, node) in nodes.iter_mut().enumerate() {
+        let mut x = [0_i16; MAX_PORTS];
+        for edge in edges.iter().filter(|e| e.to == k) {
+            let y = outputs[edge.from][edge.port];
+            match edge.target {
+                PatchTarget::Input(i) => x[i] = diag::add(x[i], y),
+                PatchTarget::Control(i) if update_controls => node.set_control(i, y),
+                _ => {}
+            }
+        }
+        let (inputs, outs) = (node.inputs(), node.outputs());
+        node.process(&x[..inputs], &mut outputs[k][..outs]);
+    }
+    edges
+        .iter()
+        .filter(|e| e.target == PatchTarget::Output)
+        .fold(0_i16, |y, e| diag::add(y, outputs[e.from][e.port]))
+}
+
+/// Declares a patch of graph nodes as a struct
+///
+/// The nodes become public fields, so their parameters stay accessible.
+/// Edges connect output ports to input ports with `->` and to controls with
+/// `=>`, where a port in brackets defaults to 0. Edges to `out` are summed
+/// into the output of the patch. The nodes are evaluated per sample in the
+/// order of declaration, so an edge to an earlier node delays by one
+/// sample, which allows feedback. Controls are updated every
+/// [CONTROL_BLOCK] samples. The patch implements [Node] with one output
+/// and needs neither allocation nor a graph.
+///
+/// ```
+/// use isopod::env::adsr::Adsr;
+/// use isopod::graph::nodes::{FilterNode, Vca};
+/// use isopod::osc::blep::BlepOscillator;
+/// use isopod::osc::lfo::Lfo;
+/// use isopod::patch;
+/// use isopod::util::units::{Frequency, Hz};
+///
+/// patch! {
+///     pub struct Pluck {
+///         saw: BlepOscillator = BlepOscillator::new(),
+///         lfo: Lfo = Lfo::new(),
+///         svf: FilterNode = FilterNode::new(Hz(800).to_mHz()),
+///         env: Adsr = Adsr::new(),
+///         vca: Vca = Vca,
+///     }
+///     saw -> svf;
+///     lfo => svf;
+///     svf[0] -> vca[0];
+///     env -> vca[1];
+///     vca -> out;
+/// }
+///
+/// let mut pluck = Pluck::new();
+/// pluck.env.gate_on();
+/// let mut out = [0_i16; 64];
+/// pluck.render(&mut out);
+/// ```
+#[macro_export]
+macro_rules! patch {
+    (@count) => { 0 };
+    (@count $head:ident $($tail:ident)*) => { 1 + $crate::patch!(@count $($tail)*) };
+
+    (@port) => { 0 };
+    (@port $port:expr) => { $port };
+
+    (@edges [$($acc:expr,)*]) => { &[$($acc,)*] };
+    (@edges [$($acc:expr,)*] $from:ident $([$fp:expr])? -> out; $($rest:tt)*) => {
+        $crate::patch!(@edges [$($acc,)* $crate::graph::patch::PatchEdge {
+            from: Idx::$from as usize,
+            port: $crate::patch!(@port $($fp)?),
+            to: usize::MAX,
+            target: $crate::graph::patch::PatchTarget::Output,
+        },] $($rest)*)
+    };
+    (@edges [$($acc:expr,)*] $from:ident $([$fp:expr])? -> $to:ident $([$tp:expr])?; $($rest:tt)*) => {
+        $crate::patch!(@edges [$($acc,)* $crate::graph::patch::PatchEdge {
+            from: Idx::$from as usize,
+            port: $crate::patch!(@port $($fp)?),
+            to: Idx::$to as usize,
+            target: $crate::graph::patch::PatchTarget::Input($crate::patch!(@port $($tp)?)),
+        },] $($rest)*)
+    };
+    (@edges [$($acc:expr,)*] $from:ident $([$fp:expr])? => $to:ident $([$tp:expr])?; $($rest:tt)*) => {
+        $crate::patch!(@edges [$($acc,)* $crate::graph::patch::PatchEdge {
+            from: Idx::$from as usize,
+            port: $crate::patch!(@port $($fp)?),
+            to: Idx::$to as usize,
+            target: $crate::graph::patch::PatchTarget::Control($crate::patch!(@port $($tp)?)),
+        },] $($rest)*)
+    };
+
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($node:ident: $ty:ty = $init:expr),* $(,)?
+        }
+        $($edges:tt)*
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(pub $node: $ty,)*
+            __outputs: [[i16; $crate::graph::MAX_PORTS]; $crate::patch!(@count $($node)*)],
+            __countdown: u32,
+        }
+
+        impl $name {
+            pub fn new() -> Self {
+                Self {
+                    $($node: $init,)*
+                    __outputs: [[0; $crate::graph::MAX_PORTS]; $crate::patch!(@count $($node)*)],
+                    __countdown: 0,
+                }
+            }
+
+            fn __edges() -> &'static [$crate::graph::patch::PatchEdge] {
+                #[allow(non_camel_case_types, dead_code)]
+                enum Idx {
+                    $($node),*
+                }
+                const EDGES: &[$crate::graph::patch::PatchEdge] =
+                    $crate::patch!(@edges [] $($edges)*);
+                EDGES
+            }
+
+            /// Returns the next sample.
+            pub fn _next(&mut self) -> i16 {
+                let update_controls = self.__countdown == 0;
+                if update_controls {
+                    self.__countdown = $crate::graph::patch::CONTROL_BLOCK;
+                }
+                self.__countdown -= 1;
+                let mut nodes: [&mut dyn $crate::graph::Node; $crate::patch!(@count $($node)*)] =
+                    [$(&mut self.$node),*];
+                $crate::graph::patch::evaluate(
+                    &mut nodes,
+                    Self::__edges(),
+                    &mut self.__outputs,
+                    update_controls,
+                )
+            }
+
+            /// Fills `out` with the next samples.
+            pub fn render(&mut self, out: &mut [i16]) {
+                for y in out.iter_mut() {
+                    *y = self._next();
+                }
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl $crate::graph::Node for $name {
+            fn process(&mut self, _inputs: &[i16], outputs: &mut [i16]) {
+                outputs[0] = self._next();
+            }
+
+            fn set_msample_rate(&mut self, msample_rate: $crate::util::units::mHz) {
+                $($crate::graph::Node::set_msample_rate(&mut self.$node, msample_rate);)*
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::env::adsr::Adsr;
+    use crate::graph::nodes::{FilterNode, Vca};
+    use crate::graph::Node;
+    use crate::osc::blep::BlepOscillator;
+    use crate::osc::lfo::Lfo;
+    use crate::util::units::{mHz, Frequency, Hz};
+
+    crate::patch! {
+        /// Filtered saw with an envelope
+        struct Pluck {
+            saw: BlepOscillator = BlepOscillator::new(),
+            lfo: Lfo = Lfo::new(),
+            svf: FilterNode = FilterNode::new(Hz(800).to_mHz()),
+            env: Adsr = Adsr::new(),
+            vca: Vca = Vca,
+        }
+        saw -> svf;
+        lfo => svf;
+        svf[0] -> vca[0];
+        env -> vca[1];
+        vca -> out;
+    }
+
+    /// Feeds its own previous output back with a one sample delay
+    struct Counter;
+
+    impl Node for Counter {
+        fn inputs(&self) -> usize {
+            1
+        }
+
+        fn process(&mut self, inputs: &[i16], outputs: &mut [i16]) {
+            outputs[0] = inputs[0] + 1;
+        }
+    }
+
+    crate::patch! {
+        struct Feedback {
+            counter: Counter = Counter,
+        }
+        counter -> counter;
+        counter -> out;
+        counter -> out;
+    }
+
+    #[test]
+    fn test_patch() {
+        let mut pluck = Pluck::new();
+        pluck.set_msample_rate(mHz(48_000_000));
+        let mut out = [0_i16; 1_000];
+        pluck.render(&mut out);
+        assert!(out.iter().all(|y| *y == 0));
+
+        pluck.env.gate_on();
+        pluck.render(&mut out);
+        let peak = out.iter().map(|y| y.saturating_abs()).max().unwrap();
+        assert!(peak > 5_000, "{}", peak);
+    }
+
+    #[test]
+    fn test_patch_feedback() {
+        let mut feedback = Feedback::new();
+        let mut out = [0_i16; 4];
+        feedback.render(&mut out);
+        assert_eq!(out, [2, 4, 6, 8]);
+    }
+}
@@ -0,0 +1,206 @@
+// Filling of interleaved output buffers, e.g. for DMA transfers to I2S DACs.
+
+use crate::synth::{StereoSynth, Synth};
+use crate::util::units::Frame;
+
+/// Fills the interleaved `out` with the next frames of a mono `synth`,
+/// copying each sample to all `channels`. Works in place without a scratch
+/// buffer, so the time per call only depends on the length of `out`. A
+/// trailing incomplete frame is set to 0.
+/// ```
+/// # use isopod::io::buffer::fill_buffer;
+/// # use isopod::synth::fmpiano::FmPiano;
+/// let mut piano = FmPiano::new();
+/// let mut out = [1; 7];
+/// fill_buffer(&mut piano, &mut out, 2);
+/// assert_eq!(out[6], 0);
+/// assert!(out.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+/// ```
+pub fn fill_buffer<S: Synth + ?Sized>(synth: &mut S, out: &mut [i16], channels: usize) {
+    let channels = channels.max(1);
+    let frames = out.len() / channels;
+    let (block, rest) = out.split_at_mut(frames * channels);
+    rest.fill(0);
+    synth.render(&mut block[..frames]);
+    if channels > 1 {
+        // Backwards, since every frame starts at or after its mono sample
+        for n in (0..frames).rev() {
+            let x = block[n];
+            block[n * channels..(n + 1) * channels].fill(x);
+        }
+    }
+}
+
+/// Fills the interleaved `out` with the next frames of a stereo `synth` on
+/// the first two `channels`. Further channels are 0 and a single channel
+/// gets the average of left and right. Works in place like [fill_buffer].
+pub fn fill_buffer_stereo<S: StereoSynth + ?Sized>(
+    synth: &mut S,
+    out: &mut [i16],
+    channels: usize,
+) {
+    let channels = channels.max(1);
+    if channels == 1 {
+        for y in out.iter_mut() {
+            *y = synth._next_frame().unwrap_or(Frame::mono(0)).to_mono().0;
+        }
+        return;
+    }
+    let frames = out.len() / channels;
+    let (block, rest) = out.split_at_mut(frames * channels);
+    rest.fill(0);
+    synth.render_interleaved(&mut block[..2 * frames]);
+    if channels > 2 {
+        for n in (0..frames).rev() {
+            let (left, right) = (block[2 * n], block[2 * n + 1]);
+            let frame = &mut block[n * channels..(n + 1) * channels];
+            frame.fill(0);
+            (frame[0], frame[1]) = (left, right);
+        }
+    }
+}
+
+/// Half of a [PingPong] buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Half {
+    First,
+    Second,
+}
+
+/// Double buffer for circular DMA transfers
+///
+/// Holds two halves of `N` interleaved samples each, contiguous in memory,
+/// without allocation. The DMA controller streams the whole buffer to the
+/// DAC in a loop and raises an interrupt after each half. The handler of
+/// the half transfer interrupt then refills the [Half::First] while the
+/// second half plays, and the handler of the transfer complete interrupt
+/// refills the [Half::Second]. A block of `N / channels` frames therefore
+/// has to be rendered within the playing time of one half.
+///
+/// ```
+/// use isopod::io::buffer::{Half, PingPong};
+/// use isopod::synth::fmpiano::FmPiano;
+/// use isopod::synth::Synth;
+///
+/// // 64 stereo frames per half
+/// static mut BUFFER: PingPong<128> = PingPong::new(2);
+///
+/// let mut piano = FmPiano::new();
+/// piano.note_on(60, 100);
+/// let buffer = unsafe { &mut *core::ptr::addr_of_mut!(BUFFER) };
+/// // Prefill before starting the DMA from buffer.as_slice().as_ptr()
+/// buffer.fill(Half::First, &mut piano);
+/// buffer.fill(Half::Second, &mut piano);
+/// // In the interrupt handlers
+/// buffer.fill(Half::First, &mut piano);
+/// assert_eq!(buffer.as_slice().len(), 256);
+/// ```
+pub struct PingPong<const N: usize> {
+    halves: [[i16; N]; 2],
+    channels: usize,
+}
+
+impl<const N: usize> PingPong<N> {
+    /// Returns a silent buffer for `channels` interleaved channels. `N`
+    /// should be a multiple of `channels`.
+    pub const fn new(channels: usize) -> Self {
+        Self {
+            halves: [[0; N]; 2],
+            channels: if channels == 0 { 1 } else { channels },
+        }
+    }
+
+    /// Renders the next block of a mono `synth` into `half`, see
+    /// [fill_buffer].
+    #[inline]
+    pub fn fill<S: Synth + ?Sized>(&mut self, half: Half, synth: &mut S) {
+        let channels = self.channels;
+        fill_buffer(synth, self.get_half_mut(half), channels);
+    }
+
+    /// Renders the next block of a stereo `synth` into `half`, see
+    /// [fill_buffer_stereo].
+    #[inline]
+    pub fn fill_stereo<S: StereoSynth + ?Sized>(&mut self, half: Half, synth: &mut S) {
+        let channels = self.channels;
+        fill_buffer_stereo(synth, self.get_half_mut(half), channels);
+    }
+
+    /// Returns `half` for writing samples directly.
+    pub fn get_half_mut(&mut self, half: Half) -> &mut [i16; N] {
+        &mut self.halves[half as usize]
+    }
+
+    /// Returns both halves as one slice, whose address and length are the
+    /// source of the DMA transfer.
+    pub fn as_slice(&self) -> &[i16] {
+        self.halves.as_flattened()
+    }
+
+    pub fn get_channels(&self) -> usize {
+        self.channels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::units::Hz;
+
+    struct Ramp(i16);
+
+    impl Synth for Ramp {
+        fn _next(&mut self) -> Option<i16> {
+            self.0 += 1;
+            Some(self.0)
+        }
+
+        fn get_sample_rate(&self) -> Hz {
+            Hz(48_000)
+        }
+
+        fn set_sample_rate(&mut self, _sample_rate: Hz) {}
+    }
+
+    impl StereoSynth for Ramp {
+        fn _next_frame(&mut self) -> Option<Frame> {
+            self.0 += 1;
+            Some(Frame::new(self.0, -2 * self.0))
+        }
+
+        fn get_sample_rate(&self) -> Hz {
+            Hz(48_000)
+        }
+
+        fn set_sample_rate(&mut self, _sample_rate: Hz) {}
+    }
+
+    #[test]
+    fn test_fill_buffer() {
+        let mut synth = Ramp(0);
+        let mut out = [9; 7];
+        fill_buffer(&mut synth, &mut out, 3);
+        assert_eq!(out, [1, 1, 1, 2, 2, 2, 0]);
+        fill_buffer(&mut synth, &mut out, 1);
+        assert_eq!(out, [3, 4, 5, 6, 7, 8, 9]);
+
+        let mut out = [9; 7];
+        fill_buffer_stereo(&mut synth, &mut out, 3);
+        assert_eq!(out, [10, -20, 0, 11, -22, 0, 0]);
+        fill_buffer_stereo(&mut synth, &mut out[..4], 2);
+        assert_eq!(out[..4], [12, -24, 13, -26]);
+        fill_buffer_stereo(&mut synth, &mut out[..2], 1);
+        assert_eq!(out[..2], [-7, -7]);
+    }
+
+    #[test]
+    fn test_ping_pong() {
+        let mut synth = Ramp(0);
+        let mut buffer = PingPong::<4>::new(2);
+        buffer.fill(Half::Second, &mut synth);
+        buffer.fill(Half::First, &mut synth);
+        assert_eq!(buffer.as_slice(), [3, 3, 4, 4, 1, 1, 2, 2]);
+        buffer.fill_stereo(Half::Second, &mut synth);
+        assert_eq!(buffer.as_slice()[4..], [5, -10, 6, -12]);
+    }
+}
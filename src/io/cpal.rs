@@ -0,0 +1,183 @@
+// Low latency playback of synths through a cpal output stream.
+
+use crate::io::buffer::{fill_buffer, fill_buffer_stereo};
+use crate::synth::{StereoSynth, Synth};
+use crate::util::units::Hz;
+use ::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use ::cpal::{BufferSize, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+
+/// Samples reserved for the scratch buffer of the callback if the device
+/// doesn't fix its block size
+const DEFAULT_CAPACITY: usize = 4_096;
+
+/// Reason why an output stream couldn't be started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpalError {
+    /// No output device is available.
+    NoDevice,
+    /// The device has no usable stream configuration.
+    Config,
+    /// The sample format of the device isn't supported.
+    Format,
+    /// The stream couldn't be built or started.
+    Stream,
+}
+
+/// Playback of a synth on the default output device
+///
+/// The synth is moved into the audio callback of cpal and rendered block by
+/// block with [Synth::render], so it works with any synth rather than only
+/// the types with a rodio source. The sample rate of the synth is set to the
+/// one of the device. Parameters can be changed while playing through
+/// parameter handles or a MIDI input inside the synth. Mono synths are
+/// copied to all channels. Playback stops when the output is dropped.
+///
+/// ```no_run
+/// use isopod::io::cpal::CpalOutput;
+/// use isopod::synth::fmpiano::FmPiano;
+/// use isopod::synth::Synth;
+///
+/// let mut piano = FmPiano::new();
+/// piano.note_on(60, 100);
+/// // Blocks of 128 frames keep the latency low
+/// let output = CpalOutput::play(piano, Some(128)).unwrap();
+/// std::thread::sleep(std::time::Duration::from_secs(1));
+/// drop(output);
+/// ```
+pub struct CpalOutput {
+    stream: Stream,
+    sample_rate: Hz,
+    channels: u16,
+}
+
+impl CpalOutput {
+    /// Plays `synth` with blocks of `buffer_frames` frames, or with the
+    /// default block size of the device.
+    pub fn play<S: Synth + Send + 'static>(
+        mut synth: S,
+        buffer_frames: Option<u32>,
+    ) -> Result<Self, CpalError> {
+        Self::start(
+            buffer_frames,
+            move |sample_rate| {
+                synth.set_sample_rate(sample_rate);
+                synth
+            },
+            fill_buffer,
+        )
+    }
+
+    /// Plays a stereo `synth` on the first two channels. Mono devices get
+    /// the average of both channels.
+    pub fn play_stereo<S: StereoSynth + Send + 'static>(
+        mut synth: S,
+        buffer_frames: Option<u32>,
+    ) -> Result<Self, CpalError> {
+        Self::start(
+            buffer_frames,
+            move |sample_rate| {
+                synth.set_sample_rate(sample_rate);
+                synth
+            },
+            fill_buffer_stereo,
+        )
+    }
+
+    /// Opens the default device, lets `init` set the sample rate of the
+    /// synth it holds and starts a stream calling `fill` with the
+    /// interleaved block and the number of channels.
+    fn start<S, I, F>(buffer_frames: Option<u32>, init: I, fill: F) -> Result<Self, CpalError>
+    where
+        S: Send + 'static,
+        I: FnOnce(Hz) -> S,
+        F: FnMut(&mut S, &mut [i16], usize) + Send + 'static,
+    {
+        let device = ::cpal::default_host()
+            .default_output_device()
+            .ok_or(CpalError::NoDevice)?;
+        let supported = device
+            .default_output_config()
+            .map_err(|_| CpalError::Config)?;
+        let format = supported.sample_format();
+        let mut config = supported.config();
+        if let Some(frames) = buffer_frames {
+            config.buffer_size = BufferSize::Fixed(frames);
+        }
+        let sample_rate = Hz(config.sample_rate.0);
+        let channels = config.channels;
+        let synth = init(sample_rate);
+        let stream = match format {
+            SampleFormat::I8 => build::<i8, S, F>(&device, &config, synth, fill),
+            SampleFormat::I16 => build::<i16, S, F>(&device, &config, synth, fill),
+            SampleFormat::I32 => build::<i32, S, F>(&device, &config, synth, fill),
+            SampleFormat::U8 => build::<u8, S, F>(&device, &config, synth, fill),
+            SampleFormat::U16 => build::<u16, S, F>(&device, &config, synth, fill),
+            SampleFormat::F32 => build::<f32, S, F>(&device, &config, synth, fill),
+            SampleFormat::F64 => build::<f64, S, F>(&device, &config, synth, fill),
+            _ => Err(CpalError::Format),
+        }?;
+        stream.play().map_err(|_| CpalError::Stream)?;
+        Ok(Self {
+            stream,
+            sample_rate,
+            channels,
+        })
+    }
+
+    /// Pauses the stream, if the device supports it.
+    pub fn pause(&self) -> Result<(), CpalError> {
+        self.stream.pause().map_err(|_| CpalError::Stream)
+    }
+
+    /// Resumes a paused stream.
+    pub fn resume(&self) -> Result<(), CpalError> {
+        self.stream.play().map_err(|_| CpalError::Stream)
+    }
+
+    /// Returns the sample rate of the device.
+    pub fn get_sample_rate(&self) -> Hz {
+        self.sample_rate
+    }
+
+    /// Returns the number of channels of the device.
+    pub fn get_channels(&self) -> u16 {
+        self.channels
+    }
+}
+
+/// Builds a stream of samples of type `T`, which are converted from an i16
+/// scratch block filled by `fill`.
+fn build<T, S, F>(
+    device: &::cpal::Device,
+    config: &StreamConfig,
+    mut synth: S,
+    mut fill: F,
+) -> Result<Stream, CpalError>
+where
+    T: SizedSample + FromSample<i16>,
+    S: Send + 'static,
+    F: FnMut(&mut S, &mut [i16], usize) + Send + 'static,
+{
+    let channels = config.channels.max(1) as usize;
+    let capacity = match config.buffer_size {
+        BufferSize::Fixed(frames) => frames as usize * channels,
+        BufferSize::Default => DEFAULT_CAPACITY,
+    };
+    let mut scratch = Vec::with_capacity(capacity);
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                // Allocates only if the device exceeds the reserved block
+                scratch.resize(data.len(), 0);
+                fill(&mut synth, &mut scratch, channels);
+                for (y, x) in data.iter_mut().zip(&scratch) {
+                    *y = T::from_sample(*x);
+                }
+            },
+            // Underruns can't be fixed from within the callback
+            |_| {},
+            None,
+        )
+        .map_err(|_| CpalError::Stream)
+}
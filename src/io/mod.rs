@@ -0,0 +1,12 @@
+// Audio output to devices, plugin hosts and files.
+
+pub mod buffer;
+#[cfg(feature = "cpal")]
+pub mod cpal;
+#[cfg(feature = "plugin")]
+pub mod plugin;
+#[cfg(feature = "std")]
+pub mod recorder;
+#[cfg(feature = "std")]
+pub mod wav;
+pub mod web;
@@ -0,0 +1,387 @@
+// Host independent core of an instrument plugin, which plays voices and an
+// effect chain from the parameters and note events of a host.
+//
+// This module doesn't depend on a plugin framework and doesn't export a
+// CLAP or VST3 plugin itself. A wrapper crate implements the plugin traits
+// of its framework and only forwards to this module: it hands the
+// normalized parameter values of the host to [PluginParams] and calls
+// [PluginInstrument::process] with the note events and the channel buffers
+// of each block.
+
+use crate::fx::Effect;
+use crate::synth::voice::{Voice, VoiceAllocator};
+use crate::synth::{NoteEvent, Synth};
+use crate::util::mapping::{Curve, MapValue};
+use crate::util::param::ParamHandle;
+use crate::util::units::{Frequency, Hz, SAMPLE_NORM};
+
+/// Samples rendered at once between note events
+const BLOCK: usize = 64;
+
+/// Parameter of a plugin with its range and law
+struct PluginParam {
+    name: String,
+    lo: i64,
+    hi: i64,
+    curve: Curve,
+    default: f32,
+    normalized: f32,
+    set: Box<dyn Fn(i64) + Send + Sync>,
+}
+
+/// Parameters of a plugin as the host sees them
+///
+/// Hosts automate parameters as normalized values from 0 to 1. Each
+/// registered parameter maps them onto its range with a [Curve] and sets
+/// its [ParamHandle], so the audio side polls the values like any other
+/// [crate::util::param::Param]. Parameters are numbered in the order of
+/// registration, which is also the order in which the host lists them.
+///
+/// ```
+/// use isopod::io::plugin::PluginParams;
+/// use isopod::util::mapping::Curve;
+/// use isopod::util::param::param;
+/// use isopod::util::units::mHz;
+///
+/// let (mut cutoff, handle) = param(mHz(1_000_000));
+/// let mut params = PluginParams::new();
+/// params.register("Cutoff", mHz(20_000), mHz(20_000_000), Curve::Exponential, handle);
+///
+/// params.set_normalized(0, 1.0);
+/// assert_eq!(cutoff.poll(), Some(mHz(20_000_000)));
+/// assert_eq!(params.get_name(0), Some("Cutoff"));
+/// ```
+pub struct PluginParams {
+    params: Vec<PluginParam>,
+}
+
+impl PluginParams {
+    pub fn new() -> Self {
+        Self { params: Vec::new() }
+    }
+
+    /// Registers a parameter named `name` ranging from `lo` to `hi` with
+    /// the law `curve`. The current value of `handle` becomes the default.
+    pub fn register<T: MapValue + Send + Sync + 'static>(
+        &mut self,
+        name: &str,
+        lo: T,
+        hi: T,
+        curve: Curve,
+        handle: ParamHandle<T>,
+    ) {
+        let (lo, hi) = (lo.to_i64(), hi.to_i64());
+        let default = normalize(curve, handle.get().to_i64(), lo, hi);
+        self.params.push(PluginParam {
+            name: name.into(),
+            lo,
+            hi,
+            curve,
+            default,
+            normalized: default,
+            set: Box::new(move |x| handle.set(T::from_i64(x))),
+        });
+    }
+
+    /// Returns the number of registered parameters.
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// True if no parameters are registered.
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    /// Returns the name of parameter `index`.
+    pub fn get_name(&self, index: usize) -> Option<&str> {
+        self.params.get(index).map(|p| p.name.as_str())
+    }
+
+    /// Returns the normalized default of parameter `index`.
+    pub fn get_default(&self, index: usize) -> Option<f32> {
+        self.params.get(index).map(|p| p.default)
+    }
+
+    /// Returns the last normalized value of parameter `index`.
+    pub fn get_normalized(&self, index: usize) -> Option<f32> {
+        self.params.get(index).map(|p| p.normalized)
+    }
+
+    /// Sets parameter `index` from a normalized value between 0 and 1.
+    /// Unchanged values are skipped, so hosts can pass all values on every
+    /// block. Unknown indices are ignored.
+    pub fn set_normalized(&mut self, index: usize, normalized: f32) {
+        let Some(param) = self.params.get_mut(index) else {
+            return;
+        };
+        let normalized = normalized.clamp(0.0, 1.0);
+        if normalized == param.normalized {
+            return;
+        }
+        param.normalized = normalized;
+        let x = (normalized * SAMPLE_NORM as f32) as i64;
+        (param.set)(param.curve.map(x, SAMPLE_NORM as i64, param.lo, param.hi));
+    }
+}
+
+impl Default for PluginParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the normalized control position of `value`. The laws are
+/// monotonic, so the position is found by bisection.
+fn normalize(curve: Curve, value: i64, lo: i64, hi: i64) -> f32 {
+    let norm = SAMPLE_NORM as i64;
+    let rising = hi >= lo;
+    let (mut a, mut b) = (0, norm);
+    while a < b {
+        let x = (a + b) / 2;
+        let y = curve.map(x, norm, lo, hi);
+        if (y < value) == rising && y != value {
+            a = x + 1;
+        } else {
+            b = x;
+        }
+    }
+    a as f32 / norm as f32
+}
+
+/// Function applying parameter changes to the voices
+type Update<V, const N: usize> = Box<dyn FnMut(&mut [V; N]) + Send>;
+
+/// Voices with an effect chain as a plugin instrument
+///
+/// Renders blocks into the channel buffers of the host, with the note
+/// events applied at their sample offsets within the block. All channels
+/// get the same mono signal. Parameter changes are applied once per block
+/// by the update function, which polls the parameters registered in
+/// [PluginParams].
+///
+/// ```
+/// use isopod::io::plugin::PluginInstrument;
+/// use isopod::synth::subtractive::SubtractiveVoice;
+/// use isopod::synth::NoteEvent;
+/// use isopod::util::param::param;
+/// use isopod::util::units::{mHz, Hz};
+///
+/// let (mut cutoff, handle) = param(mHz(2_000_000));
+/// let voices = core::array::from_fn::<_, 4, _>(|_| SubtractiveVoice::new());
+/// let mut instrument = PluginInstrument::new(voices);
+/// instrument.set_update(move |voices| {
+///     if let Some(mfreq) = cutoff.poll() {
+///         voices.iter_mut().for_each(|v| v.set_cutoff(mfreq));
+///     }
+/// });
+/// instrument.set_sample_rate(Hz(48_000));
+///
+/// // From the host, e.g. through PluginParams
+/// handle.set(mHz(1_000_000));
+/// let (mut left, mut right) = ([0.0; 256], [0.0; 256]);
+/// let events = [(128, NoteEvent::On { note: 60, velocity: 100 })];
+/// instrument.process(&events, &mut [&mut left, &mut right]);
+/// assert!(left[..128].iter().all(|x| *x == 0.0));
+/// assert!(left[128..].iter().any(|x| *x != 0.0));
+/// assert_eq!(left, right);
+/// ```
+pub struct PluginInstrument<V: Voice + Synth, const N: usize> {
+    voices: VoiceAllocator<V, N>,
+    effects: Vec<Box<dyn Effect + Send>>,
+    update: Option<Update<V, N>>,
+}
+
+impl<V: Voice + Synth, const N: usize> PluginInstrument<V, N> {
+    pub fn new(voices: [V; N]) -> Self {
+        Self {
+            voices: VoiceAllocator::new(voices),
+            effects: Vec::new(),
+            update: None,
+        }
+    }
+
+    /// Appends `effect` to the end of the effect chain. It keeps its sample
+    /// rate until the next [PluginInstrument::set_sample_rate].
+    pub fn add_effect(&mut self, effect: Box<dyn Effect + Send>) {
+        self.effects.push(effect);
+    }
+
+    /// Sets the function that applies parameter changes to the voices at
+    /// the start of each block.
+    pub fn set_update(&mut self, update: impl FnMut(&mut [V; N]) + Send + 'static) {
+        self.update = Some(Box::new(update));
+    }
+
+    /// Sets the sample rate of all voices and effects, e.g. when the host
+    /// activates the plugin.
+    pub fn set_sample_rate(&mut self, sample_rate: Hz) {
+        for voice in self.voices.get_voices_mut() {
+            voice.set_sample_rate(sample_rate);
+        }
+        for effect in self.effects.iter_mut() {
+            effect.set_msample_rate(sample_rate.to_mHz());
+        }
+    }
+
+    /// Releases all notes, e.g. when the host resets the plugin.
+    pub fn reset(&mut self) {
+        self.voices.all_notes_off();
+    }
+
+    /// Returns the voice allocator, e.g. for pitch bends.
+    pub fn get_voices_mut(&mut self) -> &mut VoiceAllocator<V, N> {
+        &mut self.voices
+    }
+
+    /// Fills the channel buffers with the next block. `events` holds note
+    /// events with their sample offset in the block, sorted by the offset
+    /// as hosts deliver them. Events at or past the end of the block apply
+    /// at its end.
+    pub fn process(&mut self, events: &[(u32, NoteEvent)], channels: &mut [&mut [f32]]) {
+        if let Some(update) = &mut self.update {
+            update(self.voices.get_voices_mut());
+        }
+        let len = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        let mut events = events.iter().peekable();
+        let mut buf = [0_i16; BLOCK];
+        let mut start = 0;
+        while start < len {
+            while let Some((_, event)) = events.next_if(|(t, _)| *t as usize <= start) {
+                self.voices.handle_event(*event);
+            }
+            let end = match events.peek() {
+                Some((t, _)) => (*t as usize).min(len),
+                None => len,
+            }
+            .min(start + BLOCK);
+            let buf = &mut buf[..end - start];
+            self.voices.render(buf);
+            for x in buf.iter_mut() {
+                for effect in self.effects.iter_mut() {
+                    *x = effect.process(*x);
+                }
+            }
+            for channel in channels.iter_mut() {
+                for (y, x) in channel[start..end].iter_mut().zip(buf.iter()) {
+                    *y = *x as f32 / SAMPLE_NORM as f32;
+                }
+            }
+            start = end;
+        }
+        for (_, event) in events {
+            self.voices.handle_event(*event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::fmpiano::FmPiano;
+    use crate::util::param::param;
+    use crate::util::units::{mHz, ms};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    struct Invert;
+
+    impl Effect for Invert {
+        fn process(&mut self, input: i16) -> i16 {
+            input.saturating_neg()
+        }
+    }
+
+    /// Records the sample rate it was given
+    struct Rate(Arc<AtomicU32>);
+
+    impl Effect for Rate {
+        fn process(&mut self, input: i16) -> i16 {
+            input
+        }
+
+        fn set_msample_rate(&mut self, msample_rate: mHz) {
+            self.0.store(msample_rate.0, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_plugin_params() {
+        let (mut attack, handle) = param(ms(100));
+        let mut params = PluginParams::new();
+        params.register("Attack", ms(0), ms(10_000), Curve::Logarithmic, handle);
+        assert_eq!(params.len(), 1);
+
+        // The default maps back onto the initial value
+        let default = params.get_default(0).unwrap();
+        params.set_normalized(0, default + 1.0 / SAMPLE_NORM as f32);
+        let value = attack.poll().unwrap();
+        assert!((100..=101).contains(&value.0), "{:?}", value);
+
+        params.set_normalized(0, 2.0);
+        assert_eq!(attack.poll(), Some(ms(10_000)));
+        assert_eq!(params.get_normalized(0), Some(1.0));
+        // Unchanged values and unknown parameters are skipped
+        params.set_normalized(0, 1.0);
+        params.set_normalized(1, 0.5);
+        assert_eq!(attack.poll(), None);
+    }
+
+    #[test]
+    fn test_plugin_instrument() {
+        let render = |events: &[(u32, NoteEvent)], block: usize, invert: bool| {
+            let mut instrument = PluginInstrument::new([FmPiano::new(), FmPiano::new()]);
+            instrument.set_sample_rate(Hz(44_100));
+            if invert {
+                instrument.add_effect(Box::new(Invert));
+            }
+            let mut out = vec![0.0; 1_000];
+            for (n, chunk) in out.chunks_mut(block).enumerate() {
+                let offset = (n * block) as u32;
+                let events: Vec<_> = events
+                    .iter()
+                    .filter(|(t, _)| (offset..offset + block as u32).contains(t))
+                    .map(|(t, e)| (t - offset, *e))
+                    .collect();
+                instrument.process(&events, &mut [chunk]);
+            }
+            out
+        };
+        let events = [
+            (
+                10,
+                NoteEvent::On {
+                    note: 60,
+                    velocity: 100,
+                },
+            ),
+            (
+                300,
+                NoteEvent::On {
+                    note: 67,
+                    velocity: 100,
+                },
+            ),
+        ];
+        // Events are sample accurate, whatever the block size of the host
+        let a = render(&events, 1_000, false);
+        assert_eq!(a, render(&events, 37, false));
+        assert!(a[..10].iter().all(|x| *x == 0.0));
+        assert!(a[10..].iter().any(|x| *x != 0.0));
+        assert!(a.iter().all(|x| (-1.0..1.0).contains(x)));
+
+        // The effect chain processes the sum of the voices
+        let b = render(&events, 64, true);
+        assert!(a.iter().zip(b.iter()).all(|(a, b)| *a == -*b));
+    }
+
+    #[test]
+    fn test_plugin_sample_rate() {
+        let rate = Arc::new(AtomicU32::new(0));
+        let mut instrument = PluginInstrument::new([FmPiano::new()]);
+        instrument.add_effect(Box::new(Invert));
+        instrument.add_effect(Box::new(Rate(rate.clone())));
+        instrument.set_sample_rate(Hz(96_000));
+        assert_eq!(rate.load(Ordering::Relaxed), 96_000_000);
+    }
+}
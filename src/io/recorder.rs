@@ -0,0 +1,233 @@
+// Recording of the output of a synth while it plays, e.g. to capture live
+// sessions through rodio or cpal.
+
+use crate::io::wav::{WavSpec, WavWriter};
+use crate::synth::Synth;
+use crate::util::units::Hz;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Last samples of a recording, overwriting the oldest ones
+struct Ring {
+    samples: Vec<i16>,
+    pos: usize,
+    full: bool,
+}
+
+impl Ring {
+    fn write(&mut self, samples: &[i16]) {
+        let len = self.samples.len();
+        if len == 0 {
+            return;
+        }
+        // Only the last `len` samples survive
+        let samples = &samples[samples.len().saturating_sub(len)..];
+        for x in samples {
+            self.samples[self.pos] = *x;
+            self.pos += 1;
+            if self.pos == len {
+                (self.pos, self.full) = (0, true);
+            }
+        }
+    }
+
+    fn to_vec(&self) -> Vec<i16> {
+        if self.full {
+            [&self.samples[self.pos..], &self.samples[..self.pos]].concat()
+        } else {
+            self.samples[..self.pos].to_vec()
+        }
+    }
+}
+
+/// Synth that records the samples passing through it
+///
+/// Wraps any [Synth] and returns its samples unchanged, while writing them
+/// to a WAV file, to a ring buffer of the last samples, or both. Note
+/// events and the sample rate are forwarded to the wrapped synth, so the
+/// recorder takes the place of the synth, e.g. in a `MonoSource` or in
+/// `CpalOutput::play`.
+///
+/// The WAV file is written through a buffer, i.e. with a system call every
+/// few thousand samples. Where that is too slow for the audio thread, the
+/// ring buffer keeps the last seconds in memory instead.
+///
+/// ```
+/// use isopod::io::recorder::Recorder;
+/// use isopod::synth::fmpiano::FmPiano;
+/// use isopod::synth::Synth;
+///
+/// let mut recorder = Recorder::new(FmPiano::new());
+/// recorder.record_to_ring(44_100);
+/// recorder.note_on(60, 100);
+/// let mut out = [0; 256];
+/// recorder.render(&mut out);
+/// assert_eq!(recorder.get_recording(), out);
+/// ```
+pub struct Recorder<S: Synth> {
+    synth: S,
+    wav: Option<WavWriter<BufWriter<File>>>,
+    error: Option<std::io::Error>,
+    ring: Option<Ring>,
+}
+
+impl<S: Synth> Recorder<S> {
+    pub fn new(synth: S) -> Self {
+        Self {
+            synth,
+            wav: None,
+            error: None,
+            ring: None,
+        }
+    }
+
+    /// Starts recording to a mono 16-bit WAV file at `path` with the sample
+    /// rate of the synth, after finishing the file of a previous recording.
+    pub fn record_to_wav<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<()> {
+        self.stop_wav()?;
+        let spec = WavSpec::mono(self.synth.get_sample_rate());
+        self.wav = Some(WavWriter::create(path, spec)?);
+        Ok(())
+    }
+
+    /// Starts recording the last `len` samples, discarding the previous
+    /// ones.
+    pub fn record_to_ring(&mut self, len: usize) {
+        self.ring = Some(Ring {
+            samples: vec![0; len],
+            pos: 0,
+            full: false,
+        });
+    }
+
+    /// Finishes the WAV file. Returns the first error while writing it, in
+    /// which case the recording stopped at the error.
+    pub fn stop_wav(&mut self) -> std::io::Result<()> {
+        let wav = self.wav.take();
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        if let Some(wav) = wav {
+            wav.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Stops recording to the ring buffer and frees it.
+    pub fn stop_ring(&mut self) {
+        self.ring = None;
+    }
+
+    /// True while recording to a WAV file.
+    pub fn is_recording_wav(&self) -> bool {
+        self.wav.is_some()
+    }
+
+    /// Returns the samples in the ring buffer, oldest first.
+    pub fn get_recording(&self) -> Vec<i16> {
+        self.ring.as_ref().map(Ring::to_vec).unwrap_or_default()
+    }
+
+    pub fn get_synth_mut(&mut self) -> &mut S {
+        &mut self.synth
+    }
+
+    /// Returns the wrapped synth. The WAV file isn't finished, so call
+    /// [Self::stop_wav] first.
+    pub fn into_inner(self) -> S {
+        self.synth
+    }
+
+    fn record(&mut self, samples: &[i16]) {
+        if let Some(wav) = &mut self.wav {
+            if let Err(error) = wav.write(samples) {
+                self.wav = None;
+                self.error = Some(error);
+            }
+        }
+        if let Some(ring) = &mut self.ring {
+            ring.write(samples);
+        }
+    }
+}
+
+impl<S: Synth> Synth for Recorder<S> {
+    fn _next(&mut self) -> Option<i16> {
+        let x = self.synth._next();
+        self.record(&[x.unwrap_or(0)]);
+        x
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        self.synth.render(out);
+        self.record(out);
+    }
+
+    fn note_on(&mut self, note: u8, velocity: u8) {
+        self.synth.note_on(note, velocity);
+    }
+
+    fn note_off(&mut self, note: u8) {
+        self.synth.note_off(note);
+    }
+
+    fn get_sample_rate(&self) -> Hz {
+        self.synth.get_sample_rate()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: Hz) {
+        self.synth.set_sample_rate(sample_rate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::wav::load_wav;
+    use crate::synth::fmpiano::FmPiano;
+
+    #[test]
+    fn test_recorder_ring() {
+        let mut ring = Ring {
+            samples: vec![0; 4],
+            pos: 0,
+            full: false,
+        };
+        ring.write(&[1, 2, 3]);
+        assert_eq!(ring.to_vec(), [1, 2, 3]);
+        ring.write(&[4, 5]);
+        assert_eq!(ring.to_vec(), [2, 3, 4, 5]);
+        ring.write(&[6, 7, 8, 9, 10, 11]);
+        assert_eq!(ring.to_vec(), [8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn test_recorder_wav() {
+        let path = std::env::temp_dir().join("isopod_test_recorder.wav");
+        let mut recorder = Recorder::new(FmPiano::new());
+        recorder.set_sample_rate(Hz(22_050));
+        recorder.record_to_wav(&path).unwrap();
+        recorder.record_to_ring(100);
+        recorder.note_on(57, 90);
+
+        // The samples pass through unchanged
+        let mut reference = FmPiano::new();
+        reference.set_sample_rate(Hz(22_050));
+        reference.note_on(57, 90);
+        let mut expected = vec![0; 1_000];
+        reference.render(&mut expected);
+        let mut out = vec![0; 1_000];
+        recorder.render(&mut out[..999]);
+        out[999] = recorder._next().unwrap();
+        assert_eq!(out, expected);
+        assert_eq!(recorder.get_recording(), out[900..]);
+
+        recorder.stop_wav().unwrap();
+        assert!(!recorder.is_recording_wav());
+        let (samples, sample_rate) = load_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(sample_rate, Hz(22_050));
+        assert_eq!(samples, out);
+    }
+}
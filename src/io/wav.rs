@@ -0,0 +1,611 @@
+// WAV files of interleaved i16 samples.
+
+use crate::osc::wavetable::fit_cycle;
+use crate::synth::{StereoSynth, Synth};
+use crate::util::format::SampleFormat;
+use crate::util::units::{Frame, Hz};
+use core::time::Duration;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Length of the header written by [WavWriter]
+const HEADER_LEN: usize = 44;
+/// Samples rendered and encoded per block
+const BLOCK: usize = 512;
+/// Length of the longest format chunk, which is the extensible format
+const FMT_MAX: usize = 40;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+/// Format tag whose actual format follows at the start of a GUID
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Reason why a WAV file couldn't be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavError {
+    /// Reading failed or the file ended early.
+    Io(std::io::ErrorKind),
+    /// The file isn't a WAV file or a chunk is missing.
+    Format,
+    /// The sample format, e.g. 64-bit floats or compressed data, isn't
+    /// supported.
+    Unsupported,
+}
+
+impl From<std::io::Error> for WavError {
+    fn from(error: std::io::Error) -> Self {
+        WavError::Io(error.kind())
+    }
+}
+
+/// Format of a WAV file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    pub channels: u16,
+    pub sample_rate: Hz,
+    pub format: SampleFormat,
+}
+
+impl WavSpec {
+    /// 16-bit mono at `sample_rate`
+    pub fn mono(sample_rate: Hz) -> Self {
+        Self {
+            channels: 1,
+            sample_rate,
+            format: SampleFormat::I16,
+        }
+    }
+
+    /// 16-bit stereo at `sample_rate`
+    pub fn stereo(sample_rate: Hz) -> Self {
+        Self {
+            channels: 2,
+            ..Self::mono(sample_rate)
+        }
+    }
+
+    /// Returns the header for `data_len` bytes of samples. Fails if the
+    /// frames or the byte rate are too large for the header.
+    fn header(&self, data_len: u32) -> std::io::Result<[u8; HEADER_LEN]> {
+        let tag = match self.format {
+            SampleFormat::F32 => FORMAT_FLOAT,
+            _ => FORMAT_PCM,
+        };
+        let block_align = u16::try_from(self.channels as u32 * self.format.bytes() as u32);
+        let block_align = block_align.ok().filter(|align| *align > 0);
+        let byte_rate = block_align.and_then(|align| self.sample_rate.0.checked_mul(align as u32));
+        let (Some(block_align), Some(byte_rate)) = (block_align, byte_rate) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "unsupported number of channels or sample rate",
+            ));
+        };
+        // Including the padding of odd data lengths
+        let riff_len = data_len.saturating_add(HEADER_LEN as u32 - 8 + data_len % 2);
+        let mut header = [0; HEADER_LEN];
+        let fields: [&[u8]; 13] = [
+            b"RIFF",
+            &riff_len.to_le_bytes(),
+            b"WAVE",
+            b"fmt ",
+            &16_u32.to_le_bytes(),
+            &tag.to_le_bytes(),
+            &self.channels.to_le_bytes(),
+            &self.sample_rate.0.to_le_bytes(),
+            &byte_rate.to_le_bytes(),
+            &block_align.to_le_bytes(),
+            &self.format.bits().to_le_bytes(),
+            b"data",
+            &data_len.to_le_bytes(),
+        ];
+        let mut n = 0;
+        for field in fields {
+            header[n..n + field.len()].copy_from_slice(field);
+            n += field.len();
+        }
+        Ok(header)
+    }
+}
+
+/// Writer of WAV files
+///
+/// Takes interleaved i16 samples and stores them in the format of the
+/// [WavSpec], e.g. 16-bit integers or 32-bit floats. The sizes in the header
+/// are only known at the end, so the writer has to be closed with
+/// [WavWriter::finish]. Files are limited to 4 GiB of samples.
+///
+/// ```
+/// use isopod::io::wav::{WavSpec, WavWriter};
+/// use isopod::util::units::Hz;
+/// use std::io::Cursor;
+///
+/// let mut writer = WavWriter::new(Cursor::new(Vec::new()), WavSpec::mono(Hz(8_000))).unwrap();
+/// writer.write(&[0, 1_000, -1_000]).unwrap();
+/// let bytes = writer.finish().unwrap().into_inner();
+/// assert_eq!(bytes.len(), 44 + 3 * 2);
+/// assert_eq!(&bytes[..4], b"RIFF");
+/// ```
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    spec: WavSpec,
+    data_len: u32,
+    scratch: [u8; BLOCK * 4],
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// Creates or truncates the file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P, spec: WavSpec) -> std::io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), spec)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Writes a preliminary header to `writer`.
+    pub fn new(mut writer: W, spec: WavSpec) -> std::io::Result<Self> {
+        writer.write_all(&spec.header(0)?)?;
+        Ok(Self {
+            writer,
+            spec,
+            data_len: 0,
+            scratch: [0; BLOCK * 4],
+        })
+    }
+
+    /// Appends interleaved `samples`.
+    pub fn write(&mut self, samples: &[i16]) -> std::io::Result<()> {
+        let bytes = self.spec.format.bytes();
+        for block in samples.chunks(BLOCK) {
+            let n = self.spec.format.encode(block, &mut self.scratch) * bytes;
+            self.writer.write_all(&self.scratch[..n])?;
+            self.data_len = self.data_len.saturating_add(n as u32);
+        }
+        Ok(())
+    }
+
+    /// Appends stereo `frames`. The spec should have two channels.
+    pub fn write_frames(&mut self, frames: &[Frame]) -> std::io::Result<()> {
+        let mut samples = [0; BLOCK];
+        for block in frames.chunks(BLOCK / 2) {
+            let n = Frame::interleave(block, &mut samples);
+            self.write(&samples[..2 * n])?;
+        }
+        Ok(())
+    }
+
+    /// Completes the header and returns the underlying writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        // Chunks have an even length
+        if self.data_len % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&self.spec.header(self.data_len)?)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    pub fn get_spec(&self) -> WavSpec {
+        self.spec
+    }
+
+    /// Returns the number of frames written so far.
+    pub fn get_frames(&self) -> u32 {
+        self.data_len / (self.spec.channels as u32 * self.spec.format.bytes() as u32).max(1)
+    }
+}
+
+/// Returns the length of a chunk of `len` bytes including its padding.
+fn padded(len: u32) -> u64 {
+    len as u64 + len as u64 % 2
+}
+
+/// Reader of WAV files
+///
+/// Reads 8, 16, 24 and 32-bit integer and 32-bit float files as interleaved
+/// i16 samples, converted with rounding. Chunks other than the format and
+/// the samples, e.g. metadata, are skipped.
+///
+/// ```
+/// use isopod::io::wav::{WavReader, WavSpec, WavWriter};
+/// use isopod::util::units::Hz;
+/// use std::io::Cursor;
+///
+/// let mut writer = WavWriter::new(Cursor::new(Vec::new()), WavSpec::mono(Hz(8_000))).unwrap();
+/// writer.write(&[0, 1_000, -1_000]).unwrap();
+/// let bytes = writer.finish().unwrap().into_inner();
+///
+/// let mut reader = WavReader::new(Cursor::new(bytes)).unwrap();
+/// assert_eq!(reader.get_spec(), WavSpec::mono(Hz(8_000)));
+/// let mut out = [0; 8];
+/// assert_eq!(reader.read(&mut out).unwrap(), 3);
+/// assert_eq!(out[..3], [0, 1_000, -1_000]);
+/// ```
+pub struct WavReader<R: Read> {
+    reader: R,
+    spec: WavSpec,
+    remaining: u32,
+    scratch: [u8; BLOCK * 4],
+}
+
+impl WavReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, WavError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> WavReader<R> {
+    /// Reads the header up to the samples from `reader`.
+    pub fn new(mut reader: R) -> Result<Self, WavError> {
+        let mut riff = [0; 12];
+        reader.read_exact(&mut riff)?;
+        if &riff[..4] != b"RIFF" || &riff[8..] != b"WAVE" {
+            return Err(WavError::Format);
+        }
+        let mut spec = None;
+        loop {
+            let mut chunk = [0; 8];
+            reader.read_exact(&mut chunk)?;
+            let len = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+            match &chunk[..4] {
+                b"fmt " => {
+                    // Extensions beyond the longest known format are skipped
+                    let mut fmt = [0; FMT_MAX];
+                    let read = (len as usize).min(FMT_MAX);
+                    reader.read_exact(&mut fmt[..read])?;
+                    Self::skip(&mut reader, padded(len) - read as u64)?;
+                    spec = Some(Self::parse_fmt(&fmt[..read])?);
+                }
+                b"data" => {
+                    return Ok(Self {
+                        reader,
+                        spec: spec.ok_or(WavError::Format)?,
+                        remaining: len,
+                        scratch: [0; BLOCK * 4],
+                    });
+                }
+                _ => Self::skip(&mut reader, padded(len))?,
+            }
+        }
+    }
+
+    /// Skips `len` bytes of a chunk.
+    fn skip(reader: &mut R, len: u64) -> Result<(), WavError> {
+        if std::io::copy(&mut reader.take(len), &mut std::io::sink())? < len {
+            return Err(WavError::Format);
+        }
+        Ok(())
+    }
+
+    /// Parses the format chunk.
+    fn parse_fmt(fmt: &[u8]) -> Result<WavSpec, WavError> {
+        if fmt.len() < 16 {
+            return Err(WavError::Format);
+        }
+        let u16_at = |n: usize| u16::from_le_bytes([fmt[n], fmt[n + 1]]);
+        let mut tag = u16_at(0);
+        if tag == FORMAT_EXTENSIBLE && fmt.len() >= 26 {
+            tag = u16_at(24);
+        }
+        let format = match (tag, u16_at(14)) {
+            (FORMAT_PCM, 8) => SampleFormat::U8,
+            (FORMAT_PCM, 16) => SampleFormat::I16,
+            (FORMAT_PCM, 24) => SampleFormat::I24,
+            (FORMAT_PCM, 32) => SampleFormat::I32,
+            (FORMAT_FLOAT, 32) => SampleFormat::F32,
+            _ => return Err(WavError::Unsupported),
+        };
+        let channels = u16_at(2);
+        if channels == 0 {
+            return Err(WavError::Format);
+        }
+        Ok(WavSpec {
+            channels,
+            sample_rate: Hz(u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]])),
+            format,
+        })
+    }
+
+    /// Reads the next interleaved samples into `out` and returns their
+    /// number, which is 0 at the end of the file.
+    pub fn read(&mut self, out: &mut [i16]) -> Result<usize, WavError> {
+        let bytes = self.spec.format.bytes();
+        let mut n = 0;
+        for block in out.chunks_mut(BLOCK) {
+            let len = (block.len() * bytes).min(self.remaining as usize / bytes * bytes);
+            if len == 0 {
+                break;
+            }
+            self.reader.read_exact(&mut self.scratch[..len])?;
+            self.remaining -= len as u32;
+            n += self.spec.format.decode(&self.scratch[..len], block);
+        }
+        Ok(n)
+    }
+
+    /// Reads all remaining frames and mixes their channels to mono.
+    pub fn read_mono(&mut self) -> Result<Vec<i16>, WavError> {
+        let channels = self.spec.channels as usize;
+        let mut samples = Vec::with_capacity(self.get_frames() as usize);
+        // Whole frames, even if they are longer than a block
+        let mut block = vec![0; BLOCK.max(channels) / channels * channels];
+        loop {
+            let n = self.read(&mut block)?;
+            if n == 0 {
+                return Ok(samples);
+            }
+            samples.extend(block[..n].chunks_exact(channels).map(|frame| {
+                (frame.iter().map(|x| *x as i32).sum::<i32>() / channels as i32) as i16
+            }));
+        }
+    }
+
+    pub fn get_spec(&self) -> WavSpec {
+        self.spec
+    }
+
+    /// Returns the number of frames left to read.
+    pub fn get_frames(&self) -> u32 {
+        self.remaining / (self.spec.channels as u32 * self.spec.format.bytes() as u32)
+    }
+}
+
+/// Loads the WAV file at `path` into RAM, with its channels mixed to mono.
+/// Returns the samples and their sample rate, e.g. for playback by a
+/// [crate::fx::resample::Resampler] at the output sample rate.
+pub fn load_wav<P: AsRef<Path>>(path: P) -> Result<(Vec<i16>, Hz), WavError> {
+    let mut reader = WavReader::open(path)?;
+    Ok((reader.read_mono()?, reader.get_spec().sample_rate))
+}
+
+/// Loads a single cycle of a waveform from the WAV file at `path` as a
+/// wavetable of `len` entries, see [fit_cycle]. The table is leaked to give
+/// it the static lifetime of the tables of the [crate::osc::wavetable::Engine],
+/// so tables should be loaded once, e.g. at startup.
+/// ```no_run
+/// use isopod::io::wav::load_wavetable;
+/// use isopod::osc::wavetable::WavetableOscillator;
+///
+/// let mut osc = WavetableOscillator::new();
+/// osc.set_wavetable(load_wavetable("cycle.wav", 1_024).unwrap());
+/// ```
+pub fn load_wavetable<P: AsRef<Path>>(path: P, len: usize) -> Result<&'static [i16], WavError> {
+    let (cycle, _) = load_wav(path)?;
+    let mut table = vec![0; len];
+    fit_cycle(&cycle, &mut table);
+    Ok(table.leak())
+}
+
+/// Returns the number of frames of `duration` at `sample_rate`, rounded
+/// down.
+fn frames(duration: Duration, sample_rate: Hz) -> usize {
+    (duration.as_nanos() * sample_rate.0 as u128 / 1_000_000_000) as usize
+}
+
+/// Renders `duration` of `synth` into a 16-bit mono WAV file at the sample
+/// rate of the synth. The synth is rendered block by block without an audio
+/// device, so the same patch always gives the same file.
+/// ```no_run
+/// use isopod::io::wav::render_to_wav;
+/// use isopod::synth::fmpiano::FmPiano;
+/// use isopod::synth::Synth;
+/// use std::time::Duration;
+///
+/// let mut piano = FmPiano::new();
+/// piano.note_on(60, 100);
+/// render_to_wav(&mut piano, Duration::from_secs(2), "piano.wav").unwrap();
+/// ```
+pub fn render_to_wav<S: Synth + ?Sized, P: AsRef<Path>>(
+    synth: &mut S,
+    duration: Duration,
+    path: P,
+) -> std::io::Result<()> {
+    let sample_rate = synth.get_sample_rate();
+    let mut writer = WavWriter::create(path, WavSpec::mono(sample_rate))?;
+    let mut remaining = frames(duration, sample_rate);
+    let mut block = [0; BLOCK];
+    while remaining > 0 {
+        let n = remaining.min(BLOCK);
+        synth.render(&mut block[..n]);
+        writer.write(&block[..n])?;
+        remaining -= n;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Renders `duration` of a stereo `synth` into a 16-bit stereo WAV file,
+/// see [render_to_wav].
+pub fn render_to_wav_stereo<S: StereoSynth + ?Sized, P: AsRef<Path>>(
+    synth: &mut S,
+    duration: Duration,
+    path: P,
+) -> std::io::Result<()> {
+    let sample_rate = synth.get_sample_rate();
+    let mut writer = WavWriter::create(path, WavSpec::stereo(sample_rate))?;
+    let mut remaining = frames(duration, sample_rate);
+    let mut block = [0; BLOCK];
+    while remaining > 0 {
+        let n = remaining.min(BLOCK / 2);
+        synth.render_interleaved(&mut block[..2 * n]);
+        writer.write(&block[..2 * n])?;
+        remaining -= n;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::fmpiano::FmPiano;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_wav_header() {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: Hz(48_000),
+            format: SampleFormat::F32,
+        };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+        writer
+            .write_frames(&[Frame::new(16_384, -16_384); 1_000])
+            .unwrap();
+        assert_eq!(writer.get_frames(), 1_000);
+        let bytes = writer.finish().unwrap().into_inner();
+        let u16_at = |n: usize| u16::from_le_bytes([bytes[n], bytes[n + 1]]);
+        let u32_at = |n: usize| u32::from_le_bytes(bytes[n..n + 4].try_into().unwrap());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(4) as usize, bytes.len() - 8);
+        assert_eq!((u16_at(20), u16_at(22)), (FORMAT_FLOAT, 2));
+        assert_eq!((u32_at(24), u32_at(28)), (48_000, 48_000 * 8));
+        assert_eq!((u16_at(32), u16_at(34)), (8, 32));
+        assert_eq!(u32_at(40), 8_000);
+        assert_eq!(f32::from_le_bytes(bytes[44..48].try_into().unwrap()), 0.5);
+
+        // Odd data lengths are padded
+        let spec = WavSpec {
+            format: SampleFormat::U8,
+            ..WavSpec::mono(Hz(8_000))
+        };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+        writer.write(&[0; 3]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert_eq!((bytes.len(), bytes[40]), (HEADER_LEN + 4, 3));
+        assert_eq!(bytes[4], HEADER_LEN as u8 - 8 + 4);
+    }
+
+    #[test]
+    fn test_render_to_wav() {
+        let path = std::env::temp_dir().join(format!("isopod-{}.wav", std::process::id()));
+        let render = || {
+            let mut piano = FmPiano::new();
+            piano.note_on(60, 100);
+            render_to_wav(&mut piano, Duration::from_millis(100), &path).unwrap();
+            std::fs::read(&path).unwrap()
+        };
+        let bytes = render();
+        let frames = FmPiano::new().get_sample_rate().0 as usize / 10;
+        assert_eq!(bytes.len(), HEADER_LEN + 2 * frames);
+        assert!(bytes[HEADER_LEN..].iter().any(|b| *b != 0));
+        // Renders are deterministic
+        assert_eq!(render(), bytes);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_wav_read() {
+        let samples = [i16::MIN, -300, 0, 5, 20_000, i16::MAX];
+        for format in [
+            SampleFormat::U8,
+            SampleFormat::I16,
+            SampleFormat::I24,
+            SampleFormat::I32,
+            SampleFormat::F32,
+        ] {
+            let spec = WavSpec {
+                format,
+                ..WavSpec::stereo(Hz(22_050))
+            };
+            let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+            writer.write(&samples).unwrap();
+            let bytes = writer.finish().unwrap().into_inner();
+            let mut reader = WavReader::new(Cursor::new(bytes)).unwrap();
+            assert_eq!((reader.get_spec(), reader.get_frames()), (spec, 3));
+            let mut out = [0; 8];
+            assert_eq!(reader.read(&mut out).unwrap(), samples.len());
+            for (x, y) in samples.iter().zip(out) {
+                let tolerance = if format == SampleFormat::U8 { 255 } else { 0 };
+                assert!((*x as i32 - y as i32).abs() <= tolerance, "{:?}", format);
+            }
+            assert_eq!(reader.read(&mut out).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn test_wav_read_chunks() {
+        // Extensible format with a metadata chunk of odd length before the
+        // samples
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        bytes.extend(b"fmt \x28\0\0\0\xFE\xFF\x02\0\x40\x1F\0\0");
+        bytes.extend([0, 0, 0, 0, 4, 0, 16, 0, 22, 0, 16, 0, 3, 0, 0, 0, 1, 0]);
+        bytes.extend([0; 14]);
+        bytes.extend(b"LIST\x03\0\0\0abc\0");
+        bytes.extend(b"data\x08\0\0\0");
+        for x in [100_i16, 300, -50, 50] {
+            bytes.extend(x.to_le_bytes());
+        }
+        let mut reader = WavReader::new(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(reader.get_spec(), WavSpec::stereo(Hz(8_000)));
+        assert_eq!(reader.read_mono().unwrap(), [200, 0]);
+
+        bytes[8..12].copy_from_slice(b"AVI ");
+        assert_eq!(
+            WavReader::new(Cursor::new(&bytes)).err(),
+            Some(WavError::Format)
+        );
+        bytes[8..12].copy_from_slice(b"WAVE");
+        bytes[34] = 64;
+        assert_eq!(
+            WavReader::new(Cursor::new(&bytes)).err(),
+            Some(WavError::Unsupported)
+        );
+        let missing = WavReader::new(Cursor::new(&bytes[..40])).err();
+        assert!(matches!(missing, Some(WavError::Io(_))));
+
+        // Format chunks beyond the extensible format are read in part
+        let mut long = bytes[..12].to_vec();
+        long.extend(b"fmt \xFF\xFF\xFF\xFF\x01\0\x01\0\x40\x1F\0\0");
+        long.extend([0; 40 - 8]);
+        long[34] = 8;
+        let missing = WavReader::new(Cursor::new(&long)).err();
+        assert!(matches!(missing, Some(WavError::Format)));
+        long[16..20].copy_from_slice(&41_u32.to_le_bytes());
+        long.extend([0; 2]);
+        long.extend(b"data\x02\0\0\0\x10\0");
+        let reader = WavReader::new(Cursor::new(&long)).unwrap();
+        assert_eq!(reader.get_spec().format, SampleFormat::U8);
+        assert_eq!(reader.get_frames(), 2);
+    }
+
+    #[test]
+    fn test_wav_channels() {
+        // More channels than a block of samples
+        let spec = WavSpec {
+            channels: 1_000,
+            ..WavSpec::mono(Hz(8_000))
+        };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+        writer.write(&[300; 2_000]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        let mut reader = WavReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.read_mono().unwrap(), [300, 300]);
+
+        // Frames or byte rates that don't fit into the header
+        for (channels, sample_rate) in [(u16::MAX, 8_000), (2, u32::MAX)] {
+            let spec = WavSpec {
+                channels,
+                sample_rate: Hz(sample_rate),
+                format: SampleFormat::I16,
+            };
+            let error = WavWriter::new(Cursor::new(Vec::new()), spec).err().unwrap();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_load_wavetable() {
+        let path = std::env::temp_dir().join(format!("isopod-cycle-{}.wav", std::process::id()));
+        let mut writer = WavWriter::create(&path, WavSpec::mono(Hz(44_100))).unwrap();
+        writer.write(&[0, 5_000, 10_000, 5_000, 0, -5_000]).unwrap();
+        writer.finish().unwrap();
+        let table = load_wavetable(&path, 64).unwrap();
+        assert_eq!(table.len(), 64);
+        assert_eq!(table.iter().max(), Some(&i16::MAX));
+        let (samples, sample_rate) = load_wav(&path).unwrap();
+        assert_eq!((samples.len(), sample_rate), (6, Hz(44_100)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
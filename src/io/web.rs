@@ -0,0 +1,139 @@
+// Rendering into the planar f32 buffers of a Web Audio AudioWorklet, for
+// running synths compiled to wasm32-unknown-unknown in the browser.
+
+use crate::synth::{StereoSynth, Synth};
+use crate::util::units::{Frame, SAMPLE_NORM};
+
+/// Frames of one block of an AudioWorklet, the render quantum of Web Audio
+pub const QUANTUM: usize = 128;
+
+/// Output block of an AudioWorklet with `C` channels
+///
+/// The `process` method of an `AudioWorkletProcessor` gets one
+/// `Float32Array` of [QUANTUM] samples per channel. The block holds them
+/// one after the other without allocation, so a `static` block in the wasm
+/// memory is shared with the processor: JavaScript creates a
+/// `Float32Array` view on [Self::as_ptr] once, and after each fill copies
+/// the channels from it with `set` and `subarray`. See
+/// `examples/web.rs` for the exported functions and the processor.
+///
+/// ```
+/// use isopod::io::web::{WorkletBlock, QUANTUM};
+/// use isopod::synth::fmpiano::FmPiano;
+/// use isopod::synth::Synth;
+///
+/// static mut BLOCK: WorkletBlock<2> = WorkletBlock::new();
+///
+/// let mut piano = FmPiano::new();
+/// piano.note_on(60, 100);
+/// let block = unsafe { &mut *core::ptr::addr_of_mut!(BLOCK) };
+/// block.fill(&mut piano);
+/// assert_eq!(block.get_channel(0), block.get_channel(1));
+/// assert!(block.as_slice().iter().all(|x| (-1.0..1.0).contains(x)));
+/// assert_eq!(block.as_slice().len(), 2 * QUANTUM);
+/// ```
+pub struct WorkletBlock<const C: usize> {
+    channels: [[f32; QUANTUM]; C],
+}
+
+impl<const C: usize> WorkletBlock<C> {
+    /// Returns a silent block.
+    pub const fn new() -> Self {
+        Self {
+            channels: [[0.0; QUANTUM]; C],
+        }
+    }
+
+    /// Renders the next block of a mono `synth` into all channels.
+    pub fn fill<S: Synth + ?Sized>(&mut self, synth: &mut S) {
+        let mut buf = [0_i16; QUANTUM];
+        synth.render(&mut buf);
+        for channel in self.channels.iter_mut() {
+            for (y, x) in channel.iter_mut().zip(buf.iter()) {
+                *y = to_f32(*x);
+            }
+        }
+    }
+
+    /// Renders the next block of a stereo `synth` into the first two
+    /// channels. Further channels are 0 and a single channel gets the
+    /// average of left and right.
+    pub fn fill_stereo<S: StereoSynth + ?Sized>(&mut self, synth: &mut S) {
+        let mut buf = [Frame::mono(0); QUANTUM];
+        synth.render_frames(&mut buf);
+        for (c, channel) in self.channels.iter_mut().enumerate() {
+            for (y, x) in channel.iter_mut().zip(buf.iter()) {
+                *y = match (c, C) {
+                    (0, 1) => to_f32(x.to_mono().0),
+                    (0, _) => to_f32(x.left.0),
+                    (1, _) => to_f32(x.right.0),
+                    _ => 0.0,
+                };
+            }
+        }
+    }
+
+    /// Returns the samples of `channel`.
+    pub fn get_channel(&self, channel: usize) -> &[f32; QUANTUM] {
+        &self.channels[channel]
+    }
+
+    /// Returns all channels one after the other.
+    pub fn as_slice(&self) -> &[f32] {
+        self.channels.as_flattened()
+    }
+
+    /// Returns the address of the first sample, which is the offset of the
+    /// block in the wasm memory.
+    pub fn as_ptr(&self) -> *const f32 {
+        self.as_slice().as_ptr()
+    }
+}
+
+impl<const C: usize> Default for WorkletBlock<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts a sample to the range of Web Audio from -1 to 1.
+#[inline]
+fn to_f32(x: i16) -> f32 {
+    x as f32 / SAMPLE_NORM as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::units::Hz;
+
+    struct Ramp(i16);
+
+    impl StereoSynth for Ramp {
+        fn _next_frame(&mut self) -> Option<Frame> {
+            self.0 += 128;
+            Some(Frame::new(self.0, -self.0))
+        }
+
+        fn get_sample_rate(&self) -> Hz {
+            Hz(48_000)
+        }
+
+        fn set_sample_rate(&mut self, _sample_rate: Hz) {}
+    }
+
+    #[test]
+    fn test_worklet_block() {
+        let mut block = WorkletBlock::<3>::new();
+        block.fill_stereo(&mut Ramp(0));
+        assert_eq!(block.get_channel(0)[..2], [1.0 / 256.0, 2.0 / 256.0]);
+        assert_eq!(block.get_channel(1)[..2], [-1.0 / 256.0, -2.0 / 256.0]);
+        assert!(block.get_channel(2).iter().all(|x| *x == 0.0));
+        // Channels are contiguous
+        assert_eq!(block.as_slice()[QUANTUM], -1.0 / 256.0);
+
+        let mut block = WorkletBlock::<1>::new();
+        block.fill_stereo(&mut Ramp(0));
+        assert!(block.as_slice().iter().all(|x| *x == 0.0));
+    }
+}
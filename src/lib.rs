@@ -1,5 +1,19 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod analysis;
+pub mod drum;
 pub mod env;
 pub mod fx;
+pub mod graph;
+pub mod io;
+pub mod midi;
 pub mod osc;
+pub mod preset;
+#[cfg(feature = "std")]
+pub mod remote;
+pub mod seq;
 pub mod synth;
 pub mod util;
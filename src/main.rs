@@ -1,102 +1,393 @@
-use isopod::fx::filter::{StateVariableFilter, Q_MAX};
-use isopod::osc::luts::SINE_I16;
-use isopod::osc::noise::{WhiteNoise, LFSR};
-use isopod::osc::wavetable::WavetableOscillator;
-use isopod::synth::Synth;
-use isopod::util::units::{mHz, Frequency, Hz};
-use rodio::{OutputStream, Source};
-
-fn main() {
-    struct ProtoSynth {
-        msample_rate: mHz,
-
-        // Primitives
-        noise: WhiteNoise,
-        filter: StateVariableFilter,
-    }
-
-    impl Synth for ProtoSynth {
-        fn new() -> Self {
-            let mut s = Self {
-                msample_rate: mHz(44_100_000),
-                noise: WhiteNoise::new(),
-                filter: StateVariableFilter::new(),
-            };
+// Command line renderer and player of JSON patch files.
 
-            s.filter.set_msample_rate(s.msample_rate);
-            s.filter.set_mfreq(Hz(200).to_mHz());
-            s.filter.set_q(Q_MAX / 8);
-            s
-        }
+use isopod::io::wav::render_to_wav;
+use isopod::preset::json::{from_json, to_json};
+use isopod::preset::Preset;
+use isopod::synth::fmpiano::{FmPiano, FmPianoParams};
+use isopod::synth::subtractive::{SubtractiveParams, SubtractiveVoice};
+use isopod::synth::voice::{Voice, VoiceAllocator};
+use isopod::synth::{NoteEvent, Synth};
+use isopod::util::units::{ms, Hz};
+use serde::{Deserialize, Serialize};
+use std::process::ExitCode;
+use std::time::Duration;
 
-        fn _next(&mut self) -> Option<i16> {
-            self.filter.feed(self.noise.next().unwrap() / 4);
-            Some(self.filter.get_lp())
-        }
+const USAGE: &str = "\
+Usage:
+  isopod render <patch.json> [--out <file.wav>] [--dur <duration>]
+  isopod play <patch.json> [--dur <duration>] [--midi]
+  isopod new <subtractive|fmpiano>
 
-        fn get_sample_rate(&self) -> Hz {
-            self.msample_rate.to_Hz()
-        }
+render writes a 16-bit WAV file, out.wav by default. play plays the notes
+of the patch on the default output device, or notes from the first MIDI
+input with --midi. new prints a patch with default parameters to start
+from. Durations are given in s or ms, e.g. 2.5s or 400ms. By default the
+notes play until 2 s after the last release.";
+
+/// Polyphony of patches
+const VOICES: usize = 8;
+/// Sample rate of renders and playback
+const SAMPLE_RATE: Hz = Hz(44_100);
+/// Time after the last note of a patch for the release tails
+const TAIL: Duration = Duration::from_secs(2);
+/// Latest release of the notes of a patch
+const MAX_END: Duration = Duration::from_secs(60 * 60);
+
+/// Instrument of a patch with its parameters
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Instrument {
+    Subtractive(SubtractiveParams),
+    FmPiano(FmPianoParams),
+}
+
+/// Note of a patch with its start and length
+#[derive(Serialize, Deserialize)]
+struct PatchNote {
+    note: u8,
+    velocity: u8,
+    start: ms,
+    length: ms,
+}
+
+/// Contents of a patch file
+#[derive(Serialize, Deserialize)]
+struct Patch {
+    instrument: Instrument,
+    #[serde(default)]
+    notes: Vec<PatchNote>,
+}
+
+impl Patch {
+    /// Returns the time of the last release.
+    fn end(&self) -> Duration {
+        let end = self
+            .notes
+            .iter()
+            .map(|n| n.start.0 as u64 + n.length.0 as u64);
+        Duration::from_millis(end.max().unwrap_or(0))
+    }
+
+    /// Returns the time of the last release plus the tail.
+    fn duration(&self) -> Duration {
+        self.end() + TAIL
+    }
+}
+
+/// Voices of a patch playing its notes
+struct PatchSynth<V: Voice + Synth> {
+    voices: VoiceAllocator<V, VOICES>,
+    // Note events sorted by their sample
+    events: Vec<(u64, NoteEvent)>,
+    next_event: usize,
+    position: u64,
+    #[cfg(feature = "midi")]
+    midi: Option<isopod::midi::input::MidiInput>,
+}
 
-        fn set_sample_rate(&mut self, sample_rate: Hz) {
-            self.msample_rate = sample_rate.to_mHz();
+/// Returns the note events of `notes` sorted by their sample.
+fn note_events(notes: &[PatchNote]) -> Vec<(u64, NoteEvent)> {
+    let sample = |t: ms| t.0 as u64 * SAMPLE_RATE.0 as u64 / 1_000;
+    let mut events = Vec::with_capacity(2 * notes.len());
+    for n in notes {
+        let (note, velocity) = (n.note, n.velocity);
+        events.push((sample(n.start), NoteEvent::On { note, velocity }));
+        events.push((sample(n.start + n.length), NoteEvent::Off { note }));
+    }
+    // Releases before notes starting at the same time, so that a note
+    // doesn't cut off the next note of the same pitch
+    events.sort_by_key(|(t, event)| (*t, matches!(event, NoteEvent::On { .. })));
+    events
+}
+
+impl<V: Voice + Synth> PatchSynth<V> {
+    fn new(voice: impl Fn() -> V, notes: &[PatchNote]) -> Self {
+        let events = note_events(notes);
+        let mut voices = VoiceAllocator::new(core::array::from_fn(|_| voice()));
+        for v in voices.get_voices_mut() {
+            v.set_sample_rate(SAMPLE_RATE);
+        }
+        Self {
+            voices,
+            events,
+            next_event: 0,
+            position: 0,
+            #[cfg(feature = "midi")]
+            midi: None,
         }
     }
+}
+
+impl<V: Voice + Synth> Synth for PatchSynth<V> {
+    fn _next(&mut self) -> Option<i16> {
+        let mut out = [0];
+        self.render(&mut out);
+        Some(out[0])
+    }
 
-    impl Iterator for ProtoSynth {
-        type Item = i16;
-        fn next(&mut self) -> Option<Self::Item> {
-            self._next()
+    fn render(&mut self, mut out: &mut [i16]) {
+        #[cfg(feature = "midi")]
+        if let Some(midi) = &self.midi {
+            midi.feed(&mut self.voices, |_| {});
         }
+        // Blocks are split at the note events
+        while !out.is_empty() {
+            while let Some((t, event)) = self.events.get(self.next_event) {
+                if *t > self.position {
+                    break;
+                }
+                self.voices.handle_event(*event);
+                self.next_event += 1;
+            }
+            let until = match self.events.get(self.next_event) {
+                Some((t, _)) => (*t - self.position).min(out.len() as u64) as usize,
+                None => out.len(),
+            };
+            let (block, rest) = out.split_at_mut(until);
+            self.voices.render(block);
+            self.position += until as u64;
+            out = rest;
+        }
+    }
+
+    fn get_sample_rate(&self) -> Hz {
+        SAMPLE_RATE
+    }
+
+    fn set_sample_rate(&mut self, _sample_rate: Hz) {}
+}
+
+/// Parses durations like 10s, 2.5s or 400ms.
+fn parse_duration(text: &str) -> Option<Duration> {
+    if let Some(millis) = text.strip_suffix("ms") {
+        millis.parse().ok().map(Duration::from_millis)
+    } else {
+        let secs: f64 = text.strip_suffix('s').unwrap_or(text).parse().ok()?;
+        Duration::try_from_secs_f64(secs).ok()
     }
+}
 
-    impl Source for ProtoSynth {
-        fn channels(&self) -> u16 {
-            1
+/// Options after the patch file
+struct Options {
+    out: String,
+    duration: Option<Duration>,
+    midi: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        out: String::from("out.wav"),
+        duration: None,
+        midi: false,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => options.out = args.next().ok_or("missing file after --out")?.clone(),
+            "--dur" => {
+                let duration = args.next().ok_or("missing duration after --dur")?;
+                options.duration =
+                    Some(parse_duration(duration).ok_or(format!("invalid duration {}", duration))?);
+            }
+            "--midi" => options.midi = true,
+            _ => return Err(format!("unknown option {}", arg)),
         }
+    }
+    Ok(options)
+}
 
-        fn sample_rate(&self) -> u32 {
-            self.get_sample_rate().0
+fn load_patch(path: &str) -> Result<Patch, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let patch: Patch = from_json(&text).map_err(|_| format!("{}: invalid patch", path))?;
+    if patch.end() > MAX_END {
+        return Err(format!(
+            "{}: notes end after more than {} s",
+            path,
+            MAX_END.as_secs()
+        ));
+    }
+    let valid = match &patch.instrument {
+        Instrument::Subtractive(params) => is_valid(SubtractiveVoice::new(), params),
+        Instrument::FmPiano(params) => is_valid(FmPiano::new(), params),
+    };
+    if !valid {
+        return Err(format!("{}: instrument parameters out of range", path));
+    }
+    Ok(patch)
+}
+
+/// True if `voice` takes `params` as they are, without clamping them.
+fn is_valid<V: Preset>(mut voice: V, params: &V::Params) -> bool
+where
+    V::Params: PartialEq,
+{
+    voice.set_params(params);
+    voice.get_params() == *params
+}
+
+fn render<V: Voice + Synth>(
+    mut synth: PatchSynth<V>,
+    options: &Options,
+    duration: Duration,
+) -> Result<(), String> {
+    render_to_wav(&mut synth, duration, &options.out)
+        .map_err(|e| format!("{}: {}", options.out, e))?;
+    println!(
+        "Rendered {:.1} s to {}",
+        duration.as_secs_f64(),
+        options.out
+    );
+    Ok(())
+}
+
+#[cfg(feature = "rodio")]
+fn play<V: Voice + Synth + Send + 'static>(
+    #[allow(unused_mut)] mut synth: PatchSynth<V>,
+    options: &Options,
+    duration: Duration,
+) -> Result<(), String> {
+    use isopod::synth::MonoSource;
+    use rodio::{OutputStream, Source};
+
+    if options.midi {
+        #[cfg(feature = "midi")]
+        {
+            let midi = isopod::midi::input::MidiInput::connect(None)
+                .map_err(|e| format!("no MIDI input: {:?}", e))?;
+            synth.midi = Some(midi);
         }
+        #[cfg(not(feature = "midi"))]
+        return Err(String::from("MIDI input needs the midi feature"));
+    }
+    let (_stream, stream_handle) =
+        OutputStream::try_default().map_err(|e| format!("no output device: {}", e))?;
+    stream_handle
+        .play_raw(MonoSource::new(synth).convert_samples())
+        .map_err(|e| format!("playback failed: {}", e))?;
+    if options.midi && options.duration.is_none() {
+        // Plays until interrupted
+        loop {
+            std::thread::sleep(Duration::from_secs(1));
+        }
+    }
+    std::thread::sleep(duration);
+    Ok(())
+}
 
-        fn current_frame_len(&self) -> Option<usize> {
-            None
+#[cfg(not(feature = "rodio"))]
+fn play<V: Voice + Synth>(_: PatchSynth<V>, _: &Options, _: Duration) -> Result<(), String> {
+    Err(String::from("playback needs the rodio feature"))
+}
+
+/// Renders or plays the patch at `path`.
+fn run(command: &str, path: &str, options: &Options) -> Result<(), String> {
+    let patch = load_patch(path)?;
+    let duration = options.duration.unwrap_or(patch.duration());
+    match (&patch.instrument, command) {
+        (Instrument::Subtractive(params), "render") => render(
+            PatchSynth::new(|| voice(SubtractiveVoice::new(), params), &patch.notes),
+            options,
+            duration,
+        ),
+        (Instrument::Subtractive(params), _) => play(
+            PatchSynth::new(|| voice(SubtractiveVoice::new(), params), &patch.notes),
+            options,
+            duration,
+        ),
+        (Instrument::FmPiano(params), "render") => render(
+            PatchSynth::new(|| voice(FmPiano::new(), params), &patch.notes),
+            options,
+            duration,
+        ),
+        (Instrument::FmPiano(params), _) => play(
+            PatchSynth::new(|| voice(FmPiano::new(), params), &patch.notes),
+            options,
+            duration,
+        ),
+    }
+}
+
+fn voice<V: Preset>(mut voice: V, params: &V::Params) -> V {
+    voice.set_params(params);
+    voice
+}
+
+/// Prints a patch with default parameters and a single note.
+fn new_patch(name: &str) -> Result<(), String> {
+    let instrument = match name {
+        "subtractive" => Instrument::Subtractive(SubtractiveVoice::new().get_params()),
+        "fmpiano" => Instrument::FmPiano(FmPiano::new().get_params()),
+        _ => return Err(format!("unknown instrument {}", name)),
+    };
+    let patch = Patch {
+        instrument,
+        notes: vec![PatchNote {
+            note: 60,
+            velocity: 100,
+            start: ms(0),
+            length: ms(1_000),
+        }],
+    };
+    println!("{}", to_json(&patch).map_err(|_| "invalid parameters")?);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.as_slice() {
+        [command, path, rest @ ..] if command == "render" || command == "play" => {
+            parse_options(rest).and_then(|options| run(command, path, &options))
         }
+        [command, name] if command == "new" => new_patch(name),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("isopod: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-        fn total_duration(&self) -> Option<std::time::Duration> {
-            None
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn note(note: u8, start: u32, length: u32) -> PatchNote {
+        PatchNote {
+            note,
+            velocity: 100,
+            start: ms(start),
+            length: ms(length),
         }
     }
 
-    let synth = ProtoSynth::new();
-
-    let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-    let _result = stream_handle.play_raw(synth.convert_samples());
-    std::thread::sleep(std::time::Duration::from_secs(3));
-
-    // // Debug wavetable oscillator
-    // let mut osc = WavetableOscillator::<i16>::new();
-    // osc.set_wavetable(&SINE_I16);
-    // osc.set_freq(Hz(2));
-    // osc.set_sample_rate(Hz(100));
-    // osc.set_repeat(false);
-    // osc.start();
-    // for x in 0..110 {
-    //     let _y = osc._next();
-    //     match _y {
-    //         Some(y) => println!("{}: {}\n", x, (y as f64) / (i16::MAX as f64)),
-    //         None => {
-    //             println!("Generator stopped at {}", x);
-    //             break;
-    //         }
-    //     }
-    // }
-
-    // // Debug LFSR
-    // let mut lfsr16 = LFSR::<u16>::default();
-    // let mut lfsr32 = LFSR::<u32>::default();
-    // for i in 0..33 {
-    //     println!("lfsr16: {:?}", lfsr16.next());
-    //     println!("lfsr32: {:?}", lfsr32.next());
-    // }
+    #[test]
+    fn test_note_events() {
+        // Listed out of time order, with the second note starting at the
+        // release of the first
+        let events = note_events(&[note(60, 1_000, 500), note(60, 0, 1_000)]);
+        let on = NoteEvent::On {
+            note: 60,
+            velocity: 100,
+        };
+        let off = NoteEvent::Off { note: 60 };
+        assert_eq!(
+            events,
+            [(0, on), (44_100, off), (44_100, on), (66_150, off)]
+        );
+    }
+
+    #[test]
+    fn test_patch_params() {
+        let mut params = SubtractiveVoice::new().get_params();
+        assert!(is_valid(SubtractiveVoice::new(), &params));
+        params.detune = -100_000;
+        assert!(!is_valid(SubtractiveVoice::new(), &params));
+        assert!(is_valid(FmPiano::new(), &FmPiano::new().get_params()));
+    }
 }
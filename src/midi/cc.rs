@@ -0,0 +1,385 @@
+// Mapping of MIDI control changes to parameters, with MIDI learn.
+
+use crate::midi::MidiMessage;
+use crate::preset::Preset;
+use crate::util::mapping::{Curve, MapValue, CONTROL_MAX};
+use crate::util::param::ParamHandle;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// Maximum number of bindings of a [CcMap]
+pub const MAX_BINDINGS: usize = 32;
+
+/// Response of a parameter to the controller value
+pub type CcCurve = Curve;
+
+/// How a binding takes over a parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CcMode {
+    /// Every controller value sets the parameter immediately, which jumps if
+    /// the knob isn't at the current value.
+    Jump,
+    /// The controller only takes over after it reached the current value of
+    /// the parameter, e.g. after a preset change.
+    Pickup,
+    /// Values of 64 and above toggle the parameter between the ends of its
+    /// range, for buttons.
+    Latch,
+}
+
+/// Binding of a controller to a registered parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CcBinding {
+    /// Channel from 0 to 15, or `None` for all channels
+    pub channel: Option<u8>,
+    pub controller: u8,
+    /// Index of the parameter in registration order
+    pub param: u8,
+    pub curve: CcCurve,
+    pub mode: CcMode,
+}
+
+/// Parameters of a [CcMap]
+pub type CcMapParams = [Option<CcBinding>; MAX_BINDINGS];
+
+/// Registered parameter with its range
+struct Target {
+    name: &'static str,
+    lo: i64,
+    hi: i64,
+    set: Box<dyn Fn(i64) + Send>,
+    get: Box<dyn Fn() -> i64 + Send>,
+}
+
+/// Runtime state of a binding
+#[derive(Debug, Clone, Copy, Default)]
+struct Takeover {
+    /// Mapped value of the last controller value
+    last: Option<i64>,
+    picked_up: bool,
+    pressed: bool,
+}
+
+/// Maps control change messages to parameters
+///
+/// Parameters are registered with a name and a range, and are set through
+/// their [ParamHandle], so the map lives on the control thread like the
+/// MIDI input. The bindings are plain data and can be stored like any
+/// other preset, while the parameters need to be registered in the same
+/// order again.
+///
+/// ```
+/// use isopod::midi::cc::{CcCurve, CcMap, CcMode};
+/// use isopod::midi::MidiMessage;
+/// use isopod::util::param::param;
+/// use isopod::util::units::mHz;
+///
+/// let (mut cutoff, handle) = param(mHz(1_000_000));
+/// let mut map = CcMap::new();
+/// let id = map.register("cutoff", mHz(100_000), mHz(8_000_000), handle);
+/// map.bind(None, 74, id, CcCurve::Exponential, CcMode::Jump);
+///
+/// map.handle(MidiMessage::ControlChange { channel: 0, controller: 74, value: 127 });
+/// assert_eq!(cutoff.poll(), Some(mHz(8_000_000)));
+/// ```
+pub struct CcMap {
+    targets: Vec<Target>,
+    bindings: CcMapParams,
+    takeovers: [Takeover; MAX_BINDINGS],
+    learning: Option<(u8, CcCurve, CcMode)>,
+}
+
+impl CcMap {
+    pub fn new() -> Self {
+        Self {
+            targets: Vec::new(),
+            bindings: [None; MAX_BINDINGS],
+            takeovers: [Takeover::default(); MAX_BINDINGS],
+            learning: None,
+        }
+    }
+
+    /// Registers a parameter that controllers map to the range from `lo` to
+    /// `hi`. `lo` may be above `hi` to invert the controller. Returns the
+    /// index of the parameter for [CcMap::bind].
+    pub fn register<T: MapValue + Send + Sync + 'static>(
+        &mut self,
+        name: &'static str,
+        lo: T,
+        hi: T,
+        handle: ParamHandle<T>,
+    ) -> u8 {
+        let getter = handle.clone();
+        self.targets.push(Target {
+            name,
+            lo: lo.to_i64(),
+            hi: hi.to_i64(),
+            set: Box::new(move |x| handle.set(T::from_i64(x))),
+            get: Box::new(move || getter.get().to_i64()),
+        });
+        (self.targets.len() - 1) as u8
+    }
+
+    /// Returns the index of the parameter registered as `name`.
+    pub fn find(&self, name: &str) -> Option<u8> {
+        self.targets
+            .iter()
+            .position(|t| t.name == name)
+            .map(|i| i as u8)
+    }
+
+    /// Returns the names of the registered parameters in registration order.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.targets.iter().map(|t| t.name)
+    }
+
+    /// Binds `controller` on `channel` to the parameter `param`, replacing a
+    /// previous binding of the controller. Returns false if all bindings are
+    /// used or the parameter doesn't exist.
+    pub fn bind(
+        &mut self,
+        channel: Option<u8>,
+        controller: u8,
+        param: u8,
+        curve: CcCurve,
+        mode: CcMode,
+    ) -> bool {
+        if param as usize >= self.targets.len() {
+            return false;
+        }
+        self.unbind(channel, controller);
+        match self.bindings.iter().position(|b| b.is_none()) {
+            Some(i) => {
+                self.bindings[i] = Some(CcBinding {
+                    channel,
+                    controller,
+                    param,
+                    curve,
+                    mode,
+                });
+                self.takeovers[i] = Takeover::default();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the binding of `controller` on `channel`.
+    pub fn unbind(&mut self, channel: Option<u8>, controller: u8) {
+        for binding in self.bindings.iter_mut() {
+            if binding.is_some_and(|b| b.channel == channel && b.controller == controller) {
+                *binding = None;
+            }
+        }
+    }
+
+    /// Binds the next controller that moves to the parameter `param`.
+    pub fn learn(&mut self, param: u8, curve: CcCurve, mode: CcMode) {
+        self.learning = Some((param, curve, mode));
+    }
+
+    /// True while waiting for a controller to learn.
+    pub fn is_learning(&self) -> bool {
+        self.learning.is_some()
+    }
+
+    /// Stops learning without binding a controller.
+    pub fn cancel_learn(&mut self) {
+        self.learning = None;
+    }
+
+    /// Applies a control change to the bound parameters. Returns false for
+    /// other messages and unbound controllers, so they can be handled
+    /// elsewhere.
+    pub fn handle(&mut self, message: MidiMessage) -> bool {
+        let MidiMessage::ControlChange {
+            channel,
+            controller,
+            value,
+        } = message
+        else {
+            return false;
+        };
+        if let Some((param, curve, mode)) = self.learning.take() {
+            self.bind(Some(channel), controller, param, curve, mode);
+        }
+
+        let mut handled = false;
+        for (binding, takeover) in self.bindings.iter().zip(self.takeovers.iter_mut()) {
+            let Some(b) = binding else { continue };
+            if b.controller != controller || b.channel.is_some_and(|c| c != channel) {
+                continue;
+            }
+            let target = &self.targets[b.param as usize];
+            handled = true;
+            match b.mode {
+                CcMode::Latch => {
+                    let pressed = value >= 64;
+                    if pressed && !takeover.pressed {
+                        let x = if (target.get)() == target.hi {
+                            target.lo
+                        } else {
+                            target.hi
+                        };
+                        (target.set)(x);
+                    }
+                    takeover.pressed = pressed;
+                }
+                CcMode::Jump | CcMode::Pickup => {
+                    let x = b.curve.map(
+                        value.min(CONTROL_MAX) as i64,
+                        CONTROL_MAX as i64,
+                        target.lo,
+                        target.hi,
+                    );
+                    if b.mode == CcMode::Pickup && !takeover.picked_up {
+                        // Picked up when the controller reaches or crosses
+                        // the current value
+                        let current = (target.get)();
+                        takeover.picked_up = x == current
+                            || takeover
+                                .last
+                                .is_some_and(|last| (last < current) != (x < current));
+                        takeover.last = Some(x);
+                    }
+                    if b.mode == CcMode::Jump || takeover.picked_up {
+                        (target.set)(x);
+                    }
+                }
+            }
+        }
+        handled
+    }
+
+    /// Requires all pickup bindings to reach the parameter value again, e.g.
+    /// after loading a preset of the parameters.
+    pub fn reset_pickup(&mut self) {
+        self.takeovers = [Takeover::default(); MAX_BINDINGS];
+    }
+}
+
+impl Default for CcMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for CcMap {
+    type Params = CcMapParams;
+
+    fn get_params(&self) -> CcMapParams {
+        self.bindings
+    }
+
+    /// Bindings of parameters which aren't registered are dropped.
+    fn set_params(&mut self, params: &CcMapParams) {
+        self.bindings = *params;
+        for binding in self.bindings.iter_mut() {
+            if binding.is_some_and(|b| b.param as usize >= self.targets.len()) {
+                *binding = None;
+            }
+        }
+        self.reset_pickup();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::param::param;
+    use crate::util::units::mHz;
+
+    fn cc(channel: u8, controller: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange {
+            channel,
+            controller,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_cc_curves() {
+        let (mut level, handle) = param(0_i16);
+        let mut map = CcMap::new();
+        let id = map.register("level", 0, 12_700, handle);
+        assert!(map.bind(Some(2), 7, id, CcCurve::Linear, CcMode::Jump));
+        assert!(!map.bind(None, 8, 1, CcCurve::Linear, CcMode::Jump));
+
+        assert!(map.handle(cc(2, 7, 64)));
+        assert_eq!(level.poll(), Some(6_400));
+        // Other channels, controllers and messages aren't handled
+        assert!(!map.handle(cc(3, 7, 0)));
+        assert!(!map.handle(cc(2, 1, 0)));
+        assert!(!map.handle(MidiMessage::ChannelPressure {
+            channel: 2,
+            pressure: 0
+        }));
+        assert_eq!(level.poll(), None);
+
+        map.bind(Some(2), 7, id, CcCurve::Exponential, CcMode::Jump);
+        map.handle(cc(2, 7, 64));
+        assert!(level.poll().unwrap() < 3_300);
+        map.bind(Some(2), 7, id, CcCurve::Logarithmic, CcMode::Jump);
+        map.handle(cc(2, 7, 64));
+        assert!(level.poll().unwrap() < 1_500);
+        map.handle(cc(2, 7, 127));
+        assert_eq!(level.poll(), Some(12_700));
+        assert_eq!(map.get_params().iter().flatten().count(), 1);
+    }
+
+    #[test]
+    fn test_cc_pickup_latch() {
+        let (mut depth, depth_handle) = param(500_i32);
+        let (mut hold, hold_handle) = param(false);
+        let mut map = CcMap::new();
+        map.register("depth", 0, 1_270, depth_handle);
+        map.register("hold", false, true, hold_handle);
+        assert_eq!(map.find("hold"), Some(1));
+        assert_eq!(map.names().collect::<Vec<_>>(), ["depth", "hold"]);
+
+        map.bind(None, 1, 0, CcCurve::Linear, CcMode::Pickup);
+        map.handle(cc(0, 1, 10));
+        map.handle(cc(0, 1, 40));
+        assert_eq!(depth.poll(), None);
+        // Crosses 500 between 40 and 60
+        map.handle(cc(0, 1, 60));
+        assert_eq!(depth.poll(), Some(600));
+        map.handle(cc(0, 1, 0));
+        assert_eq!(depth.poll(), Some(0));
+
+        map.bind(None, 64, 1, CcCurve::Linear, CcMode::Latch);
+        map.handle(cc(0, 64, 127));
+        map.handle(cc(0, 64, 100));
+        assert_eq!(hold.poll(), Some(true));
+        map.handle(cc(0, 64, 0));
+        map.handle(cc(0, 64, 127));
+        assert_eq!(hold.poll(), Some(false));
+    }
+
+    #[test]
+    fn test_cc_learn() {
+        let (mut cutoff, handle) = param(mHz(1_000));
+        let mut map = CcMap::new();
+        let id = map.register("cutoff", mHz(1_000), mHz(128_000), handle);
+        map.learn(id, CcCurve::Linear, CcMode::Jump);
+        assert!(map.is_learning());
+        assert!(map.handle(cc(5, 21, 127)));
+        assert!(!map.is_learning());
+        assert_eq!(cutoff.poll(), Some(mHz(128_000)));
+        assert!(!map.handle(cc(4, 21, 0)));
+
+        // Bindings are restored with the parameters registered again
+        let params = map.get_params();
+        let (mut cutoff, handle) = param(mHz(1_000));
+        let mut restored = CcMap::new();
+        restored.set_params(&params);
+        assert!(!restored.handle(cc(5, 21, 0)));
+        restored.register("cutoff", mHz(1_000), mHz(128_000), handle);
+        restored.set_params(&params);
+        assert_eq!(restored.get_params(), params);
+        restored.handle(cc(5, 21, 0));
+        assert_eq!(cutoff.poll(), Some(mHz(1_000)));
+    }
+}
@@ -0,0 +1,260 @@
+// MIDI clock receiver, which follows the tempo and position of an external
+// sequencer.
+
+use crate::util::units::{mHz, us};
+
+/// Clock ticks per quarter note
+pub const CLOCK_PPQ: u32 = 24;
+/// Clock ticks per sixteenth, the unit of the song position
+const TICKS_PER_SIXTEENTH: u32 = CLOCK_PPQ / 4;
+/// Weight of a new tick interval in the average as a shift
+const SMOOTHING: u32 = 3;
+
+/// System real time and common messages of the MIDI clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockMessage {
+    /// Timing clock, sent 24 times per quarter note
+    Tick,
+    /// Starts from the beginning.
+    Start,
+    /// Continues from the current song position.
+    Continue,
+    Stop,
+    /// Song position in sixteenths
+    SongPosition(u16),
+}
+
+impl ClockMessage {
+    /// Parses a complete message. Returns `None` for other messages.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        match *bytes.first()? {
+            0xF8 => Some(Self::Tick),
+            0xFA => Some(Self::Start),
+            0xFB => Some(Self::Continue),
+            0xFC => Some(Self::Stop),
+            0xF2 => {
+                let lsb = *bytes.get(1)? as u16 & 0x7F;
+                let msb = *bytes.get(2)? as u16 & 0x7F;
+                Some(Self::SongPosition(msb << 7 | lsb))
+            }
+            _ => None,
+        }
+    }
+
+    /// Writes the message to `buf` and returns the used part of it.
+    pub fn encode<'a>(&self, buf: &'a mut [u8; 3]) -> &'a [u8] {
+        let (bytes, len) = match *self {
+            Self::Tick => ([0xF8, 0, 0], 1),
+            Self::Start => ([0xFA, 0, 0], 1),
+            Self::Continue => ([0xFB, 0, 0], 1),
+            Self::Stop => ([0xFC, 0, 0], 1),
+            Self::SongPosition(sixteenths) => (
+                [
+                    0xF2,
+                    sixteenths as u8 & 0x7F,
+                    (sixteenths >> 7) as u8 & 0x7F,
+                ],
+                3,
+            ),
+        };
+        *buf = bytes;
+        &buf[..len]
+    }
+}
+
+/// Tempo and position of an external MIDI clock
+///
+/// The tempo is measured from the intervals between ticks in samples,
+/// averaged over a few ticks to remove the jitter of messages that are
+/// only polled once per block. Call [MidiClock::advance] after every block
+/// and [MidiClock::handle] for each clock message, before or after the
+/// block, as the messages are received.
+///
+/// ```
+/// use isopod::midi::clock::{ClockMessage, MidiClock};
+///
+/// let mut clock = MidiClock::new();
+/// clock.handle(ClockMessage::Start);
+/// // 120 BPM at 44.1 kHz are 918.75 samples per tick
+/// for n in 0..48 {
+///     clock.handle(ClockMessage::Tick);
+///     clock.advance(if n % 4 == 0 { 918 } else { 919 });
+/// }
+/// assert!(clock.get_beat().unwrap().0.abs_diff(500_000) < 500);
+/// assert_eq!(clock.get_position(), 47);
+/// ```
+pub struct MidiClock {
+    running: bool,
+    // Song position in ticks
+    position: u32,
+    // The next tick plays the current position rather than advancing it
+    armed: bool,
+    // Samples since the last tick, None before the first tick
+    since_tick: Option<u32>,
+    // Averaged tick interval in 1/256 samples, 0 while unknown
+    interval: u32,
+
+    msample_rate: mHz,
+}
+
+impl MidiClock {
+    pub fn new() -> Self {
+        Self {
+            running: false,
+            position: 0,
+            armed: true,
+            since_tick: None,
+            interval: 0,
+
+            msample_rate: mHz(44_100_000),
+        }
+    }
+
+    /// Applies a clock message at the current time.
+    pub fn handle(&mut self, message: ClockMessage) {
+        match message {
+            ClockMessage::Tick => {
+                if let Some(since_tick) = self.since_tick {
+                    let measured = since_tick.min(u32::MAX >> 8) << 8;
+                    self.interval = if self.interval == 0 {
+                        measured
+                    } else {
+                        self.interval - (self.interval >> SMOOTHING) + (measured >> SMOOTHING)
+                    };
+                }
+                self.since_tick = Some(0);
+                if self.running {
+                    if self.armed {
+                        self.armed = false;
+                    } else {
+                        self.position += 1;
+                    }
+                }
+            }
+            ClockMessage::Start => {
+                self.position = 0;
+                self.armed = true;
+                self.running = true;
+            }
+            ClockMessage::Continue => self.running = true,
+            ClockMessage::Stop => self.running = false,
+            ClockMessage::SongPosition(sixteenths) => {
+                self.position = sixteenths as u32 * TICKS_PER_SIXTEENTH;
+                self.armed = true;
+            }
+        }
+    }
+
+    /// Advances the time by `samples`.
+    pub fn advance(&mut self, samples: usize) {
+        if let Some(since_tick) = self.since_tick.as_mut() {
+            *since_tick = since_tick.saturating_add(samples as u32);
+        }
+    }
+
+    /// True between start or continue and stop.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Returns the song position in ticks of [CLOCK_PPQ].
+    pub fn get_position(&self) -> u32 {
+        self.position
+    }
+
+    /// Returns the duration of a quarter note, or `None` before two ticks
+    /// were received.
+    pub fn get_beat(&self) -> Option<us> {
+        if self.interval == 0 {
+            return None;
+        }
+        let beat = self.interval as u64 * CLOCK_PPQ as u64 * 1_000_000_000
+            / (self.msample_rate.0 as u64 * 256);
+        Some(us(beat.min(u32::MAX as u64) as u32))
+    }
+
+    /// Returns the phase within the current quarter note, where the full
+    /// u32 range corresponds to one quarter. It is interpolated between the
+    /// ticks once the tempo is known.
+    pub fn get_phase(&self) -> u32 {
+        let tick = (self.position % CLOCK_PPQ) as u64;
+        if self.interval == 0 {
+            return ((tick << 32) / CLOCK_PPQ as u64) as u32;
+        }
+        let interval = self.interval as u64;
+        // Not beyond the next tick while it is late
+        let since_tick = ((self.since_tick.unwrap_or(0) as u64) << 8).min(interval - 1);
+        (((tick * interval + since_tick) << 32) / (CLOCK_PPQ as u64 * interval)) as u32
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+    }
+}
+
+impl Default for MidiClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clock_parse() {
+        assert_eq!(ClockMessage::parse(&[0xF8]), Some(ClockMessage::Tick));
+        assert_eq!(ClockMessage::parse(&[0xFC]), Some(ClockMessage::Stop));
+        assert_eq!(
+            ClockMessage::parse(&[0xF2, 0x01, 0x02]),
+            Some(ClockMessage::SongPosition(257))
+        );
+        assert_eq!(ClockMessage::parse(&[0xF2, 0x01]), None);
+        assert_eq!(ClockMessage::parse(&[0x90, 60, 100]), None);
+        let mut buf = [0; 3];
+        for bytes in [&[0xFB][..], &[0xF2, 0x01, 0x02]] {
+            let message = ClockMessage::parse(bytes).unwrap();
+            assert_eq!(message.encode(&mut buf), bytes);
+        }
+    }
+
+    #[test]
+    fn test_clock_follow() {
+        let mut clock = MidiClock::new();
+        clock.set_msample_rate(mHz(48_000_000));
+        let tick = |clock: &mut MidiClock, n: usize, samples: usize| {
+            for _ in 0..n {
+                clock.handle(ClockMessage::Tick);
+                clock.advance(samples);
+            }
+        };
+        // Ticks while stopped measure the tempo but don't move
+        tick(&mut clock, 4, 1_000);
+        assert!(!clock.is_running());
+        assert_eq!(clock.get_position(), 0);
+        assert_eq!(clock.get_beat(), Some(us(500_000)));
+
+        clock.handle(ClockMessage::Start);
+        tick(&mut clock, 11, 1_000);
+        tick(&mut clock, 1, 500);
+        assert_eq!(clock.get_position(), 11);
+        // Half way from tick 11 to 12
+        let phase = clock.get_phase() as u64;
+        assert!(phase.abs_diff((23 << 32) / 48) < 1 << 20, "{}", phase);
+
+        // Tempo changes are followed within a few ticks
+        tick(&mut clock, 48, 2_000);
+        assert!(clock.get_beat().unwrap().0.abs_diff(1_000_000) < 2_000);
+
+        clock.handle(ClockMessage::Stop);
+        clock.handle(ClockMessage::SongPosition(4));
+        clock.handle(ClockMessage::Continue);
+        tick(&mut clock, 3, 2_000);
+        assert_eq!(clock.get_position(), 26);
+        // A late tick holds the phase just before the next tick
+        let phase = clock.get_phase() as u64;
+        assert!(phase.abs_diff((3 << 32) / 24) < 1 << 20, "{}", phase);
+    }
+}
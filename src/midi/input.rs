@@ -0,0 +1,105 @@
+// Live MIDI input from a port of the operating system through midir.
+
+use crate::midi::clock::ClockMessage;
+use crate::midi::{feed_voices, MidiMessage};
+use crate::synth::voice::{Voice, VoiceAllocator};
+use std::sync::mpsc::{channel, Receiver};
+
+/// Reason why a MIDI input couldn't be opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiInputError {
+    /// The MIDI system of the operating system isn't available.
+    Init,
+    /// No port matches the name.
+    NoPort,
+    /// The port couldn't be opened.
+    Connect,
+}
+
+/// Connection to a MIDI input port
+///
+/// Incoming messages are parsed in the callback thread of midir and passed
+/// through a channel, so the audio thread only has to poll them, e.g. once
+/// per block. Clock messages arrive through a separate channel, see
+/// [MidiInput::poll_clock]. The port stays open as long as the input lives.
+pub struct MidiInput {
+    _connection: midir::MidiInputConnection<()>,
+    receiver: Receiver<MidiMessage>,
+    clock_receiver: Receiver<ClockMessage>,
+}
+
+impl MidiInput {
+    /// Returns the names of all input ports.
+    pub fn port_names() -> Result<Vec<String>, MidiInputError> {
+        let input = midir::MidiInput::new("isopod").map_err(|_| MidiInputError::Init)?;
+        Ok(input
+            .ports()
+            .iter()
+            .filter_map(|port| input.port_name(port).ok())
+            .collect())
+    }
+
+    /// Connects to the first port whose name contains `name`, or to the
+    /// first port at all without a name.
+    pub fn connect(name: Option<&str>) -> Result<Self, MidiInputError> {
+        let mut input = midir::MidiInput::new("isopod").map_err(|_| MidiInputError::Init)?;
+        // Keep the timing messages for the clock
+        input.ignore(midir::Ignore::SysexAndActiveSense);
+        let port = input
+            .ports()
+            .into_iter()
+            .find(|port| match (name, input.port_name(port)) {
+                (None, _) => true,
+                (Some(name), Ok(port_name)) => port_name.contains(name),
+                (Some(_), Err(_)) => false,
+            })
+            .ok_or(MidiInputError::NoPort)?;
+        let (sender, receiver) = channel();
+        let (clock_sender, clock_receiver) = channel();
+        let connection = input
+            .connect(
+                &port,
+                "isopod-in",
+                move |_, bytes, _| {
+                    // The receivers are gone only while the input is dropped
+                    if let Some(message) = MidiMessage::parse(bytes) {
+                        let _ = sender.send(message);
+                    } else if let Some(message) = ClockMessage::parse(bytes) {
+                        let _ = clock_sender.send(message);
+                    }
+                },
+                (),
+            )
+            .map_err(|_| MidiInputError::Connect)?;
+        Ok(Self {
+            _connection: connection,
+            receiver,
+            clock_receiver,
+        })
+    }
+
+    /// Returns the messages received since the last poll.
+    pub fn poll(&self) -> impl Iterator<Item = MidiMessage> + '_ {
+        self.receiver.try_iter()
+    }
+
+    /// Returns the clock messages received since the last poll, e.g. for a
+    /// [crate::midi::clock::MidiClock].
+    pub fn poll_clock(&self) -> impl Iterator<Item = ClockMessage> + '_ {
+        self.clock_receiver.try_iter()
+    }
+
+    /// Plays the received notes on `allocator` and passes all other
+    /// messages to `other`, see [feed_voices].
+    pub fn feed<V: Voice, const N: usize>(
+        &self,
+        allocator: &mut VoiceAllocator<V, N>,
+        mut other: impl FnMut(MidiMessage),
+    ) {
+        for message in self.poll() {
+            if let Some(message) = feed_voices(allocator, message) {
+                other(message);
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+// MIDI messages and their conversion to the note events of the synths.
+
+#[cfg(feature = "alloc")]
+pub mod cc;
+pub mod clock;
+#[cfg(feature = "midi")]
+pub mod input;
+pub mod mpe;
+#[cfg(feature = "midi")]
+pub mod output;
+#[cfg(feature = "alloc")]
+pub mod smf;
+
+use crate::synth::voice::{Voice, VoiceAllocator};
+use crate::synth::NoteEvent;
+
+/// Controller that releases all notes
+pub const CC_ALL_NOTES_OFF: u8 = 123;
+
+/// Channel voice message
+///
+/// Channels are numbered from 0 to 15. The pitch bend is centered at 0 and
+/// ranges from -8192 to 8191.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    PitchBend {
+        channel: u8,
+        value: i16,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+}
+
+impl MidiMessage {
+    /// Parses a complete message. Returns `None` for incomplete messages and
+    /// for messages other than the channel voice messages above.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let status = *bytes.first()?;
+        let channel = status & 0x0F;
+        let data = |i: usize| bytes.get(i).map(|b| b & 0x7F);
+        match status & 0xF0 {
+            0x80 => Some(Self::NoteOff {
+                channel,
+                note: data(1)?,
+            }),
+            // Note on with velocity 0 is a note off
+            0x90 => match (data(1)?, data(2)?) {
+                (note, 0) => Some(Self::NoteOff { channel, note }),
+                (note, velocity) => Some(Self::NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                }),
+            },
+            0xB0 => Some(Self::ControlChange {
+                channel,
+                controller: data(1)?,
+                value: data(2)?,
+            }),
+            0xD0 => Some(Self::ChannelPressure {
+                channel,
+                pressure: data(1)?,
+            }),
+            0xE0 => {
+                let value = (data(2)? as i16) << 7 | data(1)? as i16;
+                Some(Self::PitchBend {
+                    channel,
+                    value: value - 8192,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Builds a note on or off message on `channel` from a note event.
+    pub fn from_note_event(channel: u8, event: NoteEvent) -> Self {
+        match event {
+            NoteEvent::On { note, velocity } => Self::NoteOn {
+                channel,
+                note,
+                velocity,
+            },
+            NoteEvent::Off { note } => Self::NoteOff { channel, note },
+        }
+    }
+
+    /// Writes the message to `buf` and returns the used part of it. Values
+    /// out of range are cut to their 7 bits.
+    pub fn encode<'a>(&self, buf: &'a mut [u8; 3]) -> &'a [u8] {
+        let status = |kind: u8, channel: u8| kind | (channel & 0x0F);
+        let (bytes, len) = match *self {
+            Self::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => ([status(0x90, channel), note, velocity], 3),
+            Self::NoteOff { channel, note } => ([status(0x80, channel), note, 0], 3),
+            Self::ControlChange {
+                channel,
+                controller,
+                value,
+            } => ([status(0xB0, channel), controller, value], 3),
+            Self::ChannelPressure { channel, pressure } => {
+                ([status(0xD0, channel), pressure, 0], 2)
+            }
+            Self::PitchBend { channel, value } => {
+                let value = (value.clamp(-8192, 8191) + 8192) as u16;
+                ([status(0xE0, channel), value as u8, (value >> 7) as u8], 3)
+            }
+        };
+        *buf = bytes;
+        for b in buf[1..].iter_mut() {
+            *b &= 0x7F;
+        }
+        &buf[..len]
+    }
+
+    /// Returns the channel of the message.
+    pub fn get_channel(&self) -> u8 {
+        match *self {
+            Self::NoteOn { channel, .. }
+            | Self::NoteOff { channel, .. }
+            | Self::ControlChange { channel, .. }
+            | Self::PitchBend { channel, .. }
+            | Self::ChannelPressure { channel, .. } => channel,
+        }
+    }
+
+    /// Returns the note event of note on and off messages.
+    pub fn to_note_event(&self) -> Option<NoteEvent> {
+        match *self {
+            Self::NoteOn { note, velocity, .. } => Some(NoteEvent::On { note, velocity }),
+            Self::NoteOff { note, .. } => Some(NoteEvent::Off { note }),
+            _ => None,
+        }
+    }
+}
+
+/// Plays note messages on `allocator`, applies pitch bends and releases all
+/// notes on [CC_ALL_NOTES_OFF]. Returns the other messages, e.g. for
+/// controllers.
+pub fn feed_voices<V: Voice, const N: usize>(
+    allocator: &mut VoiceAllocator<V, N>,
+    message: MidiMessage,
+) -> Option<MidiMessage> {
+    match message {
+        MidiMessage::ControlChange {
+            controller: CC_ALL_NOTES_OFF,
+            ..
+        } => allocator.all_notes_off(),
+        MidiMessage::PitchBend { value, .. } => allocator.pitch_bend(value),
+        _ => match message.to_note_event() {
+            Some(event) => allocator.handle_event(event),
+            None => return Some(message),
+        },
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            MidiMessage::parse(&[0x91, 60, 100]),
+            Some(MidiMessage::NoteOn {
+                channel: 1,
+                note: 60,
+                velocity: 100
+            })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0x90, 60, 0]),
+            Some(MidiMessage::NoteOff {
+                channel: 0,
+                note: 60
+            })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0xBF, 74, 127]),
+            Some(MidiMessage::ControlChange {
+                channel: 15,
+                controller: 74,
+                value: 127
+            })
+        );
+        let bend = |lsb, msb| match MidiMessage::parse(&[0xE0, lsb, msb]) {
+            Some(MidiMessage::PitchBend { value, .. }) => value,
+            _ => panic!(),
+        };
+        assert_eq!(bend(0x00, 0x40), 0);
+        assert_eq!(bend(0x00, 0x00), -8192);
+        assert_eq!(bend(0x7F, 0x7F), 8191);
+
+        // Incomplete and system messages
+        assert_eq!(MidiMessage::parse(&[0x90, 60]), None);
+        assert_eq!(MidiMessage::parse(&[0xF8]), None);
+        assert_eq!(MidiMessage::parse(&[]), None);
+    }
+
+    #[test]
+    fn test_encode() {
+        let mut buf = [0; 3];
+        for bytes in [
+            &[0x91, 60, 100][..],
+            &[0x8F, 60, 0],
+            &[0xB2, 74, 127],
+            &[0xD3, 5],
+            &[0xE0, 0x00, 0x00],
+            &[0xE0, 0x7F, 0x7F],
+            &[0xE4, 0x12, 0x40],
+        ] {
+            let message = MidiMessage::parse(bytes).unwrap();
+            assert_eq!(message.encode(&mut buf), bytes);
+        }
+        let off = MidiMessage::from_note_event(2, NoteEvent::Off { note: 64 });
+        assert_eq!(off.encode(&mut buf), [0x82, 64, 0]);
+    }
+
+    #[test]
+    fn test_note_event() {
+        let on = MidiMessage::parse(&[0x90, 64, 1]).unwrap();
+        assert_eq!(
+            on.to_note_event(),
+            Some(NoteEvent::On {
+                note: 64,
+                velocity: 1
+            })
+        );
+        let cc = MidiMessage::parse(&[0xB3, 1, 0]).unwrap();
+        assert_eq!(cc.to_note_event(), None);
+        assert_eq!(cc.get_channel(), 3);
+    }
+
+    #[test]
+    fn test_feed_voices() {
+        use crate::synth::subtractive::SubtractiveVoice;
+
+        let mut allocator = VoiceAllocator::new([SubtractiveVoice::new(), SubtractiveVoice::new()]);
+        let mut feed =
+            |bytes: &[u8]| feed_voices(&mut allocator, MidiMessage::parse(bytes).unwrap());
+        assert_eq!(feed(&[0x90, 60, 100]), None);
+        assert_eq!(feed(&[0x90, 64, 100]), None);
+        assert_eq!(feed(&[0xE0, 0, 0x60]), None);
+        let pressure = MidiMessage::parse(&[0xD0, 10]).unwrap();
+        assert_eq!(feed(&[0xD0, 10]), Some(pressure));
+        assert_eq!(allocator.get_active_count(), 2);
+        assert_eq!(allocator.get_pitch_bend(), 4_096);
+
+        feed_voices(&mut allocator, MidiMessage::parse(&[0xB0, 123, 0]).unwrap());
+        let mut out = [0_i16; 1_024];
+        for _ in 0..100 {
+            allocator.render(&mut out);
+        }
+        assert_eq!(allocator.get_active_count(), 0);
+    }
+}
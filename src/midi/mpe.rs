@@ -0,0 +1,326 @@
+// MIDI Polyphonic Expression, where every note has its own channel for
+// pitch bend, pressure and timbre.
+
+use crate::midi::{MidiMessage, CC_ALL_NOTES_OFF};
+use crate::synth::voice::{Voice, BEND_MAX};
+use crate::util::diag;
+use crate::util::tuning::Tuning;
+use crate::util::units::{mHz, Cents};
+
+/// Controller of the timbre dimension
+pub const CC_TIMBRE: u8 = 74;
+/// Pitch bend range of the member channels in semitones, as recommended by
+/// the MPE specification
+pub const MEMBER_BEND_RANGE: u8 = 48;
+/// Pitch bend range of the master channel in semitones
+pub const MASTER_BEND_RANGE: u8 = 2;
+/// Samples rendered per voice at once by [MpeSynth::render]
+const BLOCK: usize = 64;
+
+/// Expression of a channel
+#[derive(Debug, Clone, Copy, Default)]
+struct Expression {
+    bend: i16,
+    pressure: i16,
+    timbre: i16,
+}
+
+/// Bookkeeping of a voice
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    // Channel and note of the voice, kept during the release
+    channel: u8,
+    note: u8,
+    held: bool,
+}
+
+/// Converts a 7-bit controller value to the range of i16::MAX.
+fn expression(value: u8) -> i16 {
+    (value.min(127) as i32 * i16::MAX as i32 / 127) as i16
+}
+
+/// MPE synth of `N` voices in the lower zone
+///
+/// Channel 0 is the master channel and the member channels follow it. Each
+/// note is assigned to a voice together with its channel, and the pitch
+/// bend, channel pressure and CC74 of that channel only reach that voice,
+/// through [Voice::set_mfreq], [Voice::set_pressure] and
+/// [Voice::set_timbre]. The pitch bend of the master channel bends all
+/// voices. Expression that arrives before the note on, as MPE controllers
+/// send it, applies from the start of the note.
+///
+/// ```
+/// use isopod::midi::mpe::MpeSynth;
+/// use isopod::midi::MidiMessage;
+/// use isopod::synth::subtractive::SubtractiveVoice;
+///
+/// let mut synth = MpeSynth::new([(); 4].map(|_| SubtractiveVoice::new()));
+/// synth.handle(MidiMessage::NoteOn { channel: 1, note: 60, velocity: 100 });
+/// synth.handle(MidiMessage::NoteOn { channel: 2, note: 64, velocity: 100 });
+/// // Bends only the second note
+/// synth.handle(MidiMessage::PitchBend { channel: 2, value: 2_048 });
+/// let mut out = [0_i16; 256];
+/// synth.render(&mut out);
+/// ```
+pub struct MpeSynth<V: Voice, const N: usize> {
+    voices: [V; N],
+    slots: [Slot; N],
+    channels: [Expression; 16],
+    member_bend_range: u8,
+    master_bend_range: u8,
+    tuning: Tuning,
+    // Next voice to try
+    next: usize,
+}
+
+impl<V: Voice, const N: usize> MpeSynth<V, N> {
+    pub fn new(voices: [V; N]) -> Self {
+        Self {
+            voices,
+            slots: [Slot::default(); N],
+            channels: [Expression::default(); 16],
+            member_bend_range: MEMBER_BEND_RANGE,
+            master_bend_range: MASTER_BEND_RANGE,
+            tuning: Tuning::equal(),
+            next: 0,
+        }
+    }
+
+    /// Returns the pitch of voice `i` with the bends of its channel and the
+    /// master channel.
+    fn voice_mfreq(&self, i: usize) -> mHz {
+        let slot = &self.slots[i];
+        let cents = |bend: i16, range: u8| bend as i32 * range as i32 * 100 / (BEND_MAX as i32 + 1);
+        let mut bend = cents(self.channels[0].bend, self.master_bend_range);
+        if slot.channel != 0 {
+            bend += cents(
+                self.channels[slot.channel as usize].bend,
+                self.member_bend_range,
+            );
+        }
+        let mfreq = self.tuning.get_mfreq(slot.note).unwrap_or(mHz(0));
+        mfreq.transpose(Cents(bend))
+    }
+
+    /// Applies the expression of its channel to voice `i`.
+    fn update_voice(&mut self, i: usize) {
+        let expression = self.channels[self.slots[i].channel as usize];
+        let mfreq = self.voice_mfreq(i);
+        let voice = &mut self.voices[i];
+        voice.set_mfreq(mfreq);
+        voice.set_pressure(expression.pressure);
+        voice.set_timbre(expression.timbre);
+    }
+
+    /// Applies the expression to the active voices of `channel`, or of all
+    /// channels for the master channel.
+    fn update_channel(&mut self, channel: u8) {
+        for i in 0..N {
+            if self.voices[i].is_active() && (channel == 0 || self.slots[i].channel == channel) {
+                self.update_voice(i);
+            }
+        }
+    }
+
+    fn note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        if N == 0 || self.tuning.get_mfreq(note).is_none() {
+            return;
+        }
+        // Free voices first, then the voice after the last note on
+        let i = (0..N)
+            .map(|i| (self.next + i) % N)
+            .find(|i| !self.voices[*i].is_active())
+            .unwrap_or(self.next);
+        self.slots[i] = Slot {
+            channel,
+            note,
+            held: true,
+        };
+        self.voices[i].note_on(note, velocity);
+        self.update_voice(i);
+        self.next = (i + 1) % N;
+    }
+
+    fn note_off(&mut self, channel: u8, note: u8) {
+        for (voice, slot) in self.voices.iter_mut().zip(self.slots.iter_mut()) {
+            if slot.held && slot.channel == channel && slot.note == note {
+                slot.held = false;
+                voice.note_off();
+            }
+        }
+    }
+
+    /// Releases all voices.
+    pub fn all_notes_off(&mut self) {
+        for (voice, slot) in self.voices.iter_mut().zip(self.slots.iter_mut()) {
+            if slot.held {
+                slot.held = false;
+                voice.note_off();
+            }
+        }
+    }
+
+    /// Plays note messages and routes the expression of the channels to
+    /// their voices. Returns the messages that aren't part of MPE, e.g. for
+    /// a [crate::midi::cc::CcMap].
+    pub fn handle(&mut self, message: MidiMessage) -> Option<MidiMessage> {
+        match message {
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => self.note_on(channel, note, velocity),
+            MidiMessage::NoteOff { channel, note } => self.note_off(channel, note),
+            MidiMessage::PitchBend { channel, value } => {
+                self.channels[channel as usize & 0x0F].bend = value.clamp(-BEND_MAX - 1, BEND_MAX);
+                self.update_channel(channel & 0x0F);
+            }
+            MidiMessage::ChannelPressure { channel, pressure } if channel & 0x0F != 0 => {
+                self.channels[channel as usize & 0x0F].pressure = expression(pressure);
+                self.update_channel(channel & 0x0F);
+            }
+            MidiMessage::ControlChange {
+                channel,
+                controller: CC_TIMBRE,
+                value,
+            } if channel & 0x0F != 0 => {
+                self.channels[channel as usize & 0x0F].timbre = expression(value);
+                self.update_channel(channel & 0x0F);
+            }
+            MidiMessage::ControlChange {
+                controller: CC_ALL_NOTES_OFF,
+                ..
+            } => self.all_notes_off(),
+            _ => return Some(message),
+        }
+        None
+    }
+
+    /// Fills `out` with the saturated sum of all active voices.
+    pub fn render(&mut self, out: &mut [i16]) {
+        let mut buf = [0_i16; BLOCK];
+        for chunk in out.chunks_mut(BLOCK) {
+            let mut acc = [0_i32; BLOCK];
+            let buf = &mut buf[..chunk.len()];
+            for voice in self.voices.iter_mut().filter(|v| v.is_active()) {
+                voice.render(buf);
+                for (a, x) in acc.iter_mut().zip(buf.iter()) {
+                    *a += *x as i32;
+                }
+            }
+            for (y, a) in chunk.iter_mut().zip(acc.iter()) {
+                *y = diag::clip(*a as i64);
+            }
+        }
+    }
+
+    /// Sets the tuning of all notes, including the sounding ones. Unmapped
+    /// notes are ignored.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+        self.update_channel(0);
+    }
+
+    /// Sets the pitch bend range of the member channels in semitones.
+    pub fn set_member_bend_range(&mut self, semitones: u8) {
+        self.member_bend_range = semitones;
+        self.update_channel(0);
+    }
+
+    /// Sets the pitch bend range of the master channel in semitones.
+    pub fn set_master_bend_range(&mut self, semitones: u8) {
+        self.master_bend_range = semitones;
+        self.update_channel(0);
+    }
+
+    /// Returns the number of voices producing sound.
+    pub fn get_active_count(&self) -> usize {
+        self.voices.iter().filter(|v| v.is_active()).count()
+    }
+
+    /// Returns all voices, e.g. for changing their parameters.
+    pub fn get_voices_mut(&mut self) -> &mut [V; N] {
+        &mut self.voices
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::voice::test::TestVoice;
+
+    #[test]
+    fn test_mpe_expression() {
+        let mut synth = MpeSynth::new([(); 3].map(|_| TestVoice::default()));
+        let on = |channel, note| MidiMessage::NoteOn {
+            channel,
+            note,
+            velocity: 100,
+        };
+        // Expression before the note on applies to the new note
+        synth.handle(MidiMessage::ChannelPressure {
+            channel: 1,
+            pressure: 127,
+        });
+        synth.handle(on(1, 69));
+        synth.handle(on(2, 69));
+        // A whole tone up with the range of 48 semitones
+        synth.handle(MidiMessage::PitchBend {
+            channel: 2,
+            value: 341,
+        });
+        synth.handle(MidiMessage::ControlChange {
+            channel: 2,
+            controller: CC_TIMBRE,
+            value: 64,
+        });
+        let voices = synth.get_voices_mut();
+        assert_eq!((voices[0].mfreq, voices[0].pressure), (440_000, i16::MAX));
+        assert_eq!(voices[0].timbre, 0);
+        assert!(
+            voices[1].mfreq.abs_diff(493_883) < 500,
+            "{}",
+            voices[1].mfreq
+        );
+        assert_eq!((voices[1].pressure, voices[1].timbre), (0, 16_512));
+
+        // The master channel bends all notes
+        synth.handle(MidiMessage::PitchBend {
+            channel: 0,
+            value: -BEND_MAX - 1,
+        });
+        let voices = synth.get_voices_mut();
+        assert!(
+            voices[0].mfreq.abs_diff(391_995) < 400,
+            "{}",
+            voices[0].mfreq
+        );
+        assert!(
+            voices[1].mfreq.abs_diff(440_000) < 500,
+            "{}",
+            voices[1].mfreq
+        );
+
+        // Notes are released per channel
+        let cc = MidiMessage::ControlChange {
+            channel: 1,
+            controller: 1,
+            value: 0,
+        };
+        assert_eq!(synth.handle(cc), Some(cc));
+        synth.handle(MidiMessage::NoteOff {
+            channel: 1,
+            note: 69,
+        });
+        assert_eq!(synth.get_active_count(), 1);
+        let mut out = [0_i16; 100];
+        synth.render(&mut out);
+        assert!(out.iter().all(|y| *y == 100));
+        synth.handle(MidiMessage::ControlChange {
+            channel: 0,
+            controller: CC_ALL_NOTES_OFF,
+            value: 0,
+        });
+        assert_eq!(synth.get_active_count(), 0);
+    }
+}
@@ -0,0 +1,89 @@
+// Live MIDI output to a port of the operating system through midir.
+
+use crate::midi::clock::ClockMessage;
+use crate::midi::MidiMessage;
+use crate::synth::NoteEvent;
+
+/// Reason why a MIDI output couldn't be opened or written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiOutputError {
+    /// The MIDI system of the operating system isn't available.
+    Init,
+    /// No port matches the name.
+    NoPort,
+    /// The port couldn't be opened.
+    Connect,
+    /// The message couldn't be sent.
+    Send,
+}
+
+/// Connection to a MIDI output port
+///
+/// Sends the note events of sequencers and arpeggiators, or any other
+/// message, to external synths. Messages are sent immediately, so call it
+/// from the control thread or once per block from the audio thread. The
+/// port stays open as long as the output lives.
+pub struct MidiOutput {
+    connection: midir::MidiOutputConnection,
+    channel: u8,
+}
+
+impl MidiOutput {
+    /// Returns the names of all output ports.
+    pub fn port_names() -> Result<Vec<String>, MidiOutputError> {
+        let output = midir::MidiOutput::new("isopod").map_err(|_| MidiOutputError::Init)?;
+        Ok(output
+            .ports()
+            .iter()
+            .filter_map(|port| output.port_name(port).ok())
+            .collect())
+    }
+
+    /// Connects to the first port whose name contains `name`, or to the
+    /// first port at all without a name.
+    pub fn connect(name: Option<&str>) -> Result<Self, MidiOutputError> {
+        let output = midir::MidiOutput::new("isopod").map_err(|_| MidiOutputError::Init)?;
+        let port = output
+            .ports()
+            .into_iter()
+            .find(|port| match (name, output.port_name(port)) {
+                (None, _) => true,
+                (Some(name), Ok(port_name)) => port_name.contains(name),
+                (Some(_), Err(_)) => false,
+            })
+            .ok_or(MidiOutputError::NoPort)?;
+        let connection = output
+            .connect(&port, "isopod-out")
+            .map_err(|_| MidiOutputError::Connect)?;
+        Ok(Self {
+            connection,
+            channel: 0,
+        })
+    }
+
+    /// Sets the channel of [MidiOutput::send_event] from 0 to 15.
+    pub fn set_channel(&mut self, channel: u8) {
+        self.channel = channel & 0x0F;
+    }
+
+    /// Sends `message`.
+    pub fn send(&mut self, message: MidiMessage) -> Result<(), MidiOutputError> {
+        let mut buf = [0; 3];
+        self.connection
+            .send(message.encode(&mut buf))
+            .map_err(|_| MidiOutputError::Send)
+    }
+
+    /// Sends a note event on the channel of the output.
+    pub fn send_event(&mut self, event: NoteEvent) -> Result<(), MidiOutputError> {
+        self.send(MidiMessage::from_note_event(self.channel, event))
+    }
+
+    /// Sends a clock message, e.g. to drive external sequencers.
+    pub fn send_clock(&mut self, message: ClockMessage) -> Result<(), MidiOutputError> {
+        let mut buf = [0; 3];
+        self.connection
+            .send(message.encode(&mut buf))
+            .map_err(|_| MidiOutputError::Send)
+    }
+}
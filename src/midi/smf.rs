@@ -0,0 +1,391 @@
+// Standard MIDI File parser and sample accurate player.
+
+use crate::midi::{feed_voices, MidiMessage};
+use crate::synth::voice::{Voice, VoiceAllocator};
+use crate::util::units::mHz;
+use alloc::vec::Vec;
+
+/// Tempo until the first tempo event in µs per quarter note (120 BPM)
+const DEFAULT_TEMPO: u64 = 500_000;
+
+/// Reason why a file couldn't be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmfError {
+    /// The file doesn't start with a valid header chunk.
+    Header,
+    /// Format 2 and SMPTE time division aren't supported.
+    Unsupported,
+    /// A chunk or an event ends early.
+    Truncated,
+}
+
+/// Message of a [MidiFile] with its time since the start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedMessage {
+    pub us: u64,
+    pub message: MidiMessage,
+}
+
+/// Parsed Standard MIDI File of format 0 or 1
+///
+/// The messages of all tracks are merged in time order, and tempo changes
+/// are already applied to their times. Other meta events and system
+/// exclusive messages are skipped.
+pub struct MidiFile {
+    messages: Vec<TimedMessage>,
+}
+
+/// Reads chunks, variable length quantities and bytes from a slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Result<u8, SmfError> {
+        let b = *self.bytes.get(self.pos).ok_or(SmfError::Truncated)?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SmfError> {
+        let end = self.pos.checked_add(len).ok_or(SmfError::Truncated)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(SmfError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, SmfError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, SmfError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn varlen(&mut self) -> Result<u32, SmfError> {
+        let mut value = 0_u32;
+        for _ in 0..4 {
+            let b = self.u8()?;
+            value = value << 7 | (b & 0x7F) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SmfError::Truncated)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+}
+
+/// Message with its time in ticks, before the tempo is applied
+struct TickMessage {
+    tick: u64,
+    message: Option<MidiMessage>,
+    tempo: Option<u64>,
+}
+
+fn parse_track(track: &[u8], out: &mut Vec<TickMessage>) -> Result<(), SmfError> {
+    let mut reader = Reader {
+        bytes: track,
+        pos: 0,
+    };
+    let mut tick = 0_u64;
+    let mut running = 0_u8;
+    while !reader.is_empty() {
+        tick += reader.varlen()? as u64;
+        let mut status = reader.u8()?;
+        match status {
+            0xFF => {
+                let kind = reader.u8()?;
+                let len = reader.varlen()? as usize;
+                let data = reader.take(len)?;
+                if kind == 0x51 && len == 3 {
+                    let tempo = (data[0] as u64) << 16 | (data[1] as u64) << 8 | data[2] as u64;
+                    out.push(TickMessage {
+                        tick,
+                        message: None,
+                        tempo: Some(tempo),
+                    });
+                } else if kind == 0x2F {
+                    break;
+                }
+                continue;
+            }
+            0xF0 | 0xF7 => {
+                let len = reader.varlen()? as usize;
+                reader.take(len)?;
+                continue;
+            }
+            _ => {}
+        }
+        // Running status repeats the last status byte
+        if status < 0x80 {
+            status = running;
+            reader.pos -= 1;
+        }
+        running = status;
+        let len = match status & 0xF0 {
+            0xC0 | 0xD0 => 1,
+            0x80..=0xE0 => 2,
+            _ => return Err(SmfError::Truncated),
+        };
+        let mut bytes = [status, 0, 0];
+        bytes[1..=len].copy_from_slice(reader.take(len)?);
+        if let Some(message) = MidiMessage::parse(&bytes[..=len]) {
+            out.push(TickMessage {
+                tick,
+                message: Some(message),
+                tempo: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+impl MidiFile {
+    /// Parses the contents of a file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, SmfError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(4).map_err(|_| SmfError::Header)? != b"MThd" {
+            return Err(SmfError::Header);
+        }
+        // Longer headers are allowed and their extra bytes skipped
+        let len = reader.u32().map_err(|_| SmfError::Header)? as usize;
+        if len < 6 {
+            return Err(SmfError::Header);
+        }
+        let mut header = Reader {
+            bytes: reader.take(len).map_err(|_| SmfError::Header)?,
+            pos: 0,
+        };
+        let format = header.u16()?;
+        let tracks = header.u16()?;
+        let division = header.u16()?;
+        if format > 1 || division & 0x8000 != 0 || division == 0 {
+            return Err(SmfError::Unsupported);
+        }
+
+        let mut ticks = Vec::new();
+        for _ in 0..tracks {
+            let kind = reader.take(4)?;
+            let len = reader.u32()? as usize;
+            let chunk = reader.take(len)?;
+            // Unknown chunks are skipped as the standard demands
+            if kind == b"MTrk" {
+                parse_track(chunk, &mut ticks)?;
+            }
+        }
+        // Stable, so the order within a tick and track is kept
+        ticks.sort_by_key(|m| m.tick);
+
+        // Elapsed time in µs times the division, to keep it exact
+        let (mut time, mut tick, mut tempo) = (0_u64, 0_u64, DEFAULT_TEMPO);
+        let mut messages = Vec::with_capacity(ticks.len());
+        for m in ticks {
+            // Saturates for crafted files with huge gaps at a slow tempo
+            time = time.saturating_add((m.tick - tick).saturating_mul(tempo));
+            tick = m.tick;
+            if let Some(t) = m.tempo {
+                tempo = t;
+            }
+            if let Some(message) = m.message {
+                messages.push(TimedMessage {
+                    us: time / division as u64,
+                    message,
+                });
+            }
+        }
+        Ok(Self { messages })
+    }
+
+    /// Returns all messages in time order.
+    pub fn get_messages(&self) -> &[TimedMessage] {
+        &self.messages
+    }
+
+    /// Returns the time of the last message in µs.
+    pub fn get_duration_us(&self) -> u64 {
+        self.messages.last().map_or(0, |m| m.us)
+    }
+}
+
+/// Plays a [MidiFile] on a [VoiceAllocator]
+///
+/// Blocks are split at the messages, so every message takes effect at the
+/// exact sample, independent of the block size. This makes it suitable for
+/// offline rendering.
+pub struct MidiFilePlayer {
+    file: MidiFile,
+    next: usize,
+    sample: u64,
+    msample_rate: mHz,
+}
+
+impl MidiFilePlayer {
+    pub fn new(file: MidiFile) -> Self {
+        Self {
+            file,
+            next: 0,
+            sample: 0,
+            msample_rate: mHz(44_100_000),
+        }
+    }
+
+    fn message_sample(&self, m: &TimedMessage) -> u64 {
+        (m.us as u128 * self.msample_rate.0 as u128 / 1_000_000_000) as u64
+    }
+
+    /// Fills `out` with the next samples of `allocator` and applies the
+    /// messages that fall into the block, see [feed_voices].
+    pub fn render<V: Voice, const N: usize>(
+        &mut self,
+        allocator: &mut VoiceAllocator<V, N>,
+        out: &mut [i16],
+    ) {
+        let mut start = 0;
+        while start < out.len() {
+            let end = self.sample + (out.len() - start) as u64;
+            let split = match self.file.messages.get(self.next) {
+                Some(m) if self.message_sample(m) < end => self.message_sample(m).max(self.sample),
+                _ => end,
+            };
+            let len = (split - self.sample) as usize;
+            allocator.render(&mut out[start..start + len]);
+            start += len;
+            self.sample = split;
+            while let Some(m) = self.file.messages.get(self.next) {
+                if self.message_sample(m) > self.sample {
+                    break;
+                }
+                feed_voices(allocator, m.message);
+                self.next += 1;
+            }
+        }
+    }
+
+    /// True after the last message was played.
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.file.messages.len()
+    }
+
+    /// Jumps back to the start.
+    pub fn rewind(&mut self) {
+        self.next = 0;
+        self.sample = 0;
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::voice::test::TestVoice;
+
+    /// Format 1 file with a tempo track and a note track at 96 ticks per
+    /// quarter
+    fn smf_bytes() -> Vec<u8> {
+        let mut bytes = b"MThd\0\0\0\x06\0\x01\0\x02\0\x60".to_vec();
+        // 250 ms per quarter, then 1 s per quarter after 96 ticks
+        let tempo = [
+            0x00, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90, 0x60, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let notes = [
+            // Note on after a quarter, running status, system exclusive
+            0x60, 0x90, 60, 100, 0x00, 64, 100, 0x00, 0xF0, 0x01, 0xF7,
+            // Note off as note on with velocity 0 after an eighth
+            0x30, 60, 0, 0x00, 0x80, 64, 0, 0x00, 0xFF, 0x2F, 0x00,
+        ];
+        for track in [&tempo[..], &notes[..]] {
+            bytes.extend_from_slice(b"MTrk");
+            bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(track);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_smf_parse() {
+        let file = MidiFile::parse(&smf_bytes()).unwrap();
+        let times: Vec<u64> = file.get_messages().iter().map(|m| m.us).collect();
+        assert_eq!(times, [250_000, 250_000, 750_000, 750_000]);
+        assert_eq!(
+            file.get_messages()[1].message,
+            MidiMessage::NoteOn {
+                channel: 0,
+                note: 64,
+                velocity: 100
+            }
+        );
+        assert_eq!(file.get_duration_us(), 750_000);
+
+        assert_eq!(MidiFile::parse(b"RIFF").err(), Some(SmfError::Header));
+        let mut format2 = smf_bytes();
+        format2[9] = 2;
+        assert_eq!(MidiFile::parse(&format2).err(), Some(SmfError::Unsupported));
+        let bytes = smf_bytes();
+        assert_eq!(
+            MidiFile::parse(&bytes[..bytes.len() - 4]).err(),
+            Some(SmfError::Truncated)
+        );
+
+        // The extra bytes of a longer header are skipped
+        let mut long = b"MThd\0\0\0\x08\0\x01\0\x02\0\x60\xAB\xCD".to_vec();
+        long.extend_from_slice(&smf_bytes()[14..]);
+        assert_eq!(
+            MidiFile::parse(&long).unwrap().get_messages(),
+            file.get_messages()
+        );
+        let mut short = smf_bytes();
+        short[7] = 5;
+        assert_eq!(MidiFile::parse(&short).err(), Some(SmfError::Header));
+    }
+
+    #[test]
+    fn test_smf_time_saturates() {
+        // At the slowest tempo, a note after 2^41 ticks of unknown meta
+        // events
+        let mut track = vec![0x00, 0xFF, 0x51, 0x03, 0xFF, 0xFF, 0xFF];
+        for _ in 0..8_192 {
+            track.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0x7F, 0xFF, 0x01, 0x00]);
+        }
+        track.extend_from_slice(&[0x00, 0x90, 60, 100, 0x00, 0xFF, 0x2F, 0x00]);
+        let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01\0\x01MTrk".to_vec();
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&track);
+        let file = MidiFile::parse(&bytes).unwrap();
+        assert_eq!(file.get_duration_us(), u64::MAX);
+    }
+
+    #[test]
+    fn test_smf_player() {
+        let mut player = MidiFilePlayer::new(MidiFile::parse(&smf_bytes()).unwrap());
+        player.set_msample_rate(mHz(1_000_000));
+        let mut allocator = VoiceAllocator::new([TestVoice::default(), TestVoice::default()]);
+        // Odd block sizes don't move the messages
+        let mut out = vec![0_i16; 1_000];
+        for chunk in out.chunks_mut(77) {
+            player.render(&mut allocator, chunk);
+        }
+        assert!(out[..250].iter().all(|y| *y == 0));
+        assert!(
+            out[250..750].iter().all(|y| *y == 200),
+            "{:?}",
+            &out[245..255]
+        );
+        assert!(out[750..].iter().all(|y| *y == 0));
+        assert!(player.is_finished());
+
+        player.rewind();
+        assert!(!player.is_finished());
+    }
+}
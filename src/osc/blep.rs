@@ -0,0 +1,235 @@
+// Band limited saw and square oscillators using polynomial band limited
+// steps (PolyBLEP).
+
+use crate::preset::Preset;
+use crate::util::note::Note;
+use crate::util::param::{check_mfreq, check_msample_rate, ParamError};
+use crate::util::units::{mHz, Frequency, Hz};
+
+/// Waveform of a [BlepOscillator]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Waveform {
+    Saw,
+    Square,
+}
+
+/// Parameters of a [BlepOscillator]. The frequency is set by the notes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlepParams {
+    pub waveform: Waveform,
+    pub pulse_width: u32,
+}
+
+/// Saw or square oscillator with reduced aliasing
+///
+/// The naive waveforms are generated from a phase accumulator and every
+/// discontinuity is smoothed by a polynomial approximation of a band
+/// limited step. This removes most of the aliasing at the cost of two
+/// divisions per discontinuity. The phase spans the full u32 range.
+pub struct BlepOscillator {
+    waveform: Waveform,
+
+    phi: u32,
+    delta_phi: u32,
+    // Phase offset of the falling edge of the square
+    pulse_width: u32,
+
+    mfreq: mHz,
+    msample_rate: mHz,
+}
+
+impl BlepOscillator {
+    pub fn new() -> Self {
+        let mut s = Self {
+            waveform: Waveform::Saw,
+
+            phi: 0,
+            delta_phi: 0,
+            pulse_width: 1 << 31,
+
+            mfreq: Hz(440).to_mHz(),
+            msample_rate: mHz(44_100_000),
+        };
+        s.update_delta_phi();
+        s
+    }
+
+    fn update_delta_phi(&mut self) {
+        self.delta_phi = ((self.mfreq.0 as u64) << 32)
+            .checked_div(self.msample_rate.0 as u64)
+            .unwrap_or(0)
+            .min(u32::MAX as u64 / 2) as u32;
+    }
+
+    /// Correction of the step at phase 0 in 1 << 15
+    #[inline]
+    fn blep(&self, phi: u32) -> i32 {
+        let dt = self.delta_phi as u64;
+        if (phi as u64) < dt {
+            // Just after the step, x in [0, 1)
+            let x = (((phi as u64) << 15) / dt) as i32;
+            2 * x - ((x * x) >> 15) - (1 << 15)
+        } else if phi as u64 > u32::MAX as u64 - dt {
+            // Just before the step, x in (-1, 0]
+            let x = -(((((1_u64 << 32) - phi as u64) << 15) / dt) as i32);
+            ((x * x) >> 15) + 2 * x + (1 << 15)
+        } else {
+            0
+        }
+    }
+
+    /// Band limited saw from -1 to 1 in 1 << 15 at phase `phi`
+    #[inline]
+    fn saw(&self, phi: u32) -> i32 {
+        ((phi >> 16) as i32 - (1 << 15)) - self.blep(phi)
+    }
+
+    /// Returns the next sample.
+    #[inline]
+    pub fn _next(&mut self) -> i16 {
+        let y = match self.waveform {
+            Waveform::Saw => self.saw(self.phi),
+            Waveform::Square => {
+                // The difference of two shifted saws is a pulse. The offset
+                // removes its DC.
+                let offset = (self.pulse_width >> 16) as i32 - (1 << 15);
+                self.saw(self.phi) - self.saw(self.phi.wrapping_add(self.pulse_width)) + offset
+            }
+        };
+        self.phi = self.phi.wrapping_add(self.delta_phi);
+        y.clamp(-i16::MAX as i32, i16::MAX as i32) as i16
+    }
+
+    /// Fills `out` with the next samples.
+    pub fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self._next();
+        }
+    }
+
+    /// Sets the waveform.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    /// Sets the pulse width of the square, where the full u32 range
+    /// corresponds to one period.
+    pub fn set_pulse_width(&mut self, pulse_width: u32) {
+        self.pulse_width = pulse_width;
+    }
+
+    /// Sets the phase, where the full u32 range corresponds to one period.
+    pub fn set_phase(&mut self, phase: u32) {
+        self.phi = phase;
+    }
+
+    /// Sets the frequency in mHz. Frequencies above Nyquist play at
+    /// Nyquist, see [BlepOscillator::try_set_mfreq] for a checked variant.
+    pub fn set_mfreq(&mut self, mfreq: mHz) {
+        self.mfreq = mfreq;
+        self.update_delta_phi();
+    }
+
+    /// Sets the frequency in mHz or fails if it is above Nyquist.
+    pub fn try_set_mfreq(&mut self, mfreq: mHz) -> Result<(), ParamError> {
+        check_mfreq(mfreq, self.msample_rate)?;
+        self.set_mfreq(mfreq);
+        Ok(())
+    }
+
+    /// Sets the frequency to the equal tempered frequency of `note`.
+    pub fn set_note(&mut self, note: Note) {
+        self.set_mfreq(note.to_mfreq());
+    }
+
+    /// Sets the frequency in Hz.
+    pub fn set_freq(&mut self, freq: Hz) {
+        self.set_mfreq(freq.to_mHz());
+    }
+
+    /// Sets the sample rate in mHz. A rate of 0 is raised to 1 mHz, see
+    /// [BlepOscillator::try_set_msample_rate] for a checked variant.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.update_delta_phi();
+    }
+
+    /// Sets the sample rate in mHz or fails if it is 0.
+    pub fn try_set_msample_rate(&mut self, msample_rate: mHz) -> Result<(), ParamError> {
+        check_msample_rate(msample_rate)?;
+        self.set_msample_rate(msample_rate);
+        Ok(())
+    }
+}
+
+impl Default for BlepOscillator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for BlepOscillator {
+    type Params = BlepParams;
+
+    fn get_params(&self) -> BlepParams {
+        BlepParams {
+            waveform: self.waveform,
+            pulse_width: self.pulse_width,
+        }
+    }
+
+    fn set_params(&mut self, params: &BlepParams) {
+        self.waveform = params.waveform;
+        self.pulse_width = params.pulse_width;
+    }
+}
+
+impl Iterator for BlepOscillator {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self._next())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_blep_saw() {
+        let mut osc = BlepOscillator::new();
+        osc.set_msample_rate(mHz(44_100_000));
+        osc.set_freq(Hz(441));
+        let out: Vec<i16> = osc.by_ref().take(100).collect();
+        // Rising saw, one period per 100 samples without DC
+        assert!(out[10] < out[11] && out[60] < out[61]);
+        let mean = out.iter().map(|y| *y as i32).sum::<i32>() / 100;
+        assert!(mean.abs() < 400, "{}", mean);
+        // The step is spread over the samples around the wrap
+        assert!(out[99] > 20_000 && out[1] < -20_000, "{:?}", out);
+        assert!(out[0].abs() < 10_000, "{:?}", out);
+    }
+
+    #[test]
+    fn test_blep_note() {
+        let mut a = BlepOscillator::new();
+        let mut b = BlepOscillator::new();
+        a.set_note(Note::A4);
+        b.set_freq(Hz(440));
+        assert!(a.take(100).eq(b.take(100)));
+    }
+
+    #[test]
+    fn test_blep_square() {
+        let mut osc = BlepOscillator::new();
+        osc.set_waveform(Waveform::Square);
+        osc.set_freq(Hz(441));
+        let out: Vec<i16> = osc.take(100).collect();
+        assert!(out[25] < -30_000 && out[75] > 30_000, "{:?}", out);
+        let mean = out.iter().map(|y| *y as i32).sum::<i32>() / 100;
+        assert!(mean.abs() < 400, "{}", mean);
+    }
+}
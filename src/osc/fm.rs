@@ -0,0 +1,169 @@
+// Sine operator for phase modulation (FM) synthesis.
+
+use crate::osc::luts::SINE_I16;
+use crate::util::note::Note;
+use crate::util::param::{check_mfreq, check_msample_rate, ParamError};
+use crate::util::units::{mHz, Frequency, Hz};
+
+/// Shift that maps the 32-bit phase onto the 1024 entries of [SINE_I16]
+const SINE_SHIFT: u32 = 22;
+/// Fractional bits of the modulation index
+pub const INDEX_SHIFT: u32 = 8;
+
+// 2*pi as the fraction 710/113 (relative error < 1e-7)
+const TWO_PI_NUM: i64 = 710;
+const TWO_PI_DEN: i64 = 113;
+
+/// Sine oscillator with a phase modulation input
+///
+/// As on most digital FM synths, the modulator shifts the phase rather than
+/// the frequency, which keeps the pitch stable at any index. The
+/// modulation index is the phase deviation in radians at a full scale
+/// modulator with [INDEX_SHIFT] fractional bits, e.g. `2 << INDEX_SHIFT`
+/// for an index of 2. The sine is interpolated linearly between the table
+/// entries, since FM exposes the table noise.
+pub struct FmOperator {
+    phi: u32,
+    delta_phi: u32,
+    index: u32,
+
+    mfreq: mHz,
+    msample_rate: mHz,
+}
+
+impl FmOperator {
+    pub fn new() -> Self {
+        let mut s = Self {
+            phi: 0,
+            delta_phi: 0,
+            index: 1 << INDEX_SHIFT,
+
+            mfreq: Hz(440).to_mHz(),
+            msample_rate: mHz(44_100_000),
+        };
+        s.update_delta_phi();
+        s
+    }
+
+    fn update_delta_phi(&mut self) {
+        self.delta_phi = ((self.mfreq.0 as u64) << 32)
+            .checked_div(self.msample_rate.0 as u64)
+            .unwrap_or(0)
+            .min(u32::MAX as u64 / 2) as u32;
+    }
+
+    /// Returns the interpolated sine at phase `phi`.
+    #[inline]
+    pub(crate) fn sine(phi: u32) -> i16 {
+        let i = (phi >> SINE_SHIFT) as usize;
+        let a = SINE_I16[i] as i32;
+        let b = SINE_I16[(i + 1) % SINE_I16.len()] as i32;
+        let frac = ((phi >> (SINE_SHIFT - 15)) & 0x7fff) as i32;
+        (a + (((b - a) * frac) >> 15)) as i16
+    }
+
+    /// Returns the next sample with the phase shifted by `modulator` times
+    /// the modulation index.
+    #[inline]
+    pub fn process(&mut self, modulator: i16) -> i16 {
+        // Radians with 15 + INDEX_SHIFT fractional bits to full u32 periods
+        let shift =
+            (modulator as i64 * self.index as i64 * (1 << (32 - 15 - INDEX_SHIFT)) * TWO_PI_DEN)
+                / TWO_PI_NUM;
+        let y = Self::sine(self.phi.wrapping_add(shift as u32));
+        self.phi = self.phi.wrapping_add(self.delta_phi);
+        y
+    }
+
+    /// Returns the next sample without modulation.
+    #[inline]
+    pub fn _next(&mut self) -> i16 {
+        self.process(0)
+    }
+
+    /// Sets the modulation index with [INDEX_SHIFT] fractional bits.
+    pub fn set_index(&mut self, index: u32) {
+        self.index = index;
+    }
+
+    /// Returns the modulation index.
+    pub fn get_index(&self) -> u32 {
+        self.index
+    }
+
+    /// Sets the phase, where the full u32 range corresponds to one period.
+    pub fn set_phase(&mut self, phase: u32) {
+        self.phi = phase;
+    }
+
+    /// Sets the frequency in mHz. Frequencies above Nyquist play at
+    /// Nyquist, see [FmOperator::try_set_mfreq] for a checked variant.
+    pub fn set_mfreq(&mut self, mfreq: mHz) {
+        self.mfreq = mfreq;
+        self.update_delta_phi();
+    }
+
+    /// Sets the frequency in mHz or fails if it is above Nyquist.
+    pub fn try_set_mfreq(&mut self, mfreq: mHz) -> Result<(), ParamError> {
+        check_mfreq(mfreq, self.msample_rate)?;
+        self.set_mfreq(mfreq);
+        Ok(())
+    }
+
+    /// Sets the frequency to the equal tempered frequency of `note`.
+    pub fn set_note(&mut self, note: Note) {
+        self.set_mfreq(note.to_mfreq());
+    }
+
+    /// Sets the frequency in Hz.
+    pub fn set_freq(&mut self, freq: Hz) {
+        self.set_mfreq(freq.to_mHz());
+    }
+
+    /// Sets the sample rate in mHz. A rate of 0 is raised to 1 mHz, see
+    /// [FmOperator::try_set_msample_rate] for a checked variant.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.update_delta_phi();
+    }
+
+    /// Sets the sample rate in mHz or fails if it is 0.
+    pub fn try_set_msample_rate(&mut self, msample_rate: mHz) -> Result<(), ParamError> {
+        check_msample_rate(msample_rate)?;
+        self.set_msample_rate(msample_rate);
+        Ok(())
+    }
+}
+
+impl Default for FmOperator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fm_operator_sine() {
+        let mut op = FmOperator::new();
+        op.set_msample_rate(mHz(4_000_000));
+        op.set_freq(Hz(1_000));
+        let out: Vec<i16> = (0..4).map(|_| op._next()).collect();
+        assert_eq!(out, vec![0, i16::MAX, 0, -i16::MAX]);
+    }
+
+    #[test]
+    fn test_fm_operator_modulation() {
+        // A full scale modulator with index pi/2 shifts by a quarter period
+        let mut op = FmOperator::new();
+        op.set_msample_rate(mHz(4_000_000));
+        op.set_freq(Hz(1_000));
+        op.set_index(402);
+        let y = op.process(i16::MAX) as i32;
+        assert!((y - i16::MAX as i32).abs() < 100, "{}", y);
+        let y = op.process(-i16::MAX) as i32;
+        assert!(y.abs() < 100, "{}", y);
+    }
+}
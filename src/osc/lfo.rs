@@ -0,0 +1,255 @@
+// Low frequency oscillator with algorithmic shapes for modulation purposes.
+
+use crate::osc::luts::SINE_I16;
+use crate::preset::Preset;
+use crate::seq::Division;
+use crate::util::param::{check_mfreq, check_msample_rate, ParamError};
+use crate::util::units::{mHz, ms, uHz, Bpm, Frequency, Hz};
+
+/// Shift that maps the 32-bit phase onto the 1024 entries of [SINE_I16]
+const SINE_SHIFT: u32 = 22;
+
+/// Waveform of an [Lfo]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    /// Rising sawtooth
+    Saw,
+    Square,
+}
+
+/// Parameters of an [Lfo]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LfoParams {
+    pub shape: LfoShape,
+    pub mfreq: mHz,
+}
+
+/// Low frequency oscillator
+///
+/// The phase accumulator spans the full `u32` range, so wrapping is free and
+/// the output is bipolar in [-i16::MAX, i16::MAX]. All shapes start at the
+/// zero crossing (except the sawtooth, which starts at its minimum).
+pub struct Lfo {
+    shape: LfoShape,
+
+    // Phase accumulator
+    phi: u32,
+    // Frequency dependent phase increment
+    delta_phi: u32,
+
+    ufreq: uHz,
+    msample_rate: mHz,
+}
+
+impl Lfo {
+    pub fn new() -> Self {
+        let mut s = Self {
+            shape: LfoShape::Sine,
+
+            phi: 0,
+            delta_phi: 0,
+
+            ufreq: Hz(1).to_uHz(),
+            msample_rate: mHz(44_100_000),
+        };
+        s.update_delta_phi();
+        s
+    }
+
+    fn update_delta_phi(&mut self) {
+        let usample_rate = self.msample_rate.0 as u128 * 1_000;
+        self.delta_phi = ((((self.ufreq.0 as u128) << 32) + usample_rate / 2) / usample_rate)
+            .min(u32::MAX as u128 / 2) as u32;
+    }
+
+    /// Returns the value at the current phase without advancing it.
+    #[inline]
+    pub fn value(&self) -> i16 {
+        match self.shape {
+            LfoShape::Sine => SINE_I16[(self.phi >> SINE_SHIFT) as usize],
+            LfoShape::Triangle => {
+                let v = (self.phi.wrapping_add(1 << 30) >> 15) as i32;
+                let tri = if v < 65536 { v - 32768 } else { 98303 - v };
+                tri.max(-(i16::MAX as i32)) as i16
+            }
+            LfoShape::Saw => ((self.phi >> 16) as i32 - 32768).max(-(i16::MAX as i32)) as i16,
+            LfoShape::Square => {
+                if self.phi < (1 << 31) {
+                    i16::MAX
+                } else {
+                    -i16::MAX
+                }
+            }
+        }
+    }
+
+    /// Sets the waveform.
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.shape = shape;
+    }
+
+    /// Resets the phase to 0, e.g. to realign the LFO to a downbeat.
+    pub fn reset(&mut self) {
+        self.phi = 0;
+    }
+
+    /// Sets the frequency in mHz. Frequencies above Nyquist play at
+    /// Nyquist, see [Lfo::try_set_mfreq] for a checked variant.
+    pub fn set_mfreq(&mut self, mfreq: mHz) {
+        self.set_ufreq(mfreq.to_uHz());
+    }
+
+    /// Sets the frequency in uHz, e.g. for very slow sweeps. At 44.1 kHz
+    /// the phase increment resolves about 10 uHz.
+    pub fn set_ufreq(&mut self, ufreq: uHz) {
+        self.ufreq = ufreq;
+        self.update_delta_phi();
+    }
+
+    /// Sets the frequency in mHz or fails if it is above Nyquist.
+    pub fn try_set_mfreq(&mut self, mfreq: mHz) -> Result<(), ParamError> {
+        check_mfreq(mfreq, self.msample_rate)?;
+        self.set_mfreq(mfreq);
+        Ok(())
+    }
+
+    /// Sets the frequency in Hz.
+    pub fn set_freq(&mut self, freq: Hz) {
+        self.set_mfreq(freq.to_mHz());
+    }
+
+    /// Sets the rate to `cycles` periods every `beats` beats of length
+    /// `beat`, e.g. `(beat, 2, 1)` for eighth notes.
+    pub fn set_sync(&mut self, beat: ms, cycles: u32, beats: u32) {
+        let mfreq = (cycles as u64 * 1_000_000) / (beats as u64 * beat.0 as u64).max(1);
+        self.set_mfreq(mHz(mfreq as u32));
+    }
+
+    /// Sets the rate to one period per `division` at `bpm`.
+    pub fn set_sync_division(&mut self, bpm: Bpm, division: Division) {
+        self.set_mfreq(division.to_mHz(bpm));
+    }
+
+    /// Sets the sample rate in mHz. A rate of 0 is raised to 1 mHz, see
+    /// [Lfo::try_set_msample_rate] for a checked variant.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.update_delta_phi();
+    }
+
+    /// Sets the sample rate in mHz or fails if it is 0.
+    pub fn try_set_msample_rate(&mut self, msample_rate: mHz) -> Result<(), ParamError> {
+        check_msample_rate(msample_rate)?;
+        self.set_msample_rate(msample_rate);
+        Ok(())
+    }
+
+    /// Sets the sample rate in Hz.
+    pub fn set_sample_rate(&mut self, sample_rate: Hz) {
+        self.set_msample_rate(sample_rate.to_mHz());
+    }
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for Lfo {
+    type Params = LfoParams;
+
+    fn get_params(&self) -> LfoParams {
+        LfoParams {
+            shape: self.shape,
+            mfreq: self.ufreq.to_mHz(),
+        }
+    }
+
+    fn set_params(&mut self, params: &LfoParams) {
+        self.shape = params.shape;
+        self.set_mfreq(params.mfreq);
+    }
+}
+
+impl Iterator for Lfo {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let out = self.value();
+        self.phi = self.phi.wrapping_add(self.delta_phi);
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn min_max(lfo: &mut Lfo, n: usize) -> (i16, i16) {
+        let mut min = 0;
+        let mut max = 0;
+        for _ in 0..n {
+            let y = lfo.next().unwrap();
+            min = min.min(y);
+            max = max.max(y);
+        }
+        (min, max)
+    }
+
+    #[test]
+    fn test_lfo_shapes() {
+        let mut lfo = Lfo::new();
+        lfo.set_sample_rate(Hz(1000));
+        lfo.set_freq(Hz(10));
+        for shape in [
+            LfoShape::Sine,
+            LfoShape::Triangle,
+            LfoShape::Saw,
+            LfoShape::Square,
+        ] {
+            lfo.set_shape(shape);
+            lfo.reset();
+            let (min, max) = min_max(&mut lfo, 100);
+            assert!(min < -30_000, "{:?}: {}", shape, min);
+            assert!(max > 30_000, "{:?}: {}", shape, max);
+        }
+    }
+
+    #[test]
+    fn test_lfo_period() {
+        let mut lfo = Lfo::new();
+        lfo.set_shape(LfoShape::Square);
+        lfo.set_sample_rate(Hz(1000));
+        lfo.set_freq(Hz(10));
+        let high = (0..100).filter(|_| lfo.next().unwrap() > 0).count();
+        assert_eq!(high, 50);
+    }
+
+    #[test]
+    fn test_lfo_ufreq() {
+        let mut lfo = Lfo::new();
+        lfo.set_mfreq(mHz(1));
+        let coarse = lfo.delta_phi;
+        // 1.5 mHz, between two steps of mHz
+        lfo.set_ufreq(uHz(1_500));
+        assert!(lfo.delta_phi.abs_diff(coarse * 3 / 2) <= 1);
+        assert_eq!(lfo.get_params().mfreq, mHz(1));
+    }
+
+    #[test]
+    fn test_lfo_sync() {
+        let mut lfo = Lfo::new();
+        // Eighth notes at 120 BPM
+        lfo.set_sync(ms(500), 2, 1);
+        assert_eq!(lfo.ufreq.to_mHz(), mHz(4_000));
+        lfo.set_sync_division(Bpm(120), Division::Eighth);
+        assert_eq!(lfo.ufreq.to_mHz(), mHz(4_000));
+        lfo.set_sync_division(Bpm(120), Division::DottedQuarter);
+        assert_eq!(lfo.ufreq.to_mHz(), mHz(1_333));
+    }
+}
@@ -1,3 +1,6 @@
+pub mod blep;
+pub mod fm;
+pub mod lfo;
 pub mod luts;
 pub mod noise;
 pub mod wavetable;
@@ -9,6 +9,8 @@ pub struct LFSR<T> {
 /// 32-bit linear feedback shift register
 impl LFSR<u32> {
     /// Returns the next value
+    // Not an Iterator, since the register never ends and isn't an Option
+    #[allow(clippy::should_implement_trait)]
     #[inline]
     pub fn next(&mut self) -> u32 {
         let lsb: bool = (self.lfsr & 0x01) != 0;
@@ -36,6 +38,8 @@ impl Default for LFSR<u32> {
 /// 16-bit linear feedback shift register
 impl LFSR<u16> {
     /// Returns the next value
+    // Not an Iterator, since the register never ends and isn't an Option
+    #[allow(clippy::should_implement_trait)]
     #[inline]
     pub fn next(&mut self) -> u16 {
         let lsb: bool = (self.lfsr & 0x01) != 0;
@@ -67,40 +71,59 @@ pub struct WhiteNoise {
 
 impl WhiteNoise {
     pub fn new() -> Self {
-        let _lfsr = LFSR::<u32>::default();
-        let s = Self { lfsr: _lfsr };
-        s
+        Self {
+            lfsr: LFSR::<u32>::default(),
+        }
     }
 
     pub fn set_seed(&mut self, seed: u32) {
         self.lfsr.lfsr = seed;
     }
+
+    #[inline]
+    fn sample(&mut self) -> i16 {
+        i16::MAX
+            .overflowing_sub_unsigned((self.lfsr.next() & 0xFFFF) as u16)
+            .0
+    }
+
+    /// Fills `out` with the next samples.
+    pub fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self.sample();
+        }
+    }
+}
+
+impl Default for WhiteNoise {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Iterator for WhiteNoise {
     type Item = i16;
 
     fn next(&mut self) -> Option<Self::Item> {
-        Some(
-            i16::MAX
-                .overflowing_sub_unsigned((self.lfsr.next() & 0xFFFF) as u16)
-                .0,
-        )
+        Some(self.sample())
     }
 }
 
 /// Pink noise generator
+#[allow(dead_code)]
 pub struct PinkNoise {
     lfsr: LFSR<u32>,
     msample_rate: mHz,
 }
 
 /// Bit flip noise generator
+#[allow(dead_code)]
 pub struct BitFlipNoise {
     lfsr: LFSR<u32>,
 }
 
 /// Crackle noise generator
+#[allow(dead_code)]
 pub struct CrackleNoise {
     lfsr: LFSR<u32>,
     msample_rate: mHz,
@@ -133,6 +156,15 @@ mod test {
         assert_eq!(period, 4_294_967_294);
     }
 
+    #[test]
+    fn test_white_noise_render() {
+        let mut noise = WhiteNoise::new();
+        let mut block = WhiteNoise::new();
+        let mut buf = [0; 64];
+        block.render(&mut buf);
+        assert!(buf.iter().all(|y| Some(*y) == noise.next()));
+    }
+
     #[test]
     fn test_white_noise16() {
         const N: i32 = 1_000_000;
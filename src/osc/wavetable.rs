@@ -1,17 +1,27 @@
 use derive_deref_rs::Deref;
 
 use crate::osc::luts::SINE_I16;
-use crate::osc::luts::{EXP_I16, EXP_I16_NORM, EXP_I16_TAU};
-use crate::util::units::{mHz, ms, Frequency, Hz};
+use crate::osc::luts::{EXP_I16, EXP_I16_TAU};
+use crate::util::fixed::{div_frac, mul_frac};
+use crate::util::interp;
+use crate::util::note::Note;
+use crate::util::param::{check_mfreq, check_msample_rate, nyquist, ParamError};
+use crate::util::sample::SampleType;
+use crate::util::units::{mHz, ms, uHz, Frequency, Hz};
 
 /// Maximum value of the phase accumulator
 const PHI_MAX: u32 = 1 << 20;
-/// Magic normalization constant for efficient divisions in the performance
-/// critical functions
-const NORM: u32 = 1 << 26;
+/// Fractional bits of alpha, which turn the division by the sample rate in
+/// the performance critical functions into a shift
+const ALPHA_FRAC: u32 = 26;
+/// Lowest sample rate in mHz for which alpha fits into 32 bits
+const MSAMPLE_RATE_MIN: u32 = mul_frac(PHI_MAX as u64, 1 << ALPHA_FRAC, 32) as u32 + 1;
 
 /// Stateful wavetable signal generator
-pub struct Engine<T: 'static> {
+///
+/// The wavetable entries are of any [SampleType], so the generator renders
+/// directly in the sample type of the following chain.
+pub struct Engine<T: SampleType> {
     // If false, the oscillator will stop after one period
     repeat: bool,
     // Indicates if the oscillator is running
@@ -36,22 +46,27 @@ pub struct Engine<T: 'static> {
     idx: usize,
     // Maximum wavetable index
     idx_max: usize,
+    // Interpolate linearly between wavetable entries
+    interpolate: bool,
 }
 
-impl<T> Engine<T> {
+impl<T: SampleType> Engine<T> {
     fn update_idx(&mut self) {
         self.idx = (((self.idx_max as u32) * self.phi) / PHI_MAX) as usize;
     }
 
+    /// Returns the wavetable linearly interpolated at `phi`.
+    #[inline]
+    fn interpolated(&self, phi: u32) -> T {
+        let pos = self.idx_max as u64 * phi as u64;
+        let idx = (pos >> PHI_MAX.trailing_zeros()) as usize;
+        let frac = (pos & (PHI_MAX as u64 - 1)) << interp::FRAC_BITS >> PHI_MAX.trailing_zeros();
+        let next = if idx + 1 < self.idx_max { idx + 1 } else { 0 };
+        interp::linear(self.wavetable[idx], self.wavetable[next], frac as u32)
+    }
+
     fn update_alpha(&mut self) {
-        self.alpha =
-            (((PHI_MAX as u64) * (NORM as u64) as u64) / (self.msample_rate.0 as u64)) as u32;
-        // println!("------------------alpha");
-        // println!("PHI_MAX: {:?}", PHI_MAX);
-        // println!("NORM: {:?}", NORM);
-        // println!("PHI_MAX*NORM: {:?}", (PHI_MAX as i64)*(NORM as i64));
-        // println!("alpha: {:?}", self.alpha);
-        // println!("alpha (casted): {:?}", ((PHI_MAX as i64)*(NORM as i64) as i64) / (self.msample_rate.0 as i64));
+        self.alpha = div_frac(PHI_MAX as u64, self.msample_rate.0 as u64, ALPHA_FRAC) as u32;
     }
 
     fn update_delta_phi(&mut self) {
@@ -62,27 +77,20 @@ impl<T> Engine<T> {
         // But since msample_rate is not constrained to be a power of
         // two, the resulting 64-bit division could be very expensive.
         // To avoid this, we perform a coefficient exchange between
-        // PHI_MAX and msample_rate such that the denominator NORM
-        // becomes a power of two and alpha absorbs the exact value. See
+        // PHI_MAX and msample_rate such that the denominator becomes a
+        // power of two and alpha absorbs the exact value. See
         // also [update_alpha].
-        self.delta_phi = (((self.mfreq.0 as u64) * (self.alpha as u64)) / (NORM as u64)) as u32;
-        // println!("------------------delta_phi");
-        // println!("delta_phi: {:?}", self.delta_phi);
-        // println!("alpha: {:?}", self.alpha);
-        // println!("mfreq: {:?}", self.mfreq.0);
-        // println!("NORM: {:?}", NORM);
+        let mfreq = self.mfreq.0.min(nyquist(self.msample_rate).0);
+        self.delta_phi = mul_frac(mfreq as u64, self.alpha as u64, ALPHA_FRAC) as u32;
     }
 
     /// Increments the phase accumulator and returns the next sample. If
     /// the generator is not running, it returns `None`.
     #[inline]
-    pub fn _next(&mut self) -> Option<T>
-    where
-        T: Copy,
-    {
+    pub fn _next(&mut self) -> Option<T> {
         // TODO Replace if-clause by masked addition
         self.phi += self.delta_phi;
-        if self.phi > PHI_MAX {
+        if self.phi >= PHI_MAX {
             self.phi -= PHI_MAX;
             if !self.repeat {
                 self.stop_and_reset();
@@ -90,19 +98,61 @@ impl<T> Engine<T> {
         };
         if self.is_running() {
             self.update_idx();
-            let out = self.wavetable[self.idx];
+            let out = if self.interpolate {
+                self.interpolated(self.phi)
+            } else {
+                self.wavetable[self.idx]
+            };
             Some(out)
         } else {
             None
         }
     }
 
+    /// Fills `out` with the next samples and returns how many were written.
+    /// Produces the same samples as repeated calls of [Engine::_next], but
+    /// without per-sample state checks while the generator keeps running.
+    pub fn render(&mut self, out: &mut [T]) -> usize {
+        if !(self.repeat && self.is_running()) {
+            let mut n = 0;
+            for y in out.iter_mut() {
+                match self._next() {
+                    Some(x) => *y = x,
+                    None => break,
+                }
+                n += 1;
+            }
+            return n;
+        }
+        let mut phi = self.phi;
+        for y in out.iter_mut() {
+            phi += self.delta_phi;
+            if phi >= PHI_MAX {
+                phi -= PHI_MAX;
+            }
+            *y = if self.interpolate {
+                self.interpolated(phi)
+            } else {
+                self.wavetable[(((self.idx_max as u32) * phi) / PHI_MAX) as usize]
+            };
+        }
+        self.phi = phi;
+        self.update_idx();
+        out.len()
+    }
+
     /// Sets the wavetable.
     pub fn set_wavetable(&mut self, wavetable: &'static [T]) {
         self.wavetable = wavetable;
         self.idx_max = self.wavetable.len();
     }
 
+    /// Interpolates linearly between the wavetable entries, which lowers
+    /// the quantization noise of short tables at the cost of a multiply.
+    pub fn set_interpolate(&mut self, interpolate: bool) {
+        self.interpolate = interpolate;
+    }
+
     /// Sets repeat to true or false. If false, the oscillator will stop
     /// after one period.
     pub fn set_repeat(&mut self, repeat: bool) {
@@ -125,6 +175,12 @@ impl<T> Engine<T> {
         self.phi = 0;
     }
 
+    /// Sets the phase, where the full u32 range corresponds to one period.
+    pub fn set_phase(&mut self, phase: u32) {
+        self.phi = phase >> (32 - PHI_MAX.trailing_zeros());
+        self.update_idx();
+    }
+
     /// Resets the phase accumulator 0 and sets the generator into
     /// "running" mode
     pub fn reset_and_start(&mut self) {
@@ -143,30 +199,59 @@ impl<T> Engine<T> {
         self.running
     }
 
-    /// Sets the frequency in mHz.
+    /// Sets the frequency in mHz. Frequencies above Nyquist play at
+    /// Nyquist, see [Engine::try_set_mfreq] for a checked variant.
     pub fn set_mfreq(&mut self, mfreq: mHz) {
         self.mfreq = mfreq;
         self.update_delta_phi();
     }
 
+    /// Sets the frequency in uHz. The phase increment of the engine
+    /// resolves about 42 mHz at 44.1 kHz, so the frequency is rounded to
+    /// whole mHz.
+    pub fn set_ufreq(&mut self, ufreq: uHz) {
+        self.set_mfreq(mHz(((ufreq.0 + 500) / 1_000).min(u32::MAX as u64) as u32));
+    }
+
+    /// Sets the frequency in mHz or fails if it is above Nyquist.
+    pub fn try_set_mfreq(&mut self, mfreq: mHz) -> Result<(), ParamError> {
+        check_mfreq(mfreq, self.msample_rate)?;
+        self.set_mfreq(mfreq);
+        Ok(())
+    }
+
+    /// Sets the frequency to the equal tempered frequency of `note`.
+    pub fn set_note(&mut self, note: Note) {
+        self.set_mfreq(note.to_mfreq());
+    }
+
     /// Sets the frequency in Hz.
     pub fn set_freq(&mut self, freq: Hz) {
-        self.mfreq = freq.to_mHz();
-        self.update_delta_phi();
+        self.set_mfreq(freq.to_mHz());
     }
 
-    /// Sets the sample rate in mHz.
+    /// Sets the sample rate in mHz. Rates below about 16 Hz, including 0,
+    /// are raised to the lowest supported rate, see
+    /// [Engine::try_set_msample_rate] for a checked variant.
     pub fn set_msample_rate(&mut self, msample_rate: mHz) {
-        self.msample_rate = msample_rate;
+        self.msample_rate = mHz(msample_rate.0.max(MSAMPLE_RATE_MIN));
         self.update_alpha();
         self.update_delta_phi();
     }
 
+    /// Sets the sample rate in mHz or fails if it is too low.
+    pub fn try_set_msample_rate(&mut self, msample_rate: mHz) -> Result<(), ParamError> {
+        check_msample_rate(msample_rate)?;
+        if msample_rate.0 < MSAMPLE_RATE_MIN {
+            return Err(ParamError::OutOfRange);
+        }
+        self.set_msample_rate(msample_rate);
+        Ok(())
+    }
+
     /// Sets the sample rate in Hz.
     pub fn set_sample_rate(&mut self, sample_rate: Hz) {
-        self.msample_rate = sample_rate.to_mHz();
-        self.update_alpha();
-        self.update_delta_phi();
+        self.set_msample_rate(sample_rate.to_mHz());
     }
 
     pub fn new() -> Self {
@@ -185,6 +270,7 @@ impl<T> Engine<T> {
 
             idx: 0,
             idx_max: 0,
+            interpolate: false,
         };
         s.set_sample_rate(Hz(44100));
         s.set_freq(Hz(440));
@@ -192,6 +278,45 @@ impl<T> Engine<T> {
     }
 }
 
+impl<T: SampleType> Default for Engine<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fits a single cycle of a waveform, e.g. loaded from a file, into the
+/// wavetable `out`. The cycle is stretched or squeezed to the length of `out`
+/// with cubic interpolation, wrapping around at the end, and normalized to
+/// full scale. Tables of a power of two length suit the [Engine] best.
+/// ```
+/// # use isopod::osc::wavetable::fit_cycle;
+/// let mut table = [0; 8];
+/// fit_cycle(&[0, 1_000, 0, -1_000], &mut table);
+/// assert_eq!(table[..3], [0, 20_479, i16::MAX]);
+/// assert_eq!(table[6], -i16::MAX);
+/// ```
+pub fn fit_cycle(cycle: &[i16], out: &mut [i16]) {
+    let len = cycle.len();
+    if len == 0 {
+        out.fill(0);
+        return;
+    }
+    let at = |n: usize| cycle[n % len];
+    let out_len = out.len() as u64;
+    for (n, y) in out.iter_mut().enumerate() {
+        let position = ((n as u64 * len as u64) << interp::FRAC_BITS) / out_len;
+        let idx = (position >> interp::FRAC_BITS) as usize;
+        let frac = position as u32 & (interp::FRAC_ONE - 1);
+        *y = interp::cubic(at(idx + len - 1), at(idx), at(idx + 1), at(idx + 2), frac);
+    }
+    let peak = out.iter().map(|y| y.unsigned_abs()).max().unwrap_or(0) as i32;
+    if peak > 0 {
+        for y in out.iter_mut() {
+            *y = (*y as i32 * i16::MAX as i32 / peak) as i16;
+        }
+    }
+}
+
 // Generic i16
 #[derive(Deref)]
 pub struct WavetableOscillator {
@@ -215,6 +340,7 @@ impl WavetableOscillator {
 
                 idx: 0,
                 idx_max: 0,
+                interpolate: false,
             },
         };
         s.set_sample_rate(Hz(44100));
@@ -222,6 +348,11 @@ impl WavetableOscillator {
         s
     }
 }
+impl Default for WavetableOscillator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Iterator for WavetableOscillator {
     type Item = i16;
 
@@ -253,6 +384,7 @@ impl SineOscillator {
 
                 idx: 0,
                 idx_max: SINE_I16.len(),
+                interpolate: false,
             },
         };
         s.set_sample_rate(Hz(44100));
@@ -260,6 +392,11 @@ impl SineOscillator {
         s
     }
 }
+impl Default for SineOscillator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Iterator for SineOscillator {
     type Item = i16;
 
@@ -291,6 +428,7 @@ impl ExpDecay {
 
                 idx: 0,
                 idx_max: EXP_I16.len(),
+                interpolate: false,
             },
         };
         s.set_sample_rate(Hz(44100));
@@ -307,6 +445,11 @@ impl ExpDecay {
         self.set_mfreq(dur.to_mHz());
     }
 }
+impl Default for ExpDecay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Iterator for ExpDecay {
     type Item = i16;
 
@@ -315,8 +458,11 @@ impl Iterator for ExpDecay {
     }
 }
 
+#[cfg(feature = "rodio")]
 use core::time::Duration;
+#[cfg(feature = "rodio")]
 use rodio::source::Source;
+#[cfg(feature = "rodio")]
 impl Source for WavetableOscillator {
     fn channels(&self) -> u16 {
         1
@@ -360,6 +506,87 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_engine_sample_type() {
+        static SQUARE: [f32; 4] = [1.0, 1.0, -1.0, -1.0];
+        let mut osc = Engine::<f32>::new();
+        osc.set_wavetable(&SQUARE);
+        osc.set_freq(Hz(4));
+        osc.set_sample_rate(Hz(32));
+        osc.start();
+        let mut buf = [0.0; 8];
+        osc.render(&mut buf);
+        assert_eq!(buf.iter().filter(|y| **y == 1.0).count(), 4);
+        assert_eq!(buf.iter().filter(|y| **y == -1.0).count(), 4);
+    }
+
+    #[test]
+    fn test_engine_interpolate() {
+        static SQUARE: [f32; 4] = [1.0, 1.0, -1.0, -1.0];
+        let mut osc = Engine::<f32>::new();
+        osc.set_wavetable(&SQUARE);
+        osc.set_freq(Hz(4));
+        osc.set_sample_rate(Hz(32));
+        osc.set_interpolate(true);
+        osc.start();
+        let mut buf = [0.0; 8];
+        osc.render(&mut buf);
+        // The two samples halfway across the edges are close to 0
+        assert_eq!(buf.iter().filter(|y| y.abs() < 0.01).count(), 2);
+        assert_eq!(buf.iter().filter(|y| y.abs() > 0.99).count(), 6);
+    }
+
+    #[test]
+    fn test_engine_checked() {
+        let mut osc = SineOscillator::new();
+        assert_eq!(
+            osc.try_set_mfreq(Hz(30_000).to_mHz()),
+            Err(ParamError::AboveNyquist)
+        );
+        assert_eq!(
+            osc.try_set_msample_rate(mHz(0)),
+            Err(ParamError::ZeroSampleRate)
+        );
+        assert_eq!(
+            osc.try_set_msample_rate(mHz(1_000)),
+            Err(ParamError::OutOfRange)
+        );
+        assert_eq!(osc.try_set_mfreq(Hz(1_000).to_mHz()), Ok(()));
+
+        // The infallible setters saturate
+        let mut nyquist = SineOscillator::new();
+        nyquist.set_freq(Hz(22_050));
+        osc.set_freq(Hz(30_000));
+        osc.set_msample_rate(mHz(0));
+        osc.set_sample_rate(Hz(44_100));
+        osc.start();
+        nyquist.start();
+        for _ in 0..100 {
+            assert_eq!(osc.next(), nyquist.next());
+        }
+    }
+
+    #[test]
+    fn test_engine_render() {
+        let mut osc = SineOscillator::new();
+        osc.set_freq(Hz(1_234));
+        osc.start();
+        let mut block = SineOscillator::new();
+        block.set_freq(Hz(1_234));
+        block.start();
+        let mut buf = [0; 500];
+        assert_eq!(block.render(&mut buf), 500);
+        assert!(buf.iter().all(|y| Some(*y) == osc.next()));
+        assert_eq!(block.next(), osc.next());
+
+        // One-shot generators stop after one period
+        let mut decay = ExpDecay::new();
+        decay.set_decay_ms(ms(1));
+        decay.set_repeat(false);
+        decay.start();
+        assert!(decay.render(&mut buf) < 500);
+    }
+
     #[test]
     fn test_exp_decay() {
         let mut decay = ExpDecay::new();
@@ -378,4 +605,28 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_fit_cycle() {
+        // A quiet sine of 100 samples becomes the full scale sine table
+        let cycle: Vec<i16> = (0..100)
+            .map(|n| ((n as f64 / 100.0 * core::f64::consts::TAU).sin() * 8_000.0) as i16)
+            .collect();
+        let mut table = [0; 1_024];
+        fit_cycle(&cycle, &mut table);
+        let max_err = table
+            .iter()
+            .zip(SINE_I16.iter())
+            .map(|(y, x)| (*y as i32 - *x as i32).abs())
+            .max()
+            .unwrap();
+        assert!(max_err < 100, "{}", max_err);
+        assert_eq!(table.iter().max(), Some(&i16::MAX));
+
+        // Longer cycles are squeezed
+        let mut table = [0; 16];
+        fit_cycle(&SINE_I16, &mut table);
+        assert_eq!(table[4], i16::MAX);
+        assert!(table[12] <= -i16::MAX + 1);
+    }
 }
@@ -0,0 +1,103 @@
+// JSON text format of presets, e.g. for patch files edited by hand.
+
+use crate::preset::PresetError;
+use alloc::string::String;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Stores `params` as indented JSON text. Structs become objects, unit
+/// enum variants strings and other variants objects with the variant name
+/// as the only key. Non-finite floats become `null`, and keys that aren't
+/// strings or numbers fail.
+/// ```
+/// # use isopod::preset::json::*;
+/// # use isopod::env::adsr::AdsrParams;
+/// # use isopod::util::units::ms;
+/// let params = AdsrParams {
+///     attack: ms(5),
+///     decay: ms(100),
+///     sustain: 20_000,
+///     release: ms(300),
+/// };
+/// let text = to_json(&params).unwrap();
+/// assert!(text.starts_with("{\n  \"attack\": 5,\n"));
+/// assert_eq!(from_json::<AdsrParams>(&text), Ok(params));
+/// ```
+pub fn to_json<P: Serialize + ?Sized>(params: &P) -> Result<String, PresetError> {
+    serde_json::to_string_pretty(params).map_err(|_| PresetError::Invalid)
+}
+
+/// Loads parameters from JSON text as written by [to_json]. Integers are
+/// accepted for floats.
+pub fn from_json<P: DeserializeOwned>(text: &str) -> Result<P, PresetError> {
+    serde_json::from_str(text).map_err(|_| PresetError::Invalid)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::preset::Preset;
+    use crate::synth::fmpiano::FmPiano;
+    use crate::synth::subtractive::{SubtractiveParams, SubtractiveVoice};
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Shape {
+        Point,
+        Circle(f32),
+        Line(i32, i32),
+        Rect { w: u16, h: u16 },
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let mut voice = SubtractiveVoice::new();
+        voice.set_detune(-7);
+        voice.set_resonance(1_000);
+        let params = voice.get_params();
+        let text = to_json(&params).unwrap();
+        assert_eq!(from_json::<SubtractiveParams>(&text), Ok(params));
+
+        let params = FmPiano::new().get_params();
+        assert_eq!(from_json(&to_json(&params).unwrap()), Ok(params));
+
+        let shapes = vec![
+            Shape::Point,
+            Shape::Circle(1.5),
+            Shape::Line(-1, 2),
+            Shape::Rect { w: 3, h: 4 },
+        ];
+        let text = to_json(&shapes).unwrap();
+        assert_eq!(from_json::<Vec<Shape>>(&text), Ok(shapes));
+        let options: Vec<Option<String>> = vec![None, Some("a\"\\\n\u{1}é".into())];
+        assert_eq!(from_json(&to_json(&options).unwrap()), Ok(options));
+        assert_eq!(to_json(&f32::NAN), Ok(String::from("null")));
+        let keys = std::collections::BTreeMap::from([((1, 2), 3)]);
+        assert_eq!(to_json(&keys), Err(PresetError::Invalid));
+    }
+
+    #[test]
+    fn test_json_parse() {
+        let text = r#" { "Rect" : { "h" : 2 , "w" : 1 } } "#;
+        assert_eq!(from_json(text), Ok(Shape::Rect { w: 1, h: 2 }));
+        assert_eq!(from_json(r#"{"Circle": 2}"#), Ok(Shape::Circle(2.0)));
+        assert_eq!(from_json("[1e3, -0.5]"), Ok([1_000.0_f64, -0.5]));
+        assert_eq!(from_json("18446744073709551615"), Ok(u64::MAX));
+        assert_eq!(
+            from_json(r#""\u00e9\ud83d\ude00""#),
+            Ok(String::from("é😀"))
+        );
+        assert_eq!(from_json::<[u8; 0]>("[]"), Ok([]));
+
+        for invalid in ["", "[1, 2", "{\"a\" 1}", "[1] 2", "\"\\x\"", "nul", "300"] {
+            assert_eq!(
+                from_json::<u8>(invalid),
+                Err(PresetError::Invalid),
+                "{}",
+                invalid
+            );
+        }
+        assert_eq!(from_json::<Shape>("\"Square\""), Err(PresetError::Invalid));
+        let deep = "[".repeat(100) + &"]".repeat(100);
+        assert_eq!(from_json::<()>(&deep), Err(PresetError::Invalid));
+    }
+}
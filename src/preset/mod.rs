@@ -0,0 +1,80 @@
+// Presets of block parameters that can be saved and loaded at runtime.
+
+#[cfg(feature = "json")]
+pub mod json;
+pub mod morph;
+pub mod random;
+
+/// Block whose parameters can be saved and restored
+///
+/// The parameters are plain data without runtime state like phases or
+/// levels, so they are independent of the sample rate. With the `serde`
+/// feature, all parameter types can be serialized with any serde format.
+/// The `postcard` feature adds [to_bytes] and [from_bytes] for a compact
+/// binary format that works without allocation, and the `json` feature
+/// adds [json] for presets as text.
+pub trait Preset {
+    type Params;
+
+    /// Returns the current parameters.
+    fn get_params(&self) -> Self::Params;
+
+    /// Sets all parameters.
+    fn set_params(&mut self, params: &Self::Params);
+}
+
+/// Reason why a preset couldn't be stored or loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresetError {
+    /// The buffer is too small for the preset.
+    BufferFull,
+    /// The bytes aren't a valid preset of this type.
+    Invalid,
+}
+
+/// Stores `params` in `buf` and returns the used part of it.
+#[cfg(feature = "postcard")]
+pub fn to_bytes<'a, P: serde::Serialize>(
+    params: &P,
+    buf: &'a mut [u8],
+) -> Result<&'a mut [u8], PresetError> {
+    postcard::to_slice(params, buf).map_err(|e| match e {
+        postcard::Error::SerializeBufferFull => PresetError::BufferFull,
+        _ => PresetError::Invalid,
+    })
+}
+
+/// Loads parameters from `bytes`.
+#[cfg(feature = "postcard")]
+pub fn from_bytes<'a, P: serde::Deserialize<'a>>(bytes: &'a [u8]) -> Result<P, PresetError> {
+    postcard::from_bytes(bytes).map_err(|_| PresetError::Invalid)
+}
+
+#[cfg(all(test, feature = "postcard"))]
+mod test {
+    use super::*;
+    use crate::synth::subtractive::{SubtractiveParams, SubtractiveVoice};
+
+    #[test]
+    fn test_preset_bytes() {
+        let mut voice = SubtractiveVoice::new();
+        voice.set_detune(-7);
+        voice.set_resonance(1_000);
+        let params = voice.get_params();
+
+        let mut buf = [0_u8; 128];
+        let bytes = to_bytes(&params, &mut buf).unwrap();
+        assert!(bytes.len() < 96, "{}", bytes.len());
+        let loaded: SubtractiveParams = from_bytes(bytes).unwrap();
+        assert_eq!(loaded, params);
+
+        assert_eq!(
+            to_bytes(&params, &mut [0_u8; 8]),
+            Err(PresetError::BufferFull)
+        );
+        assert_eq!(
+            from_bytes::<SubtractiveParams>(&[0xff; 4]),
+            Err(PresetError::Invalid)
+        );
+    }
+}
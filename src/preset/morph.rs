@@ -0,0 +1,404 @@
+// Morphing between presets with a single parameter.
+
+use crate::env::adsr::AdsrParams;
+use crate::fx::crossfader::Crossfader;
+use crate::graph::nodes::FilterParams;
+use crate::osc::blep::BlepParams;
+use crate::osc::lfo::LfoParams;
+use crate::preset::Preset;
+use crate::synth::fmpiano::FmPianoParams;
+use crate::synth::subtractive::SubtractiveParams;
+use crate::synth::velocity::VelocityParams;
+use crate::synth::voice::Voice;
+use crate::util::units::{mHz, ms};
+
+/// Samples rendered per voice at once
+const BLOCK: usize = 64;
+
+/// Interpolates from `a` (amount 0) to `b` (amount i16::MAX).
+fn lerp(a: i64, b: i64, amount: i16) -> i64 {
+    a + (b - a) * amount.max(0) as i64 / i16::MAX as i64
+}
+
+/// Parameters that can be interpolated
+///
+/// Numeric parameters are interpolated linearly, discrete ones like
+/// waveforms are kept from `self`. Discrete parameters are morphed by
+/// crossfading two voices instead, see [MorphVoice].
+pub trait Morph {
+    /// Returns the parameters `amount` of the way from `self` (0) to
+    /// `other` (i16::MAX).
+    fn morph(&self, other: &Self, amount: i16) -> Self;
+
+    /// True if all discrete parameters are equal, so no crossfade is
+    /// needed.
+    fn is_discrete_eq(&self, other: &Self) -> bool;
+}
+
+impl Morph for i16 {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        lerp(*self as i64, *other as i64, amount) as i16
+    }
+
+    fn is_discrete_eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Morph for i32 {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        lerp(*self as i64, *other as i64, amount) as i32
+    }
+
+    fn is_discrete_eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Morph for u32 {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        lerp(*self as i64, *other as i64, amount) as u32
+    }
+
+    fn is_discrete_eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Morph for ms {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        ms(self.0.morph(&other.0, amount))
+    }
+
+    fn is_discrete_eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Morph for mHz {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        mHz(self.0.morph(&other.0, amount))
+    }
+
+    fn is_discrete_eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Morph for AdsrParams {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        Self {
+            attack: self.attack.morph(&other.attack, amount),
+            decay: self.decay.morph(&other.decay, amount),
+            sustain: self.sustain.morph(&other.sustain, amount),
+            release: self.release.morph(&other.release, amount),
+        }
+    }
+
+    fn is_discrete_eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Morph for BlepParams {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        Self {
+            waveform: self.waveform,
+            pulse_width: self.pulse_width.morph(&other.pulse_width, amount),
+        }
+    }
+
+    fn is_discrete_eq(&self, other: &Self) -> bool {
+        self.waveform == other.waveform
+    }
+}
+
+impl Morph for LfoParams {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        Self {
+            shape: self.shape,
+            mfreq: self.mfreq.morph(&other.mfreq, amount),
+        }
+    }
+
+    fn is_discrete_eq(&self, other: &Self) -> bool {
+        self.shape == other.shape
+    }
+}
+
+impl Morph for VelocityParams {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        Self {
+            curve: self.curve,
+            amp_depth: self.amp_depth.morph(&other.amp_depth, amount),
+            cutoff_depth: self.cutoff_depth.morph(&other.cutoff_depth, amount),
+        }
+    }
+
+    fn is_discrete_eq(&self, other: &Self) -> bool {
+        self.curve == other.curve
+    }
+}
+
+impl Morph for FilterParams {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        Self {
+            cutoff: self.cutoff.morph(&other.cutoff, amount),
+            q: self.q.morph(&other.q, amount),
+            depth: self.depth.morph(&other.depth, amount),
+        }
+    }
+
+    fn is_discrete_eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Morph for SubtractiveParams {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        Self {
+            osc1: self.osc1.morph(&other.osc1, amount),
+            osc2: self.osc2.morph(&other.osc2, amount),
+            osc1_level: self.osc1_level.morph(&other.osc1_level, amount),
+            osc2_level: self.osc2_level.morph(&other.osc2_level, amount),
+            noise_level: self.noise_level.morph(&other.noise_level, amount),
+            detune: self.detune.morph(&other.detune, amount),
+            cutoff: self.cutoff.morph(&other.cutoff, amount),
+            resonance: self.resonance.morph(&other.resonance, amount),
+            filter_env_depth: self.filter_env_depth.morph(&other.filter_env_depth, amount),
+            lfo_cutoff_depth: self.lfo_cutoff_depth.morph(&other.lfo_cutoff_depth, amount),
+            lfo_pitch_depth: self.lfo_pitch_depth.morph(&other.lfo_pitch_depth, amount),
+            pressure_cutoff_depth: self
+                .pressure_cutoff_depth
+                .morph(&other.pressure_cutoff_depth, amount),
+            timbre_cutoff_depth: self
+                .timbre_cutoff_depth
+                .morph(&other.timbre_cutoff_depth, amount),
+            amp_env: self.amp_env.morph(&other.amp_env, amount),
+            filter_env: self.filter_env.morph(&other.filter_env, amount),
+            lfo: self.lfo.morph(&other.lfo, amount),
+            velocity: self.velocity.morph(&other.velocity, amount),
+        }
+    }
+
+    fn is_discrete_eq(&self, other: &Self) -> bool {
+        self.osc1.is_discrete_eq(&other.osc1)
+            && self.osc2.is_discrete_eq(&other.osc2)
+            && self.lfo.is_discrete_eq(&other.lfo)
+            && self.velocity.is_discrete_eq(&other.velocity)
+    }
+}
+
+impl Morph for FmPianoParams {
+    fn morph(&self, other: &Self, amount: i16) -> Self {
+        Self {
+            mod_ratio: self.mod_ratio.morph(&other.mod_ratio, amount),
+            index: self.index.morph(&other.index, amount),
+            decay: self.decay.morph(&other.decay, amount),
+            index_decay: self.index_decay.morph(&other.index_decay, amount),
+            release: self.release.morph(&other.release, amount),
+            velocity: self.velocity.morph(&other.velocity, amount),
+        }
+    }
+
+    fn is_discrete_eq(&self, other: &Self) -> bool {
+        self.velocity.is_discrete_eq(&other.velocity)
+    }
+}
+
+/// Voice morphing between two presets
+///
+/// The morph amount goes from 0 (preset `a`) to i16::MAX (preset `b`).
+/// Numeric parameters are interpolated. If the presets differ in discrete
+/// parameters, a second voice plays with the discrete parameters of `b`
+/// and the two voices are crossfaded. Both voices play all notes then,
+/// so this doubles the load.
+pub struct MorphVoice<V: Voice + Preset>
+where
+    V::Params: Morph,
+{
+    voices: [V; 2],
+    a: V::Params,
+    b: V::Params,
+    amount: i16,
+    crossfade: bool,
+    crossfader: Crossfader,
+}
+
+impl<V: Voice + Preset> MorphVoice<V>
+where
+    V::Params: Morph,
+{
+    /// Morphs between the presets `a` and `b` with two instances of the
+    /// voice.
+    pub fn new(voices: [V; 2], a: V::Params, b: V::Params) -> Self {
+        let mut s = Self {
+            voices,
+            crossfade: !a.is_discrete_eq(&b),
+            a,
+            b,
+            amount: 0,
+            crossfader: Crossfader::new(),
+        };
+        s.set_morph(0);
+        s
+    }
+
+    /// Sets the morph amount from 0 (preset `a`) to i16::MAX (preset `b`).
+    pub fn set_morph(&mut self, amount: i16) {
+        self.amount = amount.max(0);
+        self.voices[0].set_params(&self.a.morph(&self.b, self.amount));
+        if self.crossfade {
+            self.voices[1].set_params(&self.b.morph(&self.a, i16::MAX - self.amount));
+            self.crossfader.set_position(self.amount);
+        }
+    }
+
+    /// Returns the morph amount.
+    pub fn get_morph(&self) -> i16 {
+        self.amount
+    }
+
+    /// Returns the voices, the second one only plays when crossfading.
+    pub fn get_voices_mut(&mut self) -> &mut [V; 2] {
+        &mut self.voices
+    }
+}
+
+impl<V: Voice + Preset> Voice for MorphVoice<V>
+where
+    V::Params: Morph,
+{
+    fn note_on(&mut self, note: u8, velocity: u8) {
+        for voice in self.voices.iter_mut() {
+            voice.note_on(note, velocity);
+        }
+    }
+
+    fn set_note(&mut self, note: u8) {
+        for voice in self.voices.iter_mut() {
+            voice.set_note(note);
+        }
+    }
+
+    fn set_mfreq(&mut self, mfreq: mHz) {
+        for voice in self.voices.iter_mut() {
+            voice.set_mfreq(mfreq);
+        }
+    }
+
+    fn set_phase(&mut self, phase: u32) {
+        for voice in self.voices.iter_mut() {
+            voice.set_phase(phase);
+        }
+    }
+
+    fn set_pressure(&mut self, pressure: i16) {
+        for voice in self.voices.iter_mut() {
+            voice.set_pressure(pressure);
+        }
+    }
+
+    fn set_timbre(&mut self, timbre: i16) {
+        for voice in self.voices.iter_mut() {
+            voice.set_timbre(timbre);
+        }
+    }
+
+    fn note_off(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.note_off();
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.voices[0].is_active() || (self.crossfade && self.voices[1].is_active())
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        if !self.crossfade {
+            self.voices[0].render(out);
+            return;
+        }
+        let mut buf = [0_i16; BLOCK];
+        for chunk in out.chunks_mut(BLOCK) {
+            let buf = &mut buf[..chunk.len()];
+            self.voices[0].render(chunk);
+            self.voices[1].render(buf);
+            for (y, b) in chunk.iter_mut().zip(buf.iter()) {
+                *y = self.crossfader.process(*y, *b);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::osc::blep::Waveform;
+    use crate::synth::subtractive::SubtractiveVoice;
+
+    #[test]
+    fn test_morph_params() {
+        let a = AdsrParams {
+            attack: ms(0),
+            decay: ms(100),
+            sustain: 0,
+            release: ms(1_000),
+        };
+        let b = AdsrParams {
+            attack: ms(100),
+            decay: ms(100),
+            sustain: i16::MAX,
+            release: ms(0),
+        };
+        assert_eq!(a.morph(&b, 0), a);
+        assert_eq!(a.morph(&b, i16::MAX), b);
+        let half = a.morph(&b, i16::MAX / 2 + 1);
+        assert_eq!(half.attack, ms(50));
+        assert_eq!(half.release, ms(500));
+
+        // Discrete parameters stay
+        let saw = BlepParams {
+            waveform: Waveform::Saw,
+            pulse_width: 0,
+        };
+        let square = BlepParams {
+            waveform: Waveform::Square,
+            pulse_width: 1 << 31,
+        };
+        assert_eq!(saw.morph(&square, i16::MAX).waveform, Waveform::Saw);
+        assert!(!saw.is_discrete_eq(&square));
+    }
+
+    #[test]
+    fn test_morph_voice() {
+        let a = SubtractiveVoice::new().get_params();
+        let mut b = a;
+        b.cutoff = mHz(4_000_000);
+        b.osc1.waveform = Waveform::Square;
+
+        let render = |voice: &mut dyn Voice| {
+            voice.note_on(60, 100);
+            let mut out = [0_i16; 500];
+            voice.render(&mut out);
+            out
+        };
+        let mut morph = MorphVoice::new([SubtractiveVoice::new(), SubtractiveVoice::new()], a, b);
+        // Only the crossfade gain differs from preset a
+        let mut voice = SubtractiveVoice::new();
+        voice.set_params(&a);
+        let (x, y) = (render(&mut morph), render(&mut voice));
+        assert!(x.iter().zip(y.iter()).all(|(x, y)| x.abs_diff(*y) <= 1));
+
+        // Numeric parameters in between, discrete ones from b
+        let mut morph = MorphVoice::new([SubtractiveVoice::new(), SubtractiveVoice::new()], a, b);
+        morph.set_morph(i16::MAX);
+        let params = morph.get_voices_mut()[1].get_params();
+        assert_eq!(params, b);
+        let mut voice = SubtractiveVoice::new();
+        voice.set_params(&b);
+        let (x, y) = (render(&mut morph), render(&mut voice));
+        assert!(x.iter().zip(y.iter()).all(|(x, y)| x.abs_diff(*y) <= 1));
+    }
+}
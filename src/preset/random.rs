@@ -0,0 +1,232 @@
+// Random presets for sound design exploration and fuzz testing.
+
+use crate::env::adsr::AdsrParams;
+use crate::fx::filter::Q_MAX;
+use crate::graph::nodes::FilterParams;
+use crate::osc::blep::{BlepParams, Waveform};
+use crate::osc::fm::INDEX_SHIFT;
+use crate::osc::lfo::{LfoParams, LfoShape};
+use crate::preset::Preset;
+use crate::synth::fmpiano::FmPianoParams;
+use crate::synth::subtractive::SubtractiveParams;
+use crate::synth::velocity::{VelocityCurve, VelocityParams};
+use crate::util::units::{mHz, ms};
+
+/// Xorshift generator of random parameter values
+///
+/// The same seed always yields the same presets, so interesting finds and
+/// failing fuzz cases can be reproduced.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        // The state must never be 0
+        Self {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    /// Returns the next value of the full u32 range.
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Returns a value from `lo` to `hi` inclusive.
+    pub fn range(&mut self, lo: i32, hi: i32) -> i32 {
+        let span = (hi as i64 - lo as i64 + 1) as u64;
+        (lo as i64 + ((self.next_u32() as u64 * span) >> 32) as i64) as i32
+    }
+
+    /// Returns a value from `lo` to `hi` inclusive, where low values are
+    /// more likely. Suits times and frequencies, which are perceived
+    /// logarithmically.
+    pub fn skewed(&mut self, lo: u32, hi: u32) -> u32 {
+        let x = self.next_u32() as u64 >> 16;
+        lo + (((hi - lo) as u64 * x * x) >> 32) as u32
+    }
+
+    /// Returns one of `choices`.
+    pub fn choose<T: Copy>(&mut self, choices: &[T]) -> T {
+        choices[self.range(0, choices.len() as i32 - 1) as usize]
+    }
+}
+
+/// Parameters with random values in sensible ranges
+pub trait Randomize {
+    fn random(rng: &mut Rng) -> Self;
+}
+
+/// Sets random parameters of `block`.
+///
+/// ```
+/// use isopod::preset::random::{randomize, Rng};
+/// use isopod::synth::subtractive::SubtractiveVoice;
+/// use isopod::synth::voice::Voice;
+///
+/// let mut rng = Rng::new(42);
+/// let mut voice = SubtractiveVoice::new();
+/// randomize(&mut voice, &mut rng);
+/// voice.note_on(60, 100);
+/// let mut out = [0_i16; 256];
+/// voice.render(&mut out);
+/// ```
+pub fn randomize<P: Preset>(block: &mut P, rng: &mut Rng)
+where
+    P::Params: Randomize,
+{
+    block.set_params(&P::Params::random(rng));
+}
+
+impl Randomize for AdsrParams {
+    fn random(rng: &mut Rng) -> Self {
+        Self {
+            attack: ms(rng.skewed(1, 1_000)),
+            decay: ms(rng.skewed(10, 2_000)),
+            sustain: rng.range(0, i16::MAX as i32) as i16,
+            release: ms(rng.skewed(10, 2_000)),
+        }
+    }
+}
+
+impl Randomize for BlepParams {
+    fn random(rng: &mut Rng) -> Self {
+        Self {
+            waveform: rng.choose(&[Waveform::Saw, Waveform::Square]),
+            // From 12.5 % to 50 %
+            pulse_width: rng.range(1 << 29, 1 << 30) as u32 * 2,
+        }
+    }
+}
+
+impl Randomize for LfoParams {
+    fn random(rng: &mut Rng) -> Self {
+        Self {
+            shape: rng.choose(&[
+                LfoShape::Sine,
+                LfoShape::Triangle,
+                LfoShape::Saw,
+                LfoShape::Square,
+            ]),
+            mfreq: mHz(rng.skewed(50, 10_000)),
+        }
+    }
+}
+
+impl Randomize for VelocityParams {
+    fn random(rng: &mut Rng) -> Self {
+        Self {
+            curve: rng.choose(&[
+                VelocityCurve::Linear,
+                VelocityCurve::Exponential,
+                VelocityCurve::Fixed,
+            ]),
+            amp_depth: rng.range(0, i16::MAX as i32) as i16,
+            cutoff_depth: rng.range(0, 2_400),
+        }
+    }
+}
+
+impl Randomize for FilterParams {
+    fn random(rng: &mut Rng) -> Self {
+        Self {
+            cutoff: mHz(rng.skewed(100_000, 8_000_000)),
+            q: rng.range(0, Q_MAX as i32 * 3 / 4) as u32,
+            depth: rng.range(0, 2_400),
+        }
+    }
+}
+
+impl Randomize for SubtractiveParams {
+    fn random(rng: &mut Rng) -> Self {
+        let max = i16::MAX as i32;
+        Self {
+            osc1: BlepParams::random(rng),
+            osc2: BlepParams::random(rng),
+            osc1_level: rng.range(max / 4, max) as i16,
+            osc2_level: rng.range(0, max) as i16,
+            noise_level: rng.range(0, max / 4) as i16,
+            // Mostly detuned unison, sometimes an interval
+            detune: rng.range(-30, 30) + rng.choose(&[0, 0, 0, 700, 1_200, -1_200]),
+            cutoff: mHz(rng.skewed(200_000, 8_000_000)),
+            resonance: rng.range(0, Q_MAX as i32 * 3 / 4) as u32,
+            filter_env_depth: rng.range(0, 4_800),
+            lfo_cutoff_depth: rng.skewed(0, 1_200) as i32,
+            lfo_pitch_depth: rng.skewed(0, 30) as i32,
+            pressure_cutoff_depth: rng.range(0, 2_400),
+            timbre_cutoff_depth: rng.range(-2_400, 2_400),
+            amp_env: AdsrParams::random(rng),
+            filter_env: AdsrParams::random(rng),
+            lfo: LfoParams::random(rng),
+            velocity: VelocityParams::random(rng),
+        }
+    }
+}
+
+impl Randomize for FmPianoParams {
+    fn random(rng: &mut Rng) -> Self {
+        Self {
+            mod_ratio: (rng.range(1, 8) as u32) << 16,
+            // From 0.5 to 4 radians
+            index: rng.range(1 << (INDEX_SHIFT - 1), 4 << INDEX_SHIFT) as u32,
+            decay: ms(rng.skewed(300, 4_000)),
+            index_decay: ms(rng.skewed(50, 2_000)),
+            release: ms(rng.skewed(50, 500)),
+            velocity: VelocityParams::random(rng),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::fmpiano::FmPiano;
+    use crate::synth::subtractive::SubtractiveVoice;
+    use crate::synth::voice::Voice;
+
+    #[test]
+    fn test_rng() {
+        let mut rng = Rng::new(0);
+        assert!((0..1_000).all(|_| (-3..=3).contains(&rng.range(-3, 3))));
+        assert!((0..1_000).all(|_| rng.skewed(10, 20) <= 20));
+        assert!((0..1_000).any(|_| rng.range(-3, 3) == 3));
+        let a: Vec<u32> = (0..8).map(|_| Rng::new(7).next_u32()).collect();
+        assert!(a.iter().all(|x| *x == a[0]));
+    }
+
+    #[test]
+    fn test_randomize() {
+        let mut rng = Rng::new(1);
+        let mut voice = SubtractiveVoice::new();
+        randomize(&mut voice, &mut rng);
+        let params = voice.get_params();
+        randomize(&mut voice, &mut Rng::new(1));
+        assert_eq!(voice.get_params(), params);
+        randomize(&mut voice, &mut rng);
+        assert_ne!(voice.get_params(), params);
+
+        // Fuzz the voices with random presets
+        let mut out = [0_i16; 512];
+        for _ in 0..20 {
+            let mut voice = SubtractiveVoice::new();
+            randomize(&mut voice, &mut rng);
+            voice.note_on(rng.range(24, 96) as u8, rng.range(1, 127) as u8);
+            voice.render(&mut out);
+            voice.note_off();
+            voice.render(&mut out);
+
+            let mut piano = FmPiano::new();
+            randomize(&mut piano, &mut rng);
+            piano.note_on(rng.range(24, 96) as u8, rng.range(1, 127) as u8);
+            piano.render(&mut out);
+            assert!(out.iter().any(|y| *y != 0));
+        }
+    }
+}
@@ -0,0 +1,331 @@
+// Remote control through Open Sound Control messages, e.g. from TouchOSC,
+// Max or SuperCollider.
+
+#[cfg(feature = "osc")]
+pub mod server;
+
+use crate::synth::NoteEvent;
+use crate::util::mapping::MapValue;
+use crate::util::param::ParamHandle;
+
+/// Prefix of the addresses of registered parameters
+pub const PARAM_PREFIX: &str = "/param/";
+/// Address of note messages with the note number and velocity
+pub const NOTE_ADDRESS: &str = "/note";
+
+/// Argument of an [OscMessage]
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    Str(String),
+}
+
+impl OscArg {
+    /// Returns numbers as i32, rounding floats.
+    pub fn to_i32(&self) -> Option<i32> {
+        match self {
+            OscArg::Int(x) => Some(*x),
+            OscArg::Float(x) => Some(x.round() as i32),
+            OscArg::Str(_) => None,
+        }
+    }
+}
+
+/// Message with an address and arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+/// Reads the 4 byte aligned strings and numbers of a packet
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(slice)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let b = self.take(4)?;
+        Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        let rest = self.bytes.get(self.pos..)?;
+        let len = rest.iter().position(|b| *b == 0)?;
+        let s = core::str::from_utf8(&rest[..len]).ok()?;
+        // The terminator and the padding to the next multiple of 4
+        self.take((len + 4) & !3)?;
+        Some(s)
+    }
+}
+
+impl OscMessage {
+    /// Parses a single message. Returns `None` for malformed messages, for
+    /// bundles and for unsupported argument types.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let address = reader.string()?;
+        if !address.starts_with('/') {
+            return None;
+        }
+        // Messages without a type tag string have no arguments
+        let tags = if reader.pos < bytes.len() {
+            reader.string()?.strip_prefix(',')?
+        } else {
+            ""
+        };
+        let mut args = Vec::with_capacity(tags.len());
+        for tag in tags.chars() {
+            args.push(match tag {
+                'i' => OscArg::Int(reader.u32()? as i32),
+                'f' => OscArg::Float(f32::from_bits(reader.u32()?)),
+                's' => OscArg::Str(reader.string()?.into()),
+                _ => return None,
+            });
+        }
+        Some(Self {
+            address: address.into(),
+            args,
+        })
+    }
+
+    /// Parses a packet, which is a message or a bundle of messages and
+    /// bundles. Malformed elements are skipped and time tags are ignored,
+    /// so bundles apply immediately.
+    pub fn parse_packet(bytes: &[u8]) -> Vec<Self> {
+        let mut messages = Vec::new();
+        Self::parse_into(bytes, &mut messages);
+        messages
+    }
+
+    fn parse_into(bytes: &[u8], out: &mut Vec<Self>) {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.string() != Some("#bundle") {
+            out.extend(Self::parse(bytes));
+            return;
+        }
+        if reader.take(8).is_none() {
+            return;
+        }
+        while let Some(len) = reader.u32() {
+            match reader.take(len as usize) {
+                Some(element) => Self::parse_into(element, out),
+                None => return,
+            }
+        }
+    }
+
+    /// Writes the message to `out`, e.g. for replies or tests.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let string = |out: &mut Vec<u8>, s: &str| {
+            out.extend_from_slice(s.as_bytes());
+            out.resize((out.len() + 4) & !3, 0);
+        };
+        string(out, &self.address);
+        let mut tags = String::from(",");
+        for arg in self.args.iter() {
+            tags.push(match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::Str(_) => 's',
+            });
+        }
+        string(out, &tags);
+        for arg in self.args.iter() {
+            match arg {
+                OscArg::Int(x) => out.extend_from_slice(&x.to_be_bytes()),
+                OscArg::Float(x) => out.extend_from_slice(&x.to_bits().to_be_bytes()),
+                OscArg::Str(s) => string(out, s),
+            }
+        }
+    }
+}
+
+/// Registered parameter with its range
+struct Target {
+    name: String,
+    lo: i64,
+    hi: i64,
+    set: Box<dyn Fn(i64) + Send>,
+}
+
+/// Routes OSC messages to parameters and notes
+///
+/// `/param/<name>` sets the parameter registered as `name`. Integers set
+/// the value directly and floats from 0 to 1, as sent by faders, map to the
+/// range of the parameter. `/note` with the note number and the velocity
+/// returns a note event, where velocity 0 releases the note.
+///
+/// ```
+/// use isopod::remote::{OscArg, OscMessage, OscRouter};
+/// use isopod::util::param::param;
+/// use isopod::util::units::mHz;
+///
+/// let (mut cutoff, handle) = param(mHz(1_000_000));
+/// let mut router = OscRouter::new();
+/// router.register("cutoff", mHz(100_000), mHz(8_100_000), handle);
+///
+/// router.route(&OscMessage {
+///     address: "/param/cutoff".into(),
+///     args: vec![OscArg::Float(0.5)],
+/// });
+/// assert_eq!(cutoff.poll(), Some(mHz(4_100_000)));
+/// ```
+pub struct OscRouter {
+    targets: Vec<Target>,
+}
+
+impl OscRouter {
+    pub fn new() -> Self {
+        Self {
+            targets: Vec::new(),
+        }
+    }
+
+    /// Registers a parameter as `/param/<name>` with the range of faders
+    /// from `lo` to `hi`.
+    pub fn register<T: MapValue + Send + Sync + 'static>(
+        &mut self,
+        name: &str,
+        lo: T,
+        hi: T,
+        handle: ParamHandle<T>,
+    ) {
+        self.targets.push(Target {
+            name: name.into(),
+            lo: lo.to_i64(),
+            hi: hi.to_i64(),
+            set: Box::new(move |x| handle.set(T::from_i64(x))),
+        });
+    }
+
+    /// Applies `message` to its parameter, or returns its note event.
+    /// Messages with unknown addresses or wrong arguments are ignored.
+    pub fn route(&self, message: &OscMessage) -> Option<NoteEvent> {
+        if let Some(name) = message.address.strip_prefix(PARAM_PREFIX) {
+            let target = self.targets.iter().find(|t| t.name == name)?;
+            let value = match message.args.first()? {
+                OscArg::Int(x) => {
+                    (*x as i64).clamp(target.lo.min(target.hi), target.lo.max(target.hi))
+                }
+                OscArg::Float(x) => {
+                    let x = (x.clamp(0.0, 1.0) * 65_536.0) as i64;
+                    target.lo + (((target.hi - target.lo) * x) >> 16)
+                }
+                OscArg::Str(_) => return None,
+            };
+            (target.set)(value);
+            None
+        } else if message.address == NOTE_ADDRESS {
+            let note = message.args.first()?.to_i32()?.clamp(0, 127) as u8;
+            let velocity = match message.args.get(1) {
+                Some(arg) => arg.to_i32()?.clamp(0, 127) as u8,
+                None => 100,
+            };
+            Some(if velocity == 0 {
+                NoteEvent::Off { note }
+            } else {
+                NoteEvent::On { note, velocity }
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for OscRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::param::param;
+
+    fn message(address: &str, args: Vec<OscArg>) -> OscMessage {
+        OscMessage {
+            address: address.into(),
+            args,
+        }
+    }
+
+    #[test]
+    fn test_osc_parse() {
+        // From the OSC 1.0 specification
+        let bytes = b"/foo\0\0\0\0,iisff\0\0\0\0\x03\xe8\xff\xff\xff\xffhello\0\0\0\x3f\x9d\xf3\xb6\x40\xb5\xb2\x2d";
+        let parsed = OscMessage::parse(bytes).unwrap();
+        assert_eq!(
+            parsed,
+            message(
+                "/foo",
+                vec![
+                    OscArg::Int(1_000),
+                    OscArg::Int(-1),
+                    OscArg::Str("hello".into()),
+                    OscArg::Float(1.234),
+                    OscArg::Float(5.678),
+                ]
+            )
+        );
+        let mut encoded = Vec::new();
+        parsed.encode(&mut encoded);
+        assert_eq!(encoded, bytes);
+        assert_eq!(OscMessage::parse(&bytes[..bytes.len() - 2]), None);
+        assert_eq!(OscMessage::parse(b"foo\0"), None);
+
+        // Nested bundles
+        let mut inner = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        let mut note = Vec::new();
+        message("/note", vec![OscArg::Int(60)]).encode(&mut note);
+        inner.extend_from_slice(&(note.len() as u32).to_be_bytes());
+        inner.extend_from_slice(&note);
+        let mut bundle = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        for element in [&inner, &encoded] {
+            bundle.extend_from_slice(&(element.len() as u32).to_be_bytes());
+            bundle.extend_from_slice(element);
+        }
+        let messages = OscMessage::parse_packet(&bundle);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].address, "/note");
+    }
+
+    #[test]
+    fn test_osc_route() {
+        let (mut level, handle) = param(0_i16);
+        let mut router = OscRouter::new();
+        router.register("level", 0, 1_000, handle);
+
+        let set = |args| router.route(&message("/param/level", args));
+        assert_eq!(set(vec![OscArg::Float(0.25)]), None);
+        assert_eq!(level.poll(), Some(250));
+        set(vec![OscArg::Int(2_000)]);
+        assert_eq!(level.poll(), Some(1_000));
+        set(vec![OscArg::Str("x".into())]);
+        router.route(&message("/param/other", vec![OscArg::Int(1)]));
+        assert_eq!(level.poll(), None);
+
+        let note = |args| router.route(&message(NOTE_ADDRESS, args));
+        assert_eq!(
+            note(vec![OscArg::Int(60), OscArg::Float(90.0)]),
+            Some(NoteEvent::On {
+                note: 60,
+                velocity: 90
+            })
+        );
+        assert_eq!(
+            note(vec![OscArg::Int(60), OscArg::Int(0)]),
+            Some(NoteEvent::Off { note: 60 })
+        );
+        assert_eq!(note(vec![]), None);
+    }
+}
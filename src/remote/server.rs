@@ -0,0 +1,138 @@
+// UDP server that receives OSC packets in a thread of its own.
+
+use crate::remote::{OscMessage, OscRouter};
+use crate::synth::voice::{Voice, VoiceAllocator};
+use crate::synth::NoteEvent;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Largest packet that is received
+const PACKET_MAX: usize = 4_096;
+/// Interval at which the thread checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// OSC server on a UDP port
+///
+/// The server thread sets the parameters of the [OscRouter] directly
+/// through their handles and passes the note events through a channel, so
+/// the audio thread only has to poll them, e.g. once per block. The thread
+/// stops when the server is dropped.
+pub struct OscServer {
+    addr: SocketAddr,
+    receiver: Receiver<NoteEvent>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl OscServer {
+    /// Listens on `addr`, e.g. `"0.0.0.0:9000"`, and routes the messages
+    /// through `router`.
+    pub fn bind(addr: impl ToSocketAddrs, router: OscRouter) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let addr = socket.local_addr()?;
+        let (sender, receiver) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let mut buf = [0_u8; PACKET_MAX];
+            while !thread_stop.load(Ordering::Relaxed) {
+                // Timeouts and malformed packets are skipped alike
+                let Ok(len) = socket.recv(&mut buf) else {
+                    continue;
+                };
+                for message in OscMessage::parse_packet(&buf[..len]) {
+                    if let Some(event) = router.route(&message) {
+                        let _ = sender.send(event);
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            addr,
+            receiver,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the note events received since the last poll.
+    pub fn poll(&self) -> impl Iterator<Item = NoteEvent> + '_ {
+        self.receiver.try_iter()
+    }
+
+    /// Plays the received note events on `allocator`.
+    pub fn feed<V: Voice, const N: usize>(&self, allocator: &mut VoiceAllocator<V, N>) {
+        for event in self.poll() {
+            allocator.handle_event(event);
+        }
+    }
+}
+
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::remote::OscArg;
+    use crate::util::param::param;
+
+    #[test]
+    fn test_osc_server() {
+        let (mut gain, handle) = param(0_i32);
+        let mut router = OscRouter::new();
+        router.register("gain", 0, 100, handle);
+        let server = OscServer::bind("127.0.0.1:0", router).unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for message in [
+            OscMessage {
+                address: "/param/gain".into(),
+                args: vec![OscArg::Int(42)],
+            },
+            OscMessage {
+                address: "/note".into(),
+                args: vec![OscArg::Int(64), OscArg::Int(80)],
+            },
+        ] {
+            let mut bytes = Vec::new();
+            message.encode(&mut bytes);
+            client.send_to(&bytes, server.local_addr()).unwrap();
+        }
+
+        let mut events = Vec::new();
+        for _ in 0..100 {
+            events.extend(server.poll());
+            if !events.is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            events,
+            [NoteEvent::On {
+                note: 64,
+                velocity: 80
+            }]
+        );
+        // The parameter is set before the later note arrives
+        assert_eq!(gain.poll(), Some(42));
+    }
+}
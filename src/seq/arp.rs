@@ -0,0 +1,326 @@
+// Arpeggiator that plays the held notes one after another on the ticks of
+// a clock.
+
+use crate::preset::random::Rng;
+use crate::seq::Division;
+use crate::synth::NoteEvent;
+
+/// Most notes an [Arpeggiator] holds
+pub const ARP_MAX_NOTES: usize = 16;
+/// Most octaves an [Arpeggiator] spans
+pub const ARP_MAX_OCTAVES: u8 = 4;
+
+/// Order of the notes of an [Arpeggiator]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ArpMode {
+    /// From the lowest to the highest note.
+    Up,
+    /// From the highest to the lowest note.
+    Down,
+    /// Up and down again without repeating the highest and lowest notes.
+    UpDown,
+    /// Random notes from a seeded generator.
+    Random,
+    /// In the order the notes were pressed.
+    AsPlayed,
+}
+
+/// Arpeggiator
+///
+/// Notes that are held with [Arpeggiator::note_on] play one per step, in
+/// the order of the mode and repeated in higher octaves. The steps run on
+/// the ticks of a clock like the [crate::seq::step::StepSequencer], and the
+/// gate length is the part of the step that a note sounds.
+///
+/// ```
+/// use isopod::seq::arp::{ArpMode, Arpeggiator};
+/// use isopod::synth::NoteEvent;
+///
+/// let mut arp = Arpeggiator::new();
+/// arp.set_mode(ArpMode::Down);
+/// arp.set_octaves(2);
+/// arp.note_on(60, 100);
+/// arp.note_on(64, 100);
+/// let mut notes = Vec::new();
+/// // Four sixteenths of 6 ticks
+/// for _ in 0..24 {
+///     arp.tick(|event| {
+///         if let NoteEvent::On { note, .. } = event {
+///             notes.push(note);
+///         }
+///     });
+/// }
+/// assert_eq!(notes, [76, 72, 64, 60]);
+/// ```
+pub struct Arpeggiator {
+    // Notes and velocities in the order they were pressed
+    held: [(u8, u8); ARP_MAX_NOTES],
+    len: usize,
+    mode: ArpMode,
+    octaves: u8,
+    division: Division,
+    gate: u8,
+    rng: Rng,
+
+    // Index of the next note in the sequence
+    index: usize,
+    // Ticks since the start of the step
+    tick: u32,
+    playing: Option<u8>,
+}
+
+impl Arpeggiator {
+    pub fn new() -> Self {
+        Self {
+            held: [(0, 0); ARP_MAX_NOTES],
+            len: 0,
+            mode: ArpMode::Up,
+            octaves: 1,
+            division: Division::Sixteenth,
+            gate: 50,
+            rng: Rng::new(1),
+
+            index: 0,
+            tick: 0,
+            playing: None,
+        }
+    }
+
+    /// Holds `note`. Notes beyond [ARP_MAX_NOTES] are ignored. The sequence
+    /// starts from the beginning when the first note is pressed.
+    pub fn note_on(&mut self, note: u8, velocity: u8) {
+        if velocity == 0 {
+            return self.note_off(note);
+        }
+        if self.held[..self.len].iter().any(|(n, _)| *n == note) || self.len == ARP_MAX_NOTES {
+            return;
+        }
+        if self.len == 0 {
+            self.index = 0;
+        }
+        self.held[self.len] = (note, velocity);
+        self.len += 1;
+    }
+
+    /// Stops holding `note`. The playing note sounds until its gate ends.
+    pub fn note_off(&mut self, note: u8) {
+        if let Some(i) = self.held[..self.len].iter().position(|(n, _)| *n == note) {
+            self.held.copy_within(i + 1..self.len, i);
+            self.len -= 1;
+        }
+    }
+
+    /// Dispatches `event` to [Self::note_on] or [Self::note_off].
+    pub fn handle_event(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On { note, velocity } => self.note_on(note, velocity),
+            NoteEvent::Off { note } => self.note_off(note),
+        }
+    }
+
+    /// Stops holding all notes.
+    pub fn all_notes_off(&mut self) {
+        self.len = 0;
+    }
+
+    /// Returns the number of held notes.
+    pub fn get_held_count(&self) -> usize {
+        self.len
+    }
+
+    /// Advances by one clock tick and passes the note events of the tick to
+    /// `emit`.
+    pub fn tick(&mut self, mut emit: impl FnMut(NoteEvent)) {
+        let ticks = self.division.get_ticks();
+        if self.tick == 0 {
+            self.release(&mut emit);
+            if let Some((note, velocity)) = self.next_note() {
+                emit(NoteEvent::On { note, velocity });
+                self.playing = Some(note);
+            }
+        }
+        self.tick += 1;
+        if self.tick >= (ticks * self.gate as u32 / 100).max(1) {
+            self.release(&mut emit);
+        }
+        if self.tick >= ticks {
+            self.tick = 0;
+        }
+    }
+
+    fn release(&mut self, emit: &mut impl FnMut(NoteEvent)) {
+        if let Some(note) = self.playing.take() {
+            emit(NoteEvent::Off { note });
+        }
+    }
+
+    /// Returns the next note of the sequence and advances it.
+    fn next_note(&mut self) -> Option<(u8, u8)> {
+        if self.len == 0 {
+            return None;
+        }
+        let mut notes = self.held;
+        if self.mode != ArpMode::AsPlayed {
+            notes[..self.len].sort_unstable();
+        }
+        let total = self.len * self.octaves as usize;
+        let position = match self.mode {
+            ArpMode::Up | ArpMode::AsPlayed => self.index % total,
+            ArpMode::Down => total - 1 - self.index % total,
+            ArpMode::UpDown if total > 1 => {
+                let i = self.index % (2 * total - 2);
+                if i < total {
+                    i
+                } else {
+                    2 * total - 2 - i
+                }
+            }
+            ArpMode::UpDown => 0,
+            ArpMode::Random => self.rng.range(0, total as i32 - 1) as usize,
+        };
+        self.index = self.index.wrapping_add(1);
+        let (note, velocity) = notes[position % self.len];
+        let octave = (position / self.len) as u8;
+        Some((note.saturating_add(12 * octave).min(127), velocity))
+    }
+
+    /// Releases the playing note and restarts the sequence, e.g. when the
+    /// clock stops.
+    pub fn reset(&mut self, mut emit: impl FnMut(NoteEvent)) {
+        self.release(&mut emit);
+        self.index = 0;
+        self.tick = 0;
+    }
+
+    pub fn set_mode(&mut self, mode: ArpMode) {
+        self.mode = mode;
+    }
+
+    /// Sets the number of octaves from 1 to [ARP_MAX_OCTAVES].
+    pub fn set_octaves(&mut self, octaves: u8) {
+        self.octaves = octaves.clamp(1, ARP_MAX_OCTAVES);
+    }
+
+    /// Sets the note value of the steps.
+    pub fn set_division(&mut self, division: Division) {
+        self.division = division;
+        self.tick = self.tick.min(division.get_ticks() - 1);
+    }
+
+    /// Sets the gate length in percent of a step, from 1 to 100.
+    pub fn set_gate(&mut self, gate: u8) {
+        self.gate = gate.clamp(1, 100);
+    }
+
+    /// Seeds the generator of [ArpMode::Random].
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng = Rng::new(seed);
+    }
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns the notes of `steps` sixteenths.
+    fn notes(arp: &mut Arpeggiator, steps: usize) -> Vec<u8> {
+        let mut notes = Vec::new();
+        for _ in 0..steps * 6 {
+            arp.tick(|event| {
+                if let NoteEvent::On { note, .. } = event {
+                    notes.push(note)
+                }
+            });
+        }
+        notes
+    }
+
+    #[test]
+    fn test_arp_modes() {
+        let mut arp = Arpeggiator::new();
+        assert!(notes(&mut arp, 2).is_empty());
+        for note in [64, 60, 67] {
+            arp.note_on(note, 100);
+        }
+        arp.note_on(60, 90);
+        assert_eq!(arp.get_held_count(), 3);
+        assert_eq!(notes(&mut arp, 4), [60, 64, 67, 60]);
+
+        arp.set_mode(ArpMode::UpDown);
+        arp.set_octaves(2);
+        arp.reset(|_| {});
+        assert_eq!(
+            notes(&mut arp, 11),
+            [60, 64, 67, 72, 76, 79, 76, 72, 67, 64, 60]
+        );
+
+        arp.set_mode(ArpMode::AsPlayed);
+        arp.set_octaves(1);
+        arp.reset(|_| {});
+        assert_eq!(notes(&mut arp, 3), [64, 60, 67]);
+
+        // The sequence continues with the remaining notes
+        arp.note_off(60);
+        assert_eq!(notes(&mut arp, 2), [67, 64]);
+
+        arp.set_mode(ArpMode::Random);
+        arp.set_seed(7);
+        let random = notes(&mut arp, 16);
+        assert!(random.iter().all(|n| [64, 67].contains(n)));
+        assert!(random.contains(&64) && random.contains(&67));
+        arp.set_seed(7);
+        assert_eq!(notes(&mut arp, 16), random);
+    }
+
+    #[test]
+    fn test_arp_gate() {
+        let mut arp = Arpeggiator::new();
+        arp.set_division(Division::Quarter);
+        arp.set_gate(25);
+        arp.note_on(60, 80);
+        let mut events = Vec::new();
+        for t in 0..30 {
+            arp.tick(|event| events.push((t, event)));
+        }
+        assert_eq!(
+            events,
+            [
+                (
+                    0,
+                    NoteEvent::On {
+                        note: 60,
+                        velocity: 80
+                    }
+                ),
+                (5, NoteEvent::Off { note: 60 }),
+                (
+                    24,
+                    NoteEvent::On {
+                        note: 60,
+                        velocity: 80
+                    }
+                ),
+                (29, NoteEvent::Off { note: 60 }),
+            ]
+        );
+
+        // A full gate releases when the next note starts
+        arp.set_gate(100);
+        arp.reset(|_| {});
+        arp.all_notes_off();
+        arp.note_on(62, 80);
+        events.clear();
+        for t in 0..25 {
+            arp.tick(|event| events.push((t, event)));
+        }
+        assert_eq!(events[1], (23, NoteEvent::Off { note: 62 }));
+        assert_eq!(events.len(), 3);
+    }
+}
@@ -0,0 +1,145 @@
+// Euclidean rhythms, which distribute pulses as evenly as possible over
+// the steps of a loop.
+
+use crate::seq::Division;
+
+/// Most steps of a [Euclid] loop
+pub const EUCLID_MAX_STEPS: u8 = 64;
+
+/// Euclidean rhythm generator
+///
+/// `pulses` onsets are spread over `steps` steps, e.g. 3 of 8 is the
+/// tresillo `x..x..x.`. The rotation moves the pattern to later steps. Like
+/// the [crate::seq::step::StepSequencer], the generator runs on the ticks
+/// of a clock, and can set the gates of a sequencer track with
+/// [crate::seq::step::StepSequencer::set_gates].
+///
+/// ```
+/// use isopod::seq::euclid::Euclid;
+///
+/// let euclid = Euclid::new(3, 8);
+/// let pattern: String = (0..8)
+///     .map(|i| if euclid.is_pulse(i) { 'x' } else { '.' })
+///     .collect();
+/// assert_eq!(pattern, "x..x..x.");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Euclid {
+    pulses: u8,
+    steps: u8,
+    rotation: u8,
+    division: Division,
+    // Step that plays next or is playing
+    step: usize,
+    // Ticks since the start of the step
+    tick: u32,
+}
+
+impl Euclid {
+    /// Generator of `pulses` onsets in `steps` steps of a sixteenth.
+    pub fn new(pulses: u8, steps: u8) -> Self {
+        let mut euclid = Self {
+            pulses: 0,
+            steps: 1,
+            rotation: 0,
+            division: Division::Sixteenth,
+            step: 0,
+            tick: 0,
+        };
+        euclid.set_pattern(pulses, steps);
+        euclid
+    }
+
+    /// True if step `index` of the loop is an onset. Indices wrap around.
+    pub fn is_pulse(&self, index: usize) -> bool {
+        let steps = self.steps as usize;
+        let i = (index + steps - self.rotation as usize % steps) % steps;
+        i * (self.pulses as usize) % steps < self.pulses as usize
+    }
+
+    /// Advances by one clock tick. Returns true if an onset starts at this
+    /// tick.
+    pub fn tick(&mut self) -> bool {
+        let onset = self.tick == 0 && self.is_pulse(self.step);
+        self.tick += 1;
+        if self.tick >= self.division.get_ticks() {
+            self.tick = 0;
+            self.step = (self.step + 1) % self.steps as usize;
+        }
+        onset
+    }
+
+    /// Moves back to the first step.
+    pub fn reset(&mut self) {
+        self.step = 0;
+        self.tick = 0;
+    }
+
+    /// Returns the step that plays next or is playing.
+    pub fn get_current_step(&self) -> usize {
+        self.step
+    }
+
+    /// Sets `pulses` onsets in `steps` steps from 1 to [EUCLID_MAX_STEPS].
+    /// There are at most as many pulses as steps.
+    pub fn set_pattern(&mut self, pulses: u8, steps: u8) {
+        self.steps = steps.clamp(1, EUCLID_MAX_STEPS);
+        self.pulses = pulses.min(self.steps);
+        self.step %= self.steps as usize;
+    }
+
+    pub fn get_pulses(&self) -> u8 {
+        self.pulses
+    }
+
+    pub fn get_steps(&self) -> u8 {
+        self.steps
+    }
+
+    /// Moves the onsets `rotation` steps later.
+    pub fn set_rotation(&mut self, rotation: u8) {
+        self.rotation = rotation;
+    }
+
+    /// Sets the note value of the steps.
+    pub fn set_division(&mut self, division: Division) {
+        self.division = division;
+        self.tick = self.tick.min(division.get_ticks() - 1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pattern(euclid: &Euclid) -> String {
+        (0..euclid.get_steps() as usize)
+            .map(|i| if euclid.is_pulse(i) { 'x' } else { '.' })
+            .collect()
+    }
+
+    #[test]
+    fn test_euclid_patterns() {
+        assert_eq!(pattern(&Euclid::new(4, 16)), "x...x...x...x...");
+        assert_eq!(pattern(&Euclid::new(5, 8)), "x.x.xx.x");
+        assert_eq!(pattern(&Euclid::new(0, 5)), ".....");
+        let mut euclid = Euclid::new(9, 4);
+        assert_eq!(pattern(&euclid), "xxxx");
+        euclid.set_pattern(2, 5);
+        assert_eq!(pattern(&euclid), "x..x.");
+        euclid.set_rotation(1);
+        assert_eq!(pattern(&euclid), ".x..x");
+        // Rotations wrap around the loop
+        euclid.set_rotation(7);
+        assert_eq!(pattern(&euclid), "x.x..");
+    }
+
+    #[test]
+    fn test_euclid_tick() {
+        let mut euclid = Euclid::new(3, 8);
+        euclid.set_division(Division::ThirtySecond);
+        let onsets: Vec<usize> = (0..48).filter(|_| euclid.tick()).collect();
+        assert_eq!(onsets, [0, 9, 18, 24, 33, 42]);
+        assert_eq!(euclid.get_current_step(), 0);
+    }
+}
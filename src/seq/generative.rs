@@ -0,0 +1,377 @@
+// Generative sequencer track that evolves a loop of notes from a random walk
+// over a scale or from transitions learned from a pattern.
+
+use crate::preset::random::Rng;
+use crate::seq::step::{Pattern, MAX_STEPS};
+use crate::seq::Division;
+use crate::synth::quantizer::Quantizer;
+use crate::synth::NoteEvent;
+use crate::util::note::Note;
+use crate::util::scale::Scale;
+
+/// Most notes a [Generative] track learns transitions between
+pub const MARKOV_STATES: usize = 16;
+
+/// Source of new notes of a [Generative] track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GenerativeMode {
+    /// Random steps of up to a few semitones from the previous note,
+    /// quantized to the scale.
+    RandomWalk,
+    /// Transitions between notes as often as they appear in the learned
+    /// pattern.
+    Markov,
+}
+
+/// Generative sequencer track
+///
+/// The track loops a number of steps like the
+/// [crate::seq::step::StepSequencer]. Each time a step comes around, it is
+/// replaced by a new note with the chance of the mutation amount, so 0 %
+/// locks the loop and 100 % plays a new melody every time. The generator
+/// is seeded, so the same seed plays the same melody.
+///
+/// ```
+/// use isopod::seq::generative::{Generative, GenerativeMode};
+/// use isopod::synth::NoteEvent;
+/// use isopod::util::note::Note;
+/// use isopod::util::scale::Scale;
+///
+/// let mut track = Generative::new(7);
+/// track.set_mode(GenerativeMode::RandomWalk);
+/// track.set_scale(Scale::PENTATONIC_MINOR, 9);
+/// let mut notes = Vec::new();
+/// for _ in 0..16 * 6 {
+///     track.tick(|event| {
+///         if let NoteEvent::On { note, .. } = event {
+///             notes.push(note);
+///         }
+///     });
+/// }
+/// assert!(notes.iter().all(|n| Scale::PENTATONIC_MINOR.contains(9, Note(*n))));
+/// ```
+pub struct Generative {
+    mode: GenerativeMode,
+    quantizer: Quantizer,
+    scale: Scale,
+    root: u8,
+    lo: Note,
+    hi: Note,
+    spread: u8,
+    mutation: u8,
+    velocity: u8,
+    division: Division,
+    length: usize,
+    rng: Rng,
+
+    // Learned notes and the counts of the transitions between them
+    states: [u8; MARKOV_STATES],
+    state_count: usize,
+    transitions: [[u8; MARKOV_STATES]; MARKOV_STATES],
+
+    notes: [Option<u8>; MAX_STEPS],
+    step: usize,
+    tick: u32,
+    previous: u8,
+    playing: Option<u8>,
+}
+
+impl Generative {
+    /// Track with a random walk over C major from `seed`.
+    pub fn new(seed: u32) -> Self {
+        let mut track = Self {
+            mode: GenerativeMode::RandomWalk,
+            quantizer: Quantizer::new(),
+            scale: Scale::MAJOR,
+            root: 0,
+            lo: Note::C3,
+            hi: Note::C5,
+            spread: 4,
+            mutation: 25,
+            velocity: 100,
+            division: Division::Sixteenth,
+            length: 16,
+            rng: Rng::new(seed),
+
+            states: [0; MARKOV_STATES],
+            state_count: 0,
+            transitions: [[0; MARKOV_STATES]; MARKOV_STATES],
+
+            notes: [None; MAX_STEPS],
+            step: 0,
+            tick: 0,
+            previous: Note::C4.0,
+            playing: None,
+        };
+        track.set_scale(Scale::MAJOR, 0);
+        track
+    }
+
+    /// Advances by one clock tick and passes the note events of the tick to
+    /// `emit`. Notes are held for half a step.
+    pub fn tick(&mut self, mut emit: impl FnMut(NoteEvent)) {
+        let ticks = self.division.get_ticks();
+        if self.tick == 0 {
+            self.release(&mut emit);
+            let mutate = self.rng.range(0, 99) < self.mutation as i32;
+            let note = match self.notes[self.step] {
+                Some(note) if !mutate => note,
+                _ => self.generate(),
+            };
+            self.notes[self.step] = Some(note);
+            self.previous = note;
+            emit(NoteEvent::On {
+                note,
+                velocity: self.velocity,
+            });
+            self.playing = Some(note);
+        }
+        self.tick += 1;
+        if self.tick >= (ticks / 2).max(1) {
+            self.release(&mut emit);
+        }
+        if self.tick >= ticks {
+            self.tick = 0;
+            self.step = (self.step + 1) % self.length;
+        }
+    }
+
+    fn release(&mut self, emit: &mut impl FnMut(NoteEvent)) {
+        if let Some(note) = self.playing.take() {
+            emit(NoteEvent::Off { note });
+        }
+    }
+
+    /// Returns a new note after the previous one.
+    fn generate(&mut self) -> u8 {
+        if self.mode == GenerativeMode::Markov && self.state_count > 0 {
+            let from = self.states[..self.state_count]
+                .iter()
+                .position(|n| *n == self.previous);
+            let row = from
+                .map(|i| self.transitions[i])
+                .unwrap_or([1; MARKOV_STATES]);
+            let total: i32 = row[..self.state_count].iter().map(|c| *c as i32).sum();
+            if total > 0 {
+                let mut pick = self.rng.range(0, total - 1);
+                for (i, count) in row[..self.state_count].iter().enumerate() {
+                    pick -= *count as i32;
+                    if pick < 0 {
+                        return self.states[i];
+                    }
+                }
+            }
+        }
+        // Random walk, which reflects at the ends of the range
+        let spread = self.spread as i32 * 100;
+        let mut cents = self.previous as i32 * 100 + self.rng.range(-spread, spread);
+        let (lo, hi) = (self.lo.0 as i32 * 100, self.hi.0 as i32 * 100);
+        if cents < lo {
+            cents = 2 * lo - cents;
+        } else if cents > hi {
+            cents = 2 * hi - cents;
+        }
+        let Some(note) = self.quantizer.quantize_cents(cents.clamp(lo, hi)) else {
+            return self.previous;
+        };
+        // The nearest note of the scale may lie just outside the range
+        let inside = |n: &u8| self.scale.contains(self.root, Note(*n));
+        if note > self.hi {
+            (self.lo.0..=self.hi.0).rev().find(inside)
+        } else if note < self.lo {
+            (self.lo.0..=self.hi.0).find(inside)
+        } else {
+            Some(note.0)
+        }
+        .unwrap_or(self.previous)
+    }
+
+    /// Learns the transitions between the gated notes of `pattern`, where
+    /// the last note leads back to the first. Only the first
+    /// [MARKOV_STATES] different notes are learned.
+    pub fn learn(&mut self, pattern: &Pattern) {
+        self.state_count = 0;
+        self.transitions = [[0; MARKOV_STATES]; MARKOV_STATES];
+        let length = (pattern.length as usize).clamp(1, MAX_STEPS);
+        let mut sequence = pattern.steps[..length]
+            .iter()
+            .filter(|s| s.gate)
+            .map(|s| s.note)
+            .peekable();
+        let Some(&first) = sequence.peek() else {
+            return;
+        };
+        let mut from: Option<usize> = None;
+        for note in sequence.chain(core::iter::once(first)) {
+            let state = match self.states[..self.state_count]
+                .iter()
+                .position(|n| *n == note)
+            {
+                Some(i) => Some(i),
+                None if self.state_count < MARKOV_STATES => {
+                    self.states[self.state_count] = note;
+                    self.state_count += 1;
+                    Some(self.state_count - 1)
+                }
+                None => None,
+            };
+            if let (Some(from), Some(to)) = (from, state) {
+                let count = &mut self.transitions[from][to];
+                *count = count.saturating_add(1);
+            }
+            from = state;
+        }
+    }
+
+    /// Returns the number of learned notes.
+    pub fn get_state_count(&self) -> usize {
+        self.state_count
+    }
+
+    /// Releases the playing note and moves back to the first step. The
+    /// loop is kept.
+    pub fn reset(&mut self, mut emit: impl FnMut(NoteEvent)) {
+        self.release(&mut emit);
+        self.step = 0;
+        self.tick = 0;
+    }
+
+    /// Forgets the loop, so all steps are generated anew.
+    pub fn clear(&mut self) {
+        self.notes = [None; MAX_STEPS];
+    }
+
+    pub fn set_mode(&mut self, mode: GenerativeMode) {
+        self.mode = mode;
+    }
+
+    /// Sets the scale of the random walk on the pitch class `root`.
+    pub fn set_scale(&mut self, scale: Scale, root: u8) {
+        self.scale = scale;
+        self.root = root % 12;
+        self.quantizer.set_scale(scale, self.root);
+    }
+
+    pub fn get_scale(&self) -> (Scale, u8) {
+        (self.scale, self.root)
+    }
+
+    /// Sets the notes the random walk stays within.
+    pub fn set_range(&mut self, lo: Note, hi: Note) {
+        self.lo = lo.min(hi);
+        self.hi = hi.max(lo);
+        self.previous = self.previous.clamp(self.lo.0, self.hi.0);
+    }
+
+    /// Sets the largest step of the random walk in semitones.
+    pub fn set_spread(&mut self, semitones: u8) {
+        self.spread = semitones.max(1);
+    }
+
+    /// Sets the chance in percent that a step changes when it comes
+    /// around, from 0 to 100.
+    pub fn set_mutation(&mut self, mutation: u8) {
+        self.mutation = mutation.min(100);
+    }
+
+    pub fn set_velocity(&mut self, velocity: u8) {
+        self.velocity = velocity.clamp(1, 127);
+    }
+
+    /// Sets the number of steps of the loop, from 1 to [MAX_STEPS].
+    pub fn set_length(&mut self, length: usize) {
+        self.length = length.clamp(1, MAX_STEPS);
+        self.step %= self.length;
+    }
+
+    /// Sets the note value of the steps.
+    pub fn set_division(&mut self, division: Division) {
+        self.division = division;
+        self.tick = self.tick.min(division.get_ticks() - 1);
+    }
+
+    /// Seeds the generator.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng = Rng::new(seed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::seq::step::Step;
+
+    /// Returns the notes of `steps` sixteenths.
+    fn notes(track: &mut Generative, steps: usize) -> Vec<u8> {
+        let mut notes = Vec::new();
+        for _ in 0..steps * 6 {
+            track.tick(|event| {
+                if let NoteEvent::On { note, .. } = event {
+                    notes.push(note);
+                }
+            });
+        }
+        notes
+    }
+
+    #[test]
+    fn test_generative_walk() {
+        let mut track = Generative::new(3);
+        track.set_range(Note::C4, Note::C5);
+        track.set_spread(3);
+        track.set_length(8);
+        track.set_mutation(0);
+        let first = notes(&mut track, 8);
+        // Locked loops repeat, steps stay in the scale, range and spread
+        assert_eq!(notes(&mut track, 8), first);
+        assert!(first.iter().all(|n| (60..=72).contains(n)));
+        assert!(first.iter().all(|n| Scale::MAJOR.contains(0, Note(*n))));
+        for pair in first.windows(2) {
+            assert!(pair[0].abs_diff(pair[1]) <= 4, "{:?}", first);
+        }
+
+        // Full mutation changes the loop
+        track.set_mutation(100);
+        assert_ne!(notes(&mut track, 8), first);
+
+        let mut same = Generative::new(3);
+        same.set_range(Note::C4, Note::C5);
+        same.set_spread(3);
+        same.set_length(8);
+        same.set_mutation(0);
+        assert_eq!(notes(&mut same, 8), first);
+    }
+
+    #[test]
+    fn test_generative_markov() {
+        let mut pattern = Pattern {
+            length: 4,
+            ..Default::default()
+        };
+        for (i, note) in [60, 64, 67, 64].iter().enumerate() {
+            pattern.steps[i] = Step::note(*note, 100);
+        }
+        let mut track = Generative::new(11);
+        track.learn(&pattern);
+        assert_eq!(track.get_state_count(), 3);
+        track.set_mode(GenerativeMode::Markov);
+        track.set_mutation(100);
+        let played = notes(&mut track, 64);
+        // Only learned transitions: the third always follows the
+        // fifth and 60 or 67 always follow the third
+        for pair in played.windows(2) {
+            match pair[0] {
+                60 | 67 => assert_eq!(pair[1], 64),
+                64 => assert!([60, 67].contains(&pair[1])),
+                _ => panic!("{:?}", played),
+            }
+        }
+
+        // Without learned notes the walk takes over
+        track.learn(&Pattern::default());
+        assert_eq!(track.get_state_count(), 0);
+        track.clear();
+        assert_eq!(notes(&mut track, 4).len(), 4);
+    }
+}
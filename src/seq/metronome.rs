@@ -0,0 +1,199 @@
+// Metronome that clicks on the beats of the transport.
+
+use crate::fx::dynamics::{coefficient, COEF_NORM};
+use crate::midi::clock::CLOCK_PPQ;
+use crate::osc::luts::SINE_I16;
+use crate::osc::noise::LFSR;
+use crate::seq::transport::Transport;
+use crate::util::units::{mHz, ms, Frequency, Hz};
+
+/// Shift that maps the 32-bit phase onto the 1024 entries of [SINE_I16]
+const SINE_SHIFT: u32 = 22;
+/// Fractional bits of the click envelope
+const LEVEL_SHIFT: u32 = 16;
+/// Pitch of the downbeat click
+const ACCENT_FREQ: Hz = Hz(1_760);
+/// Pitch of the other clicks
+const BEAT_FREQ: Hz = Hz(880);
+/// Time constant of the click decay
+const CLICK_DECAY: ms = ms(8);
+/// Most beats of a bar
+pub const BEATS_MAX: u8 = 16;
+
+/// Metronome
+///
+/// Clicks on each quarter note of a [Transport], with a higher and louder
+/// click on the first beat of the bar. The clicks are short sine bursts
+/// with a little noise for a sharp attack. [Metronome::process] runs the
+/// transport itself. When the transport also drives sequencers, pass its
+/// ticks to [Metronome::on_tick] and render the blocks between the ticks.
+///
+/// ```
+/// use isopod::seq::metronome::Metronome;
+/// use isopod::seq::transport::Transport;
+///
+/// let mut transport = Transport::new();
+/// let mut metronome = Metronome::new();
+/// transport.start();
+/// let mut out = [0; 256];
+/// metronome.process(&mut transport, &mut out);
+/// assert!(out.iter().any(|y| *y != 0));
+/// ```
+pub struct Metronome {
+    beats_per_bar: u8,
+    volume: i16,
+    noise: LFSR<u32>,
+
+    // Envelope of the current click with LEVEL_SHIFT fractional bits
+    level: i64,
+    phi: u32,
+    delta_phi: u32,
+    decay_coef: u32,
+
+    msample_rate: mHz,
+}
+
+impl Metronome {
+    pub fn new() -> Self {
+        let mut s = Self {
+            beats_per_bar: 4,
+            volume: i16::MAX / 2,
+            noise: LFSR::<u32>::default(),
+
+            level: 0,
+            phi: 0,
+            delta_phi: 0,
+            decay_coef: 0,
+
+            msample_rate: mHz(44_100_000),
+        };
+        s.set_msample_rate(s.msample_rate);
+        s
+    }
+
+    /// Starts a click if `tick` of the transport is on a beat.
+    pub fn on_tick(&mut self, tick: u32) {
+        if !tick.is_multiple_of(CLOCK_PPQ) {
+            return;
+        }
+        let beat = tick / CLOCK_PPQ;
+        let accent = beat.is_multiple_of(self.beats_per_bar as u32);
+        let (freq, volume) = if accent {
+            (ACCENT_FREQ, self.volume as i64)
+        } else {
+            (BEAT_FREQ, self.volume as i64 * 3 / 5)
+        };
+        self.delta_phi = (((freq.to_mHz().0 as u64) << 32) / self.msample_rate.0 as u64) as u32;
+        self.phi = 0;
+        self.level = volume << LEVEL_SHIFT;
+    }
+
+    #[inline]
+    fn _next(&mut self) -> i16 {
+        if self.level == 0 {
+            return 0;
+        }
+        let sine = SINE_I16[(self.phi >> SINE_SHIFT) as usize] as i64;
+        let noise = (self.noise.next() & 0xFFFF) as u16 as i16 as i64;
+        let y = ((sine + noise / 4) * self.level) >> (LEVEL_SHIFT + 15);
+        self.phi = self.phi.wrapping_add(self.delta_phi);
+        self.level -= (self.level * self.decay_coef as i64) / COEF_NORM as i64;
+        if self.level < 1 << LEVEL_SHIFT {
+            self.level = 0;
+        }
+        y.clamp(i16::MIN as i64, i16::MAX as i64) as i16
+    }
+
+    /// Fills `out` with the clicks that were started.
+    pub fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self._next();
+        }
+    }
+
+    /// Advances `transport` by the length of `out` and fills `out` with
+    /// the clicks on the exact samples of the beats.
+    pub fn process(&mut self, transport: &mut Transport, out: &mut [i16]) {
+        let mut start = 0;
+        transport.advance(out.len(), |offset, tick| {
+            self.render(&mut out[start..offset]);
+            start = offset;
+            self.on_tick(tick);
+        });
+        self.render(&mut out[start..]);
+    }
+
+    /// True while a click sounds.
+    pub fn is_active(&self) -> bool {
+        self.level != 0
+    }
+
+    /// Sets the number of beats of a bar, from 1 to [BEATS_MAX].
+    pub fn set_beats_per_bar(&mut self, beats: u8) {
+        self.beats_per_bar = beats.clamp(1, BEATS_MAX);
+    }
+
+    pub fn get_beats_per_bar(&self) -> u8 {
+        self.beats_per_bar
+    }
+
+    /// Sets the peak of the downbeat clicks. The other clicks are softer.
+    pub fn set_volume(&mut self, volume: i16) {
+        self.volume = volume.max(0);
+    }
+
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = mHz(msample_rate.0.max(1));
+        self.decay_coef = coefficient(CLICK_DECAY, self.msample_rate);
+    }
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::units::Bpm;
+
+    #[test]
+    fn test_metronome_clicks() {
+        let mut transport = Transport::new();
+        transport.set_bpm(Bpm(120));
+        transport.start();
+        let mut metronome = Metronome::new();
+        metronome.set_beats_per_bar(3);
+        // Two bars of 3/4 of 22050 samples per beat
+        let mut out = vec![0; 6 * 22_050];
+        for block in out.chunks_mut(128) {
+            metronome.process(&mut transport, block);
+        }
+        let peaks: Vec<i16> = out
+            .chunks(22_050)
+            .map(|beat| beat.iter().map(|y| y.abs()).max().unwrap())
+            .collect();
+        assert!(peaks.iter().all(|p| *p > 1_000), "{:?}", peaks);
+        assert!(peaks[0] > peaks[1] && peaks[3] > peaks[2]);
+        assert!(peaks[1].abs_diff(peaks[2]) < 1_000);
+        // The clicks are over long before the next beat
+        assert!(out[..22_050][5_000..].iter().all(|y| *y == 0));
+        assert!(out[22_050] != 0 || out[22_051] != 0);
+    }
+
+    #[test]
+    fn test_metronome_ticks() {
+        let mut metronome = Metronome::new();
+        metronome.on_tick(5);
+        assert!(!metronome.is_active());
+        metronome.on_tick(CLOCK_PPQ);
+        assert!(metronome.is_active());
+        metronome.render(&mut [0; 10_000]);
+        assert!(!metronome.is_active());
+        metronome.set_volume(0);
+        metronome.on_tick(0);
+        assert!(!metronome.is_active());
+    }
+}
@@ -0,0 +1,92 @@
+// Sequencers that generate note events on the ticks of a clock.
+
+pub mod arp;
+pub mod euclid;
+pub mod generative;
+pub mod metronome;
+pub mod multitrack;
+pub mod song;
+pub mod step;
+pub mod transport;
+
+use crate::midi::clock::CLOCK_PPQ;
+use crate::util::units::{mHz, ms, us, Bpm, Period};
+
+/// Note value of the steps of a sequencer
+///
+/// Besides the ticks of a step, a division converts to a duration, a rate or
+/// a number of samples at a tempo, e.g. for synced LFOs and delays.
+/// ```
+/// use isopod::seq::Division;
+/// use isopod::util::units::{mHz, ms, us, Bpm};
+///
+/// assert_eq!(Division::Sixteenth.to_ms(Bpm(120)), ms(125));
+/// assert_eq!(Division::DottedEighth.to_us(Bpm(120)), us(375_000));
+/// assert_eq!(Division::EighthTriplet.to_mHz(Bpm(120)), mHz(6_000));
+/// assert_eq!(Division::Quarter.to_samples(Bpm(120), mHz(48_000_000)), 24_000);
+/// assert_eq!(Division::Quarter.to_ms(Bpm(0)), ms(u32::MAX));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Division {
+    Half,
+    DottedQuarter,
+    Quarter,
+    QuarterTriplet,
+    DottedEighth,
+    Eighth,
+    EighthTriplet,
+    DottedSixteenth,
+    Sixteenth,
+    SixteenthTriplet,
+    ThirtySecond,
+}
+
+impl Division {
+    /// Returns the length in clock ticks of [CLOCK_PPQ].
+    pub const fn get_ticks(self) -> u32 {
+        match self {
+            Division::Half => CLOCK_PPQ * 2,
+            Division::DottedQuarter => CLOCK_PPQ * 3 / 2,
+            Division::Quarter => CLOCK_PPQ,
+            Division::QuarterTriplet => CLOCK_PPQ * 2 / 3,
+            Division::DottedEighth => CLOCK_PPQ * 3 / 4,
+            Division::Eighth => CLOCK_PPQ / 2,
+            Division::EighthTriplet => CLOCK_PPQ / 3,
+            Division::DottedSixteenth => CLOCK_PPQ * 3 / 8,
+            Division::Sixteenth => CLOCK_PPQ / 4,
+            Division::SixteenthTriplet => CLOCK_PPQ / 6,
+            Division::ThirtySecond => CLOCK_PPQ / 8,
+        }
+    }
+
+    /// Returns the duration at `bpm`. A tempo of 0 saturates to u32::MAX.
+    pub fn to_us(self, bpm: Bpm) -> us {
+        let beat = bpm.to_us().0 as u64;
+        us((beat * self.get_ticks() as u64 / CLOCK_PPQ as u64).min(u32::MAX as u64) as u32)
+    }
+
+    /// Returns the duration at `bpm`. A tempo of 0 saturates to u32::MAX.
+    pub fn to_ms(self, bpm: Bpm) -> ms {
+        match bpm.0 {
+            0 => ms(u32::MAX),
+            _ => ms(self.to_us(bpm).0 / 1_000),
+        }
+    }
+
+    /// Returns the rate of one cycle per division at `bpm`.
+    #[allow(non_snake_case)]
+    pub fn to_mHz(self, bpm: Bpm) -> mHz {
+        let mfreq = bpm.0 as u64 * 1_000 * CLOCK_PPQ as u64 / (60 * self.get_ticks() as u64);
+        mHz(mfreq.min(u32::MAX as u64) as u32)
+    }
+
+    /// Returns the number of samples at `bpm` and `msample_rate`, e.g. for a
+    /// delay time. A tempo of 0 saturates to u32::MAX.
+    pub fn to_samples(self, bpm: Bpm, msample_rate: mHz) -> u32 {
+        let n = (60_000 * self.get_ticks() as u64 * msample_rate.0 as u64)
+            .checked_div(1_000_000 * bpm.0 as u64 * CLOCK_PPQ as u64)
+            .unwrap_or(u64::MAX);
+        n.min(u32::MAX as u64) as u32
+    }
+}
@@ -0,0 +1,158 @@
+// Several step sequencer tracks on one clock, each with its own length and
+// clock division.
+
+use crate::seq::step::StepSequencer;
+use crate::synth::NoteEvent;
+
+/// Slowest clock divider of a track
+pub const DIVIDER_MAX: u8 = 16;
+
+/// Step sequencer tracks that share a clock
+///
+/// Each track has its own pattern length and note division, and can run
+/// on every n-th clock tick. Tracks of different lengths drift against
+/// each other and line up again after [MultiTrack::get_cycle_ticks], so
+/// short loops add up to long polymetric patterns. The note events are
+/// passed on with the index of their track, e.g. to play the tracks on
+/// different voices.
+///
+/// ```
+/// use isopod::seq::multitrack::MultiTrack;
+/// use isopod::seq::step::{StepSequencer, Step};
+///
+/// let mut tracks = MultiTrack::new([StepSequencer::new(), StepSequencer::new()]);
+/// tracks.get_track_mut(0).set_length(3);
+/// tracks.get_track_mut(1).set_length(4);
+/// // 3 against 4 sixteenths line up after 12 sixteenths
+/// assert_eq!(tracks.get_cycle_ticks(), 12 * 6);
+/// ```
+pub struct MultiTrack<const N: usize> {
+    tracks: [StepSequencer; N],
+    dividers: [u8; N],
+    // Clock ticks since the last tick of each track
+    counts: [u8; N],
+}
+
+impl<const N: usize> MultiTrack<N> {
+    pub fn new(tracks: [StepSequencer; N]) -> Self {
+        Self {
+            tracks,
+            dividers: [1; N],
+            counts: [0; N],
+        }
+    }
+
+    /// Advances by one clock tick and passes the note events of all tracks
+    /// with their track index to `emit`.
+    pub fn tick(&mut self, mut emit: impl FnMut(usize, NoteEvent)) {
+        for (i, track) in self.tracks.iter_mut().enumerate() {
+            if self.counts[i] == 0 {
+                track.tick(|event| emit(i, event));
+            }
+            self.counts[i] = (self.counts[i] + 1) % self.dividers[i];
+        }
+    }
+
+    /// Releases the playing notes and moves all tracks back to their first
+    /// step, so they line up again.
+    pub fn reset(&mut self, mut emit: impl FnMut(usize, NoteEvent)) {
+        for (i, track) in self.tracks.iter_mut().enumerate() {
+            track.reset(|event| emit(i, event));
+        }
+        self.counts = [0; N];
+    }
+
+    /// Returns the number of clock ticks after which all tracks loop
+    /// together.
+    pub fn get_cycle_ticks(&self) -> u64 {
+        self.tracks
+            .iter()
+            .zip(self.dividers.iter())
+            .map(|(track, divider)| {
+                let ticks = track.get_division().get_ticks() as u64;
+                track.get_length() as u64 * ticks * *divider as u64
+            })
+            .fold(1, lcm)
+    }
+
+    /// Runs `track` on every `divider`-th clock tick, from 1 to
+    /// [DIVIDER_MAX].
+    pub fn set_divider(&mut self, track: usize, divider: u8) {
+        if let Some(d) = self.dividers.get_mut(track) {
+            *d = divider.clamp(1, DIVIDER_MAX);
+            self.counts[track] %= *d;
+        }
+    }
+
+    pub fn get_divider(&self, track: usize) -> Option<u8> {
+        self.dividers.get(track).copied()
+    }
+
+    pub fn get_track_mut(&mut self, track: usize) -> &mut StepSequencer {
+        &mut self.tracks[track]
+    }
+
+    pub fn get_tracks_mut(&mut self) -> &mut [StepSequencer; N] {
+        &mut self.tracks
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn lcm(a: u64, b: u64) -> u64 {
+    a / gcd(a, b) * b
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::seq::step::Step;
+    use crate::seq::Division;
+
+    #[test]
+    fn test_polymetric_tracks() {
+        let mut tracks = MultiTrack::new([StepSequencer::new(), StepSequencer::new()]);
+        for (i, track) in tracks.get_tracks_mut().iter_mut().enumerate() {
+            track.set_step(0, Step::note(36 + i as u8, 100));
+        }
+        tracks.get_track_mut(0).set_length(3);
+        tracks.get_track_mut(1).set_length(2);
+        tracks.get_track_mut(1).set_division(Division::Eighth);
+        assert_eq!(tracks.get_cycle_ticks(), 72);
+
+        let mut ons = Vec::new();
+        for t in 0..72 {
+            tracks.tick(|track, event| {
+                if let NoteEvent::On { .. } = event {
+                    ons.push((t, track));
+                }
+            });
+        }
+        let first: Vec<usize> = ons.iter().filter(|o| o.1 == 0).map(|o| o.0).collect();
+        let second: Vec<usize> = ons.iter().filter(|o| o.1 == 1).map(|o| o.0).collect();
+        assert_eq!(first, [0, 18, 36, 54]);
+        assert_eq!(second, [0, 24, 48]);
+
+        // A divider of 3 stretches the second track to 72 ticks
+        tracks.reset(|_, _| {});
+        tracks.set_divider(1, 3);
+        tracks.set_divider(2, 3);
+        assert_eq!(tracks.get_divider(1), Some(3));
+        assert_eq!(tracks.get_cycle_ticks(), 72);
+        let mut second = Vec::new();
+        for t in 0..144 {
+            tracks.tick(|track, event| {
+                if let (1, NoteEvent::On { .. }) = (track, event) {
+                    second.push(t);
+                }
+            });
+        }
+        assert_eq!(second, [0, 72]);
+    }
+}
@@ -0,0 +1,306 @@
+// Bank of sequencer patterns and song arrangements that chain them.
+
+use crate::midi::clock::CLOCK_PPQ;
+use crate::preset::Preset;
+use crate::seq::step::{Pattern, StepSequencer};
+use crate::synth::NoteEvent;
+
+/// Number of patterns in the bank of a [Song]
+pub const BANK_SIZE: usize = 16;
+/// Most entries of the arrangement of a [Song]
+pub const SONG_MAX: usize = 32;
+/// Ticks of a bar in 4/4
+pub const TICKS_PER_BAR: u32 = 4 * CLOCK_PPQ;
+
+/// Entry of a song arrangement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SongEntry {
+    /// Index of the pattern in the bank
+    pub pattern: u8,
+    /// Number of loops of the pattern, at least 1
+    pub repeats: u8,
+}
+
+/// Step sequencer with a bank of patterns and a song arrangement
+///
+/// In pattern mode the current pattern loops, and [Song::queue] switches to
+/// another pattern at the next bar, so live changes stay on the beat. In
+/// song mode the entries of the arrangement play one after another, each
+/// for a number of loops of its pattern, and the song starts over at the
+/// end.
+///
+/// ```
+/// use isopod::seq::song::{Song, SongEntry};
+/// use isopod::seq::step::{Pattern, Step};
+///
+/// let mut song = Song::new();
+/// let mut pattern = Pattern::default();
+/// pattern.steps[0] = Step::note(36, 100);
+/// song.set_pattern(1, pattern);
+/// song.set_arrangement(&[
+///     SongEntry { pattern: 0, repeats: 1 },
+///     SongEntry { pattern: 1, repeats: 2 },
+/// ]);
+/// song.set_song_mode(true);
+/// // The first bar is silent, the second starts with the kick
+/// let mut notes = 0;
+/// for _ in 0..2 * 96 {
+///     song.tick(|event| notes += matches!(event, isopod::synth::NoteEvent::On { .. }) as usize);
+/// }
+/// assert_eq!(notes, 1);
+/// assert_eq!(song.get_current_pattern(), 1);
+/// ```
+pub struct Song {
+    seq: StepSequencer,
+    bank: [Pattern; BANK_SIZE],
+    arrangement: [SongEntry; SONG_MAX],
+    len: usize,
+    song_mode: bool,
+
+    // Pattern in the sequencer
+    current: u8,
+    // Pattern that starts with the next bar
+    queued: Option<u8>,
+    // Position in the arrangement
+    entry: usize,
+    repeat: u8,
+    // Ticks since the start of the bar and of the loop of the pattern
+    bar_tick: u32,
+    loop_tick: u32,
+}
+
+impl Song {
+    pub fn new() -> Self {
+        Self {
+            seq: StepSequencer::new(),
+            bank: [Pattern::default(); BANK_SIZE],
+            arrangement: [SongEntry {
+                pattern: 0,
+                repeats: 1,
+            }; SONG_MAX],
+            len: 1,
+            song_mode: false,
+
+            current: 0,
+            queued: None,
+            entry: 0,
+            repeat: 0,
+            bar_tick: 0,
+            loop_tick: 0,
+        }
+    }
+
+    /// Advances by one clock tick and passes the note events of the tick to
+    /// `emit`.
+    pub fn tick(&mut self, mut emit: impl FnMut(NoteEvent)) {
+        if self.bar_tick == 0 {
+            if let Some(pattern) = self.queued.take() {
+                self.load(pattern, &mut emit);
+            }
+        }
+        self.seq.tick(&mut emit);
+        self.bar_tick = (self.bar_tick + 1) % TICKS_PER_BAR;
+        self.loop_tick += 1;
+
+        let pattern = &self.bank[self.current as usize];
+        let loop_ticks = self.seq.get_length() as u32 * pattern.division.get_ticks();
+        if self.loop_tick >= loop_ticks {
+            self.loop_tick = 0;
+            if self.song_mode && self.queued.is_none() {
+                self.repeat += 1;
+                if self.repeat >= self.arrangement[self.entry].repeats.max(1) {
+                    self.entry = (self.entry + 1) % self.len;
+                    self.repeat = 0;
+                    self.load(self.arrangement[self.entry].pattern, &mut emit);
+                }
+            }
+        }
+    }
+
+    fn load(&mut self, pattern: u8, emit: &mut impl FnMut(NoteEvent)) {
+        self.current = pattern;
+        self.seq.reset(emit);
+        self.seq.set_params(&self.bank[pattern as usize]);
+        self.loop_tick = 0;
+    }
+
+    /// Releases the playing note and moves back to the start of the bar and
+    /// of the song, e.g. when the clock stops.
+    pub fn reset(&mut self, mut emit: impl FnMut(NoteEvent)) {
+        self.bar_tick = 0;
+        self.entry = 0;
+        self.repeat = 0;
+        self.queued = None;
+        let pattern = if self.song_mode {
+            self.arrangement[0].pattern
+        } else {
+            self.current
+        };
+        self.load(pattern, &mut emit);
+    }
+
+    /// Switches to the pattern `index` of the bank at the next bar.
+    pub fn queue(&mut self, index: usize) {
+        if index < BANK_SIZE {
+            self.queued = Some(index as u8);
+        }
+    }
+
+    /// Returns the pattern that switches at the next bar.
+    pub fn get_queued(&self) -> Option<usize> {
+        self.queued.map(|q| q as usize)
+    }
+
+    /// Returns the index of the pattern that plays.
+    pub fn get_current_pattern(&self) -> usize {
+        self.current as usize
+    }
+
+    /// Stores `pattern` in the bank at `index`. Changes of the playing
+    /// pattern apply immediately.
+    pub fn set_pattern(&mut self, index: usize, pattern: Pattern) {
+        if let Some(p) = self.bank.get_mut(index) {
+            *p = pattern;
+            if index == self.current as usize {
+                self.seq.set_params(&pattern);
+            }
+        }
+    }
+
+    pub fn get_pattern(&self, index: usize) -> Option<&Pattern> {
+        self.bank.get(index)
+    }
+
+    /// Sets the arrangement from up to [SONG_MAX] entries. Entries of
+    /// patterns beyond the bank are skipped, and an empty arrangement
+    /// loops the current pattern.
+    pub fn set_arrangement(&mut self, entries: &[SongEntry]) {
+        self.len = 0;
+        for entry in entries.iter().filter(|e| (e.pattern as usize) < BANK_SIZE) {
+            if self.len == SONG_MAX {
+                break;
+            }
+            self.arrangement[self.len] = *entry;
+            self.len += 1;
+        }
+        if self.len == 0 {
+            self.arrangement[0] = SongEntry {
+                pattern: self.current,
+                repeats: 1,
+            };
+            self.len = 1;
+        }
+        self.entry %= self.len;
+    }
+
+    /// Returns the entries of the arrangement.
+    pub fn get_arrangement(&self) -> &[SongEntry] {
+        &self.arrangement[..self.len]
+    }
+
+    /// Returns the position in the arrangement.
+    pub fn get_entry(&self) -> usize {
+        self.entry
+    }
+
+    /// Switches to song mode, which starts the arrangement at the next bar,
+    /// or back to looping the current pattern.
+    pub fn set_song_mode(&mut self, song_mode: bool) {
+        if song_mode && !self.song_mode {
+            self.entry = 0;
+            self.repeat = 0;
+            self.queued = Some(self.arrangement[0].pattern);
+        }
+        self.song_mode = song_mode;
+    }
+
+    pub fn get_sequencer(&self) -> &StepSequencer {
+        &self.seq
+    }
+}
+
+impl Default for Song {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::seq::step::Step;
+    use crate::seq::Division;
+
+    fn pattern(note: u8, length: u8) -> Pattern {
+        let mut pattern = Pattern::default();
+        pattern.steps[0] = Step::note(note, 100);
+        pattern.length = length;
+        pattern
+    }
+
+    /// Returns the ticks and notes of the note ons.
+    fn run(song: &mut Song, ticks: usize) -> Vec<(usize, u8)> {
+        let mut notes = Vec::new();
+        for t in 0..ticks {
+            song.tick(|event| {
+                if let NoteEvent::On { note, .. } = event {
+                    notes.push((t, note));
+                }
+            });
+        }
+        notes
+    }
+
+    #[test]
+    fn test_song_queue() {
+        let mut song = Song::new();
+        // Loops of 3 sixteenths don't line up with the bar
+        song.set_pattern(0, pattern(36, 3));
+        song.set_pattern(2, pattern(38, 16));
+        assert_eq!(run(&mut song, 40), [(0, 36), (18, 36), (36, 36)]);
+        song.queue(2);
+        song.queue(BANK_SIZE);
+        assert_eq!(song.get_queued(), Some(2));
+        // Switches at the second bar
+        assert_eq!(run(&mut song, 80), [(14, 36), (32, 36), (50, 36), (56, 38)]);
+        assert_eq!(song.get_current_pattern(), 2);
+        assert_eq!(song.get_queued(), None);
+    }
+
+    #[test]
+    fn test_song_arrangement() {
+        let mut song = Song::new();
+        let mut eighths = pattern(40, 2);
+        eighths.division = Division::Eighth;
+        song.set_pattern(0, pattern(36, 2));
+        song.set_pattern(1, eighths);
+        song.set_arrangement(&[
+            SongEntry {
+                pattern: 0,
+                repeats: 2,
+            },
+            SongEntry {
+                pattern: 99,
+                repeats: 1,
+            },
+            SongEntry {
+                pattern: 1,
+                repeats: 1,
+            },
+        ]);
+        assert_eq!(song.get_arrangement().len(), 2);
+        song.set_song_mode(true);
+        song.reset(|_| {});
+        // Two loops of 12 ticks, one loop of 24 ticks, and again
+        assert_eq!(
+            run(&mut song, 72),
+            [(0, 36), (12, 36), (24, 40), (48, 36), (60, 36)]
+        );
+        assert_eq!(song.get_entry(), 1);
+
+        song.set_song_mode(false);
+        song.set_arrangement(&[]);
+        assert_eq!(song.get_arrangement()[0].pattern, 1);
+    }
+}
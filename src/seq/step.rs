@@ -0,0 +1,565 @@
+// Step sequencer with a pattern of notes on a grid of steps.
+
+use crate::preset::random::Rng;
+use crate::preset::Preset;
+use crate::seq::Division;
+use crate::synth::NoteEvent;
+
+/// Maximum number of steps of a [Pattern]
+pub const MAX_STEPS: usize = 32;
+/// Most notes that are held at once while recording
+pub const RECORD_MAX_HELD: usize = 8;
+
+/// Step of a [Pattern]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Step {
+    /// MIDI note number
+    pub note: u8,
+    /// Velocity from 1 to 127
+    pub velocity: u8,
+    /// Steps without gate are rests.
+    pub gate: bool,
+    /// Chance in percent that the step plays
+    pub probability: u8,
+    /// Number of notes in the step, which split it evenly
+    pub ratchets: u8,
+    /// Gate length in percent of the step or of a ratchet, from 1 to 100
+    pub length: u8,
+    /// Holds the note until the next step starts to play. The next note
+    /// starts before this one is released, so mono voices play legato, and
+    /// the same note is held without a new attack.
+    pub tie: bool,
+}
+
+impl Step {
+    /// Step that plays `note` with `velocity`.
+    pub const fn note(note: u8, velocity: u8) -> Self {
+        Self {
+            note,
+            velocity,
+            gate: true,
+            probability: 100,
+            ratchets: 1,
+            length: 50,
+            tie: false,
+        }
+    }
+
+    pub const fn rest() -> Self {
+        Self {
+            note: 60,
+            velocity: 100,
+            gate: false,
+            probability: 100,
+            ratchets: 1,
+            length: 50,
+            tie: false,
+        }
+    }
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Self::rest()
+    }
+}
+
+/// Steps of a [StepSequencer], which are its parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pattern {
+    pub steps: [Step; MAX_STEPS],
+    /// Number of steps that loop, from 1 to [MAX_STEPS]
+    pub length: u8,
+    pub division: Division,
+}
+
+impl Default for Pattern {
+    fn default() -> Self {
+        Self {
+            steps: [Step::rest(); MAX_STEPS],
+            length: 16,
+            division: Division::Sixteenth,
+        }
+    }
+}
+
+/// Sequencer that loops a pattern of steps
+///
+/// The sequencer is driven by the ticks of a clock, e.g. the
+/// [crate::seq::transport::Transport] or a [crate::midi::clock::MidiClock],
+/// and passes the note events of each
+/// tick to a callback, e.g. [crate::synth::voice::VoiceAllocator::handle_event].
+/// The notes of the steps are held for their gate length, and tied notes
+/// until the next step.
+/// Whether a step with a probability plays is decided by a seeded
+/// generator, so generative patterns repeat with the same seed.
+/// In record mode, notes played with [StepSequencer::record] are written
+/// into the pattern at the current position.
+///
+/// ```
+/// use isopod::seq::step::{Step, StepSequencer};
+/// use isopod::synth::NoteEvent;
+///
+/// let mut seq = StepSequencer::new();
+/// seq.set_step(0, Step::note(60, 100));
+/// let mut events = Vec::new();
+/// // A sixteenth is 6 ticks
+/// for _ in 0..6 {
+///     seq.tick(|event| events.push(event));
+/// }
+/// assert_eq!(
+///     events,
+///     [NoteEvent::On { note: 60, velocity: 100 }, NoteEvent::Off { note: 60 }]
+/// );
+/// assert_eq!(seq.get_current_step(), 1);
+/// ```
+pub struct StepSequencer {
+    pattern: Pattern,
+    // Step that plays next or is playing
+    step: usize,
+    // Ticks since the start of the step
+    tick: u32,
+    // The current step won the roll of its probability
+    active: bool,
+    playing: Option<u8>,
+    // The playing note is tied into the current step
+    tied: bool,
+    rng: Rng,
+
+    recording: bool,
+    quantize: Option<Division>,
+    // Notes, positions in ticks and steps of the held notes
+    held: [(u8, u32, usize); RECORD_MAX_HELD],
+    held_len: usize,
+}
+
+impl StepSequencer {
+    pub fn new() -> Self {
+        Self {
+            pattern: Pattern::default(),
+            step: 0,
+            tick: 0,
+            active: false,
+            playing: None,
+            tied: false,
+            rng: Rng::new(1),
+
+            recording: false,
+            quantize: Some(Division::Sixteenth),
+            held: [(0, 0, 0); RECORD_MAX_HELD],
+            held_len: 0,
+        }
+    }
+
+    /// Advances by one clock tick and passes the note events of the tick to
+    /// `emit`.
+    pub fn tick(&mut self, mut emit: impl FnMut(NoteEvent)) {
+        let ticks = self.pattern.division.get_ticks();
+        let step = self.pattern.steps[self.step];
+        if self.tick == 0 {
+            // Certain steps don't advance the generator
+            self.active = step.gate
+                && step.velocity > 0
+                && (step.probability >= 100 || self.rng.range(0, 99) < step.probability as i32);
+            if !(self.active && self.tied) {
+                self.release(&mut emit);
+            }
+            self.tied = false;
+        }
+        let ratchets = (step.ratchets.max(1) as u32).min(ticks);
+        let ratchet = ticks / ratchets;
+        if self.active && self.tick.is_multiple_of(ratchet) && self.tick / ratchet < ratchets {
+            // A tied note is released after the next one started
+            let previous = self.playing.take();
+            if previous == Some(step.note) {
+                self.playing = previous;
+            } else {
+                emit(NoteEvent::On {
+                    note: step.note,
+                    velocity: step.velocity,
+                });
+                self.playing = Some(step.note);
+                if let Some(note) = previous {
+                    emit(NoteEvent::Off { note });
+                }
+            }
+        }
+        self.tick += 1;
+        let last = (self.tick - 1) / ratchet + 1 >= ratchets;
+        let hold = step.tie && self.active && last;
+        let gate = (ratchet * step.length.clamp(1, 100) as u32 / 100).max(1);
+        if !hold && (self.tick - 1) % ratchet + 1 >= gate {
+            self.release(&mut emit);
+        }
+        if self.tick >= ticks {
+            self.tick = 0;
+            self.tied = hold && self.playing.is_some();
+            self.step = (self.step + 1) % self.get_length();
+        }
+    }
+
+    fn release(&mut self, emit: &mut impl FnMut(NoteEvent)) {
+        if let Some(note) = self.playing.take() {
+            emit(NoteEvent::Off { note });
+        }
+    }
+
+    /// Moves to the song position `ticks`, e.g. after a song position
+    /// message of the clock.
+    pub fn set_position(&mut self, ticks: u32) {
+        let ticks_per_step = self.pattern.division.get_ticks();
+        self.step = (ticks / ticks_per_step) as usize % self.get_length();
+        self.tick = ticks % ticks_per_step;
+    }
+
+    /// Releases the playing note and moves back to the first step, e.g.
+    /// when the clock stops.
+    pub fn reset(&mut self, mut emit: impl FnMut(NoteEvent)) {
+        self.release(&mut emit);
+        self.tied = false;
+        self.step = 0;
+        self.tick = 0;
+    }
+
+    /// Records `event` into the pattern in record mode. Note ons write a
+    /// step at the current position, rounded to the grid of the
+    /// quantization, and note offs set its gate length. Notes longer than a
+    /// step are tied over the following steps.
+    pub fn record(&mut self, event: NoteEvent) {
+        let ticks = self.pattern.division.get_ticks();
+        let loop_ticks = self.get_length() as u32 * ticks;
+        let position = self.step as u32 * ticks + self.tick;
+        match event {
+            NoteEvent::On { note, velocity } if velocity > 0 => {
+                if !self.recording || self.held_len == RECORD_MAX_HELD {
+                    return;
+                }
+                let start = match self.quantize {
+                    Some(grid) => {
+                        let grid = grid.get_ticks();
+                        (position + grid / 2) / grid * grid % loop_ticks
+                    }
+                    None => position,
+                };
+                let step = (start / ticks) as usize;
+                self.pattern.steps[step] = Step::note(note, velocity);
+                self.held[self.held_len] = (note, position, step);
+                self.held_len += 1;
+            }
+            NoteEvent::On { note, .. } | NoteEvent::Off { note } => {
+                let Some(i) = self.held[..self.held_len].iter().position(|h| h.0 == note) else {
+                    return;
+                };
+                let (_, started, step) = self.held[i];
+                self.held.copy_within(i + 1..self.held_len, i);
+                self.held_len -= 1;
+                let duration = (position + loop_ticks - started) % loop_ticks;
+                let steps = (duration.max(1).div_ceil(ticks) as usize).min(self.get_length());
+                let remainder = duration - (steps as u32 - 1) * ticks;
+                let first = self.pattern.steps[step];
+                for k in 0..steps {
+                    let s = &mut self.pattern.steps[(step + k) % self.get_length()];
+                    *s = Step {
+                        tie: k + 1 < steps,
+                        length: if k + 1 < steps {
+                            100
+                        } else {
+                            (remainder * 100 / ticks).clamp(1, 100) as u8
+                        },
+                        ..first
+                    };
+                }
+            }
+        }
+    }
+
+    /// Switches record mode on or off. Held notes still get their length
+    /// when they are released.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Sets the grid that recorded notes are rounded to, e.g.
+    /// [Division::Sixteenth] or [Division::Eighth], or `None` to record
+    /// into the step that is playing.
+    pub fn set_record_quantize(&mut self, quantize: Option<Division>) {
+        self.quantize = quantize;
+    }
+
+    /// Returns the step that plays next or is playing.
+    pub fn get_current_step(&self) -> usize {
+        self.step
+    }
+
+    /// Sets step `index`. Indices beyond [MAX_STEPS] are ignored.
+    pub fn set_step(&mut self, index: usize, step: Step) {
+        if let Some(s) = self.pattern.steps.get_mut(index) {
+            *s = step;
+        }
+    }
+
+    /// Sets the gates of the looping steps from `gates`, e.g. a
+    /// [crate::seq::euclid::Euclid] rhythm, and keeps their notes.
+    pub fn set_gates(&mut self, gates: impl Fn(usize) -> bool) {
+        let length = self.get_length();
+        for (i, step) in self.pattern.steps[..length].iter_mut().enumerate() {
+            step.gate = gates(i);
+        }
+    }
+
+    pub fn get_step(&self, index: usize) -> Option<Step> {
+        self.pattern.steps.get(index).copied()
+    }
+
+    /// Sets the number of steps that loop, from 1 to [MAX_STEPS].
+    pub fn set_length(&mut self, length: usize) {
+        self.pattern.length = length.clamp(1, MAX_STEPS) as u8;
+        self.step %= self.get_length();
+    }
+
+    pub fn get_length(&self) -> usize {
+        (self.pattern.length as usize).clamp(1, MAX_STEPS)
+    }
+
+    /// Seeds the generator of the step probabilities.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Sets the note value of the steps. The next step starts on the new
+    /// grid.
+    pub fn set_division(&mut self, division: Division) {
+        self.pattern.division = division;
+        self.tick = self.tick.min(division.get_ticks() - 1);
+    }
+
+    pub fn get_division(&self) -> Division {
+        self.pattern.division
+    }
+}
+
+impl Default for StepSequencer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for StepSequencer {
+    type Params = Pattern;
+
+    fn get_params(&self) -> Pattern {
+        self.pattern
+    }
+
+    fn set_params(&mut self, params: &Pattern) {
+        self.pattern = *params;
+        self.step %= self.get_length();
+        self.set_division(params.division);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(seq: &mut StepSequencer, ticks: usize) -> Vec<(usize, NoteEvent)> {
+        let mut events = Vec::new();
+        for t in 0..ticks {
+            seq.tick(|event| events.push((t, event)));
+        }
+        events
+    }
+
+    #[test]
+    fn test_step_sequencer() {
+        let mut seq = StepSequencer::new();
+        seq.set_length(3);
+        seq.set_step(0, Step::note(60, 100));
+        seq.set_step(2, Step::note(67, 80));
+        seq.set_step(MAX_STEPS, Step::note(0, 1));
+        let on = |note, velocity| NoteEvent::On { note, velocity };
+        let off = |note| NoteEvent::Off { note };
+        assert_eq!(
+            run(&mut seq, 24),
+            [
+                (0, on(60, 100)),
+                (2, off(60)),
+                (12, on(67, 80)),
+                (14, off(67)),
+                (18, on(60, 100)),
+                (20, off(60)),
+            ]
+        );
+
+        // Quarter notes from the middle of the third step
+        seq.set_division(Division::Quarter);
+        seq.set_position(2 * 24 + 12);
+        assert_eq!(seq.get_current_step(), 2);
+        assert_eq!(run(&mut seq, 13), [(12, on(60, 100))]);
+        seq.reset(|event| assert_eq!(event, off(60)));
+        assert_eq!(seq.get_current_step(), 0);
+
+        // Gates from a Euclidean rhythm
+        let euclid = crate::seq::euclid::Euclid::new(2, 3);
+        seq.set_gates(|i| euclid.is_pulse(i));
+        let gates: Vec<bool> = (0..4).map(|i| seq.get_step(i).unwrap().gate).collect();
+        assert_eq!(gates, [true, false, true, false]);
+        assert_eq!(seq.get_step(2).unwrap().note, 67);
+    }
+
+    #[test]
+    fn test_step_probability_ratchets() {
+        let mut seq = StepSequencer::new();
+        seq.set_length(1);
+        seq.set_step(
+            0,
+            Step {
+                ratchets: 3,
+                ..Step::note(60, 100)
+            },
+        );
+        let ons: Vec<usize> = run(&mut seq, 12)
+            .into_iter()
+            .filter(|(_, e)| matches!(e, NoteEvent::On { .. }))
+            .map(|(t, _)| t)
+            .collect();
+        assert_eq!(ons, [0, 2, 4, 6, 8, 10]);
+
+        // More ratchets than ticks retrigger on every tick
+        seq.set_step(
+            0,
+            Step {
+                probability: 50,
+                ratchets: 9,
+                ..Step::note(60, 100)
+            },
+        );
+        seq.set_seed(3);
+        let played = run(&mut seq, 6 * 64);
+        assert!(
+            (24 * 6..=40 * 6).contains(&(played.len() / 2)),
+            "{}",
+            played.len()
+        );
+        seq.set_seed(3);
+        assert_eq!(run(&mut seq, 6 * 64), played);
+
+        seq.set_step(
+            0,
+            Step {
+                probability: 0,
+                ..Step::note(60, 100)
+            },
+        );
+        assert!(run(&mut seq, 60).is_empty());
+    }
+
+    #[test]
+    fn test_step_length_tie() {
+        let mut seq = StepSequencer::new();
+        seq.set_length(4);
+        let step = |note, length, tie| Step {
+            length,
+            tie,
+            ..Step::note(note, 100)
+        };
+        seq.set_step(0, step(60, 100, true));
+        seq.set_step(1, step(60, 20, true));
+        seq.set_step(2, step(64, 34, false));
+        let on = |note| NoteEvent::On {
+            note,
+            velocity: 100,
+        };
+        let off = |note| NoteEvent::Off { note };
+        // The tied note is held over the first two steps without a new
+        // attack, and overlaps the third
+        assert_eq!(
+            run(&mut seq, 24),
+            [(0, on(60)), (12, on(64)), (12, off(60)), (13, off(64))]
+        );
+
+        // A tie into a rest ends with the rest
+        seq.set_step(2, Step::rest());
+        assert_eq!(run(&mut seq, 24), [(0, on(60)), (12, off(60))]);
+        seq.reset(|_| {});
+    }
+
+    #[test]
+    fn test_step_record() {
+        let mut seq = StepSequencer::new();
+        seq.set_length(8);
+        let on = |note| NoteEvent::On { note, velocity: 90 };
+        let off = |note| NoteEvent::Off { note };
+        seq.record(on(40));
+        assert_eq!(seq.get_step(0), Some(Step::rest()));
+
+        // Late by 2 ticks and early by 2 ticks of a sixteenth
+        seq.set_recording(true);
+        run(&mut seq, 2);
+        seq.record(on(60));
+        run(&mut seq, 2);
+        seq.record(off(60));
+        seq.record(on(62));
+        let notes: Vec<u8> = (0..2).map(|i| seq.get_step(i).unwrap().note).collect();
+        assert_eq!(notes, [60, 62]);
+        assert_eq!(seq.get_step(0).unwrap().length, 33);
+        assert_eq!(seq.get_step(1).unwrap().velocity, 90);
+
+        // A held note over two and a half steps
+        run(&mut seq, 15);
+        seq.record(off(62));
+        let steps: Vec<(u8, bool, u8)> = (1..4)
+            .map(|i| seq.get_step(i).map(|s| (s.note, s.tie, s.length)).unwrap())
+            .collect();
+        assert_eq!(steps, [(62, true, 100), (62, true, 100), (62, false, 50)]);
+
+        // Eighths round to the next eighth and wrap around the loop
+        seq.set_record_quantize(Some(Division::Eighth));
+        run(&mut seq, 4 * 6);
+        assert_eq!(seq.get_current_step(), 7);
+        seq.record(on(64));
+        seq.record(off(64));
+        assert!(seq.get_step(0).unwrap().note == 64 && !seq.get_step(7).unwrap().gate);
+
+        // Without quantization the note goes into the playing step
+        seq.set_record_quantize(None);
+        run(&mut seq, 6);
+        seq.record(on(65));
+        assert_eq!(seq.get_step(0).unwrap().note, 65);
+        seq.set_recording(false);
+        seq.record(on(67));
+        seq.record(off(65));
+        assert_eq!(seq.get_step(0).unwrap().note, 65);
+        assert_eq!(seq.get_step(1).unwrap().note, 62);
+    }
+
+    #[test]
+    fn test_step_sequencer_pattern() {
+        let mut seq = StepSequencer::new();
+        let mut pattern = Pattern::default();
+        for (i, step) in pattern.steps.iter_mut().enumerate() {
+            *step = Step::note(48 + i as u8, 100);
+        }
+        pattern.length = 4;
+        pattern.division = Division::ThirtySecond;
+        seq.set_params(&pattern);
+        assert_eq!(seq.get_params(), pattern);
+        // Adjacent steps release before the next note starts
+        let events: Vec<NoteEvent> = run(&mut seq, 3 * 5).into_iter().map(|(_, e)| e).collect();
+        assert_eq!(events.len(), 10);
+        assert_eq!(
+            events[8],
+            NoteEvent::On {
+                note: 48,
+                velocity: 100
+            }
+        );
+        assert!(matches!(events[7], NoteEvent::Off { note: 51 }));
+    }
+}
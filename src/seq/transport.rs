@@ -0,0 +1,358 @@
+// Transport that generates sample accurate clock ticks from a tempo.
+
+use crate::midi::clock::{MidiClock, CLOCK_PPQ};
+use crate::util::units::{mBpm, mHz, Bpm};
+
+/// Ticks of the sixteenths that swing moves
+const TICKS_PER_SIXTEENTH: u32 = CLOCK_PPQ / 4;
+/// Swing in percent that plays straight
+pub const SWING_STRAIGHT: u8 = 50;
+/// Most swing in percent, where the off beat sixteenths are late by a
+/// quarter of a sixteenth more than triplets
+pub const SWING_MAX: u8 = 75;
+
+/// Internal clock with tempo, start, stop and swing
+///
+/// The transport counts ticks of [CLOCK_PPQ] per quarter note and reports
+/// each tick with its offset in the block, so sequencers can start notes on
+/// the exact sample. Swing delays the off beat sixteenths: at 50 % the
+/// sixteenths are straight, at 67 % they are triplets.
+///
+/// Instead of its own tempo, the transport can follow a [MidiClock] (see
+/// [Transport::follow]).
+///
+/// ```
+/// use isopod::seq::transport::Transport;
+/// use isopod::util::units::Bpm;
+///
+/// let mut transport = Transport::new();
+/// transport.set_bpm(Bpm(125));
+/// transport.start();
+/// // 125 BPM at 44.1 kHz are 882 samples per tick
+/// let mut ticks = Vec::new();
+/// transport.advance(2_000, |offset, tick| ticks.push((offset, tick)));
+/// assert_eq!(ticks, [(0, 0), (882, 1), (1_764, 2)]);
+/// ```
+pub struct Transport {
+    tempo: mBpm,
+    swing: u8,
+    running: bool,
+    // Tick that fires next
+    position: u32,
+    // Samples until the next tick in 1/65536 samples
+    until_tick: u64,
+    // Phase within the current quarter note
+    phase: u32,
+    // Last tick that may fire while following a clock
+    limit: Option<u32>,
+
+    msample_rate: mHz,
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Self {
+            tempo: mBpm(120_000),
+            swing: SWING_STRAIGHT,
+            running: false,
+            position: 0,
+            until_tick: 0,
+            phase: 0,
+            limit: None,
+
+            msample_rate: mHz(44_100_000),
+        }
+    }
+
+    /// Starts from the beginning. The first tick fires with the next block.
+    pub fn start(&mut self) {
+        self.set_position(0);
+        self.running = true;
+    }
+
+    /// Continues from the current position.
+    pub fn resume(&mut self) {
+        self.running = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    /// True between start or resume and stop.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Moves to the tick `position`, which fires with the next block.
+    pub fn set_position(&mut self, position: u32) {
+        self.position = position;
+        self.until_tick = 0;
+        self.phase = ((((position % CLOCK_PPQ) as u64) << 32) / CLOCK_PPQ as u64) as u32;
+    }
+
+    /// Returns the tick that fires next.
+    pub fn get_position(&self) -> u32 {
+        self.position
+    }
+
+    /// Returns the phase within the current quarter note, where the full
+    /// u32 range corresponds to one quarter. Swing doesn't apply, so synced
+    /// LFOs run straight.
+    pub fn get_phase(&self) -> u32 {
+        self.phase
+    }
+
+    /// Advances the time by `samples` and passes the offset in the block
+    /// and the position of each tick to `on_tick`. Nothing happens while
+    /// stopped.
+    pub fn advance(&mut self, samples: usize, mut on_tick: impl FnMut(usize, u32)) {
+        if !self.running {
+            return;
+        }
+        let end = (samples as u64) << 16;
+        let mut offset = self.until_tick;
+        let mut quarter = None;
+        while offset < end {
+            if self.is_held() {
+                // Waits for the clock at the start of the next block
+                offset = end;
+                break;
+            }
+            if self.position.is_multiple_of(CLOCK_PPQ) {
+                quarter = Some(offset);
+            }
+            on_tick((offset >> 16) as usize, self.position);
+            offset += self.get_tick_interval(self.position);
+            self.position = self.position.wrapping_add(1);
+        }
+        self.until_tick = offset - end;
+
+        let quarter_len = self.get_base_interval() * CLOCK_PPQ as u64;
+        let delta = |len: u64| ((len << 32) / quarter_len.max(1)) as u32;
+        self.phase = match quarter {
+            Some(start) => delta(end - start),
+            None => self.phase.wrapping_add(delta(end)),
+        };
+    }
+
+    fn is_held(&self) -> bool {
+        self.limit
+            .is_some_and(|limit| (self.position.wrapping_sub(limit) as i32) > 0)
+    }
+
+    /// Follows the tempo, position, start and stop of `clock` instead of the
+    /// own tempo. Call it before every [Transport::advance], after the
+    /// clock messages were handled.
+    ///
+    /// The ticks stay sample accurate at the measured tempo and run at
+    /// most one tick ahead of the clock. If they fall behind or the clock
+    /// jumps, e.g. to a new song position, the transport continues at the
+    /// tick of the clock.
+    pub fn follow(&mut self, clock: &MidiClock) {
+        if let Some(beat) = clock.get_beat() {
+            self.set_mbpm(mBpm::from_beat(beat));
+        }
+        let position = clock.get_position();
+        if !clock.is_running() {
+            self.running = false;
+        } else if !self.running {
+            self.set_position(position);
+            self.running = true;
+        } else if (position.wrapping_sub(self.position) as i32) >= 0
+            || (self.position.wrapping_sub(position) as i32) > 2
+        {
+            self.set_position(position);
+        }
+        self.limit = Some(position.wrapping_add(1));
+    }
+
+    /// Stops following a clock and runs at the own tempo again.
+    pub fn unfollow(&mut self) {
+        self.limit = None;
+    }
+
+    /// Returns the number of samples until the next tick while running,
+    /// e.g. to split blocks at the ticks.
+    pub fn get_samples_to_tick(&self) -> Option<usize> {
+        (self.running && !self.is_held()).then_some(self.until_tick.div_ceil(1 << 16) as usize)
+    }
+
+    /// Interval between straight ticks in 1/65536 samples
+    fn get_base_interval(&self) -> u64 {
+        ((self.msample_rate.0 as u64) << 16) * 5 / (2 * self.tempo.0.max(1) as u64)
+    }
+
+    fn get_tick_interval(&self, position: u32) -> u64 {
+        let base = self.get_base_interval();
+        let swing = self.swing as u64;
+        if position % (2 * TICKS_PER_SIXTEENTH) < TICKS_PER_SIXTEENTH {
+            base * 2 * swing / 100
+        } else {
+            base * 2 * (100 - swing) / 100
+        }
+    }
+
+    /// Sets the tempo, which applies from the next tick.
+    pub fn set_bpm(&mut self, bpm: Bpm) {
+        self.set_mbpm(Bpm(bpm.0.max(1)).into());
+    }
+
+    /// Returns the tempo rounded down to whole BPM.
+    pub fn get_bpm(&self) -> Bpm {
+        Bpm(self.tempo.0 / 1_000)
+    }
+
+    /// Sets the tempo in mBPM, which applies from the next tick.
+    pub fn set_mbpm(&mut self, tempo: mBpm) {
+        self.tempo = mBpm(tempo.0.max(1));
+    }
+
+    pub fn get_mbpm(&self) -> mBpm {
+        self.tempo
+    }
+
+    /// Sets the swing in percent from [SWING_STRAIGHT] to [SWING_MAX].
+    pub fn set_swing(&mut self, swing: u8) {
+        self.swing = swing.clamp(SWING_STRAIGHT, SWING_MAX);
+    }
+
+    pub fn get_swing(&self) -> u8 {
+        self.swing
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+    }
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ticks(transport: &mut Transport, blocks: usize, len: usize) -> Vec<(usize, u32)> {
+        let mut ticks = Vec::new();
+        for block in 0..blocks {
+            transport.advance(len, |offset, tick| ticks.push((block * len + offset, tick)));
+        }
+        ticks
+    }
+
+    #[test]
+    fn test_transport() {
+        let mut transport = Transport::new();
+        transport.set_msample_rate(mHz(48_000_000));
+        assert!(ticks(&mut transport, 4, 64).is_empty());
+
+        // 120 BPM at 48 kHz are 1000 samples per tick, in any block size
+        transport.start();
+        let straight = ticks(&mut transport, 30, 100);
+        assert_eq!(straight, [(0, 0), (1_000, 1), (2_000, 2)]);
+        assert_eq!(transport.get_samples_to_tick(), Some(0));
+        transport.advance(1, |_, _| {});
+        assert_eq!(transport.get_samples_to_tick(), Some(999));
+
+        // Phase follows the quarter note
+        transport.start();
+        transport.advance(6_000, |_, _| {});
+        assert_eq!(transport.get_phase(), 1 << 30);
+        transport.advance(18_000, |_, _| {});
+        assert_eq!(transport.get_phase(), 0);
+        assert_eq!(transport.get_position(), 24);
+
+        // Stop and resume keeps the position
+        transport.stop();
+        assert!(!transport.is_running());
+        assert_eq!(transport.get_samples_to_tick(), None);
+        transport.resume();
+        assert_eq!(ticks(&mut transport, 1, 1)[0], (0, 24));
+
+        transport.set_bpm(Bpm(0));
+        assert_eq!(transport.get_bpm(), Bpm(1));
+
+        // 93.75 BPM at 48 kHz are 1280 samples per tick
+        transport.set_mbpm(mBpm(93_750));
+        assert_eq!(transport.get_bpm(), Bpm(93));
+        transport.start();
+        assert_eq!(ticks(&mut transport, 1, 2_000), [(0, 0), (1_280, 1)]);
+    }
+
+    #[test]
+    fn test_transport_follow() {
+        use crate::midi::clock::ClockMessage;
+
+        let mut clock = MidiClock::new();
+        clock.set_msample_rate(mHz(48_000_000));
+        let mut transport = Transport::new();
+        transport.set_msample_rate(mHz(48_000_000));
+        // Blocks of 50 samples from `start`, with a clock tick every
+        // `interval` samples or none for 0
+        let run = |clock: &mut MidiClock, transport: &mut Transport, start, len, interval| {
+            let mut ticks = Vec::new();
+            for block in (start..start + len).step_by(50) {
+                if interval > 0 && block % interval == 0 {
+                    clock.handle(ClockMessage::Tick);
+                }
+                clock.advance(50);
+                transport.follow(clock);
+                transport.advance(50, |offset, tick| ticks.push((block + offset, tick)));
+            }
+            ticks
+        };
+        // Measures the tempo, but stays stopped with the clock
+        assert!(run(&mut clock, &mut transport, 0, 10_000, 1_000).is_empty());
+        assert_eq!(transport.get_mbpm(), mBpm(120_000));
+
+        clock.handle(ClockMessage::Start);
+        let followed = run(&mut clock, &mut transport, 10_000, 100_000, 1_000);
+        let expected: Vec<_> = (0..100).map(|n| (10_000 + n * 1_000, n as u32)).collect();
+        assert_eq!(followed, expected);
+
+        // Runs at most one tick ahead of a late clock
+        let followed = run(&mut clock, &mut transport, 110_000, 5_000, 0);
+        assert_eq!(followed, [(110_000, 100)]);
+        assert_eq!(transport.get_samples_to_tick(), None);
+
+        // Continues at the tick of a clock that jumps or runs ahead
+        clock.handle(ClockMessage::SongPosition(16));
+        let followed = run(&mut clock, &mut transport, 115_000, 1_000, 1_000);
+        assert_eq!(followed, [(115_000, 96)]);
+        let followed = run(&mut clock, &mut transport, 116_000, 1_000, 250);
+        assert_eq!(
+            followed,
+            [(116_000, 97), (116_250, 98), (116_500, 99), (116_750, 100)]
+        );
+
+        clock.handle(ClockMessage::Stop);
+        run(&mut clock, &mut transport, 117_000, 50, 0);
+        assert!(!transport.is_running());
+        // Runs freely at the own tempo again
+        transport.unfollow();
+        transport.set_bpm(Bpm(120));
+        transport.start();
+        assert_eq!(ticks(&mut transport, 1, 4_000).len(), 4);
+    }
+
+    #[test]
+    fn test_transport_swing() {
+        let mut transport = Transport::new();
+        transport.set_msample_rate(mHz(48_000_000));
+        transport.set_swing(80);
+        assert_eq!(transport.get_swing(), SWING_MAX);
+        transport.set_swing(60);
+        transport.start();
+        let swung = ticks(&mut transport, 1, 24_001);
+        // Off beat sixteenths are late by 10 % of an eighth
+        assert_eq!(swung[6], (7_200, 6));
+        assert_eq!(swung[12], (12_000, 12));
+        assert_eq!(swung[18], (19_200, 18));
+        assert_eq!(swung[24], (24_000, 24));
+    }
+}
@@ -0,0 +1,320 @@
+// Electric piano voice from two FM operators.
+
+use crate::env::adsr::Adsr;
+use crate::osc::fm::{FmOperator, INDEX_SHIFT};
+use crate::preset::Preset;
+use crate::synth::velocity::{Velocity, VelocityCurve, VelocityMap, VelocityParams};
+use crate::synth::voice::{note_mfreq, Voice};
+use crate::synth::Synth;
+use crate::util::units::{mHz, ms, ratio, Frequency, Hz};
+
+/// Parameters of an [FmPiano]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FmPianoParams {
+    pub mod_ratio: u32,
+    pub index: u32,
+    pub decay: ms,
+    pub index_decay: ms,
+    pub release: ms,
+    pub velocity: VelocityParams,
+}
+
+/// Electric piano voice
+///
+/// A modulator at the note frequency drives the phase of a sine carrier.
+/// Both follow tine-like envelopes without sustain: the carrier decays
+/// slowly, while the modulation index decays quickly, so the bright bark of
+/// the attack settles into an almost pure tone. Harder notes get a higher
+/// index and sound brighter on top of being louder. The decay of both
+/// envelopes shortens by a factor of two every two octaves above middle C,
+/// as on the real instrument.
+pub struct FmPiano {
+    carrier: FmOperator,
+    modulator: FmOperator,
+    amp_env: Adsr,
+    index_env: Adsr,
+    velocity_map: VelocityMap,
+
+    // Frequency ratio of the modulator normalized to 1 << 16
+    mod_ratio: u32,
+    index: u32,
+    // Peak index of the current note
+    note_index: u32,
+    decay: ms,
+    index_decay: ms,
+
+    note: u8,
+    velocity: Velocity,
+    // Velocity gain of the current note
+    gain: i16,
+
+    msample_rate: mHz,
+}
+
+impl FmPiano {
+    pub fn new() -> Self {
+        let mut s = Self {
+            carrier: FmOperator::new(),
+            modulator: FmOperator::new(),
+            amp_env: Adsr::new(),
+            index_env: Adsr::new(),
+            velocity_map: VelocityMap::new(),
+
+            mod_ratio: 1 << 16,
+            index: 3 << INDEX_SHIFT,
+            note_index: 0,
+            decay: ms(3_000),
+            index_decay: ms(400),
+
+            note: 69,
+            velocity: Velocity(0),
+            gain: 0,
+
+            msample_rate: mHz(44_100_000),
+        };
+        s.amp_env.set_attack_ms(ms(1));
+        s.amp_env.set_sustain(0);
+        s.amp_env.set_release_ms(ms(150));
+        s.index_env.set_attack_ms(ms(1));
+        s.index_env.set_sustain(0);
+        s.index_env.set_release_ms(ms(150));
+        s.set_msample_rate(s.msample_rate);
+        s
+    }
+
+    /// Sets the pitch of both operators.
+    fn update_note(&mut self, mfreq: mHz) {
+        self.carrier.set_mfreq(mfreq);
+        self.modulator
+            .set_mfreq(mHz(((mfreq.0 as u64 * self.mod_ratio as u64) >> 16) as u32));
+    }
+
+    /// Scales the decays to the current note.
+    fn update_decays(&mut self) {
+        // Halves every 2400 cents above middle C
+        let cents = (self.note as i32 - 60).max(0) * 100;
+        let scale =
+            |time: ms| ms((((time.0 as u64) << 16) / ratio(cents / 2).max(1) as u64) as u32);
+        let (decay, index_decay) = (scale(self.decay), scale(self.index_decay));
+        self.amp_env.set_decay_ms(decay);
+        self.index_env.set_decay_ms(index_decay);
+    }
+
+    /// Returns the next sample.
+    #[inline]
+    fn sample(&mut self) -> i16 {
+        let env = self.index_env._next() as u64;
+        self.carrier
+            .set_index(((self.note_index as u64 * env) >> 15) as u32);
+        let y = self.carrier.process(self.modulator._next());
+        let amp = (self.amp_env._next() as i32 * self.gain as i32) >> 15;
+        ((y as i32 * amp) >> 15) as i16
+    }
+
+    /// Sets the modulator frequency relative to the note, normalized to
+    /// 1 << 16. Integer ratios are harmonic, e.g. `14 << 16` adds the
+    /// metallic tine of some models.
+    pub fn set_mod_ratio(&mut self, ratio: u32) {
+        self.mod_ratio = ratio;
+    }
+
+    /// Sets the modulation index at full velocity with [INDEX_SHIFT]
+    /// fractional bits.
+    pub fn set_index(&mut self, index: u32) {
+        self.index = index;
+    }
+
+    /// Sets the decay time of the tone at middle C.
+    pub fn set_decay_ms(&mut self, decay: ms) {
+        self.decay = decay;
+        self.update_decays();
+    }
+
+    /// Sets the decay time of the modulation index at middle C.
+    pub fn set_index_decay_ms(&mut self, decay: ms) {
+        self.index_decay = decay;
+        self.update_decays();
+    }
+
+    /// Sets the release time after note off.
+    pub fn set_release_ms(&mut self, release: ms) {
+        self.amp_env.set_release_ms(release);
+        self.index_env.set_release_ms(release);
+    }
+
+    /// Returns the velocity routing of the amplitude.
+    pub fn get_velocity_map_mut(&mut self) -> &mut VelocityMap {
+        &mut self.velocity_map
+    }
+
+    /// Returns the note of the last note on.
+    pub fn get_note(&self) -> u8 {
+        self.note
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        self.carrier.set_msample_rate(msample_rate);
+        self.modulator.set_msample_rate(msample_rate);
+        self.amp_env.set_msample_rate(msample_rate);
+        self.index_env.set_msample_rate(msample_rate);
+        self.update_decays();
+    }
+}
+
+impl Default for FmPiano {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for FmPiano {
+    type Params = FmPianoParams;
+
+    fn get_params(&self) -> FmPianoParams {
+        FmPianoParams {
+            mod_ratio: self.mod_ratio,
+            index: self.index,
+            decay: self.decay,
+            index_decay: self.index_decay,
+            release: self.amp_env.get_params().release,
+            velocity: self.velocity_map.get_params(),
+        }
+    }
+
+    fn set_params(&mut self, params: &FmPianoParams) {
+        self.mod_ratio = params.mod_ratio;
+        self.index = params.index;
+        (self.decay, self.index_decay) = (params.decay, params.index_decay);
+        self.update_decays();
+        self.set_release_ms(params.release);
+        self.velocity_map.set_params(&params.velocity);
+    }
+}
+
+impl Voice for FmPiano {
+    fn note_on(&mut self, note: u8, velocity: u8) {
+        self.note = note;
+        self.velocity = Velocity::new(velocity);
+        self.gain = self.velocity_map.get_gain(self.velocity);
+        let response = VelocityCurve::Linear.apply(self.velocity) as u64;
+        self.note_index = ((self.index as u64 * response) >> 15) as u32;
+        self.update_note(note_mfreq(note));
+        self.update_decays();
+        self.amp_env.gate_on();
+        self.index_env.gate_on();
+    }
+
+    fn set_note(&mut self, note: u8) {
+        self.note = note;
+        self.update_note(note_mfreq(note));
+    }
+
+    fn set_mfreq(&mut self, mfreq: mHz) {
+        self.update_note(mfreq);
+    }
+
+    fn set_phase(&mut self, phase: u32) {
+        self.carrier.set_phase(phase);
+        self.modulator.set_phase(phase);
+    }
+
+    fn note_off(&mut self) {
+        self.amp_env.gate_off();
+        self.index_env.gate_off();
+    }
+
+    fn is_active(&self) -> bool {
+        self.amp_env.is_active()
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self.sample();
+        }
+    }
+}
+
+impl Synth for FmPiano {
+    fn _next(&mut self) -> Option<i16> {
+        Some(self.sample())
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        Voice::render(self, out);
+    }
+
+    fn note_on(&mut self, note: u8, velocity: u8) {
+        Voice::note_on(self, note, velocity);
+    }
+
+    /// Releases the voice if `note` is the note it plays.
+    fn note_off(&mut self, note: u8) {
+        if note == self.note {
+            Voice::note_off(self);
+        }
+    }
+
+    fn get_sample_rate(&self) -> Hz {
+        self.msample_rate.to_Hz()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: Hz) {
+        self.set_msample_rate(sample_rate.to_mHz());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn peak(out: &[i16]) -> i16 {
+        out.iter().map(|y| y.saturating_abs()).max().unwrap_or(0)
+    }
+
+    /// Sum of squared sample differences, a measure of brightness
+    fn roughness(out: &[i16]) -> i64 {
+        out.windows(2)
+            .map(|w| (w[1] as i64 - w[0] as i64).pow(2))
+            .sum()
+    }
+
+    #[test]
+    fn test_fm_piano_decay() {
+        let mut piano = FmPiano::new();
+        Voice::note_on(&mut piano, 60, 127);
+        let mut out = vec![0_i16; 44_100];
+        Voice::render(&mut piano, &mut out);
+        // Decays without sustain, the bark fades faster than the tone
+        let (attack, late) = (&out[..4_410], &out[39_690..]);
+        assert!(
+            peak(late) < peak(attack) / 2,
+            "{} {}",
+            peak(attack),
+            peak(late)
+        );
+        let brightness = |out: &[i16]| roughness(out) / (peak(out) as i64).pow(2).max(1);
+        assert!(brightness(attack) > 2 * brightness(late));
+
+        Synth::note_off(&mut piano, 60);
+        Voice::render(&mut piano, &mut out);
+        assert!(!Voice::is_active(&piano));
+    }
+
+    #[test]
+    fn test_fm_piano_velocity() {
+        let render = |velocity| {
+            let mut piano = FmPiano::new();
+            Voice::note_on(&mut piano, 60, velocity);
+            let mut out = [0_i16; 2_205];
+            Voice::render(&mut piano, &mut out);
+            (peak(&out) as i64, roughness(&out))
+        };
+        let ((soft_peak, soft), (loud_peak, loud)) = (render(40), render(127));
+        assert!(soft_peak < loud_peak / 4);
+        // Brighter relative to the level
+        assert!(loud / loud_peak.pow(2) > soft / soft_peak.pow(2));
+    }
+}
@@ -0,0 +1,141 @@
+// Portamento control block gliding towards a target frequency.
+
+use crate::fx::dynamics::{coefficient, COEF_NORM};
+use crate::util::units::{mHz, ms};
+
+/// Fractional bits of the glide state
+const FREQ_SHIFT: u32 = 16;
+
+/// Exponential glide between frequencies
+///
+/// Every sample, the frequency moves by a constant fraction of the
+/// remaining distance towards the target. The glide time is the time
+/// constant, i.e. about 63 % of the distance are covered within it. A glide
+/// time of 0 jumps to the target immediately.
+pub struct Glide {
+    // Frequency in mHz with FREQ_SHIFT fractional bits
+    current: i64,
+    target: i64,
+    coef: u32,
+
+    time: ms,
+    msample_rate: mHz,
+}
+
+impl Glide {
+    pub fn new() -> Self {
+        let mut s = Self {
+            current: 0,
+            target: 0,
+            coef: COEF_NORM,
+
+            time: ms(0),
+            msample_rate: mHz(44_100_000),
+        };
+        s.update_coef();
+        s
+    }
+
+    fn update_coef(&mut self) {
+        self.coef = coefficient(self.time, self.msample_rate);
+    }
+
+    /// Advances by one sample and returns the current frequency.
+    #[inline]
+    pub fn _next(&mut self) -> mHz {
+        let diff = self.target - self.current;
+        let step = ((diff >> 8) * self.coef as i64) >> 16;
+        // Snaps to the target once less than 1 mHz away or stalling
+        if diff.abs() < 1 << FREQ_SHIFT || step == 0 || self.coef >= COEF_NORM {
+            self.current = self.target;
+        } else {
+            self.current += step;
+        }
+        self.get_mfreq()
+    }
+
+    /// Advances by `samples` samples and returns the current frequency.
+    pub fn advance(&mut self, samples: usize) -> mHz {
+        for _ in 0..samples {
+            self._next();
+        }
+        self.get_mfreq()
+    }
+
+    /// Sets the frequency to glide to.
+    pub fn set_target_mfreq(&mut self, mfreq: mHz) {
+        self.target = (mfreq.0 as i64) << FREQ_SHIFT;
+    }
+
+    /// Jumps to `mfreq` without gliding.
+    pub fn set_mfreq(&mut self, mfreq: mHz) {
+        self.set_target_mfreq(mfreq);
+        self.current = self.target;
+    }
+
+    /// Returns the current frequency.
+    pub fn get_mfreq(&self) -> mHz {
+        mHz((self.current >> FREQ_SHIFT) as u32)
+    }
+
+    /// True while the target isn't reached.
+    pub fn is_gliding(&self) -> bool {
+        self.current != self.target
+    }
+
+    /// Sets the glide time.
+    pub fn set_glide_ms(&mut self, time: ms) {
+        self.time = time;
+        self.update_coef();
+    }
+
+    /// Returns the glide time.
+    pub fn get_glide_ms(&self) -> ms {
+        self.time
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        self.update_coef();
+    }
+}
+
+impl Default for Glide {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::units::{Frequency, Hz};
+
+    #[test]
+    fn test_glide() {
+        let mut glide = Glide::new();
+        glide.set_mfreq(Hz(0).to_mHz());
+        glide.set_glide_ms(ms(10));
+        glide.set_target_mfreq(Hz(1_000).to_mHz());
+        // 63 % after one time constant
+        let mfreq = glide.advance(441).0;
+        assert!(mfreq.abs_diff(632_000) < 5_000, "{}", mfreq);
+        // Reaches the target exactly
+        glide.advance(44_100);
+        assert_eq!(glide.get_mfreq(), Hz(1_000).to_mHz());
+        assert!(!glide.is_gliding());
+
+        // Gliding down
+        glide.set_target_mfreq(Hz(500).to_mHz());
+        let mfreq = glide.advance(441).0;
+        assert!(mfreq.abs_diff(684_000) < 5_000, "{}", mfreq);
+    }
+
+    #[test]
+    fn test_glide_off() {
+        let mut glide = Glide::new();
+        glide.set_target_mfreq(Hz(440).to_mHz());
+        assert_eq!(glide._next(), Hz(440).to_mHz());
+    }
+}
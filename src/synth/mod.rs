@@ -1,9 +1,330 @@
-use crate::util::units::Hz;
+pub mod fmpiano;
+pub mod glide;
+pub mod mono;
+pub mod quantizer;
+pub mod subtractive;
+pub mod unison;
+pub mod velocity;
+pub mod voice;
 
+use crate::util::units::{Frame, Hz};
+#[cfg(feature = "rodio")]
+use core::time::Duration;
+#[cfg(feature = "rodio")]
+use rodio::source::Source;
+
+/// Note input of a synth
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteEvent {
+    /// Starts `note` (MIDI note number) with `velocity` (0 to 127).
+    On { note: u8, velocity: u8 },
+    /// Releases `note`.
+    Off { note: u8 },
+}
+
+/// Mono synth
+///
+/// Construction is left to the implementation, so synths can take
+/// wavetables, buffers or a sample rate in their constructors. Without a
+/// constructor the trait is object safe, i.e. synths can be used as
+/// `Box<dyn Synth>`.
 pub trait Synth {
-    fn new() -> Self;
     fn _next(&mut self) -> Option<i16>;
 
+    /// Fills `out` with the next samples. Samples after the synth stopped
+    /// are 0. Implementations should override this with a block path where
+    /// possible, since it avoids a call per sample.
+    fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self._next().unwrap_or(0);
+        }
+    }
+
+    /// Starts `note` (MIDI note number) with `velocity` (0 to 127).
+    /// Synths without note input ignore it.
+    fn note_on(&mut self, _note: u8, _velocity: u8) {}
+
+    /// Releases `note`. Synths without note input ignore it.
+    fn note_off(&mut self, _note: u8) {}
+
+    /// Dispatches `event` to [Self::note_on] or [Self::note_off].
+    fn handle_event(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On { note, velocity } => self.note_on(note, velocity),
+            NoteEvent::Off { note } => self.note_off(note),
+        }
+    }
+
+    fn get_sample_rate(&self) -> Hz;
+    fn set_sample_rate(&mut self, sample_rate: Hz);
+}
+
+/// Synth emitting stereo frames
+pub trait StereoSynth {
+    fn _next_frame(&mut self) -> Option<Frame>;
+
+    /// Fills `out` with the next frames. Frames after the synth stopped
+    /// are silent.
+    fn render_frames(&mut self, out: &mut [Frame]) {
+        for y in out.iter_mut() {
+            *y = self._next_frame().unwrap_or(Frame::mono(0));
+        }
+    }
+
+    /// Fills `out` with the next frames as interleaved left and right
+    /// samples. A trailing odd sample is set to 0.
+    fn render_interleaved(&mut self, out: &mut [i16]) {
+        let mut chunks = out.chunks_exact_mut(2);
+        for pair in &mut chunks {
+            let frame = self._next_frame().unwrap_or(Frame::mono(0));
+            pair.copy_from_slice(&<[i16; 2]>::from(frame));
+        }
+        for y in chunks.into_remainder() {
+            *y = 0;
+        }
+    }
+
+    /// Starts `note` (MIDI note number) with `velocity` (0 to 127).
+    /// Synths without note input ignore it.
+    fn note_on(&mut self, _note: u8, _velocity: u8) {}
+
+    /// Releases `note`. Synths without note input ignore it.
+    fn note_off(&mut self, _note: u8) {}
+
+    /// Dispatches `event` to [Self::note_on] or [Self::note_off].
+    fn handle_event(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On { note, velocity } => self.note_on(note, velocity),
+            NoteEvent::Off { note } => self.note_off(note),
+        }
+    }
+
     fn get_sample_rate(&self) -> Hz;
     fn set_sample_rate(&mut self, sample_rate: Hz);
 }
+
+/// Plays a [Synth] as a mono rodio source with feature `rodio`, or as an
+/// iterator
+pub struct MonoSource<S: Synth> {
+    synth: S,
+}
+
+impl<S: Synth> MonoSource<S> {
+    pub fn new(synth: S) -> Self {
+        Self { synth }
+    }
+
+    /// Returns the synth for changing its parameters.
+    pub fn get_synth_mut(&mut self) -> &mut S {
+        &mut self.synth
+    }
+}
+
+impl<S: Synth> Iterator for MonoSource<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.synth._next()
+    }
+}
+
+#[cfg(feature = "rodio")]
+impl<S: Synth> Source for MonoSource<S> {
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.synth.get_sample_rate().0
+    }
+
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Plays a [StereoSynth] as an interleaved two channel rodio source with
+/// feature `rodio`, or as an iterator
+pub struct StereoSource<S: StereoSynth> {
+    synth: S,
+    // Right sample of the current frame, if the left one was emitted
+    right: Option<i16>,
+}
+
+impl<S: StereoSynth> StereoSource<S> {
+    pub fn new(synth: S) -> Self {
+        Self { synth, right: None }
+    }
+
+    /// Returns the synth for changing its parameters.
+    pub fn get_synth_mut(&mut self) -> &mut S {
+        &mut self.synth
+    }
+}
+
+impl<S: StereoSynth> Iterator for StereoSource<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.right.take() {
+            Some(right) => Some(right),
+            None => {
+                let frame = self.synth._next_frame()?;
+                self.right = Some(frame.right.0);
+                Some(frame.left.0)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rodio")]
+impl<S: StereoSynth> Source for StereoSource<S> {
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.synth.get_sample_rate().0
+    }
+
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Emits an increasing ramp on the left and its negation on the right.
+    struct Ramp {
+        n: i16,
+        len: i16,
+    }
+
+    impl StereoSynth for Ramp {
+        fn _next_frame(&mut self) -> Option<Frame> {
+            if self.n == self.len {
+                return None;
+            }
+            self.n += 1;
+            Some(Frame::new(self.n, -self.n))
+        }
+
+        fn get_sample_rate(&self) -> Hz {
+            Hz(48_000)
+        }
+
+        fn set_sample_rate(&mut self, _sample_rate: Hz) {}
+    }
+
+    /// Plays back a borrowed buffer, so it can't have an argument-free
+    /// constructor.
+    struct Player<'a> {
+        buffer: &'a [i16],
+        pos: usize,
+    }
+
+    impl<'a> Player<'a> {
+        fn new(buffer: &'a [i16]) -> Self {
+            Self { buffer, pos: 0 }
+        }
+    }
+
+    impl Synth for Player<'_> {
+        fn _next(&mut self) -> Option<i16> {
+            let y = self.buffer.get(self.pos).copied();
+            self.pos += 1;
+            y
+        }
+
+        fn get_sample_rate(&self) -> Hz {
+            Hz(44_100)
+        }
+
+        fn set_sample_rate(&mut self, _sample_rate: Hz) {}
+    }
+
+    /// Emits the velocity of the held note.
+    #[derive(Default)]
+    struct Keys {
+        note: Option<(u8, u8)>,
+    }
+
+    impl Synth for Keys {
+        fn _next(&mut self) -> Option<i16> {
+            Some(self.note.map_or(0, |(_, velocity)| velocity as i16))
+        }
+
+        fn note_on(&mut self, note: u8, velocity: u8) {
+            self.note = Some((note, velocity));
+        }
+
+        fn note_off(&mut self, note: u8) {
+            if self.note.map(|(n, _)| n) == Some(note) {
+                self.note = None;
+            }
+        }
+
+        fn get_sample_rate(&self) -> Hz {
+            Hz(44_100)
+        }
+
+        fn set_sample_rate(&mut self, _sample_rate: Hz) {}
+    }
+
+    #[test]
+    fn test_synth_note_events() {
+        let mut keys = Keys::default();
+        keys.handle_event(NoteEvent::On {
+            note: 60,
+            velocity: 100,
+        });
+        assert_eq!(keys._next(), Some(100));
+        keys.handle_event(NoteEvent::Off { note: 62 });
+        assert_eq!(keys._next(), Some(100));
+        keys.handle_event(NoteEvent::Off { note: 60 });
+        assert_eq!(keys._next(), Some(0));
+
+        // Synths without note input ignore events
+        let buffer = [1];
+        let mut player = Player::new(&buffer);
+        player.note_on(60, 100);
+        assert_eq!(player._next(), Some(1));
+    }
+
+    #[test]
+    fn test_synth_trait_object() {
+        let buffer = [3, 2, 1];
+        let mut synth: Box<dyn Synth> = Box::new(Player::new(&buffer));
+        let mut out = [9; 4];
+        synth.render(&mut out);
+        assert_eq!(out, [3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_stereo_render_interleaved() {
+        let mut ramp = Ramp { n: 0, len: 2 };
+        let mut out = [9; 7];
+        ramp.render_interleaved(&mut out);
+        assert_eq!(out, [1, -1, 2, -2, 0, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(feature = "rodio")]
+    fn test_stereo_source() {
+        let source = StereoSource::new(Ramp { n: 0, len: 3 });
+        assert_eq!(source.channels(), 2);
+        assert_eq!(source.sample_rate(), 48_000);
+        let out: Vec<i16> = source.collect();
+        assert_eq!(out, [1, -1, 2, -2, 3, -3]);
+    }
+}
@@ -0,0 +1,366 @@
+// Monophonic note handling with note priority and legato.
+
+use crate::synth::glide::Glide;
+use crate::synth::voice::Voice;
+use crate::synth::NoteEvent;
+use crate::util::tuning::Tuning;
+use crate::util::units::{mHz, ms};
+
+/// Number of held notes a [MonoHandler] remembers
+const STACK_LEN: usize = 16;
+/// Samples rendered between pitch updates while gliding
+const GLIDE_BLOCK: usize = 16;
+
+/// Note that sounds while several notes are held
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotePriority {
+    /// The most recently pressed note.
+    Last,
+    /// The lowest held note.
+    Low,
+    /// The highest held note.
+    High,
+}
+
+/// Reaction of a [MonoHandler] to note changes while notes are held
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MonoMode {
+    /// Changes the pitch without retriggering the voice.
+    Legato,
+    /// Retriggers the voice on every note change.
+    Retrigger,
+}
+
+/// Plays a single voice from overlapping notes
+///
+/// Held notes are kept on a stack, so releasing a note falls back to the
+/// remaining notes according to the [NotePriority]. The voice is released
+/// once all notes are released. With a glide time, note changes while
+/// notes are held glide to the new pitch (see [Glide]). With a [Tuning],
+/// the voice is retuned after every note change and unmapped notes are
+/// ignored.
+pub struct MonoHandler<V: Voice> {
+    voice: V,
+    priority: NotePriority,
+    mode: MonoMode,
+    glide: Glide,
+    tuning: Tuning,
+    // Whether the voice is retuned after note changes
+    retune: bool,
+
+    // Held notes in the order they were pressed
+    stack: [u8; STACK_LEN],
+    len: usize,
+    // Sounding note and its velocity
+    note: Option<u8>,
+    velocity: u8,
+}
+
+impl<V: Voice> MonoHandler<V> {
+    pub fn new(voice: V) -> Self {
+        Self {
+            voice,
+            priority: NotePriority::Last,
+            mode: MonoMode::Legato,
+            glide: Glide::new(),
+            tuning: Tuning::equal(),
+            retune: false,
+
+            stack: [0; STACK_LEN],
+            len: 0,
+            note: None,
+            velocity: 0,
+        }
+    }
+
+    fn remove(&mut self, note: u8) {
+        if let Some(i) = self.stack[..self.len].iter().position(|n| *n == note) {
+            self.stack.copy_within(i + 1..self.len, i);
+            self.len -= 1;
+        }
+    }
+
+    /// Returns the held note selected by the priority.
+    fn select(&self) -> Option<u8> {
+        let held = &self.stack[..self.len];
+        match self.priority {
+            NotePriority::Last => held.last().copied(),
+            NotePriority::Low => held.iter().min().copied(),
+            NotePriority::High => held.iter().max().copied(),
+        }
+    }
+
+    fn mfreq(&self, note: u8) -> mHz {
+        self.tuning.get_mfreq(note).unwrap_or(mHz(0))
+    }
+
+    /// Moves the voice to the selected note.
+    fn update(&mut self) {
+        let target = self.select();
+        let glide = self.glide.get_glide_ms().0 > 0;
+        match (self.note, target) {
+            (Some(_), None) => self.voice.note_off(),
+            (None, Some(note)) => {
+                self.glide.set_mfreq(self.mfreq(note));
+                self.voice.note_on(note, self.velocity);
+                if self.retune {
+                    self.voice.set_mfreq(self.mfreq(note));
+                }
+            }
+            (Some(current), Some(note)) if current != note => {
+                self.glide.set_target_mfreq(self.mfreq(note));
+                match (self.mode, glide) {
+                    (MonoMode::Legato, true) => {}
+                    (MonoMode::Legato, false) => self.voice.set_note(note),
+                    (MonoMode::Retrigger, _) => self.voice.note_on(note, self.velocity),
+                }
+                if glide {
+                    // Start from the previous pitch
+                    self.voice.set_mfreq(self.glide.get_mfreq());
+                } else {
+                    self.glide.set_mfreq(self.mfreq(note));
+                    if self.retune {
+                        self.voice.set_mfreq(self.mfreq(note));
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.note = target;
+    }
+
+    /// Adds `note` to the held notes.
+    pub fn note_on(&mut self, note: u8, velocity: u8) {
+        if self.tuning.get_mfreq(note).is_none() {
+            return;
+        }
+        self.remove(note);
+        if self.len == STACK_LEN {
+            // Forget the oldest note
+            self.stack.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.stack[self.len] = note;
+        self.len += 1;
+        self.velocity = velocity;
+        self.update();
+    }
+
+    /// Removes `note` from the held notes.
+    pub fn note_off(&mut self, note: u8) {
+        let held = self.len;
+        self.remove(note);
+        if self.len != held {
+            self.update();
+        }
+    }
+
+    /// Dispatches `event` to [Self::note_on] or [Self::note_off].
+    pub fn handle_event(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On { note, velocity } => self.note_on(note, velocity),
+            NoteEvent::Off { note } => self.note_off(note),
+        }
+    }
+
+    /// Releases all notes.
+    pub fn all_notes_off(&mut self) {
+        self.len = 0;
+        if self.note.is_some() {
+            self.update();
+        }
+    }
+
+    /// Fills `out` with the next samples of the voice.
+    pub fn render(&mut self, out: &mut [i16]) {
+        if !self.voice.is_active() {
+            out.fill(0);
+        } else if self.glide.is_gliding() {
+            for chunk in out.chunks_mut(GLIDE_BLOCK) {
+                let mfreq = self.glide.advance(chunk.len());
+                self.voice.set_mfreq(mfreq);
+                self.voice.render(chunk);
+            }
+        } else {
+            self.voice.render(out);
+        }
+    }
+
+    /// Sets the glide time for note changes while notes are held. 0
+    /// disables gliding.
+    pub fn set_glide_ms(&mut self, time: ms) {
+        self.glide.set_glide_ms(time);
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.glide.set_msample_rate(msample_rate);
+    }
+
+    /// Returns the sounding note.
+    pub fn get_note(&self) -> Option<u8> {
+        self.note
+    }
+
+    /// Sets the tuning of all notes, including the sounding one.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.retune = !tuning.is_equal();
+        self.tuning = tuning;
+        // Drop the held notes that are unmapped now
+        let held = self.len;
+        self.len = 0;
+        for i in 0..held {
+            if self.tuning.get_mfreq(self.stack[i]).is_some() {
+                self.stack[self.len] = self.stack[i];
+                self.len += 1;
+            }
+        }
+        self.update();
+        if let Some(note) = self.note {
+            let mfreq = self.mfreq(note);
+            if self.glide.is_gliding() {
+                self.glide.set_target_mfreq(mfreq);
+            } else {
+                self.glide.set_mfreq(mfreq);
+                self.voice.set_mfreq(mfreq);
+            }
+        }
+    }
+
+    /// Sets the note priority.
+    pub fn set_priority(&mut self, priority: NotePriority) {
+        self.priority = priority;
+        if self.note.is_some() {
+            self.update();
+        }
+    }
+
+    /// Sets the legato or retrigger mode.
+    pub fn set_mode(&mut self, mode: MonoMode) {
+        self.mode = mode;
+    }
+
+    /// Returns the voice for changing its parameters.
+    pub fn get_voice_mut(&mut self) -> &mut V {
+        &mut self.voice
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Logs the calls it receives.
+    #[derive(Default)]
+    struct LogVoice {
+        log: Vec<(&'static str, u8)>,
+        mfreq: u32,
+    }
+
+    impl Voice for LogVoice {
+        fn note_on(&mut self, note: u8, _velocity: u8) {
+            self.log.push(("on", note));
+        }
+
+        fn set_note(&mut self, note: u8) {
+            self.log.push(("set", note));
+        }
+
+        fn set_mfreq(&mut self, mfreq: mHz) {
+            self.mfreq = mfreq.0;
+        }
+
+        fn note_off(&mut self) {
+            self.log.push(("off", 0));
+        }
+
+        fn is_active(&self) -> bool {
+            true
+        }
+
+        fn render(&mut self, out: &mut [i16]) {
+            out.fill(1);
+        }
+    }
+
+    fn log(handler: &mut MonoHandler<LogVoice>) -> Vec<(&'static str, u8)> {
+        core::mem::take(&mut handler.get_voice_mut().log)
+    }
+
+    #[test]
+    fn test_mono_legato_last() {
+        let mut mono = MonoHandler::new(LogVoice::default());
+        mono.note_on(60, 100);
+        mono.note_on(64, 100);
+        mono.note_on(62, 100);
+        assert_eq!(log(&mut mono), [("on", 60), ("set", 64), ("set", 62)]);
+        // Releasing a note that doesn't sound changes nothing
+        mono.note_off(64);
+        mono.note_off(62);
+        mono.note_off(60);
+        assert_eq!(log(&mut mono), [("set", 60), ("off", 0)]);
+        assert_eq!(mono.get_note(), None);
+    }
+
+    #[test]
+    fn test_mono_retrigger_low_high() {
+        let mut mono = MonoHandler::new(LogVoice::default());
+        mono.set_mode(MonoMode::Retrigger);
+        mono.set_priority(NotePriority::Low);
+        mono.note_on(60, 100);
+        mono.note_on(64, 100);
+        mono.note_on(55, 100);
+        assert_eq!(log(&mut mono), [("on", 60), ("on", 55)]);
+        assert_eq!(mono.get_note(), Some(55));
+
+        mono.set_priority(NotePriority::High);
+        assert_eq!(mono.get_note(), Some(64));
+        mono.note_off(64);
+        assert_eq!(log(&mut mono), [("on", 64), ("on", 60)]);
+        mono.all_notes_off();
+        assert_eq!(log(&mut mono), [("off", 0)]);
+    }
+
+    #[test]
+    fn test_mono_glide() {
+        let mut mono = MonoHandler::new(LogVoice::default());
+        mono.set_glide_ms(ms(10));
+        mono.note_on(69, 100);
+        mono.note_on(81, 100);
+        // Legato glides without retriggering or setting the note
+        assert_eq!(log(&mut mono), [("on", 69)]);
+        assert_eq!(mono.get_voice_mut().mfreq, 440_000);
+        let mut out = [0; 441];
+        mono.render(&mut out);
+        // 63 % of the way to 880 Hz after one glide time
+        let mfreq = mono.get_voice_mut().mfreq;
+        assert!(mfreq.abs_diff(718_000) < 5_000, "{}", mfreq);
+
+        mono.set_mode(MonoMode::Retrigger);
+        mono.note_on(69, 100);
+        assert_eq!(log(&mut mono), [("on", 69)]);
+        assert_eq!(mono.get_voice_mut().mfreq, mfreq);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_mono_tuning() {
+        let tuning = Tuning::edo(24);
+        let mfreq = |note| tuning.get_mfreq(note).unwrap().0;
+        let mut mono = MonoHandler::new(LogVoice::default());
+        mono.note_on(60, 100);
+        // Retunes the sounding note
+        mono.set_tuning(tuning.clone());
+        assert_eq!(mono.get_voice_mut().mfreq, mfreq(60));
+        mono.note_on(62, 100);
+        assert_eq!(log(&mut mono), [("on", 60), ("set", 62)]);
+        assert_eq!(mono.get_voice_mut().mfreq, mfreq(62));
+
+        // Glides to the tuned pitch
+        mono.set_glide_ms(ms(10));
+        mono.note_on(72, 100);
+        let mut out = [0; 4_410];
+        mono.render(&mut out);
+        assert!(mono.get_voice_mut().mfreq.abs_diff(mfreq(72)) < 100);
+    }
+}
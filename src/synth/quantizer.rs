@@ -0,0 +1,167 @@
+// Pitch quantizer that snaps control signals to the notes of a scale.
+
+use crate::util::note::Note;
+use crate::util::scale::Scale;
+
+/// Cents a pitch must move past the middle between two notes before the
+/// quantizer switches, so noisy inputs don't flicker between them
+const HYSTERESIS_CENTS: i32 = 15;
+
+/// Snaps pitches to the nearest note of a scale
+///
+/// Pitches are given in cents above MIDI note 0, or as a control signal,
+/// e.g. from a random LFO or a knob, that sweeps the range from the lowest
+/// to the highest note. [Quantizer::process] reports note changes, so the
+/// quantizer can trigger notes of a voice.
+///
+/// ```
+/// use isopod::synth::quantizer::Quantizer;
+/// use isopod::util::note::Note;
+/// use isopod::util::scale::Scale;
+///
+/// let mut quantizer = Quantizer::new();
+/// quantizer.set_scale(Scale::PENTATONIC_MINOR, 9);
+/// quantizer.set_range(Note::A3, Note::A4);
+/// assert_eq!(quantizer.process(8_192), Some(Note::E4));
+/// assert_eq!(quantizer.process(8_300), None);
+/// assert_eq!(quantizer.process(i16::MIN), Some(Note::A3));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Quantizer {
+    scale: Scale,
+    root: u8,
+    lo: Note,
+    hi: Note,
+    note: Option<Note>,
+}
+
+impl Quantizer {
+    pub fn new() -> Self {
+        Self {
+            scale: Scale::CHROMATIC,
+            root: 0,
+            lo: Note::C3,
+            hi: Note::C6,
+            note: None,
+        }
+    }
+
+    /// Returns the note of `scale` nearest to `cents`, the lower one for
+    /// ties, or `None` for an empty scale.
+    pub fn quantize_cents(&self, cents: i32) -> Option<Note> {
+        if self.scale.is_empty() {
+            return None;
+        }
+        let cents = cents.clamp(0, Note::MAX.0 as i32 * 100);
+        let below = (0..=cents / 100)
+            .rev()
+            .map(|n| Note(n as u8))
+            .find(|n| self.scale.contains(self.root, *n));
+        let above = (cents / 100 + 1..=Note::MAX.0 as i32)
+            .map(|n| Note(n as u8))
+            .find(|n| self.scale.contains(self.root, *n));
+        match (below, above) {
+            (Some(b), Some(a)) if a.0 as i32 * 100 - cents < cents - b.0 as i32 * 100 => Some(a),
+            (None, a) => a,
+            (b, _) => b,
+        }
+    }
+
+    /// Quantizes the pitch `cents` above MIDI note 0 and returns the new
+    /// note if it changed.
+    pub fn process_cents(&mut self, cents: i32) -> Option<Note> {
+        let nearest = self.quantize_cents(cents)?;
+        if let Some(note) = self.note {
+            let distance = |n: Note| (cents - n.0 as i32 * 100).abs();
+            if note == nearest
+                || (self.scale.contains(self.root, note)
+                    && distance(note) <= distance(nearest) + 2 * HYSTERESIS_CENTS)
+            {
+                return None;
+            }
+        }
+        self.note = Some(nearest);
+        self.note
+    }
+
+    /// Quantizes a control signal, where i16::MIN is the lowest and i16::MAX
+    /// the highest note of the range, and returns the new note if it
+    /// changed.
+    pub fn process(&mut self, input: i16) -> Option<Note> {
+        let span = (self.hi.0 as i32 - self.lo.0 as i32) * 100;
+        let offset = ((input as i32 + 32_768) * span) >> 16;
+        // Full scale reaches the highest note
+        let offset = if input == i16::MAX { span } else { offset };
+        self.process_cents(self.lo.0 as i32 * 100 + offset)
+    }
+
+    /// Returns the current note.
+    pub fn get_note(&self) -> Option<Note> {
+        self.note
+    }
+
+    /// Sets the scale on the pitch class `root`, where C is 0.
+    pub fn set_scale(&mut self, scale: Scale, root: u8) {
+        self.scale = scale;
+        self.root = root % 12;
+    }
+
+    /// Sets the notes that the control signal of [Quantizer::process]
+    /// sweeps.
+    pub fn set_range(&mut self, lo: Note, hi: Note) {
+        self.lo = lo.min(hi);
+        self.hi = hi.max(lo);
+    }
+}
+
+impl Default for Quantizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quantize() {
+        let mut quantizer = Quantizer::new();
+        quantizer.set_scale(Scale::MAJOR, 0);
+        // Between E and F there is no gap, between F and G there is F#
+        assert_eq!(quantizer.quantize_cents(6_449), Some(Note::E4));
+        assert_eq!(quantizer.quantize_cents(6_451), Some(Note::F4));
+        assert_eq!(quantizer.quantize_cents(6_600), Some(Note::F4));
+        assert_eq!(quantizer.quantize_cents(6_601), Some(Note::G4));
+        assert_eq!(
+            quantizer.quantize_cents(-500),
+            Some(Note::C4.transpose(-60))
+        );
+        assert_eq!(quantizer.quantize_cents(20_000), Some(Note(127)));
+
+        quantizer.set_scale(Scale(0), 0);
+        assert_eq!(quantizer.quantize_cents(6_000), None);
+    }
+
+    #[test]
+    fn test_quantizer_hysteresis() {
+        let mut quantizer = Quantizer::new();
+        assert_eq!(quantizer.process_cents(6_000), Some(Note::C4));
+        // Noise around the middle doesn't switch
+        for cents in [6_040, 6_060, 6_045, 6_064] {
+            assert_eq!(quantizer.process_cents(cents), None);
+        }
+        assert_eq!(quantizer.process_cents(6_070), Some(Note::Cs4));
+        assert_eq!(quantizer.process_cents(6_040), None);
+        assert_eq!(quantizer.process_cents(6_030), Some(Note::C4));
+
+        // A scale change switches to a note of the new scale
+        quantizer.set_scale(Scale::from_steps(&[1]), 0);
+        assert_eq!(quantizer.process_cents(6_030), Some(Note::Cs4));
+
+        quantizer.set_scale(Scale::CHROMATIC, 0);
+        quantizer.set_range(Note::C5, Note::C4);
+        assert_eq!(quantizer.process(i16::MAX), Some(Note::C5));
+        assert_eq!(quantizer.get_note(), Some(Note::C5));
+    }
+}
@@ -0,0 +1,535 @@
+// Ready-made subtractive voice built from the oscillator, filter and
+// envelope primitives.
+
+use crate::env::adsr::{Adsr, AdsrParams};
+use crate::fx::filter::{StateVariableFilter, Q_MAX};
+use crate::osc::blep::{BlepOscillator, BlepParams, Waveform};
+use crate::osc::lfo::{Lfo, LfoParams};
+use crate::osc::noise::WhiteNoise;
+use crate::preset::Preset;
+use crate::synth::velocity::{Velocity, VelocityMap, VelocityParams};
+use crate::synth::voice::{note_mfreq, Voice};
+use crate::synth::Synth;
+use crate::util::units::{mHz, ratio, Frequency, Hz};
+
+/// Samples per update of the filter envelope, the LFO and the pitches
+const CONTROL_BLOCK: u32 = 16;
+/// Range of the cutoff modulation in cents
+const CUTOFF_CENTS_MAX: i32 = 6_000;
+/// Range of the detune and the vibrato depth in cents, 4 octaves
+pub const PITCH_CENTS_MAX: i32 = 4_800;
+/// Lowest cutoff frequency
+const CUTOFF_MIN: mHz = mHz(20_000);
+
+/// Parameters of a [SubtractiveVoice]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubtractiveParams {
+    pub osc1: BlepParams,
+    pub osc2: BlepParams,
+    pub osc1_level: i16,
+    pub osc2_level: i16,
+    pub noise_level: i16,
+    pub detune: i32,
+    pub cutoff: mHz,
+    pub resonance: u32,
+    pub filter_env_depth: i32,
+    pub lfo_cutoff_depth: i32,
+    pub lfo_pitch_depth: i32,
+    pub pressure_cutoff_depth: i32,
+    pub timbre_cutoff_depth: i32,
+    pub amp_env: AdsrParams,
+    pub filter_env: AdsrParams,
+    pub lfo: LfoParams,
+    pub velocity: VelocityParams,
+}
+
+/// Subtractive synth voice
+///
+/// Two band limited oscillators and white noise are mixed into a resonant
+/// state variable lowpass, followed by the amplifier envelope. The cutoff is
+/// modulated in cents by a second envelope, the LFO and the velocity, the
+/// LFO also modulates the pitch. The per note pressure and timbre of MPE
+/// controllers open the filter as well. Modulation is updated every 16
+/// samples, the amplifier envelope runs at the full sample rate.
+///
+/// The voice plays on its own as a [Synth] or polyphonically in a
+/// [crate::synth::voice::VoiceAllocator]:
+///
+/// ```
+/// use isopod::synth::subtractive::SubtractiveVoice;
+/// use isopod::synth::Synth;
+///
+/// let mut voice = SubtractiveVoice::new();
+/// voice.set_detune(7);
+/// voice.set_filter_env_depth(3_600);
+/// Synth::note_on(&mut voice, 60, 100);
+/// let mut out = [0_i16; 256];
+/// Synth::render(&mut voice, &mut out);
+/// ```
+pub struct SubtractiveVoice {
+    osc1: BlepOscillator,
+    osc2: BlepOscillator,
+    noise: WhiteNoise,
+    filter: StateVariableFilter,
+    amp_env: Adsr,
+    filter_env: Adsr,
+    lfo: Lfo,
+    velocity_map: VelocityMap,
+
+    // Mix levels normalized to SAMPLE_NORM
+    osc1_level: i16,
+    osc2_level: i16,
+    noise_level: i16,
+    // Frequency ratio of osc2 normalized to 1 << 16
+    osc2_ratio: u32,
+    detune: i32,
+
+    cutoff: mHz,
+    filter_env_depth: i32,
+    lfo_cutoff_depth: i32,
+    lfo_pitch_depth: i32,
+    pressure_cutoff_depth: i32,
+    timbre_cutoff_depth: i32,
+
+    note: u8,
+    mfreq: mHz,
+    velocity: Velocity,
+    // Velocity gain of the current note
+    gain: i16,
+    // Expression normalized to i16::MAX
+    pressure: i16,
+    timbre: i16,
+    // Samples until the next control update
+    countdown: u32,
+
+    msample_rate: mHz,
+}
+
+impl SubtractiveVoice {
+    pub fn new() -> Self {
+        let mut s = Self {
+            osc1: BlepOscillator::new(),
+            osc2: BlepOscillator::new(),
+            noise: WhiteNoise::new(),
+            filter: StateVariableFilter::new(),
+            amp_env: Adsr::new(),
+            filter_env: Adsr::new(),
+            lfo: Lfo::new(),
+            velocity_map: VelocityMap::new(),
+
+            osc1_level: i16::MAX,
+            osc2_level: i16::MAX,
+            noise_level: 0,
+            osc2_ratio: 1 << 16,
+            detune: 0,
+
+            cutoff: Hz(1_000).to_mHz(),
+            filter_env_depth: 2_400,
+            lfo_cutoff_depth: 0,
+            lfo_pitch_depth: 0,
+            pressure_cutoff_depth: 1_200,
+            timbre_cutoff_depth: 2_400,
+
+            note: 69,
+            mfreq: Hz(440).to_mHz(),
+            velocity: Velocity(0),
+            gain: 0,
+            pressure: 0,
+            timbre: 0,
+            countdown: 0,
+
+            msample_rate: mHz(44_100_000),
+        };
+        s.filter.set_q(Q_MAX / 4);
+        s.set_msample_rate(s.msample_rate);
+        s
+    }
+
+    /// Runs the filter envelope and the LFO and updates cutoff and pitches.
+    fn update_control(&mut self) {
+        let env = self.filter_env._next() as i64;
+        let lfo = self.lfo.next().unwrap_or(0) as i64;
+        let max = i16::MAX as i64;
+
+        let cents = (self.filter_env_depth as i64 * env
+            + self.lfo_cutoff_depth as i64 * lfo
+            + self.pressure_cutoff_depth as i64 * self.pressure as i64
+            + self.timbre_cutoff_depth as i64 * self.timbre as i64)
+            / max
+            + self.velocity_map.get_cutoff_cents(self.velocity) as i64;
+        let cents = cents.clamp(-CUTOFF_CENTS_MAX as i64, CUTOFF_CENTS_MAX as i64) as i32;
+        // The filter clamps the cutoff to its stable range
+        let cutoff = (self.cutoff.0 as u64 * ratio(cents) as u64) >> 16;
+        let cutoff = cutoff.clamp(CUTOFF_MIN.0 as u64, u32::MAX as u64) as u32;
+        self.filter.set_mfreq(mHz(cutoff));
+
+        let pitch = ratio((self.lfo_pitch_depth as i64 * lfo / max) as i32) as u64;
+        let mfreq = (self.mfreq.0 as u64 * pitch) >> 16;
+        self.osc1.set_mfreq(mHz(mfreq as u32));
+        let mfreq2 = (mfreq * self.osc2_ratio as u64) >> 16;
+        self.osc2.set_mfreq(mHz(mfreq2.min(u32::MAX as u64) as u32));
+    }
+
+    /// Returns the next sample.
+    #[inline]
+    fn sample(&mut self) -> i16 {
+        if self.countdown == 0 {
+            self.update_control();
+            self.countdown = CONTROL_BLOCK;
+        }
+        self.countdown -= 1;
+
+        // In i64, since three full scale sources at full level exceed i32
+        let mix = self.osc1._next() as i64 * self.osc1_level as i64
+            + self.osc2._next() as i64 * self.osc2_level as i64
+            + self.noise.next().unwrap_or(0) as i64 * self.noise_level as i64;
+        // Headroom for the sum of three sources and the resonance
+        self.filter.feed((mix >> 17) as i16);
+
+        let amp = (self.amp_env._next() as i32 * self.gain as i32) >> 15;
+        let y = (self.filter.get_lp() as i32 * amp) >> 14;
+        y.clamp(-i16::MAX as i32, i16::MAX as i32) as i16
+    }
+
+    /// Sets the waveform of the first oscillator.
+    pub fn set_osc1_waveform(&mut self, waveform: Waveform) {
+        self.osc1.set_waveform(waveform);
+    }
+
+    /// Sets the waveform of the second oscillator.
+    pub fn set_osc2_waveform(&mut self, waveform: Waveform) {
+        self.osc2.set_waveform(waveform);
+    }
+
+    /// Sets the mix levels of both oscillators and the noise normalized to
+    /// [crate::util::units::SAMPLE_NORM].
+    pub fn set_levels(&mut self, osc1: i16, osc2: i16, noise: i16) {
+        self.osc1_level = osc1.max(0);
+        self.osc2_level = osc2.max(0);
+        self.noise_level = noise.max(0);
+    }
+
+    /// Sets the pitch of the second oscillator relative to the first in
+    /// cents, up to [PITCH_CENTS_MAX] either way.
+    pub fn set_detune(&mut self, cents: i32) {
+        self.detune = cents.clamp(-PITCH_CENTS_MAX, PITCH_CENTS_MAX);
+        self.osc2_ratio = ratio(self.detune);
+    }
+
+    /// Sets the cutoff frequency without modulation.
+    pub fn set_cutoff(&mut self, cutoff: mHz) {
+        self.cutoff = cutoff;
+    }
+
+    /// Sets the resonance from 0 to [Q_MAX].
+    pub fn set_resonance(&mut self, q: u32) {
+        self.filter.set_q(q);
+    }
+
+    /// Sets the cutoff offset at the peak of the filter envelope in cents.
+    pub fn set_filter_env_depth(&mut self, cents: i32) {
+        self.filter_env_depth = cents;
+    }
+
+    /// Sets the cutoff offset at full pressure in cents.
+    pub fn set_pressure_cutoff_depth(&mut self, cents: i32) {
+        self.pressure_cutoff_depth = cents;
+    }
+
+    /// Sets the cutoff offset at full timbre in cents.
+    pub fn set_timbre_cutoff_depth(&mut self, cents: i32) {
+        self.timbre_cutoff_depth = cents;
+    }
+
+    /// Sets the cutoff offset at the LFO peaks in cents.
+    pub fn set_lfo_cutoff_depth(&mut self, cents: i32) {
+        self.lfo_cutoff_depth = cents;
+    }
+
+    /// Sets the pitch offset at the LFO peaks in cents, i.e. the vibrato
+    /// depth, up to [PITCH_CENTS_MAX] either way.
+    pub fn set_lfo_pitch_depth(&mut self, cents: i32) {
+        self.lfo_pitch_depth = cents.clamp(-PITCH_CENTS_MAX, PITCH_CENTS_MAX);
+    }
+
+    /// Returns the amplifier envelope for changing its times and sustain.
+    /// Its sample rate is managed by the voice.
+    pub fn get_amp_env_mut(&mut self) -> &mut Adsr {
+        &mut self.amp_env
+    }
+
+    /// Returns the filter envelope for changing its times and sustain. It
+    /// runs at the control rate, which is managed by the voice.
+    pub fn get_filter_env_mut(&mut self) -> &mut Adsr {
+        &mut self.filter_env
+    }
+
+    /// Returns the LFO for changing its shape and rate. It runs at the
+    /// control rate, which is managed by the voice.
+    pub fn get_lfo_mut(&mut self) -> &mut Lfo {
+        &mut self.lfo
+    }
+
+    /// Returns the velocity routing.
+    pub fn get_velocity_map_mut(&mut self) -> &mut VelocityMap {
+        &mut self.velocity_map
+    }
+
+    /// Returns the note of the last note on.
+    pub fn get_note(&self) -> u8 {
+        self.note
+    }
+
+    /// Sets the sample rate in mHz.
+    pub fn set_msample_rate(&mut self, msample_rate: mHz) {
+        self.msample_rate = msample_rate;
+        let control_rate = mHz(msample_rate.0 / CONTROL_BLOCK);
+        self.osc1.set_msample_rate(msample_rate);
+        self.osc2.set_msample_rate(msample_rate);
+        self.filter.set_msample_rate(msample_rate);
+        self.amp_env.set_msample_rate(msample_rate);
+        self.filter_env.set_msample_rate(control_rate);
+        self.lfo.set_msample_rate(control_rate);
+        self.countdown = 0;
+    }
+}
+
+impl Default for SubtractiveVoice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for SubtractiveVoice {
+    type Params = SubtractiveParams;
+
+    fn get_params(&self) -> SubtractiveParams {
+        SubtractiveParams {
+            osc1: self.osc1.get_params(),
+            osc2: self.osc2.get_params(),
+            osc1_level: self.osc1_level,
+            osc2_level: self.osc2_level,
+            noise_level: self.noise_level,
+            detune: self.detune,
+            cutoff: self.cutoff,
+            resonance: self.filter.get_q(),
+            filter_env_depth: self.filter_env_depth,
+            lfo_cutoff_depth: self.lfo_cutoff_depth,
+            lfo_pitch_depth: self.lfo_pitch_depth,
+            pressure_cutoff_depth: self.pressure_cutoff_depth,
+            timbre_cutoff_depth: self.timbre_cutoff_depth,
+            amp_env: self.amp_env.get_params(),
+            filter_env: self.filter_env.get_params(),
+            lfo: self.lfo.get_params(),
+            velocity: self.velocity_map.get_params(),
+        }
+    }
+
+    fn set_params(&mut self, params: &SubtractiveParams) {
+        self.osc1.set_params(&params.osc1);
+        self.osc2.set_params(&params.osc2);
+        self.set_levels(params.osc1_level, params.osc2_level, params.noise_level);
+        self.set_detune(params.detune);
+        self.cutoff = params.cutoff;
+        self.filter.set_q(params.resonance);
+        self.filter_env_depth = params.filter_env_depth;
+        self.lfo_cutoff_depth = params.lfo_cutoff_depth;
+        self.set_lfo_pitch_depth(params.lfo_pitch_depth);
+        self.pressure_cutoff_depth = params.pressure_cutoff_depth;
+        self.timbre_cutoff_depth = params.timbre_cutoff_depth;
+        self.amp_env.set_params(&params.amp_env);
+        self.filter_env.set_params(&params.filter_env);
+        self.lfo.set_params(&params.lfo);
+        self.velocity_map.set_params(&params.velocity);
+        self.countdown = 0;
+    }
+}
+
+impl Voice for SubtractiveVoice {
+    fn note_on(&mut self, note: u8, velocity: u8) {
+        self.note = note;
+        self.mfreq = note_mfreq(note);
+        self.velocity = Velocity::new(velocity);
+        self.gain = self.velocity_map.get_gain(self.velocity);
+        self.amp_env.gate_on();
+        self.filter_env.gate_on();
+        self.countdown = 0;
+    }
+
+    fn set_note(&mut self, note: u8) {
+        self.note = note;
+        self.set_mfreq(note_mfreq(note));
+    }
+
+    fn set_mfreq(&mut self, mfreq: mHz) {
+        self.mfreq = mfreq;
+        self.countdown = 0;
+    }
+
+    fn set_phase(&mut self, phase: u32) {
+        self.osc1.set_phase(phase);
+        self.osc2.set_phase(phase);
+    }
+
+    fn set_pressure(&mut self, pressure: i16) {
+        self.pressure = pressure;
+    }
+
+    fn set_timbre(&mut self, timbre: i16) {
+        self.timbre = timbre;
+    }
+
+    fn note_off(&mut self) {
+        self.amp_env.gate_off();
+        self.filter_env.gate_off();
+    }
+
+    fn is_active(&self) -> bool {
+        self.amp_env.is_active()
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for y in out.iter_mut() {
+            *y = self.sample();
+        }
+    }
+}
+
+impl Synth for SubtractiveVoice {
+    fn _next(&mut self) -> Option<i16> {
+        Some(self.sample())
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        Voice::render(self, out);
+    }
+
+    fn note_on(&mut self, note: u8, velocity: u8) {
+        Voice::note_on(self, note, velocity);
+    }
+
+    /// Releases the voice if `note` is the note it plays.
+    fn note_off(&mut self, note: u8) {
+        if note == self.note {
+            Voice::note_off(self);
+        }
+    }
+
+    fn get_sample_rate(&self) -> Hz {
+        self.msample_rate.to_Hz()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: Hz) {
+        self.set_msample_rate(sample_rate.to_mHz());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::units::ms;
+
+    fn peak(out: &[i16]) -> i16 {
+        out.iter().map(|y| y.saturating_abs()).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn test_subtractive_voice() {
+        let mut voice = SubtractiveVoice::new();
+        voice.get_amp_env_mut().set_release_ms(ms(10));
+        assert!(!Voice::is_active(&voice));
+
+        Voice::note_on(&mut voice, 57, 127);
+        let mut out = [0_i16; 4_410];
+        Voice::render(&mut voice, &mut out);
+        assert!(Voice::is_active(&voice));
+        assert!(peak(&out[2_205..]) > 4_000, "{}", peak(&out));
+
+        // Other notes don't release the voice
+        Synth::note_off(&mut voice, 60);
+        assert!(Voice::is_active(&voice));
+        Synth::note_off(&mut voice, 57);
+        Voice::render(&mut voice, &mut out);
+        assert!(!Voice::is_active(&voice));
+        assert_eq!(peak(&out[4_000..]), 0);
+    }
+
+    #[test]
+    fn test_subtractive_params() {
+        let mut voice = SubtractiveVoice::new();
+        voice.set_osc2_waveform(Waveform::Square);
+        voice.set_detune(12);
+        voice.set_resonance(Q_MAX / 2);
+        voice.get_filter_env_mut().set_decay_ms(ms(1_234));
+        voice.get_velocity_map_mut().set_cutoff_depth(600);
+        let params = voice.get_params();
+        assert_eq!(params.osc2.waveform, Waveform::Square);
+        assert_eq!(params.resonance, Q_MAX / 2);
+        assert_eq!(params.filter_env.decay, ms(1_234));
+
+        // Restores the parameters, but not the sample rate
+        let mut other = SubtractiveVoice::new();
+        other.set_msample_rate(mHz(48_000_000));
+        other.set_params(&params);
+        assert_eq!(other.get_params(), params);
+
+        let render = |voice: &mut SubtractiveVoice| {
+            Voice::note_on(voice, 60, 100);
+            let mut out = [0_i16; 256];
+            Voice::render(voice, &mut out);
+            out
+        };
+        let mut copy = SubtractiveVoice::new();
+        copy.set_params(&params);
+        assert_eq!(render(&mut copy), render(&mut voice));
+
+        // Pitch offsets are limited, also from presets
+        let mut params = params;
+        params.detune = -100_000;
+        params.lfo_pitch_depth = i32::MIN;
+        copy.set_params(&params);
+        let params = copy.get_params();
+        assert_eq!((params.detune, params.lfo_pitch_depth), (-4_800, -4_800));
+        copy.set_detune(i32::MAX);
+        copy.set_lfo_pitch_depth(i32::MAX);
+        copy.set_levels(i16::MAX, i16::MAX, i16::MAX);
+        render(&mut copy);
+        assert_eq!(copy.get_params().detune, PITCH_CENTS_MAX);
+    }
+
+    #[test]
+    fn test_subtractive_velocity() {
+        let render = |velocity| {
+            let mut voice = SubtractiveVoice::new();
+            Voice::note_on(&mut voice, 57, velocity);
+            let mut out = [0_i16; 2_205];
+            Voice::render(&mut voice, &mut out);
+            peak(&out)
+        };
+        let (soft, loud) = (render(40), render(127));
+        assert!(soft < loud / 4, "{} {}", soft, loud);
+    }
+
+    #[test]
+    fn test_subtractive_filter_env() {
+        // The filter envelope brightens the attack, so the signal has more
+        // high frequency content (squared sample differences) than when sustained
+        let mut voice = SubtractiveVoice::new();
+        voice.set_cutoff(Hz(200).to_mHz());
+        voice.set_filter_env_depth(4_800);
+        voice.get_filter_env_mut().set_sustain(0);
+        voice.get_filter_env_mut().set_decay_ms(ms(50));
+        voice.get_amp_env_mut().set_sustain(i16::MAX);
+        Voice::note_on(&mut voice, 45, 127);
+        let mut out = [0_i16; 8_820];
+        Voice::render(&mut voice, &mut out);
+        let roughness = |out: &[i16]| {
+            out.windows(2)
+                .map(|w| (w[1] as i64 - w[0] as i64).pow(2))
+                .sum::<i64>()
+        };
+        let (attack, sustain) = (roughness(&out[..1_000]), roughness(&out[7_820..]));
+        assert!(attack > 2 * sustain, "{} {}", attack, sustain);
+    }
+}
@@ -0,0 +1,246 @@
+// Unison stack playing several detuned copies of a voice.
+
+use crate::fx::panner::Panner;
+use crate::osc::noise::LFSR;
+use crate::synth::voice::{note_mfreq, Voice};
+use crate::util::units::{mHz, ratio, Frame, Sample};
+
+/// Samples rendered per copy at once
+const BLOCK: usize = 64;
+/// Largest detune between the outermost copies in cents, 2 octaves
+pub const DETUNE_MAX: i32 = 2_400;
+
+/// `N` copies of a voice acting as a single voice
+///
+/// The copies are detuned and panned symmetrically around the center. The
+/// detune is the distance between the outermost copies in cents and the
+/// spread is the pan position of the outermost copies (0 to i16::MAX).
+/// With random phases, every note starts the copies at different phases,
+/// which avoids the flanging of copies that start in phase. The output is
+/// the sum of all copies divided by `N`, so it can't clip.
+pub struct UnisonStack<V: Voice, const N: usize> {
+    voices: [V; N],
+    panners: [Panner; N],
+    // Frequency ratios of the copies normalized to 1 << 16
+    ratios: [u32; N],
+
+    mfreq: mHz,
+    detune: i32,
+    spread: i16,
+    random_phase: bool,
+    lfsr: LFSR<u32>,
+}
+
+impl<V: Voice, const N: usize> UnisonStack<V, N> {
+    pub fn new(voices: [V; N]) -> Self {
+        let mut s = Self {
+            voices,
+            panners: core::array::from_fn(|_| Panner::new()),
+            ratios: [1 << 16; N],
+
+            mfreq: mHz(0),
+            detune: 0,
+            spread: 0,
+            random_phase: true,
+            lfsr: LFSR::<u32>::new(),
+        };
+        s.update_copies();
+        s
+    }
+
+    /// Returns the symmetric offset of copy `i` scaled to `amount`.
+    fn offset(i: usize, amount: i32) -> i32 {
+        if N < 2 {
+            return 0;
+        }
+        (amount as i64 * (2 * i as i64 - (N as i64 - 1)) / (2 * (N as i64 - 1))) as i32
+    }
+
+    fn update_copies(&mut self) {
+        for i in 0..N {
+            self.ratios[i] = ratio(Self::offset(i, self.detune));
+            self.panners[i].set_pan(2 * Self::offset(i, self.spread as i32) as i16);
+        }
+    }
+
+    fn update_mfreqs(&mut self) {
+        for (voice, ratio) in self.voices.iter_mut().zip(self.ratios.iter()) {
+            voice.set_mfreq(mHz(((self.mfreq.0 as u64 * *ratio as u64) >> 16) as u32));
+        }
+    }
+
+    /// Sets the detune between the outermost copies in cents, up to
+    /// [DETUNE_MAX] either way.
+    pub fn set_detune(&mut self, cents: i32) {
+        self.detune = cents.clamp(-DETUNE_MAX, DETUNE_MAX);
+        self.update_copies();
+        self.update_mfreqs();
+    }
+
+    /// Sets the pan position of the outermost copies (0 to i16::MAX).
+    pub fn set_spread(&mut self, spread: i16) {
+        self.spread = spread.max(0);
+        self.update_copies();
+    }
+
+    /// Enables or disables random start phases.
+    pub fn set_random_phase(&mut self, random_phase: bool) {
+        self.random_phase = random_phase;
+    }
+
+    /// Fills `out` with the panned copies.
+    pub fn render_frames(&mut self, out: &mut [Frame]) {
+        let mut buf = [0_i16; BLOCK];
+        for chunk in out.chunks_mut(BLOCK) {
+            let mut acc = [(0_i32, 0_i32); BLOCK];
+            let buf = &mut buf[..chunk.len()];
+            for (voice, panner) in self.voices.iter_mut().zip(self.panners.iter()) {
+                if !voice.is_active() {
+                    continue;
+                }
+                voice.render(buf);
+                for (a, x) in acc.iter_mut().zip(buf.iter()) {
+                    let frame = panner.process(*x);
+                    a.0 += frame.left.0 as i32;
+                    a.1 += frame.right.0 as i32;
+                }
+            }
+            for (y, (left, right)) in chunk.iter_mut().zip(acc.iter()) {
+                *y = Frame {
+                    left: Sample((left / N as i32) as i16),
+                    right: Sample((right / N as i32) as i16),
+                };
+            }
+        }
+    }
+
+    /// Returns all copies, e.g. for changing their parameters.
+    pub fn get_voices_mut(&mut self) -> &mut [V; N] {
+        &mut self.voices
+    }
+}
+
+impl<V: Voice, const N: usize> Voice for UnisonStack<V, N> {
+    fn note_on(&mut self, note: u8, velocity: u8) {
+        for voice in self.voices.iter_mut() {
+            voice.note_on(note, velocity);
+            if self.random_phase {
+                voice.set_phase(self.lfsr.next());
+            }
+        }
+        self.mfreq = note_mfreq(note);
+        self.update_mfreqs();
+    }
+
+    fn set_note(&mut self, note: u8) {
+        for voice in self.voices.iter_mut() {
+            voice.set_note(note);
+        }
+        self.mfreq = note_mfreq(note);
+        self.update_mfreqs();
+    }
+
+    fn set_mfreq(&mut self, mfreq: mHz) {
+        self.mfreq = mfreq;
+        self.update_mfreqs();
+    }
+
+    fn set_phase(&mut self, phase: u32) {
+        for voice in self.voices.iter_mut() {
+            voice.set_phase(phase);
+        }
+    }
+
+    fn set_pressure(&mut self, pressure: i16) {
+        for voice in self.voices.iter_mut() {
+            voice.set_pressure(pressure);
+        }
+    }
+
+    fn set_timbre(&mut self, timbre: i16) {
+        for voice in self.voices.iter_mut() {
+            voice.set_timbre(timbre);
+        }
+    }
+
+    fn note_off(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.note_off();
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.voices.iter().any(|v| v.is_active())
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        let mut buf = [0_i16; BLOCK];
+        for chunk in out.chunks_mut(BLOCK) {
+            let mut acc = [0_i32; BLOCK];
+            let buf = &mut buf[..chunk.len()];
+            for voice in self.voices.iter_mut() {
+                if !voice.is_active() {
+                    continue;
+                }
+                voice.render(buf);
+                for (a, x) in acc.iter_mut().zip(buf.iter()) {
+                    *a += *x as i32;
+                }
+            }
+            for (y, a) in chunk.iter_mut().zip(acc.iter()) {
+                *y = (a / N as i32) as i16;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::synth::voice::test::TestVoice;
+
+    fn stack<const N: usize>() -> UnisonStack<TestVoice, N> {
+        UnisonStack::new(core::array::from_fn(|i| TestVoice {
+            level: 1_000 * (i as i16 + 1),
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn test_unison_detune() {
+        let mut unison = stack::<3>();
+        unison.set_detune(20);
+        unison.note_on(69, 100);
+        let mfreqs: Vec<u32> = unison.get_voices_mut().iter().map(|v| v.mfreq).collect();
+        assert!(mfreqs[0].abs_diff(437_466) <= 5, "{:?}", mfreqs);
+        assert_eq!(mfreqs[1], 440_000);
+        assert!(mfreqs[2].abs_diff(442_548) <= 5, "{:?}", mfreqs);
+
+        // Random phases differ between the copies
+        let phases: Vec<u32> = unison.get_voices_mut().iter().map(|v| v.phase).collect();
+        assert!(phases[0] != phases[1] && phases[1] != phases[2]);
+
+        let mut out = [0; 100];
+        unison.render(&mut out);
+        assert!(out.iter().all(|y| *y == 2_000));
+        unison.note_off();
+        assert!(!unison.is_active());
+
+        // An octave up and down at most
+        unison.set_detune(i32::MIN);
+        let mfreqs: Vec<u32> = unison.get_voices_mut().iter().map(|v| v.mfreq).collect();
+        assert_eq!(mfreqs, [880_000, 440_000, 220_000]);
+    }
+
+    #[test]
+    fn test_unison_spread() {
+        let mut unison = stack::<2>();
+        unison.set_spread(i16::MAX);
+        unison.note_on(60, 100);
+        let mut out = [Frame::mono(0); 10];
+        unison.render_frames(&mut out);
+        // The copies are panned hard left and right
+        assert!(out[9].left.0.abs_diff(500) <= 10, "{:?}", out[9]);
+        assert!(out[9].right.0.abs_diff(1_000) <= 10, "{:?}", out[9]);
+    }
+}
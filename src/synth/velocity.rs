@@ -0,0 +1,172 @@
+// Note velocity and its mapping to amplitude and filter cutoff.
+
+use crate::preset::Preset;
+use crate::util::units::dB;
+
+/// Highest MIDI velocity
+pub const VELOCITY_MAX: u8 = 127;
+/// Dynamic range of [VelocityCurve::Exponential] in dB
+const EXP_RANGE_DB: i32 = 40;
+
+/// Note velocity from 0 to [VELOCITY_MAX]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Velocity(pub u8);
+
+impl Velocity {
+    /// Velocity clamped to [VELOCITY_MAX]
+    pub fn new(velocity: u8) -> Self {
+        Self(velocity.min(VELOCITY_MAX))
+    }
+}
+
+/// Response of a [VelocityMap] to the velocity
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VelocityCurve {
+    /// Proportional to the velocity.
+    Linear,
+    /// Constant steps in dB over a range of 40 dB, which sounds more even
+    /// than linear.
+    Exponential,
+    /// Ignores the velocity, as on organs.
+    Fixed,
+}
+
+impl VelocityCurve {
+    /// Returns the response to `velocity` normalized to
+    /// [crate::util::units::SAMPLE_NORM].
+    pub fn apply(self, velocity: Velocity) -> i16 {
+        let v = velocity.0.min(VELOCITY_MAX) as i32;
+        let max = VELOCITY_MAX as i32;
+        match self {
+            VelocityCurve::Linear => (v * i16::MAX as i32 / max) as i16,
+            VelocityCurve::Exponential => {
+                if v == 0 {
+                    return 0;
+                }
+                // Level in 1/256 dB, interpolated between whole dB
+                let level = -EXP_RANGE_DB * 256 + EXP_RANGE_DB * 256 * v / max;
+                let lo = dB(level.div_euclid(256)).to_gain() as i32;
+                let hi = dB(level.div_euclid(256) + 1).to_gain() as i32;
+                let gain = lo + (((hi - lo) * level.rem_euclid(256)) >> 8);
+                gain.min(i16::MAX as i32) as i16
+            }
+            VelocityCurve::Fixed => i16::MAX,
+        }
+    }
+}
+
+/// Parameters of a [VelocityMap]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VelocityParams {
+    pub curve: VelocityCurve,
+    pub amp_depth: i16,
+    pub cutoff_depth: i32,
+}
+
+/// Standard velocity routings of a voice
+///
+/// The amplitude depth sets how much the velocity attenuates quiet notes
+/// (0 ignores the velocity, i16::MAX silences velocity 0). The cutoff depth
+/// is the offset in cents by which full velocity opens the filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VelocityMap {
+    curve: VelocityCurve,
+    amp_depth: i16,
+    cutoff_depth: i32,
+}
+
+impl VelocityMap {
+    pub fn new() -> Self {
+        Self {
+            curve: VelocityCurve::Exponential,
+            amp_depth: i16::MAX,
+            cutoff_depth: 0,
+        }
+    }
+
+    /// Returns the amplitude normalized to
+    /// [crate::util::units::SAMPLE_NORM].
+    pub fn get_gain(&self, velocity: Velocity) -> i16 {
+        let response = self.curve.apply(velocity) as i32;
+        let attenuation = ((i16::MAX as i32 - response) * self.amp_depth as i32) >> 15;
+        (i16::MAX as i32 - attenuation) as i16
+    }
+
+    /// Returns the cutoff offset in cents.
+    pub fn get_cutoff_cents(&self, velocity: Velocity) -> i32 {
+        (self.cutoff_depth as i64 * self.curve.apply(velocity) as i64 / i16::MAX as i64) as i32
+    }
+
+    /// Sets the velocity curve.
+    pub fn set_curve(&mut self, curve: VelocityCurve) {
+        self.curve = curve;
+    }
+
+    /// Sets how much the velocity affects the amplitude.
+    pub fn set_amp_depth(&mut self, depth: i16) {
+        self.amp_depth = depth.max(0);
+    }
+
+    /// Sets the cutoff offset at full velocity in cents.
+    pub fn set_cutoff_depth(&mut self, cents: i32) {
+        self.cutoff_depth = cents;
+    }
+}
+
+impl Default for VelocityMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preset for VelocityMap {
+    type Params = VelocityParams;
+
+    fn get_params(&self) -> VelocityParams {
+        VelocityParams {
+            curve: self.curve,
+            amp_depth: self.amp_depth,
+            cutoff_depth: self.cutoff_depth,
+        }
+    }
+
+    fn set_params(&mut self, params: &VelocityParams) {
+        self.curve = params.curve;
+        self.set_amp_depth(params.amp_depth);
+        self.cutoff_depth = params.cutoff_depth;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_velocity_curves() {
+        assert_eq!(Velocity::new(200), Velocity(127));
+        for curve in [VelocityCurve::Linear, VelocityCurve::Exponential] {
+            assert_eq!(curve.apply(Velocity(0)), 0);
+            assert_eq!(curve.apply(Velocity(127)), i16::MAX);
+            let responses: Vec<i16> = (0..=127).map(|v| curve.apply(Velocity(v))).collect();
+            assert!(responses.windows(2).all(|w| w[0] <= w[1]), "{:?}", curve);
+        }
+        // Half velocity is 20 dB down (0.1)
+        let half = VelocityCurve::Exponential.apply(Velocity(64)) as i32;
+        assert!((half - 3_277).abs() < 150, "{}", half);
+        assert_eq!(VelocityCurve::Fixed.apply(Velocity(1)), i16::MAX);
+    }
+
+    #[test]
+    fn test_velocity_map() {
+        let mut map = VelocityMap::new();
+        map.set_curve(VelocityCurve::Linear);
+        map.set_amp_depth(i16::MAX / 2);
+        map.set_cutoff_depth(1_200);
+        assert_eq!(map.get_gain(Velocity(127)), i16::MAX);
+        assert!((map.get_gain(Velocity(0)) as i32 - 16_384).abs() <= 1);
+        assert_eq!(map.get_cutoff_cents(Velocity(127)), 1_200);
+        assert_eq!(map.get_cutoff_cents(Velocity(0)), 0);
+    }
+}
@@ -0,0 +1,580 @@
+// Polyphonic voice allocation.
+
+use crate::synth::NoteEvent;
+use crate::util::diag;
+use crate::util::note::Note;
+#[cfg(feature = "parallel")]
+use crate::util::pool::WorkerPool;
+use crate::util::tuning::Tuning;
+use crate::util::units::{mHz, ratio};
+
+/// Samples rendered per voice at once by [VoiceAllocator::render]
+const BLOCK: usize = 64;
+/// Longest block of [VoiceAllocator::render_parallel] until it is set
+#[cfg(feature = "parallel")]
+const PARALLEL_BLOCK: usize = 1_024;
+/// Pitch bend range of a new [VoiceAllocator] in semitones
+pub const BEND_RANGE: u8 = 2;
+/// Largest pitch bend range in semitones, the member channel range of MPE
+pub const BEND_RANGE_MAX: u8 = 48;
+/// Largest pitch bend value, the lowest is `-BEND_MAX - 1`
+pub const BEND_MAX: i16 = 8191;
+
+/// Returns the equal tempered frequency of MIDI note `note` (A4 = 69 is
+/// 440 Hz).
+pub fn note_mfreq(note: u8) -> mHz {
+    Note(note).to_mfreq()
+}
+
+/// Single voice of a polyphonic synth
+pub trait Voice {
+    /// Starts `note` (MIDI note number) with `velocity` (0 to 127).
+    fn note_on(&mut self, note: u8, velocity: u8);
+    /// Changes the pitch of the sounding note to `note` without
+    /// retriggering it, e.g. for legato playing.
+    fn set_note(&mut self, note: u8);
+    /// Sets the pitch of the sounding note directly, e.g. during a glide.
+    fn set_mfreq(&mut self, mfreq: mHz);
+    /// Sets the oscillator phase of the voice, where the full u32 range
+    /// corresponds to one period. Voices without a meaningful phase ignore
+    /// it.
+    fn set_phase(&mut self, _phase: u32) {}
+    /// Sets the pressure of the note from 0 to i16::MAX, e.g. from MPE or
+    /// channel pressure. Voices without expression ignore it.
+    fn set_pressure(&mut self, _pressure: i16) {}
+    /// Sets the timbre of the note from 0 to i16::MAX, which MPE sends as
+    /// CC74. Voices without expression ignore it.
+    fn set_timbre(&mut self, _timbre: i16) {}
+    /// Releases the current note. The voice may keep sounding, e.g. during
+    /// the release of an envelope.
+    fn note_off(&mut self);
+    /// True while the voice produces sound.
+    fn is_active(&self) -> bool;
+    /// Fills `out` with the next samples.
+    fn render(&mut self, out: &mut [i16]);
+}
+
+/// Voice that is taken over if a note arrives while all voices are busy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StealPolicy {
+    /// Cycles through the voices.
+    RoundRobin,
+    /// Takes the voice with the longest playing note.
+    Oldest,
+    /// Takes the voice with the lowest peak level in the last block.
+    Quietest,
+}
+
+/// Bookkeeping of a voice
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    // Held note, None after note off
+    note: Option<u8>,
+    // Note of the last note on, kept during the release
+    last_note: u8,
+    // Value of the note on counter at the last note on
+    started: u32,
+    peak: i16,
+}
+
+/// Assigns notes to `N` voices and mixes their output
+///
+/// Free voices are used first, cycling through them so that releases get
+/// to ring out. A note that is already sounding retriggers its voice. Once
+/// all voices are busy, one is stolen according to the [StealPolicy].
+///
+/// The pitch bend applies to all voices, including notes that start while
+/// it is bent. With a [Tuning], the voices are retuned after every note on
+/// and unmapped notes are ignored.
+pub struct VoiceAllocator<V: Voice, const N: usize> {
+    voices: [V; N],
+    slots: [Slot; N],
+    policy: StealPolicy,
+
+    bend: i16,
+    bend_range: u8,
+    // Frequency ratio of the bend normalized to 1 << 16
+    bend_ratio: u32,
+    tuning: Tuning,
+    // False for equal temperament, where the voices tune themselves
+    retune: bool,
+
+    // Next voice to try
+    next: usize,
+    // Note on counter for the age of the notes
+    counter: u32,
+
+    // Sums of the voices of each thread of render_parallel, block_size
+    // samples per voice
+    #[cfg(feature = "parallel")]
+    block_size: usize,
+    #[cfg(feature = "parallel")]
+    sums: Vec<i32>,
+}
+
+impl<V: Voice, const N: usize> VoiceAllocator<V, N> {
+    pub fn new(voices: [V; N]) -> Self {
+        Self {
+            voices,
+            slots: [Slot::default(); N],
+            policy: StealPolicy::Oldest,
+
+            bend: 0,
+            bend_range: BEND_RANGE,
+            bend_ratio: 1 << 16,
+            tuning: Tuning::equal(),
+            retune: false,
+
+            next: 0,
+            counter: 0,
+
+            #[cfg(feature = "parallel")]
+            block_size: PARALLEL_BLOCK,
+            #[cfg(feature = "parallel")]
+            sums: vec![0; N * PARALLEL_BLOCK],
+        }
+    }
+
+    fn find_voice(&self, note: u8) -> usize {
+        if let Some(i) =
+            (0..N).find(|i| self.voices[*i].is_active() && self.slots[*i].last_note == note)
+        {
+            return i;
+        }
+        if let Some(i) = (0..N)
+            .map(|i| (self.next + i) % N)
+            .find(|i| !self.voices[*i].is_active())
+        {
+            return i;
+        }
+        match self.policy {
+            StealPolicy::RoundRobin => self.next,
+            StealPolicy::Oldest => (0..N)
+                .max_by_key(|i| self.counter.wrapping_sub(self.slots[*i].started))
+                .unwrap_or(0),
+            StealPolicy::Quietest => (0..N).min_by_key(|i| self.slots[*i].peak).unwrap_or(0),
+        }
+    }
+
+    /// Starts `note` on a free or stolen voice. Ignored without voices.
+    pub fn note_on(&mut self, note: u8, velocity: u8) {
+        if N == 0 || self.tuning.get_mfreq(note).is_none() {
+            return;
+        }
+        let i = self.find_voice(note);
+        self.counter = self.counter.wrapping_add(1);
+        self.slots[i] = Slot {
+            note: Some(note),
+            last_note: note,
+            started: self.counter,
+            peak: self.slots[i].peak,
+        };
+        self.voices[i].note_on(note, velocity);
+        if self.retune || self.bend_ratio != 1 << 16 {
+            self.voices[i].set_mfreq(self.bent_mfreq(note));
+        }
+        self.next = (i + 1) % N;
+    }
+
+    fn bent_mfreq(&self, note: u8) -> mHz {
+        let mfreq = self.tuning.get_mfreq(note).unwrap_or(mHz(0));
+        let mfreq = (mfreq.0 as u64 * self.bend_ratio as u64) >> 16;
+        mHz(mfreq.min(u32::MAX as u64) as u32)
+    }
+
+    fn apply_bend(&mut self) {
+        let cents = self.bend as i32 * self.bend_range as i32 * 100 / (BEND_MAX as i32 + 1);
+        self.bend_ratio = ratio(cents);
+        for i in 0..N {
+            if self.voices[i].is_active() {
+                let mfreq = self.bent_mfreq(self.slots[i].last_note);
+                self.voices[i].set_mfreq(mfreq);
+            }
+        }
+    }
+
+    /// Bends all voices by `value` from `-BEND_MAX - 1` to [BEND_MAX], the
+    /// 14-bit range of MIDI pitch bend messages centered at 0.
+    pub fn pitch_bend(&mut self, value: i16) {
+        self.bend = value.clamp(-BEND_MAX - 1, BEND_MAX);
+        self.apply_bend();
+    }
+
+    /// Returns the current pitch bend value.
+    pub fn get_pitch_bend(&self) -> i16 {
+        self.bend
+    }
+
+    /// Sets the tuning of all notes, including the sounding ones.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.retune = !tuning.is_equal();
+        self.tuning = tuning;
+        self.apply_bend();
+    }
+
+    /// Sets the bend at the ends of the pitch bend range in semitones, up
+    /// to [BEND_RANGE_MAX].
+    pub fn set_bend_range(&mut self, semitones: u8) {
+        self.bend_range = semitones.min(BEND_RANGE_MAX);
+        self.apply_bend();
+    }
+
+    /// Releases all voices holding `note`.
+    pub fn note_off(&mut self, note: u8) {
+        for (voice, slot) in self.voices.iter_mut().zip(self.slots.iter_mut()) {
+            if slot.note == Some(note) {
+                slot.note = None;
+                voice.note_off();
+            }
+        }
+    }
+
+    /// Dispatches `event` to [Self::note_on] or [Self::note_off].
+    pub fn handle_event(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On { note, velocity } => self.note_on(note, velocity),
+            NoteEvent::Off { note } => self.note_off(note),
+        }
+    }
+
+    /// Releases all voices.
+    pub fn all_notes_off(&mut self) {
+        for (voice, slot) in self.voices.iter_mut().zip(self.slots.iter_mut()) {
+            if slot.note.take().is_some() {
+                voice.note_off();
+            }
+        }
+    }
+
+    /// Fills `out` with the saturated sum of all active voices.
+    pub fn render(&mut self, out: &mut [i16]) {
+        let mut buf = [0_i16; BLOCK];
+        for chunk in out.chunks_mut(BLOCK) {
+            let mut acc = [0_i32; BLOCK];
+            let buf = &mut buf[..chunk.len()];
+            for (voice, slot) in self.voices.iter_mut().zip(self.slots.iter_mut()) {
+                if !voice.is_active() {
+                    slot.peak = 0;
+                    continue;
+                }
+                voice.render(buf);
+                slot.peak = buf.iter().map(|x| x.saturating_abs()).max().unwrap_or(0);
+                for (a, x) in acc.iter_mut().zip(buf.iter()) {
+                    *a += *x as i32;
+                }
+            }
+            for (y, a) in chunk.iter_mut().zip(acc.iter()) {
+                *y = diag::clip(*a as i64);
+            }
+        }
+    }
+
+    /// Fills `out` like [Self::render], with the voices split across the
+    /// threads of `pool`. Every thread renders a whole block for its share
+    /// of the voices, so longer blocks spread the overhead of a run. Blocks
+    /// longer than [Self::set_block_size] are rendered in parts.
+    #[cfg(feature = "parallel")]
+    pub fn render_parallel(&mut self, pool: &mut WorkerPool, out: &mut [i16])
+    where
+        V: Send,
+    {
+        if N == 0 {
+            out.fill(0);
+            return;
+        }
+        let share = N.div_ceil(pool.get_threads().min(N));
+        for chunk in out.chunks_mut(self.block_size) {
+            let len = chunk.len();
+            let mut parts = self
+                .voices
+                .chunks_mut(share)
+                .zip(self.slots.chunks_mut(share))
+                .zip(self.sums.chunks_mut(self.block_size));
+            // On the stack, since there are at most N parts
+            let mut parts: [_; N] = core::array::from_fn(|_| parts.next());
+            pool.for_each(&mut parts, &|part| {
+                if let Some(((voices, slots), acc)) = part {
+                    render_voices(voices, slots, &mut acc[..len]);
+                }
+            });
+            let sums = &self.sums[..N.div_ceil(share) * self.block_size];
+            for (n, y) in chunk.iter_mut().enumerate() {
+                let sum = sums.chunks(self.block_size).map(|acc| acc[n] as i64);
+                *y = diag::clip(sum.sum());
+            }
+        }
+    }
+
+    /// Sets the longest block of [Self::render_parallel] and allocates its
+    /// buffers, so that rendering doesn't allocate. It is rounded up to a
+    /// multiple of 64 samples.
+    #[cfg(feature = "parallel")]
+    pub fn set_block_size(&mut self, len: usize) {
+        self.block_size = len.max(1).next_multiple_of(BLOCK);
+        self.sums = vec![0; N * self.block_size];
+    }
+
+    /// Sets the voice stealing policy.
+    pub fn set_policy(&mut self, policy: StealPolicy) {
+        self.policy = policy;
+    }
+
+    /// Returns the number of voices producing sound.
+    pub fn get_active_count(&self) -> usize {
+        self.voices.iter().filter(|v| v.is_active()).count()
+    }
+
+    /// Returns all voices, e.g. for changing their parameters.
+    pub fn get_voices_mut(&mut self) -> &mut [V; N] {
+        &mut self.voices
+    }
+}
+
+/// Sets `acc` to the sum of the active `voices` block by block and keeps
+/// their peaks like [VoiceAllocator::render].
+#[cfg(feature = "parallel")]
+fn render_voices<V: Voice>(voices: &mut [V], slots: &mut [Slot], acc: &mut [i32]) {
+    acc.fill(0);
+    let mut buf = [0_i16; BLOCK];
+    for (voice, slot) in voices.iter_mut().zip(slots.iter_mut()) {
+        for chunk in acc.chunks_mut(BLOCK) {
+            if !voice.is_active() {
+                slot.peak = 0;
+                continue;
+            }
+            let buf = &mut buf[..chunk.len()];
+            voice.render(buf);
+            slot.peak = buf.iter().map(|x| x.saturating_abs()).max().unwrap_or(0);
+            for (a, x) in chunk.iter_mut().zip(buf.iter()) {
+                *a += *x as i32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// Voice for the tests of voice containers, which records what it
+    /// receives. While held it emits its level, or its velocity without a
+    /// level, and it stops immediately on note off.
+    #[derive(Default)]
+    pub(crate) struct TestVoice {
+        pub(crate) note: Option<u8>,
+        pub(crate) velocity: u8,
+        pub(crate) mfreq: u32,
+        pub(crate) phase: u32,
+        pub(crate) pressure: i16,
+        pub(crate) timbre: i16,
+        pub(crate) level: i16,
+    }
+
+    impl Voice for TestVoice {
+        fn note_on(&mut self, note: u8, velocity: u8) {
+            self.set_note(note);
+            self.velocity = velocity;
+        }
+
+        fn set_note(&mut self, note: u8) {
+            self.note = Some(note);
+            self.mfreq = note_mfreq(note).0;
+        }
+
+        fn set_mfreq(&mut self, mfreq: mHz) {
+            self.mfreq = mfreq.0;
+        }
+
+        fn set_phase(&mut self, phase: u32) {
+            self.phase = phase;
+        }
+
+        fn set_pressure(&mut self, pressure: i16) {
+            self.pressure = pressure;
+        }
+
+        fn set_timbre(&mut self, timbre: i16) {
+            self.timbre = timbre;
+        }
+
+        fn note_off(&mut self) {
+            self.note = None;
+        }
+
+        fn is_active(&self) -> bool {
+            self.note.is_some()
+        }
+
+        fn render(&mut self, out: &mut [i16]) {
+            let level = if self.level != 0 {
+                self.level
+            } else {
+                self.velocity as i16
+            };
+            out.fill(if self.is_active() { level } else { 0 });
+        }
+    }
+
+    fn notes<const N: usize>(allocator: &mut VoiceAllocator<TestVoice, N>) -> Vec<Option<u8>> {
+        allocator.get_voices_mut().iter().map(|v| v.note).collect()
+    }
+
+    #[test]
+    fn test_note_mfreq() {
+        assert_eq!(note_mfreq(69), mHz(440_000));
+        assert_eq!(note_mfreq(81), mHz(880_000));
+        assert!(note_mfreq(60).0.abs_diff(261_626) <= 2);
+    }
+
+    #[test]
+    fn test_allocator_chord() {
+        let mut allocator = VoiceAllocator::new([(); 4].map(|_| TestVoice::default()));
+        allocator.note_on(60, 10);
+        allocator.note_on(64, 20);
+        allocator.note_on(67, 30);
+        assert_eq!(allocator.get_active_count(), 3);
+        let mut out = [0; 100];
+        allocator.render(&mut out);
+        assert!(out.iter().all(|y| *y == 60));
+
+        allocator.handle_event(NoteEvent::Off { note: 64 });
+        assert_eq!(notes(&mut allocator), [Some(60), None, Some(67), None]);
+        // Free voices are cycled, so 71 doesn't take the voice of 64
+        allocator.note_on(71, 40);
+        assert_eq!(notes(&mut allocator), [Some(60), None, Some(67), Some(71)]);
+        // Retriggering a sounding note reuses its voice
+        allocator.note_on(60, 50);
+        assert_eq!(notes(&mut allocator), [Some(60), None, Some(67), Some(71)]);
+        allocator.all_notes_off();
+        assert_eq!(allocator.get_active_count(), 0);
+    }
+
+    #[test]
+    fn test_allocator_pitch_bend() {
+        #[derive(Default)]
+        struct FreqVoice {
+            mfreq: u32,
+            active: bool,
+        }
+
+        impl Voice for FreqVoice {
+            fn note_on(&mut self, note: u8, _velocity: u8) {
+                self.set_note(note);
+                self.active = true;
+            }
+
+            fn set_note(&mut self, note: u8) {
+                self.mfreq = note_mfreq(note).0;
+            }
+
+            fn set_mfreq(&mut self, mfreq: mHz) {
+                self.mfreq = mfreq.0;
+            }
+
+            fn note_off(&mut self) {
+                self.active = false;
+            }
+
+            fn is_active(&self) -> bool {
+                self.active
+            }
+
+            fn render(&mut self, _out: &mut [i16]) {}
+        }
+
+        let mfreqs = |allocator: &mut VoiceAllocator<FreqVoice, 2>| -> Vec<u32> {
+            allocator.get_voices_mut().iter().map(|v| v.mfreq).collect()
+        };
+        // The bend resolves cents
+        let near = |a: u32, b: u32| a.abs_diff(b) <= b / 1_000;
+        let mut allocator = VoiceAllocator::new([FreqVoice::default(), FreqVoice::default()]);
+        allocator.note_on(69, 100);
+        allocator.pitch_bend(BEND_MAX);
+        let a = mfreqs(&mut allocator)[0];
+        assert!(near(a, 493_883), "{}", a);
+        // Notes started during a bend are bent as well
+        allocator.set_bend_range(12);
+        allocator.pitch_bend(-BEND_MAX - 1);
+        allocator.note_on(57, 100);
+        let (a, b) = (mfreqs(&mut allocator)[0], mfreqs(&mut allocator)[1]);
+        assert!(near(a, 220_000) && near(b, 110_000), "{} {}", a, b);
+        assert_eq!(allocator.get_pitch_bend(), -BEND_MAX - 1);
+
+        allocator.pitch_bend(0);
+        assert_eq!(mfreqs(&mut allocator), [440_000, 220_000]);
+
+        // Four octaves at most
+        allocator.set_bend_range(u8::MAX);
+        allocator.pitch_bend(BEND_MAX);
+        let a = mfreqs(&mut allocator)[0];
+        assert!(near(a, 16 * 440_000), "{}", a);
+        allocator.set_bend_range(12);
+        allocator.pitch_bend(0);
+
+        #[cfg(feature = "std")]
+        {
+            allocator.set_tuning(Tuning::edo(24));
+            assert_eq!(mfreqs(&mut allocator), [440_000, 311_127]);
+            allocator.note_off(57);
+            allocator.note_on(81, 100);
+            assert_eq!(mfreqs(&mut allocator), [440_000, 622_254]);
+        }
+    }
+
+    #[test]
+    fn test_allocator_stealing() {
+        let mut allocator = VoiceAllocator::new([(); 3].map(|_| TestVoice::default()));
+        allocator.note_on(60, 30);
+        allocator.note_on(62, 10);
+        allocator.note_on(64, 20);
+        allocator.note_on(65, 40);
+        assert_eq!(notes(&mut allocator), [Some(65), Some(62), Some(64)]);
+
+        allocator.set_policy(StealPolicy::RoundRobin);
+        allocator.note_on(67, 50);
+        assert_eq!(notes(&mut allocator), [Some(65), Some(67), Some(64)]);
+
+        allocator.set_policy(StealPolicy::Quietest);
+        let mut out = [0; 10];
+        allocator.render(&mut out);
+        allocator.note_on(69, 60);
+        assert_eq!(notes(&mut allocator), [Some(65), Some(67), Some(69)]);
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn test_allocator_parallel() {
+        use crate::synth::subtractive::SubtractiveVoice;
+
+        let voices = || core::array::from_fn::<_, 5, _>(|_| SubtractiveVoice::new());
+        let (mut serial, mut parallel) =
+            (VoiceAllocator::new(voices()), VoiceAllocator::new(voices()));
+        let mut pool = WorkerPool::new(3);
+        for note in [48, 55, 60, 64] {
+            serial.note_on(note, 100);
+            parallel.note_on(note, 100);
+        }
+        // The same samples and peaks, whatever the block length
+        for len in [1_000, 64, 7, 300, 2_500] {
+            let (mut a, mut b) = (vec![0; len], vec![0; len]);
+            serial.render(&mut a);
+            parallel.render_parallel(&mut pool, &mut b);
+            assert_eq!(a, b);
+            assert!(a.iter().any(|x| *x != 0));
+            serial.note_off(55);
+            parallel.note_off(55);
+        }
+        parallel.set_block_size(100);
+        let (mut a, mut b) = (vec![0; 500], vec![0; 500]);
+        serial.render(&mut a);
+        parallel.render_parallel(&mut pool, &mut b);
+        assert_eq!(a, b);
+        serial.set_policy(StealPolicy::Quietest);
+        parallel.set_policy(StealPolicy::Quietest);
+        for note in [67, 69] {
+            serial.note_on(note, 100);
+            parallel.note_on(note, 100);
+        }
+        assert_eq!(serial.slots.map(|s| s.note), parallel.slots.map(|s| s.note));
+    }
+}
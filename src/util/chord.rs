@@ -0,0 +1,180 @@
+// Chords of triads and sevenths, their inversions and the chords of a
+// scale.
+
+use crate::util::note::Note;
+use crate::util::scale::Scale;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+
+/// Most notes of a chord
+pub const CHORD_MAX: usize = 4;
+
+/// Quality of a chord
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Major7,
+    Minor7,
+    Dominant7,
+    HalfDiminished7,
+    Diminished7,
+}
+
+impl ChordQuality {
+    /// Returns the semitones of the notes above the root.
+    pub const fn get_steps(self) -> &'static [u8] {
+        match self {
+            ChordQuality::Major => &[0, 4, 7],
+            ChordQuality::Minor => &[0, 3, 7],
+            ChordQuality::Diminished => &[0, 3, 6],
+            ChordQuality::Augmented => &[0, 4, 8],
+            ChordQuality::Sus2 => &[0, 2, 7],
+            ChordQuality::Sus4 => &[0, 5, 7],
+            ChordQuality::Major7 => &[0, 4, 7, 11],
+            ChordQuality::Minor7 => &[0, 3, 7, 10],
+            ChordQuality::Dominant7 => &[0, 4, 7, 10],
+            ChordQuality::HalfDiminished7 => &[0, 3, 6, 10],
+            ChordQuality::Diminished7 => &[0, 3, 6, 9],
+        }
+    }
+}
+
+/// Notes of a chord from low to high
+///
+/// ```
+/// use isopod::util::chord::{Chord, ChordQuality};
+/// use isopod::util::note::Note;
+///
+/// let chord = Chord::new(Note::C4, ChordQuality::Major).inversion(1);
+/// assert_eq!(*chord, [Note::E4, Note::G4, Note::C5]);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Chord {
+    notes: [Note; CHORD_MAX],
+    len: usize,
+}
+
+impl Chord {
+    /// Chord of `quality` in root position. Notes above [Note::MAX]
+    /// saturate.
+    pub fn new(root: Note, quality: ChordQuality) -> Self {
+        Self::from_steps(root, quality.get_steps())
+    }
+
+    /// Chord of the notes `steps` semitones above `root`, up to
+    /// [CHORD_MAX] notes.
+    pub fn from_steps(root: Note, steps: &[u8]) -> Self {
+        let mut notes = [root; CHORD_MAX];
+        let len = steps.len().min(CHORD_MAX);
+        for (note, step) in notes.iter_mut().zip(steps.iter()) {
+            *note = root.transpose(*step as i32);
+        }
+        let mut chord = Self { notes, len };
+        chord.notes[..len].sort_unstable();
+        chord
+    }
+
+    /// Chord of `size` notes stacked in thirds along `scale` on `tonic`,
+    /// starting at `degree`. E.g. degree 4 of a major scale with 4 notes is
+    /// the dominant seventh chord.
+    pub fn diatonic(scale: Scale, tonic: Note, degree: i32, size: usize) -> Self {
+        let mut notes = [tonic; CHORD_MAX];
+        let len = size.min(CHORD_MAX);
+        for (i, note) in notes[..len].iter_mut().enumerate() {
+            *note = scale.degree(tonic, degree + 2 * i as i32);
+        }
+        Self { notes, len }
+    }
+
+    /// Returns the `n`-th inversion, where the lowest notes move up an
+    /// octave. Inversions beyond the number of notes continue an octave
+    /// higher.
+    pub fn inversion(mut self, n: usize) -> Self {
+        for _ in 0..n {
+            if self.len == 0 {
+                break;
+            }
+            let lowest = self.notes[0];
+            self.notes.copy_within(1..self.len, 0);
+            self.notes[self.len - 1] = lowest.transpose(12);
+        }
+        self
+    }
+
+    /// Returns the chord moved by `semitones`, saturating like
+    /// [Note::transpose].
+    pub fn transpose(mut self, semitones: i32) -> Self {
+        for note in self.notes[..self.len].iter_mut() {
+            *note = note.transpose(semitones);
+        }
+        self
+    }
+}
+
+impl Deref for Chord {
+    type Target = [Note];
+
+    fn deref(&self) -> &[Note] {
+        &self.notes[..self.len]
+    }
+}
+
+// Only the used notes count
+impl PartialEq for Chord {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Chord {}
+
+impl Hash for Chord {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chord() {
+        let c7 = Chord::new(Note::C4, ChordQuality::Dominant7);
+        assert_eq!(*c7, [Note::C4, Note::E4, Note::G4, Note::As4]);
+        assert_eq!(*c7.inversion(2), [Note::G4, Note::As4, Note::C5, Note::E5]);
+        // A full round of inversions is an octave
+        assert_eq!(c7.inversion(4), c7.transpose(12));
+        assert_eq!(
+            *Chord::new(Note::B3, ChordQuality::Diminished),
+            [Note::B3, Note::D4, Note::F4]
+        );
+        // Steps are sorted and limited to CHORD_MAX notes
+        assert_eq!(
+            *Chord::from_steps(Note::C4, &[7, 0, 4, 14, 17]),
+            [Note::C4, Note::E4, Note::G4, Note::D5]
+        );
+    }
+
+    #[test]
+    fn test_diatonic_chords() {
+        let chord = |degree, size| Chord::diatonic(Scale::MAJOR, Note::C4, degree, size);
+        assert_eq!(chord(0, 3), Chord::new(Note::C4, ChordQuality::Major));
+        assert_eq!(chord(1, 3), Chord::new(Note::D4, ChordQuality::Minor));
+        assert_eq!(chord(4, 4), Chord::new(Note::G4, ChordQuality::Dominant7));
+        assert_eq!(
+            chord(6, 4),
+            Chord::new(Note::B4, ChordQuality::HalfDiminished7)
+        );
+        assert_eq!(
+            Chord::diatonic(Scale::HARMONIC_MINOR, Note::A3, 4, 3),
+            Chord::new(Note::E4, ChordQuality::Major)
+        );
+    }
+}
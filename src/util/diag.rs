@@ -0,0 +1,186 @@
+// Counters of clipping, overflows and filter instabilities, so patches can
+// be debugged on hardware without a debugger.
+//
+// The counters only exist with the `diagnostics` feature. Without it, the
+// helpers compile to their plain saturating versions.
+
+use crate::util::sample::SampleType;
+use core::fmt;
+#[cfg(feature = "diagnostics")]
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Kind of an event counted by the diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A result was clamped to the range of its sample type.
+    Clip,
+    /// A sum of i16 signals saturated.
+    Overflow,
+    /// A filter state saturated, which means it is about to self-oscillate
+    /// or diverge.
+    Unstable,
+}
+
+/// Event counts since the last [take]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Report {
+    pub clips: u32,
+    pub overflows: u32,
+    pub instabilities: u32,
+}
+
+impl Report {
+    /// True if no event was counted.
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "clips: {}, overflows: {}, instabilities: {}",
+            self.clips, self.overflows, self.instabilities
+        )
+    }
+}
+
+#[cfg(feature = "diagnostics")]
+static COUNTS: [AtomicU32; 3] = [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)];
+
+/// Counts `event`. Does nothing without the `diagnostics` feature.
+#[inline(always)]
+pub fn record(event: Event) {
+    #[cfg(feature = "diagnostics")]
+    COUNTS[event as usize].fetch_add(1, Ordering::Relaxed);
+    #[cfg(not(feature = "diagnostics"))]
+    let _ = event;
+}
+
+/// Returns the counts without resetting them.
+pub fn peek() -> Report {
+    #[cfg(feature = "diagnostics")]
+    {
+        let count = |event: Event| COUNTS[event as usize].load(Ordering::Relaxed);
+        Report {
+            clips: count(Event::Clip),
+            overflows: count(Event::Overflow),
+            instabilities: count(Event::Unstable),
+        }
+    }
+    #[cfg(not(feature = "diagnostics"))]
+    Report::default()
+}
+
+/// Returns the counts and resets them. Call this once per block to get the
+/// events per block.
+pub fn take() -> Report {
+    #[cfg(feature = "diagnostics")]
+    {
+        let count = |event: Event| COUNTS[event as usize].swap(0, Ordering::Relaxed);
+        Report {
+            clips: count(Event::Clip),
+            overflows: count(Event::Overflow),
+            instabilities: count(Event::Unstable),
+        }
+    }
+    #[cfg(not(feature = "diagnostics"))]
+    Report::default()
+}
+
+/// Clamps `x` to the i16 range and counts a [Event::Clip] if it was out of
+/// range.
+#[inline(always)]
+pub fn clip(x: i64) -> i16 {
+    let y = x.clamp(i16::MIN as i64, i16::MAX as i64);
+    if y != x {
+        record(Event::Clip);
+    }
+    y as i16
+}
+
+/// Saturating addition that counts an [Event::Overflow] if it saturated.
+#[inline(always)]
+pub fn add(a: i16, b: i16) -> i16 {
+    a.checked_add(b).unwrap_or_else(|| {
+        record(Event::Overflow);
+        a.saturating_add(b)
+    })
+}
+
+/// Saturating subtraction that counts an [Event::Overflow] if it saturated.
+#[inline(always)]
+pub fn sub(a: i16, b: i16) -> i16 {
+    a.checked_sub(b).unwrap_or_else(|| {
+        record(Event::Overflow);
+        a.saturating_sub(b)
+    })
+}
+
+/// Saturating addition of a filter state that counts an [Event::Unstable]
+/// if it saturated.
+#[inline(always)]
+pub fn state_add<S: SampleType>(a: S, b: S) -> S {
+    a.checked_add(b).unwrap_or_else(|| {
+        record(Event::Unstable);
+        a.saturating_add(b)
+    })
+}
+
+/// Saturating subtraction counterpart of [state_add].
+#[inline(always)]
+pub fn state_sub<S: SampleType>(a: S, b: S) -> S {
+    a.checked_sub(b).unwrap_or_else(|| {
+        record(Event::Unstable);
+        a.saturating_sub(b)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diag_helpers() {
+        assert_eq!(clip(40_000), i16::MAX);
+        assert_eq!(clip(-40_000), i16::MIN);
+        assert_eq!(clip(-5), -5);
+        assert_eq!(add(i16::MAX, 1), i16::MAX);
+        assert_eq!(state_sub(i16::MIN, 1), i16::MIN);
+        assert_eq!(
+            Report {
+                clips: 1,
+                overflows: 2,
+                instabilities: 3
+            }
+            .to_string(),
+            "clips: 1, overflows: 2, instabilities: 3"
+        );
+    }
+
+    /// The only test that resets the counters, since they are global
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn test_diag_counts() {
+        use crate::fx::filter::{StateVariableFilter, Q_MAX};
+        use crate::util::units::{Frequency, Hz};
+
+        take();
+        clip(40_000);
+        add(i16::MIN, -1);
+        let report = peek();
+        assert!(report.clips >= 1 && report.overflows >= 1);
+
+        // A full resonance filter driven by a square saturates its states
+        let mut filter = StateVariableFilter::new();
+        filter.set_mfreq(Hz(1_000).to_mHz());
+        filter.set_q(Q_MAX);
+        for n in 0..4_000 {
+            filter.feed(if n % 44 < 22 { i16::MAX } else { -i16::MAX });
+        }
+        let report = take();
+        assert!(report.instabilities > 0, "{}", report);
+        assert!(!report.is_clean());
+    }
+}
@@ -0,0 +1,171 @@
+// Dither and noise shaping for reducing the bit depth of samples.
+
+use crate::util::sample::SampleType;
+
+/// Spectral shape of the requantization noise of a [Dither]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseShaping {
+    /// White noise
+    Off,
+    /// Noise rising by 6 dB per octave, out of the sensitive midrange
+    FirstOrder,
+    /// Noise rising by 12 dB per octave
+    SecondOrder,
+}
+
+/// TPDF dither with optional noise shaping
+///
+/// Before dropping low bits, triangular noise of ±1 LSB of the target is
+/// added. This turns the truncation distortion into a constant noise floor,
+/// so details below the last bit such as reverb tails stay audible instead
+/// of being cut off. Noise shaping feeds the requantization error back to
+/// move the noise to high frequencies.
+///
+/// ```
+/// use isopod::util::dither::Dither;
+///
+/// let mut dither = Dither::new();
+/// // A quarter of an i16 LSB is lost by truncation, but kept on average
+/// let sum: i32 = (0..4_000).map(|_| dither.process(1_i32 << 14) as i32).sum();
+/// assert!((sum - 1_000).abs() < 200);
+/// ```
+pub struct Dither {
+    rng: u64,
+    shaping: NoiseShaping,
+    error: [i64; 2],
+}
+
+impl Dither {
+    pub fn new() -> Self {
+        Self {
+            rng: 0x853C_49E6_748F_EA9B,
+            shaping: NoiseShaping::Off,
+            error: [0; 2],
+        }
+    }
+
+    /// Returns the next uniform random value from 0 to 2^`bits` - 1.
+    #[inline]
+    fn uniform(&mut self, bits: u32) -> i64 {
+        // The high bits of an LCG are sufficiently random, unlike the
+        // successive values of an LFSR
+        self.rng = self
+            .rng
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (self.rng >> (64 - bits)) as i64
+    }
+
+    /// Drops the low `shift` bits of `x` and clamps the result to the range
+    /// of `min` to `max`.
+    #[inline]
+    fn reduce(&mut self, x: i64, shift: u32, min: i64, max: i64) -> i64 {
+        let lsb = 1_i64 << shift;
+        let feedback = match self.shaping {
+            NoiseShaping::Off => 0,
+            NoiseShaping::FirstOrder => self.error[0],
+            NoiseShaping::SecondOrder => 2 * self.error[0] - self.error[1],
+        };
+        let v = x - feedback;
+        let noise = self.uniform(shift) + self.uniform(shift) - (lsb - 1);
+        let y = (v + noise + lsb / 2) >> shift;
+        self.error = [(y << shift) - v, self.error[0]];
+        y.clamp(min, max)
+    }
+
+    /// Reduces `input` to i16 with dither.
+    #[inline]
+    pub fn process<S: SampleType>(&mut self, input: S) -> i16 {
+        self.reduce(input.to_i32() as i64, 16, i16::MIN as i64, i16::MAX as i64) as i16
+    }
+
+    /// Fills `out` with the dithered `input`.
+    pub fn render<S: SampleType>(&mut self, input: &[S], out: &mut [i16]) {
+        for (y, x) in out.iter_mut().zip(input) {
+            *y = self.process(*x);
+        }
+    }
+
+    /// Reduces `input` to its upper `bits` from 1 to 16 with dither. The
+    /// result stays at the full scale of i16.
+    #[inline]
+    pub fn quantize(&mut self, input: i16, bits: u32) -> i16 {
+        let shift = 16 - bits.clamp(1, 16);
+        if shift == 0 {
+            return input;
+        }
+        let (min, max) = (i16::MIN as i64 >> shift, i16::MAX as i64 >> shift);
+        (self.reduce(input as i64, shift, min, max) << shift) as i16
+    }
+
+    pub fn set_shaping(&mut self, shaping: NoiseShaping) {
+        self.shaping = shaping;
+        self.error = [0; 2];
+    }
+
+    pub fn get_shaping(&self) -> NoiseShaping {
+        self.shaping
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dither_tpdf() {
+        let mut dither = Dither::new();
+        let mut out = vec![0; 10_000];
+        dither.render(&vec![0_i32; 10_000], &mut out);
+        // Silence turns into noise of at most ±1 LSB around 0
+        assert!(out.iter().all(|y| y.abs() <= 1));
+        assert!(out.iter().sum::<i16>().abs() < 200);
+        assert!(out.iter().filter(|y| **y != 0).count() > 1_000);
+
+        // Full scale saturates, i16 and f32 inputs are reduced the same way
+        assert_eq!(dither.process(i32::MAX), i16::MAX);
+        assert_eq!(dither.process(i16::MIN), i16::MIN);
+        assert!((dither.process(0.5_f32) - 16_384).abs() <= 1);
+    }
+
+    #[test]
+    fn test_dither_noise_shaping() {
+        // Error summed over blocks, i.e. its low frequency part
+        let low_error = |shaping| {
+            let mut dither = Dither::new();
+            dither.set_shaping(shaping);
+            let input: Vec<i32> = (0..8_192).map(|n| n * 1_237 % 65_536 - 32_768).collect();
+            let mut out = vec![0; input.len()];
+            dither.render(&input, &mut out);
+            let errors: Vec<i64> = input
+                .iter()
+                .zip(&out)
+                .map(|(x, y)| ((*y as i64) << 16) - *x as i64)
+                .collect();
+            errors
+                .chunks(32)
+                .map(|c| c.iter().sum::<i64>().abs())
+                .sum::<i64>()
+        };
+        let off = low_error(NoiseShaping::Off);
+        assert!(low_error(NoiseShaping::FirstOrder) < off / 2);
+        assert!(low_error(NoiseShaping::SecondOrder) < off / 2);
+    }
+
+    #[test]
+    fn test_dither_quantize() {
+        let mut dither = Dither::new();
+        assert_eq!(dither.quantize(1_234, 16), 1_234);
+        for x in [-20_000, -5, 0, 300, i16::MAX] {
+            let y = dither.quantize(x, 4);
+            assert_eq!(y % 4_096, 0);
+            assert!((y as i32 - x as i32).abs() <= 2 * 4_096, "{} {}", x, y);
+        }
+    }
+}
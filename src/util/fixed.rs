@@ -0,0 +1,277 @@
+// Fixed-point fractions and arithmetic.
+
+use core::ops::{Add, Mul, Neg, Shl, Shr, Sub};
+
+/// Returns `num / den` as a fixed-point number with `frac` fractional bits,
+/// or u64::MAX if `den` is 0.
+/// ```
+/// # use isopod::util::fixed::*;
+/// assert_eq!(div_frac(3, 4, 12), 3_072);
+/// ```
+#[inline]
+pub const fn div_frac(num: u64, den: u64, frac: u32) -> u64 {
+    match (num << frac).checked_div(den) {
+        Some(q) => q,
+        None => u64::MAX,
+    }
+}
+
+/// Multiplies `a` by the fixed-point number `b` with `frac` fractional bits,
+/// rounding down.
+/// ```
+/// # use isopod::util::fixed::*;
+/// assert_eq!(mul_frac(1_000, 3_072, 12), 750);
+/// ```
+#[inline]
+pub const fn mul_frac(a: u64, b: u64, frac: u32) -> u64 {
+    (a * b) >> frac
+}
+
+// 48/17 and 32/17 in Q30, the best linear guess of 1/m for m in [0.5, 1)
+const RECIP_C1: i64 = 3_031_741_621;
+const RECIP_C2: i64 = 2_021_161_081;
+
+/// Returns 1/`x` with `frac` fractional bits for `x` with `frac` fractional
+/// bits from 1 to 2^31, without divisions.
+fn recip(x: u64, frac: u32) -> i64 {
+    if x == 1 << frac {
+        return 1 << frac;
+    }
+    // Normalize x = m * 2^-s with m in [0.5, 1) in Q31
+    let m = x << (31 - frac);
+    let s = m.leading_zeros() - 33;
+    let m = (m << s) as i64;
+    // Three Newton-Raphson iterations of y = y * (2 - m * y) in Q30
+    let mut y = RECIP_C1 - ((RECIP_C2 * m) >> 31);
+    for _ in 0..3 {
+        let t = (m * y) >> 31;
+        y = (y * ((2 << 30) - t)) >> 30;
+    }
+    let shift = s + frac;
+    if shift >= 30 {
+        y << (shift - 30)
+    } else {
+        y >> (30 - shift)
+    }
+}
+
+macro_rules! impl_fixed {
+    ($name:ident, $repr:ty, $frac:expr, $acc_frac:expr) => {
+        impl $name {
+            /// Largest value, just below 1
+            pub const ONE: $name = $name(<$repr>::MAX);
+            pub const ZERO: $name = $name(0);
+            /// Number of fractional bits
+            pub const FRAC: u32 = $frac;
+            /// Number of fractional bits of accumulators, see
+            #[doc = concat!("[", stringify!($name), "::mac]")]
+            pub const ACC_FRAC: u32 = $acc_frac;
+
+            /// Returns `num / den`, saturated to the range of -1 to 1. A
+            /// zero `den` saturates in the direction of `num`.
+            pub const fn from_ratio(num: i64, den: i64) -> Self {
+                let (num, den) = if den < 0 { (-num, -den) } else { (num, den) };
+                let q = match (num << $frac).checked_div(den) {
+                    Some(q) => q,
+                    None if num < 0 => i64::MIN,
+                    None => i64::MAX,
+                };
+                Self(if q > <$repr>::MAX as i64 {
+                    <$repr>::MAX
+                } else if q < <$repr>::MIN as i64 {
+                    <$repr>::MIN
+                } else {
+                    q as $repr
+                })
+            }
+
+            #[inline(always)]
+            fn saturate(x: i64) -> Self {
+                Self(x.clamp(<$repr>::MIN as i64, <$repr>::MAX as i64) as $repr)
+            }
+
+            /// Returns `acc` plus the product of `a` and `b` with
+            #[doc = concat!("[", stringify!($name), "::ACC_FRAC]")]
+            /// fractional bits. The accumulator has enough headroom for
+            /// long sums, e.g. of FIR filter taps, which are rounded only
+            /// once by
+            #[doc = concat!("[", stringify!($name), "::from_acc].")]
+            #[inline(always)]
+            pub fn mac(acc: i64, a: Self, b: Self) -> i64 {
+                acc + ((a.0 as i64 * b.0 as i64) >> (2 * $frac - $acc_frac))
+            }
+
+            /// Rounds and saturates an accumulator of
+            #[doc = concat!("[", stringify!($name), "::mac].")]
+            #[inline(always)]
+            pub fn from_acc(acc: i64) -> Self {
+                let shift = $acc_frac - $frac;
+                Self::saturate((acc + (1 << (shift - 1))) >> shift)
+            }
+
+            /// Returns 1/`self` with the same fractional bits, but with an
+            /// integer part, approximated by Newton-Raphson iterations
+            /// without a division. The reciprocal of 0 saturates.
+            pub fn recip(self) -> i64 {
+                match self.0 {
+                    0 => i64::MAX,
+                    x if x < 0 => -recip((x as i64).unsigned_abs(), $frac),
+                    x => recip(x as u64, $frac),
+                }
+            }
+        }
+
+        /// Product rounded to the nearest value and saturated.
+        impl Mul for $name {
+            type Output = $name;
+
+            #[inline(always)]
+            fn mul(self, rhs: $name) -> $name {
+                Self::saturate((self.0 as i64 * rhs.0 as i64 + (1 << ($frac - 1))) >> $frac)
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+
+            #[inline(always)]
+            fn add(self, rhs: $name) -> $name {
+                Self(self.0.saturating_add(rhs.0))
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+
+            #[inline(always)]
+            fn sub(self, rhs: $name) -> $name {
+                Self(self.0.saturating_sub(rhs.0))
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+
+            #[inline(always)]
+            fn neg(self) -> $name {
+                Self(self.0.saturating_neg())
+            }
+        }
+
+        /// Multiplication by 2^`rhs` with saturation.
+        impl Shl<u32> for $name {
+            type Output = $name;
+
+            #[inline(always)]
+            fn shl(self, rhs: u32) -> $name {
+                Self::saturate((self.0 as i64) << rhs.min(32))
+            }
+        }
+
+        /// Division by 2^`rhs`, rounding down.
+        impl Shr<u32> for $name {
+            type Output = $name;
+
+            #[inline(always)]
+            fn shr(self, rhs: u32) -> $name {
+                Self(self.0 >> rhs.min(<$repr>::BITS - 1))
+            }
+        }
+    };
+}
+
+/// Fraction from -1 to 1 in 16 bits with 15 fractional bits
+///
+/// All operations saturate instead of overflowing.
+/// ```
+/// # use isopod::util::fixed::*;
+/// let half = Q15::from_ratio(1, 2);
+/// assert_eq!(half, Q15(16_384));
+/// assert_eq!(half * half, Q15(8_192));
+/// assert_eq!(half + half, Q15::ONE);
+/// assert_eq!(Q15(-3) >> 1, Q15(-2));
+/// assert_eq!(half << 2, Q15::ONE);
+/// // 1/0.25 = 4
+/// assert_eq!(Q15::from_ratio(1, 4).recip(), 4 << 15);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Q15(pub i16);
+
+impl_fixed!(Q15, i16, 15, 30);
+
+/// Fraction from -1 to 1 in 32 bits with 31 fractional bits
+///
+/// See [Q15]. The accumulator of [Q31::mac] keeps 16 bits of headroom.
+/// ```
+/// # use isopod::util::fixed::*;
+/// let half = Q31::from_ratio(1, 2);
+/// assert_eq!(half * -half, Q31::from_ratio(-1, 4));
+/// let acc = Q31::mac(Q31::mac(0, half, half), half, half);
+/// assert_eq!(Q31::from_acc(acc), half);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Q31(pub i32);
+
+impl_fixed!(Q31, i32, 31, 47);
+
+impl From<Q15> for Q31 {
+    fn from(x: Q15) -> Self {
+        Q31((x.0 as i32) << 16)
+    }
+}
+
+/// Drops the 16 low bits, rounding down.
+impl From<Q31> for Q15 {
+    fn from(x: Q31) -> Self {
+        Q15((x.0 >> 16) as i16)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fixed_saturation() {
+        assert_eq!(Q15(i16::MIN) * Q15(i16::MIN), Q15::ONE);
+        assert_eq!(Q31(i32::MIN) * Q31(i32::MIN), Q31::ONE);
+        assert_eq!(-Q15(i16::MIN), Q15::ONE);
+        assert_eq!(Q15(-20_000) - Q15(20_000), Q15(i16::MIN));
+        assert_eq!(Q31(-5) << 40, Q31(i32::MIN));
+        assert_eq!(Q15::from_ratio(3, 2), Q15::ONE);
+        assert_eq!(Q15::from_ratio(-1, 0), Q15(i16::MIN));
+        assert_eq!(Q15::from_ratio(1, -2), Q15(-16_384));
+        assert_eq!(Q31::from(Q15(-16_384)), Q31::from_ratio(-1, 2));
+    }
+
+    #[test]
+    fn test_fixed_mac() {
+        // Sum of many taps that would saturate on the way
+        let mut acc = 0;
+        for _ in 0..1_000 {
+            acc = Q15::mac(acc, Q15(30_000), Q15(30_000));
+        }
+        for _ in 0..1_000 {
+            acc = Q15::mac(acc, Q15(-30_000), Q15(30_000));
+        }
+        acc = Q15::mac(acc, Q15(300), Q15::ONE);
+        assert_eq!(Q15::from_acc(acc), Q15(300));
+    }
+
+    #[test]
+    fn test_fixed_recip() {
+        for x in [1, 3, 100, 12_345, i16::MAX as i32] {
+            let exact = (1_i64 << 30) / x as i64;
+            let approx = Q15(x as i16).recip();
+            assert!((approx - exact).abs() <= exact / 100_000 + 1, "{}", x);
+            assert_eq!(Q15(-x as i16).recip(), -approx);
+        }
+        for x in [1, 7, 1_000_000, i32::MAX] {
+            let exact = (1_i64 << 62) / x as i64;
+            let approx = Q31(x).recip();
+            assert!((approx - exact).abs() <= exact / 100_000 + 1, "{}", x);
+        }
+        assert_eq!(Q31(0).recip(), i64::MAX);
+        assert_eq!(Q15(i16::MIN).recip(), -(1 << 15));
+    }
+}
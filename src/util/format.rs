@@ -0,0 +1,217 @@
+// Conversions between i16 samples and the formats of files, DACs and hosts.
+
+/// Converts to i8, rounding to the nearest value.
+/// ```
+/// # use isopod::util::format::*;
+/// assert_eq!(to_i8(-256), -1);
+/// assert_eq!(to_i8(i16::MAX), i8::MAX);
+/// ```
+#[inline]
+pub fn to_i8(x: i16) -> i8 {
+    ((x as i32 + 0x80) >> 8).min(i8::MAX as i32) as i8
+}
+
+#[inline]
+pub fn from_i8(x: i8) -> i16 {
+    (x as i16) << 8
+}
+
+/// Converts to offset binary u8 as in 8-bit WAV files, where 128 is
+/// silence.
+#[inline]
+pub fn to_u8(x: i16) -> u8 {
+    (to_i8(x) as u8) ^ 0x80
+}
+
+#[inline]
+pub fn from_u8(x: u8) -> i16 {
+    from_i8((x ^ 0x80) as i8)
+}
+
+/// Converts to 24 bits in the low bits of an i32.
+#[inline]
+pub fn to_i24(x: i16) -> i32 {
+    (x as i32) << 8
+}
+
+/// Converts from 24 bits in the low bits of an i32, rounding to the nearest
+/// value and clamping to the 24-bit range.
+/// ```
+/// # use isopod::util::format::*;
+/// assert_eq!(from_i24(0x7F_FFFF), i16::MAX);
+/// assert_eq!(from_i24(-0x80_0000), i16::MIN);
+/// assert_eq!(from_i24(0x180), 2);
+/// ```
+#[inline]
+pub fn from_i24(x: i32) -> i16 {
+    let x = x.clamp(-0x80_0000, 0x7F_FFFF);
+    ((x + 0x80) >> 8).min(i16::MAX as i32) as i16
+}
+
+/// Converts to 24 bits packed into three little endian bytes.
+#[inline]
+pub fn to_i24_le(x: i16) -> [u8; 3] {
+    let [b0, b1, b2, _] = to_i24(x).to_le_bytes();
+    [b0, b1, b2]
+}
+
+/// Converts from 24 bits packed into three little endian bytes.
+/// ```
+/// # use isopod::util::format::*;
+/// assert_eq!(from_i24_le(to_i24_le(-1_234)), -1_234);
+/// assert_eq!(from_i24_le([0xFF, 0xFF, 0x7F]), i16::MAX);
+/// ```
+#[inline]
+pub fn from_i24_le(bytes: [u8; 3]) -> i16 {
+    // The sign is extended by the arithmetic shift
+    from_i24(i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8)
+}
+
+#[inline]
+pub fn to_i32(x: i16) -> i32 {
+    (x as i32) << 16
+}
+
+/// Converts from i32, rounding to the nearest value.
+#[inline]
+pub fn from_i32(x: i32) -> i16 {
+    ((x as i64 + 0x8000) >> 16).min(i16::MAX as i64) as i16
+}
+
+/// Converts to f32 normalized to [-1, 1).
+#[inline]
+pub fn to_f32(x: i16) -> f32 {
+    x as f32 / 32_768.0
+}
+
+/// Converts from f32 normalized to [-1, 1], rounding to the nearest value
+/// and clamping. NaN is silence.
+/// ```
+/// # use isopod::util::format::*;
+/// assert_eq!(from_f32(0.5), 16_384);
+/// assert_eq!(from_f32(-1.5), i16::MIN);
+/// assert_eq!(from_f32(1.0), i16::MAX);
+/// assert_eq!(from_f32(f32::NAN), 0);
+/// ```
+#[inline]
+pub fn from_f32(x: f32) -> i16 {
+    let y = x * 32_768.0;
+    // Float to int casts saturate and map NaN to 0
+    (if y < 0.0 { y - 0.5 } else { y + 0.5 }) as i16
+}
+
+/// Binary sample format of interleaved little endian data, e.g. in WAV
+/// files or DMA buffers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Offset binary 8 bits
+    U8,
+    I16,
+    /// Packed 24 bits
+    I24,
+    I32,
+    /// Normalized to [-1, 1]
+    F32,
+}
+
+impl SampleFormat {
+    /// Number of bytes per sample
+    pub const fn bytes(&self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::I16 => 2,
+            SampleFormat::I24 => 3,
+            SampleFormat::I32 | SampleFormat::F32 => 4,
+        }
+    }
+
+    /// Number of bits per sample
+    pub const fn bits(&self) -> u16 {
+        self.bytes() as u16 * 8
+    }
+
+    /// Writes `samples` to `out` in this format and returns the number of
+    /// samples written, which is limited by the length of `out`.
+    /// ```
+    /// # use isopod::util::format::*;
+    /// let mut out = [0; 6];
+    /// assert_eq!(SampleFormat::I24.encode(&[1, -1], &mut out), 2);
+    /// assert_eq!(out, [0x00, 0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    /// ```
+    pub fn encode(&self, samples: &[i16], out: &mut [u8]) -> usize {
+        let n = samples.len().min(out.len() / self.bytes());
+        for (x, y) in samples.iter().zip(out.chunks_exact_mut(self.bytes())) {
+            match self {
+                SampleFormat::U8 => y[0] = to_u8(*x),
+                SampleFormat::I16 => y.copy_from_slice(&x.to_le_bytes()),
+                SampleFormat::I24 => y.copy_from_slice(&to_i24_le(*x)),
+                SampleFormat::I32 => y.copy_from_slice(&to_i32(*x).to_le_bytes()),
+                SampleFormat::F32 => y.copy_from_slice(&to_f32(*x).to_le_bytes()),
+            }
+        }
+        n
+    }
+
+    /// Reads samples in this format from `bytes` into `out` and returns the
+    /// number of samples read, which is limited by the length of `out`.
+    /// Trailing bytes of an incomplete sample are ignored.
+    pub fn decode(&self, bytes: &[u8], out: &mut [i16]) -> usize {
+        let n = out.len().min(bytes.len() / self.bytes());
+        for (x, y) in bytes.chunks_exact(self.bytes()).zip(out.iter_mut()) {
+            *y = match self {
+                SampleFormat::U8 => from_u8(x[0]),
+                SampleFormat::I16 => i16::from_le_bytes([x[0], x[1]]),
+                SampleFormat::I24 => from_i24_le([x[0], x[1], x[2]]),
+                SampleFormat::I32 => from_i32(i32::from_le_bytes([x[0], x[1], x[2], x[3]])),
+                SampleFormat::F32 => from_f32(f32::from_le_bytes([x[0], x[1], x[2], x[3]])),
+            };
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_scalar() {
+        for x in [i16::MIN, -12_345, -1, 0, 1, 777, i16::MAX] {
+            assert_eq!(from_i24(to_i24(x)), x);
+            assert_eq!(from_i32(to_i32(x)), x);
+            assert_eq!(from_f32(to_f32(x)), x);
+            assert!((from_i8(to_i8(x)) as i32 - x as i32).abs() < 256);
+            assert_eq!(from_u8(to_u8(x)), from_i8(to_i8(x)));
+        }
+        assert_eq!(to_u8(0), 128);
+        assert_eq!(to_u8(i16::MIN), 0);
+        assert_eq!(from_i32(i32::MAX), i16::MAX);
+        assert_eq!(from_i32(0x1_8000), 2);
+        assert_eq!(from_f32(f32::INFINITY), i16::MAX);
+    }
+
+    #[test]
+    fn test_format_block() {
+        let samples = [i16::MIN, -300, 0, 5, i16::MAX];
+        for format in [
+            SampleFormat::U8,
+            SampleFormat::I16,
+            SampleFormat::I24,
+            SampleFormat::I32,
+            SampleFormat::F32,
+        ] {
+            let mut bytes = vec![0; samples.len() * format.bytes()];
+            assert_eq!(format.encode(&samples, &mut bytes), samples.len());
+            let mut out = [0; 8];
+            assert_eq!(format.decode(&bytes, &mut out), samples.len());
+            for (x, y) in samples.iter().zip(out) {
+                let tolerance = if format == SampleFormat::U8 { 255 } else { 0 };
+                assert!((*x as i32 - y as i32).abs() <= tolerance, "{:?}", format);
+            }
+        }
+        // Short buffers limit the count, incomplete samples are ignored
+        let mut bytes = [0; 5];
+        assert_eq!(SampleFormat::I16.encode(&samples, &mut bytes), 2);
+        assert_eq!(SampleFormat::I16.decode(&bytes, &mut [0; 8]), 2);
+    }
+}
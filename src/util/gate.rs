@@ -0,0 +1,160 @@
+// Trigger and gate signals, which carry rhythm from sequencers to envelopes
+// and one-shot oscillators without notes.
+
+use crate::env::adsr::Adsr;
+use crate::osc::wavetable::ExpDecay;
+use crate::synth::NoteEvent;
+
+/// Start of a one-shot, e.g. a drum hit or a decay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trigger {
+    /// Strength from 1 to 127 like a MIDI velocity
+    pub velocity: u8,
+}
+
+impl Default for Trigger {
+    fn default() -> Self {
+        Self { velocity: 127 }
+    }
+}
+
+/// Edge of a gate signal
+///
+/// A gate is high while a note is held. Rising edges also trigger, so
+/// consumers of triggers follow gates as well.
+///
+/// ```
+/// use isopod::osc::wavetable::ExpDecay;
+/// use isopod::seq::euclid::Euclid;
+/// use isopod::util::gate::{Trigger, Triggered};
+///
+/// let mut euclid = Euclid::new(3, 8);
+/// let mut decay = ExpDecay::new();
+/// if euclid.tick() {
+///     decay.trigger(Trigger::default());
+/// }
+/// assert!(decay.is_running());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Gate {
+    /// The gate opens with `velocity` from 1 to 127.
+    On { velocity: u8 },
+    /// The gate closes.
+    Off,
+}
+
+impl Gate {
+    /// Returns the trigger of a rising edge.
+    pub fn get_trigger(self) -> Option<Trigger> {
+        match self {
+            Gate::On { velocity } => Some(Trigger { velocity }),
+            Gate::Off => None,
+        }
+    }
+
+    pub fn is_on(self) -> bool {
+        matches!(self, Gate::On { .. })
+    }
+}
+
+impl From<NoteEvent> for Gate {
+    /// Drops the note, where a note on with a velocity of 0 closes the
+    /// gate like a note off.
+    fn from(event: NoteEvent) -> Self {
+        match event {
+            NoteEvent::On { velocity: 0, .. } | NoteEvent::Off { .. } => Gate::Off,
+            NoteEvent::On { velocity, .. } => Gate::On { velocity },
+        }
+    }
+}
+
+impl From<Trigger> for Gate {
+    fn from(trigger: Trigger) -> Self {
+        Gate::On {
+            velocity: trigger.velocity,
+        }
+    }
+}
+
+/// Consumer of triggers
+pub trait Triggered {
+    /// Starts from the beginning.
+    fn trigger(&mut self, trigger: Trigger);
+
+    /// Triggers on the rising edges of `gate`.
+    fn gate(&mut self, gate: Gate) {
+        if let Some(trigger) = gate.get_trigger() {
+            self.trigger(trigger);
+        }
+    }
+}
+
+/// Consumer of gates, which also follows the falling edges
+pub trait Gated {
+    fn gate(&mut self, gate: Gate);
+}
+
+impl Triggered for ExpDecay {
+    /// Restarts the decay. The velocity is left to the consumer of the
+    /// decay, e.g. a [crate::fx::gain::Gain].
+    fn trigger(&mut self, _trigger: Trigger) {
+        self.reset_and_start();
+    }
+}
+
+impl Gated for Adsr {
+    /// Starts the attack on rising and the release on falling edges.
+    fn gate(&mut self, gate: Gate) {
+        match gate {
+            Gate::On { .. } => self.gate_on(),
+            Gate::Off => self.gate_off(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::env::adsr::AdsrStage;
+    use crate::seq::step::{Step, StepSequencer};
+
+    #[test]
+    fn test_gate_from_sequencer() {
+        let mut seq = StepSequencer::new();
+        seq.set_step(0, Step::note(36, 90));
+        let mut gates = Vec::new();
+        for _ in 0..6 {
+            seq.tick(|event| gates.push(Gate::from(event)));
+        }
+        assert_eq!(gates, [Gate::On { velocity: 90 }, Gate::Off]);
+        assert_eq!(gates[0].get_trigger(), Some(Trigger { velocity: 90 }));
+        assert_eq!(gates[1].get_trigger(), None);
+        assert_eq!(
+            Gate::from(NoteEvent::On {
+                note: 36,
+                velocity: 0
+            }),
+            Gate::Off
+        );
+    }
+
+    #[test]
+    fn test_gate_consumers() {
+        let mut decay = ExpDecay::new();
+        assert_eq!(decay.next(), None);
+        Triggered::gate(&mut decay, Gate::Off);
+        assert!(!decay.is_running());
+        decay.trigger(Trigger::default());
+        let first = decay.next().unwrap();
+        assert!(decay.next().unwrap() <= first);
+
+        let mut adsr = Adsr::new();
+        adsr.gate(Gate::On { velocity: 100 });
+        assert_eq!(adsr.get_stage(), AdsrStage::Attack);
+        adsr.render(&mut [0; 64]);
+        adsr.gate(Gate::Off);
+        assert_eq!(adsr.get_stage(), AdsrStage::Release);
+    }
+}
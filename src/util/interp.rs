@@ -0,0 +1,151 @@
+// Interpolation between samples at fractional positions.
+
+use crate::util::diag;
+use crate::util::fixed::Q15;
+use crate::util::sample::SampleType;
+
+/// Fractional bits of the positions between two samples
+pub const FRAC_BITS: u32 = 16;
+/// Position of the next sample
+pub const FRAC_ONE: u32 = 1 << FRAC_BITS;
+
+/// Interpolates linearly from `a` at `frac` = 0 to `b` at [FRAC_ONE].
+/// ```
+/// # use isopod::util::interp::*;
+/// assert_eq!(linear(100_i16, 200, FRAC_ONE / 4), 125);
+/// assert_eq!(linear(i16::MIN, i16::MAX, FRAC_ONE / 2), -1);
+/// assert_eq!(linear(0.0_f32, 1.0, FRAC_ONE / 2), 0.5);
+/// ```
+#[inline(always)]
+pub fn linear<S: SampleType>(a: S, b: S, frac: u32) -> S {
+    a.lerp(b, frac.min(FRAC_ONE) as i64, FRAC_BITS)
+}
+
+/// Interpolates between `x0` at `frac` = 0 and `x1` at [FRAC_ONE] with a
+/// cubic Catmull-Rom spline through the neighbours `xm1` before and `x2`
+/// after. Smoother than [linear], with less high frequency loss when
+/// pitching samples up. The result saturates on overshoots.
+/// ```
+/// # use isopod::util::interp::*;
+/// assert_eq!(cubic(0, 100, 200, 300, FRAC_ONE / 2), 150);
+/// assert_eq!(cubic(0, 1_000, 1_000, 0, FRAC_ONE / 2), 1_125);
+/// ```
+#[inline]
+pub fn cubic(xm1: i16, x0: i16, x1: i16, x2: i16, frac: u32) -> i16 {
+    let (xm1, x0, x1, x2) = (xm1 as i64, x0 as i64, x1 as i64, x2 as i64);
+    let t = frac.min(FRAC_ONE) as i64;
+    // Twice the polynomial coefficients to avoid halves
+    let c1 = x1 - xm1;
+    let c2 = 2 * xm1 - 5 * x0 + 4 * x1 - x2;
+    let c3 = x2 - xm1 + 3 * (x0 - x1);
+    let y = (((((((c3 * t) >> FRAC_BITS) + c2) * t) >> FRAC_BITS) + c1) * t) >> FRAC_BITS;
+    diag::clip(x0 + y / 2)
+}
+
+/// First order allpass fractional delay
+///
+/// Delays a signal by a fraction of a sample with a flat magnitude
+/// response, which keeps the high frequencies in the feedback loops of
+/// delays, chorus and physical models where [linear] would dull them. The
+/// phase delay is exact at low frequencies. Since the interpolator has a
+/// state, it suits a fixed or slowly changing delay.
+///
+/// ```
+/// use isopod::util::interp::{Allpass, FRAC_ONE};
+///
+/// let mut allpass = Allpass::new();
+/// allpass.set_frac(FRAC_ONE / 2);
+/// // Steps settle at the input after the transient
+/// let mut y = 0;
+/// for _ in 0..32 {
+///     y = allpass.process(1_000, 1_000);
+/// }
+/// assert!((y - 1_000).abs() <= 1);
+/// ```
+pub struct Allpass {
+    coeff: Q15,
+    y1: i16,
+}
+
+impl Allpass {
+    pub fn new() -> Self {
+        Self {
+            coeff: Q15::ONE,
+            y1: 0,
+        }
+    }
+
+    /// Sets the delay past the newer sample from 0 to [FRAC_ONE] samples.
+    pub fn set_frac(&mut self, frac: u32) {
+        let frac = frac.min(FRAC_ONE) as i64;
+        self.coeff = Q15::from_ratio(FRAC_ONE as i64 - frac, FRAC_ONE as i64 + frac);
+    }
+
+    /// Returns the next sample between the newer sample `x0` and the older
+    /// sample `x1`, which was `x0` in the previous call.
+    #[inline]
+    pub fn process(&mut self, x0: i16, x1: i16) -> i16 {
+        let y = x1 as i64 + ((self.coeff.0 as i64 * (x0 as i64 - self.y1 as i64)) >> 15);
+        self.y1 = diag::clip(y);
+        self.y1
+    }
+
+    /// Clears the state, e.g. after a jump of the read position.
+    pub fn reset(&mut self) {
+        self.y1 = 0;
+    }
+}
+
+impl Default for Allpass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::osc::luts::SINE_I16;
+
+    #[test]
+    fn test_interp_ends() {
+        for (a, b) in [(-5_i16, 7), (i16::MIN, i16::MAX), (30_000, -30_000)] {
+            assert_eq!(linear(a, b, 0), a);
+            assert_eq!(linear(a, b, FRAC_ONE), b);
+            assert_eq!(linear(a as i32, b as i32, FRAC_ONE), b as i32);
+            assert_eq!(cubic(0, a, b, 0, 0), a);
+            assert_eq!(cubic(0, a, b, 0, FRAC_ONE), b);
+        }
+    }
+
+    #[test]
+    fn test_interp_cubic_sine() {
+        // The spline follows a sine more closely than the chord
+        let (mut err_linear, mut err_cubic) = (0, 0);
+        let step = 64;
+        for n in (step..SINE_I16.len() - 2 * step).step_by(step) {
+            let x = |k: usize| SINE_I16[n + k * step - step];
+            let exact = SINE_I16[n + step / 2] as i32;
+            err_linear += (linear(x(1), x(2), FRAC_ONE / 2) as i32 - exact).abs();
+            err_cubic += (cubic(x(0), x(1), x(2), x(3), FRAC_ONE / 2) as i32 - exact).abs();
+        }
+        assert!(err_cubic < err_linear / 4, "{} {}", err_cubic, err_linear);
+    }
+
+    #[test]
+    fn test_interp_allpass_delay() {
+        // Half a sample delay of a slow sine lands between the samples
+        let mut allpass = Allpass::new();
+        allpass.set_frac(FRAC_ONE / 2);
+        let x = |n: usize| SINE_I16[(n * 4) % SINE_I16.len()];
+        let mut max_err = 0;
+        for n in 1..2_048 {
+            let y = allpass.process(x(n), x(n - 1)) as i32;
+            let expected = (x(n) as i32 + x(n - 1) as i32) / 2;
+            if n > 64 {
+                max_err = max_err.max((y - expected).abs());
+            }
+        }
+        assert!(max_err < 64, "{}", max_err);
+    }
+}
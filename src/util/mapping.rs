@@ -0,0 +1,175 @@
+// Mapping of knob-style control values to parameter ranges with linear,
+// exponential and logarithmic laws.
+
+use crate::util::param::ParamValue;
+use crate::util::units::{mHz, ms, ratio, Hz, SAMPLE_NORM};
+
+/// Highest MIDI controller value
+pub const CONTROL_MAX: u8 = 127;
+/// Octaves the [Curve::Logarithmic] law spans from the first step to the
+/// end of the range
+const LOG_OCTAVES: i64 = 6;
+
+/// Parameter value that can be interpolated between the ends of its range
+pub trait MapValue: ParamValue {
+    fn to_i64(self) -> i64;
+    fn from_i64(x: i64) -> Self;
+}
+
+macro_rules! map_value {
+    ($t:ty, $x:ident => $to:expr, $from:expr) => {
+        impl MapValue for $t {
+            fn to_i64(self) -> i64 {
+                let $x = self;
+                $to
+            }
+
+            fn from_i64($x: i64) -> Self {
+                $from
+            }
+        }
+    };
+}
+
+map_value!(u32, x => x as i64, x as u32);
+map_value!(i32, x => x as i64, x as i32);
+map_value!(i16, x => x as i64, x as i16);
+map_value!(bool, x => x as i64, x != 0);
+map_value!(mHz, x => x.0 as i64, mHz(x as u32));
+map_value!(Hz, x => x.0 as i64, Hz(x as u32));
+map_value!(ms, x => x.0 as i64, ms(x as u32));
+
+/// Law that maps a control position onto a parameter range
+///
+/// ```
+/// use isopod::util::mapping::Curve;
+/// use isopod::util::units::{mHz, ms};
+///
+/// // Every octave of the knob travel gets the same share
+/// let cutoff = Curve::Exponential.map_cc(64, mHz(20_000), mHz(20_000_000));
+/// assert!((600_000..700_000).contains(&cutoff.0));
+/// // Times reach 0 and most of the travel is for short times
+/// let attack = Curve::Logarithmic.map_cc(64, ms(0), ms(10_000));
+/// assert!(attack.0 < 1_500);
+/// assert_eq!(Curve::Logarithmic.map_cc(127, ms(0), ms(10_000)), ms(10_000));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Curve {
+    /// Proportional to the control position.
+    Linear,
+    /// Equal ratios for equal steps, e.g. octaves for frequencies. Ranges
+    /// with an end at or below 0 fall back to a quadratic law.
+    Exponential,
+    /// Exponential rise with a fixed curvature that starts at the low end
+    /// even if it is 0, for times.
+    Logarithmic,
+}
+
+impl Curve {
+    /// Maps `x` from 0 to [SAMPLE_NORM] onto the range from `lo` to `hi`.
+    /// `lo` may be above `hi` to invert the control.
+    pub fn map_norm<T: MapValue>(self, x: i32, lo: T, hi: T) -> T {
+        T::from_i64(self.map(x as i64, SAMPLE_NORM as i64, lo.to_i64(), hi.to_i64()))
+    }
+
+    /// Maps a MIDI controller value from 0 to [CONTROL_MAX] onto the range
+    /// from `lo` to `hi`.
+    pub fn map_cc<T: MapValue>(self, value: u8, lo: T, hi: T) -> T {
+        T::from_i64(self.map(value as i64, CONTROL_MAX as i64, lo.to_i64(), hi.to_i64()))
+    }
+
+    /// Maps `x` from 0 to `norm` onto the range from `lo` to `hi`.
+    pub(crate) fn map(self, x: i64, norm: i64, lo: i64, hi: i64) -> i64 {
+        let x = x.clamp(0, norm);
+        // The ends are exact, whatever the rounding of the laws
+        if x == 0 {
+            return lo;
+        } else if x == norm {
+            return hi;
+        }
+        let y = match self {
+            Curve::Linear => lo + (hi - lo) * x / norm,
+            Curve::Exponential if lo <= 0 || hi <= 0 => lo + (hi - lo) * x * x / (norm * norm),
+            Curve::Exponential => scale(lo, span_cents(lo, hi) * x / norm) as i64,
+            Curve::Logarithmic => {
+                let one = 1_i128 << 16;
+                let rise = scale(1 << 16, LOG_OCTAVES * 1_200 * x / norm) - one;
+                let total = (one << LOG_OCTAVES) - one;
+                lo + ((hi - lo) as i128 * rise / total) as i64
+            }
+        };
+        y.clamp(lo.min(hi), lo.max(hi))
+    }
+}
+
+/// Returns `x` raised by `cents`.
+fn scale(x: i64, cents: i64) -> i128 {
+    let octave = cents.div_euclid(1_200);
+    let y = x as i128 * ratio(cents.rem_euclid(1_200) as i32) as i128;
+    let shift = octave - 16;
+    if shift >= 0 {
+        y << shift.min(64)
+    } else {
+        y >> (-shift).min(127)
+    }
+}
+
+/// Returns the interval from `lo` to `hi` in cents, where both are above 0.
+fn span_cents(lo: i64, hi: i64) -> i64 {
+    let octaves = hi.ilog2() as i64 - lo.ilog2() as i64;
+    let (mut a, mut b) = ((octaves - 1) * 1_200, (octaves + 1) * 1_200);
+    while b - a > 1 {
+        let c = (a + b).div_euclid(2);
+        if scale(lo, c) <= hi as i128 {
+            a = c;
+        } else {
+            b = c;
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_curves() {
+        let half = SAMPLE_NORM / 2;
+        assert_eq!(Curve::Linear.map_norm(half, 0_i32, 1_000), 500);
+        assert_eq!(Curve::Linear.map_norm(half, 1_000_i32, 0), 500);
+        assert_eq!(Curve::Linear.map_cc(200, 0_i32, 10), 10);
+        assert!(Curve::Linear.map_cc(127, false, true));
+
+        // Half way between 100 Hz and 6.4 kHz is 800 Hz
+        let mfreq = Curve::Exponential.map_norm(half, mHz(100_000), mHz(6_400_000));
+        assert!(mfreq.0.abs_diff(800_000) < 500, "{}", mfreq.0);
+        let mfreq = Curve::Exponential.map_norm(half, mHz(6_400_000), mHz(100_000));
+        assert!(mfreq.0.abs_diff(800_000) < 500, "{}", mfreq.0);
+        // Each quarter of the travel is an octave
+        let quarters: Vec<u32> = (0..=4)
+            .map(|i| Curve::Exponential.map_norm(i * half / 2, 1_000_u32, 16_000))
+            .collect();
+        for (q, expected) in quarters.iter().zip([1_000, 2_000, 4_000, 8_000, 16_000]) {
+            assert!(q.abs_diff(expected) <= expected / 500, "{:?}", quarters);
+        }
+        assert_eq!(Curve::Exponential.map_norm(half, 0_i32, 1_000), 250);
+
+        assert_eq!(Curve::Logarithmic.map_norm(0, ms(0), ms(6_300)), ms(0));
+        // 2^3 - 1 of 2^6 - 1
+        let time = Curve::Logarithmic.map_norm(half, ms(0), ms(6_300));
+        assert!(time.0.abs_diff(700) <= 2, "{}", time.0);
+        let time = Curve::Logarithmic.map_norm(half, ms(6_300), ms(0));
+        assert!(time.0.abs_diff(5_600) <= 2, "{}", time.0);
+
+        // Monotonic over the whole travel
+        for curve in [Curve::Linear, Curve::Exponential, Curve::Logarithmic] {
+            let values: Vec<i32> = (0..=CONTROL_MAX)
+                .map(|v| curve.map_cc(v, 10_i32, 100_000))
+                .collect();
+            assert!(values.windows(2).all(|w| w[0] <= w[1]), "{:?}", curve);
+            assert_eq!((values[0], values[127]), (10, 100_000));
+        }
+    }
+}
@@ -1 +1,16 @@
+pub mod chord;
+pub mod diag;
+pub mod dither;
+pub mod fixed;
+pub mod format;
+pub mod gate;
+pub mod interp;
+pub mod mapping;
+pub mod note;
+pub mod param;
+#[cfg(feature = "parallel")]
+pub mod pool;
+pub mod sample;
+pub mod scale;
+pub mod tuning;
 pub mod units;
@@ -0,0 +1,238 @@
+// MIDI note numbers and their equal tempered frequencies.
+
+use crate::util::units::mHz;
+
+/// Frequencies of the lowest octave (C-1 to B-1) in 1/1024 mHz
+const OCTAVE_MINUS_1: [u32; 12] = [
+    8_372_018, 8_869_844, 9_397_273, 9_956_063, 10_548_082, 11_175_303, 11_839_822, 12_543_854,
+    13_289_750, 14_080_000, 14_917_240, 15_804_266,
+];
+
+/// Equal tempered frequencies of all MIDI note numbers (A4 = 69 is 440 Hz)
+pub const NOTE_MFREQ: [mHz; 128] = {
+    let mut table = [mHz(0); 128];
+    let mut n = 0;
+    while n < 128 {
+        let base = OCTAVE_MINUS_1[n % 12] as u64;
+        table[n] = mHz((((base << (n / 12)) + 512) >> 10) as u32);
+        n += 1;
+    }
+    table
+};
+
+/// MIDI note number
+///
+/// The constants are named after the pitch class and octave with middle C
+/// as [Note::C4] and `s` for sharps, e.g. `Note::Fs3`. The frequency is
+/// looked up in [NOTE_MFREQ], so no floats are needed at runtime.
+///
+/// ```
+/// use isopod::util::note::Note;
+/// use isopod::util::units::mHz;
+///
+/// assert_eq!(Note::A4.to_mfreq(), mHz(440_000));
+/// assert_eq!(Note::A4.transpose(12), Note::A5);
+/// assert_eq!(Note::Cs4.get_octave(), 4);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Note(pub u8);
+
+#[allow(non_upper_case_globals)]
+impl Note {
+    /// Highest MIDI note number
+    pub const MAX: Note = Note(127);
+
+    pub const C0: Note = Note(12);
+    pub const Cs0: Note = Note(13);
+    pub const D0: Note = Note(14);
+    pub const Ds0: Note = Note(15);
+    pub const E0: Note = Note(16);
+    pub const F0: Note = Note(17);
+    pub const Fs0: Note = Note(18);
+    pub const G0: Note = Note(19);
+    pub const Gs0: Note = Note(20);
+    pub const A0: Note = Note(21);
+    pub const As0: Note = Note(22);
+    pub const B0: Note = Note(23);
+    pub const C1: Note = Note(24);
+    pub const Cs1: Note = Note(25);
+    pub const D1: Note = Note(26);
+    pub const Ds1: Note = Note(27);
+    pub const E1: Note = Note(28);
+    pub const F1: Note = Note(29);
+    pub const Fs1: Note = Note(30);
+    pub const G1: Note = Note(31);
+    pub const Gs1: Note = Note(32);
+    pub const A1: Note = Note(33);
+    pub const As1: Note = Note(34);
+    pub const B1: Note = Note(35);
+    pub const C2: Note = Note(36);
+    pub const Cs2: Note = Note(37);
+    pub const D2: Note = Note(38);
+    pub const Ds2: Note = Note(39);
+    pub const E2: Note = Note(40);
+    pub const F2: Note = Note(41);
+    pub const Fs2: Note = Note(42);
+    pub const G2: Note = Note(43);
+    pub const Gs2: Note = Note(44);
+    pub const A2: Note = Note(45);
+    pub const As2: Note = Note(46);
+    pub const B2: Note = Note(47);
+    pub const C3: Note = Note(48);
+    pub const Cs3: Note = Note(49);
+    pub const D3: Note = Note(50);
+    pub const Ds3: Note = Note(51);
+    pub const E3: Note = Note(52);
+    pub const F3: Note = Note(53);
+    pub const Fs3: Note = Note(54);
+    pub const G3: Note = Note(55);
+    pub const Gs3: Note = Note(56);
+    pub const A3: Note = Note(57);
+    pub const As3: Note = Note(58);
+    pub const B3: Note = Note(59);
+    pub const C4: Note = Note(60);
+    pub const Cs4: Note = Note(61);
+    pub const D4: Note = Note(62);
+    pub const Ds4: Note = Note(63);
+    pub const E4: Note = Note(64);
+    pub const F4: Note = Note(65);
+    pub const Fs4: Note = Note(66);
+    pub const G4: Note = Note(67);
+    pub const Gs4: Note = Note(68);
+    pub const A4: Note = Note(69);
+    pub const As4: Note = Note(70);
+    pub const B4: Note = Note(71);
+    pub const C5: Note = Note(72);
+    pub const Cs5: Note = Note(73);
+    pub const D5: Note = Note(74);
+    pub const Ds5: Note = Note(75);
+    pub const E5: Note = Note(76);
+    pub const F5: Note = Note(77);
+    pub const Fs5: Note = Note(78);
+    pub const G5: Note = Note(79);
+    pub const Gs5: Note = Note(80);
+    pub const A5: Note = Note(81);
+    pub const As5: Note = Note(82);
+    pub const B5: Note = Note(83);
+    pub const C6: Note = Note(84);
+    pub const Cs6: Note = Note(85);
+    pub const D6: Note = Note(86);
+    pub const Ds6: Note = Note(87);
+    pub const E6: Note = Note(88);
+    pub const F6: Note = Note(89);
+    pub const Fs6: Note = Note(90);
+    pub const G6: Note = Note(91);
+    pub const Gs6: Note = Note(92);
+    pub const A6: Note = Note(93);
+    pub const As6: Note = Note(94);
+    pub const B6: Note = Note(95);
+    pub const C7: Note = Note(96);
+    pub const Cs7: Note = Note(97);
+    pub const D7: Note = Note(98);
+    pub const Ds7: Note = Note(99);
+    pub const E7: Note = Note(100);
+    pub const F7: Note = Note(101);
+    pub const Fs7: Note = Note(102);
+    pub const G7: Note = Note(103);
+    pub const Gs7: Note = Note(104);
+    pub const A7: Note = Note(105);
+    pub const As7: Note = Note(106);
+    pub const B7: Note = Note(107);
+    pub const C8: Note = Note(108);
+    pub const Cs8: Note = Note(109);
+    pub const D8: Note = Note(110);
+    pub const Ds8: Note = Note(111);
+    pub const E8: Note = Note(112);
+    pub const F8: Note = Note(113);
+    pub const Fs8: Note = Note(114);
+    pub const G8: Note = Note(115);
+    pub const Gs8: Note = Note(116);
+    pub const A8: Note = Note(117);
+    pub const As8: Note = Note(118);
+    pub const B8: Note = Note(119);
+}
+
+impl Note {
+    /// Returns the equal tempered frequency. Numbers above [Note::MAX]
+    /// saturate.
+    pub const fn to_mfreq(self) -> mHz {
+        let n = if self.0 > Self::MAX.0 {
+            Self::MAX.0
+        } else {
+            self.0
+        };
+        NOTE_MFREQ[n as usize]
+    }
+
+    /// Returns the note `semitones` higher, saturating at 0 and
+    /// [Note::MAX].
+    pub const fn transpose(self, semitones: i32) -> Note {
+        let n = self.0 as i32 + semitones;
+        Note(if n < 0 {
+            0
+        } else if n > Self::MAX.0 as i32 {
+            Self::MAX.0
+        } else {
+            n as u8
+        })
+    }
+
+    /// Returns the note `octaves` higher, saturating at 0 and [Note::MAX].
+    pub const fn transpose_octaves(self, octaves: i32) -> Note {
+        self.transpose(12 * octaves)
+    }
+
+    /// Returns the octave, where middle C starts octave 4.
+    pub const fn get_octave(self) -> i32 {
+        self.0 as i32 / 12 - 1
+    }
+
+    /// Returns the semitone within the octave, where C is 0.
+    pub const fn get_pitch_class(self) -> u8 {
+        self.0 % 12
+    }
+}
+
+impl From<u8> for Note {
+    fn from(note: u8) -> Self {
+        Note(note)
+    }
+}
+
+impl From<Note> for u8 {
+    fn from(note: Note) -> Self {
+        note.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_note_mfreq() {
+        assert_eq!(NOTE_MFREQ[69], mHz(440_000));
+        assert_eq!(Note::A0.to_mfreq(), mHz(27_500));
+        assert_eq!(Note::C4.to_mfreq(), mHz(261_626));
+        assert_eq!(Note::MAX.to_mfreq(), mHz(12_543_854));
+        assert_eq!(Note(200).to_mfreq(), Note::MAX.to_mfreq());
+        // Within 1 mHz of the exact frequency
+        for (n, mfreq) in NOTE_MFREQ.iter().enumerate() {
+            let exact = 440_000.0 * 2_f64.powf((n as f64 - 69.0) / 12.0);
+            assert!((mfreq.0 as f64 - exact).abs() <= 1.0, "{}", n);
+        }
+    }
+
+    #[test]
+    fn test_note_offsets() {
+        assert_eq!(Note::C4, Note(60));
+        assert_eq!(Note::B3.transpose(1), Note::C4);
+        assert_eq!(Note::C4.transpose_octaves(-1), Note::C3);
+        assert_eq!(Note(2).transpose(-5), Note(0));
+        assert_eq!(Note::B8.transpose_octaves(4), Note::MAX);
+        assert_eq!(Note::Fs3.get_octave(), 3);
+        assert_eq!(Note::Fs3.get_pitch_class(), 6);
+        assert_eq!(Note(0).get_octave(), -1);
+    }
+}
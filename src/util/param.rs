@@ -0,0 +1,277 @@
+// Lock-free parameters shared between the audio thread and control threads,
+// and validation of parameter values.
+
+use crate::util::units::{mHz, ms, Hz};
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Value that fits into an atomic 32-bit cell
+pub trait ParamValue: Copy {
+    fn to_bits(self) -> u32;
+    fn from_bits(bits: u32) -> Self;
+}
+
+impl ParamValue for u32 {
+    fn to_bits(self) -> u32 {
+        self
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        bits
+    }
+}
+
+impl ParamValue for i32 {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        bits as i32
+    }
+}
+
+impl ParamValue for i16 {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        bits as i16
+    }
+}
+
+impl ParamValue for bool {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        bits != 0
+    }
+}
+
+impl ParamValue for mHz {
+    fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        mHz(bits)
+    }
+}
+
+impl ParamValue for Hz {
+    fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        Hz(bits)
+    }
+}
+
+impl ParamValue for ms {
+    fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        ms(bits)
+    }
+}
+
+/// Reason why a checked setter rejected a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamError {
+    /// The sample rate is zero or too low.
+    ZeroSampleRate,
+    /// The frequency is above half the sample rate.
+    AboveNyquist,
+    /// The value is outside of the valid range.
+    OutOfRange,
+}
+
+/// Checks that `msample_rate` is positive.
+pub fn check_msample_rate(msample_rate: mHz) -> Result<(), ParamError> {
+    if msample_rate.0 == 0 {
+        Err(ParamError::ZeroSampleRate)
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks that `mfreq` is at most the Nyquist frequency of `msample_rate`.
+pub fn check_mfreq(mfreq: mHz, msample_rate: mHz) -> Result<(), ParamError> {
+    if mfreq.0 > nyquist(msample_rate).0 {
+        Err(ParamError::AboveNyquist)
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns half of `msample_rate`.
+pub fn nyquist(msample_rate: mHz) -> mHz {
+    mHz(msample_rate.0 / 2)
+}
+
+#[cfg(feature = "alloc")]
+struct Shared {
+    value: AtomicU32,
+    changed: AtomicBool,
+}
+
+/// Audio thread side of a parameter, see [param]
+#[cfg(feature = "alloc")]
+pub struct Param<T: ParamValue> {
+    shared: Arc<Shared>,
+    value: T,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ParamValue> Param<T> {
+    /// Returns the new value if it changed since the last poll. Call this
+    /// once per block and forward the value to the DSP object.
+    #[inline]
+    pub fn poll(&mut self) -> Option<T> {
+        if self.shared.changed.swap(false, Ordering::Acquire) {
+            self.value = T::from_bits(self.shared.value.load(Ordering::Relaxed));
+            Some(self.value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of the last poll.
+    pub fn get(&self) -> T {
+        self.value
+    }
+}
+
+/// Control thread side of a parameter, see [param]
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub struct ParamHandle<T: ParamValue> {
+    shared: Arc<Shared>,
+    _value: core::marker::PhantomData<T>,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ParamValue> ParamHandle<T> {
+    /// Sets the value. The audio thread picks it up at its next poll.
+    pub fn set(&self, value: T) {
+        self.shared.value.store(value.to_bits(), Ordering::Relaxed);
+        self.shared.changed.store(true, Ordering::Release);
+    }
+
+    /// Returns the last set value.
+    pub fn get(&self) -> T {
+        T::from_bits(self.shared.value.load(Ordering::Relaxed))
+    }
+}
+
+/// Creates a parameter with the value `initial`
+///
+/// The audio thread owns the DSP objects and the [Param], and any number of
+/// control threads change the value through clones of the [ParamHandle].
+/// Neither side ever blocks: the value is a single atomic and the audio
+/// thread polls a change flag, so intermediate values between two polls are
+/// skipped. Only the latest value counts, just like for a knob.
+///
+/// ```
+/// use isopod::synth::subtractive::SubtractiveVoice;
+/// use isopod::util::param::param;
+/// use isopod::util::units::mHz;
+///
+/// let (mut cutoff, handle) = param(mHz(1_000_000));
+/// let ui = std::thread::spawn(move || handle.set(mHz(2_000_000)));
+/// ui.join().unwrap();
+///
+/// // In the audio callback
+/// let mut voice = SubtractiveVoice::new();
+/// if let Some(mfreq) = cutoff.poll() {
+///     voice.set_cutoff(mfreq);
+/// }
+/// assert_eq!(cutoff.get(), mHz(2_000_000));
+/// ```
+#[cfg(feature = "alloc")]
+pub fn param<T: ParamValue>(initial: T) -> (Param<T>, ParamHandle<T>) {
+    let shared = Arc::new(Shared {
+        value: AtomicU32::new(initial.to_bits()),
+        changed: AtomicBool::new(false),
+    });
+    let handle = ParamHandle {
+        shared: shared.clone(),
+        _value: core::marker::PhantomData,
+    };
+    (
+        Param {
+            shared,
+            value: initial,
+        },
+        handle,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_param() {
+        let (mut param, handle) = param(-5_i16);
+        assert_eq!(param.poll(), None);
+        assert_eq!(param.get(), -5);
+
+        handle.set(3);
+        handle.set(7);
+        // Only the latest value arrives, and only once
+        assert_eq!(param.poll(), Some(7));
+        assert_eq!(param.poll(), None);
+        assert_eq!(handle.get(), 7);
+    }
+
+    #[test]
+    fn test_check() {
+        let fs = mHz(44_100_000);
+        assert_eq!(check_msample_rate(fs), Ok(()));
+        assert_eq!(check_msample_rate(mHz(0)), Err(ParamError::ZeroSampleRate));
+        assert_eq!(check_mfreq(mHz(22_050_000), fs), Ok(()));
+        assert_eq!(
+            check_mfreq(mHz(22_050_001), fs),
+            Err(ParamError::AboveNyquist)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_param_threads() {
+        let (mut param, handle) = param(ms(0));
+        let writers: Vec<_> = (1..=4)
+            .map(|i| {
+                let handle = handle.clone();
+                std::thread::spawn(move || {
+                    for t in 0..1_000 {
+                        handle.set(ms(i * 1_000 + t));
+                    }
+                })
+            })
+            .collect();
+        let mut polls = 0;
+        while writers.iter().any(|w| !w.is_finished()) {
+            if let Some(value) = param.poll() {
+                assert!(value.0 >= 1_000 && value.0 < 5_000);
+                polls += 1;
+            }
+        }
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        param.poll();
+        assert_eq!(param.get(), handle.get());
+        assert!(polls <= 4_000);
+    }
+}
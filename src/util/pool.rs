@@ -0,0 +1,238 @@
+// Small pool of worker threads running borrowed tasks, for splitting the
+// rendering of a block across cores.
+
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+
+/// Task of a [WorkerPool::run], which may borrow from the caller
+pub type Task<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// Task sent to a worker with its lifetime erased
+enum Job {
+    Boxed(Box<dyn FnOnce() + Send + 'static>),
+    /// Shared task of [WorkerPool::for_each] with the item it runs on
+    Item(&'static (dyn Fn(*mut ()) + Sync), ItemPtr),
+}
+
+impl Job {
+    fn run(self) {
+        match self {
+            Job::Boxed(task) => task(),
+            Job::Item(task, item) => task(item.0),
+        }
+    }
+}
+
+/// Item of a [WorkerPool::for_each], which is only accessed by one task
+struct ItemPtr(*mut ());
+
+// Safety: the items are Send and every item is handed to a single worker
+unsafe impl Send for ItemPtr {}
+
+struct Worker {
+    jobs: Sender<Job>,
+    handle: JoinHandle<()>,
+}
+
+/// Worker threads that stay alive between blocks
+///
+/// Spawning threads for every block would cost more than rendering a few
+/// voices, so the workers are started once and wait for tasks. A run hands
+/// one task to each worker, runs the last one on the calling thread and
+/// returns when all are done. The tasks may therefore borrow, e.g. disjoint
+/// parts of an array of voices.
+///
+/// ```
+/// use isopod::util::pool::{Task, WorkerPool};
+///
+/// let mut pool = WorkerPool::new(2);
+/// let mut sums = [0; 3];
+/// let tasks: Vec<Task> = sums
+///     .iter_mut()
+///     .enumerate()
+///     .map(|(i, sum)| Box::new(move || *sum = (0..=i).sum()) as Task)
+///     .collect();
+/// pool.run(tasks);
+/// assert_eq!(sums, [0, 1, 3]);
+/// ```
+pub struct WorkerPool {
+    workers: Vec<Worker>,
+    // True for every finished job, false for panics
+    done: Receiver<bool>,
+}
+
+impl WorkerPool {
+    /// Starts `threads - 1` workers, since the calling thread takes part in
+    /// every run.
+    pub fn new(threads: usize) -> Self {
+        let (done_sender, done) = channel();
+        let workers = (1..threads.max(1))
+            .map(|_| {
+                let (jobs, receiver) = channel::<Job>();
+                let done = done_sender.clone();
+                let handle = std::thread::spawn(move || {
+                    while let Ok(job) = receiver.recv() {
+                        let ok = catch_unwind(AssertUnwindSafe(|| job.run())).is_ok();
+                        if done.send(ok).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Worker { jobs, handle }
+            })
+            .collect();
+        Self { workers, done }
+    }
+
+    /// Returns the number of threads of a run, including the calling one.
+    pub fn get_threads(&self) -> usize {
+        self.workers.len() + 1
+    }
+
+    /// Runs `tasks` in parallel and returns when all of them finished. At
+    /// most [Self::get_threads] tasks run at once, the ones without a
+    /// worker run on the calling thread. Panics if a task panicked.
+    pub fn run<'a>(&mut self, mut tasks: Vec<Task<'a>>) {
+        if tasks.is_empty() {
+            return;
+        }
+        let mut local = tasks.split_off((tasks.len() - 1).min(self.workers.len()));
+        let mut sent = 0;
+        for (worker, task) in self.workers.iter().zip(tasks) {
+            // Safety: the borrows of the task outlive this call, since it
+            // waits for all sent jobs below, panics included
+            let task = unsafe {
+                core::mem::transmute::<Task<'a>, Box<dyn FnOnce() + Send + 'static>>(task)
+            };
+            if worker.jobs.send(Job::Boxed(task)).is_ok() {
+                sent += 1;
+            }
+        }
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            for task in local.drain(..) {
+                task();
+            }
+        }));
+        self.wait(sent, result);
+    }
+
+    /// Runs `task` on each of `items` in parallel, like [Self::run] but
+    /// without allocating, e.g. for every block on an audio thread. Panics
+    /// if a task panicked.
+    ///
+    /// ```
+    /// use isopod::util::pool::WorkerPool;
+    ///
+    /// let mut pool = WorkerPool::new(2);
+    /// let mut sums = [0, 1, 2];
+    /// pool.for_each(&mut sums, &|n: &mut usize| *n = (0..=*n).sum());
+    /// assert_eq!(sums, [0, 1, 3]);
+    /// ```
+    pub fn for_each<T: Send>(&mut self, items: &mut [T], task: &(dyn Fn(&mut T) + Sync)) {
+        if items.is_empty() {
+            return;
+        }
+        // Safety: every pointer is to an item of type T that only this call
+        // hands out
+        let erased = |item: *mut ()| task(unsafe { &mut *(item as *mut T) });
+        let erased: &(dyn Fn(*mut ()) + Sync) = &erased;
+        // Safety: `erased` outlives this call, since it waits for all sent
+        // jobs below, panics included
+        let erased = unsafe {
+            core::mem::transmute::<&(dyn Fn(*mut ()) + Sync), &'static (dyn Fn(*mut ()) + Sync)>(
+                erased,
+            )
+        };
+        let (sent_items, local) = items.split_at_mut((items.len() - 1).min(self.workers.len()));
+        let mut sent = 0;
+        let mut result = Ok(());
+        for (worker, item) in self.workers.iter().zip(sent_items) {
+            let job = Job::Item(erased, ItemPtr(item as *mut T as *mut ()));
+            if let Err(job) = worker.jobs.send(job) {
+                // Runs here if the worker is gone
+                result = result.and(catch_unwind(AssertUnwindSafe(|| job.0.run())));
+            } else {
+                sent += 1;
+            }
+        }
+        let local = catch_unwind(AssertUnwindSafe(|| local.iter_mut().for_each(task)));
+        self.wait(sent, result.and(local));
+    }
+
+    /// Waits for `sent` jobs of the workers and passes on the panics of
+    /// the local tasks or of the jobs.
+    fn wait(&mut self, sent: usize, result: std::thread::Result<()>) {
+        let mut ok = true;
+        for _ in 0..sent {
+            ok &= self.done.recv().unwrap_or(false);
+        }
+        if let Err(panic) = result {
+            resume_unwind(panic);
+        }
+        assert!(ok, "task of a worker panicked");
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        for worker in self.workers.drain(..) {
+            // Closing the channel ends the loop of the worker
+            drop(worker.jobs);
+            let _ = worker.handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_worker_pool() {
+        let mut pool = WorkerPool::new(3);
+        assert_eq!(pool.get_threads(), 3);
+        let mut values = [0_u64; 7];
+        for round in 0..100 {
+            let tasks = values
+                .chunks_mut(2)
+                .map(|chunk| {
+                    Box::new(move || {
+                        for x in chunk.iter_mut() {
+                            *x += round;
+                        }
+                    }) as Task
+                })
+                .collect();
+            pool.run(tasks);
+        }
+        assert!(values.iter().all(|x| *x == 4_950));
+
+        // The pool survives a panicking task
+        let panics = catch_unwind(AssertUnwindSafe(|| {
+            pool.run(vec![Box::new(|| ()), Box::new(|| panic!("task"))]);
+        }));
+        assert!(panics.is_err());
+        let mut x = 0;
+        pool.run(vec![Box::new(|| x = 1)]);
+        assert_eq!(x, 1);
+    }
+
+    #[test]
+    fn test_worker_pool_for_each() {
+        let mut pool = WorkerPool::new(3);
+        let mut values = [0_u64; 7];
+        for round in 0..100 {
+            pool.for_each(&mut values, &|x| *x += round);
+        }
+        assert!(values.iter().all(|x| *x == 4_950));
+
+        let panics = catch_unwind(AssertUnwindSafe(|| {
+            pool.for_each(&mut [0, 1, 2], &|x| assert!(*x != 0));
+        }));
+        assert!(panics.is_err());
+        let mut values = [1, 2];
+        pool.for_each(&mut values, &|x| *x *= 2);
+        assert_eq!(values, [2, 4]);
+    }
+}
@@ -0,0 +1,234 @@
+// Sample types the DSP primitives can be generic over.
+
+use crate::util::diag;
+use core::fmt::Debug;
+
+/// Representation of an audio sample
+///
+/// `i16` is the primary type and the only one used on MCUs. `i32` keeps
+/// 16 more bits of precision at the same full scale, and `f32` is
+/// normalized to [-1, 1] for targets with an FPU. Integer types saturate,
+/// `f32` doesn't clip at all.
+pub trait SampleType: Copy + Default + PartialEq + PartialOrd + Debug + Send + 'static {
+    /// Silence
+    const ZERO: Self;
+    /// Positive full scale
+    const MAX: Self;
+
+    /// Converts from the full scale of i16.
+    fn from_i16(x: i16) -> Self;
+
+    /// Converts to the full scale of i16 with saturation.
+    fn to_i16(self) -> i16;
+
+    /// Converts to the full scale of i32 with saturation, e.g. for
+    /// [crate::util::dither::Dither].
+    fn to_i32(self) -> i32;
+
+    /// Addition that saturates at the limits of integer types.
+    fn saturating_add(self, other: Self) -> Self;
+
+    /// Subtraction that saturates at the limits of integer types.
+    fn saturating_sub(self, other: Self) -> Self;
+
+    /// Addition that returns `None` if it would overflow.
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    /// Subtraction that returns `None` if it would overflow.
+    fn checked_sub(self, other: Self) -> Option<Self>;
+
+    /// Multiplies by the fraction `num / (1 << shift)`, rounding towards
+    /// zero. Integer types saturate, which the diagnostics count as clips.
+    fn scale(self, num: i64, shift: u32) -> Self;
+
+    /// Moves towards `other` by the fraction `num / (1 << shift)` from 0 to
+    /// 1, rounding down, e.g. for [crate::util::interp::linear].
+    fn lerp(self, other: Self, num: i64, shift: u32) -> Self;
+}
+
+impl SampleType for i16 {
+    const ZERO: Self = 0;
+    const MAX: Self = i16::MAX;
+
+    #[inline(always)]
+    fn from_i16(x: i16) -> Self {
+        x
+    }
+
+    #[inline(always)]
+    fn to_i16(self) -> i16 {
+        self
+    }
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        (self as i32) << 16
+    }
+
+    #[inline(always)]
+    fn saturating_add(self, other: Self) -> Self {
+        i16::saturating_add(self, other)
+    }
+
+    #[inline(always)]
+    fn saturating_sub(self, other: Self) -> Self {
+        i16::saturating_sub(self, other)
+    }
+
+    #[inline(always)]
+    fn checked_add(self, other: Self) -> Option<Self> {
+        i16::checked_add(self, other)
+    }
+
+    #[inline(always)]
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        i16::checked_sub(self, other)
+    }
+
+    #[inline(always)]
+    fn scale(self, num: i64, shift: u32) -> Self {
+        diag::clip((self as i64 * num) / (1 << shift))
+    }
+
+    #[inline(always)]
+    fn lerp(self, other: Self, num: i64, shift: u32) -> Self {
+        (self as i64 + (((other as i64 - self as i64) * num) >> shift)) as i16
+    }
+}
+
+impl SampleType for i32 {
+    const ZERO: Self = 0;
+    const MAX: Self = i32::MAX;
+
+    #[inline(always)]
+    fn from_i16(x: i16) -> Self {
+        (x as i32) << 16
+    }
+
+    #[inline(always)]
+    fn to_i16(self) -> i16 {
+        (self >> 16) as i16
+    }
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        self
+    }
+
+    #[inline(always)]
+    fn saturating_add(self, other: Self) -> Self {
+        i32::saturating_add(self, other)
+    }
+
+    #[inline(always)]
+    fn saturating_sub(self, other: Self) -> Self {
+        i32::saturating_sub(self, other)
+    }
+
+    #[inline(always)]
+    fn checked_add(self, other: Self) -> Option<Self> {
+        i32::checked_add(self, other)
+    }
+
+    #[inline(always)]
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        i32::checked_sub(self, other)
+    }
+
+    #[inline(always)]
+    fn scale(self, num: i64, shift: u32) -> Self {
+        let y = (self as i128 * num as i128) / (1 << shift);
+        let out = y.clamp(i32::MIN as i128, i32::MAX as i128);
+        if out != y {
+            diag::record(diag::Event::Clip);
+        }
+        out as i32
+    }
+
+    #[inline(always)]
+    fn lerp(self, other: Self, num: i64, shift: u32) -> Self {
+        let d = ((other as i64 - self as i64) as i128 * num as i128) >> shift;
+        (self as i64 + d as i64) as i32
+    }
+}
+
+impl SampleType for f32 {
+    const ZERO: Self = 0.0;
+    const MAX: Self = 1.0;
+
+    #[inline(always)]
+    fn from_i16(x: i16) -> Self {
+        x as f32 / 32_768.0
+    }
+
+    #[inline(always)]
+    fn to_i16(self) -> i16 {
+        // Float to int casts saturate
+        (self * 32_768.0) as i16
+    }
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        (self * 2_147_483_648.0) as i32
+    }
+
+    #[inline(always)]
+    fn saturating_add(self, other: Self) -> Self {
+        self + other
+    }
+
+    #[inline(always)]
+    fn saturating_sub(self, other: Self) -> Self {
+        self - other
+    }
+
+    #[inline(always)]
+    fn checked_add(self, other: Self) -> Option<Self> {
+        Some(self + other).filter(|y| y.is_finite())
+    }
+
+    #[inline(always)]
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        Some(self - other).filter(|y| y.is_finite())
+    }
+
+    #[inline(always)]
+    fn scale(self, num: i64, shift: u32) -> Self {
+        self * (num as f32 / (1_u64 << shift) as f32)
+    }
+
+    #[inline(always)]
+    fn lerp(self, other: Self, num: i64, shift: u32) -> Self {
+        self + (other - self) * (num as f32 / (1_u64 << shift) as f32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sample_type() {
+        for x in [i16::MIN, -1_234, 0, 77, i16::MAX] {
+            assert_eq!(i32::from_i16(x).to_i16(), x);
+            assert_eq!(f32::from_i16(x).to_i16(), x);
+            assert_eq!(f32::from_i16(x).to_i32(), x.to_i32());
+        }
+        assert_eq!(f32::from_i16(16_384), 0.5);
+        assert_eq!(SampleType::saturating_add(i16::MAX, 1), i16::MAX);
+        assert_eq!(SampleType::checked_add(i32::MAX, 1), None);
+        assert_eq!(SampleType::checked_add(f32::MAX, f32::MAX), None);
+
+        // Scaling rounds towards zero and saturates
+        assert_eq!((-3_i16).scale(1, 1), -1);
+        assert_eq!(20_000_i16.scale(2, 0), i16::MAX);
+        assert_eq!(i32::MIN.scale(3, 1), i32::MIN);
+        assert_eq!(0.5_f32.scale(3, 2), 0.375);
+
+        // Interpolation keeps constant signals and reaches the ends
+        assert_eq!(1_234_i16.lerp(1_234, 3, 3), 1_234);
+        assert_eq!(i16::MIN.lerp(i16::MAX, 1, 0), i16::MAX);
+        assert_eq!(i32::MAX.lerp(i32::MIN, 1, 1), -1);
+        assert_eq!(1.0_f32.lerp(2.0, 1, 2), 1.25);
+    }
+}
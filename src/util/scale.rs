@@ -0,0 +1,138 @@
+// Musical scales as sets of pitch classes, and their degrees.
+
+use crate::util::note::Note;
+
+/// Set of pitch classes relative to the root, where bit `i` is the pitch
+/// class `i` semitones above the root
+///
+/// ```
+/// use isopod::util::note::Note;
+/// use isopod::util::scale::Scale;
+///
+/// // D major contains F#, but not F
+/// assert!(Scale::MAJOR.contains(2, Note::Fs4));
+/// assert!(!Scale::MAJOR.contains(2, Note::F4));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Scale(pub u16);
+
+impl Scale {
+    pub const CHROMATIC: Scale = Scale(0b1111_1111_1111);
+    pub const MAJOR: Scale = Scale(0b1010_1011_0101);
+    pub const MINOR: Scale = Scale(0b0101_1010_1101);
+    pub const PENTATONIC_MAJOR: Scale = Scale(0b0010_1001_0101);
+    pub const PENTATONIC_MINOR: Scale = Scale(0b0100_1010_1001);
+    pub const DORIAN: Scale = Scale::from_steps(&[0, 2, 3, 5, 7, 9, 10]);
+    pub const PHRYGIAN: Scale = Scale::from_steps(&[0, 1, 3, 5, 7, 8, 10]);
+    pub const LYDIAN: Scale = Scale::from_steps(&[0, 2, 4, 6, 7, 9, 11]);
+    pub const MIXOLYDIAN: Scale = Scale::from_steps(&[0, 2, 4, 5, 7, 9, 10]);
+    pub const LOCRIAN: Scale = Scale::from_steps(&[0, 1, 3, 5, 6, 8, 10]);
+    pub const HARMONIC_MINOR: Scale = Scale::from_steps(&[0, 2, 3, 5, 7, 8, 11]);
+    pub const MELODIC_MINOR: Scale = Scale::from_steps(&[0, 2, 3, 5, 7, 9, 11]);
+    pub const BLUES: Scale = Scale::from_steps(&[0, 3, 5, 6, 7, 10]);
+    pub const WHOLE_TONE: Scale = Scale::from_steps(&[0, 2, 4, 6, 8, 10]);
+
+    /// Builds a scale from semitones above the root, e.g. `&[0, 2, 4]`.
+    /// Steps of an octave or more wrap around.
+    pub const fn from_steps(steps: &[u8]) -> Scale {
+        let mut mask = 0;
+        let mut i = 0;
+        while i < steps.len() {
+            mask |= 1 << (steps[i] % 12);
+            i += 1;
+        }
+        Scale(mask)
+    }
+
+    /// True if `note` is in the scale on the pitch class `root`.
+    pub const fn contains(self, root: u8, note: Note) -> bool {
+        let step = (note.get_pitch_class() + 12 - root % 12) % 12;
+        self.0 & (1 << step) != 0
+    }
+
+    /// Returns the number of pitch classes.
+    pub const fn len(self) -> u32 {
+        (self.0 & 0xFFF).count_ones()
+    }
+
+    /// True for a scale without pitch classes.
+    pub const fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the semitones above the root of step `index`, counting
+    /// upwards from the root. Indices wrap around at the octave.
+    pub const fn get_step(self, index: u32) -> u8 {
+        let len = self.len();
+        if len == 0 {
+            return 0;
+        }
+        let octave = index / len;
+        let mut index = index % len;
+        let mut step = 0;
+        loop {
+            if self.0 & (1 << step) != 0 {
+                if index == 0 {
+                    break;
+                }
+                index -= 1;
+            }
+            step += 1;
+        }
+        (octave * 12 + step) as u8
+    }
+
+    /// Returns the note `degree` steps of the scale above `tonic`, e.g. 4
+    /// for the fifth of a major scale. Negative degrees go downwards and
+    /// the notes saturate like [Note::transpose]. An empty scale returns
+    /// `tonic`.
+    pub const fn degree(self, tonic: Note, degree: i32) -> Note {
+        let len = self.len() as i32;
+        if len == 0 {
+            return tonic;
+        }
+        let octave = degree.div_euclid(len);
+        let step = self.get_step(degree.rem_euclid(len) as u32) as i32;
+        tonic.transpose(octave * 12 + step)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scale() {
+        assert_eq!(Scale::from_steps(&[0, 2, 4, 5, 7, 9, 11]), Scale::MAJOR);
+        assert_eq!(Scale::from_steps(&[0, 2, 3, 5, 7, 8, 10]), Scale::MINOR);
+        assert_eq!(
+            Scale::from_steps(&[12, 4, 7]),
+            Scale::from_steps(&[0, 4, 7])
+        );
+        assert_eq!(Scale::PENTATONIC_MINOR.len(), 5);
+        assert!(Scale(0).is_empty());
+        assert_eq!(
+            Scale::DORIAN,
+            Scale((Scale::MINOR.0 & !(1 << 8)) | (1 << 9))
+        );
+        assert_eq!(Scale::BLUES.len(), 6);
+
+        // Degrees wrap around the octave in both directions
+        let steps: Vec<u8> = (0..8).map(|i| Scale::MAJOR.get_step(i)).collect();
+        assert_eq!(steps, [0, 2, 4, 5, 7, 9, 11, 12]);
+        assert_eq!(Scale::MAJOR.degree(Note::C4, 4), Note::G4);
+        assert_eq!(Scale::MAJOR.degree(Note::C4, 9), Note::E5);
+        assert_eq!(Scale::MAJOR.degree(Note::C4, -1), Note::B3);
+        assert_eq!(Scale::PENTATONIC_MINOR.degree(Note::A3, -5), Note::A2);
+        assert_eq!(Scale(0).degree(Note::C4, 3), Note::C4);
+
+        // A minor has the notes of C major
+        for n in 0..128 {
+            assert_eq!(
+                Scale::MINOR.contains(9, Note(n)),
+                Scale::MAJOR.contains(0, Note(n))
+            );
+        }
+    }
+}
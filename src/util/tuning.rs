@@ -0,0 +1,364 @@
+// Tuning tables that map MIDI notes to arbitrary frequencies, built from
+// equal divisions of the octave or from Scala files.
+
+use crate::util::note::NOTE_MFREQ;
+use crate::util::units::mHz;
+
+/// Reason why a Scala file couldn't be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalaError {
+    /// The line (counted from 1) isn't valid.
+    Syntax { line: usize },
+    /// The file has fewer pitches or keys than it declares.
+    Count,
+    /// The reference note of the keyboard mapping isn't mapped.
+    Reference,
+}
+
+/// Frequencies of all 128 MIDI notes
+///
+/// Tables are computed once when they are created, so looking up a
+/// frequency is as cheap as with [NOTE_MFREQ]. Unmapped notes of a Scala
+/// keyboard mapping have no frequency.
+///
+/// ```
+/// use isopod::util::tuning::Tuning;
+/// use isopod::util::units::mHz;
+///
+/// let scl = "! Pythagorean fifths
+/// Three notes
+///  3
+/// 9/8
+/// 3/2
+/// 2/1";
+/// let tuning = Tuning::from_scala(scl, None).unwrap();
+/// // 69 is the reference at 440 Hz, 9 degrees above middle C
+/// assert_eq!(tuning.get_mfreq(69), Some(mHz(440_000)));
+/// assert_eq!(tuning.get_mfreq(70).unwrap().0, 495_000);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    // 0 for unmapped notes
+    table: [mHz; 128],
+}
+
+impl Tuning {
+    /// Standard 12 tone equal temperament with A4 at 440 Hz
+    pub fn equal() -> Self {
+        Self { table: NOTE_MFREQ }
+    }
+
+    /// Equal division of the octave into `divisions` steps, with note 69 at
+    /// 440 Hz.
+    #[cfg(feature = "std")]
+    pub fn edo(divisions: u32) -> Self {
+        let mut table = [mHz(0); 128];
+        for (n, mfreq) in table.iter_mut().enumerate() {
+            let octaves = (n as f64 - 69.0) / divisions.max(1) as f64;
+            *mfreq = to_mfreq(440_000.0 * octaves.exp2());
+        }
+        Self { table }
+    }
+
+    /// Loads a scale (`.scl`) and optionally a keyboard mapping (`.kbm`)
+    /// in the Scala format. Without a mapping, degree 0 starts at note 60
+    /// and note 69 is tuned to 440 Hz.
+    #[cfg(feature = "std")]
+    pub fn from_scala(scl: &str, kbm: Option<&str>) -> Result<Self, ScalaError> {
+        let scale = parse_scl(scl)?;
+        let map = match kbm {
+            Some(kbm) => parse_kbm(kbm)?,
+            None => KeyboardMap::linear(),
+        };
+
+        // Cents of each key relative to the middle note
+        let size = scale.len() as i64;
+        let period = scale[scale.len() - 1];
+        let degree_cents = |degree: i64| {
+            let cents = if degree.rem_euclid(size) == 0 {
+                0.0
+            } else {
+                scale[degree.rem_euclid(size) as usize - 1]
+            };
+            degree.div_euclid(size) as f64 * period + cents
+        };
+        let map_size = if map.keys.is_empty() {
+            size
+        } else {
+            map.keys.len() as i64
+        };
+        let octave = if map.keys.is_empty() || map.octave_degree == 0 {
+            period
+        } else {
+            degree_cents(map.octave_degree as i64)
+        };
+        let key_cents = |note: i64| -> Option<f64> {
+            let d = note - map.middle as i64;
+            let degree = if map.keys.is_empty() {
+                Some(d.rem_euclid(size))
+            } else {
+                map.keys[d.rem_euclid(map_size) as usize]
+            }?;
+            Some(d.div_euclid(map_size) as f64 * octave + degree_cents(degree))
+        };
+
+        let reference = key_cents(map.reference as i64).ok_or(ScalaError::Reference)?;
+        let mut table = [mHz(0); 128];
+        for (n, mfreq) in table.iter_mut().enumerate() {
+            if !(map.first..=map.last).contains(&(n as u8)) {
+                continue;
+            }
+            if let Some(cents) = key_cents(n as i64) {
+                *mfreq = to_mfreq(map.mfreq * ((cents - reference) / 1_200.0).exp2());
+            }
+        }
+        Ok(Self { table })
+    }
+
+    /// Returns the frequency of `note`, or `None` if it isn't mapped.
+    pub fn get_mfreq(&self, note: u8) -> Option<mHz> {
+        Some(self.table[note.min(127) as usize]).filter(|mfreq| mfreq.0 > 0)
+    }
+
+    /// True for the standard 12 tone equal temperament.
+    pub fn is_equal(&self) -> bool {
+        self.table == NOTE_MFREQ
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::equal()
+    }
+}
+
+#[cfg(feature = "std")]
+fn to_mfreq(mfreq: f64) -> mHz {
+    mHz(mfreq.round().clamp(0.0, u32::MAX as f64) as u32)
+}
+
+/// Returns the non-comment lines with their line numbers.
+#[cfg(feature = "std")]
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.starts_with('!'))
+}
+
+/// Parses the pitches of degree 1 to the period in cents.
+#[cfg(feature = "std")]
+fn parse_scl(scl: &str) -> Result<Vec<f64>, ScalaError> {
+    let mut lines = lines(scl);
+    // The description may be empty
+    lines.next().ok_or(ScalaError::Count)?;
+    let (line, count) = lines.next().ok_or(ScalaError::Count)?;
+    let count: usize = first_word(count)
+        .parse()
+        .map_err(|_| ScalaError::Syntax { line })?;
+    // Not preallocated, since the count is untrusted
+    let mut pitches = Vec::new();
+    for (line, text) in lines.take(count) {
+        let pitch = first_word(text);
+        let cents = if pitch.contains('.') {
+            pitch.parse::<f64>().ok()
+        } else {
+            let (num, den) = pitch.split_once('/').unwrap_or((pitch, "1"));
+            match (num.parse::<u64>(), den.parse::<u64>()) {
+                (Ok(num), Ok(den)) if num > 0 && den > 0 => {
+                    Some(1_200.0 * (num as f64 / den as f64).log2())
+                }
+                _ => None,
+            }
+        };
+        pitches.push(cents.ok_or(ScalaError::Syntax { line })?);
+    }
+    if pitches.len() < count || count == 0 {
+        return Err(ScalaError::Count);
+    }
+    Ok(pitches)
+}
+
+#[cfg(feature = "std")]
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+/// Keyboard mapping of a `.kbm` file
+#[cfg(feature = "std")]
+struct KeyboardMap {
+    first: u8,
+    last: u8,
+    // Note of degree 0
+    middle: u8,
+    reference: u8,
+    mfreq: f64,
+    octave_degree: u32,
+    // Degree of each key of the pattern, empty for a linear mapping
+    keys: Vec<Option<i64>>,
+}
+
+#[cfg(feature = "std")]
+impl KeyboardMap {
+    fn linear() -> Self {
+        Self {
+            first: 0,
+            last: 127,
+            middle: 60,
+            reference: 69,
+            mfreq: 440_000.0,
+            octave_degree: 0,
+            keys: Vec::new(),
+        }
+    }
+}
+
+/// Returns the first word of the next line.
+#[cfg(feature = "std")]
+fn field<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Result<(usize, &'a str), ScalaError> {
+    let (line, text) = lines.next().ok_or(ScalaError::Count)?;
+    Ok((line, first_word(text)))
+}
+
+#[cfg(feature = "std")]
+fn number<'a>(lines: &mut impl Iterator<Item = (usize, &'a str)>) -> Result<u32, ScalaError> {
+    let (line, text) = field(lines)?;
+    text.parse().map_err(|_| ScalaError::Syntax { line })
+}
+
+#[cfg(feature = "std")]
+fn parse_kbm(kbm: &str) -> Result<KeyboardMap, ScalaError> {
+    let mut lines = lines(kbm);
+    let size = number(&mut lines)?;
+    let first = number(&mut lines)?.min(127) as u8;
+    let last = number(&mut lines)?.min(127) as u8;
+    let middle = number(&mut lines)?.min(127) as u8;
+    let reference = number(&mut lines)?.min(127) as u8;
+    let (line, text) = field(&mut lines)?;
+    let hz: f64 = text.parse().map_err(|_| ScalaError::Syntax { line })?;
+    let octave_degree = number(&mut lines)?;
+
+    let mut keys = Vec::new();
+    for (line, text) in lines.take(size as usize) {
+        keys.push(match first_word(text) {
+            "x" => None,
+            degree => Some(degree.parse().map_err(|_| ScalaError::Syntax { line })?),
+        });
+    }
+    if keys.len() < size as usize {
+        return Err(ScalaError::Count);
+    }
+    Ok(KeyboardMap {
+        first,
+        last,
+        middle,
+        reference,
+        mfreq: hz * 1_000.0,
+        octave_degree,
+        keys,
+    })
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    const MEANTONE: &str = "! meanquar.scl
+!
+1/4-comma meantone scale. Pietro Aaron's temperament (1523)
+ 12
+!
+ 76.04900
+ 193.15686
+ 310.26471
+ 5/4
+ 503.42157
+ 579.47057
+ 696.57843
+ 25/16
+ 889.73529
+ 1006.84314
+ 1082.89214
+ 2/1
+";
+
+    #[test]
+    fn test_tuning_edo() {
+        let equal = Tuning::equal();
+        assert!(equal.is_equal());
+        let edo = Tuning::edo(12);
+        for n in 0..128 {
+            let (a, b) = (edo.get_mfreq(n).unwrap().0, equal.get_mfreq(n).unwrap().0);
+            assert!(a.abs_diff(b) <= 1, "{} {} {}", n, a, b);
+        }
+        let quarter = Tuning::edo(24);
+        assert_eq!(quarter.get_mfreq(69), Some(mHz(440_000)));
+        assert_eq!(quarter.get_mfreq(93), Some(mHz(880_000)));
+        assert!(!quarter.is_equal());
+    }
+
+    #[test]
+    fn test_tuning_scala() {
+        let tuning = Tuning::from_scala(MEANTONE, None).unwrap();
+        assert_eq!(tuning.get_mfreq(69), Some(mHz(440_000)));
+        // The major third above middle C is pure, 5/4
+        let c4 = tuning.get_mfreq(60).unwrap().0 as f64;
+        let e4 = tuning.get_mfreq(64).unwrap().0 as f64;
+        assert!((e4 / c4 - 1.25).abs() < 1e-5);
+        assert_eq!(tuning.get_mfreq(81), Some(mHz(880_000)));
+
+        // White keys only, with A4 at 432 Hz and black keys unmapped
+        let kbm = "! white.kbm
+12
+0
+127
+60
+69
+432.0
+12
+0
+x
+2
+x
+4
+5
+x
+7
+x
+9
+x
+11
+";
+        let tuning = Tuning::from_scala(MEANTONE, Some(kbm)).unwrap();
+        assert_eq!(tuning.get_mfreq(69), Some(mHz(432_000)));
+        assert_eq!(tuning.get_mfreq(61), None);
+        assert_eq!(tuning.get_mfreq(57), Some(mHz(216_000)));
+
+        assert_eq!(
+            Tuning::from_scala("x\n 2\n 3/2\n", None),
+            Err(ScalaError::Count)
+        );
+        assert_eq!(
+            Tuning::from_scala("x\n 2\n 3/2\n two\n", None),
+            Err(ScalaError::Syntax { line: 4 })
+        );
+        let unmapped = kbm.replace("\n9\n", "\nx\n");
+        assert_eq!(
+            Tuning::from_scala(MEANTONE, Some(&unmapped)),
+            Err(ScalaError::Reference)
+        );
+
+        // Huge declared counts fail without allocating for them
+        assert_eq!(
+            Tuning::from_scala("x\n 18446744073709551615\n 3/2\n", None),
+            Err(ScalaError::Count)
+        );
+        let huge = kbm.replacen("\n12\n", "\n4294967295\n", 1);
+        assert_eq!(
+            Tuning::from_scala(MEANTONE, Some(&huge)),
+            Err(ScalaError::Count)
+        );
+    }
+}
@@ -1,3 +1,7 @@
+use crate::osc::luts::SINE_I16;
+use crate::util::param::ParamError;
+use core::time::Duration;
+
 /// Unit mHz
 /// ```
 /// # use isopod::util::units::*;
@@ -7,11 +11,33 @@
 ///
 /// assert_eq!(mHz(1_000).to_us(), us(1_000_000));
 /// assert_eq!(mHz(1_000).to_ms(), ms(1_000));
+///
+/// assert_eq!(mHz(440_000) + mHz(1_500), mHz(441_500));
+/// assert_eq!(mHz(440_000) * 2, mHz(880_000));
+/// assert!(mHz(30_000_000) > mHz(22_050_000));
 /// ```
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct mHz(pub u32);
 
+/// Unit uHz (microhertz)
+///
+/// For slow modulation and fine detune, where steps of 1 mHz are too coarse.
+/// The value is a `u64`, so every frequency in mHz fits.
+/// ```
+/// # use isopod::util::units::*;
+/// assert_eq!(uHz(1_500).to_mHz(), mHz(1));
+/// assert_eq!(mHz(440_000).to_uHz(), uHz(440_000_000));
+/// assert_eq!(uHz(u64::MAX).to_mHz(), mHz(u32::MAX));
+/// assert_eq!(uHz(2_000_000).to_ms(), ms(500));
+/// assert_eq!(uHz(10) * 3, uHz(30));
+/// ```
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct uHz(pub u64);
+
 /// Unit Hz
 /// ```
 /// # use isopod::util::units::*;
@@ -21,8 +47,10 @@ pub struct mHz(pub u32);
 ///
 /// assert_eq!(Hz(1).to_us(), us(1_000_000));
 /// assert_eq!(Hz(1).to_ms(), ms(1_000));
+/// assert_eq!(Hz(0).to_ms(), ms(u32::MAX));
 /// ```
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Hz(pub u32);
 
 /// Unit kHz
@@ -36,7 +64,8 @@ pub struct Hz(pub u32);
 /// assert_eq!(kHz(1).to_ms(), ms(1));
 /// ```
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct kHz(pub u32);
 
 /// Unit ms (milliseconds)
@@ -48,9 +77,14 @@ pub struct kHz(pub u32);
 ///
 /// assert_eq!(ms(1).to_us(), us(1_000));
 /// assert_eq!(ms(1).to_ms(), ms(1));
+///
+/// assert_eq!(ms(10) - ms(20), ms(0));
+/// assert_eq!(ms(u32::MAX) + ms(1), ms(u32::MAX));
+/// assert_eq!(ms(10) / 0, ms(u32::MAX));
 /// ```
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ms(pub u32);
 
 /// Unit us (microseconds)
@@ -64,119 +98,519 @@ pub struct ms(pub u32);
 /// assert_eq!(us(1_000).to_ms(), ms(1));
 /// ```
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct us(pub u32);
 
+/// Unit BPM (quarter notes per minute)
+/// ```
+/// # use isopod::util::units::*;
+/// assert_eq!(Bpm(120).to_us(), us(500_000));
+/// assert_eq!(Bpm(120).to_ms(), ms(500));
+/// assert_eq!(Bpm(120).to_mHz(), mHz(2_000));
+/// assert_eq!(Bpm(90).to_Hz(), Hz(1));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bpm(pub u32);
+
+/// Unit mBPM (milli BPM)
+///
+/// For tempos between whole BPM, e.g. measured from an external clock.
+/// ```
+/// # use isopod::util::units::*;
+/// assert_eq!(mBpm::from(Bpm(120)), mBpm(120_000));
+/// assert_eq!(mBpm(120_500).to_mHz(), mHz(2_008));
+/// assert_eq!(mBpm(120_500).to_us(), us(497_925));
+/// assert_eq!(mBpm::from_beat(us(497_925)), mBpm(120_500));
+/// ```
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct mBpm(pub u32);
+
+impl mBpm {
+    /// Returns the tempo of beats of `beat`, rounded to the nearest mBPM.
+    /// A beat of 0 saturates to `u32::MAX`.
+    pub fn from_beat(beat: us) -> Self {
+        match beat.0 as u64 {
+            0 => mBpm(u32::MAX),
+            beat => mBpm(((60_000_000_000 + beat / 2) / beat).min(u32::MAX as u64) as u32),
+        }
+    }
+}
+
+impl From<Bpm> for mBpm {
+    fn from(tempo: Bpm) -> Self {
+        mBpm(tempo.0.saturating_mul(1_000))
+    }
+}
+
+// Arithmetic
+
+/// Implements saturating `+` and `-` between values of a unit and `*` and
+/// `/` by a `u32`. Division by zero saturates to `u32::MAX` like the
+/// conversions, so unit arithmetic never panics on an audio thread.
+macro_rules! impl_unit_ops {
+    ($($unit:ident: $t:ty),*) => {
+        $(
+            impl core::ops::Add for $unit {
+                type Output = $unit;
+                fn add(self, rhs: $unit) -> $unit {
+                    $unit(self.0.saturating_add(rhs.0))
+                }
+            }
+            impl core::ops::Sub for $unit {
+                type Output = $unit;
+                fn sub(self, rhs: $unit) -> $unit {
+                    $unit(self.0.saturating_sub(rhs.0))
+                }
+            }
+            impl core::ops::Mul<u32> for $unit {
+                type Output = $unit;
+                fn mul(self, rhs: u32) -> $unit {
+                    $unit(self.0.saturating_mul(rhs.into()))
+                }
+            }
+            impl core::ops::Div<u32> for $unit {
+                type Output = $unit;
+                fn div(self, rhs: u32) -> $unit {
+                    $unit(self.0.checked_div(rhs.into()).unwrap_or(<$t>::MAX))
+                }
+            }
+            impl core::ops::AddAssign for $unit {
+                fn add_assign(&mut self, rhs: $unit) {
+                    *self = *self + rhs;
+                }
+            }
+            impl core::ops::SubAssign for $unit {
+                fn sub_assign(&mut self, rhs: $unit) {
+                    *self = *self - rhs;
+                }
+            }
+        )*
+    };
+}
+
+impl_unit_ops!(uHz: u64, mHz: u32, Hz: u32, kHz: u32, ms: u32, us: u32);
+
 // Conversions
+//
+// Conversions to a coarser unit round down, e.g. `mHz(1_999).to_Hz()` is
+// `Hz(1)`. Conversions between frequencies and periods divide and round
+// down as well, e.g. `Hz(3).to_ms()` is `ms(333)`. The `try_to_*` variants
+// return `None` where the result doesn't fit into a `u32` or the input is a
+// frequency or period of 0. The `to_*` variants saturate to `u32::MAX`
+// instead, so no conversion panics.
+
+/// Conversion between frequency units. Converting a period of 0 saturates to
+/// `u32::MAX`.
+/// ```
+/// # use isopod::util::units::*;
+/// assert_eq!(kHz(4_295).to_mHz(), mHz(u32::MAX));
+/// assert_eq!(kHz(4_295).try_to_mHz(), None);
+/// assert_eq!(kHz(4_294).try_to_mHz(), Some(mHz(4_294_000_000)));
+/// assert_eq!(ms(0).try_to_Hz(), None);
+/// ```
 #[allow(non_snake_case)]
 pub trait Frequency {
-    fn to_mHz(&self) -> mHz;
-    fn to_Hz(&self) -> Hz;
-    fn to_kHz(&self) -> kHz;
+    fn try_to_mHz(&self) -> Option<mHz>;
+    fn try_to_Hz(&self) -> Option<Hz>;
+    fn try_to_kHz(&self) -> Option<kHz>;
+
+    fn to_mHz(&self) -> mHz {
+        self.try_to_mHz().unwrap_or(mHz(u32::MAX))
+    }
+    fn to_Hz(&self) -> Hz {
+        self.try_to_Hz().unwrap_or(Hz(u32::MAX))
+    }
+    fn to_kHz(&self) -> kHz {
+        self.try_to_kHz().unwrap_or(kHz(u32::MAX))
+    }
+    fn to_uHz(&self) -> uHz {
+        uHz(self.to_mHz().0 as u64 * 1_000)
+    }
+
+    /// Returns the period as a [Duration] with ns resolution. A frequency of
+    /// 0 saturates to [Duration::MAX].
+    /// ```
+    /// # use isopod::util::units::*;
+    /// # use core::time::Duration;
+    /// assert_eq!(Hz(440).to_duration(), Duration::from_nanos(2_272_727));
+    /// assert_eq!(Bpm(120).to_duration(), Duration::from_millis(500));
+    /// assert_eq!(mHz(0).to_duration(), Duration::MAX);
+    /// ```
+    fn to_duration(&self) -> Duration {
+        match self.to_mHz().0 {
+            0 => Duration::MAX,
+            mfreq => Duration::from_nanos(1_000_000_000_000 / mfreq as u64),
+        }
+    }
 }
 
+/// Conversion between period units. Converting a frequency of 0 saturates to
+/// `u32::MAX`.
+/// ```
+/// # use isopod::util::units::*;
+/// assert_eq!(ms(4_294_968).to_us(), us(u32::MAX));
+/// assert_eq!(ms(4_294_968).try_to_us(), None);
+/// assert_eq!(Hz(0).try_to_ms(), None);
+/// assert_eq!(Hz(3).try_to_ms(), Some(ms(333)));
+/// ```
 pub trait Period {
-    fn to_us(&self) -> us;
-    fn to_ms(&self) -> ms;
+    fn try_to_us(&self) -> Option<us>;
+    fn try_to_ms(&self) -> Option<ms>;
+
+    fn to_us(&self) -> us {
+        self.try_to_us().unwrap_or(us(u32::MAX))
+    }
+    fn to_ms(&self) -> ms {
+        self.try_to_ms().unwrap_or(ms(u32::MAX))
+    }
+}
+
+impl Frequency for uHz {
+    fn try_to_mHz(&self) -> Option<mHz> {
+        u32::try_from(self.0 / 1_000).ok().map(mHz)
+    }
+    fn try_to_Hz(&self) -> Option<Hz> {
+        u32::try_from(self.0 / 1_000_000).ok().map(Hz)
+    }
+    fn try_to_kHz(&self) -> Option<kHz> {
+        u32::try_from(self.0 / 1_000_000_000).ok().map(kHz)
+    }
+    fn to_uHz(&self) -> uHz {
+        *self
+    }
+}
+impl Period for uHz {
+    fn try_to_us(&self) -> Option<us> {
+        1_000_000_000_000_u64
+            .checked_div(self.0)
+            .and_then(|t| u32::try_from(t).ok())
+            .map(us)
+    }
+    fn try_to_ms(&self) -> Option<ms> {
+        1_000_000_000_u64
+            .checked_div(self.0)
+            .and_then(|t| u32::try_from(t).ok())
+            .map(ms)
+    }
 }
 
 impl Frequency for mHz {
-    fn to_mHz(&self) -> mHz {
-        mHz(self.0)
+    fn try_to_mHz(&self) -> Option<mHz> {
+        Some(mHz(self.0))
     }
-    fn to_Hz(&self) -> Hz {
-        Hz(self.0 / 1_000)
+    fn try_to_Hz(&self) -> Option<Hz> {
+        Some(Hz(self.0 / 1_000))
     }
-    fn to_kHz(&self) -> kHz {
-        kHz(self.0 / 1_000_000)
+    fn try_to_kHz(&self) -> Option<kHz> {
+        Some(kHz(self.0 / 1_000_000))
     }
 }
 impl Period for mHz {
-    fn to_us(&self) -> us {
-        us(1_000_000_000 / self.0)
+    fn try_to_us(&self) -> Option<us> {
+        1_000_000_000_u32.checked_div(self.0).map(us)
     }
-    fn to_ms(&self) -> ms {
-        ms(1_000_000 / self.0)
+    fn try_to_ms(&self) -> Option<ms> {
+        1_000_000_u32.checked_div(self.0).map(ms)
     }
 }
 
 impl Frequency for Hz {
-    fn to_mHz(&self) -> mHz {
-        mHz(self.0 * 1_000)
+    fn try_to_mHz(&self) -> Option<mHz> {
+        self.0.checked_mul(1_000).map(mHz)
     }
-    fn to_Hz(&self) -> Hz {
-        Hz(self.0)
+    fn try_to_Hz(&self) -> Option<Hz> {
+        Some(Hz(self.0))
     }
-    fn to_kHz(&self) -> kHz {
-        kHz(self.0 / 1_000)
+    fn try_to_kHz(&self) -> Option<kHz> {
+        Some(kHz(self.0 / 1_000))
     }
 }
 impl Period for Hz {
-    fn to_us(&self) -> us {
-        us(1_000_000 / self.0)
+    fn try_to_us(&self) -> Option<us> {
+        1_000_000_u32.checked_div(self.0).map(us)
     }
-    fn to_ms(&self) -> ms {
-        ms(1_000 / self.0)
+    fn try_to_ms(&self) -> Option<ms> {
+        1_000_u32.checked_div(self.0).map(ms)
     }
 }
 
 impl Frequency for kHz {
-    fn to_mHz(&self) -> mHz {
-        mHz(self.0 * 1_000_000)
+    fn try_to_mHz(&self) -> Option<mHz> {
+        self.0.checked_mul(1_000_000).map(mHz)
     }
-    fn to_Hz(&self) -> Hz {
-        Hz(self.0 * 1_000)
+    fn try_to_Hz(&self) -> Option<Hz> {
+        self.0.checked_mul(1_000).map(Hz)
     }
-    fn to_kHz(&self) -> kHz {
-        kHz(self.0)
+    fn try_to_kHz(&self) -> Option<kHz> {
+        Some(kHz(self.0))
     }
 }
 impl Period for kHz {
-    fn to_us(&self) -> us {
-        us(1_000 / self.0)
+    fn try_to_us(&self) -> Option<us> {
+        1_000_u32.checked_div(self.0).map(us)
     }
-    fn to_ms(&self) -> ms {
-        ms(1 / self.0)
+    fn try_to_ms(&self) -> Option<ms> {
+        1_u32.checked_div(self.0).map(ms)
     }
 }
 
 impl Frequency for ms {
-    fn to_mHz(&self) -> mHz {
-        mHz(1_000_000 / self.0)
+    fn try_to_mHz(&self) -> Option<mHz> {
+        1_000_000_u32.checked_div(self.0).map(mHz)
     }
-    fn to_Hz(&self) -> Hz {
-        Hz(1_000 / self.0)
+    fn try_to_Hz(&self) -> Option<Hz> {
+        1_000_u32.checked_div(self.0).map(Hz)
     }
-    fn to_kHz(&self) -> kHz {
-        kHz(1 / self.0)
+    fn try_to_kHz(&self) -> Option<kHz> {
+        1_u32.checked_div(self.0).map(kHz)
     }
 }
 impl Period for ms {
-    fn to_us(&self) -> us {
-        us(1_000 * self.0)
+    fn try_to_us(&self) -> Option<us> {
+        self.0.checked_mul(1_000).map(us)
     }
-    fn to_ms(&self) -> ms {
-        ms(self.0)
+    fn try_to_ms(&self) -> Option<ms> {
+        Some(ms(self.0))
     }
 }
 
 impl Frequency for us {
-    fn to_mHz(&self) -> mHz {
-        mHz(1_000_000_000 / self.0)
+    fn try_to_mHz(&self) -> Option<mHz> {
+        1_000_000_000_u32.checked_div(self.0).map(mHz)
     }
-    fn to_Hz(&self) -> Hz {
-        Hz(1_000_000 / self.0)
+    fn try_to_Hz(&self) -> Option<Hz> {
+        1_000_000_u32.checked_div(self.0).map(Hz)
     }
-    fn to_kHz(&self) -> kHz {
-        kHz(1_000 / self.0)
+    fn try_to_kHz(&self) -> Option<kHz> {
+        1_000_u32.checked_div(self.0).map(kHz)
     }
 }
 impl Period for us {
-    fn to_us(&self) -> us {
-        us(1 * self.0)
+    fn try_to_us(&self) -> Option<us> {
+        Some(us(self.0))
     }
-    fn to_ms(&self) -> ms {
-        ms(self.0 / 1_000)
+    fn try_to_ms(&self) -> Option<ms> {
+        Some(ms(self.0 / 1_000))
+    }
+}
+
+// The frequency of a tempo is the rate of its beats
+impl Frequency for Bpm {
+    fn try_to_mHz(&self) -> Option<mHz> {
+        u32::try_from(self.0 as u64 * 1_000 / 60).ok().map(mHz)
+    }
+    fn try_to_Hz(&self) -> Option<Hz> {
+        Some(Hz(self.0 / 60))
+    }
+    fn try_to_kHz(&self) -> Option<kHz> {
+        Some(kHz(self.0 / 60_000))
+    }
+}
+
+// The period of a tempo is the duration of a beat
+impl Period for Bpm {
+    fn try_to_us(&self) -> Option<us> {
+        60_000_000_u32.checked_div(self.0).map(us)
+    }
+    fn try_to_ms(&self) -> Option<ms> {
+        60_000_u32.checked_div(self.0).map(ms)
+    }
+}
+
+impl Frequency for mBpm {
+    fn try_to_mHz(&self) -> Option<mHz> {
+        Some(mHz(self.0 / 60))
+    }
+    fn try_to_Hz(&self) -> Option<Hz> {
+        Some(Hz(self.0 / 60_000))
+    }
+    fn try_to_kHz(&self) -> Option<kHz> {
+        Some(kHz(self.0 / 60_000_000))
+    }
+}
+
+impl Period for mBpm {
+    fn try_to_us(&self) -> Option<us> {
+        60_000_000_000_u64
+            .checked_div(self.0 as u64)
+            .and_then(|t| u32::try_from(t).ok())
+            .map(us)
+    }
+    fn try_to_ms(&self) -> Option<ms> {
+        60_000_000_u32.checked_div(self.0).map(ms)
+    }
+}
+
+// Pitch intervals
+
+/// 2^(n/12) for n in [0, 12), normalized to 1 << 16
+static SEMITONE_RATIO: [u32; 12] = [
+    65536, 69433, 73562, 77936, 82570, 87480, 92682, 98193, 104032, 110218, 116772, 123715,
+];
+
+/// 2^(n/1200) for n in [0, 100), normalized to 1 << 16
+static CENT_RATIO: [u32; 100] = [
+    65536, 65574, 65612, 65650, 65688, 65726, 65764, 65802, 65840, 65878, 65916, 65954, 65992,
+    66030, 66068, 66106, 66144, 66183, 66221, 66259, 66297, 66336, 66374, 66412, 66451, 66489,
+    66528, 66566, 66605, 66643, 66682, 66720, 66759, 66797, 66836, 66874, 66913, 66952, 66990,
+    67029, 67068, 67107, 67145, 67184, 67223, 67262, 67301, 67340, 67378, 67417, 67456, 67495,
+    67534, 67573, 67612, 67651, 67691, 67730, 67769, 67808, 67847, 67886, 67926, 67965, 68004,
+    68043, 68083, 68122, 68161, 68201, 68240, 68280, 68319, 68359, 68398, 68438, 68477, 68517,
+    68556, 68596, 68635, 68675, 68715, 68755, 68794, 68834, 68874, 68914, 68953, 68993, 69033,
+    69073, 69113, 69153, 69193, 69233, 69273, 69313, 69353, 69393,
+];
+
+/// Returns the frequency ratio 2^(cents/1200) normalized to 1 << 16,
+/// which saturates at the u32 limits.
+pub(crate) fn ratio(cents: i32) -> u32 {
+    let octave = cents.div_euclid(1_200);
+    let rest = cents.rem_euclid(1_200) as usize;
+    let ratio = (SEMITONE_RATIO[rest / 100] as u64 * CENT_RATIO[rest % 100] as u64) >> 16;
+    // Below 1 << 17, so shifts by less than 32 octaves fit into a u64
+    let ratio = if octave >= 32 {
+        u64::MAX
+    } else if octave >= 0 {
+        ratio << octave
+    } else {
+        ratio.checked_shr(octave.unsigned_abs()).unwrap_or(0)
+    };
+    ratio.min(u32::MAX as u64) as u32
+}
+
+/// Unit cents (hundredths of a semitone)
+/// ```
+/// # use isopod::util::units::*;
+/// assert_eq!(Cents::from(Semitones(-2)), Cents(-200));
+/// assert_eq!(Cents(1_200).to_ratio(), 1 << 17);
+/// assert_eq!(mHz(440_000).transpose(Cents(1_200)), mHz(880_000));
+/// assert_eq!(mHz(440_000).transpose(Semitones(-12)), mHz(220_000));
+/// assert_eq!(mHz(440_000).transpose(Semitones(7)), mHz(659_254));
+/// assert_eq!(mHz(440_000).transpose(Cents(i32::MAX)), mHz(u32::MAX));
+/// assert_eq!(Cents(i32::MAX).to_ratio(), u32::MAX);
+/// assert_eq!(Cents(16 * 1_200).to_ratio(), u32::MAX);
+/// assert_eq!(Cents(-100_000).to_ratio(), 0);
+/// assert_eq!(Cents(i32::MIN).to_ratio(), 0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cents(pub i32);
+
+/// Unit semitones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Semitones(pub i32);
+
+impl From<Semitones> for Cents {
+    fn from(semitones: Semitones) -> Self {
+        Cents(semitones.0.saturating_mul(100))
+    }
+}
+
+impl Cents {
+    /// Returns the frequency ratio 2^(cents/1200) normalized to 1 << 16,
+    /// which saturates at the u32 limits, i.e. from 16 octaves up and
+    /// below 16 octaves down.
+    pub fn to_ratio(&self) -> u32 {
+        ratio(self.0)
+    }
+}
+
+impl mHz {
+    /// Returns the frequency raised by an interval, e.g. for detune, pitch
+    /// bend or keytracking. The result saturates at the u32 limits.
+    pub fn transpose(&self, interval: impl Into<Cents>) -> mHz {
+        let cents = interval.into().0;
+        let octave = cents.div_euclid(1_200);
+        let y = (self.0 as u64 * ratio(cents.rem_euclid(1_200)) as u64) >> 16;
+        let y = if octave >= 0 {
+            y.checked_shl(octave.min(63) as u32)
+                .filter(|z| z >> octave.min(63) == y)
+                .unwrap_or(u64::MAX)
+        } else {
+            y.checked_shr(-(octave.max(-63)) as u32).unwrap_or(0)
+        };
+        mHz(y.min(u32::MAX as u64) as u32)
+    }
+}
+
+macro_rules! impl_interval_ops {
+    ($($unit:ident),*) => {
+        $(
+            impl core::ops::Add for $unit {
+                type Output = $unit;
+                fn add(self, rhs: $unit) -> $unit {
+                    $unit(self.0.saturating_add(rhs.0))
+                }
+            }
+            impl core::ops::Sub for $unit {
+                type Output = $unit;
+                fn sub(self, rhs: $unit) -> $unit {
+                    $unit(self.0.saturating_sub(rhs.0))
+                }
+            }
+            impl core::ops::Neg for $unit {
+                type Output = $unit;
+                fn neg(self) -> $unit {
+                    $unit(self.0.saturating_neg())
+                }
+            }
+            impl core::ops::Mul<i32> for $unit {
+                type Output = $unit;
+                fn mul(self, rhs: i32) -> $unit {
+                    $unit(self.0.saturating_mul(rhs))
+                }
+            }
+        )*
+    };
+}
+
+impl_interval_ops!(Cents, Semitones);
+
+// Durations
+//
+// Periods convert to a core::time::Duration losslessly. Durations convert
+// back rounding down, and fail with ParamError::OutOfRange if they don't
+// fit into a u32.
+/// ```
+/// # use isopod::util::units::*;
+/// # use core::time::Duration;
+/// assert_eq!(Duration::from(ms(1_500)), Duration::from_millis(1_500));
+/// assert_eq!(ms::try_from(Duration::from_micros(2_999)), Ok(ms(2)));
+/// assert!(us::try_from(Duration::from_secs(5_000)).is_err());
+/// ```
+impl From<ms> for Duration {
+    fn from(period: ms) -> Duration {
+        Duration::from_millis(period.0 as u64)
+    }
+}
+
+impl From<us> for Duration {
+    fn from(period: us) -> Duration {
+        Duration::from_micros(period.0 as u64)
+    }
+}
+
+impl TryFrom<Duration> for ms {
+    type Error = ParamError;
+
+    fn try_from(duration: Duration) -> Result<ms, ParamError> {
+        u32::try_from(duration.as_millis())
+            .map(ms)
+            .map_err(|_| ParamError::OutOfRange)
+    }
+}
+
+impl TryFrom<Duration> for us {
+    type Error = ParamError;
+
+    fn try_from(duration: Duration) -> Result<us, ParamError> {
+        u32::try_from(duration.as_micros())
+            .map(us)
+            .map_err(|_| ParamError::OutOfRange)
     }
 }
 
@@ -191,9 +625,83 @@ pub const SAMPLE_NORM: i32 = i16::MAX as i32 + 1_i32;
 const SAMPLE_MAX: i16 = i16::MAX;
 const SAMPLE_MIN: i16 = i16::MIN;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Sample of a signal
+///
+/// The operators saturate, so DSP code reads like math without wrapping on
+/// overflow. `*` is the product normalized by [SAMPLE_NORM] like
+/// [Sample::multiply_normed], i.e. a gain stage.
+/// ```
+/// # use isopod::util::units::*;
+/// let (a, b) = (Sample(20_000), Sample::from(i16::MAX / 2));
+/// assert_eq!(a + a, Sample(i16::MAX));
+/// assert_eq!(-a - a, Sample(i16::MIN));
+/// assert_eq!(a * b, Sample(9_999));
+/// assert_eq!(Sample(i16::MIN) * Sample(i16::MIN), Sample(i16::MAX));
+/// assert_eq!(-Sample(i16::MIN), Sample(i16::MAX));
+/// assert_eq!(i16::from(a - b), 3_617);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct Sample(pub i16);
 
+impl From<i16> for Sample {
+    fn from(x: i16) -> Sample {
+        Sample(x)
+    }
+}
+
+impl From<Sample> for i16 {
+    fn from(x: Sample) -> i16 {
+        x.0
+    }
+}
+
+impl core::ops::Add for Sample {
+    type Output = Sample;
+    fn add(self, rhs: Sample) -> Sample {
+        self.saturating_add(rhs)
+    }
+}
+
+impl core::ops::Sub for Sample {
+    type Output = Sample;
+    fn sub(self, rhs: Sample) -> Sample {
+        Sample(crate::util::diag::sub(self.0, rhs.0))
+    }
+}
+
+impl core::ops::Mul for Sample {
+    type Output = Sample;
+    fn mul(self, rhs: Sample) -> Sample {
+        let y = (self.0 as i32 * rhs.0 as i32) / SAMPLE_NORM;
+        Sample(y.min(SAMPLE_MAX as i32) as i16)
+    }
+}
+
+impl core::ops::Neg for Sample {
+    type Output = Sample;
+    fn neg(self) -> Sample {
+        Sample(self.0.saturating_neg())
+    }
+}
+
+impl core::ops::AddAssign for Sample {
+    fn add_assign(&mut self, rhs: Sample) {
+        *self = *self + rhs;
+    }
+}
+
+impl core::ops::SubAssign for Sample {
+    fn sub_assign(&mut self, rhs: Sample) {
+        *self = *self - rhs;
+    }
+}
+
+impl core::ops::MulAssign for Sample {
+    fn mul_assign(&mut self, rhs: Sample) {
+        *self = *self * rhs;
+    }
+}
+
 impl Sample {
     /// Multiplies the sample and normalizes it by the maximum amplitude (i.e.
     /// 32768 = 0x8000).
@@ -208,7 +716,7 @@ impl Sample {
     /// assert_eq!(Sample(100).multiply_normed(Sample(i16::MAX)), Sample(99));
     /// ```
     pub fn multiply_normed(&self, x: Sample) -> Sample {
-        Sample(((x.0 as i32 * self.0 as i32) / SAMPLE_NORM as i32) as i16)
+        Sample(((x.0 as i32 * self.0 as i32) / SAMPLE_NORM) as i16)
     }
 
     /// Saturating addition
@@ -219,7 +727,7 @@ impl Sample {
     /// assert_eq!(Sample(14).saturating_add(Sample(42)), Sample(56));
     /// ```
     pub fn saturating_add(&self, x: Sample) -> Sample {
-        Sample(self.0.saturating_add(x.0))
+        Sample(crate::util::diag::add(self.0, x.0))
     }
 
     /// Saturating multiplication
@@ -233,6 +741,51 @@ impl Sample {
         Sample(self.0.saturating_mul(x.0))
     }
 
+    /// Interpolates linearly from `a` at `t` = 0 to `b` at `t` = i16::MAX.
+    /// Negative `t` are treated as 0.
+    /// ```
+    /// # use isopod::util::units::*;
+    /// assert_eq!(Sample::lerp(Sample(-1_000), Sample(3_000), 0), Sample(-1_000));
+    /// assert_eq!(Sample::lerp(Sample(-1_000), Sample(3_000), i16::MAX / 2), Sample(999));
+    /// assert_eq!(Sample::lerp(Sample(i16::MIN), Sample(i16::MAX), i16::MAX), Sample(i16::MAX));
+    /// ```
+    pub fn lerp(a: Sample, b: Sample, t: i16) -> Sample {
+        let (a, b) = (a.0 as i32, b.0 as i32);
+        Sample((a + (b - a) * t.max(0) as i32 / i16::MAX as i32) as i16)
+    }
+
+    /// Returns the gains of `a` and `b` of an equal power crossfade at `t`
+    /// from 0 (only `a`) to i16::MAX (only `b`), whose squares add up to 1.
+    /// ```
+    /// # use isopod::util::units::*;
+    /// assert_eq!(Sample::equal_power_gains(0), (Sample(i16::MAX), Sample(0)));
+    /// let (a, b) = Sample::equal_power_gains(i16::MAX / 2);
+    /// assert_eq!(a, b);
+    /// ```
+    pub fn equal_power_gains(t: i16) -> (Sample, Sample) {
+        let quarter = SINE_I16.len() as i32 / 4;
+        let idx = (t.max(0) as i32 * quarter + i16::MAX as i32 / 2) / i16::MAX as i32;
+        (
+            Sample(SINE_I16[(quarter - idx) as usize]),
+            Sample(SINE_I16[idx as usize]),
+        )
+    }
+
+    /// Crossfades from `a` at `t` = 0 to `b` at `t` = i16::MAX with equal
+    /// power, which keeps the loudness of uncorrelated signals, e.g. when
+    /// morphing oscillators or fading out a stolen voice.
+    /// ```
+    /// # use isopod::util::units::*;
+    /// assert_eq!(Sample::crossfade(Sample(10_000), Sample(0), i16::MAX), Sample(0));
+    /// let mid = Sample::crossfade(Sample(10_000), Sample(0), i16::MAX / 2);
+    /// // cos(pi/4) = 0.7071
+    /// assert!((mid.0 - 7_071).abs() < 10);
+    /// ```
+    pub fn crossfade(a: Sample, b: Sample, t: i16) -> Sample {
+        let (gain_a, gain_b) = Self::equal_power_gains(t);
+        a * gain_a + b * gain_b
+    }
+
     /// Clipping indicator
     ///
     /// Returns `true` if the signal is clipping
@@ -243,10 +796,271 @@ impl Sample {
     /// assert_eq!(Sample(i16::MAX - 1).is_clipping(), false);
     /// ```
     pub fn is_clipping(&self) -> bool {
-        if (self.0 == SAMPLE_MAX) | (self.0 == SAMPLE_MIN) {
-            true
+        (self.0 == SAMPLE_MAX) | (self.0 == SAMPLE_MIN)
+    }
+}
+
+/// Lowest level in dB that is distinguishable from silence
+pub const DB_MIN: i32 = -96;
+/// Highest supported level in dB
+pub const DB_MAX: i32 = 24;
+
+/// Linear gains 10^(dB/20) for dB in [DB_MIN, DB_MAX], normalized to
+/// [SAMPLE_NORM]
+static DB_GAIN: [u32; (DB_MAX - DB_MIN + 1) as usize] = [
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 4, 4, 5, 5, 6, 7, 7, 8, 9, 10, 12, 13, 15,
+    16, 18, 21, 23, 26, 29, 33, 37, 41, 46, 52, 58, 65, 73, 82, 92, 104, 116, 130, 146, 164, 184,
+    207, 232, 260, 292, 328, 368, 413, 463, 519, 583, 654, 734, 823, 924, 1036, 1163, 1305, 1464,
+    1642, 1843, 2068, 2320, 2603, 2920, 3277, 3677, 4125, 4629, 5193, 5827, 6538, 7336, 8231, 9235,
+    10362, 11627, 13045, 14637, 16423, 18427, 20675, 23198, 26029, 29205, 32768, 36766, 41252,
+    46286, 51934, 58271, 65381, 73358, 82309, 92353, 103622, 116265, 130452, 146369, 164229,
+    184268, 206752, 231980, 260285, 292045, 327680, 367663, 412525, 462860, 519338,
+];
+
+/// Unit dB (decibel, amplitude)
+/// ```
+/// # use isopod::util::units::*;
+/// assert_eq!(dB(0).to_gain(), SAMPLE_NORM as u32);
+/// assert_eq!(dB(-6).to_gain(), 16_423);
+/// assert_eq!(dB(-200).to_gain(), 0);
+/// ```
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct dB(pub i32);
+
+impl dB {
+    /// Returns the linear gain normalized to [SAMPLE_NORM]. Levels below
+    /// [DB_MIN] are silent, levels above [DB_MAX] are clamped.
+    pub fn to_gain(&self) -> u32 {
+        if self.0 < DB_MIN {
+            0
         } else {
-            false
+            DB_GAIN[(self.0.min(DB_MAX) - DB_MIN) as usize]
+        }
+    }
+
+    /// Returns the linear gain as Q15 like the i16 gains of the mixers and
+    /// dynamics processors. Levels above 0 dB saturate to i16::MAX.
+    /// ```
+    /// # use isopod::util::units::*;
+    /// assert_eq!(dB(-6).to_q15(), 16_423);
+    /// assert_eq!(dB(6).to_q15(), i16::MAX);
+    /// ```
+    pub fn to_q15(&self) -> i16 {
+        self.to_gain().min(i16::MAX as u32) as i16
+    }
+
+    /// Returns the level of a linear gain normalized to [SAMPLE_NORM],
+    /// rounded to the nearest dB. A gain of 0 returns a level below
+    /// [DB_MIN], which converts back to silence.
+    /// ```
+    /// # use isopod::util::units::*;
+    /// assert_eq!(dB::from_gain(SAMPLE_NORM as u32), dB(0));
+    /// assert_eq!(dB::from_gain(16_000), dB(-6));
+    /// assert_eq!(dB::from_gain(dB(-40).to_gain()), dB(-40));
+    /// assert_eq!(dB::from_gain(0).to_gain(), 0);
+    /// ```
+    pub fn from_gain(gain: u32) -> dB {
+        if gain == 0 {
+            return dB(DB_MIN - 1);
+        }
+        let i = DB_GAIN.partition_point(|g| *g <= gain);
+        if i == 0 {
+            return dB(DB_MIN);
         }
+        if i == DB_GAIN.len() {
+            return dB(DB_MAX);
+        }
+        // Nearest on a logarithmic scale, i.e. compared to the geometric mean
+        let (lo, hi) = (DB_GAIN[i - 1] as u64, DB_GAIN[i] as u64);
+        let gain = gain as u64;
+        let i = if gain * gain >= lo * hi { i } else { i - 1 };
+        dB(i as i32 + DB_MIN)
+    }
+
+    /// Returns the level of `sample` relative to full scale (dBFS), e.g.
+    /// for metering.
+    /// ```
+    /// # use isopod::util::units::*;
+    /// assert_eq!(dB::from_sample(i16::MIN), dB(0));
+    /// assert_eq!(dB::from_sample(3_277), dB(-20));
+    /// ```
+    pub fn from_sample(sample: i16) -> dB {
+        Self::from_gain(sample.unsigned_abs() as u32)
+    }
+}
+
+/// Stereo frame consisting of a left and a right [Sample]
+///
+/// The operators work on both channels and saturate like those of
+/// [Sample]. `*` by a [Sample] applies a gain.
+/// ```
+/// # use isopod::util::units::*;
+/// assert_eq!(Frame::mono(42), Frame::new(42, 42));
+/// assert_eq!(Frame::new(1, 2).right, Sample(2));
+///
+/// let frame = Frame::new(20_000, -4_000);
+/// assert_eq!(frame + frame, Frame::new(i16::MAX, -8_000));
+/// assert_eq!(frame * Sample(i16::MAX / 2), Frame::new(9_999, -1_999));
+/// assert_eq!(frame.to_mid_side(), (Sample(8_000), Sample(12_000)));
+/// assert_eq!(Frame::from_mid_side(Sample(8_000), Sample(12_000)), frame);
+/// assert_eq!(<[i16; 2]>::from(frame), [20_000, -4_000]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Frame {
+    pub left: Sample,
+    pub right: Sample,
+}
+
+impl Frame {
+    pub fn new(left: i16, right: i16) -> Self {
+        Self {
+            left: Sample(left),
+            right: Sample(right),
+        }
+    }
+
+    /// Frame with the same signal on both channels
+    pub fn mono(signal: i16) -> Self {
+        Self::new(signal, signal)
+    }
+
+    /// Frame with `signal` at `pan` from -i16::MAX (left) to i16::MAX
+    /// (right) with constant power, see [crate::fx::panner::Panner].
+    /// ```
+    /// # use isopod::util::units::*;
+    /// assert_eq!(Frame::panned(10_000, -i16::MAX), Frame::new(9_999, 0));
+    /// ```
+    pub fn panned(signal: i16, pan: i16) -> Self {
+        let mut panner = crate::fx::panner::Panner::new();
+        panner.set_pan(pan);
+        panner.process(signal)
+    }
+
+    /// Returns the mid (L + R) / 2 and the side (L - R) / 2.
+    pub fn to_mid_side(&self) -> (Sample, Sample) {
+        let (left, right) = (self.left.0 as i32, self.right.0 as i32);
+        (
+            Sample(((left + right) / 2) as i16),
+            Sample(((left - right) / 2) as i16),
+        )
+    }
+
+    /// Returns the frame M + S, M - S, which saturates.
+    pub fn from_mid_side(mid: Sample, side: Sample) -> Self {
+        Self {
+            left: mid + side,
+            right: mid - side,
+        }
+    }
+
+    /// Returns the average of both channels.
+    pub fn to_mono(&self) -> Sample {
+        self.to_mid_side().0
+    }
+
+    /// Writes `frames` to `out` as interleaved left and right samples and
+    /// returns the number of frames written.
+    /// ```
+    /// # use isopod::util::units::*;
+    /// let mut out = [0; 5];
+    /// assert_eq!(Frame::interleave(&[Frame::new(1, 2); 3], &mut out), 2);
+    /// assert_eq!(out, [1, 2, 1, 2, 0]);
+    /// ```
+    pub fn interleave(frames: &[Frame], out: &mut [i16]) -> usize {
+        let mut n = 0;
+        for (frame, pair) in frames.iter().zip(out.chunks_exact_mut(2)) {
+            pair.copy_from_slice(&<[i16; 2]>::from(*frame));
+            n += 1;
+        }
+        n
+    }
+
+    /// Reads interleaved left and right samples from `samples` into `out`
+    /// and returns the number of frames read.
+    /// ```
+    /// # use isopod::util::units::*;
+    /// let mut out = [Frame::default(); 4];
+    /// assert_eq!(Frame::deinterleave(&[1, 2, 3, 4, 5], &mut out), 2);
+    /// assert_eq!(out[1], Frame::new(3, 4));
+    /// ```
+    pub fn deinterleave(samples: &[i16], out: &mut [Frame]) -> usize {
+        let mut n = 0;
+        for (pair, frame) in samples.chunks_exact(2).zip(out.iter_mut()) {
+            *frame = Frame::new(pair[0], pair[1]);
+            n += 1;
+        }
+        n
+    }
+}
+
+impl From<[i16; 2]> for Frame {
+    fn from(pair: [i16; 2]) -> Frame {
+        Frame::new(pair[0], pair[1])
+    }
+}
+
+impl From<Frame> for [i16; 2] {
+    fn from(frame: Frame) -> [i16; 2] {
+        [frame.left.0, frame.right.0]
+    }
+}
+
+impl core::ops::Add for Frame {
+    type Output = Frame;
+    fn add(self, rhs: Frame) -> Frame {
+        Frame {
+            left: self.left + rhs.left,
+            right: self.right + rhs.right,
+        }
+    }
+}
+
+impl core::ops::Sub for Frame {
+    type Output = Frame;
+    fn sub(self, rhs: Frame) -> Frame {
+        Frame {
+            left: self.left - rhs.left,
+            right: self.right - rhs.right,
+        }
+    }
+}
+
+impl core::ops::Mul<Sample> for Frame {
+    type Output = Frame;
+    fn mul(self, gain: Sample) -> Frame {
+        Frame {
+            left: self.left * gain,
+            right: self.right * gain,
+        }
+    }
+}
+
+impl core::ops::Neg for Frame {
+    type Output = Frame;
+    fn neg(self) -> Frame {
+        Frame {
+            left: -self.left,
+            right: -self.right,
+        }
+    }
+}
+
+impl core::ops::AddAssign for Frame {
+    fn add_assign(&mut self, rhs: Frame) {
+        *self = *self + rhs;
+    }
+}
+
+impl core::ops::SubAssign for Frame {
+    fn sub_assign(&mut self, rhs: Frame) {
+        *self = *self - rhs;
+    }
+}
+
+impl core::ops::MulAssign<Sample> for Frame {
+    fn mul_assign(&mut self, gain: Sample) {
+        *self = *self * gain;
     }
 }